@@ -0,0 +1,34 @@
+extern crate gfapi_sys;
+extern crate libc;
+
+use gfapi_sys::cli::remote_from_args;
+use gfapi_sys::gluster::GlusterError;
+
+use std::env;
+use std::io::{self, ErrorKind};
+use std::process;
+
+// Stream a file on a volume to stdout
+fn run(args: &[String]) -> Result<(), GlusterError> {
+    let target = remote_from_args(args)?;
+    let cluster = target.connect()?;
+    let mut file = cluster.open_file(&target.path, libc::O_RDONLY)?;
+    let stdout = io::stdout();
+    match io::copy(&mut file, &mut stdout.lock()) {
+        Ok(_) => {}
+        // The reader went away, e.g. piped into head
+        Err(ref e) if e.kind() == ErrorKind::BrokenPipe => {}
+        Err(e) => return Err(GlusterError::IoError(e)),
+    }
+    file.close()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("glfs-cat: {}", e);
+        eprintln!("usage: glfs-cat gluster://host[:port]/volume/path");
+        eprintln!("       glfs-cat volume host[:port] path");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,92 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::cli::{progress_bar, RemoteTarget, Target};
+use gfapi_sys::download::DownloadOptions;
+use gfapi_sys::gluster::GlusterError;
+use gfapi_sys::upload::UploadOptions;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+
+const CHUNK: usize = 4 * 1024 * 1024;
+
+fn show_progress(done: u64, total: u64) {
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    let _ = write!(stderr, "\r{}", progress_bar(done, total, 40));
+    let _ = stderr.flush();
+}
+
+fn upload(local: &Path, remote: &RemoteTarget) -> Result<u64, GlusterError> {
+    let cluster = remote.connect()?;
+    let mut source = File::open(local)?;
+    let total = source.metadata()?.len();
+    let mut upload = cluster.upload(&remote.path, &UploadOptions::new().total_len(total))?;
+    // Only the parts an interrupted run didn't get to
+    let mut buffer = vec![0; CHUNK];
+    for (start, end) in upload.missing_ranges() {
+        source.seek(SeekFrom::Start(start))?;
+        let mut offset = start;
+        while offset < end {
+            let want = ((end - offset) as usize).min(CHUNK);
+            source.read_exact(&mut buffer[..want])?;
+            upload.write_part(offset, &buffer[..want])?;
+            offset += want as u64;
+            show_progress(upload.bytes_written(), total);
+        }
+    }
+    upload.finish()
+}
+
+fn download(remote: &RemoteTarget, local: &Path) -> Result<u64, GlusterError> {
+    let cluster = remote.connect()?;
+    let outcome =
+        cluster.download_with_progress(&remote.path, local, &DownloadOptions::new(), |p| {
+            show_progress(p.downloaded, p.total);
+            true
+        })?;
+    Ok(outcome.total)
+}
+
+fn usage_error(message: &str) -> GlusterError {
+    GlusterError::IoError(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+// Copy a file between the local filesystem and a volume
+fn run(args: &[String]) -> Result<u64, GlusterError> {
+    if args.len() != 2 {
+        return Err(usage_error("expected a source and a destination"));
+    }
+    match (args[0].parse()?, args[1].parse()?) {
+        (Target::Local(local), Target::Remote(remote)) => upload(&local, &remote),
+        (Target::Remote(remote), Target::Local(local)) => download(&remote, &local),
+        (Target::Remote(from), Target::Remote(to)) => {
+            if (&from.host, from.port, &from.volume) != (&to.host, to.port, &to.volume) {
+                return Err(usage_error(
+                    "copies between volumes go through a local file",
+                ));
+            }
+            let cluster = from.connect()?;
+            cluster.copy(&from.path, &to.path, &Default::default())
+        }
+        (Target::Local(_), Target::Local(_)) => {
+            Err(usage_error("one side needs to be a gluster:// URL"))
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(copied) => eprintln!("\ncopied {} bytes", copied),
+        Err(e) => {
+            eprintln!("\nglfs-cp: {}", e);
+            eprintln!("usage: glfs-cp SOURCE DEST");
+            eprintln!("  where one of them is gluster://host[:port]/volume/path");
+            process::exit(1);
+        }
+    }
+}
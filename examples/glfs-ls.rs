@@ -0,0 +1,48 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::cli::{format_mode, remote_from_args};
+use gfapi_sys::gluster::GlusterError;
+
+use std::env;
+use std::process;
+
+// List a directory on a volume like ls -l
+fn run(args: &[String]) -> Result<(), GlusterError> {
+    let target = remote_from_args(args)?;
+    let cluster = target.connect()?;
+    let metadata = cluster.metadata(&target.path)?;
+    let mut listing = if metadata.is_dir() {
+        cluster
+            .list_dir(&target.path, 4)?
+            .into_iter()
+            .map(|(entry, metadata)| (entry.path, metadata))
+            .collect()
+    } else {
+        vec![(target.path.clone(), metadata)]
+    };
+    listing.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, metadata) in listing {
+        let stat = metadata.as_stat();
+        println!(
+            "{} {:>3} {:>5} {:>5} {:>12} {:>11} {}",
+            format_mode(metadata.mode()),
+            stat.st_nlink,
+            metadata.uid(),
+            metadata.gid(),
+            metadata.len(),
+            metadata.mtime(),
+            name.display()
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("glfs-ls: {}", e);
+        eprintln!("usage: glfs-ls gluster://host[:port]/volume/path");
+        eprintln!("       glfs-ls volume host[:port] path");
+        process::exit(1);
+    }
+}
@@ -4,7 +4,7 @@ extern crate libc;
 use std::path::Path;
 
 use gfapi_sys::gluster::*;
-use libc::{O_CREAT, O_RDWR, O_TRUNC, O_APPEND, SEEK_SET, timespec};
+use libc::{O_CREAT, O_RDWR, O_TRUNC, SEEK_SET, timespec};
 
 fn main() {
     let cluster = match Gluster::connect("test", "localhost", 24007) {
@@ -21,17 +21,18 @@ fn main() {
         }
 
     }
-    let file_handle =
+    let file =
         match cluster.create(&Path::new("gfapi/test"), O_CREAT | O_RDWR | O_TRUNC, 0644) {
-            Ok(file_handle) => file_handle,
+            Ok(file) => file,
             Err(e) => {
                 println!("create file failed: {:?}", e);
                 return;
             }
         };
+    let file_handle = file.handle().unwrap();
 
 
-    match cluster.write(file_handle, &"hello world".as_bytes(), O_APPEND) {
+    match cluster.write_sync(file_handle, &"hello world".as_bytes()) {
         Ok(bytes_written) => {
             println!("Wrote {} bytes", bytes_written);
         }
@@ -50,7 +51,7 @@ fn main() {
         }
     };
     let mut read_buff: Vec<u8> = Vec::with_capacity(1024);
-    match cluster.read(file_handle, &mut read_buff, 1024, 0) {
+    match cluster.read(file_handle, &mut read_buff, 1024) {
         Ok(bytes_read) => {
             println!("Read {} bytes", bytes_read);
             read_buff.truncate(bytes_read as usize);
@@ -74,7 +75,7 @@ fn main() {
                       }];
     cluster.utimens(&Path::new("gfapi/test"), &file_times).unwrap();
 
-    let d = GlusterDirectory { dir_handle: cluster.opendir(&Path::new("gfapi")).unwrap() };
+    let d = cluster.opendir(&Path::new("gfapi")).unwrap();
     for dir_entry in d {
         println!("Dir_entry: {:?}", dir_entry);
     }
@@ -0,0 +1,319 @@
+use errno::{errno, Errno};
+use libc::ENODATA;
+
+use gluster::{Gluster, GlusterError};
+
+use std::error::Error as err;
+use std::fmt;
+use std::path::Path;
+
+// The xattr encoding shared by the kernel and gluster's posix-acl
+// translator: a 4 byte version followed by 8 byte entries of tag, perms
+// and qualifier id, all little endian
+const ACL_XATTR_VERSION: u32 = 2;
+const ACL_UNDEFINED_ID: u32 = 0xffff_ffff;
+const ACL_HEADER_LEN: usize = 4;
+const ACL_ENTRY_LEN: usize = 8;
+
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// Which of a file's ACLs to get or set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AclType {
+    /// Checked when the file itself is accessed
+    Access,
+    /// Inherited by files created in a directory
+    Default,
+}
+
+impl AclType {
+    /// The xattr gfapi exposes the ACL as
+    pub fn xattr_name(&self) -> &'static str {
+        match *self {
+            AclType::Access => "system.posix_acl_access",
+            AclType::Default => "system.posix_acl_default",
+        }
+    }
+}
+
+/// Who an ACL entry applies to.  User and Group carry the uid or gid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AclTag {
+    UserObj,
+    User(u32),
+    GroupObj,
+    Group(u32),
+    Mask,
+    Other,
+}
+
+impl AclTag {
+    fn encode(&self) -> (u16, u32) {
+        match *self {
+            AclTag::UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+            AclTag::User(uid) => (ACL_USER, uid),
+            AclTag::GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+            AclTag::Group(gid) => (ACL_GROUP, gid),
+            AclTag::Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+            AclTag::Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+        }
+    }
+
+    fn decode(tag: u16, id: u32) -> Result<AclTag, AclError> {
+        match tag {
+            ACL_USER_OBJ => Ok(AclTag::UserObj),
+            ACL_USER => Ok(AclTag::User(id)),
+            ACL_GROUP_OBJ => Ok(AclTag::GroupObj),
+            ACL_GROUP => Ok(AclTag::Group(id)),
+            ACL_MASK => Ok(AclTag::Mask),
+            ACL_OTHER => Ok(AclTag::Other),
+            _ => Err(AclError::UnknownTag(tag)),
+        }
+    }
+}
+
+/// Read, write and execute permission bits of an ACL entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AclPerms {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl AclPerms {
+    /// From the low three bits of a mode, e.g. 6 for rw-
+    pub fn from_bits(bits: u16) -> AclPerms {
+        AclPerms {
+            read: bits & 4 != 0,
+            write: bits & 2 != 0,
+            execute: bits & 1 != 0,
+        }
+    }
+
+    pub fn bits(&self) -> u16 {
+        (if self.read { 4 } else { 0 })
+            | (if self.write { 2 } else { 0 })
+            | (if self.execute { 1 } else { 0 })
+    }
+}
+
+impl fmt::Display for AclPerms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+/// One line of an ACL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perms: AclPerms,
+}
+
+impl AclEntry {
+    pub fn new(tag: AclTag, perms: AclPerms) -> AclEntry {
+        AclEntry { tag, perms }
+    }
+}
+
+/// Why an ACL couldn't be decoded or failed validation
+#[derive(Clone, Debug, PartialEq)]
+pub enum AclError {
+    /// The xattr isn't a header followed by whole entries
+    BadLength(usize),
+    /// The header names an encoding other than version 2
+    BadVersion(u32),
+    /// An entry has a tag that isn't one of the six POSIX ones
+    UnknownTag(u16),
+    /// One of the owner, owning group or other entries is absent
+    MissingEntry(AclTag),
+    /// There are named user or group entries but no mask
+    MissingMask,
+    /// The same tag, or the same uid or gid, appears twice
+    DuplicateEntry(AclTag),
+}
+
+impl fmt::Display for AclError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AclError::BadLength(len) => write!(f, "ACL xattr of {} bytes is malformed", len),
+            AclError::BadVersion(version) => write!(f, "unsupported ACL version {}", version),
+            AclError::UnknownTag(tag) => write!(f, "unknown ACL tag {:#x}", tag),
+            AclError::MissingEntry(tag) => write!(f, "ACL has no {:?} entry", tag),
+            AclError::MissingMask => f.write_str("ACL has named entries but no mask"),
+            AclError::DuplicateEntry(tag) => write!(f, "ACL has more than one {:?} entry", tag),
+        }
+    }
+}
+
+impl err for AclError {
+    fn description(&self) -> &str {
+        match *self {
+            AclError::BadLength(_) => "ACL xattr is malformed",
+            AclError::BadVersion(_) => "unsupported ACL version",
+            AclError::UnknownTag(_) => "unknown ACL tag",
+            AclError::MissingEntry(_) => "ACL is missing a required entry",
+            AclError::MissingMask => "ACL has named entries but no mask",
+            AclError::DuplicateEntry(_) => "ACL has a duplicate entry",
+        }
+    }
+}
+
+/// A POSIX ACL.  An empty one is only meaningful as a default ACL, where
+/// it means there is none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    pub fn new(entries: Vec<AclEntry>) -> Acl {
+        Acl { entries }
+    }
+
+    /// The minimal ACL equivalent to the permission bits of mode
+    pub fn from_mode(mode: u32) -> Acl {
+        Acl::new(vec![
+            AclEntry::new(AclTag::UserObj, AclPerms::from_bits((mode >> 6) as u16)),
+            AclEntry::new(AclTag::GroupObj, AclPerms::from_bits((mode >> 3) as u16)),
+            AclEntry::new(AclTag::Other, AclPerms::from_bits(mode as u16)),
+        ])
+    }
+
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Permissions of the entry with tag, if there is one
+    pub fn get(&self, tag: AclTag) -> Option<AclPerms> {
+        self.entries.iter().find(|e| e.tag == tag).map(|e| e.perms)
+    }
+
+    /// Add an entry, replacing any existing one with the same tag
+    pub fn set(&mut self, tag: AclTag, perms: AclPerms) {
+        match self.entries.iter_mut().find(|e| e.tag == tag) {
+            Some(entry) => entry.perms = perms,
+            None => self.entries.push(AclEntry::new(tag, perms)),
+        }
+    }
+
+    /// Remove the entry with tag, returning whether there was one
+    pub fn remove(&mut self, tag: AclTag) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.tag != tag);
+        self.entries.len() != before
+    }
+
+    /// Check the entries make a valid ACL: exactly one owner, owning
+    /// group and other entry, no duplicates, and a mask whenever there
+    /// are named users or groups.
+    pub fn validate(&self) -> Result<(), AclError> {
+        let mut tags: Vec<AclTag> = self.entries.iter().map(|e| e.tag).collect();
+        tags.sort();
+        for pair in tags.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(AclError::DuplicateEntry(pair[0]));
+            }
+        }
+        for required in &[AclTag::UserObj, AclTag::GroupObj, AclTag::Other] {
+            if !tags.contains(required) {
+                return Err(AclError::MissingEntry(*required));
+            }
+        }
+        let named = tags
+            .iter()
+            .any(|t| matches!(*t, AclTag::User(_) | AclTag::Group(_)));
+        if named && !tags.contains(&AclTag::Mask) {
+            return Err(AclError::MissingMask);
+        }
+        Ok(())
+    }
+
+    /// Encode into the xattr format, entries sorted the way the kernel
+    /// expects.  An empty ACL encodes to just the header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|e| e.tag);
+        let mut bytes = Vec::with_capacity(ACL_HEADER_LEN + ACL_ENTRY_LEN * entries.len());
+        bytes.extend_from_slice(&ACL_XATTR_VERSION.to_le_bytes());
+        for entry in &entries {
+            let (tag, id) = entry.tag.encode();
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&entry.perms.bits().to_le_bytes());
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode the xattr format.  The result isn't validated, so ACLs
+    /// written by other tools can still be inspected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Acl, AclError> {
+        let body = match bytes.get(ACL_HEADER_LEN..) {
+            Some(body) if body.chunks_exact(ACL_ENTRY_LEN).remainder().is_empty() => body,
+            _ => return Err(AclError::BadLength(bytes.len())),
+        };
+        let version = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if version != ACL_XATTR_VERSION {
+            return Err(AclError::BadVersion(version));
+        }
+        let mut entries = Vec::new();
+        for chunk in body.chunks(ACL_ENTRY_LEN) {
+            let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+            let perms = u16::from_le_bytes([chunk[2], chunk[3]]);
+            let id = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            entries.push(AclEntry::new(
+                AclTag::decode(tag, id)?,
+                AclPerms::from_bits(perms),
+            ));
+        }
+        Ok(Acl { entries })
+    }
+}
+
+impl Gluster {
+    /// Read the ACL of path.  A file without an access ACL gets one
+    /// built from its mode, and a directory without a default ACL gets an
+    /// empty one.
+    pub fn get_acl(&self, path: &Path, acl_type: AclType) -> Result<Acl, GlusterError> {
+        match self.getxattr_bytes(path, acl_type.xattr_name()) {
+            Ok(bytes) => Ok(Acl::from_bytes(&bytes)?),
+            Err(e) => {
+                if errno() != Errno(ENODATA) {
+                    return Err(e);
+                }
+                match acl_type {
+                    AclType::Access => Ok(Acl::from_mode(self.stat(path)?.st_mode)),
+                    AclType::Default => Ok(Acl::default()),
+                }
+            }
+        }
+    }
+
+    /// Replace the ACL of path after validating it.  Setting an empty
+    /// default ACL removes it.
+    pub fn set_acl(&self, path: &Path, acl_type: AclType, acl: &Acl) -> Result<(), GlusterError> {
+        if acl_type == AclType::Default && acl.is_empty() {
+            return match self.removexattr(path, acl_type.xattr_name()) {
+                Err(ref _e) if errno() == Errno(ENODATA) => Ok(()),
+                other => other,
+            };
+        }
+        acl.validate()?;
+        self.setxattr(path, acl_type.xattr_name(), &acl.to_bytes(), 0)
+    }
+}
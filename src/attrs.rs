@@ -0,0 +1,265 @@
+use libc::{c_int, gid_t, mode_t, stat, timespec, uid_t, UTIME_OMIT};
+
+use glfs::*;
+use gluster::{get_error, Gluster, GlusterError};
+
+use std::fmt;
+use std::mem::zeroed;
+use std::path::Path;
+use std::ptr;
+
+// Mode bits a change of owner can clear
+const SET_ID_BITS: mode_t = 0o6000;
+
+/// The groups of attributes Gluster::set_attrs applies, in the order
+/// they're applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttrField {
+    Size,
+    Owner,
+    Mode,
+    Times,
+}
+
+impl fmt::Display for AttrField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AttrField::Size => "size",
+            AttrField::Owner => "owner",
+            AttrField::Mode => "mode",
+            AttrField::Times => "times",
+        })
+    }
+}
+
+/// Attributes for Gluster::set_attrs to change.  Anything not set is left
+/// as it is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SetAttrs {
+    mode: Option<mode_t>,
+    uid: Option<uid_t>,
+    gid: Option<gid_t>,
+    atime: Option<timespec>,
+    mtime: Option<timespec>,
+    size: Option<u64>,
+}
+
+impl SetAttrs {
+    pub fn new() -> SetAttrs {
+        SetAttrs::default()
+    }
+
+    /// Permission bits, including setuid, setgid and sticky
+    pub fn mode(mut self, mode: mode_t) -> SetAttrs {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn uid(mut self, uid: uid_t) -> SetAttrs {
+        self.uid = Some(uid);
+        self
+    }
+
+    pub fn gid(mut self, gid: gid_t) -> SetAttrs {
+        self.gid = Some(gid);
+        self
+    }
+
+    pub fn owner(self, uid: uid_t, gid: gid_t) -> SetAttrs {
+        self.uid(uid).gid(gid)
+    }
+
+    pub fn atime(mut self, atime: timespec) -> SetAttrs {
+        self.atime = Some(atime);
+        self
+    }
+
+    pub fn mtime(mut self, mtime: timespec) -> SetAttrs {
+        self.mtime = Some(mtime);
+        self
+    }
+
+    /// Truncate or extend the file to this length
+    pub fn size(mut self, size: u64) -> SetAttrs {
+        self.size = Some(size);
+        self
+    }
+
+    fn has_owner(&self) -> bool {
+        self.uid.is_some() || self.gid.is_some()
+    }
+
+    fn has_times(&self) -> bool {
+        self.atime.is_some() || self.mtime.is_some()
+    }
+}
+
+/// True if the linked libgfapi exports glfs_h_setattrs, so set_attrs can
+/// change owner, mode and times in one call
+pub fn handle_setattrs_available() -> bool {
+    handle_setattrs_fn().is_some()
+}
+
+fn handle_setattrs_fn() -> Option<glfs_h_setattrs_t> {
+    unsafe { optional_fn::<glfs_h_setattrs_t>("glfs_h_setattrs") }
+}
+
+// Tracks what set_attrs has done so a failure part way can say so
+struct Progress<'a> {
+    path: &'a Path,
+    applied: Vec<AttrField>,
+}
+
+impl<'a> Progress<'a> {
+    fn step(
+        &mut self,
+        fields: &[AttrField],
+        result: Result<(), GlusterError>,
+    ) -> Result<(), GlusterError> {
+        match result {
+            Ok(()) => {
+                self.applied.extend_from_slice(fields);
+                Ok(())
+            }
+            // Nothing changed, the error is all there is to report
+            Err(e) if self.applied.is_empty() => Err(e),
+            Err(e) => Err(GlusterError::AttrsPartiallyApplied {
+                path: self.path.to_path_buf(),
+                applied: self.applied.clone(),
+                failed: fields[0],
+                error: e.to_string(),
+            }),
+        }
+    }
+}
+
+impl Gluster {
+    /// Change any of a file's size, owner, mode and times together, in
+    /// that order: truncating would update mtime and a change of owner
+    /// can clear the setuid and setgid bits, so each goes before what it
+    /// would undo.  Symlinks are followed.
+    ///
+    /// When the linked libgfapi has glfs_h_setattrs, see
+    /// handle_setattrs_available, owner, mode and times are set with a
+    /// single call, plus a second to restore setuid or setgid bits if
+    /// the owner changed too.  Otherwise truncate, chown, chmod and
+    /// utimens are called in turn.  If a step fails after others have
+    /// succeeded the error is GlusterError::AttrsPartiallyApplied, naming
+    /// what was applied.
+    pub fn set_attrs(&self, path: &Path, attrs: &SetAttrs) -> Result<(), GlusterError> {
+        self.check_writable()?;
+        let mut progress = Progress {
+            path,
+            applied: Vec::new(),
+        };
+        if let Some(size) = attrs.size {
+            progress.step(&[AttrField::Size], self.truncate(path, size as i64))?;
+        }
+        match handle_setattrs_fn() {
+            Some(setattrs) => self.set_attrs_handle(setattrs, path, attrs, &mut progress),
+            None => self.set_attrs_paths(path, attrs, &mut progress),
+        }
+    }
+
+    fn set_attrs_handle(
+        &self,
+        setattrs: glfs_h_setattrs_t,
+        path: &Path,
+        attrs: &SetAttrs,
+        progress: &mut Progress,
+    ) -> Result<(), GlusterError> {
+        let mut fields = Vec::new();
+        let mut valid: c_int = 0;
+        let mut sb: stat = unsafe { zeroed() };
+        if let Some(uid) = attrs.uid {
+            sb.st_uid = uid;
+            valid |= GFAPI_SET_ATTR_UID;
+        }
+        if let Some(gid) = attrs.gid {
+            sb.st_gid = gid;
+            valid |= GFAPI_SET_ATTR_GID;
+        }
+        if attrs.has_owner() {
+            fields.push(AttrField::Owner);
+        }
+        if let Some(mode) = attrs.mode {
+            sb.st_mode = mode;
+            valid |= GFAPI_SET_ATTR_MODE;
+            fields.push(AttrField::Mode);
+        }
+        if let Some(atime) = attrs.atime {
+            sb.st_atime = atime.tv_sec;
+            sb.st_atime_nsec = atime.tv_nsec;
+            valid |= GFAPI_SET_ATTR_ATIME;
+        }
+        if let Some(mtime) = attrs.mtime {
+            sb.st_mtime = mtime.tv_sec;
+            sb.st_mtime_nsec = mtime.tv_nsec;
+            valid |= GFAPI_SET_ATTR_MTIME;
+        }
+        if attrs.has_times() {
+            fields.push(AttrField::Times);
+        }
+        if valid == 0 {
+            return Ok(());
+        }
+
+        let c_path = self.c_path(path)?;
+        unsafe {
+            let mut found: stat = zeroed();
+            let object = glfs_h_lookupat(
+                self.cluster_handle,
+                ptr::null_mut(),
+                c_path.as_ptr(),
+                &mut found,
+                1,
+            );
+            if object.is_null() {
+                return progress.step(&fields, Err(GlusterError::new(get_error())));
+            }
+            let mut result = if setattrs(self.cluster_handle, object, &mut sb, valid) < 0 {
+                Err(GlusterError::new(get_error()))
+            } else {
+                Ok(())
+            };
+            // The brick may apply the owner after the mode
+            if let (Ok(()), Some(mode)) = (&result, attrs.mode) {
+                if attrs.has_owner() && mode & SET_ID_BITS != 0 {
+                    let mut sb: stat = zeroed();
+                    sb.st_mode = mode;
+                    if setattrs(self.cluster_handle, object, &mut sb, GFAPI_SET_ATTR_MODE) < 0 {
+                        result = Err(GlusterError::new(get_error()));
+                    }
+                }
+            }
+            glfs_h_close(object);
+            progress.step(&fields, result)
+        }
+    }
+
+    fn set_attrs_paths(
+        &self,
+        path: &Path,
+        attrs: &SetAttrs,
+        progress: &mut Progress,
+    ) -> Result<(), GlusterError> {
+        if attrs.has_owner() {
+            // -1 leaves that id alone
+            let uid = attrs.uid.unwrap_or(!0);
+            let gid = attrs.gid.unwrap_or(!0);
+            progress.step(&[AttrField::Owner], self.chown(path, uid, gid))?;
+        }
+        if let Some(mode) = attrs.mode {
+            progress.step(&[AttrField::Mode], self.chmod(path, mode))?;
+        }
+        if attrs.has_times() {
+            let omit = timespec {
+                tv_sec: 0,
+                tv_nsec: UTIME_OMIT,
+            };
+            let times = [attrs.atime.unwrap_or(omit), attrs.mtime.unwrap_or(omit)];
+            progress.step(&[AttrField::Times], self.utimens(path, &times))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+use errno::{errno, set_errno, Errno};
+use libc::{self, mode_t};
+
+use file::GlusterFile;
+use gluster::{DirEntry, Gluster, GlusterError};
+use metadata::Metadata;
+use remote_fs::{io_result, remote_metadata, remote_read_dir};
+use remote_fs::{RemoteFs, RemoteMetadata, RemoteReadDir};
+use write::WriteOptions;
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A change made through an AuditedGluster
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuditOp {
+    Create,
+    Write,
+    Copy,
+    Truncate,
+    Unlink,
+    Rename,
+    Link,
+    Symlink,
+    Mkdir,
+    Rmdir,
+    RemoveDirAll,
+    Chmod,
+    Chown,
+    SetXattr,
+    RemoveXattr,
+}
+
+impl fmt::Display for AuditOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            AuditOp::Create => "create",
+            AuditOp::Write => "write",
+            AuditOp::Copy => "copy",
+            AuditOp::Truncate => "truncate",
+            AuditOp::Unlink => "unlink",
+            AuditOp::Rename => "rename",
+            AuditOp::Link => "link",
+            AuditOp::Symlink => "symlink",
+            AuditOp::Mkdir => "mkdir",
+            AuditOp::Rmdir => "rmdir",
+            AuditOp::RemoveDirAll => "remove_dir_all",
+            AuditOp::Chmod => "chmod",
+            AuditOp::Chown => "chown",
+            AuditOp::SetXattr => "setxattr",
+            AuditOp::RemoveXattr => "removexattr",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One entry in the audit journal, written whether or not the change
+/// succeeded
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// Counts up from 0 for each AuditedGluster, so a missing or reordered
+    /// record shows as a gap
+    pub seq: u64,
+    pub timestamp: SystemTime,
+    pub op: AuditOp,
+    pub path: PathBuf,
+    /// The second path of a rename, link, symlink or copy
+    pub target: Option<PathBuf>,
+    /// Bytes written, or the length truncated to
+    pub size: Option<u64>,
+    /// Real uid of this process
+    pub uid: u32,
+    /// The errno the call failed with.  None on success and for failures
+    /// that didn't come from a system or gfapi call, a path that's too
+    /// long for example.
+    pub errno: Option<i32>,
+}
+
+impl fmt::Display for AuditRecord {
+    /// One line: seq, seconds since the epoch, uid, op, path, target,
+    /// size and the result, separated by tabs with - for anything missing
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "{}\t{}.{:03}\t{}\t{}\t{}\t",
+            self.seq,
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis(),
+            self.uid,
+            self.op,
+            self.path.display()
+        )?;
+        match self.target {
+            Some(ref target) => write!(f, "{}\t", target.display())?,
+            None => f.write_str("-\t")?,
+        }
+        match self.size {
+            Some(size) => write!(f, "{}\t", size)?,
+            None => f.write_str("-\t")?,
+        }
+        match self.errno {
+            Some(errno) => write!(f, "errno {}", errno),
+            None => f.write_str("ok"),
+        }
+    }
+}
+
+/// Where an AuditedGluster writes its records
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord) -> io::Result<()>;
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for &S {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        (**self).record(record)
+    }
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for Arc<S> {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        (**self).record(record)
+    }
+}
+
+/// Appends each record as a line to a local file, flushed after every
+/// record
+#[derive(Debug)]
+pub struct FileAuditSink {
+    file: Mutex<File>,
+}
+
+impl FileAuditSink {
+    /// Open path for appending, creating it if needed
+    pub fn open(path: &Path) -> io::Result<FileAuditSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileAuditSink {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        writeln!(file, "{}", record)?;
+        file.flush()
+    }
+}
+
+/// Keeps records in memory, for tests
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> MemoryAuditSink {
+        MemoryAuditSink::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<AuditRecord>> {
+        match self.records.lock() {
+            Ok(records) => records,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Everything recorded so far, in order
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.lock().clone()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        self.lock().push(record.clone());
+        Ok(())
+    }
+}
+
+/// What an AuditedGluster does when its sink fails to take a record
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SinkErrorPolicy {
+    /// Carry on as if the record was written
+    Ignore,
+    /// Log the failure with warn! and carry on
+    #[default]
+    Log,
+    /// Return the sink's error from the operation, even though the change
+    /// itself may have been made
+    Fail,
+}
+
+/// A connection that writes an AuditRecord to a sink for every change
+/// made through it, after the change is attempted.  Reads pass straight
+/// through and aren't recorded.
+pub struct AuditedGluster<'a> {
+    gluster: &'a Gluster,
+    sink: Box<dyn AuditSink + 'a>,
+    policy: SinkErrorPolicy,
+    seq: AtomicU64,
+}
+
+impl<'a> fmt::Debug for AuditedGluster<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuditedGluster")
+            .field("gluster", &self.gluster)
+            .field("policy", &self.policy)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+impl<'a> AuditedGluster<'a> {
+    pub fn new<S: AuditSink + 'a>(gluster: &'a Gluster, sink: S) -> AuditedGluster<'a> {
+        AuditedGluster {
+            gluster,
+            sink: Box::new(sink),
+            policy: SinkErrorPolicy::default(),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Defaults to SinkErrorPolicy::Log
+    pub fn sink_error_policy(mut self, policy: SinkErrorPolicy) -> AuditedGluster<'a> {
+        self.policy = policy;
+        self
+    }
+
+    /// The connection being audited.  Changes made through it directly
+    /// aren't recorded.
+    pub fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    // Record the outcome of op and hand back its result, unless the sink
+    // failed and the policy says to fail
+    fn audit<T>(
+        &self,
+        op: AuditOp,
+        path: &Path,
+        target: Option<&Path>,
+        size: Option<u64>,
+        attempt: Attempt<T>,
+    ) -> Result<T, GlusterError> {
+        let record = AuditRecord {
+            seq: self.seq.fetch_add(1, Ordering::SeqCst),
+            timestamp: SystemTime::now(),
+            op,
+            path: path.to_path_buf(),
+            target: target.map(Path::to_path_buf),
+            size,
+            uid: unsafe { libc::getuid() },
+            errno: attempt.errno,
+        };
+        if let Err(e) = self.sink.record(&record) {
+            match self.policy {
+                SinkErrorPolicy::Ignore => {}
+                SinkErrorPolicy::Log => warn!("failed to audit {}: {}", record, e),
+                SinkErrorPolicy::Fail => return Err(GlusterError::IoError(e)),
+            }
+        }
+        attempt.result
+    }
+
+    pub fn read_to_vec(&self, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        self.gluster.read_to_vec(path)
+    }
+
+    pub fn metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        self.gluster.metadata(path)
+    }
+
+    pub fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        self.gluster.exists(path)
+    }
+
+    pub fn list_dir(&self, path: &Path) -> Result<Vec<(DirEntry, Metadata)>, GlusterError> {
+        self.gluster.list_dir(path, 1)
+    }
+
+    /// Create a file.  Only the create is recorded, not what's written to
+    /// the file afterwards.
+    pub fn create_file(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+    ) -> Result<GlusterFile<'a>, GlusterError> {
+        let result = attempt(|| self.gluster.create_file(path, flags, mode));
+        self.audit(AuditOp::Create, path, None, None, result)
+    }
+
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.write_file(path, data));
+        self.audit(AuditOp::Write, path, None, Some(data.len() as u64), result)
+    }
+
+    /// Copy from to to, recorded as one copy with the number of bytes
+    /// copied
+    pub fn copy(&self, from: &Path, to: &Path, opts: &WriteOptions) -> Result<u64, GlusterError> {
+        let result = attempt(|| self.gluster.copy(from, to, opts));
+        let size = result.result.as_ref().ok().cloned();
+        self.audit(AuditOp::Copy, from, Some(to), size, result)
+    }
+
+    pub fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.truncate(path, length));
+        self.audit(AuditOp::Truncate, path, None, Some(length as u64), result)
+    }
+
+    pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.unlink(path));
+        self.audit(AuditOp::Unlink, path, None, None, result)
+    }
+
+    pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.rename(oldpath, newpath));
+        self.audit(AuditOp::Rename, oldpath, Some(newpath), None, result)
+    }
+
+    pub fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.link(oldpath, newpath));
+        self.audit(AuditOp::Link, oldpath, Some(newpath), None, result)
+    }
+
+    /// Recorded with the link as the path and what it points to as the
+    /// target
+    pub fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.symlink(oldpath, newpath));
+        self.audit(AuditOp::Symlink, newpath, Some(oldpath), None, result)
+    }
+
+    pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.mkdir(path, mode));
+        self.audit(AuditOp::Mkdir, path, None, None, result)
+    }
+
+    pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.rmdir(path));
+        self.audit(AuditOp::Rmdir, path, None, None, result)
+    }
+
+    /// Recorded as a single remove_dir_all of path, not each removal
+    pub fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.remove_dir_all(path));
+        self.audit(AuditOp::RemoveDirAll, path, None, None, result)
+    }
+
+    pub fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.chmod(path, mode));
+        self.audit(AuditOp::Chmod, path, None, None, result)
+    }
+
+    pub fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.chown(path, uid, gid));
+        self.audit(AuditOp::Chown, path, None, None, result)
+    }
+
+    pub fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.setxattr(path, name, value, flags));
+        let size = Some(value.len() as u64);
+        self.audit(AuditOp::SetXattr, path, None, size, result)
+    }
+
+    pub fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        let result = attempt(|| self.gluster.removexattr(path, name));
+        self.audit(AuditOp::RemoveXattr, path, None, None, result)
+    }
+}
+
+/// Changes are recorded as with the inherent methods, reads aren't
+impl<'a> RemoteFs for AuditedGluster<'a> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        io_result(|| self.read_to_vec(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        io_result(|| self.write_file(path, data))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let metadata = io_result(|| AuditedGluster::metadata(self, path))?;
+        Ok(remote_metadata(&metadata))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        Ok(remote_read_dir(io_result(|| self.list_dir(path))?))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.mkdir(path, 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        io_result(|| AuditedGluster::rename(self, from, to))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.unlink(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.rmdir(path))
+    }
+}
+
+// What an audited call returned, with the errno behind its failure
+struct Attempt<T> {
+    result: Result<T, GlusterError>,
+    errno: Option<i32>,
+}
+
+// Run op with errno cleared first, so that an errno left behind by a
+// failure was set by op rather than by something before it
+fn attempt<T, F: FnOnce() -> Result<T, GlusterError>>(op: F) -> Attempt<T> {
+    set_errno(Errno(0));
+    let result = op();
+    let errno = match result {
+        Ok(_) => None,
+        Err(GlusterError::IoError(ref e)) => e.raw_os_error(),
+        // Made by get_error from errno where the call failed
+        Err(GlusterError::Error(_)) if errno().0 != 0 => Some(errno().0),
+        Err(_) => None,
+    };
+    Attempt { result, errno }
+}
+
+impl Gluster {
+    /// Wrap this connection so every change made through the wrapper is
+    /// recorded to sink.  See AuditedGluster.
+    pub fn audited<'a, S: AuditSink + 'a>(&'a self, sink: S) -> AuditedGluster<'a> {
+        AuditedGluster::new(self, sink)
+    }
+}
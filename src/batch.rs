@@ -0,0 +1,181 @@
+use errno::errno;
+use libc::ENOENT;
+
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Options controlling unlink_many and rmdir_many
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    /// Count paths that are already gone (ENOENT) as removed
+    pub ignore_missing: bool,
+    /// Number of worker threads to spread the calls over.  1 runs the
+    /// batch serially on the calling thread.
+    pub workers: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> BatchOptions {
+        BatchOptions {
+            ignore_missing: false,
+            workers: 1,
+        }
+    }
+}
+
+/// What happened to a single path in a batch
+#[derive(Debug)]
+pub enum BatchStatus {
+    Removed,
+    /// The path didn't exist and ignore_missing was set
+    Missing,
+    /// The call failed.  errno is the raw error code gfapi reported.
+    Failed { errno: i32, error: GlusterError },
+}
+
+impl BatchStatus {
+    pub fn is_ok(&self) -> bool {
+        !matches!(*self, BatchStatus::Failed { .. })
+    }
+}
+
+#[derive(Debug)]
+pub struct BatchOutcome {
+    pub path: PathBuf,
+    pub status: BatchStatus,
+}
+
+/// Per path outcomes of a batch, in the same order as the input paths
+#[derive(Debug)]
+pub struct BatchResult {
+    pub outcomes: Vec<BatchOutcome>,
+    pub removed: usize,
+    pub missing: usize,
+    pub failed: usize,
+}
+
+impl BatchResult {
+    /// True when no path failed
+    pub fn is_ok(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// Iterate over just the paths that failed
+    pub fn failures(&self) -> impl Iterator<Item = &BatchOutcome> {
+        self.outcomes.iter().filter(|o| !o.status.is_ok())
+    }
+}
+
+/// Run f over every item using up to workers threads and return the
+/// results in input order.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = workers.max(1).min(items.len());
+    if workers <= 1 {
+        return items.iter().map(&f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= items.len() {
+                    break;
+                }
+                let result = f(&items[i]);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
+
+fn run_batch<'a, I, F>(paths: I, opts: &BatchOptions, op: F) -> BatchResult
+where
+    I: IntoIterator<Item = &'a Path>,
+    F: Fn(&Path) -> Result<(), GlusterError> + Sync,
+{
+    let paths: Vec<&Path> = paths.into_iter().collect();
+    let outcomes = parallel_map(&paths, opts.workers, |path| {
+        let status = match op(path) {
+            Ok(_) => BatchStatus::Removed,
+            Err(e) => {
+                // errno is thread local so this is still the failed call's
+                let error_code = errno().0;
+                if error_code == ENOENT && opts.ignore_missing {
+                    BatchStatus::Missing
+                } else {
+                    BatchStatus::Failed {
+                        errno: error_code,
+                        error: e,
+                    }
+                }
+            }
+        };
+        BatchOutcome {
+            path: path.to_path_buf(),
+            status,
+        }
+    });
+    let mut result = BatchResult {
+        outcomes: Vec::with_capacity(paths.len()),
+        removed: 0,
+        missing: 0,
+        failed: 0,
+    };
+    for outcome in outcomes {
+        match outcome.status {
+            BatchStatus::Removed => result.removed += 1,
+            BatchStatus::Missing => result.missing += 1,
+            BatchStatus::Failed { .. } => result.failed += 1,
+        }
+        result.outcomes.push(outcome);
+    }
+    result
+}
+
+impl Gluster {
+    /// Unlink every path, carrying on past failures.  The result records
+    /// what happened to each path so nothing gets lost.
+    pub fn unlink_many<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Path>,
+        opts: &BatchOptions,
+    ) -> BatchResult {
+        run_batch(paths, opts, |p| self.unlink(p))
+    }
+
+    /// Remove every (empty) directory, carrying on past failures.
+    pub fn rmdir_many<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Path>,
+        opts: &BatchOptions,
+    ) -> BatchResult {
+        run_batch(paths, opts, |p| self.rmdir(p))
+    }
+
+    /// Stat every path using up to concurrency threads sharing this
+    /// connection.  Results are returned in the same order as paths.
+    pub fn stat_many(
+        &self,
+        paths: &[PathBuf],
+        concurrency: usize,
+    ) -> Vec<Result<Metadata, GlusterError>> {
+        parallel_map(paths, concurrency, |p| self.metadata(p))
+    }
+}
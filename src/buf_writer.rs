@@ -0,0 +1,165 @@
+use cleanup::{self, DropError, DropTarget};
+use file::GlusterFile;
+use gluster::GlusterError;
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// Buffers small writes in memory and hands them to gfapi as one large
+/// write, saving a network round trip per write.  This is the gfapi
+/// equivalent of std::io::BufWriter.
+///
+/// Writes at least as large as the buffer skip it and go straight to the
+/// file.  flush() writes out the buffer and, if sync_on_flush is set,
+/// follows it with an fdatasync.  Dropping the writer flushes it too but
+/// any error can only be reported through cleanup::DropError; call
+/// flush() or into_parts() first if the outcome matters.
+pub struct GlusterBufWriter<'a> {
+    file: Option<GlusterFile<'a>>,
+    buffer: Vec<u8>,
+    capacity: usize,
+    sync_on_flush: bool,
+    writes: u64,
+}
+
+impl<'a> GlusterBufWriter<'a> {
+    /// Wrap file with a 1MB buffer
+    pub fn new(file: GlusterFile<'a>) -> GlusterBufWriter<'a> {
+        GlusterBufWriter::with_capacity(1024 * 1024, file)
+    }
+
+    pub fn with_capacity(capacity: usize, file: GlusterFile<'a>) -> GlusterBufWriter<'a> {
+        GlusterBufWriter {
+            file: Some(file),
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            sync_on_flush: false,
+            writes: 0,
+        }
+    }
+
+    /// fdatasync the file every time the writer is explicitly flushed
+    pub fn sync_on_flush(mut self, sync: bool) -> GlusterBufWriter<'a> {
+        self.sync_on_flush = sync;
+        self
+    }
+
+    pub fn get_ref(&self) -> &GlusterFile<'a> {
+        self.file.as_ref().expect("file is present until into_parts")
+    }
+
+    /// Bytes written to the writer but not yet to the file
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many writes have been handed to the file so far, to see how
+    /// well small writes are being coalesced
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Flush the buffer and return the file.  On failure the writer is
+    /// handed back along with the error so no data is lost.
+    pub fn into_inner(mut self) -> Result<GlusterFile<'a>, (io::Error, GlusterBufWriter<'a>)> {
+        match self.flush_buf() {
+            Ok(_) => Ok(self.file.take().expect("file is present until into_parts")),
+            Err(e) => Err((e, self)),
+        }
+    }
+
+    /// Take the file and whatever is still buffered without writing
+    /// anything.  Useful for recovering data after a failed flush.
+    pub fn into_parts(mut self) -> (GlusterFile<'a>, Vec<u8>) {
+        let buffer = ::std::mem::take(&mut self.buffer);
+        let file = self.file.take().expect("file is present until into_parts");
+        (file, buffer)
+    }
+
+    // Write out the buffer, keeping whatever couldn't be written
+    fn flush_buf(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let mut result = Ok(());
+        {
+            let file = self.file.as_mut().expect("file is present until into_parts");
+            while written < self.buffer.len() {
+                self.writes += 1;
+                match file.write(&self.buffer[written..]) {
+                    Ok(0) => {
+                        result = Err(io::Error::new(
+                            io::ErrorKind::WriteZero,
+                            "failed to write the buffered data",
+                        ));
+                        break;
+                    }
+                    Ok(n) => written += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+        self.buffer.drain(..written);
+        result
+    }
+}
+
+impl<'a> Write for GlusterBufWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() + buf.len() > self.capacity {
+            self.flush_buf()?;
+        }
+        if buf.len() >= self.capacity {
+            // Too big to be worth buffering
+            self.writes += 1;
+            self.file
+                .as_mut()
+                .expect("file is present until into_parts")
+                .write(buf)
+        } else {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        if self.sync_on_flush {
+            self.get_ref()
+                .fdatasync()
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for GlusterBufWriter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GlusterBufWriter")
+            .field("file", &self.file)
+            .field("buffered", &self.buffer.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl<'a> Drop for GlusterBufWriter<'a> {
+    fn drop(&mut self) {
+        if self.file.is_none() || self.buffer.is_empty() {
+            return;
+        }
+        if let Err(error) = self.flush_buf() {
+            cleanup::report(DropError {
+                target: DropTarget::BufferedWrites,
+                path: self.file.as_ref().map(|file| file.path().to_path_buf()),
+                error: GlusterError::IoError(error),
+            });
+        }
+    }
+}
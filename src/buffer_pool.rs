@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// Buffers are rounded up to one of these sizes.  Anything bigger than the
+// last is allocated and freed as usual.
+const SIZE_CLASSES: [usize; 3] = [64 * 1024, 1024 * 1024, 4 * 1024 * 1024];
+// Most buffers of one size a thread keeps
+const PER_THREAD: usize = 8;
+const DEFAULT_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BYTES);
+// Bytes sitting idle in every thread's stacks
+static POOLED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+static REUSED: AtomicU64 = AtomicU64::new(0);
+static DISCARDED: AtomicU64 = AtomicU64::new(0);
+
+// One thread's idle buffers, given back to the allocator when the thread
+// exits
+struct Stacks([Vec<Box<[u8]>>; 3]);
+
+impl Drop for Stacks {
+    fn drop(&mut self) {
+        for (class, stack) in self.0.iter().enumerate() {
+            POOLED_BYTES.fetch_sub(stack.len() * SIZE_CLASSES[class], Ordering::SeqCst);
+        }
+    }
+}
+
+thread_local! {
+    static STACKS: RefCell<Stacks> = const { RefCell::new(Stacks([Vec::new(), Vec::new(), Vec::new()])) };
+}
+
+fn size_class(len: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|&size| size >= len)
+}
+
+/// Counters for the buffer pool, covering every thread
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    /// Buffers that had to be allocated
+    pub allocated: u64,
+    /// Buffers handed out again from a thread's stack
+    pub reused: u64,
+    /// Buffers freed on return because the pool was full
+    pub discarded: u64,
+    /// Bytes held idle right now
+    pub pooled_bytes: usize,
+}
+
+/// Handle to the process wide pool of byte buffers used by the data path
+/// helpers (copy, write_from_reader, read_to_writer and write
+/// verification).  Each thread keeps its own stacks of idle buffers in a
+/// few sizes so taking one doesn't contend with other threads, and the
+/// total kept idle across threads is capped.  Every handle refers to the
+/// same pool.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferPool;
+
+impl BufferPool {
+    pub fn global() -> BufferPool {
+        BufferPool
+    }
+
+    /// A buffer of exactly len bytes.  It's returned to the calling
+    /// thread's pool when dropped.  The contents are whatever the last
+    /// user left in it, not zeros.
+    pub fn get(&self, len: usize) -> PooledBuffer {
+        let class = match size_class(len) {
+            Some(class) => class,
+            None => {
+                ALLOCATED.fetch_add(1, Ordering::Relaxed);
+                return PooledBuffer {
+                    buffer: Some(vec![0; len].into_boxed_slice()),
+                    len,
+                    class: None,
+                };
+            }
+        };
+        let reused = STACKS
+            .try_with(|stacks| stacks.borrow_mut().0[class].pop())
+            .ok()
+            .and_then(|buffer| buffer);
+        let buffer = match reused {
+            Some(buffer) => {
+                POOLED_BYTES.fetch_sub(SIZE_CLASSES[class], Ordering::SeqCst);
+                REUSED.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                ALLOCATED.fetch_add(1, Ordering::Relaxed);
+                vec![0; SIZE_CLASSES[class]].into_boxed_slice()
+            }
+        };
+        PooledBuffer {
+            buffer: Some(buffer),
+            len,
+            class: Some(class),
+        }
+    }
+
+    /// Cap the bytes kept idle across all threads.  Buffers returned while
+    /// the pool is at the cap are freed.  Defaults to 256MiB.
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        MAX_BYTES.store(max_bytes, Ordering::SeqCst);
+    }
+
+    /// Free the calling thread's idle buffers
+    pub fn clear_thread(&self) {
+        let _ = STACKS.try_with(|stacks| {
+            let mut stacks = stacks.borrow_mut();
+            for (class, stack) in stacks.0.iter_mut().enumerate() {
+                POOLED_BYTES.fetch_sub(stack.len() * SIZE_CLASSES[class], Ordering::SeqCst);
+                stack.clear();
+            }
+        });
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            allocated: ALLOCATED.load(Ordering::Relaxed),
+            reused: REUSED.load(Ordering::Relaxed),
+            discarded: DISCARDED.load(Ordering::Relaxed),
+            pooled_bytes: POOLED_BYTES.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// A buffer from a BufferPool, returned to the pool when dropped however
+/// the code using it exits
+pub struct PooledBuffer {
+    buffer: Option<Box<[u8]>>,
+    len: usize,
+    class: Option<usize>,
+}
+
+impl fmt::Debug for PooledBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledBuffer")
+            .field("len", &self.len)
+            .field("class", &self.class)
+            .finish()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.buffer {
+            Some(ref buffer) => &buffer[..self.len],
+            None => &[],
+        }
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self.buffer {
+            Some(ref mut buffer) => &mut buffer[..self.len],
+            None => &mut [],
+        }
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let (buffer, class) = match (self.buffer.take(), self.class) {
+            (Some(buffer), Some(class)) => (buffer, class),
+            _ => return,
+        };
+        let size = SIZE_CLASSES[class];
+        let max = MAX_BYTES.load(Ordering::SeqCst);
+        if POOLED_BYTES.fetch_add(size, Ordering::SeqCst) + size > max {
+            POOLED_BYTES.fetch_sub(size, Ordering::SeqCst);
+            DISCARDED.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let kept = STACKS.try_with(|stacks| {
+            let mut stacks = stacks.borrow_mut();
+            let stack = &mut stacks.0[class];
+            if stack.len() < PER_THREAD {
+                stack.push(buffer);
+                true
+            } else {
+                false
+            }
+        });
+        if !matches!(kept, Ok(true)) {
+            // This thread's stack is full or already torn down
+            POOLED_BYTES.fetch_sub(size, Ordering::SeqCst);
+            DISCARDED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
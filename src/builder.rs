@@ -0,0 +1,553 @@
+use errno::errno;
+use glfs::*;
+use libc::{c_int, EACCES, ENOENT, EPERM};
+
+use gluster::{get_error, Gluster, GlusterError, GlusterLogLevel};
+use tls::TlsOptions;
+use tuning::{TuningProfile, XlatorOption};
+use watchdog::{Watchdog, WatchdogOptions};
+
+use std::ffi::CString;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+// glusterd's port, used when a server list entry doesn't give one
+const DEFAULT_PORT: u16 = 24007;
+
+/// A volfile server host and port, as parsed by parse_servers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolfileServer {
+    /// Host name or address, IPv6 addresses without brackets
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for VolfileServer {
+    /// host:port, with IPv6 addresses in brackets, which parse_servers
+    /// reads back
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+fn parse_port(port: &str) -> Option<u16> {
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match port.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(port) => Some(port),
+    }
+}
+
+fn parse_server(token: &str, position: usize) -> Result<VolfileServer, GlusterError> {
+    let invalid = |reason| GlusterError::InvalidServer {
+        token: token.to_string(),
+        position,
+        reason,
+    };
+    if token.is_empty() {
+        return Err(invalid("empty entry"));
+    }
+    let (host, port) = if let Some(rest) = token.strip_prefix('[') {
+        let close = rest.find(']').ok_or_else(|| invalid("unclosed ["))?;
+        let host = &rest[..close];
+        if host.parse::<Ipv6Addr>().is_err() {
+            return Err(invalid("not an IPv6 address inside []"));
+        }
+        let port = match &rest[close + 1..] {
+            "" => None,
+            after => Some(
+                after
+                    .strip_prefix(':')
+                    .ok_or_else(|| invalid("expected :port after ]"))?,
+            ),
+        };
+        (host, port)
+    } else {
+        let mut parts = token.splitn(2, ':');
+        let host = parts.next().unwrap_or("");
+        let port = parts.next();
+        if port.is_some_and(|port| port.contains(':')) {
+            return Err(invalid("IPv6 addresses need brackets, as in [::1]:24007"));
+        }
+        (host, port)
+    };
+    if host.is_empty() {
+        return Err(invalid("missing host"));
+    }
+    if host.chars().any(char::is_whitespace) {
+        return Err(invalid("host contains whitespace"));
+    }
+    let port = match port {
+        Some(port) => {
+            parse_port(port).ok_or_else(|| invalid("port isn't a number from 1 to 65535"))?
+        }
+        None => DEFAULT_PORT,
+    };
+    Ok(VolfileServer {
+        host: host.to_string(),
+        port,
+    })
+}
+
+// The transports glfs_set_volfile_server accepts
+pub(crate) fn valid_transport(transport: &str) -> bool {
+    ["tcp", "unix", "rdma"].contains(&transport)
+}
+
+/// Parse a comma separated list of volfile servers such as
+/// "host1:24007, host2,[fd00::1]:24008".  Each entry is a host with an
+/// optional :port, 24007 if it's left out, and whitespace around entries
+/// is ignored.  IPv6 addresses go in brackets.  Fails with
+/// GlusterError::InvalidServer naming the first bad entry, including
+/// empty ones from a stray comma.
+pub fn parse_servers(list: &str) -> Result<Vec<VolfileServer>, GlusterError> {
+    list.split(',')
+        .enumerate()
+        .map(|(position, token)| parse_server(token.trim(), position))
+        .collect()
+}
+
+/// Connection settings for a Gluster volume.  Created with
+/// Gluster::builder.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlusterBuilder {
+    pub(crate) volume: String,
+    pub(crate) subdir: Option<PathBuf>,
+    pub(crate) server: String,
+    pub(crate) port: u16,
+    pub(crate) backup_servers: Vec<VolfileServer>,
+    volfile: Option<PathBuf>,
+    pub(crate) transport: String,
+    tls: Option<TlsOptions>,
+    tuning: TuningProfile,
+    xlator_options: Vec<XlatorOption>,
+    // Log file, None for gfapi's default, and level
+    pub(crate) logging: Option<(Option<PathBuf>, GlusterLogLevel)>,
+    pub(crate) read_only: bool,
+    pub(crate) max_path_len: usize,
+    pub(crate) max_name_len: usize,
+    pub(crate) strict_utf8: bool,
+    pub(crate) watchdog: Option<WatchdogOptions>,
+}
+
+impl GlusterBuilder {
+    /// volume may be a plain volume name or volume/subdir to mount a
+    /// subdirectory of the volume, same as the FUSE client accepts.
+    pub fn new(volume: &str) -> GlusterBuilder {
+        let mut parts = volume.trim_matches('/').splitn(2, '/');
+        let name = parts.next().unwrap_or("").to_string();
+        let subdir = parts.next().map(PathBuf::from);
+        GlusterBuilder {
+            volume: name,
+            subdir,
+            server: "localhost".to_string(),
+            port: DEFAULT_PORT,
+            backup_servers: Vec::new(),
+            volfile: None,
+            transport: "tcp".to_string(),
+            tls: None,
+            tuning: TuningProfile::Default,
+            xlator_options: Vec::new(),
+            logging: None,
+            read_only: false,
+            max_path_len: 4096,
+            max_name_len: 255,
+            strict_utf8: false,
+            watchdog: None,
+        }
+    }
+
+    /// Host of the volfile server.  Defaults to localhost.
+    pub fn server(mut self, server: &str) -> GlusterBuilder {
+        self.server = server.to_string();
+        self
+    }
+
+    /// Port of the volfile server.  Defaults to 24007.
+    pub fn port(mut self, port: u16) -> GlusterBuilder {
+        self.port = port;
+        self
+    }
+
+    /// Another volfile server for gfapi to fall back to if the ones before
+    /// it can't be reached, tried in the order added
+    pub fn backup_server(mut self, server: &str, port: u16) -> GlusterBuilder {
+        self.backup_servers.push(VolfileServer {
+            host: server.to_string(),
+            port,
+        });
+        self
+    }
+
+    /// Set the volfile servers from a list in the form parse_servers
+    /// takes, as deployments often keep them in one setting.  The first
+    /// becomes server and port, the rest replace any backup servers.
+    pub fn servers_from_str(mut self, list: &str) -> Result<GlusterBuilder, GlusterError> {
+        let mut servers = parse_servers(list)?.into_iter();
+        // split always yields at least one entry, and empty ones fail
+        let first = servers.next().unwrap();
+        self.server = first.host;
+        self.port = first.port;
+        self.backup_servers = servers.collect();
+        Ok(self)
+    }
+
+    /// Build the client graph from this volfile on the local filesystem
+    /// instead of fetching it from a server, in which case the server
+    /// settings are ignored
+    pub fn volfile<P: AsRef<Path>>(mut self, volfile: P) -> GlusterBuilder {
+        self.volfile = Some(volfile.as_ref().to_path_buf());
+        self
+    }
+
+    /// Transport used to fetch the volfile, tcp, unix or rdma.  Defaults
+    /// to tcp.
+    pub fn transport(mut self, transport: &str) -> GlusterBuilder {
+        self.transport = transport.to_string();
+        self
+    }
+
+    /// Mount this subdirectory of the volume instead of its root.  "/" on
+    /// the resulting connection is this directory and nothing outside of
+    /// it can be reached.  The volume has to allow subdirectory mounts
+    /// for this client in auth.allow.
+    pub fn subdir<P: AsRef<Path>>(mut self, subdir: P) -> GlusterBuilder {
+        self.subdir = Some(subdir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Connect with TLS.  The certificate files are checked before
+    /// connecting.  Volumes with client.ssl on refuse connections without
+    /// this.
+    pub fn tls(mut self, tls: TlsOptions) -> GlusterBuilder {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Client side performance options to apply on top of the volfile.
+    /// Defaults to TuningProfile::Default, which changes nothing.  The
+    /// options that were set can be seen with
+    /// Gluster::applied_xlator_options.
+    pub fn tuning(mut self, tuning: TuningProfile) -> GlusterBuilder {
+        self.tuning = tuning;
+        self
+    }
+
+    /// Set key to value on the xlators matching xlator, a glob such as
+    /// "*-write-behind", before the connection is initialized.  Options
+    /// are applied in the order added, after the tuning profile's, so
+    /// they can override it.
+    pub fn xlator_option(mut self, xlator: &str, key: &str, value: &str) -> GlusterBuilder {
+        self.xlator_options
+            .push(XlatorOption::new(xlator, key, value));
+        self
+    }
+
+    /// Have gfapi log at level to logfile from the start of the
+    /// connection, so problems fetching the volfile are logged too.  The
+    /// same as calling Gluster::set_logging right after connecting
+    /// otherwise.
+    pub fn logging<P: AsRef<Path>>(mut self, logfile: P, level: GlusterLogLevel) -> GlusterBuilder {
+        self.logging = Some((Some(logfile.as_ref().to_path_buf()), level));
+        self
+    }
+
+    /// Have gfapi log at level, to the log file given to logging or
+    /// gfapi's default log file if there wasn't one
+    pub fn log_level(mut self, level: GlusterLogLevel) -> GlusterBuilder {
+        let logfile = self.logging.take().and_then(|(logfile, _)| logfile);
+        self.logging = Some((logfile, level));
+        self
+    }
+
+    /// Make every call that could modify the volume fail with
+    /// GlusterError::ReadOnly before it reaches gfapi, whatever the
+    /// server would allow.  Files can only be opened O_RDONLY.  Defaults
+    /// to false.
+    pub fn read_only(mut self, read_only: bool) -> GlusterBuilder {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Longest path in bytes, and longest single component of one, that
+    /// calls will pass to gfapi.  Anything longer fails locally with
+    /// GlusterError::PathTooLong instead of ENAMETOOLONG from the server.
+    /// Defaults to 4096 and 255, Linux's PATH_MAX and NAME_MAX.
+    pub fn path_limits(mut self, max_path_len: usize, max_name_len: usize) -> GlusterBuilder {
+        self.max_path_len = max_path_len;
+        self.max_name_len = max_name_len;
+        self
+    }
+
+    /// Make the calls that return text, such as getxattr, listxattr,
+    /// getcwd and volfile, fail with GlusterError::FromUtf8Error if it
+    /// isn't valid UTF-8 instead of replacing the bad bytes with U+FFFD.
+    /// The original bytes can be had from the error with into_bytes.
+    /// Calls like getxattr_bytes that return bytes are unaffected and
+    /// remain the better choice for values that may not be text.
+    /// Defaults to false.
+    pub fn strict_utf8(mut self, strict: bool) -> GlusterBuilder {
+        self.strict_utf8 = strict;
+        self
+    }
+
+    /// Watch for calls that spend longer than opts' threshold inside
+    /// gfapi and report them, and keep the calls in flight for
+    /// Gluster::in_flight.  Off by default, which costs nothing.
+    pub fn watchdog(mut self, opts: WatchdogOptions) -> GlusterBuilder {
+        self.watchdog = Some(opts);
+        self
+    }
+
+    // The name handed to glfs_new, volume or volume/subdir
+    fn volume_spec(&self) -> Result<String, GlusterError> {
+        if self.volume.is_empty() {
+            return Err(GlusterError::new("volume name is empty".to_string()));
+        }
+        let subdir = match self.subdir {
+            Some(ref subdir) => subdir,
+            None => return Ok(self.volume.clone()),
+        };
+        let mut parts = Vec::new();
+        for component in subdir.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+                Component::RootDir | Component::CurDir => {}
+                _ => {
+                    return Err(GlusterError::new(format!(
+                        "invalid subdirectory {}",
+                        subdir.display()
+                    )))
+                }
+            }
+        }
+        if parts.is_empty() {
+            return Ok(self.volume.clone());
+        }
+        Ok(format!("{}/{}", self.volume, parts.join("/")))
+    }
+
+    /// Check the settings make sense without calling into gfapi.  connect
+    /// does this first, so a builder with no server, an unknown transport
+    /// or an empty xlator option fails with an error saying so rather
+    /// than whatever glfs_init makes of it.
+    pub fn validate(&self) -> Result<(), GlusterError> {
+        self.volume_spec()?;
+        if self.volfile.is_none() {
+            if self.server.is_empty() {
+                return Err(GlusterError::new(
+                    "no volfile server configured, set one with server or \
+                     servers_from_str, or use a local volfile"
+                        .to_string(),
+                ));
+            }
+            if let Some(backup) = self.backup_servers.iter().find(|s| s.host.is_empty()) {
+                return Err(GlusterError::new(format!(
+                    "backup volfile server with port {} has no host",
+                    backup.port
+                )));
+            }
+            if !valid_transport(&self.transport) {
+                return Err(GlusterError::new(format!(
+                    "unknown transport {:?}, expected tcp, unix or rdma",
+                    self.transport
+                )));
+            }
+        }
+        if let Some(option) = self
+            .xlator_options
+            .iter()
+            .find(|o| o.xlator.is_empty() || o.key.is_empty())
+        {
+            return Err(GlusterError::new(format!(
+                "xlator option {} needs both an xlator and a key",
+                option
+            )));
+        }
+        Ok(())
+    }
+
+    /// Connect to the volume
+    pub fn connect(&self) -> Result<Gluster, GlusterError> {
+        self.validate()?;
+        let volume_spec = self.volume_spec()?;
+        if let Some(ref tls) = self.tls {
+            tls.validate()?;
+        }
+        let mut xlator_options = self.tuning.options()?;
+        xlator_options.extend(self.xlator_options.iter().cloned());
+        let vol_name = CString::new(volume_spec.clone())?;
+        unsafe {
+            let cluster_handle = glfs_new(vol_name.as_ptr());
+            if cluster_handle.is_null() {
+                return Err(GlusterError::new("glfs_new failed".to_string()));
+            }
+            // Owning the handle straight away means it's cleaned up with
+            // glfs_fini on every error path below
+            let gluster = Gluster {
+                cluster_handle,
+                read_only: self.read_only,
+                config: self.clone(),
+                logging: Mutex::new(None),
+                xlator_options: Mutex::new(Vec::new()),
+                volfile_servers: Mutex::new(Vec::new()),
+                watchdog: self.watchdog.as_ref().map(Watchdog::start),
+            };
+            match self.logging {
+                Some((Some(ref logfile), level)) => {
+                    gluster.set_logging_level(logfile, level as i32)?
+                }
+                Some((None, level)) => {
+                    // gfapi picks its default log file when given none
+                    let ret_code = glfs_set_logging(cluster_handle, ptr::null(), level as c_int);
+                    if ret_code < 0 {
+                        return Err(GlusterError::new(get_error()));
+                    }
+                }
+                None => {}
+            }
+            if let Some(ref volfile) = self.volfile {
+                let volfile = CString::new(volfile.as_os_str().as_bytes())?;
+                if glfs_set_volfile(cluster_handle, volfile.as_ptr()) < 0 {
+                    return Err(GlusterError::new(get_error()));
+                }
+            } else {
+                gluster.add_volfile_server(&self.server, self.port, &self.transport)?;
+                // Each further one is a server for gfapi to fail over to
+                for backup in &self.backup_servers {
+                    gluster.add_volfile_server(&backup.host, backup.port, &self.transport)?;
+                }
+            }
+            if let Some(ref tls) = self.tls {
+                tls.apply(&gluster)?;
+            }
+            for option in &xlator_options {
+                gluster.set_xlator_option(&option.xlator, &option.key, &option.value)?;
+            }
+
+            let ret_code = glfs_init(cluster_handle);
+            if ret_code < 0 {
+                let error = errno();
+                if self.subdir.is_some() && [EACCES, EPERM, ENOENT].contains(&error.0) {
+                    return Err(GlusterError::new(format!(
+                        "mounting {} was refused: {}.  Check that the directory exists \
+                         and that auth.allow on volume {} permits subdirectory mounts \
+                         for this client",
+                        volume_spec, error, self.volume
+                    )));
+                }
+                if let Some(ref tls) = self.tls {
+                    return Err(GlusterError::new(format!(
+                        "connecting to {} with TLS failed: {}.  Using {}.  Check the \
+                         certificate is signed by a CA the servers trust, its common name \
+                         is listed in auth.ssl-allow and the volume has client.ssl on",
+                        volume_spec,
+                        error,
+                        tls.describe()
+                    )));
+                }
+                return Err(GlusterError::new(format!("{}", error)));
+            }
+            Ok(gluster)
+        }
+    }
+}
+
+impl Gluster {
+    /// Start building a connection to volume.  See GlusterBuilder::new for
+    /// the volume/subdir form.
+    pub fn builder(volume: &str) -> GlusterBuilder {
+        GlusterBuilder::new(volume)
+    }
+
+    /// Add a volfile server for gfapi to fetch volume changes from, or
+    /// fail over to if the others can't be reached.  Works on a connected
+    /// volume as well as during connect.  transport is tcp, unix or rdma.
+    pub fn add_volfile_server(
+        &self,
+        host: &str,
+        port: u16,
+        transport: &str,
+    ) -> Result<(), GlusterError> {
+        let host_c = CString::new(host)?;
+        let transport_c = CString::new(transport)?;
+        unsafe {
+            let ret_code = glfs_set_volfile_server(
+                self.cluster_handle,
+                transport_c.as_ptr(),
+                host_c.as_ptr(),
+                port as c_int,
+            );
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+        }
+        self.lock_volfile_servers().push((
+            transport.to_string(),
+            VolfileServer {
+                host: host.to_string(),
+                port,
+            },
+        ));
+        Ok(())
+    }
+
+    /// Stop using a volfile server, such as a decommissioned management
+    /// node.  Fails with gfapi's error if it isn't one this connection
+    /// was using.
+    pub fn remove_volfile_server(
+        &self,
+        host: &str,
+        port: u16,
+        transport: &str,
+    ) -> Result<(), GlusterError> {
+        let host_c = CString::new(host)?;
+        let transport_c = CString::new(transport)?;
+        unsafe {
+            let ret_code = glfs_unset_volfile_server(
+                self.cluster_handle,
+                transport_c.as_ptr(),
+                host_c.as_ptr(),
+                port as c_int,
+            );
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+        }
+        let mut servers = self.lock_volfile_servers();
+        if let Some(index) = servers
+            .iter()
+            .position(|(t, s)| t == transport && s.host == host && s.port == port)
+        {
+            servers.remove(index);
+        }
+        Ok(())
+    }
+
+    /// The volfile servers this connection is using, from the builder
+    /// and add_volfile_server, in the order they were added.  Empty when
+    /// connected with a local volfile.
+    pub fn volfile_servers(&self) -> Vec<VolfileServer> {
+        self.lock_volfile_servers()
+            .iter()
+            .map(|(_, server)| server.clone())
+            .collect()
+    }
+
+    fn lock_volfile_servers(&self) -> MutexGuard<'_, Vec<(String, VolfileServer)>> {
+        match self.volfile_servers.lock() {
+            Ok(servers) => servers,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
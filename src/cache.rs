@@ -0,0 +1,449 @@
+use errno::{errno, set_errno, Errno};
+use file::GlusterFile;
+use gluster::{DirEntry, Gluster, GlusterError};
+use libc::{mode_t, ENOENT, O_CREAT};
+use metadata::Metadata;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits and validation policy for CachedGluster
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    /// Upper bound on the total size of all cached file contents
+    pub max_bytes: usize,
+    /// Upper bound on the number of cached files
+    pub max_entries: usize,
+    /// Within this long of the last validation, cached bytes are served
+    /// without a stat.  None always validates with a stat first.
+    pub ttl: Option<Duration>,
+    /// How long a directory listing from list_dir is served from memory.
+    /// None disables the listing cache.
+    pub listing_ttl: Option<Duration>,
+    /// Upper bound on the number of cached directory listings
+    pub max_listings: usize,
+    /// How long a path that stat or open found missing (ENOENT) is
+    /// reported missing again without asking the volume.  Creating,
+    /// writing or renaming onto the path or a directory above it through
+    /// the wrapper forgets it at once, changes by other clients need
+    /// invalidate or the ttl to expire.  None, the default, disables the
+    /// negative cache.
+    pub negative_ttl: Option<Duration>,
+    /// Upper bound on the number of remembered missing paths
+    pub max_negative: usize,
+}
+
+impl Default for CacheOptions {
+    fn default() -> CacheOptions {
+        CacheOptions {
+            max_bytes: 64 * 1024 * 1024,
+            max_entries: 1024,
+            ttl: None,
+            listing_ttl: None,
+            max_listings: 128,
+            negative_ttl: None,
+            max_negative: 4096,
+        }
+    }
+}
+
+/// Counters describing how effective the cache has been
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads that went to the volume
+    pub misses: u64,
+    /// Stats issued to validate a cached entry
+    pub validations: u64,
+    /// Entries dropped to stay under the configured limits
+    pub evictions: u64,
+    /// list_dir calls served from the listing cache
+    pub listing_hits: u64,
+    /// list_dir calls that read the directory from the volume
+    pub listing_misses: u64,
+    /// Lookups answered ENOENT from the negative cache
+    pub negative_hits: u64,
+    /// Lookups that found nothing on the volume and were remembered
+    pub negative_inserts: u64,
+}
+
+struct CacheEntry {
+    data: Vec<u8>,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    validated_at: Instant,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn matches(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && self.mtime == metadata.mtime()
+            && self.mtime_nsec == metadata.mtime_nsec()
+    }
+}
+
+struct CachedListing {
+    entries: Vec<(DirEntry, Metadata)>,
+    fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<PathBuf, CacheEntry>,
+    total_bytes: usize,
+    clock: u64,
+    listings: HashMap<PathBuf, CachedListing>,
+    // Paths found missing and when
+    negatives: HashMap<PathBuf, Instant>,
+}
+
+impl LruState {
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.total_bytes -= entry.data.len();
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// A wrapper around a connection that caches the contents of small files
+/// in memory.  Entries are validated against the file's current size and
+/// mtime before being served, so changes made by other clients are seen
+/// as soon as the next read (or once the ttl expires, if one is set).
+/// Writes, unlinks and renames through the wrapper invalidate the cache.
+///
+/// Directory listings can optionally be cached too, see
+/// CacheOptions::listing_ttl.  Mutations made through the wrapper drop the
+/// listing of the parent directory right away.  Changes made by other
+/// clients are only noticed once the ttl expires since upcall
+/// notifications aren't bound by this crate.
+pub struct CachedGluster<'a> {
+    gluster: &'a Gluster,
+    opts: CacheOptions,
+    state: Mutex<LruState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    validations: AtomicU64,
+    evictions: AtomicU64,
+    listing_hits: AtomicU64,
+    listing_misses: AtomicU64,
+    negative_hits: AtomicU64,
+    negative_inserts: AtomicU64,
+}
+
+// The error a lookup of a missing path gets from the volume, with errno
+// set to match
+fn not_found() -> GlusterError {
+    set_errno(Errno(ENOENT));
+    GlusterError::new(format!("{}", Errno(ENOENT)))
+}
+
+impl<'a> CachedGluster<'a> {
+    pub fn new(gluster: &'a Gluster, opts: CacheOptions) -> CachedGluster<'a> {
+        CachedGluster {
+            gluster,
+            opts,
+            state: Mutex::new(LruState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            validations: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            listing_hits: AtomicU64::new(0),
+            listing_misses: AtomicU64::new(0),
+            negative_hits: AtomicU64::new(0),
+            negative_inserts: AtomicU64::new(0),
+        }
+    }
+
+    /// The wrapped connection.  Changes made through it directly are only
+    /// noticed by the cache on the next validation.
+    pub fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            validations: self.validations.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            listing_hits: self.listing_hits.load(Ordering::Relaxed),
+            listing_misses: self.listing_misses.load(Ordering::Relaxed),
+            negative_hits: self.negative_hits.load(Ordering::Relaxed),
+            negative_inserts: self.negative_inserts.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Read the whole file, from the cache if the cached copy is still
+    /// current.
+    pub fn read_to_vec(&self, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.tick();
+            if let (Some(ttl), Some(entry)) = (self.opts.ttl, state.entries.get_mut(path)) {
+                if entry.validated_at.elapsed() < ttl {
+                    entry.last_used = tick;
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(entry.data.clone());
+                }
+            }
+        }
+
+        self.validations.fetch_add(1, Ordering::Relaxed);
+        let metadata = self.metadata(path)?;
+        {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.tick();
+            let mut stale = false;
+            if let Some(entry) = state.entries.get_mut(path) {
+                if entry.matches(&metadata) {
+                    entry.validated_at = Instant::now();
+                    entry.last_used = tick;
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(entry.data.clone());
+                }
+                stale = true;
+            }
+            if stale {
+                state.remove(path);
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let data = self.gluster.read_to_vec(path)?;
+        // Don't cache a read that raced with a writer
+        if data.len() as u64 == metadata.len() {
+            self.insert(path, &metadata, &data);
+        }
+        Ok(data)
+    }
+
+    /// Stat path, answering ENOENT from memory if it was found missing
+    /// within negative_ttl
+    pub fn metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        self.check_negative(path)?;
+        let result = self.gluster.metadata(path);
+        if result.is_err() {
+            self.remember_missing(path);
+        }
+        result
+    }
+
+    /// Open an existing file, answering ENOENT from memory if it was found
+    /// missing within negative_ttl.  Opens with O_CREAT always go to the
+    /// volume.
+    pub fn open_file(&self, path: &Path, flags: i32) -> Result<GlusterFile<'a>, GlusterError> {
+        if flags & O_CREAT != 0 {
+            let result = self.gluster.open_file(path, flags);
+            self.invalidate(path);
+            return result;
+        }
+        self.check_negative(path)?;
+        let result = self.gluster.open_file(path, flags);
+        if result.is_err() {
+            self.remember_missing(path);
+        }
+        result
+    }
+
+    fn check_negative(&self, path: &Path) -> Result<(), GlusterError> {
+        let ttl = match self.opts.negative_ttl {
+            Some(ttl) => ttl,
+            None => return Ok(()),
+        };
+        let mut state = self.state.lock().unwrap();
+        let missing_since = match state.negatives.get(path) {
+            Some(missing_since) => *missing_since,
+            None => return Ok(()),
+        };
+        if missing_since.elapsed() < ttl {
+            self.negative_hits.fetch_add(1, Ordering::Relaxed);
+            return Err(not_found());
+        }
+        state.negatives.remove(path);
+        Ok(())
+    }
+
+    // Call straight after a failed lookup, while errno still says why
+    fn remember_missing(&self, path: &Path) {
+        let ttl = match self.opts.negative_ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+        if errno() != Errno(ENOENT) || self.opts.max_negative == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.negatives.len() >= self.opts.max_negative {
+            state.negatives.retain(|_, since| since.elapsed() < ttl);
+        }
+        if state.negatives.len() >= self.opts.max_negative {
+            let oldest = state
+                .negatives
+                .iter()
+                .min_by_key(|n| *n.1)
+                .map(|(p, _)| p.clone());
+            if let Some(oldest) = oldest {
+                state.negatives.remove(&oldest);
+            }
+        }
+        state.negatives.insert(path.to_path_buf(), Instant::now());
+        self.negative_inserts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn insert(&self, path: &Path, metadata: &Metadata, data: &[u8]) {
+        if data.len() > self.opts.max_bytes || self.opts.max_entries == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(path);
+        while state.entries.len() >= self.opts.max_entries
+            || state.total_bytes + data.len() > self.opts.max_bytes
+        {
+            let oldest = match state.entries.iter().min_by_key(|e| e.1.last_used) {
+                Some((p, _)) => p.clone(),
+                None => break,
+            };
+            state.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        let tick = state.tick();
+        state.total_bytes += data.len();
+        state.entries.insert(
+            path.to_path_buf(),
+            CacheEntry {
+                data: data.to_vec(),
+                size: metadata.len(),
+                mtime: metadata.mtime(),
+                mtime_nsec: metadata.mtime_nsec(),
+                validated_at: Instant::now(),
+                last_used: tick,
+            },
+        );
+    }
+
+    /// List a directory, serving it from the listing cache when it was
+    /// fetched less than listing_ttl ago.
+    pub fn list_dir(&self, path: &Path) -> Result<Vec<(DirEntry, Metadata)>, GlusterError> {
+        let ttl = match self.opts.listing_ttl {
+            Some(ttl) => ttl,
+            None => return self.gluster.list_dir(path, 1),
+        };
+        {
+            let state = self.state.lock().unwrap();
+            if let Some(listing) = state.listings.get(path) {
+                if listing.fetched_at.elapsed() < ttl {
+                    self.listing_hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(listing.entries.clone());
+                }
+            }
+        }
+        self.listing_misses.fetch_add(1, Ordering::Relaxed);
+        let entries = self.gluster.list_dir(path, 1)?;
+        let mut state = self.state.lock().unwrap();
+        state.listings.retain(|_, l| l.fetched_at.elapsed() < ttl);
+        if state.listings.len() >= self.opts.max_listings {
+            let oldest = state
+                .listings
+                .iter()
+                .min_by_key(|l| l.1.fetched_at)
+                .map(|(p, _)| p.clone());
+            if let Some(oldest) = oldest {
+                state.listings.remove(&oldest);
+            }
+        }
+        if self.opts.max_listings > 0 {
+            state.listings.insert(
+                path.to_path_buf(),
+                CachedListing {
+                    entries: entries.clone(),
+                    fetched_at: Instant::now(),
+                },
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Drop any cached copy or listing of path or anything under it,
+    /// along with the cached listing of the directory containing it, and
+    /// forget that path or anything under it was missing
+    pub fn invalidate(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        let stale: Vec<PathBuf> = state
+            .entries
+            .keys()
+            .filter(|cached| cached.starts_with(path))
+            .cloned()
+            .collect();
+        for cached in stale {
+            state.remove(&cached);
+        }
+        state.listings.retain(|listed, _| !listed.starts_with(path));
+        if let Some(parent) = path.parent() {
+            state.listings.remove(parent);
+        }
+        if !state.negatives.is_empty() {
+            state.negatives.retain(|missing, _| !missing.starts_with(path));
+        }
+    }
+
+    /// Drop everything from the cache
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.listings.clear();
+        state.negatives.clear();
+        state.total_bytes = 0;
+    }
+
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+        let result = self.gluster.write_file(path, data);
+        self.invalidate(path);
+        result
+    }
+
+    pub fn create(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+    ) -> Result<GlusterFile<'a>, GlusterError> {
+        let result = self.gluster.create_file(path, flags, mode);
+        self.invalidate(path);
+        result
+    }
+
+    pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let result = self.gluster.mkdir(path, mode);
+        self.invalidate(path);
+        result
+    }
+
+    pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        let result = self.gluster.unlink(path);
+        self.invalidate(path);
+        result
+    }
+
+    pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        let result = self.gluster.rmdir(path);
+        self.invalidate(path);
+        result
+    }
+
+    pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let result = self.gluster.rename(oldpath, newpath);
+        self.invalidate(oldpath);
+        self.invalidate(newpath);
+        result
+    }
+}
@@ -0,0 +1,149 @@
+use gluster::{Gluster, GlusterError};
+use space::FreeSpace;
+
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How far usage has to drop back below a threshold, in percentage
+/// points, before it counts as crossed downwards.  Stops a volume sitting
+/// right on a threshold from sending an event every poll.
+pub const HYSTERESIS_PERCENT: f64 = 1.0;
+
+/// Which way a threshold was crossed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crossing {
+    Rising,
+    Falling,
+}
+
+/// Sent by a CapacityWatcher each time usage crosses a threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapacityEvent {
+    pub percent_used: f64,
+    pub bytes_free: u64,
+    pub crossed_threshold: f64,
+    pub direction: Crossing,
+}
+
+fn percent_used(space: &FreeSpace) -> f64 {
+    if space.total == 0 {
+        return 0.0;
+    }
+    100.0 * (space.total.saturating_sub(space.available)) as f64 / space.total as f64
+}
+
+// Work out which thresholds a new reading crossed.  level is how many of
+// the sorted thresholds are currently exceeded and is updated in place.
+fn crossings(thresholds: &[f64], level: &mut usize, space: &FreeSpace) -> Vec<CapacityEvent> {
+    let used = percent_used(space);
+    let mut events = Vec::new();
+    while *level < thresholds.len() && used >= thresholds[*level] {
+        events.push(CapacityEvent {
+            percent_used: used,
+            bytes_free: space.available,
+            crossed_threshold: thresholds[*level],
+            direction: Crossing::Rising,
+        });
+        *level += 1;
+    }
+    while *level > 0 && used < thresholds[*level - 1] - HYSTERESIS_PERCENT {
+        *level -= 1;
+        events.push(CapacityEvent {
+            percent_used: used,
+            bytes_free: space.available,
+            crossed_threshold: thresholds[*level],
+            direction: Crossing::Falling,
+        });
+    }
+    events
+}
+
+/// Polls free space on a background thread and reports threshold
+/// crossings over a channel.  Polling errors are sent on the same channel
+/// and the thread carries on.  Dropping the watcher stops the thread and
+/// waits for it.
+pub struct CapacityWatcher {
+    events: Receiver<Result<CapacityEvent, GlusterError>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CapacityWatcher {
+    /// Watch the space reported by poll instead of a volume.  Thresholds
+    /// are percentages of the total in use.  A threshold that's already
+    /// exceeded at the first poll is reported as a rising crossing.
+    pub fn spawn<F>(interval: Duration, thresholds: &[f64], mut poll: F) -> CapacityWatcher
+    where
+        F: FnMut() -> Result<FreeSpace, GlusterError> + Send + 'static,
+    {
+        let mut thresholds = thresholds.to_vec();
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        let (event_tx, event_rx) = channel();
+        let (stop_tx, stop_rx) = channel::<()>();
+        let thread = thread::spawn(move || {
+            let mut level = 0;
+            loop {
+                match poll() {
+                    Ok(space) => {
+                        for event in crossings(&thresholds, &mut level, &space) {
+                            if event_tx.send(Ok(event)).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if event_tx.send(Err(e)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                match stop_rx.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // Told to stop or the watcher is gone
+                    _ => return,
+                }
+            }
+        });
+        CapacityWatcher {
+            events: event_rx,
+            stop: Some(stop_tx),
+            thread: Some(thread),
+        }
+    }
+
+    /// Threshold crossings and polling errors, in the order they happened
+    pub fn events(&self) -> &Receiver<Result<CapacityEvent, GlusterError>> {
+        &self.events
+    }
+}
+
+impl Drop for CapacityWatcher {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Gluster {
+    /// Poll the space on the volume every interval and report when usage
+    /// crosses any of thresholds (percentages, e.g. 80.0) in either
+    /// direction.  The watcher's thread holds a clone of the Arc, see
+    /// Gluster::shared, so the connection lives as long as it's polled.
+    pub fn watch_capacity(
+        self: &Arc<Self>,
+        interval: Duration,
+        thresholds: &[f64],
+    ) -> CapacityWatcher {
+        let gluster = self.clone();
+        CapacityWatcher::spawn(interval, thresholds, move || {
+            gluster.free_space(Path::new("/"))
+        })
+    }
+}
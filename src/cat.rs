@@ -0,0 +1,171 @@
+use errno::{errno, Errno};
+use libc::{ENOENT, O_RDONLY};
+
+use buffer_pool::BufferPool;
+use gluster::{Gluster, GlusterError};
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Options for Gluster::cat
+#[derive(Clone, Debug)]
+pub struct CatOptions {
+    separator: Option<Vec<u8>>,
+    ignore_missing: bool,
+    chunk_size: usize,
+    max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for CatOptions {
+    fn default() -> CatOptions {
+        CatOptions {
+            separator: None,
+            ignore_missing: false,
+            chunk_size: 1024 * 1024,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+impl CatOptions {
+    pub fn new() -> CatOptions {
+        CatOptions::default()
+    }
+
+    /// Written between one file and the next, not before the first or
+    /// after the last.  Missing files skipped with ignore_missing don't
+    /// get one.  Defaults to nothing.
+    pub fn separator(mut self, separator: &[u8]) -> CatOptions {
+        self.separator = Some(separator.to_vec());
+        self
+    }
+
+    /// Skip paths that don't exist instead of failing.  Defaults to
+    /// false.
+    pub fn ignore_missing(mut self, ignore: bool) -> CatOptions {
+        self.ignore_missing = ignore;
+        self
+    }
+
+    /// Size of each read from the volume.  Defaults to 1MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> CatOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Limit the average rate bytes are written to the output, counting
+    /// separators.  Defaults to unlimited.
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> CatOptions {
+        self.max_bytes_per_sec = Some(max_bytes_per_sec.max(1));
+        self
+    }
+}
+
+/// What Gluster::cat did with one of its inputs
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatFile {
+    pub path: PathBuf,
+    /// Bytes of the file written to the output
+    pub bytes: u64,
+    /// The file didn't exist and ignore_missing skipped it
+    pub missing: bool,
+}
+
+/// What Gluster::cat wrote
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CatReport {
+    /// Bytes written to the output, separators included
+    pub bytes: u64,
+    /// One per input path, in the order given
+    pub files: Vec<CatFile>,
+}
+
+// Counts bytes written and sleeps to keep them under a rate
+struct Throttled<'a, W: 'a> {
+    inner: &'a mut W,
+    written: u64,
+    max_bytes_per_sec: Option<u64>,
+    start: Instant,
+}
+
+impl<'a, W: Write> Throttled<'a, W> {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write_all(data)?;
+        self.written += data.len() as u64;
+        if let Some(rate) = self.max_bytes_per_sec {
+            let due = Duration::from_secs_f64(self.written as f64 / rate as f64);
+            let elapsed = self.start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Gluster {
+    /// Write each of paths into out in turn, like cat(1), without
+    /// holding more than one chunk of any of them in memory.  Returns
+    /// the total written and how much came from each file.  A failure
+    /// part way leaves out with whatever was written before it.
+    pub fn cat<W: Write>(
+        &self,
+        paths: &[PathBuf],
+        out: &mut W,
+        opts: &CatOptions,
+    ) -> Result<CatReport, GlusterError> {
+        let mut out = Throttled {
+            inner: out,
+            written: 0,
+            max_bytes_per_sec: opts.max_bytes_per_sec,
+            start: Instant::now(),
+        };
+        let mut buffer = BufferPool::global().get(opts.chunk_size);
+        let mut files = Vec::with_capacity(paths.len());
+        let mut first = true;
+        for path in paths {
+            let mut file = match self.open_file(path, O_RDONLY) {
+                Ok(file) => file,
+                Err(_) if opts.ignore_missing && errno() == Errno(ENOENT) => {
+                    files.push(CatFile {
+                        path: path.clone(),
+                        bytes: 0,
+                        missing: true,
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !first {
+                if let Some(ref separator) = opts.separator {
+                    out.send(separator)?;
+                }
+            }
+            first = false;
+            let mut bytes = 0;
+            loop {
+                let read = match file.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(GlusterError::IoError(e)),
+                };
+                out.send(&buffer[..read])?;
+                bytes += read as u64;
+            }
+            file.close()?;
+            files.push(CatFile {
+                path: path.clone(),
+                bytes,
+                missing: false,
+            });
+        }
+        out.inner.flush()?;
+        Ok(CatReport {
+            bytes: out.written,
+            files,
+        })
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Incremental CRC32C (Castagnoli) as used by iSCSI and ext4
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32c {
+    crc: u32,
+}
+
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+fn crc32c_table() -> &'static [u32; 256] {
+    static TABLE: ::std::sync::OnceLock<[u32; 256]> = ::std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ CRC32C_POLY
+                } else {
+                    crc >> 1
+                };
+            }
+            *entry = crc;
+        }
+        table
+    })
+}
+
+impl Default for Crc32c {
+    fn default() -> Crc32c {
+        Crc32c::new()
+    }
+}
+
+impl Crc32c {
+    pub fn new() -> Crc32c {
+        Crc32c { crc: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let table = crc32c_table();
+        let mut crc = self.crc;
+        for byte in data {
+            crc = table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+        self.crc = crc;
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+
+    /// Checksum a single buffer
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = Crc32c::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+/// Incremental Adler-32 as used by zlib, the weak half of a block
+/// signature in Gluster::sync_file
+#[derive(Clone, Copy, Debug)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+const ADLER_MOD: u32 = 65_521;
+// Most bytes that can be summed before b could overflow a u32
+const ADLER_NMAX: usize = 5552;
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+impl Adler32 {
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(ADLER_NMAX) {
+            for byte in chunk {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+
+    /// Checksum a single buffer
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut adler = Adler32::new();
+        adler.update(data);
+        adler.finish()
+    }
+}
+
+/// Incremental SHA-256 (FIPS 180-4)
+#[derive(Clone, Copy, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    // Bytes of a block not yet compressed
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+#[rustfmt::skip]
+const SHA256_K: [u32; 64] = [
+    0x428a_2f98, 0x7137_4491, 0xb5c0_fbcf, 0xe9b5_dba5, 0x3956_c25b, 0x59f1_11f1, 0x923f_82a4,
+    0xab1c_5ed5, 0xd807_aa98, 0x1283_5b01, 0x2431_85be, 0x550c_7dc3, 0x72be_5d74, 0x80de_b1fe,
+    0x9bdc_06a7, 0xc19b_f174, 0xe49b_69c1, 0xefbe_4786, 0x0fc1_9dc6, 0x240c_a1cc, 0x2de9_2c6f,
+    0x4a74_84aa, 0x5cb0_a9dc, 0x76f9_88da, 0x983e_5152, 0xa831_c66d, 0xb003_27c8, 0xbf59_7fc7,
+    0xc6e0_0bf3, 0xd5a7_9147, 0x06ca_6351, 0x1429_2967, 0x27b7_0a85, 0x2e1b_2138, 0x4d2c_6dfc,
+    0x5338_0d13, 0x650a_7354, 0x766a_0abb, 0x81c2_c92e, 0x9272_2c85, 0xa2bf_e8a1, 0xa81a_664b,
+    0xc24b_8b70, 0xc76c_51a3, 0xd192_e819, 0xd699_0624, 0xf40e_3585, 0x106a_a070, 0x19a4_c116,
+    0x1e37_6c08, 0x2748_774c, 0x34b0_bcb5, 0x391c_0cb3, 0x4ed8_aa4a, 0x5b9c_ca4f, 0x682e_6ff3,
+    0x748f_82ee, 0x78a5_636f, 0x84c8_7814, 0x8cc7_0208, 0x90be_fffa, 0xa450_6ceb, 0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+impl Default for Sha256 {
+    fn default() -> Sha256 {
+        Sha256::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 {
+            state: SHA256_INIT,
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        if self.block_len > 0 {
+            let take = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < 64 {
+                return;
+            }
+            let block = self.block;
+            self.compress(&block);
+            self.block_len = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    pub fn finish(&self) -> [u8; 32] {
+        let mut hasher = *self;
+        let bit_len = self.total_len.wrapping_mul(8);
+        let padding_len = if self.block_len < 56 {
+            56 - self.block_len
+        } else {
+            120 - self.block_len
+        };
+        let mut padding = [0u8; 64];
+        padding[0] = 0x80;
+        hasher.update(&padding[..padding_len]);
+        hasher.update(&bit_len.to_be_bytes());
+        let mut digest = [0u8; 32];
+        for (bytes, word) in digest.chunks_mut(4).zip(&hasher.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Hash a single buffer
+    pub fn checksum(data: &[u8]) -> [u8; 32] {
+        let mut sha = Sha256::new();
+        sha.update(data);
+        sha.finish()
+    }
+}
+
+/// A checksum Gluster::checksum_cached can compute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// 4 bytes, big endian
+    Crc32c,
+    /// 32 bytes
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// Digest of everything read from reader
+    pub fn digest_reader<R: Read>(&self, reader: &mut R) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut crc = Crc32c::new();
+        let mut sha = Sha256::new();
+        loop {
+            let len = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            match *self {
+                ChecksumAlgorithm::Crc32c => crc.update(&buffer[..len]),
+                ChecksumAlgorithm::Sha256 => sha.update(&buffer[..len]),
+            }
+        }
+        Ok(match *self {
+            ChecksumAlgorithm::Crc32c => crc.finish().to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Sha256 => sha.finish().to_vec(),
+        })
+    }
+
+    /// Digest of a single buffer
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match *self {
+            ChecksumAlgorithm::Crc32c => Crc32c::checksum(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::checksum(data).to_vec(),
+        }
+    }
+
+    /// Length of a digest in bytes
+    pub fn digest_len(&self) -> usize {
+        match *self {
+            ChecksumAlgorithm::Crc32c => 4,
+            ChecksumAlgorithm::Sha256 => 32,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ChecksumAlgorithm::Crc32c => "crc32c",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        })
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ChecksumAlgorithm, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "crc32c" => Ok(ChecksumAlgorithm::Crc32c),
+            "sha256" | "sha-256" => Ok(ChecksumAlgorithm::Sha256),
+            _ => Err(format!("{:?} isn't a checksum algorithm", s)),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use errno::{errno, Errno};
+use libc::{ENODATA, O_RDONLY};
+
+use checksum::ChecksumAlgorithm;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::path::Path;
+use std::str;
+
+/// The xattr checksum_cached keeps its digest in, the same one
+/// ObjectStore::head reports
+pub use object_store::CHECKSUM_XATTR;
+
+// Leads the xattr value, for telling later formats apart
+const FORMAT_VERSION: &str = "v1";
+
+/// A digest as cached on a file, along with the size and mtime the file
+/// had when it was computed.  Stored as a single line of text:
+///
+/// v1 sha256 <size> <mtime seconds>.<nanoseconds> <hex digest>
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedChecksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub digest: Vec<u8>,
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl CachedChecksum {
+    /// The cache entry for digest of a file with metadata
+    pub fn new(
+        algorithm: ChecksumAlgorithm,
+        metadata: &Metadata,
+        digest: Vec<u8>,
+    ) -> CachedChecksum {
+        CachedChecksum {
+            algorithm,
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            digest,
+        }
+    }
+
+    /// Parse an xattr value.  Anything malformed, including a digest of
+    /// the wrong length for its algorithm, is None.
+    pub fn parse(value: &[u8]) -> Option<CachedChecksum> {
+        let value = str::from_utf8(value).ok()?;
+        let mut fields = value.split(' ');
+        if fields.next()? != FORMAT_VERSION {
+            return None;
+        }
+        let algorithm = fields.next()?.parse::<ChecksumAlgorithm>().ok()?;
+        let size = fields.next()?.parse::<u64>().ok()?;
+        let mut mtime = fields.next()?.splitn(2, '.');
+        let seconds = mtime.next()?.parse::<i64>().ok()?;
+        let nanoseconds = mtime.next()?;
+        if nanoseconds.len() != 9 || !nanoseconds.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let digest = from_hex(fields.next()?)?;
+        if fields.next().is_some() || digest.len() != algorithm.digest_len() {
+            return None;
+        }
+        Some(CachedChecksum {
+            algorithm,
+            size,
+            mtime: seconds,
+            // Nine digits are always below a second
+            mtime_nsec: nanoseconds.parse().ok()?,
+            digest,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{} {} {} {}.{:09} {}",
+            FORMAT_VERSION,
+            self.algorithm,
+            self.size,
+            self.mtime,
+            self.mtime_nsec,
+            to_hex(&self.digest)
+        )
+        .into_bytes()
+    }
+
+    /// Whether this still describes a file with metadata
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len()
+            && self.mtime == metadata.mtime()
+            && self.mtime_nsec == metadata.mtime_nsec()
+    }
+}
+
+impl Gluster {
+    /// The digest of the file at path.  A digest cached in the
+    /// user.gfapi.checksum xattr is returned as long as the file's size
+    /// and mtime still match what they were when it was computed.
+    /// Otherwise the file is read through, and the digest cached if the
+    /// file didn't change while it was being read.  The xattr is written
+    /// whole in one setxattr, so concurrent refreshers leave one of their
+    /// values rather than a mix.  Failing to write the cache, on a read
+    /// only connection say, doesn't fail the call.
+    ///
+    /// A write that keeps both the size and the mtime, which takes a
+    /// deliberate utimens, isn't noticed.
+    pub fn checksum_cached(
+        &self,
+        path: &Path,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<Vec<u8>, GlusterError> {
+        let mut file = self.open_file(path, O_RDONLY)?;
+        let before = Metadata::from_stat(file.fstat()?);
+        if let Some(cached) = self.cached_checksum(path)? {
+            if cached.algorithm == algorithm && cached.matches(&before) {
+                return Ok(cached.digest);
+            }
+        }
+        let digest = algorithm.digest_reader(&mut file)?;
+        let after = Metadata::from_stat(file.fstat()?);
+        file.close()?;
+
+        let cached = CachedChecksum::new(algorithm, &before, digest);
+        if !cached.matches(&after) {
+            trace!("{} changed while checksumming, not caching", path.display());
+            return Ok(cached.digest);
+        }
+        match self.setxattr(path, CHECKSUM_XATTR, &cached.to_bytes(), 0) {
+            Ok(()) => {}
+            Err(GlusterError::ReadOnly) => {}
+            Err(e) => warn!("couldn't cache the checksum of {}: {}", path.display(), e),
+        }
+        Ok(cached.digest)
+    }
+
+    /// The checksum cached on path, None if there's none or it can't be
+    /// parsed.  It isn't checked against the file.
+    pub fn cached_checksum(&self, path: &Path) -> Result<Option<CachedChecksum>, GlusterError> {
+        match self.getxattr_bytes(path, CHECKSUM_XATTR) {
+            Ok(value) => {
+                let cached = CachedChecksum::parse(&value);
+                if cached.is_none() {
+                    trace!(
+                        "ignoring a malformed {} on {}",
+                        CHECKSUM_XATTR,
+                        path.display()
+                    );
+                }
+                Ok(cached)
+            }
+            Err(_) if errno() == Errno(ENODATA) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the file at path has the digest expected, going by
+    /// checksum_cached
+    pub fn verify_checksum(
+        &self,
+        path: &Path,
+        algorithm: ChecksumAlgorithm,
+        expected: &[u8],
+    ) -> Result<bool, GlusterError> {
+        Ok(self.checksum_cached(path, algorithm)? == expected)
+    }
+
+    /// Remove the cached checksum from path, if it has one
+    pub fn clear_checksum_cache(&self, path: &Path) -> Result<(), GlusterError> {
+        match self.removexattr(path, CHECKSUM_XATTR) {
+            Err(_) if errno() == Errno(ENODATA) => Ok(()),
+            other => other,
+        }
+    }
+}
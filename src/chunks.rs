@@ -0,0 +1,95 @@
+use libc::O_RDONLY;
+
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+/// Iterator over a remote file in fixed size chunks, created by
+/// Gluster::chunks.  Every chunk but the last is chunk_size bytes.  The
+/// file is closed as soon as the end is reached, an error is returned or
+/// the iterator is dropped.
+#[derive(Debug)]
+pub struct Chunks<'a> {
+    file: Option<GlusterFile<'a>>,
+    chunk_size: usize,
+}
+
+impl<'a> Chunks<'a> {
+    // Fill buf as far as possible, closing the file at the end or on an
+    // error.  Returns 0 once there's nothing left.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize, GlusterError> {
+        let mut filled = 0;
+        let result = match self.file {
+            Some(ref mut file) => loop {
+                if filled == buf.len() {
+                    break Ok(());
+                }
+                match file.read(&mut buf[filled..]) {
+                    Ok(0) => break Ok(()),
+                    Ok(n) => filled += n,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => break Err(GlusterError::IoError(e)),
+                }
+            },
+            None => return Ok(0),
+        };
+        if let Err(e) = result {
+            self.file = None;
+            return Err(e);
+        }
+        if filled < buf.len() {
+            // Short read, that was the end of the file
+            if let Some(file) = self.file.take() {
+                file.close()?;
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Call f with each remaining chunk in turn, reusing one buffer for
+    /// all of them.  Returns the number of bytes passed to f.
+    pub fn for_each_chunk<F>(&mut self, mut f: F) -> Result<u64, GlusterError>
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut buffer = vec![0; self.chunk_size];
+        let mut total = 0;
+        loop {
+            let len = self.fill(&mut buffer)?;
+            if len == 0 {
+                return Ok(total);
+            }
+            f(&buffer[..len]);
+            total += len as u64;
+        }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Vec<u8>, GlusterError>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>, GlusterError>> {
+        let mut chunk = vec![0; self.chunk_size];
+        match self.fill(&mut chunk) {
+            Ok(0) => None,
+            Ok(len) => {
+                chunk.truncate(len);
+                Some(Ok(chunk))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl Gluster {
+    /// Open the file at path for reading it chunk_size bytes at a time
+    pub fn chunks(&self, path: &Path, chunk_size: usize) -> Result<Chunks<'_>, GlusterError> {
+        let file = self.open_file(path, O_RDONLY)?;
+        Ok(Chunks {
+            file: Some(file),
+            chunk_size: chunk_size.max(1),
+        })
+    }
+}
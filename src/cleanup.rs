@@ -0,0 +1,73 @@
+use gluster::GlusterError;
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What failed to clean up when it was dropped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropTarget {
+    /// glfs_fini of a connection
+    Connection,
+    /// glfs_close of a file
+    File,
+    /// Unlocking a range locked with GlusterFile::lock_exclusive_timeout
+    /// or lock_shared_timeout
+    Lock,
+    /// glfs_h_close of a GlusterObject
+    Object,
+    /// glfs_closedir of a GlusterDirectory or GlusterDirectoryPlus
+    Directory,
+    /// Writing out what a GlusterBufWriter still had buffered
+    BufferedWrites,
+    /// Removing the lock file of a FileLockGuard
+    LockFile,
+    /// Removing a GlusterTempDir and everything in it
+    TempDir,
+}
+
+/// A failure while dropping a connection, a file or anything else that
+/// cleans up after itself, which had nobody to return it to.  A failed
+/// close or fini can mean buffered writes were lost, so they're reported
+/// here rather than ignored.  Call GlusterFile::close or
+/// Gluster::disconnect to get the error back directly.
+#[derive(Debug)]
+pub struct DropError {
+    pub target: DropTarget,
+    /// The file's path, when known
+    pub path: Option<PathBuf>,
+    pub error: GlusterError,
+}
+
+type Handler = Box<dyn Fn(&DropError) + Send + Sync>;
+
+static HANDLER: Mutex<Option<Handler>> = Mutex::new(None);
+
+/// Send failures from Drop impls to handler instead of logging them with
+/// warn!.  Pass None to go back to logging.  Applies to every connection
+/// in the process.
+pub fn set_drop_error_handler(handler: Option<Handler>) {
+    let mut current = match HANDLER.lock() {
+        Ok(current) => current,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *current = handler;
+}
+
+pub(crate) fn report(error: DropError) {
+    let handler = match HANDLER.lock() {
+        Ok(handler) => handler,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    match *handler {
+        Some(ref handler) => handler(&error),
+        None => match error.path {
+            Some(ref path) => warn!(
+                "{:?} cleanup of {} failed: {}",
+                error.target,
+                path.display(),
+                error.error
+            ),
+            None => warn!("{:?} cleanup failed: {}", error.target, error.error),
+        },
+    }
+}
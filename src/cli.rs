@@ -0,0 +1,187 @@
+use libc::{mode_t, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK};
+
+use gluster::{Gluster, GlusterError};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Port glusterd listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 24007;
+
+/// A path on a volume, written gluster://host[:port]/volume/path
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteTarget {
+    pub host: String,
+    pub port: u16,
+    pub volume: String,
+    /// Always absolute within the volume
+    pub path: PathBuf,
+}
+
+impl RemoteTarget {
+    /// Build a target from the separate volume, host and path arguments
+    /// the tools also accept.  host may carry a :port.
+    pub fn from_parts(volume: &str, host: &str, path: &str) -> Result<RemoteTarget, GlusterError> {
+        if volume.is_empty() {
+            return Err(GlusterError::new("the volume name is empty".to_string()));
+        }
+        let (host, port) = parse_host(host)?;
+        Ok(RemoteTarget {
+            host,
+            port,
+            volume: volume.to_string(),
+            path: Path::new("/").join(path.trim_start_matches('/')),
+        })
+    }
+
+    pub fn connect(&self) -> Result<Gluster, GlusterError> {
+        Gluster::connect(&self.volume, &self.host, self.port)
+    }
+}
+
+impl fmt::Display for RemoteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "gluster://{}", self.host)?;
+        if self.port != DEFAULT_PORT {
+            write!(f, ":{}", self.port)?;
+        }
+        write!(f, "/{}{}", self.volume, self.path.display())
+    }
+}
+
+impl FromStr for RemoteTarget {
+    type Err = GlusterError;
+
+    fn from_str(s: &str) -> Result<RemoteTarget, GlusterError> {
+        let rest = match s.strip_prefix("gluster://") {
+            Some(rest) => rest,
+            None => return Err(bad_url(s)),
+        };
+        let mut parts = rest.splitn(3, '/');
+        let host = parts.next().unwrap_or("");
+        let volume = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("");
+        if host.is_empty() || volume.is_empty() {
+            return Err(bad_url(s));
+        }
+        RemoteTarget::from_parts(volume, host, path)
+    }
+}
+
+fn bad_url(s: &str) -> GlusterError {
+    GlusterError::new(format!(
+        "{:?} isn't of the form gluster://host[:port]/volume/path",
+        s
+    ))
+}
+
+fn parse_host(host: &str) -> Result<(String, u16), GlusterError> {
+    // [v6addr]:port, host:port or a bare host
+    let (name, port) = if host.starts_with('[') {
+        match host.find(']') {
+            Some(end) => (&host[1..end], host[end + 1..].strip_prefix(':')),
+            None => return Err(GlusterError::new(format!("bad host {:?}", host))),
+        }
+    } else {
+        match host.rfind(':') {
+            Some(colon) => (&host[..colon], Some(&host[colon + 1..])),
+            None => (host, None),
+        }
+    };
+    if name.is_empty() {
+        return Err(GlusterError::new(format!("bad host {:?}", host)));
+    }
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .map_err(|_| GlusterError::new(format!("bad port in {:?}", host)))?,
+        None => DEFAULT_PORT,
+    };
+    Ok((name.to_string(), port))
+}
+
+/// One side of a copy
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Local(PathBuf),
+    Remote(RemoteTarget),
+}
+
+impl FromStr for Target {
+    type Err = GlusterError;
+
+    /// gluster:// URLs are remote, anything else is a local path
+    fn from_str(s: &str) -> Result<Target, GlusterError> {
+        if s.starts_with("gluster://") {
+            Ok(Target::Remote(s.parse()?))
+        } else if s.is_empty() {
+            Err(GlusterError::new("empty path".to_string()))
+        } else {
+            Ok(Target::Local(PathBuf::from(s)))
+        }
+    }
+}
+
+/// The remote path named by a tool's arguments, either a single
+/// gluster:// URL or volume host path
+pub fn remote_from_args(args: &[String]) -> Result<RemoteTarget, GlusterError> {
+    match args.len() {
+        1 => args[0].parse(),
+        3 => RemoteTarget::from_parts(&args[0], &args[1], &args[2]),
+        _ => Err(GlusterError::new(
+            "expected gluster://host[:port]/volume/path or volume host path".to_string(),
+        )),
+    }
+}
+
+/// The mode as ls -l shows it, e.g. drwxr-xr-x
+pub fn format_mode(mode: mode_t) -> String {
+    let kind = match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFCHR => 'c',
+        S_IFBLK => 'b',
+        S_IFIFO => 'p',
+        S_IFSOCK => 's',
+        _ => '-',
+    };
+    let mut out = String::with_capacity(10);
+    out.push(kind);
+    // (read bit, write bit, execute bit, special bit, special letter)
+    let classes = [
+        (0o400, 0o200, 0o100, 0o4000, 's'),
+        (0o040, 0o020, 0o010, 0o2000, 's'),
+        (0o004, 0o002, 0o001, 0o1000, 't'),
+    ];
+    for &(r, w, x, special, letter) in &classes {
+        out.push(if mode & r != 0 { 'r' } else { '-' });
+        out.push(if mode & w != 0 { 'w' } else { '-' });
+        out.push(match (mode & x != 0, mode & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+/// A one line progress bar of width characters between the brackets,
+/// followed by the percentage and byte counts
+pub fn progress_bar(done: u64, total: u64, width: usize) -> String {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        (done.min(total) as f64) / (total as f64)
+    };
+    let filled = (fraction * width as f64) as usize;
+    format!(
+        "[{}{}] {:>3}% {}/{}",
+        "#".repeat(filled),
+        " ".repeat(width - filled),
+        (fraction * 100.0) as u32,
+        done,
+        total
+    )
+}
@@ -0,0 +1,196 @@
+use builder::{parse_servers, valid_transport, GlusterBuilder};
+use gluster::{Gluster, GlusterError, GlusterLogLevel};
+use tuning::{TuningProfile, XlatorOption};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// Xlators the timeouts are set on
+const CLIENT_XLATORS: &str = "*-client-*";
+
+/// Where and how much gfapi logs
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    pub file: PathBuf,
+    /// A GlusterLogLevel by name.  Defaults to info.
+    #[serde(default)]
+    pub level: Option<String>,
+}
+
+/// Timeouts of the client xlators, in seconds
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// How long a brick can go without answering before the client
+    /// treats it as down, network.ping-timeout on the volume
+    #[serde(default)]
+    pub ping: Option<u64>,
+    /// How long a single call can wait for its reply,
+    /// network.frame-timeout on the volume
+    #[serde(default)]
+    pub frame: Option<u64>,
+}
+
+/// Connection settings as kept in a config file, for instance
+///
+/// ```toml
+/// volume = "archive"
+/// servers = ["gl1:24007", "gl2", "[fd00::1]:24008"]
+/// read_only = true
+///
+/// [log]
+/// file = "/var/log/app/gfapi.log"
+/// level = "warning"
+///
+/// [xlator_options]
+/// "*-io-cache.cache-size" = "256MB"
+///
+/// [timeouts]
+/// ping = 10
+/// ```
+///
+/// GlusterBuilder::from_config checks it and turns it into a builder.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlusterConfig {
+    /// The volume, or volume/subdir as GlusterBuilder::new takes it
+    pub volume: String,
+    /// Volfile servers in the host[:port] form parse_servers takes, the
+    /// first tried first.  Required unless volfile is given.
+    #[serde(default)]
+    pub servers: Vec<String>,
+    /// A local volfile to use instead of fetching one from servers
+    #[serde(default)]
+    pub volfile: Option<PathBuf>,
+    /// tcp, unix or rdma
+    #[serde(default)]
+    pub transport: Option<String>,
+    #[serde(default)]
+    pub subdir: Option<PathBuf>,
+    #[serde(default)]
+    pub log: Option<LogConfig>,
+    /// Client xlator options keyed by xlator glob and option name, such
+    /// as "*-io-cache.cache-size", applied as a TuningProfile::Custom
+    #[serde(default)]
+    pub xlator_options: BTreeMap<String, String>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+}
+
+fn invalid(field: &str, reason: String) -> GlusterError {
+    GlusterError::InvalidConfig {
+        field: field.to_string(),
+        reason,
+    }
+}
+
+impl GlusterConfig {
+    /// Check the config and connect with it
+    pub fn connect(&self) -> Result<Gluster, GlusterError> {
+        GlusterBuilder::from_config(self)?.connect()
+    }
+}
+
+impl GlusterBuilder {
+    /// A builder with the settings in config.  Everything is checked
+    /// here, before anything reaches gfapi, failing with
+    /// GlusterError::InvalidConfig naming the field at fault.
+    pub fn from_config(config: &GlusterConfig) -> Result<GlusterBuilder, GlusterError> {
+        if config.volume.is_empty() {
+            return Err(invalid("volume", "is empty".to_string()));
+        }
+        let mut builder = GlusterBuilder::new(&config.volume);
+
+        match (config.volfile.as_ref(), config.servers.is_empty()) {
+            (Some(_), false) => {
+                return Err(invalid(
+                    "volfile",
+                    "can't be used together with servers".to_string(),
+                ))
+            }
+            (Some(volfile), true) => builder = builder.volfile(volfile),
+            (None, true) => {
+                return Err(invalid(
+                    "servers",
+                    "is empty, give at least one server or a volfile".to_string(),
+                ))
+            }
+            (None, false) => {
+                let mut servers = parse_servers(&config.servers.join(","))
+                    .map_err(|e| invalid("servers", e.to_string()))?
+                    .into_iter();
+                // parse_servers never returns an empty list
+                let first = servers.next().unwrap();
+                builder = builder.server(&first.host).port(first.port);
+                for backup in servers {
+                    builder = builder.backup_server(&backup.host, backup.port);
+                }
+            }
+        }
+
+        if let Some(ref transport) = config.transport {
+            if !valid_transport(transport) {
+                return Err(invalid(
+                    "transport",
+                    format!("{:?} isn't tcp, unix or rdma", transport),
+                ));
+            }
+            builder = builder.transport(transport);
+        }
+
+        if let Some(ref subdir) = config.subdir {
+            builder = builder.subdir(subdir);
+        }
+
+        if let Some(ref log) = config.log {
+            let level = match log.level {
+                Some(ref level) => level
+                    .parse::<GlusterLogLevel>()
+                    .map_err(|e| invalid("log.level", e.to_string()))?,
+                None => GlusterLogLevel::Info,
+            };
+            builder = builder.logging(&log.file, level);
+        }
+
+        let mut options = Vec::new();
+        for (name, value) in &config.xlator_options {
+            let mut parts = name.splitn(2, '.');
+            match (parts.next(), parts.next()) {
+                (Some(xlator), Some(key)) if !xlator.is_empty() && !key.is_empty() => {
+                    options.push(XlatorOption::new(xlator, key, value))
+                }
+                _ => {
+                    return Err(invalid(
+                        "xlator_options",
+                        format!("{:?} isn't in the form xlator.option", name),
+                    ))
+                }
+            }
+        }
+        let timeouts = [
+            ("timeouts.ping", "ping-timeout", config.timeouts.ping),
+            ("timeouts.frame", "frame-timeout", config.timeouts.frame),
+        ];
+        for &(field, key, seconds) in &timeouts {
+            match seconds {
+                Some(0) => return Err(invalid(field, "can't be 0".to_string())),
+                Some(seconds) => {
+                    options.push(XlatorOption::new(CLIENT_XLATORS, key, &seconds.to_string()))
+                }
+                None => {}
+            }
+        }
+        if !options.is_empty() {
+            let tuning = TuningProfile::Custom(options);
+            tuning
+                .options()
+                .map_err(|e| invalid("xlator_options", e.to_string()))?;
+            builder = builder.tuning(tuning);
+        }
+
+        Ok(builder.read_only(config.read_only))
+    }
+}
@@ -0,0 +1,388 @@
+use errno::{errno, Errno};
+use libc::{EEXIST, ENOENT};
+
+use batch::parallel_map;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use mode::defaults;
+use preserve::{CopyReport, PreserveOptions, PreserveWarning};
+use write::{VerifyMode, WriteOptions};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What a copy does when its destination already exists
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overwrite {
+    /// Leave the destination alone
+    Never,
+    /// Replace the destination
+    Always,
+    /// Replace the destination if the source's mtime is later than its
+    IfNewer,
+    /// Replace the destination if its size differs from the source's
+    IfDifferentSize,
+}
+
+impl Overwrite {
+    /// Whether the policy replaces a destination with metadata
+    /// destination by a source with metadata source
+    pub fn replaces(&self, source: &Metadata, destination: &Metadata) -> bool {
+        match *self {
+            Overwrite::Never => false,
+            Overwrite::Always => true,
+            Overwrite::IfNewer => {
+                (source.mtime(), source.mtime_nsec())
+                    > (destination.mtime(), destination.mtime_nsec())
+            }
+            Overwrite::IfDifferentSize => source.len() != destination.len(),
+        }
+    }
+}
+
+/// What to do about one existing destination
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictAction {
+    Overwrite,
+    Skip,
+    /// Stop with GlusterError::CopyAborted
+    Abort,
+}
+
+/// An existing destination, as handed to CopyOptions::on_conflict
+#[derive(Debug)]
+pub struct Conflict<'a> {
+    pub source: &'a Path,
+    pub destination: &'a Path,
+    pub source_metadata: &'a Metadata,
+    pub destination_metadata: &'a Metadata,
+    /// What the Overwrite policy would do
+    pub policy: ConflictAction,
+}
+
+type ConflictHandler = Arc<dyn Fn(&Conflict) -> ConflictAction + Send + Sync>;
+
+/// Options shared by copy_with, copy_parallel, copy_dir and
+/// VolumeSet::copy_with and move_item_with.  The data transfer itself is
+/// set up with the WriteOptions these carry, see write_options.
+#[derive(Clone)]
+pub struct CopyOptions {
+    overwrite: Overwrite,
+    create_parents: bool,
+    on_conflict: Option<ConflictHandler>,
+    pub(crate) write: WriteOptions,
+}
+
+impl Default for CopyOptions {
+    fn default() -> CopyOptions {
+        CopyOptions {
+            overwrite: Overwrite::Always,
+            create_parents: false,
+            on_conflict: None,
+            write: WriteOptions::default(),
+        }
+    }
+}
+
+/// Copies with these WriteOptions replace existing destinations, as
+/// Gluster::copy does
+impl From<WriteOptions> for CopyOptions {
+    fn from(write: WriteOptions) -> CopyOptions {
+        CopyOptions {
+            write,
+            ..CopyOptions::default()
+        }
+    }
+}
+
+impl fmt::Debug for CopyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CopyOptions")
+            .field("overwrite", &self.overwrite)
+            .field("create_parents", &self.create_parents)
+            .field("on_conflict", &self.on_conflict.is_some())
+            .field("write", &self.write)
+            .finish()
+    }
+}
+
+impl CopyOptions {
+    pub fn new() -> CopyOptions {
+        CopyOptions::default()
+    }
+
+    /// Defaults to Overwrite::Always
+    pub fn overwrite(mut self, overwrite: Overwrite) -> CopyOptions {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Size of each read and write of the transfer.  Defaults to 1MB.
+    pub fn buffer_size(mut self, buffer_size: usize) -> CopyOptions {
+        self.write = self.write.chunk_size(buffer_size);
+        self
+    }
+
+    /// Create the destination's missing parent directories, 0755 before
+    /// the umask.  Defaults to false.
+    pub fn create_parents(mut self, create_parents: bool) -> CopyOptions {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Decide each existing destination with handler instead of the
+    /// Overwrite policy, for interactive tools.  The Conflict says what
+    /// the policy would have done.  handler can be called from several
+    /// threads at once by copy_parallel.
+    pub fn on_conflict<F>(mut self, handler: F) -> CopyOptions
+    where
+        F: Fn(&Conflict) -> ConflictAction + Send + Sync + 'static,
+    {
+        self.on_conflict = Some(Arc::new(handler));
+        self
+    }
+
+    /// See WriteOptions::preserve
+    pub fn preserve(mut self, preserve: PreserveOptions) -> CopyOptions {
+        self.write = self.write.preserve(preserve);
+        self
+    }
+
+    /// See WriteOptions::verify
+    pub fn verify(mut self, verify: VerifyMode) -> CopyOptions {
+        self.write = self.write.verify(verify);
+        self
+    }
+
+    /// See WriteOptions::sparse
+    pub fn sparse(mut self, sparse: bool) -> CopyOptions {
+        self.write = self.write.sparse(sparse);
+        self
+    }
+
+    /// Replace the WriteOptions used for the transfer, for the settings
+    /// CopyOptions doesn't repeat such as mode and free space checks.
+    /// This resets buffer_size, preserve, verify and sparse to what write
+    /// has.
+    pub fn write_options(mut self, write: WriteOptions) -> CopyOptions {
+        self.write = write;
+        self
+    }
+}
+
+/// What happened to one copy
+#[derive(Clone, Debug, PartialEq)]
+pub enum CopyOutcome {
+    Copied(CopyReport),
+    /// move_item_with renamed the source within its volume
+    Renamed,
+    /// The destination existed and was left alone
+    Skipped,
+}
+
+/// What copy_dir did
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CopyDirReport {
+    /// Files and symlinks copied
+    pub copied: u64,
+    /// Files and symlinks left alone because their destination existed
+    pub skipped: u64,
+    /// Entries that aren't files, directories or symlinks, which aren't
+    /// copied
+    pub unsupported: Vec<PathBuf>,
+    /// Bytes of file data copied
+    pub bytes: u64,
+    /// Destination paths and what preserve had to skip on them
+    pub warnings: Vec<(PathBuf, PreserveWarning)>,
+}
+
+// The metadata of path, None if it doesn't exist
+pub(crate) fn existing(
+    gluster: &Gluster,
+    path: &Path,
+    follow: bool,
+) -> Result<Option<Metadata>, GlusterError> {
+    let result = if follow {
+        gluster.metadata(path)
+    } else {
+        gluster.symlink_metadata(path)
+    };
+    match result {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(_) if errno() == Errno(ENOENT) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether a copy from from to to goes ahead, given the metadata of both
+/// when to exists.  Fails with CopyAborted if the conflict handler says
+/// so, and with SameFile, before any policy or handler is consulted,
+/// when to is from or a hard link to it, since overwriting it would
+/// truncate the source.
+pub(crate) fn resolve(
+    opts: &CopyOptions,
+    from: &Path,
+    source: &Metadata,
+    to: &Path,
+    destination: Option<&Metadata>,
+) -> Result<bool, GlusterError> {
+    let destination = match destination {
+        Some(destination) => destination,
+        None => return Ok(true),
+    };
+    if source.is_same_file_as(destination) {
+        return Err(GlusterError::SameFile {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+    }
+    let policy = if opts.overwrite.replaces(source, destination) {
+        ConflictAction::Overwrite
+    } else {
+        ConflictAction::Skip
+    };
+    let action = match opts.on_conflict {
+        Some(ref handler) => handler(&Conflict {
+            source: from,
+            destination: to,
+            source_metadata: source,
+            destination_metadata: destination,
+            policy,
+        }),
+        None => policy,
+    };
+    match action {
+        ConflictAction::Overwrite => Ok(true),
+        ConflictAction::Skip => Ok(false),
+        ConflictAction::Abort => Err(GlusterError::CopyAborted {
+            path: to.to_path_buf(),
+        }),
+    }
+}
+
+/// Make the parents of to if opts asks for it
+pub(crate) fn create_parents(
+    gluster: &Gluster,
+    to: &Path,
+    opts: &CopyOptions,
+) -> Result<(), GlusterError> {
+    match to.parent() {
+        Some(parent) if opts.create_parents && !parent.as_os_str().is_empty() => {
+            gluster.create_dir_all(parent, defaults::DIR_0755)
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Gluster {
+    /// Copy the file at from to to according to opts, both on this
+    /// volume.  An existing destination is dealt with by the Overwrite
+    /// policy or on_conflict handler before any data is read.
+    pub fn copy_with(
+        &self,
+        from: &Path,
+        to: &Path,
+        opts: &CopyOptions,
+    ) -> Result<CopyOutcome, GlusterError> {
+        let source = self.metadata(from)?;
+        if !resolve(opts, from, &source, to, existing(self, to, true)?.as_ref())? {
+            return Ok(CopyOutcome::Skipped);
+        }
+        create_parents(self, to, opts)?;
+        Ok(CopyOutcome::Copied(self.copy_with_report(
+            from,
+            to,
+            &opts.write,
+        )?))
+    }
+
+    /// copy_with each (from, to) pair using up to workers threads sharing
+    /// this connection.  Results are in the same order as pairs, and one
+    /// failing doesn't stop the others.
+    pub fn copy_parallel(
+        &self,
+        pairs: &[(PathBuf, PathBuf)],
+        workers: usize,
+        opts: &CopyOptions,
+    ) -> Vec<Result<CopyOutcome, GlusterError>> {
+        parallel_map(pairs, workers, |(from, to)| self.copy_with(from, to, opts))
+    }
+
+    /// Copy the directory tree at from to to.  Directories are created
+    /// with their source's permission bits, or merged into when they
+    /// exist.  Files go through copy_with and symlinks are recreated,
+    /// both subject to the Overwrite policy or on_conflict handler.  The
+    /// first error stops the copy.
+    pub fn copy_dir(
+        &self,
+        from: &Path,
+        to: &Path,
+        opts: &CopyOptions,
+    ) -> Result<CopyDirReport, GlusterError> {
+        if to.starts_with(from) {
+            return Err(GlusterError::new(format!(
+                "can't copy {} into itself",
+                from.display()
+            )));
+        }
+        create_parents(self, to, opts)?;
+        let mut report = CopyDirReport::default();
+        self.copy_dir_into(from, to, opts, &mut report)?;
+        Ok(report)
+    }
+
+    fn copy_dir_into(
+        &self,
+        from: &Path,
+        to: &Path,
+        opts: &CopyOptions,
+        report: &mut CopyDirReport,
+    ) -> Result<(), GlusterError> {
+        let source = self.metadata(from)?;
+        if let Err(e) = self.mkdir(to, source.permissions()) {
+            if errno() != Errno(EEXIST) || !self.metadata(to)?.is_dir() {
+                return Err(e);
+            }
+        }
+        for (entry, metadata) in self.list_dir(from, 1)? {
+            let source_path = from.join(&entry.path);
+            let dest_path = to.join(&entry.path);
+            if metadata.is_dir() {
+                self.copy_dir_into(&source_path, &dest_path, opts, report)?;
+            } else if metadata.is_symlink() {
+                let destination = existing(self, &dest_path, false)?;
+                if !resolve(
+                    opts,
+                    &source_path,
+                    &metadata,
+                    &dest_path,
+                    destination.as_ref(),
+                )? {
+                    report.skipped += 1;
+                    continue;
+                }
+                if destination.is_some() {
+                    self.unlink(&dest_path)?;
+                }
+                let target = self.read_link(&source_path)?;
+                self.symlink(&target, &dest_path)?;
+                report.copied += 1;
+            } else if metadata.is_file() {
+                match self.copy_with(&source_path, &dest_path, opts)? {
+                    CopyOutcome::Copied(copied) => {
+                        report.copied += 1;
+                        report.bytes += copied.bytes;
+                        for warning in copied.warnings {
+                            report.warnings.push((dest_path.clone(), warning));
+                        }
+                    }
+                    _ => report.skipped += 1,
+                }
+            } else {
+                report.unsupported.push(source_path);
+            }
+        }
+        Ok(())
+    }
+}
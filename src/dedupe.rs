@@ -0,0 +1,451 @@
+use libc::O_RDONLY;
+
+use batch::parallel_map;
+use buffer_pool::BufferPool;
+use checksum::{ChecksumAlgorithm, Sha256};
+use checksum_cache::to_hex;
+use gluster::{Gluster, GlusterError};
+use walk::WalkOptions;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const COMPARE_CHUNK: usize = 1024 * 1024;
+
+// How much of each end of a file find_duplicates hashes before reading
+// all of it
+const PARTIAL_HASH_LEN: u64 = 64 * 1024;
+
+/// Options for Gluster::dedupe_hardlink
+#[derive(Clone, Debug)]
+pub struct DedupeOptions {
+    dry_run: bool,
+    min_size: u64,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> DedupeOptions {
+        DedupeOptions {
+            dry_run: false,
+            min_size: 1,
+        }
+    }
+}
+
+impl DedupeOptions {
+    pub fn new() -> DedupeOptions {
+        DedupeOptions::default()
+    }
+
+    /// Find and report the duplicates without linking anything.  Defaults
+    /// to false.
+    pub fn dry_run(mut self, dry_run: bool) -> DedupeOptions {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Leave files smaller than this alone.  Defaults to 1 so empty files
+    /// aren't linked together.
+    pub fn min_size(mut self, min_size: u64) -> DedupeOptions {
+        self.min_size = min_size;
+        self
+    }
+}
+
+/// Files found to have the same contents
+#[derive(Clone, Debug, PartialEq)]
+pub struct DedupeGroup {
+    /// The copy the others are linked to, the first by path
+    pub canonical: PathBuf,
+    /// Replaced by links to canonical, or would be on a dry run
+    pub duplicates: Vec<PathBuf>,
+    /// Length of each file
+    pub size: u64,
+}
+
+/// What Gluster::dedupe_hardlink did, or would do on a dry run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DedupeReport {
+    pub groups: Vec<DedupeGroup>,
+    pub links_created: u64,
+    pub bytes_reclaimed: u64,
+}
+
+/// Options for Gluster::find_duplicates
+#[derive(Clone, Debug)]
+pub struct DuplicateOptions {
+    min_size: u64,
+    workers: usize,
+    walk: WalkOptions,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> DuplicateOptions {
+        DuplicateOptions {
+            min_size: 1,
+            workers: 4,
+            walk: WalkOptions::new(),
+        }
+    }
+}
+
+impl DuplicateOptions {
+    pub fn new() -> DuplicateOptions {
+        DuplicateOptions::default()
+    }
+
+    /// Leave out files smaller than this.  Defaults to 1 so empty files
+    /// aren't reported.
+    pub fn min_size(mut self, min_size: u64) -> DuplicateOptions {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Number of files hashed at once.  Defaults to 4.
+    pub fn workers(mut self, workers: usize) -> DuplicateOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// How the tree is walked
+    pub fn walk_options(mut self, walk: WalkOptions) -> DuplicateOptions {
+        self.walk = walk;
+        self
+    }
+}
+
+/// Files found by Gluster::find_duplicates to have the same contents
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DuplicateGroup {
+    /// Length of each file
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the contents
+    pub sha256: String,
+    /// One path per inode, sorted.  Of several hard links to one file
+    /// only the first by path is listed.
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Number of separate copies
+    pub fn count(&self) -> u64 {
+        self.paths.len() as u64
+    }
+
+    /// Bytes freed if all but one copy were replaced by hard links
+    pub fn reclaimable(&self) -> u64 {
+        self.count().saturating_sub(1) * self.size
+    }
+}
+
+/// What Gluster::find_duplicates found
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DuplicateReport {
+    /// Sorted by their first path, see sort_by_reclaimable
+    pub groups: Vec<DuplicateGroup>,
+    /// Distinct inodes looked at, not counting those under min_size
+    pub files_scanned: u64,
+    /// Paths left out because they're hard links to a file already
+    /// scanned
+    pub hard_links: u64,
+    /// Total of DuplicateGroup::reclaimable over the groups
+    pub reclaimable: u64,
+}
+
+impl DuplicateReport {
+    /// Put the groups that would free the most space first
+    pub fn sort_by_reclaimable(&mut self) {
+        self.groups.sort_by(|a, b| {
+            b.reclaimable()
+                .cmp(&a.reclaimable())
+                .then_with(|| a.paths.cmp(&b.paths))
+        });
+    }
+}
+
+// A file with a distinct inode, found by the walk
+struct Candidate {
+    path: PathBuf,
+    dev: u64,
+}
+
+// Name in dir for the link made before renaming it over a duplicate
+fn temp_name(duplicate: &Path, n: u64) -> PathBuf {
+    let mut name = OsString::from(".");
+    if let Some(file_name) = duplicate.file_name() {
+        name.push(file_name);
+    }
+    name.push(format!(".dedupe-{}-{}", process::id(), n));
+    duplicate.with_file_name(name)
+}
+
+impl Gluster {
+    /// Replace byte identical regular files under root with hard links to
+    /// one copy.  Files are grouped by length and candidates compared a
+    /// chunk at a time before anything is linked.  Each duplicate is
+    /// replaced by linking the canonical copy to a temporary name beside
+    /// it, fsyncing the directory and renaming the link over the
+    /// duplicate, so one of the two names always holds the data.  Paths
+    /// already sharing an inode are only looked at once.
+    ///
+    /// A duplicate written to between the comparison and the rename loses
+    /// that write, so don't run this over files still being modified.
+    /// Ownership, permissions and xattrs of the duplicates aren't kept,
+    /// every path ends up with the canonical copy's.
+    pub fn dedupe_hardlink(
+        &self,
+        root: &Path,
+        opts: &DedupeOptions,
+    ) -> Result<DedupeReport, GlusterError> {
+        let mut by_size: BTreeMap<u64, Vec<Candidate>> = BTreeMap::new();
+        let mut seen: HashSet<(u64, u64)> = HashSet::new();
+        for entry in self.walk(root, &WalkOptions::new()) {
+            let entry = entry?;
+            if !entry.metadata.is_file() || entry.metadata.len() < opts.min_size {
+                continue;
+            }
+            if !seen.insert(entry.metadata.file_id()) {
+                continue;
+            }
+            by_size
+                .entry(entry.metadata.len())
+                .or_default()
+                .push(Candidate {
+                    path: entry.path,
+                    dev: entry.metadata.dev(),
+                });
+        }
+
+        let mut report = DedupeReport::default();
+        let mut temp_count = 0;
+        for (size, mut candidates) in by_size {
+            if candidates.len() < 2 {
+                continue;
+            }
+            candidates.sort_by(|a, b| a.path.cmp(&b.path));
+            // Split the candidates into sets with the same contents, each
+            // compared against the first member of the sets so far
+            let mut sets: Vec<Vec<Candidate>> = Vec::new();
+            for candidate in candidates {
+                let mut found = None;
+                for (i, set) in sets.iter().enumerate() {
+                    let first = &set[0];
+                    // Hard links can't cross devices
+                    if first.dev == candidate.dev
+                        && self.same_contents(&first.path, &candidate.path, size)?
+                    {
+                        found = Some(i);
+                        break;
+                    }
+                }
+                match found {
+                    Some(i) => sets[i].push(candidate),
+                    None => sets.push(vec![candidate]),
+                }
+            }
+
+            for set in sets {
+                if set.len() < 2 {
+                    continue;
+                }
+                let mut set = set.into_iter();
+                let canonical = set.next().map(|c| c.path).unwrap_or_default();
+                let mut group = DedupeGroup {
+                    canonical,
+                    duplicates: Vec::new(),
+                    size,
+                };
+                for duplicate in set {
+                    if !opts.dry_run {
+                        temp_count += 1;
+                        self.replace_with_link(
+                            &group.canonical,
+                            &duplicate.path,
+                            &temp_name(&duplicate.path, temp_count),
+                        )?;
+                        report.links_created += 1;
+                    }
+                    trace!(
+                        "{} duplicates {}",
+                        duplicate.path.display(),
+                        group.canonical.display()
+                    );
+                    report.bytes_reclaimed += size;
+                    group.duplicates.push(duplicate.path);
+                }
+                report.groups.push(group);
+            }
+        }
+        Ok(report)
+    }
+
+    fn replace_with_link(
+        &self,
+        canonical: &Path,
+        duplicate: &Path,
+        temp: &Path,
+    ) -> Result<(), GlusterError> {
+        self.link(canonical, temp)?;
+        let parent = match duplicate.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let result = self
+            .fsync_dir(parent)
+            .and_then(|_| self.rename(temp, duplicate));
+        if result.is_err() {
+            let _ = self.unlink(temp);
+        }
+        result
+    }
+
+    /// Report regular files under root with the same contents, without
+    /// changing anything.  Files are grouped by length, then by a hash of
+    /// their first and last 64KiB, and only those still alike are read in
+    /// full and grouped by SHA-256, so files that differ early cost little
+    /// to rule out.  Up to opts.workers files are hashed at once.  Paths
+    /// sharing an inode count as one file, since linking them again would
+    /// free nothing.
+    pub fn find_duplicates(
+        &self,
+        root: &Path,
+        opts: &DuplicateOptions,
+    ) -> Result<DuplicateReport, GlusterError> {
+        let mut report = DuplicateReport::default();
+        // The first path by name of each inode, with its length
+        let mut inodes: HashMap<(u64, u64), (PathBuf, u64)> = HashMap::new();
+        for entry in self.walk(root, &opts.walk) {
+            let entry = entry?;
+            if !entry.metadata.is_file() || entry.metadata.len() < opts.min_size {
+                continue;
+            }
+            let len = entry.metadata.len();
+            match inodes.get_mut(&entry.metadata.file_id()) {
+                Some(first) => {
+                    report.hard_links += 1;
+                    if entry.path < first.0 {
+                        first.0 = entry.path;
+                    }
+                }
+                None => {
+                    inodes.insert(entry.metadata.file_id(), (entry.path, len));
+                }
+            }
+        }
+        report.files_scanned = inodes.len() as u64;
+
+        let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+        for (_, (path, len)) in inodes {
+            by_size.entry(len).or_default().push(path);
+        }
+        let candidates: Vec<(PathBuf, u64)> = by_size
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .flat_map(|(len, paths)| paths.into_iter().map(move |path| (path, len)))
+            .collect();
+
+        let partial = parallel_map(&candidates, opts.workers, |(path, len)| {
+            self.partial_digest(path, *len)
+        });
+        let mut by_partial: BTreeMap<(u64, String), Vec<PathBuf>> = BTreeMap::new();
+        for ((path, len), digest) in candidates.into_iter().zip(partial) {
+            by_partial.entry((len, digest?)).or_default().push(path);
+        }
+
+        // A partial digest of a file no longer than both ends is already
+        // the digest of all of it
+        let mut by_digest: BTreeMap<(u64, String), Vec<PathBuf>> = BTreeMap::new();
+        let mut unresolved: Vec<(PathBuf, u64)> = Vec::new();
+        for ((len, digest), paths) in by_partial {
+            if paths.len() < 2 {
+                continue;
+            }
+            if len <= 2 * PARTIAL_HASH_LEN {
+                by_digest.insert((len, digest), paths);
+            } else {
+                unresolved.extend(paths.into_iter().map(|path| (path, len)));
+            }
+        }
+        let full = parallel_map(
+            &unresolved,
+            opts.workers,
+            |(path, _)| -> Result<String, GlusterError> {
+                let mut file = self.open_file(path, O_RDONLY)?;
+                let digest = ChecksumAlgorithm::Sha256.digest_reader(&mut file)?;
+                file.close()?;
+                Ok(to_hex(&digest))
+            },
+        );
+        for ((path, len), digest) in unresolved.into_iter().zip(full) {
+            by_digest.entry((len, digest?)).or_default().push(path);
+        }
+
+        for ((size, sha256), mut paths) in by_digest {
+            if paths.len() < 2 {
+                continue;
+            }
+            paths.sort();
+            let group = DuplicateGroup {
+                size,
+                sha256,
+                paths,
+            };
+            report.reclaimable += group.reclaimable();
+            report.groups.push(group);
+        }
+        report.groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+        Ok(report)
+    }
+
+    // SHA-256 of the first and last PARTIAL_HASH_LEN bytes of the len
+    // byte file at path, or of all of it if it's no longer than that
+    fn partial_digest(&self, path: &Path, len: u64) -> Result<String, GlusterError> {
+        let file = self.open_file(path, O_RDONLY)?;
+        let mut buffer = BufferPool::global().get(PARTIAL_HASH_LEN as usize);
+        let mut digest = Sha256::new();
+        let ranges = if len <= 2 * PARTIAL_HASH_LEN {
+            vec![(0, len)]
+        } else {
+            vec![
+                (0, PARTIAL_HASH_LEN),
+                (len - PARTIAL_HASH_LEN, PARTIAL_HASH_LEN),
+            ]
+        };
+        for (start, range_len) in ranges {
+            let mut offset = start;
+            while offset < start + range_len {
+                let chunk = (start + range_len - offset).min(buffer.len() as u64) as usize;
+                file.read_exact_at(&mut buffer[..chunk], offset)?;
+                digest.update(&buffer[..chunk]);
+                offset += chunk as u64;
+            }
+        }
+        file.close()?;
+        Ok(to_hex(&digest.finish()))
+    }
+
+    // Whether the files at a and b, both len bytes long, hold the same
+    // bytes
+    fn same_contents(&self, a: &Path, b: &Path, len: u64) -> Result<bool, GlusterError> {
+        let pool = BufferPool::global();
+        let mut a_buf = pool.get(COMPARE_CHUNK);
+        let mut b_buf = pool.get(COMPARE_CHUNK);
+        let a_file = self.open_file(a, O_RDONLY)?;
+        let b_file = self.open_file(b, O_RDONLY)?;
+        let mut offset = 0;
+        while offset < len {
+            let chunk = (len - offset).min(COMPARE_CHUNK as u64) as usize;
+            a_file.read_exact_at(&mut a_buf[..chunk], offset)?;
+            b_file.read_exact_at(&mut b_buf[..chunk], offset)?;
+            if a_buf[..chunk] != b_buf[..chunk] {
+                return Ok(false);
+            }
+            offset += chunk as u64;
+        }
+        Ok(true)
+    }
+}
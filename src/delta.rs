@@ -0,0 +1,174 @@
+use libc::{mode_t, O_CREAT, O_RDONLY, O_RDWR};
+
+use checksum::{Adler32, Crc32c};
+use gluster::{Gluster, GlusterError};
+use mode::{defaults, ModePolicy};
+
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+/// Where Gluster::sync_file reads the new contents from
+#[derive(Clone, Copy, Debug)]
+pub enum SourceFile<'a> {
+    /// A file on the local filesystem
+    Local(&'a Path),
+    /// A file on the same volume as the destination
+    Remote(&'a Path),
+}
+
+/// Options for Gluster::sync_file
+#[derive(Clone, Debug)]
+pub struct SyncOptions {
+    mode: mode_t,
+    fsync: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> SyncOptions {
+        SyncOptions {
+            mode: defaults::FILE_0644,
+            fsync: true,
+        }
+    }
+}
+
+impl SyncOptions {
+    pub fn new() -> SyncOptions {
+        SyncOptions::default()
+    }
+
+    /// Permissions for the destination if it has to be created.  Defaults
+    /// to 0644.
+    pub fn mode(mut self, mode: mode_t) -> SyncOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// fsync the destination once it's updated.  Defaults to true.
+    pub fn fsync(mut self, fsync: bool) -> SyncOptions {
+        self.fsync = fsync;
+        self
+    }
+}
+
+/// What Gluster::sync_file did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyncReport {
+    /// Blocks already the same in the destination
+    pub blocks_reused: u64,
+    /// Blocks written to the destination
+    pub blocks_transferred: u64,
+    pub bytes_transferred: u64,
+    /// Length of the source, and now of the destination
+    pub len: u64,
+}
+
+// Weak and strong checksums of a block.  Both have to match for the
+// block to be left alone.
+#[derive(Clone, Copy, PartialEq)]
+struct BlockSignature {
+    adler: u32,
+    crc: u32,
+    len: usize,
+}
+
+impl BlockSignature {
+    fn of(block: &[u8]) -> BlockSignature {
+        BlockSignature {
+            adler: Adler32::checksum(block),
+            crc: Crc32c::checksum(block),
+            len: block.len(),
+        }
+    }
+}
+
+// Read until buf is full or the end of input, returning how much was read
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, GlusterError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(GlusterError::IoError(e)),
+        }
+    }
+    Ok(filled)
+}
+
+impl Gluster {
+    /// Make the file at dest the same as src, writing only the blocks of
+    /// block_size bytes that differ.  The destination is read first to
+    /// checksum each block, then the source is read through and every
+    /// block whose Adler-32 and CRC32C don't match the destination's
+    /// block at the same offset is written.  Finally the destination is
+    /// truncated to the source's length.  dest is created if it doesn't
+    /// exist.
+    ///
+    /// Blocks are only compared at the same offset, unlike rsync's rolling
+    /// match, so data inserted or removed part way through causes
+    /// everything after it to be rewritten.  Suited to files modified in
+    /// place such as images and databases.
+    pub fn sync_file(
+        &self,
+        src: SourceFile,
+        dest: &Path,
+        block_size: usize,
+        opts: &SyncOptions,
+    ) -> Result<SyncReport, GlusterError> {
+        let block_size = block_size.max(1);
+        let mut buffer = vec![0; block_size];
+        let dest_file =
+            self.create_file_with(dest, O_CREAT | O_RDWR, opts.mode, ModePolicy::RespectUmask)?;
+
+        let mut signatures = Vec::new();
+        {
+            let mut reader = self.open_file(dest, O_RDONLY)?;
+            loop {
+                let len = read_block(&mut reader, &mut buffer)?;
+                if len == 0 {
+                    break;
+                }
+                signatures.push(BlockSignature::of(&buffer[..len]));
+            }
+        }
+
+        let mut source: Box<dyn Read> = match src {
+            SourceFile::Local(path) => Box::new(File::open(path)?),
+            SourceFile::Remote(path) => Box::new(self.open_file(path, O_RDONLY)?),
+        };
+        let mut report = SyncReport {
+            blocks_reused: 0,
+            blocks_transferred: 0,
+            bytes_transferred: 0,
+            len: 0,
+        };
+        let mut index = 0;
+        loop {
+            let len = read_block(&mut source, &mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            let block = &buffer[..len];
+            if signatures.get(index) == Some(&BlockSignature::of(block)) {
+                report.blocks_reused += 1;
+            } else {
+                dest_file.write_all_at(block, report.len)?;
+                report.blocks_transferred += 1;
+                report.bytes_transferred += len as u64;
+            }
+            report.len += len as u64;
+            index += 1;
+        }
+
+        if (dest_file.fstat()?.st_size as u64) > report.len {
+            self.ftruncate(dest_file.handle()?, report.len as i64)?;
+        }
+        if opts.fsync {
+            dest_file.fsync()?;
+        }
+        dest_file.close()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,184 @@
+use libc::{dirent, stat};
+
+use glfs::*;
+use gluster::{get_error, DirEntry, Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::ffi::{CStr, OsStr};
+use std::mem::zeroed;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+/// An entry sent by a DirStream
+#[derive(Clone, Debug)]
+pub struct StreamedEntry {
+    pub entry: DirEntry,
+    /// The stat readdirplus returned with the entry, from
+    /// Gluster::read_dir_streamed_plus.  None without readdirplus or when
+    /// the server didn't send one.
+    pub metadata: Option<Metadata>,
+}
+
+/// Entries of a directory as a background thread reads them, handed to
+/// the closure given to Gluster::read_dir_streamed.  The thread reads at
+/// most the buffer size ahead of the iterator.  An error ends the stream
+/// with a final Err.  Once the closure returns the thread is stopped and
+/// joined, however much of the stream was read.
+pub struct DirStream {
+    entries: Option<Receiver<Result<StreamedEntry, GlusterError>>>,
+    stop: Arc<AtomicBool>,
+    read: Arc<AtomicUsize>,
+}
+
+impl DirStream {
+    /// How many entries the background thread has read so far, including
+    /// any that are buffered and not yet returned by the iterator
+    pub fn entries_read(&self) -> usize {
+        self.read.load(Ordering::SeqCst)
+    }
+}
+
+impl Iterator for DirStream {
+    type Item = Result<StreamedEntry, GlusterError>;
+
+    fn next(&mut self) -> Option<Result<StreamedEntry, GlusterError>> {
+        self.entries.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for DirStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Dropping the receiver wakes the thread if it's blocked sending
+        self.entries = None;
+    }
+}
+
+// Reads the directory on the background thread
+struct Producer {
+    dir: PathBuf,
+    plus: bool,
+    tx: SyncSender<Result<StreamedEntry, GlusterError>>,
+    stop: Arc<AtomicBool>,
+    read: Arc<AtomicUsize>,
+}
+
+impl Producer {
+    fn run(self, gluster: &Gluster) {
+        let dir = match gluster.opendir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) => {
+                let _ = self.tx.send(Err(e));
+                return;
+            }
+        };
+        while !self.stop.load(Ordering::SeqCst) {
+            let result = unsafe { self.next_entry(dir.dir_handle) };
+            let entry = match result {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = self.tx.send(Err(e));
+                    break;
+                }
+            };
+            if entry.entry.path == Path::new(".") || entry.entry.path == Path::new("..") {
+                continue;
+            }
+            self.read.fetch_add(1, Ordering::SeqCst);
+            if self.tx.send(Ok(entry)).is_err() {
+                // The stream was dropped
+                break;
+            }
+        }
+    }
+
+    unsafe fn next_entry(
+        &self,
+        dir_handle: *mut Struct_glfs_fd,
+    ) -> Result<Option<StreamedEntry>, GlusterError> {
+        let mut dirent: dirent = zeroed();
+        let mut next_entry: *mut dirent = ptr::null_mut();
+        let mut stat_buf: stat = zeroed();
+        let ret_code = if self.plus {
+            glfs_readdirplus_r(dir_handle, &mut stat_buf, &mut dirent, &mut next_entry)
+        } else {
+            glfs_readdir_r(dir_handle, &mut dirent, &mut next_entry)
+        };
+        if ret_code < 0 {
+            return Err(GlusterError::new(get_error()));
+        }
+        if next_entry.is_null() {
+            // End of stream reached
+            return Ok(None);
+        }
+        let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
+        let metadata = if self.plus && stat_buf.st_ino != 0 {
+            Some(Metadata::from_stat(stat_buf))
+        } else {
+            None
+        };
+        Ok(Some(StreamedEntry {
+            entry: DirEntry {
+                path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
+                inode: dirent.d_ino,
+                file_type: dirent.d_type,
+            },
+            metadata,
+        }))
+    }
+}
+
+impl Gluster {
+    fn stream_dir<R, F>(&self, path: &Path, buffer: usize, plus: bool, f: F) -> R
+    where
+        F: FnOnce(&mut DirStream) -> R,
+    {
+        let (tx, rx) = sync_channel(buffer);
+        let stop = Arc::new(AtomicBool::new(false));
+        let read = Arc::new(AtomicUsize::new(0));
+        let producer = Producer {
+            dir: path.to_path_buf(),
+            plus,
+            tx,
+            stop: stop.clone(),
+            read: read.clone(),
+        };
+        // The scope joins the producer before the connection borrow ends,
+        // even if f panics
+        thread::scope(|scope| {
+            scope.spawn(move || producer.run(self));
+            let mut stream = DirStream {
+                entries: Some(rx),
+                stop,
+                read,
+            };
+            f(&mut stream)
+        })
+    }
+
+    /// Read the directory at path on a background thread, which sends
+    /// entries as it reads them and stays at most buffer entries ahead,
+    /// and pass the stream to f.  . and .. are left out.  The thread is
+    /// stopped and joined before this returns f's result.
+    pub fn read_dir_streamed<R, F>(&self, path: &Path, buffer: usize, f: F) -> R
+    where
+        F: FnOnce(&mut DirStream) -> R,
+    {
+        self.stream_dir(path, buffer, false, f)
+    }
+
+    /// Like read_dir_streamed but uses readdirplus, so entries come with
+    /// their metadata where the server provides it
+    pub fn read_dir_streamed_plus<R, F>(&self, path: &Path, buffer: usize, f: F) -> R
+    where
+        F: FnOnce(&mut DirStream) -> R,
+    {
+        self.stream_dir(path, buffer, true, f)
+    }
+}
@@ -0,0 +1,304 @@
+use libc::O_RDONLY;
+
+use buffer_pool::BufferPool;
+use checksum::Crc32c;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What download does when the remote file changed since an earlier
+/// interrupted run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RemoteChanged {
+    /// Throw away what was downloaded and start again
+    Restart,
+    /// Fail with an error and leave the partial download alone
+    Error,
+}
+
+/// Options for Gluster::download
+#[derive(Clone, Debug)]
+pub struct DownloadOptions {
+    chunk_size: usize,
+    on_change: RemoteChanged,
+    verify: bool,
+    max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> DownloadOptions {
+        DownloadOptions {
+            chunk_size: 1024 * 1024,
+            on_change: RemoteChanged::Restart,
+            verify: false,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn new() -> DownloadOptions {
+        DownloadOptions::default()
+    }
+
+    /// Size of each read from the volume.  At most this much is fetched
+    /// twice after an interruption.  Defaults to 1MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> DownloadOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Defaults to RemoteChanged::Restart
+    pub fn on_change(mut self, on_change: RemoteChanged) -> DownloadOptions {
+        self.on_change = on_change;
+        self
+    }
+
+    /// Once complete, read the remote file again and compare its CRC32C
+    /// against the local copy.  Defaults to false.
+    pub fn verify(mut self, verify: bool) -> DownloadOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Limit the average transfer rate of a run.  Defaults to unlimited.
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> DownloadOptions {
+        self.max_bytes_per_sec = Some(max_bytes_per_sec.max(1));
+        self
+    }
+}
+
+/// Progress reported to the callback of download_with_progress
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DownloadProgress {
+    /// Bytes of the file present locally
+    pub downloaded: u64,
+    /// Size of the remote file
+    pub total: u64,
+}
+
+/// What a call to download did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DownloadOutcome {
+    /// Offset this run started from, non zero when it resumed
+    pub resumed_from: u64,
+    /// Bytes fetched by this run
+    pub transferred: u64,
+    /// Size of the remote file
+    pub total: u64,
+    /// False if the progress callback stopped the download early
+    pub complete: bool,
+}
+
+// What's recorded about the remote file so a later run can tell if it
+// changed.  Kept next to the local file until the download completes.
+struct DownloadState {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    offset: u64,
+}
+
+impl DownloadState {
+    fn from_metadata(metadata: &Metadata, offset: u64) -> DownloadState {
+        DownloadState {
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            offset,
+        }
+    }
+
+    fn same_file(&self, other: &DownloadState) -> bool {
+        self.size == other.size && self.mtime == other.mtime
+            && self.mtime_nsec == other.mtime_nsec
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut scratch = path.as_os_str().to_os_string();
+        scratch.push(".tmp");
+        fs::write(
+            &scratch,
+            format!(
+                "size={}\nmtime={}\nmtime_nsec={}\noffset={}\n",
+                self.size, self.mtime, self.mtime_nsec, self.offset
+            ),
+        )?;
+        fs::rename(&scratch, path)
+    }
+
+    fn load(path: &Path) -> Option<DownloadState> {
+        let text = fs::read_to_string(path).ok()?;
+        let mut state = DownloadState {
+            size: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            offset: 0,
+        };
+        for line in text.lines() {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("size"), Some(v)) => state.size = v.parse().ok()?,
+                (Some("mtime"), Some(v)) => state.mtime = v.parse().ok()?,
+                (Some("mtime_nsec"), Some(v)) => state.mtime_nsec = v.parse().ok()?,
+                (Some("offset"), Some(v)) => state.offset = v.parse().ok()?,
+                _ => return None,
+            }
+        }
+        Some(state)
+    }
+}
+
+fn state_path(local: &Path) -> PathBuf {
+    let mut path = local.as_os_str().to_os_string();
+    path.push(".download-state");
+    PathBuf::from(path)
+}
+
+impl Gluster {
+    /// Copy src from the volume to the local file local, resuming an
+    /// earlier interrupted download of the same file.  See
+    /// download_with_progress.
+    pub fn download(
+        &self,
+        src: &Path,
+        local: &Path,
+        opts: &DownloadOptions,
+    ) -> Result<DownloadOutcome, GlusterError> {
+        self.download_with_progress(src, local, opts, |_| true)
+    }
+
+    /// Copy src from the volume to the local file local.  Progress is
+    /// recorded in a state file next to local (local.download-state) after
+    /// every chunk, and a later call picks up from there as long as the
+    /// remote file's size and mtime are unchanged.  progress is called
+    /// after each chunk and can return false to stop early, which leaves
+    /// the download resumable.
+    pub fn download_with_progress<F>(
+        &self,
+        src: &Path,
+        local: &Path,
+        opts: &DownloadOptions,
+        mut progress: F,
+    ) -> Result<DownloadOutcome, GlusterError>
+    where
+        F: FnMut(&DownloadProgress) -> bool,
+    {
+        let mut remote = self.open_file(src, O_RDONLY)?;
+        let metadata = Metadata::from_stat(remote.fstat()?);
+        let current = DownloadState::from_metadata(&metadata, 0);
+        let state_file = state_path(local);
+
+        let mut local_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(local)?;
+        let local_len = local_file.metadata()?.len();
+        let mut offset = match DownloadState::load(&state_file) {
+            Some(ref saved) if saved.same_file(&current) => saved.offset.min(local_len),
+            Some(_) => {
+                if opts.on_change == RemoteChanged::Error {
+                    return Err(GlusterError::new(format!(
+                        "{} changed since the download into {} started",
+                        src.display(),
+                        local.display()
+                    )));
+                }
+                0
+            }
+            // No record of an earlier run, whatever is there is stale
+            None => 0,
+        };
+        // Anything past the recorded offset may not have been synced
+        local_file.set_len(offset)?;
+        local_file.seek(SeekFrom::Start(offset))?;
+        let resumed_from = offset;
+
+        let start = Instant::now();
+        let mut buffer = BufferPool::global().get(opts.chunk_size);
+        let mut complete = true;
+        while offset < current.size {
+            remote.seek(SeekFrom::Start(offset))?;
+            let read = remote.read(&mut buffer)?;
+            if read == 0 {
+                // Shrunk underneath us
+                break;
+            }
+            local_file.write_all(&buffer[..read])?;
+            local_file.sync_data()?;
+            offset += read as u64;
+            DownloadState::from_metadata(&metadata, offset).save(&state_file)?;
+
+            if let Some(rate) = opts.max_bytes_per_sec {
+                let due = Duration::from_secs_f64((offset - resumed_from) as f64 / rate as f64);
+                let elapsed = start.elapsed();
+                if due > elapsed {
+                    thread::sleep(due - elapsed);
+                }
+            }
+            let keep_going = progress(&DownloadProgress {
+                downloaded: offset,
+                total: current.size,
+            });
+            if !keep_going && offset < current.size {
+                complete = false;
+                break;
+            }
+        }
+
+        if complete {
+            if offset != current.size {
+                return Err(GlusterError::new(format!(
+                    "{} changed size during the download",
+                    src.display()
+                )));
+            }
+            if opts.verify {
+                verify_download(&mut remote, local, src)?;
+            }
+            // Gone once the download is finished
+            let _ = fs::remove_file(&state_file);
+        }
+        Ok(DownloadOutcome {
+            resumed_from,
+            transferred: offset - resumed_from,
+            total: current.size,
+            complete,
+        })
+    }
+}
+
+// Compare CRC32Cs of the whole remote file and the local copy
+fn verify_download<R: Read + Seek>(
+    remote: &mut R,
+    local: &Path,
+    src: &Path,
+) -> Result<(), GlusterError> {
+    fn crc_of<R: Read>(reader: &mut R) -> io::Result<u32> {
+        let mut crc = Crc32c::new();
+        let mut buffer = BufferPool::global().get(1024 * 1024);
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(crc.finish());
+            }
+            crc.update(&buffer[..read]);
+        }
+    }
+    remote.seek(SeekFrom::Start(0))?;
+    let remote_crc = crc_of(remote)?;
+    let local_crc = crc_of(&mut File::open(local)?)?;
+    if remote_crc != local_crc {
+        return Err(GlusterError::VerificationFailed {
+            path: src.to_path_buf(),
+            offset: 0,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use libc::mode_t;
+
+use gluster::{DirEntry, Gluster, GlusterError};
+use metadata::Metadata;
+use remote_fs::{self, io_result, remote_metadata, remote_read_dir};
+use remote_fs::{RemoteFs, RemoteMetadata, RemoteReadDir};
+use write::WriteOptions;
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// A change a DryRunGluster would have made
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedOp {
+    Create {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    Write {
+        path: PathBuf,
+        len: u64,
+    },
+    Unlink {
+        path: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Chmod {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    SetXattr {
+        path: PathBuf,
+        name: String,
+        len: usize,
+    },
+    Mkdir {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    Rmdir {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for PlannedOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlannedOp::Create { ref path, mode } => {
+                write!(f, "create {} ({:04o})", path.display(), mode)
+            }
+            PlannedOp::Write { ref path, len } => {
+                write!(f, "write {} bytes to {}", len, path.display())
+            }
+            PlannedOp::Unlink { ref path } => write!(f, "unlink {}", path.display()),
+            PlannedOp::Rename { ref from, ref to } => {
+                write!(f, "rename {} to {}", from.display(), to.display())
+            }
+            PlannedOp::Chmod { ref path, mode } => {
+                write!(f, "chmod {} to {:04o}", path.display(), mode)
+            }
+            PlannedOp::SetXattr {
+                ref path,
+                ref name,
+                len,
+            } => write!(f, "set {} ({} bytes) on {}", name, len, path.display()),
+            PlannedOp::Mkdir { ref path, mode } => {
+                write!(f, "mkdir {} ({:04o})", path.display(), mode)
+            }
+            PlannedOp::Rmdir { ref path } => write!(f, "rmdir {}", path.display()),
+        }
+    }
+}
+
+/// A connection that reads from the volume but only records changes.
+/// Reads go to the volume as it is, so they don't see earlier planned
+/// changes.  Every change is reported as successful and added to the
+/// plan, see DryRunGluster::planned.
+#[derive(Debug)]
+pub struct DryRunGluster<'a> {
+    gluster: &'a Gluster,
+    planned: Mutex<Vec<PlannedOp>>,
+}
+
+impl<'a> DryRunGluster<'a> {
+    pub fn new(gluster: &'a Gluster) -> DryRunGluster<'a> {
+        DryRunGluster {
+            gluster,
+            planned: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The connection reads go to.  Anything done through it directly
+    /// isn't a dry run.
+    pub fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<PlannedOp>> {
+        match self.planned.lock() {
+            Ok(planned) => planned,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn plan(&self, op: PlannedOp) {
+        trace!("dry run: {}", op);
+        self.lock().push(op);
+    }
+
+    /// Everything recorded so far, in order
+    pub fn planned(&self) -> Vec<PlannedOp> {
+        self.lock().clone()
+    }
+
+    /// Return everything recorded so far and start a new plan
+    pub fn take_planned(&self) -> Vec<PlannedOp> {
+        let mut planned = self.lock();
+        planned.drain(..).collect()
+    }
+
+    pub fn read_to_vec(&self, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        self.gluster.read_to_vec(path)
+    }
+
+    pub fn metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        self.gluster.metadata(path)
+    }
+
+    pub fn symlink_metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        self.gluster.symlink_metadata(path)
+    }
+
+    pub fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        self.gluster.exists(path)
+    }
+
+    pub fn list_dir(&self, path: &Path) -> Result<Vec<(DirEntry, Metadata)>, GlusterError> {
+        self.gluster.list_dir(path, 1)
+    }
+
+    pub fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        self.gluster.getxattr(path, name)
+    }
+
+    pub fn create_file(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Create {
+            path: path.to_path_buf(),
+            mode,
+        });
+        Ok(())
+    }
+
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Write {
+            path: path.to_path_buf(),
+            len: data.len() as u64,
+        });
+        Ok(())
+    }
+
+    pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Unlink {
+            path: path.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Rename {
+            from: oldpath.to_path_buf(),
+            to: newpath.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Chmod {
+            path: path.to_path_buf(),
+            mode,
+        });
+        Ok(())
+    }
+
+    pub fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        _flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::SetXattr {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            len: value.len(),
+        });
+        Ok(())
+    }
+
+    pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Mkdir {
+            path: path.to_path_buf(),
+            mode,
+        });
+        Ok(())
+    }
+
+    pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        self.plan(PlannedOp::Rmdir {
+            path: path.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Plan a mkdir with mode for each directory leading to path that
+    /// doesn't exist on the volume, outermost first.
+    /// remote_fs::create_dir_all does the same with the default mode.
+    pub fn create_dir_all(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let mut missing = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current {
+            if dir.as_os_str().is_empty() || self.gluster.exists(dir)? {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+        for dir in missing.into_iter().rev() {
+            self.mkdir(&dir, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Plan removing everything under path and then path itself, see
+    /// remote_fs::remove_dir_all
+    pub fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        Ok(remote_fs::remove_dir_all(self, path)?)
+    }
+
+    /// Plan copying the file at from to to.  Returns the number of bytes
+    /// that would be copied.  Unlike a copy through RemoteFs the source
+    /// is only stat'd, not read.
+    pub fn copy(&self, from: &Path, to: &Path, opts: &WriteOptions) -> Result<u64, GlusterError> {
+        let len = self.metadata(from)?.len();
+        self.create_file(to, opts.mode)?;
+        self.plan(PlannedOp::Write {
+            path: to.to_path_buf(),
+            len,
+        });
+        Ok(len)
+    }
+}
+
+impl<'a> fmt::Display for DryRunGluster<'a> {
+    /// The plan, one numbered change per line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, op) in self.lock().iter().enumerate() {
+            writeln!(f, "{:>4}. {}", i + 1, op)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads go to the volume and changes are planned, as with the inherent
+/// methods.  create_dir plans a mkdir with mode 0755.
+impl<'a> RemoteFs for DryRunGluster<'a> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        io_result(|| self.read_to_vec(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        io_result(|| self.write_file(path, data))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let metadata = io_result(|| DryRunGluster::metadata(self, path))?;
+        Ok(remote_metadata(&metadata))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        Ok(remote_read_dir(io_result(|| self.list_dir(path))?))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.mkdir(path, 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        io_result(|| DryRunGluster::rename(self, from, to))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.unlink(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.rmdir(path))
+    }
+}
+
+impl Gluster {
+    /// Wrap this connection so changes are recorded instead of made.  See
+    /// DryRunGluster.
+    pub fn dry_run(&self) -> DryRunGluster<'_> {
+        DryRunGluster::new(self)
+    }
+}
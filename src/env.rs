@@ -0,0 +1,112 @@
+use builder::{valid_transport, GlusterBuilder};
+use gluster::{GlusterError, GlusterLogLevel};
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+// An environment variable's value, None if it's unset or empty
+fn var_os(name: &str) -> Option<OsString> {
+    match env::var_os(name) {
+        Some(ref value) if value.is_empty() => None,
+        value => value,
+    }
+}
+
+fn invalid(name: &str, reason: String) -> GlusterError {
+    GlusterError::InvalidEnv {
+        name: name.to_string(),
+        reason,
+    }
+}
+
+// An environment variable that has to be text, None if it's unset or
+// empty
+fn var(name: &str) -> Result<Option<String>, GlusterError> {
+    match var_os(name) {
+        Some(value) => value
+            .into_string()
+            .map(Some)
+            .map_err(|_| invalid(name, "isn't valid UTF-8".to_string())),
+        None => Ok(None),
+    }
+}
+
+impl GlusterBuilder {
+    /// Build the connection settings from the environment, see
+    /// from_env_prefixed
+    pub fn from_env() -> Result<GlusterBuilder, GlusterError> {
+        GlusterBuilder::from_env_prefixed("")
+    }
+
+    /// Build the connection settings from these environment variables,
+    /// each name preceded by prefix, as in ARCHIVE_GLUSTER_VOLUME:
+    ///
+    /// * GLUSTER_VOLUME, the volume, or volume/subdir as GlusterBuilder::new
+    ///   takes it.  Required.
+    /// * GLUSTER_SERVERS, a server list as parse_servers takes it
+    /// * GLUSTER_TRANSPORT, tcp, unix or rdma
+    /// * GLUSTER_SUBDIR, a subdirectory to mount
+    /// * GLUSTER_LOG_FILE and GLUSTER_LOG_LEVEL, where gfapi logs and at
+    ///   what GlusterLogLevel, by name.  The level defaults to info and
+    ///   needs the file.
+    ///
+    /// Unset or empty variables other than GLUSTER_VOLUME leave the
+    /// builder's defaults.  Fails with GlusterError::InvalidEnv naming
+    /// the variable that's missing or malformed.
+    pub fn from_env_prefixed(prefix: &str) -> Result<GlusterBuilder, GlusterError> {
+        let name = |suffix: &str| format!("{}GLUSTER_{}", prefix, suffix);
+
+        let volume_var = name("VOLUME");
+        let volume =
+            var(&volume_var)?.ok_or_else(|| invalid(&volume_var, "isn't set".to_string()))?;
+        let mut builder = GlusterBuilder::new(&volume);
+
+        let servers_var = name("SERVERS");
+        if let Some(servers) = var(&servers_var)? {
+            builder = builder
+                .servers_from_str(&servers)
+                .map_err(|e| invalid(&servers_var, e.to_string()))?;
+        }
+
+        let transport_var = name("TRANSPORT");
+        if let Some(transport) = var(&transport_var)? {
+            if !valid_transport(&transport) {
+                return Err(invalid(
+                    &transport_var,
+                    format!("{:?} isn't tcp, unix or rdma", transport),
+                ));
+            }
+            builder = builder.transport(&transport);
+        }
+
+        if let Some(subdir) = var_os(&name("SUBDIR")) {
+            builder = builder.subdir(PathBuf::from(subdir));
+        }
+
+        let level_var = name("LOG_LEVEL");
+        let level = match var(&level_var)? {
+            Some(level) => Some(
+                level
+                    .parse::<GlusterLogLevel>()
+                    .map_err(|e| invalid(&level_var, e.to_string()))?,
+            ),
+            None => None,
+        };
+        let file_var = name("LOG_FILE");
+        match (var_os(&file_var), level) {
+            (Some(file), level) => {
+                builder =
+                    builder.logging(PathBuf::from(file), level.unwrap_or(GlusterLogLevel::Info));
+            }
+            (None, Some(_)) => {
+                return Err(invalid(
+                    &file_var,
+                    format!("isn't set but {} is", level_var),
+                ));
+            }
+            (None, None) => {}
+        }
+        Ok(builder)
+    }
+}
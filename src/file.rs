@@ -0,0 +1,810 @@
+use errno::errno;
+use glfs::*;
+use libc::{c_short, c_void, flock, off_t, pid_t, stat, EACCES, EAGAIN, F_GETLK, F_RDLCK, F_SETLK,
+           F_UNLCK, F_WRLCK, O_APPEND, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_SET};
+
+use buffer_pool::BufferPool;
+use cleanup::{self, DropError, DropTarget};
+use gluster::{get_error, Gluster, GlusterError, GlusterFd};
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem::zeroed;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Buffer size read_to_writer copies with
+const READ_TO_WRITER_CHUNK: usize = 1024 * 1024;
+
+fn last_os_error() -> io::Error {
+    io::Error::from_raw_os_error(errno().0)
+}
+
+/// The kind of POSIX record lock to take or look for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockKind {
+    /// Any number of shared locks can overlap, but not an exclusive one
+    Shared,
+    Exclusive,
+}
+
+impl LockKind {
+    fn l_type(self) -> c_short {
+        match self {
+            LockKind::Shared => F_RDLCK as c_short,
+            LockKind::Exclusive => F_WRLCK as c_short,
+        }
+    }
+}
+
+/// A lock held by someone else that's in the way, as reported by
+/// GlusterFile::query_lock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConflictingLock {
+    /// The pid of the holder as its client reported it.  Only
+    /// meaningful to processes on the same client machine, holders on
+    /// other clients may report anything.
+    pub pid: pid_t,
+    pub start: u64,
+    /// 0 means the lock runs to the end of the file however long it gets
+    pub len: u64,
+    pub kind: LockKind,
+}
+
+// Sleeps between attempts in lock_exclusive_timeout and
+// lock_shared_timeout, the same as LockOptions' defaults
+const LOCK_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const LOCK_MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+fn lock_request(l_type: c_short, start: u64, len: u64) -> flock {
+    let mut lock: flock = unsafe { zeroed() };
+    lock.l_type = l_type;
+    lock.l_whence = SEEK_SET as c_short;
+    lock.l_start = start as off_t;
+    lock.l_len = len as off_t;
+    lock
+}
+
+/// An open file on a Gluster volume.  The file handle is closed when
+/// this is dropped.  A GlusterFile borrows the connection it was opened
+/// from so it can never outlive it.
+///
+/// The Read, Write and Seek impls work on a position tracked by the
+/// GlusterFile itself and are implemented with pread and pwrite, so the
+/// fd's own offset is never used (files opened with O_APPEND are the
+/// exception, their writes always go to the end of the file).  That
+/// makes the type safe to move between threads or share behind a lock
+/// without the wrapper and gfapi disagreeing about where the file is
+/// positioned.
+#[derive(Debug)]
+pub struct GlusterFile<'a> {
+    gluster: &'a Gluster,
+    // Null once closed
+    file_handle: *mut Struct_glfs_fd,
+    path: PathBuf,
+    // Shared with try_clone'd files, like a file description's offset.
+    // Held across each read, write or seek so clones on other threads
+    // never use the same range twice.
+    position: Arc<Mutex<u64>>,
+    flags: i32,
+    append: bool,
+}
+
+// The fd is only ever used through gfapi which is thread safe, and the
+// &self methods are positionless or hold the position's lock
+unsafe impl<'a> Send for GlusterFile<'a> {}
+unsafe impl<'a> Sync for GlusterFile<'a> {}
+
+impl<'a> GlusterFile<'a> {
+    /// Take ownership of an fd for path returned by glfs_open or
+    /// glfs_creat with the given open flags.
+    pub(crate) fn new(
+        gluster: &'a Gluster,
+        file_handle: *mut Struct_glfs_fd,
+        flags: i32,
+        path: &Path,
+    ) -> GlusterFile<'a> {
+        GlusterFile {
+            gluster,
+            file_handle,
+            path: path.to_path_buf(),
+            position: Arc::new(Mutex::new(0)),
+            flags,
+            append: flags & O_APPEND == O_APPEND,
+        }
+    }
+
+    /// The connection this file was opened from
+    pub fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    /// The path the file was opened with
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The flags the file was opened or created with
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    /// The fd, or GlusterError::HandleClosed once close_in_place has
+    /// closed it.  Everything that hands the fd to gfapi goes through
+    /// this, including callers of the fd level Gluster methods.  The
+    /// file still owns the fd, so don't close it.
+    pub fn handle(&self) -> Result<*mut Struct_glfs_fd, GlusterError> {
+        if self.file_handle.is_null() {
+            return Err(GlusterError::HandleClosed {
+                opened_path: self.path.clone(),
+            });
+        }
+        Ok(self.file_handle)
+    }
+
+    // handle for the io::Result methods, with the HandleClosed inside the
+    // io::Error where get_ref can find it
+    pub(crate) fn io_handle(&self) -> io::Result<*mut Struct_glfs_fd> {
+        self.handle().map_err(io::Error::other)
+    }
+
+    /// True once close_in_place has closed the file
+    pub fn is_closed(&self) -> bool {
+        self.file_handle.is_null()
+    }
+
+    /// The glfs_fd_t, null once closed.  The file still owns it, don't
+    /// close it.
+    pub fn as_raw_fd(&self) -> *mut Struct_glfs_fd {
+        self.file_handle
+    }
+
+    /// Give up the glfs_fd_t without closing it.  The caller becomes
+    /// responsible for glfs_close, or for handing it back to from_raw_fd.
+    pub fn into_raw_fd(mut self) -> *mut Struct_glfs_fd {
+        let file_handle = self.file_handle;
+        self.file_handle = ::std::ptr::null_mut();
+        file_handle
+    }
+
+    /// Take ownership of an fd opened on gluster's connection with the
+    /// given flags, closing it when dropped.  path is only used in
+    /// errors.
+    ///
+    /// # Safety
+    ///
+    /// file_handle must be an open fd from gluster's glfs_t, and nothing
+    /// else may close it.
+    pub unsafe fn from_raw_fd(
+        gluster: &'a Gluster,
+        file_handle: *mut Struct_glfs_fd,
+        flags: i32,
+        path: &Path,
+    ) -> GlusterFile<'a> {
+        GlusterFile::new(gluster, file_handle, flags, path)
+    }
+
+    pub fn fstat(&self) -> Result<stat, GlusterError> {
+        self.gluster.fstat(self.handle()?)
+    }
+
+    /// Flush file data and metadata to stable storage
+    pub fn fsync(&self) -> Result<(), GlusterError> {
+        let _op = self.gluster.track("fsync", &self.path);
+        self.gluster.fsync(self.handle()?)
+    }
+
+    /// Flush file data (but not necessarily metadata) to stable storage
+    pub fn fdatasync(&self) -> Result<(), GlusterError> {
+        let _op = self.gluster.track("fdatasync", &self.path);
+        self.gluster.fdatasync(self.handle()?)
+    }
+
+    /// Cut the file down or extend it with zeros to len bytes, like
+    /// ftruncate(2).  The position isn't moved.
+    pub fn set_len(&self, len: u64) -> Result<(), GlusterError> {
+        let _op = self.gluster.track("ftruncate", &self.path);
+        self.gluster.ftruncate(self.handle()?, len as i64)
+    }
+
+    /// The names of the file's extended attributes, exactly as gfapi
+    /// returned them with no UTF-8 conversion
+    pub fn list_xattr_raw(&self) -> Result<Vec<Vec<u8>>, GlusterError> {
+        let _op = self.gluster.track("flist_xattr_raw", &self.path);
+        self.gluster.flist_xattr_raw(self.handle()?)
+    }
+
+    /// The raw value of the extended attribute with the byte string name
+    pub fn getxattr_raw_name(&self, name: &[u8]) -> Result<Vec<u8>, GlusterError> {
+        let _op = self.gluster.track("fgetxattr_raw_name", &self.path);
+        self.gluster.fgetxattr_raw_name(self.handle()?, name)
+    }
+
+    /// Set the extended attribute with the byte string name, for names
+    /// that aren't valid UTF-8.  flags are XATTR_CREATE or XATTR_REPLACE,
+    /// or 0 for either.
+    pub fn setxattr_raw_name(
+        &self,
+        name: &[u8],
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        let _op = self.gluster.track("fsetxattr_raw_name", &self.path);
+        self.gluster
+            .fsetxattr_raw_name(self.handle()?, name, value, flags)
+    }
+
+    /// Try to take a POSIX record lock on len bytes from start, 0 meaning
+    /// to the end of the file.  Returns false without waiting if a
+    /// conflicting lock is held, query_lock says by whom.
+    pub fn try_lock_range(
+        &self,
+        start: u64,
+        len: u64,
+        kind: LockKind,
+    ) -> Result<bool, GlusterError> {
+        let file_handle = self.handle()?;
+        let mut lock = lock_request(kind.l_type(), start, len);
+        if unsafe { glfs_posix_lock(file_handle, F_SETLK, &mut lock) } < 0 {
+            let error = errno().0;
+            if error == EAGAIN || error == EACCES {
+                return Ok(false);
+            }
+            return Err(GlusterError::IoError(io::Error::from_raw_os_error(error)));
+        }
+        Ok(true)
+    }
+
+    /// Release this handle's lock on len bytes from start
+    pub fn unlock_range(&self, start: u64, len: u64) -> Result<(), GlusterError> {
+        let file_handle = self.handle()?;
+        let mut lock = lock_request(F_UNLCK as c_short, start, len);
+        if unsafe { glfs_posix_lock(file_handle, F_SETLK, &mut lock) } < 0 {
+            return Err(GlusterError::IoError(last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Ask, with F_GETLK, whether a lock of kind on len bytes from start
+    /// could be taken right now.  None if it could, otherwise one of the
+    /// locks in the way.  Locks held through this same handle never
+    /// conflict.  Nothing is locked and the answer can be stale by the
+    /// time it's returned, so it's for reporting why try_lock_range
+    /// failed rather than deciding whether to call it.
+    pub fn query_lock(
+        &self,
+        start: u64,
+        len: u64,
+        kind: LockKind,
+    ) -> Result<Option<ConflictingLock>, GlusterError> {
+        let file_handle = self.handle()?;
+        let mut lock = lock_request(kind.l_type(), start, len);
+        if unsafe { glfs_posix_lock(file_handle, F_GETLK, &mut lock) } < 0 {
+            return Err(GlusterError::IoError(last_os_error()));
+        }
+        let kind = match lock.l_type as i32 {
+            F_UNLCK => return Ok(None),
+            F_RDLCK => LockKind::Shared,
+            _ => LockKind::Exclusive,
+        };
+        Ok(Some(ConflictingLock {
+            pid: lock.l_pid,
+            start: lock.l_start as u64,
+            len: lock.l_len as u64,
+            kind,
+        }))
+    }
+
+    /// Take an exclusive lock on len bytes from start, 0 meaning to the
+    /// end of the file, waiting up to timeout for it.  gfapi can't
+    /// reliably interrupt a blocked F_SETLKW, so this retries
+    /// try_lock_range with backoff from 10ms up to 1s instead.  Fails
+    /// with GlusterError::Timeout, carrying the lock still in the way,
+    /// when time runs out.  The lock is released when the guard is
+    /// dropped.
+    pub fn lock_exclusive_timeout(
+        &self,
+        start: u64,
+        len: u64,
+        timeout: Duration,
+    ) -> Result<RangeLockGuard<'_, 'a>, GlusterError> {
+        self.lock_timeout(start, len, LockKind::Exclusive, timeout)
+    }
+
+    /// Like lock_exclusive_timeout for a shared lock
+    pub fn lock_shared_timeout(
+        &self,
+        start: u64,
+        len: u64,
+        timeout: Duration,
+    ) -> Result<RangeLockGuard<'_, 'a>, GlusterError> {
+        self.lock_timeout(start, len, LockKind::Shared, timeout)
+    }
+
+    fn lock_timeout(
+        &self,
+        start: u64,
+        len: u64,
+        kind: LockKind,
+        timeout: Duration,
+    ) -> Result<RangeLockGuard<'_, 'a>, GlusterError> {
+        let began = Instant::now();
+        let mut backoff = LOCK_INITIAL_BACKOFF;
+        loop {
+            if self.try_lock_range(start, len, kind)? {
+                return Ok(RangeLockGuard {
+                    file: self,
+                    start,
+                    len,
+                    kind,
+                    released: false,
+                });
+            }
+            let elapsed = began.elapsed();
+            if elapsed >= timeout {
+                return Err(GlusterError::Timeout {
+                    waited: elapsed,
+                    conflict: self.query_lock(start, len, kind)?,
+                });
+            }
+            thread::sleep(backoff.min(timeout - elapsed));
+            backoff = (backoff * 2).min(LOCK_MAX_BACKOFF);
+        }
+    }
+
+    /// Read into buf from offset without touching the file's position
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let file_handle = self.io_handle()?;
+        let _op = self.gluster.track("pread", &self.path);
+        unsafe {
+            let read_size = glfs_pread(
+                file_handle,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                offset as i64,
+                0,
+            );
+            if read_size < 0 {
+                return Err(last_os_error());
+            }
+            Ok(read_size as usize)
+        }
+    }
+
+    /// Write buf at offset without touching the file's position.  Like
+    /// pwrite(2), on a file opened with O_APPEND the data may go to the
+    /// end of the file instead.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let file_handle = self.io_handle()?;
+        let _op = self.gluster.track("pwrite", &self.path);
+        unsafe {
+            let write_size = glfs_pwrite(
+                file_handle,
+                buf.as_ptr() as *const c_void,
+                buf.len(),
+                offset as i64,
+                0,
+            );
+            if write_size < 0 {
+                return Err(last_os_error());
+            }
+            Ok(write_size as usize)
+        }
+    }
+
+    /// Fill buf from offset, failing with ErrorKind::UnexpectedEof if the
+    /// file ends first
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                    offset += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write all of buf at offset
+    pub fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Append data as one record and return the offset it landed at.  The
+    /// file must have been opened with O_APPEND, which places each write
+    /// at the end of the file atomically, so records from concurrent
+    /// appenders never interleave.  The offset is read back from the fd
+    /// position after the write, with the position held from the write
+    /// until then, so threads appending through one GlusterFile or its
+    /// clones each get their own record's offset.  Across clients
+    /// O_APPEND is only as atomic as the bricks make it, replicated and
+    /// sharded volumes can order racing appends differently on different
+    /// bricks.  Use append_record_locked when the offsets have to be
+    /// exact.  A short write fails rather than being continued, since the
+    /// rest could land after someone else's record.
+    pub fn append_record(&self, data: &[u8]) -> Result<u64, GlusterError> {
+        let file_handle = self.handle()?;
+        if !self.append {
+            return Err(GlusterError::new(format!(
+                "{} wasn't opened with O_APPEND",
+                self.path.display()
+            )));
+        }
+        // Nothing else may move the fd's offset between the write and the
+        // lseek that reads it back
+        let mut position = self.lock_position();
+        let written =
+            unsafe { glfs_write(file_handle, data.as_ptr() as *const c_void, data.len(), 0) };
+        if written < 0 {
+            return Err(GlusterError::IoError(last_os_error()));
+        }
+        if written as usize != data.len() {
+            return Err(GlusterError::new(format!(
+                "appending {} bytes to {} only wrote {}",
+                data.len(),
+                self.path.display(),
+                written
+            )));
+        }
+        let end = unsafe { glfs_lseek(file_handle, 0, SEEK_CUR) };
+        if end < 0 {
+            return Err(GlusterError::IoError(last_os_error()));
+        }
+        *position = end as u64;
+        Ok(end as u64 - data.len() as u64)
+    }
+
+    /// Append data at the current end of the file while holding an
+    /// exclusive lock on the whole file, waiting up to timeout for it, and
+    /// return the offset it was written at.  Slower than append_record
+    /// but exact across clients, as long as every appender uses this.
+    /// Record locks are advisory, an append_record or plain write from
+    /// elsewhere isn't held back.  O_APPEND isn't needed.
+    pub fn append_record_locked(
+        &self,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<u64, GlusterError> {
+        let guard = self.lock_exclusive_timeout(0, 0, timeout)?;
+        let offset = self.fstat()?.st_size as u64;
+        self.write_all_at(data, offset)?;
+        guard.release()?;
+        Ok(offset)
+    }
+
+    /// A reader over len bytes of the file from start, or to the end of
+    /// the file when len is None.  Each reader has its own position and
+    /// reads with pread on this file's fd, so any number of them can be
+    /// used at once, from different threads too, without reopening the
+    /// file.  A glfs_dup'd fd per reader isn't needed since pread never
+    /// moves the fd's offset.  The readers borrow the file, so it stays
+    /// open until the last of them is dropped.
+    pub fn reader_at(&self, start: u64, len: Option<u64>) -> RangeReader<'_, 'a> {
+        RangeReader {
+            file: self,
+            start,
+            len,
+            position: 0,
+        }
+    }
+
+    /// Open a second handle on the same file with glfs_dup, closed when
+    /// it's dropped like any other GlusterFile.  Like dup(2) the two
+    /// share a position, reading, writing or seeking through either moves
+    /// both, so one can be handed to a reader thread and one to a writer.
+    /// Each read, write or seek holds the position for its whole call, so
+    /// concurrent calls through clones each get a range of their own.
+    pub fn try_clone(&self) -> Result<GlusterFile<'a>, GlusterError> {
+        let file_handle = unsafe { glfs_dup(self.handle()?) };
+        if file_handle.is_null() {
+            return Err(GlusterError::new(get_error()));
+        }
+        let mut file = GlusterFile::new(self.gluster, file_handle, self.flags, &self.path);
+        file.position = self.position.clone();
+        Ok(file)
+    }
+
+    // The shared position, locked for the whole of a read, write or seek
+    fn lock_position(&self) -> MutexGuard<'_, u64> {
+        match self.position.lock() {
+            Ok(position) => position,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Close the file and report any error from glfs_close, which can
+    /// mean buffered writes were lost.  Dropping the file also closes it
+    /// but the error can only be logged, see cleanup::DropError.
+    pub fn close(mut self) -> Result<(), GlusterError> {
+        self.close_in_place()
+    }
+
+    /// Close the file but keep the value, for when it's stored somewhere
+    /// close can't move it out of.  From then on every method that uses
+    /// the fd fails with GlusterError::HandleClosed naming the path the
+    /// file was opened with, the io::Read, Write and Seek ones with an
+    /// io::Error wrapping it, rather than handing gfapi a freed fd.
+    /// Closing again fails the same way.
+    pub fn close_in_place(&mut self) -> Result<(), GlusterError> {
+        let file_handle = self.handle()?;
+        self.file_handle = ::std::ptr::null_mut();
+        self.gluster.close(file_handle)
+    }
+}
+
+impl<'a> GlusterFd for GlusterFile<'a> {
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        GlusterFile::fstat(self)
+    }
+}
+
+impl<'a> Drop for GlusterFile<'a> {
+    fn drop(&mut self) {
+        if self.file_handle.is_null() {
+            return;
+        }
+        if let Err(error) = self.gluster.close(self.file_handle) {
+            cleanup::report(DropError {
+                target: DropTarget::File,
+                path: Some(self.path.clone()),
+                error,
+            });
+        }
+    }
+}
+
+/// A record lock taken with GlusterFile::lock_exclusive_timeout or
+/// lock_shared_timeout, released when dropped.  A failure to unlock on
+/// drop goes to the handler set with set_drop_error_handler, call release
+/// to get it back directly.
+#[derive(Debug)]
+pub struct RangeLockGuard<'f, 'a: 'f> {
+    file: &'f GlusterFile<'a>,
+    start: u64,
+    len: u64,
+    kind: LockKind,
+    released: bool,
+}
+
+impl<'f, 'a> RangeLockGuard<'f, 'a> {
+    /// The locked start offset and length
+    pub fn range(&self) -> (u64, u64) {
+        (self.start, self.len)
+    }
+
+    pub fn kind(&self) -> LockKind {
+        self.kind
+    }
+
+    /// Unlock now
+    pub fn release(mut self) -> Result<(), GlusterError> {
+        self.released = true;
+        self.file.unlock_range(self.start, self.len)
+    }
+}
+
+impl<'f, 'a> Drop for RangeLockGuard<'f, 'a> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Err(error) = self.file.unlock_range(self.start, self.len) {
+            cleanup::report(DropError {
+                target: DropTarget::Lock,
+                path: Some(self.file.path.clone()),
+                error,
+            });
+        }
+    }
+}
+
+impl<'a> Read for GlusterFile<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut position = self.lock_position();
+        let read_size = self.read_at(buf, *position)?;
+        *position += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<'a> Write for GlusterFile<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut position = self.lock_position();
+        if self.append {
+            let file_handle = self.io_handle()?;
+            let _op = self.gluster.track("write", &self.path);
+            unsafe {
+                let write_size =
+                    glfs_write(file_handle, buf.as_ptr() as *const c_void, buf.len(), 0);
+                if write_size < 0 {
+                    return Err(last_os_error());
+                }
+                // Appends land wherever the end of the file was
+                let file_offset = glfs_lseek(file_handle, 0, SEEK_CUR);
+                if file_offset < 0 {
+                    return Err(last_os_error());
+                }
+                *position = file_offset as u64;
+                return Ok(write_size as usize);
+            }
+        }
+        let write_size = self.write_at(buf, *position)?;
+        *position += write_size as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Writes go straight to gfapi, there's nothing buffered here
+        Ok(())
+    }
+}
+
+impl<'a> Seek for GlusterFile<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.io_handle()?;
+        let mut position = self.lock_position();
+        let new_position = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::Current(n) => *position as i64 + n,
+            SeekFrom::End(n) => {
+                let size = self.fstat()
+                    .map_err(io::Error::other)?
+                    .st_size;
+                size + n
+            }
+        };
+        if new_position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            ));
+        }
+        *position = new_position as u64;
+        Ok(new_position as u64)
+    }
+}
+
+/// A window onto part of a GlusterFile with its own position, see
+/// GlusterFile::reader_at.  Positions are relative to the start of the
+/// window and reads stop at its end.
+#[derive(Debug)]
+pub struct RangeReader<'f, 'a: 'f> {
+    file: &'f GlusterFile<'a>,
+    start: u64,
+    len: Option<u64>,
+    position: u64,
+}
+
+impl<'f, 'a> RangeReader<'f, 'a> {
+    /// Offset of the window in the file
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Length of the window, None if it runs to the end of the file
+    pub fn limit(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// Position within the window
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<'f, 'a> Read for RangeReader<'f, 'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = match self.len {
+            Some(len) => len.saturating_sub(self.position).min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+        if wanted == 0 {
+            return Ok(0);
+        }
+        let read_size = self.file
+            .read_at(&mut buf[..wanted], self.start + self.position)?;
+        self.position += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<'f, 'a> Seek for RangeReader<'f, 'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::Current(n) => self.position as i64 + n,
+            SeekFrom::End(n) => {
+                let end = match self.len {
+                    Some(len) => len as i64,
+                    None => {
+                        let size = self.file
+                            .fstat()
+                            .map_err(io::Error::other)?
+                            .st_size;
+                        size - self.start as i64
+                    }
+                };
+                end + n
+            }
+        };
+        if new_position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            ));
+        }
+        self.position = new_position as u64;
+        Ok(self.position)
+    }
+}
+
+impl Gluster {
+    /// Read the entire contents of a file into memory
+    pub fn read_to_vec(&self, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        let mut file = self.open_file(path, O_RDONLY)?;
+        // Sized up front so it isn't regrown as it fills
+        let len = file.fstat()?.st_size as usize;
+        let mut buffer = Vec::with_capacity(len);
+        file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Copy the entire contents of a file into writer through a pooled
+    /// buffer.  Returns the number of bytes copied.
+    pub fn read_to_writer<W: Write>(
+        &self,
+        path: &Path,
+        writer: &mut W,
+    ) -> Result<u64, GlusterError> {
+        let mut file = self.open_file(path, O_RDONLY)?;
+        let mut buffer = BufferPool::global().get(READ_TO_WRITER_CHUNK);
+        let mut copied = 0;
+        loop {
+            let read = match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(GlusterError::IoError(e)),
+            };
+            writer.write_all(&buffer[..read])?;
+            copied += read as u64;
+        }
+        file.close()?;
+        Ok(copied)
+    }
+
+    /// Create or truncate the file at path and write data into it
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+        let mut file = self.create_file(path, O_CREAT | O_WRONLY | O_TRUNC, 0o644)?;
+        file.write_all(data)?;
+        file.close()
+    }
+}
@@ -0,0 +1,202 @@
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How much of each entry goes into a DirFingerprint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FingerprintLevel {
+    /// Only entry names.  Creating, removing or renaming an entry changes
+    /// the fingerprint, rewriting a file in place doesn't.  Needs a plain
+    /// readdir and no per-entry stat.
+    Names,
+    /// Entry names plus each entry's inode number, size and mtime, so
+    /// rewriting a file in place or replacing it with a new inode under
+    /// the same name changes the fingerprint too.  Costs a stat per entry
+    /// when the server doesn't support readdirplus.
+    Inodes,
+}
+
+impl fmt::Display for FingerprintLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FingerprintLevel::Names => f.write_str("names"),
+            FingerprintLevel::Inodes => f.write_str("inodes"),
+        }
+    }
+}
+
+/// A cheap summary of a directory's state from Gluster::dir_fingerprint,
+/// for telling whether anything changed between two runs without keeping
+/// the whole listing around.
+///
+/// It combines the directory's own stat (mtime, ctime and size) with an
+/// order-independent hash over its entries.  The directory's times only
+/// change when entries are added, removed or renamed, and only with the
+/// granularity the bricks' filesystem keeps, so two changes in the same
+/// tick can leave them the same.  The entry hash catches those unless the
+/// directory ends up with exactly the same entries.  At
+/// FingerprintLevel::Names a file rewritten in place changes neither.
+///
+/// Display writes one line that FromStr reads back, so a fingerprint can
+/// be stored between runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DirFingerprint {
+    pub level: FingerprintLevel,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub ctime: i64,
+    pub ctime_nsec: i64,
+    /// st_size of the directory itself
+    pub size: u64,
+    /// Number of entries, not counting . and ..
+    pub entries: u64,
+    /// Sum of a 64 bit FNV-1a hash of each entry
+    pub hash: u64,
+}
+
+impl DirFingerprint {
+    /// True if anything differs from an earlier fingerprint of the same
+    /// directory.  Fingerprints taken at different levels can't be
+    /// compared and always count as changed.
+    pub fn changed_from(&self, old: &DirFingerprint) -> bool {
+        self != old
+    }
+}
+
+impl fmt::Display for DirFingerprint {
+    /// The level, mtime, ctime, size, entry count and hex hash separated
+    /// by tabs, with times written as seconds.nanoseconds
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}.{:09}\t{}.{:09}\t{}\t{}\t{:016x}",
+            self.level,
+            self.mtime,
+            self.mtime_nsec,
+            self.ctime,
+            self.ctime_nsec,
+            self.size,
+            self.entries,
+            self.hash
+        )
+    }
+}
+
+impl FromStr for DirFingerprint {
+    type Err = GlusterError;
+
+    fn from_str(s: &str) -> Result<DirFingerprint, GlusterError> {
+        let invalid = || GlusterError::new(format!("{:?} isn't a directory fingerprint", s));
+        let fields: Vec<&str> = s.trim_end().split('\t').collect();
+        if fields.len() != 6 {
+            return Err(invalid());
+        }
+        let level = match fields[0] {
+            "names" => FingerprintLevel::Names,
+            "inodes" => FingerprintLevel::Inodes,
+            _ => return Err(invalid()),
+        };
+        let time = |field: &str| -> Option<(i64, i64)> {
+            let mut parts = field.splitn(2, '.');
+            let secs = parts.next()?.parse().ok()?;
+            let nsecs = parts.next()?.parse().ok()?;
+            Some((secs, nsecs))
+        };
+        let (mtime, mtime_nsec) = time(fields[1]).ok_or_else(invalid)?;
+        let (ctime, ctime_nsec) = time(fields[2]).ok_or_else(invalid)?;
+        Ok(DirFingerprint {
+            level,
+            mtime,
+            mtime_nsec,
+            ctime,
+            ctime_nsec,
+            size: fields[3].parse().map_err(|_| invalid())?,
+            entries: fields[4].parse().map_err(|_| invalid())?,
+            hash: u64::from_str_radix(fields[5], 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+// FNV-1a, which unlike the std hashers is the same in every build so
+// fingerprints stay comparable across runs
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn entry_hash(name: &Path, metadata: Option<&Metadata>) -> u64 {
+    let mut hasher = Fnv::new();
+    hasher.write(name.as_os_str().as_bytes());
+    if let Some(metadata) = metadata {
+        // Names can't contain a nul so this keeps them apart from the stat
+        hasher.write(&[0]);
+        hasher.write(&metadata.as_stat().st_ino.to_le_bytes());
+        hasher.write(&metadata.len().to_le_bytes());
+        hasher.write(&metadata.mtime().to_le_bytes());
+        hasher.write(&metadata.mtime_nsec().to_le_bytes());
+    }
+    hasher.0
+}
+
+impl Gluster {
+    /// Fingerprint the directory at path from its names, see
+    /// DirFingerprint
+    pub fn dir_fingerprint(&self, path: &Path) -> Result<DirFingerprint, GlusterError> {
+        self.dir_fingerprint_with(path, FingerprintLevel::Names)
+    }
+
+    /// Fingerprint the directory at path, hashing as much of each entry as
+    /// level asks for
+    pub fn dir_fingerprint_with(
+        &self,
+        path: &Path,
+        level: FingerprintLevel,
+    ) -> Result<DirFingerprint, GlusterError> {
+        let mut entries = 0u64;
+        let mut hash = 0u64;
+        match level {
+            FingerprintLevel::Names => {
+                self.read_dir_streamed(path, 256, |stream| -> Result<(), GlusterError> {
+                    for entry in stream {
+                        let entry = entry?;
+                        entries += 1;
+                        hash = hash.wrapping_add(entry_hash(&entry.entry.path, None));
+                    }
+                    Ok(())
+                })?;
+            }
+            FingerprintLevel::Inodes => {
+                for (entry, metadata) in self.list_dir(path, 1)? {
+                    entries += 1;
+                    hash = hash.wrapping_add(entry_hash(&entry.path, Some(&metadata)));
+                }
+            }
+        }
+        let dir = self.metadata(path)?;
+        let stat = dir.as_stat();
+        Ok(DirFingerprint {
+            level,
+            mtime: stat.st_mtime,
+            mtime_nsec: stat.st_mtime_nsec,
+            ctime: stat.st_ctime,
+            ctime_nsec: stat.st_ctime_nsec,
+            size: dir.len(),
+            entries,
+            hash,
+        })
+    }
+}
@@ -1,17 +1,63 @@
 #![allow(non_camel_case_types)]
-use libc::{c_char, c_int, c_long, c_void, dev_t, dirent, gid_t, flock, mode_t, off_t, size_t,
-           stat, ssize_t, statvfs, timespec, uid_t};
+use libc::{c_char, c_int, c_long, c_uint, c_void, dev_t, dirent, dlsym, gid_t, flock, mode_t,
+           off_t, size_t, stat, ssize_t, statvfs, timespec, uid_t, RTLD_DEFAULT};
+
+use std::ffi::CString;
+use std::mem;
 
 pub enum Struct_glfs { }
 pub type glfs_t = Struct_glfs;
 pub enum Struct_glfs_fd { }
 pub type glfs_fd_t = Struct_glfs_fd;
+pub enum glfs_object { }
 pub type glfs_io_cbk = ::std::option::Option<
     extern "C" fn(fd: *mut glfs_fd_t,
                   ret: ssize_t,
                   data: *mut c_void)
                   -> (),
 >;
+/// glfs_copy_file_range, only exported by gfapi 6 and later so looked up
+/// with dlsym rather than linked.  The glfs_stat arguments may be null.
+pub type glfs_copy_file_range_t = unsafe extern "C" fn(fd_in: *mut glfs_fd_t,
+                                                       off_in: *mut off_t,
+                                                       fd_out: *mut glfs_fd_t,
+                                                       off_out: *mut off_t,
+                                                       len: size_t,
+                                                       flags: c_uint,
+                                                       statbuf: *mut c_void,
+                                                       prestat: *mut c_void,
+                                                       poststat: *mut c_void)
+                                                       -> ssize_t;
+/// glfs_h_anonymous_read and glfs_h_anonymous_write, which read or write
+/// through an anonymous fd the server opens and closes itself.  Not
+/// exported by every gfapi so looked up with dlsym rather than linked.
+pub type glfs_h_anonymous_io_t = unsafe extern "C" fn(fs: *mut glfs_t,
+                                                      object: *mut glfs_object,
+                                                      buf: *const c_void,
+                                                      count: size_t,
+                                                      offset: off_t)
+                                                      -> ssize_t;
+/// glfs_h_setattrs, which sets the fields of stat selected by valid, a
+/// mask of the GFAPI_SET_ATTR_* constants, in one call.  Looked up with
+/// dlsym rather than linked since not every gfapi exports it.
+pub type glfs_h_setattrs_t = unsafe extern "C" fn(fs: *mut glfs_t,
+                                                  object: *mut glfs_object,
+                                                  stat: *mut stat,
+                                                  valid: c_int)
+                                                  -> c_int;
+
+/// Look up one of the optional gfapi functions above with dlsym, None if
+/// the loaded libgfapi doesn't export it.  T must be the function pointer
+/// type matching name.
+pub(crate) unsafe fn optional_fn<T: Copy>(name: &str) -> Option<T> {
+    assert_eq!(mem::size_of::<T>(), mem::size_of::<*mut c_void>());
+    let symbol = CString::new(name).ok()?;
+    let address = dlsym(RTLD_DEFAULT, symbol.as_ptr());
+    if address.is_null() {
+        return None;
+    }
+    Some(mem::transmute_copy::<*mut c_void, T>(&address))
+}
 
 #[repr(C)]
 pub struct iovec {
@@ -19,6 +65,17 @@ pub struct iovec {
     pub iov_len: size_t,
 }
 
+// Fields of the stat passed to glfs_h_setattrs that are applied
+pub const GFAPI_SET_ATTR_MODE: c_int = 0x1;
+pub const GFAPI_SET_ATTR_UID: c_int = 0x2;
+pub const GFAPI_SET_ATTR_GID: c_int = 0x4;
+pub const GFAPI_SET_ATTR_SIZE: c_int = 0x8;
+pub const GFAPI_SET_ATTR_ATIME: c_int = 0x10;
+pub const GFAPI_SET_ATTR_MTIME: c_int = 0x20;
+
+/// Length of the gfid glfs_h_extract_handle writes out
+pub const GFAPI_HANDLE_LENGTH: usize = 16;
+
 #[link(name = "gfapi")]
 extern "C" {
     /// Create a new 'virtual mount' object.
@@ -388,4 +445,28 @@ extern "C" {
     ) -> *mut c_char;
     pub fn glfs_posix_lock(fd: *mut glfs_fd_t, cmd: c_int, flock: *mut flock) -> c_int;
     pub fn glfs_dup(fd: *mut glfs_fd_t) -> *mut glfs_fd_t;
+
+    /// Look up path, relative to parent or the root if parent is null, and
+    /// return a handle to it that has to be released with glfs_h_close.
+    pub fn glfs_h_lookupat(
+        fs: *mut glfs_t,
+        parent: *mut glfs_object,
+        path: *const c_char,
+        stat: *mut stat,
+        follow: c_int,
+    ) -> *mut glfs_object;
+    pub fn glfs_h_close(object: *mut glfs_object) -> c_int;
+    /// Copy the object's gfid into handle, which must hold at least
+    /// GFAPI_HANDLE_LENGTH bytes.  Returns the length copied.
+    pub fn glfs_h_extract_handle(object: *mut glfs_object, handle: *mut u8, len: c_int) -> c_int;
+    /// Make an object from a gfid written by glfs_h_extract_handle,
+    /// filling in stat if it isn't null
+    pub fn glfs_h_create_from_handle(
+        fs: *mut glfs_t,
+        handle: *mut u8,
+        len: c_int,
+        stat: *mut stat,
+    ) -> *mut glfs_object;
+    /// Open the object with the given flags, like glfs_open on its path
+    pub fn glfs_h_open(fs: *mut glfs_t, object: *mut glfs_object, flags: c_int) -> *mut glfs_fd_t;
 }
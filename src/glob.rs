@@ -0,0 +1,189 @@
+use gluster::GlusterError;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A shell style pattern matched against relative paths.
+///
+/// `*` matches any run of characters within one path component, `?` any
+/// single character but `/`, `[abc]`, `[a-z]` and `[!a-z]` one character
+/// from (or not from) a set, and `**` any number of whole components, so
+/// `src/**/*.rs` matches `src/lib.rs` and `src/a/b/lib.rs`.  A `\` makes
+/// the character after it literal.  A pattern without a `/` is matched
+/// against the last component of the path only, so `*.tmp` matches
+/// temporary files at any depth.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+    whole_path: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyRun,
+    AnyComponents,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, GlusterError> {
+        let invalid = |reason: &str| GlusterError::InvalidGlob {
+            pattern: pattern.to_string(),
+            reason: reason.to_string(),
+        };
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    let ends_component = matches!(chars.get(i + 2), None | Some(&'/'));
+                    if !starts_component || !ends_component {
+                        return Err(invalid("** must be a whole path component"));
+                    }
+                    tokens.push(Token::AnyComponents);
+                    // The / after ** is part of it, so **/ can match nothing
+                    i += 3;
+                    continue;
+                }
+                '*' => tokens.push(Token::AnyRun),
+                '?' => tokens.push(Token::AnyChar),
+                '\\' => match chars.get(i + 1) {
+                    Some(&c) => {
+                        tokens.push(Token::Literal(c));
+                        i += 1;
+                    }
+                    None => return Err(invalid("trailing \\")),
+                },
+                '[' => {
+                    let mut j = i + 1;
+                    let negated = match chars.get(j) {
+                        Some(&'!') | Some(&'^') => {
+                            j += 1;
+                            true
+                        }
+                        _ => false,
+                    };
+                    let mut ranges = Vec::new();
+                    // A ] straight after the [ is a member, not the end
+                    let mut first = true;
+                    loop {
+                        let c = match chars.get(j) {
+                            Some(&']') if !first => break,
+                            Some(&c) => c,
+                            None => return Err(invalid("unclosed [")),
+                        };
+                        first = false;
+                        if chars.get(j + 1) == Some(&'-')
+                            && chars.get(j + 2).is_some_and(|&c| c != ']')
+                        {
+                            let end = chars[j + 2];
+                            if end < c {
+                                return Err(invalid("range out of order in [ ]"));
+                            }
+                            ranges.push((c, end));
+                            j += 3;
+                        } else {
+                            ranges.push((c, c));
+                            j += 1;
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    i = j;
+                }
+                c => tokens.push(Token::Literal(c)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            tokens,
+            whole_path: chars.contains(&'/'),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether path, relative to whatever the pattern is for, matches
+    pub fn matches(&self, path: &Path) -> bool {
+        let subject = if self.whole_path {
+            path.to_string_lossy()
+        } else {
+            match path.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => return false,
+            }
+        };
+        let subject: Vec<char> = subject.chars().collect();
+        match_tokens(&self.tokens, &subject)
+    }
+}
+
+fn match_tokens(tokens: &[Token], subject: &[char]) -> bool {
+    let token = match tokens.first() {
+        Some(token) => token,
+        None => return subject.is_empty(),
+    };
+    let rest = &tokens[1..];
+    match *token {
+        Token::Literal(c) => subject.first() == Some(&c) && match_tokens(rest, &subject[1..]),
+        Token::AnyChar => match subject.first() {
+            Some(&c) if c != '/' => match_tokens(rest, &subject[1..]),
+            _ => false,
+        },
+        Token::Class {
+            negated,
+            ref ranges,
+        } => match subject.first() {
+            Some(&c) if c != '/' => {
+                let member = ranges.iter().any(|&(low, high)| low <= c && c <= high);
+                member != negated && match_tokens(rest, &subject[1..])
+            }
+            _ => false,
+        },
+        Token::AnyRun => {
+            for skip in 0..=subject.len() {
+                if match_tokens(rest, &subject[skip..]) {
+                    return true;
+                }
+                if subject.get(skip) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        // A trailing ** takes the rest of the path
+        Token::AnyComponents if rest.is_empty() => true,
+        Token::AnyComponents => {
+            // Nothing, or everything up to and including a /
+            match_tokens(rest, subject)
+                || (0..subject.len())
+                    .filter(|&i| subject[i] == '/')
+                    .any(|i| match_tokens(rest, &subject[i + 1..]))
+        }
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl FromStr for Glob {
+    type Err = GlusterError;
+
+    fn from_str(s: &str) -> Result<Glob, GlusterError> {
+        Glob::new(s)
+    }
+}
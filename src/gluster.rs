@@ -1,77 +1,411 @@
-use errno::{errno, Errno};
+use acl::AclError;
+use attrs::AttrField;
+use builder::{GlusterBuilder, VolfileServer};
+use cleanup::{self, DropError, DropTarget};
+use errno::{errno, set_errno, Errno};
+use file::{ConflictingLock, GlusterFile};
 use glfs::*;
+use vectored;
+use metadata::{FileType, Metadata};
+use mode::{self, ModePolicy};
+use path::PathError;
+use tuning::XlatorOption;
+use url;
+use watchdog::Watchdog;
+use xattr::XattrFailure;
 use libc::{c_uchar, c_void, dev_t, dirent, flock, ino_t, mode_t, stat, statvfs, timespec, DT_DIR,
-           ENOENT, LOCK_EX, LOCK_SH, LOCK_UN};
+           EEXIST, ENAMETOOLONG, ENOENT, EROFS, LOCK_EX, LOCK_SH, LOCK_UN, O_ACCMODE, O_APPEND, O_CREAT,
+           O_RDONLY, O_TRUNC, S_IFDIR, S_IFMT};
 use uuid::{ParseError, Uuid};
 
 use std::error::Error as err;
 use std::mem::zeroed;
-use std::ffi::{CStr, CString, IntoStringError, NulError};
+use std::ffi::{CStr, CString, IntoStringError, NulError, OsStr, OsString};
 use std::fmt;
-use std::io::Error;
-use std::os::unix::ffi::OsStrExt;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut};
+use std::marker::PhantomData;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 use std::ptr;
+use std::str::FromStr;
 use std::string::FromUtf8Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// Custom error handling for the library
 #[derive(Debug)]
 pub enum GlusterError {
+    AclError(AclError),
     Error(String),
     FromUtf8Error(FromUtf8Error),
     IntoStringError(IntoStringError),
     IoError(Error),
+    /// A mode string given to chmod_str couldn't be parsed
+    ModeError(mode::ParseError),
     NulError(NulError),
     ParseError(ParseError),
+    PathError(PathError),
+    /// A gluster:// URL given to Gluster::from_url couldn't be parsed
+    UrlError(url::UrlError),
+    /// Data read back after a verified write didn't match what was sent
+    VerificationFailed { path: PathBuf, offset: u64 },
+    /// A modifying call was made on a connection built with read_only
+    ReadOnly,
+    /// A path given to a ScopedGluster reaches outside its root
+    EscapesRoot { path: PathBuf },
+    /// A write was refused because there wasn't enough free space for it
+    InsufficientSpace { needed: u64, available: u64 },
+    /// The .snaps directory isn't there, features.uss is off
+    SnapshotsNotEnabled { path: PathBuf },
+    /// Following symlinks from path took more than the allowed hops or
+    /// came back around to a link already seen (ELOOP)
+    SymlinkLoop { path: PathBuf, hops: usize },
+    /// The symlink chain starting at path ends at target, which doesn't
+    /// exist
+    DanglingSymlink { path: PathBuf, target: PathBuf },
+    /// rename_noreplace found something already at path
+    AlreadyExists { path: PathBuf },
+    /// A copy was refused because from and to are the same file, either
+    /// the same path or hard links to one inode
+    SameFile { from: PathBuf, to: PathBuf },
+    /// path, or the component of it given, is longer than the limit set
+    /// with GlusterBuilder::path_limits.  Checked before calling gfapi.
+    PathTooLong {
+        path: PathBuf,
+        component: Option<OsString>,
+        len: usize,
+        limit: usize,
+    },
+    /// set_attrs changed the fields in applied before failing to set
+    /// failed with error
+    AttrsPartiallyApplied {
+        path: PathBuf,
+        applied: Vec<AttrField>,
+        failed: AttrField,
+        error: String,
+    },
+    /// Waiting for a record lock gave up after waited.  conflict is the
+    /// lock still in the way, if a last check found one.
+    Timeout {
+        waited: Duration,
+        conflict: Option<ConflictingLock>,
+    },
+    /// A GlusterFile was used after close_in_place closed it
+    HandleClosed { opened_path: PathBuf },
+    /// A best effort copy_xattrs copied copied attributes but couldn't
+    /// copy the ones in failed
+    XattrsPartiallyCopied {
+        copied: usize,
+        failed: Vec<XattrFailure>,
+    },
+    /// Entry number position, counting from 0, of a server list given to
+    /// GlusterBuilder::servers_from_str couldn't be parsed
+    InvalidServer {
+        token: String,
+        position: usize,
+        reason: &'static str,
+    },
+    /// The environment variable name read by GlusterBuilder::from_env is
+    /// missing or malformed
+    InvalidEnv { name: String, reason: String },
+    /// field of a GlusterConfig is missing, malformed or conflicts with
+    /// another
+    InvalidConfig { field: String, reason: String },
+    /// A CopyOptions::on_conflict handler stopped a copy at the existing
+    /// destination path
+    CopyAborted { path: PathBuf },
+    /// A glob pattern given to Glob::new couldn't be parsed
+    InvalidGlob {
+        pattern: String,
+        reason: String,
+    },
 }
 
 impl fmt::Display for GlusterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(self.description())
+        match *self {
+            GlusterError::VerificationFailed { ref path, offset } => write!(
+                f,
+                "verification of {} failed at offset {}",
+                path.display(),
+                offset
+            ),
+            GlusterError::InsufficientSpace { needed, available } => write!(
+                f,
+                "insufficient space: {} bytes needed but only {} available",
+                needed, available
+            ),
+            GlusterError::SnapshotsNotEnabled { ref path } => write!(
+                f,
+                "{} doesn't exist, snapshots aren't enabled (features.uss)",
+                path.display()
+            ),
+            GlusterError::SymlinkLoop { ref path, hops } => write!(
+                f,
+                "too many levels of symbolic links resolving {} ({} hops)",
+                path.display(),
+                hops
+            ),
+            GlusterError::DanglingSymlink {
+                ref path,
+                ref target,
+            } => write!(
+                f,
+                "{} resolves to {}, which doesn't exist",
+                path.display(),
+                target.display()
+            ),
+            GlusterError::AlreadyExists { ref path } => {
+                write!(f, "{} already exists", path.display())
+            }
+            GlusterError::SameFile { ref from, ref to } => write!(
+                f,
+                "{} and {} are the same file",
+                from.display(),
+                to.display()
+            ),
+            GlusterError::PathTooLong {
+                ref path,
+                component: Some(ref component),
+                len,
+                limit,
+            } => write!(
+                f,
+                "component {:?} of {} is {} bytes, longer than the limit of {}",
+                component,
+                path.display(),
+                len,
+                limit
+            ),
+            GlusterError::PathTooLong {
+                ref path,
+                component: None,
+                len,
+                limit,
+            } => write!(
+                f,
+                "{} is {} bytes, longer than the limit of {}",
+                path.display(),
+                len,
+                limit
+            ),
+            GlusterError::AttrsPartiallyApplied {
+                ref path,
+                ref applied,
+                failed,
+                ref error,
+            } => {
+                let applied: Vec<String> = applied.iter().map(|field| field.to_string()).collect();
+                write!(
+                    f,
+                    "setting the {} of {} failed: {}.  Already applied: {}",
+                    failed,
+                    path.display(),
+                    error,
+                    applied.join(", ")
+                )
+            }
+            GlusterError::Timeout {
+                waited,
+                conflict: Some(ref conflict),
+            } => write!(
+                f,
+                "gave up waiting for a lock after {:?}, pid {} holds a {:?} lock on {} bytes from {}",
+                waited, conflict.pid, conflict.kind, conflict.len, conflict.start
+            ),
+            GlusterError::Timeout {
+                waited,
+                conflict: None,
+            } => write!(f, "gave up waiting for a lock after {:?}", waited),
+            GlusterError::HandleClosed { ref opened_path } => write!(
+                f,
+                "file handle for {} was used after being closed",
+                opened_path.display()
+            ),
+            GlusterError::XattrsPartiallyCopied {
+                copied,
+                ref failed,
+            } => {
+                let failed: Vec<String> = failed
+                    .iter()
+                    .map(|failure| {
+                        format!(
+                            "{} ({})",
+                            String::from_utf8_lossy(&failure.name),
+                            failure.error
+                        )
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "copied {} extended attributes but not {}",
+                    copied,
+                    failed.join(", ")
+                )
+            }
+            GlusterError::InvalidServer {
+                ref token,
+                position,
+                reason,
+            } => write!(
+                f,
+                "invalid volfile server {:?} at entry {}: {}",
+                token, position, reason
+            ),
+            GlusterError::InvalidEnv {
+                ref name,
+                ref reason,
+            } => write!(f, "environment variable {}: {}", name, reason),
+            GlusterError::InvalidConfig {
+                ref field,
+                ref reason,
+            } => write!(f, "config field {}: {}", field, reason),
+            GlusterError::CopyAborted { ref path } => {
+                write!(f, "copy aborted at existing {}", path.display())
+            }
+            GlusterError::InvalidGlob {
+                ref pattern,
+                ref reason,
+            } => write!(f, "invalid glob {:?}: {}", pattern, reason),
+            GlusterError::AclError(ref e) => write!(f, "{}", e),
+            GlusterError::ModeError(ref e) => write!(f, "{}", e),
+            GlusterError::UrlError(ref e) => write!(f, "{}", e),
+            GlusterError::EscapesRoot { ref path } => {
+                write!(f, "{} is outside the permitted directory", path.display())
+            }
+            _ => f.write_str(self.description()),
+        }
     }
 }
 
 impl err for GlusterError {
     fn description(&self) -> &str {
         match *self {
+            GlusterError::AclError(ref e) => e.description(),
             GlusterError::Error(ref e) => &e,
             GlusterError::FromUtf8Error(ref e) => e.description(),
             GlusterError::IntoStringError(ref e) => e.description(),
             GlusterError::IoError(ref e) => e.description(),
+            GlusterError::ModeError(ref e) => e.description(),
+            GlusterError::UrlError(ref e) => e.description(),
             GlusterError::NulError(ref e) => e.description(),
             GlusterError::ParseError(ref e) => e.description(),
+            GlusterError::PathError(ref e) => e.description(),
+            GlusterError::VerificationFailed { .. } => "verification failed",
+            GlusterError::ReadOnly => "connection is read only",
+            GlusterError::EscapesRoot { .. } => "path is outside the permitted directory",
+            GlusterError::InsufficientSpace { .. } => "insufficient space",
+            GlusterError::SnapshotsNotEnabled { .. } => "snapshots are not enabled",
+            GlusterError::SymlinkLoop { .. } => "too many levels of symbolic links",
+            GlusterError::DanglingSymlink { .. } => "symlink target doesn't exist",
+            GlusterError::AlreadyExists { .. } => "destination already exists",
+            GlusterError::SameFile { .. } => "source and destination are the same file",
+            GlusterError::PathTooLong { .. } => "path too long",
+            GlusterError::AttrsPartiallyApplied { .. } => "attributes were partially applied",
+            GlusterError::Timeout { .. } => "timed out waiting for a lock",
+            GlusterError::HandleClosed { .. } => "file handle used after close",
+            GlusterError::XattrsPartiallyCopied { .. } => "extended attributes were partially copied",
+            GlusterError::InvalidServer { .. } => "invalid volfile server",
+            GlusterError::InvalidEnv { .. } => "invalid environment variable",
+            GlusterError::InvalidConfig { .. } => "invalid config",
+            GlusterError::CopyAborted { .. } => "copy aborted",
+            GlusterError::InvalidGlob { .. } => "invalid glob",
         }
     }
     fn cause(&self) -> Option<&err> {
         match *self {
+            GlusterError::AclError(_) => None,
             GlusterError::Error(_) => None,
             GlusterError::FromUtf8Error(ref e) => e.cause(),
             GlusterError::IntoStringError(ref e) => e.cause(),
             GlusterError::IoError(ref e) => e.cause(),
+            GlusterError::ModeError(_) => None,
+            GlusterError::UrlError(_) => None,
             GlusterError::NulError(ref e) => e.cause(),
             GlusterError::ParseError(ref e) => e.cause(),
+            GlusterError::PathError(_) => None,
+            GlusterError::VerificationFailed { .. } => None,
+            GlusterError::ReadOnly => None,
+            GlusterError::EscapesRoot { .. } => None,
+            GlusterError::InsufficientSpace { .. } => None,
+            GlusterError::SnapshotsNotEnabled { .. } => None,
+            GlusterError::SymlinkLoop { .. } => None,
+            GlusterError::DanglingSymlink { .. } => None,
+            GlusterError::AlreadyExists { .. } => None,
+            GlusterError::SameFile { .. } => None,
+            GlusterError::PathTooLong { .. } => None,
+            GlusterError::AttrsPartiallyApplied { .. } => None,
+            GlusterError::Timeout { .. } => None,
+            GlusterError::HandleClosed { .. } => None,
+            GlusterError::XattrsPartiallyCopied { .. } => None,
+            GlusterError::InvalidServer { .. } => None,
+            GlusterError::InvalidEnv { .. } => None,
+            GlusterError::InvalidConfig { .. } => None,
+            GlusterError::CopyAborted { .. } => None,
+            GlusterError::InvalidGlob { .. } => None,
         }
     }
 }
 impl GlusterError {
     /// Create a new GlusterError with a String message
-    fn new(err: String) -> GlusterError {
+    pub(crate) fn new(err: String) -> GlusterError {
         GlusterError::Error(err)
     }
 
     /// Convert a GlusterError into a String representation.
     pub fn to_string(&self) -> String {
         match *self {
+            GlusterError::AclError(ref err) => err.to_string(),
             GlusterError::Error(ref err) => err.to_string(),
             GlusterError::FromUtf8Error(ref err) => err.utf8_error().to_string(),
-            GlusterError::IntoStringError(ref err) => err.description().to_string(),
-            GlusterError::IoError(ref err) => err.description().to_string(),
-            GlusterError::NulError(ref err) => err.description().to_string(),
-            GlusterError::ParseError(ref err) => err.description().to_string(),
+            GlusterError::IntoStringError(ref err) => err.to_string(),
+            GlusterError::IoError(ref err) => err.to_string(),
+            GlusterError::ModeError(ref err) => err.to_string(),
+            GlusterError::UrlError(ref err) => err.to_string(),
+            GlusterError::NulError(ref err) => err.to_string(),
+            GlusterError::ParseError(ref err) => err.to_string(),
+            GlusterError::PathError(ref err) => err.to_string(),
+            GlusterError::VerificationFailed { .. } => format!("{}", self),
+            GlusterError::ReadOnly => self.description().to_string(),
+            GlusterError::EscapesRoot { .. } => format!("{}", self),
+            GlusterError::InsufficientSpace { .. } => format!("{}", self),
+            GlusterError::SnapshotsNotEnabled { .. } => format!("{}", self),
+            GlusterError::SymlinkLoop { .. } => format!("{}", self),
+            GlusterError::DanglingSymlink { .. } => format!("{}", self),
+            GlusterError::AlreadyExists { .. } => format!("{}", self),
+            GlusterError::SameFile { .. } => format!("{}", self),
+            GlusterError::PathTooLong { .. } => format!("{}", self),
+            GlusterError::AttrsPartiallyApplied { .. } => format!("{}", self),
+            GlusterError::Timeout { .. } => format!("{}", self),
+            GlusterError::HandleClosed { .. } => format!("{}", self),
+            GlusterError::XattrsPartiallyCopied { .. } => format!("{}", self),
+            GlusterError::InvalidServer { .. } => format!("{}", self),
+            GlusterError::InvalidEnv { .. } => format!("{}", self),
+            GlusterError::InvalidConfig { .. } => format!("{}", self),
+            GlusterError::CopyAborted { .. } => format!("{}", self),
+            GlusterError::InvalidGlob { .. } => format!("{}", self),
         }
     }
 }
 
+impl From<AclError> for GlusterError {
+    fn from(err: AclError) -> GlusterError {
+        GlusterError::AclError(err)
+    }
+}
+
+impl From<mode::ParseError> for GlusterError {
+    fn from(err: mode::ParseError) -> GlusterError {
+        GlusterError::ModeError(err)
+    }
+}
+
+impl From<url::UrlError> for GlusterError {
+    fn from(err: url::UrlError) -> GlusterError {
+        GlusterError::UrlError(err)
+    }
+}
+
 impl From<NulError> for GlusterError {
     fn from(err: NulError) -> GlusterError {
         GlusterError::NulError(err)
@@ -96,13 +430,36 @@ impl From<Error> for GlusterError {
     }
 }
 
+impl From<PathError> for GlusterError {
+    fn from(err: PathError) -> GlusterError {
+        GlusterError::PathError(err)
+    }
+}
+
 impl From<ParseError> for GlusterError {
     fn from(err: ParseError) -> GlusterError {
         GlusterError::ParseError(err)
     }
 }
 
-fn get_error() -> String {
+// listxattr's buffer is each name followed by a NUL
+fn split_xattr_names(buf: &[u8]) -> Vec<Vec<u8>> {
+    buf.split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_vec())
+        .collect()
+}
+
+fn join_xattr_names(names: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for name in names {
+        buf.extend_from_slice(name);
+        buf.push(0);
+    }
+    buf
+}
+
+pub(crate) fn get_error() -> String {
     let error = errno();
     format!("{}", error)
 }
@@ -130,7 +487,7 @@ impl Into<i32> for PosixLockCmd {
 }
 
 #[repr(i32)]
-#[derive(PartialEq, Debug, Hash)]
+#[derive(Clone, Copy, PartialEq, Debug, Hash)]
 ///  None to Trace correspond to the equivalent gluster log levels
 pub enum GlusterLogLevel {
     None = 0,
@@ -145,6 +502,46 @@ pub enum GlusterLogLevel {
     Trace,
 }
 
+impl fmt::Display for GlusterLogLevel {
+    /// The level's name in lower case, which FromStr reads back
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            GlusterLogLevel::None => "none",
+            GlusterLogLevel::Emerg => "emerg",
+            GlusterLogLevel::Alert => "alert",
+            GlusterLogLevel::Critical => "critical",
+            GlusterLogLevel::Error => "error",
+            GlusterLogLevel::Warning => "warning",
+            GlusterLogLevel::Notice => "notice",
+            GlusterLogLevel::Info => "info",
+            GlusterLogLevel::Debug => "debug",
+            GlusterLogLevel::Trace => "trace",
+        })
+    }
+}
+
+impl FromStr for GlusterLogLevel {
+    type Err = GlusterError;
+
+    /// The level's name in any case, such as "warning" or "TRACE", with
+    /// the short forms gluster's own options take (crit, warn)
+    fn from_str(s: &str) -> Result<GlusterLogLevel, GlusterError> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(GlusterLogLevel::None),
+            "emerg" => Ok(GlusterLogLevel::Emerg),
+            "alert" => Ok(GlusterLogLevel::Alert),
+            "critical" | "crit" => Ok(GlusterLogLevel::Critical),
+            "error" => Ok(GlusterLogLevel::Error),
+            "warning" | "warn" => Ok(GlusterLogLevel::Warning),
+            "notice" => Ok(GlusterLogLevel::Notice),
+            "info" => Ok(GlusterLogLevel::Info),
+            "debug" => Ok(GlusterLogLevel::Debug),
+            "trace" => Ok(GlusterLogLevel::Trace),
+            _ => Err(GlusterError::new(format!("{:?} isn't a log level", s))),
+        }
+    }
+}
+
 // pub type glfs_io_cbk = ::std::option::Option<extern "C" fn(fd: *mut glfs_fd_t,
 // ret: ssize_t,
 // data: *mut c_void)
@@ -156,30 +553,59 @@ pub enum GlusterLogLevel {
 
 #[derive(Debug)]
 pub struct Gluster {
-    cluster_handle: *mut Struct_glfs,
+    pub(crate) cluster_handle: *mut Struct_glfs,
+    pub(crate) read_only: bool,
+    // What the connection was made with, for try_clone
+    pub(crate) config: GlusterBuilder,
+    // Log file and level from set_logging
+    pub(crate) logging: Mutex<Option<(PathBuf, i32)>>,
+    // Everything set with set_xlator_option, in order
+    pub(crate) xlator_options: Mutex<Vec<XlatorOption>>,
+    // Transport and server of each volfile server added, in order
+    pub(crate) volfile_servers: Mutex<Vec<(String, VolfileServer)>>,
+    // Only with GlusterBuilder::watchdog
+    pub(crate) watchdog: Option<Watchdog>,
 }
 
 // As far as I can tell the cluster handle to gluster is thread safe
 unsafe impl Send for Gluster {}
 unsafe impl Sync for Gluster {}
 
+// glfs_fini frees the glfs_t even when it fails, so it's called exactly
+// once whatever it returns
+fn fini(cluster_handle: *mut Struct_glfs) -> Result<(), GlusterError> {
+    if cluster_handle.is_null() {
+        // No cleanup needed
+        return Ok(());
+    }
+    if unsafe { glfs_fini(cluster_handle) } < 0 {
+        return Err(GlusterError::new(get_error()));
+    }
+    Ok(())
+}
+
 impl Drop for Gluster {
     fn drop(&mut self) {
-        if self.cluster_handle.is_null() {
-            // No cleanup needed
-            return;
-        }
-        unsafe {
-            glfs_fini(self.cluster_handle);
+        if let Err(error) = fini(self.cluster_handle) {
+            cleanup::report(DropError {
+                target: DropTarget::Connection,
+                path: None,
+                error,
+            });
         }
     }
 }
 
 /// This uses readdirplus which is very efficient in Gluster.  In addition
-/// to returning directory entries this also stats each file.
+/// to returning directory entries this also stats each file.  The handle
+/// is closed when this is dropped, however far it was iterated.
 #[derive(Debug)]
-pub struct GlusterDirectoryPlus {
-    pub dir_handle: *mut Struct_glfs_fd,
+pub struct GlusterDirectoryPlus<'a> {
+    // Null once closed.  Private so a directory handle can't be passed
+    // to the fd level methods meant for files.
+    pub(crate) dir_handle: *mut Struct_glfs_fd,
+    // The handle is only valid while the connection is
+    gluster: PhantomData<&'a Gluster>,
 }
 
 pub struct DirEntryPlus {
@@ -189,9 +615,12 @@ pub struct DirEntryPlus {
     pub stat: stat,
 }
 
-impl Iterator for GlusterDirectoryPlus {
+impl<'a> Iterator for GlusterDirectoryPlus<'a> {
     type Item = DirEntryPlus;
     fn next(&mut self) -> Option<DirEntryPlus> {
+        if self.dir_handle.is_null() {
+            return None;
+        }
         let mut dirent: dirent = unsafe { zeroed() };
         let mut next_entry: *mut dirent = ptr::null_mut();
         unsafe {
@@ -199,7 +628,6 @@ impl Iterator for GlusterDirectoryPlus {
             let ret_code =
                 glfs_readdirplus_r(self.dir_handle, &mut stat_buf, &mut dirent, &mut next_entry);
             if ret_code < 0 {
-                glfs_closedir(self.dir_handle);
                 return None;
             }
             if dirent.d_ino == 0 {
@@ -212,7 +640,7 @@ impl Iterator for GlusterDirectoryPlus {
             }
             let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
             return Some(DirEntryPlus {
-                path: PathBuf::from(file_name.to_string_lossy().into_owned()),
+                path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
                 inode: dirent.d_ino,
                 file_type: dirent.d_type,
                 stat: stat_buf,
@@ -221,27 +649,55 @@ impl Iterator for GlusterDirectoryPlus {
     }
 }
 
+/// The entries of a directory opened with Gluster::opendir.  The handle
+/// is closed when this is dropped, however far it was iterated.  It's a
+/// separate type from GlusterFile so it can't be read from or written to:
+///
+/// ```compile_fail,E0616
+/// # use gfapi_sys::gluster::Gluster;
+/// # use std::path::Path;
+/// let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+/// let dir = cluster.opendir(Path::new("dir")).unwrap();
+/// cluster.read(dir.dir_handle, &mut Vec::new(), 1).unwrap();
+/// ```
 #[derive(Debug)]
-pub struct GlusterDirectory {
-    pub dir_handle: *mut Struct_glfs_fd,
+pub struct GlusterDirectory<'a> {
+    // Null once closed.  Private so a directory handle can't be passed
+    // to the fd level methods meant for files.
+    pub(crate) dir_handle: *mut Struct_glfs_fd,
+    // The handle is only valid while the connection is
+    gluster: PhantomData<&'a Gluster>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct DirEntry {
     pub path: PathBuf,
     pub inode: ino_t,
     pub file_type: c_uchar,
 }
 
-impl Iterator for GlusterDirectory {
+impl<'a> GlusterDirectory<'a> {
+    /// Switch to readdirplus for the rest of the entries, which also
+    /// stats each one.
+    pub fn plus(mut self) -> GlusterDirectoryPlus<'a> {
+        GlusterDirectoryPlus {
+            dir_handle: std::mem::replace(&mut self.dir_handle, ptr::null_mut()),
+            gluster: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for GlusterDirectory<'a> {
     type Item = DirEntry;
     fn next(&mut self) -> Option<DirEntry> {
+        if self.dir_handle.is_null() {
+            return None;
+        }
         let mut dirent: dirent = unsafe { zeroed() };
         let mut next_entry: *mut dirent = ptr::null_mut();
         unsafe {
             let ret_code = glfs_readdir_r(self.dir_handle, &mut dirent, &mut next_entry);
             if ret_code < 0 {
-                glfs_closedir(self.dir_handle);
                 return None;
             }
             if dirent.d_ino == 0 {
@@ -254,7 +710,7 @@ impl Iterator for GlusterDirectory {
             }
             let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
             return Some(DirEntry {
-                path: PathBuf::from(file_name.to_string_lossy().into_owned()),
+                path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
                 inode: dirent.d_ino,
                 file_type: dirent.d_type,
             });
@@ -262,52 +718,182 @@ impl Iterator for GlusterDirectory {
     }
 }
 
+// Close a directory handle from Drop, reporting any failure
+fn close_dir(dir_handle: &mut *mut Struct_glfs_fd) {
+    if dir_handle.is_null() {
+        return;
+    }
+    let ret_code = unsafe { glfs_closedir(*dir_handle) };
+    *dir_handle = ptr::null_mut();
+    if ret_code < 0 {
+        cleanup::report(DropError {
+            target: DropTarget::Directory,
+            path: None,
+            error: GlusterError::new(get_error()),
+        });
+    }
+}
+
+impl<'a> Drop for GlusterDirectoryPlus<'a> {
+    fn drop(&mut self) {
+        close_dir(&mut self.dir_handle);
+    }
+}
+
+impl<'a> Drop for GlusterDirectory<'a> {
+    fn drop(&mut self) {
+        close_dir(&mut self.dir_handle);
+    }
+}
+
+/// An open file or directory, both of which can be fstat'd
+pub trait GlusterFd {
+    fn fstat(&self) -> Result<stat, GlusterError>;
+}
+
+// Directories are only closed by drop or by plus, which consumes them, so
+// the handle is never null here
+fn fstat_dir(dir_handle: *mut Struct_glfs_fd) -> Result<stat, GlusterError> {
+    unsafe {
+        let mut stat_buf: stat = zeroed();
+        if glfs_fstat(dir_handle, &mut stat_buf) < 0 {
+            return Err(GlusterError::new(get_error()));
+        }
+        Ok(stat_buf)
+    }
+}
+
+impl<'a> GlusterFd for GlusterDirectory<'a> {
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        fstat_dir(self.dir_handle)
+    }
+}
+
+impl<'a> GlusterFd for GlusterDirectoryPlus<'a> {
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        fstat_dir(self.dir_handle)
+    }
+}
+
 impl Gluster {
     /// Connect to a Ceph cluster and return a connection handle glfs_t
     /// port is usually 24007 but may differ depending on how the service was configured
     pub fn connect(volume_name: &str, server: &str, port: u16) -> Result<Gluster, GlusterError> {
-        let vol_name = try!(CString::new(volume_name));
-        let vol_transport = try!(CString::new("tcp"));
-        let vol_host = try!(CString::new(server));
-        unsafe {
-            let cluster_handle = glfs_new(vol_name.as_ptr());
-            if cluster_handle.is_null() {
-                return Err(GlusterError::new("glfs_new failed".to_string()));
-            }
-            let ret_code = glfs_set_volfile_server(
-                cluster_handle,
-                vol_transport.as_ptr(),
-                vol_host.as_ptr(),
-                port as ::libc::c_int,
-            );
-            if ret_code < 0 {
-                return Err(GlusterError::new(get_error()));
-            }
+        Gluster::builder(volume_name)
+            .server(server)
+            .port(port)
+            .connect()
+    }
+
+    /// True if this connection was built with GlusterBuilder::read_only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The glfs_t, for passing to C code or calling gfapi functions this
+    /// crate doesn't wrap.  The connection still owns it, don't fini it.
+    pub fn as_raw(&self) -> *mut Struct_glfs {
+        self.cluster_handle
+    }
+
+    /// Give up the glfs_t without finalizing it.  The caller becomes
+    /// responsible for glfs_fini, or for handing it back to from_raw.
+    pub fn into_raw(mut self) -> *mut Struct_glfs {
+        let cluster_handle = self.cluster_handle;
+        self.cluster_handle = ptr::null_mut();
+        cluster_handle
+    }
+
+    /// Take ownership of an initialized glfs_t, which is finalized when
+    /// the Gluster is dropped.  config is what try_clone connects with
+    /// and whether the connection is read only; nothing in it is applied
+    /// to the handle, except that a watchdog is started if it has one.
+    ///
+    /// # Safety
+    ///
+    /// cluster_handle must come from glfs_new and glfs_init, or into_raw,
+    /// and nothing else may fini it.
+    pub unsafe fn from_raw(cluster_handle: *mut Struct_glfs, config: &GlusterBuilder) -> Gluster {
+        Gluster {
+            cluster_handle,
+            read_only: config.read_only,
+            config: config.clone(),
+            logging: Mutex::new(None),
+            xlator_options: Mutex::new(Vec::new()),
+            volfile_servers: Mutex::new(Vec::new()),
+            watchdog: config.watchdog.as_ref().map(Watchdog::start),
+        }
+    }
+
+    // Refuse to modify anything on a read only connection.  errno is set
+    // to EROFS as well so callers that check errno after a failure see a
+    // sensible value.
+    pub(crate) fn check_writable(&self) -> Result<(), GlusterError> {
+        if self.read_only {
+            set_errno(Errno(EROFS));
+            return Err(GlusterError::ReadOnly);
+        }
+        Ok(())
+    }
 
-            let ret_code = glfs_init(cluster_handle);
-            if ret_code < 0 {
-                return Err(GlusterError::new(get_error()));
-            }
-            Ok(Gluster {
-                cluster_handle: cluster_handle,
-            })
+    // Convert a path on the volume for gfapi, refusing it with errno set to
+    // ENAMETOOLONG if it's over the limits from GlusterBuilder::path_limits
+    pub(crate) fn c_path(&self, path: &Path) -> Result<CString, GlusterError> {
+        let (max_path, max_name) = (self.config.max_path_len, self.config.max_name_len);
+        let bytes = path.as_os_str().as_bytes();
+        if bytes.len() > max_path {
+            set_errno(Errno(ENAMETOOLONG));
+            return Err(GlusterError::PathTooLong {
+                path: path.to_path_buf(),
+                component: None,
+                len: bytes.len(),
+                limit: max_path,
+            });
         }
+        if let Some(long) = bytes.split(|b| *b == b'/').find(|c| c.len() > max_name) {
+            set_errno(Errno(ENAMETOOLONG));
+            return Err(GlusterError::PathTooLong {
+                path: path.to_path_buf(),
+                component: Some(OsStr::from_bytes(long).to_os_string()),
+                len: long.len(),
+                limit: max_name,
+            });
+        }
+        Ok(CString::new(bytes)?)
     }
 
-    /// Disconnect from a Gluster cluster and destroy the connection handle
-    /// For clean up, this is only necessary after connect() has succeeded.
-    /// Normally there is no need to call this function.  When Rust cleans
-    /// up the Gluster struct it will automatically call disconnect
-    pub fn disconnect(self) {
-        if self.cluster_handle.is_null() {
-            // No cleanup needed
-            return;
+    // Turn text gfapi returned into a String, replacing invalid UTF-8
+    // unless the connection was built with strict_utf8
+    pub(crate) fn bytes_to_string(&self, bytes: Vec<u8>) -> Result<String, GlusterError> {
+        if self.config.strict_utf8 {
+            return Ok(String::from_utf8(bytes)?);
         }
-        unsafe {
-            glfs_fini(self.cluster_handle);
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(text),
+            Err(e) => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
         }
     }
 
+    fn check_open_flags(&self, flags: i32) -> Result<(), GlusterError> {
+        if flags & O_ACCMODE != O_RDONLY || flags & (O_CREAT | O_TRUNC | O_APPEND) != 0 {
+            return self.check_writable();
+        }
+        Ok(())
+    }
+
+    /// Disconnect from a Gluster cluster and destroy the connection handle
+    /// For clean up, this is only necessary after connect() has succeeded.
+    /// When Rust cleans up the Gluster struct it will automatically call
+    /// disconnect, but any error only goes to the drop error handler.
+    /// Call this explicitly if a clean shutdown matters: glfs_fini fails
+    /// when fds are still open or the volume graph can't be torn down,
+    /// and that can mean cached writes were lost.
+    pub fn disconnect(mut self) -> Result<(), GlusterError> {
+        let cluster_handle = self.cluster_handle;
+        self.cluster_handle = ptr::null_mut();
+        fini(cluster_handle)
+    }
+
     /// This function specifies logging parameters for the virtual mount.
     /// Sets the log file to write to
     pub fn set_logging(
@@ -315,77 +901,100 @@ impl Gluster {
         logfile: &Path,
         loglevel: GlusterLogLevel,
     ) -> Result<(), GlusterError> {
+        self.set_logging_level(logfile, loglevel as i32)
+    }
+
+    pub(crate) fn set_logging_level(&self, logfile: &Path, loglevel: i32) -> Result<(), GlusterError> {
         let path = try!(CString::new(logfile.as_os_str().as_bytes()));
         unsafe {
-            let ret_code = glfs_set_logging(self.cluster_handle, path.as_ptr(), loglevel as i32);
+            let ret_code = glfs_set_logging(self.cluster_handle, path.as_ptr(), loglevel);
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
         }
+        let mut logging = match self.logging.lock() {
+            Ok(logging) => logging,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *logging = Some((logfile.to_path_buf(), loglevel));
         Ok(())
     }
 
+    /// The xlator options the builder set on this connection, from TLS,
+    /// tuning and xlator_option, in the order they were applied
+    pub fn applied_xlator_options(&self) -> Vec<XlatorOption> {
+        match self.xlator_options.lock() {
+            Ok(options) => options.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// The settings this connection was made with
+    pub fn config(&self) -> &GlusterBuilder {
+        &self.config
+    }
+
+    /// Replace a dead connection, one where every call fails with
+    /// ENOTCONN after all the servers went away, with a new one made
+    /// with the same settings as try_clone would.  The new connection is
+    /// made first, so if that fails this one is left as it was.  The old
+    /// handle is then finalized, any error from that going to the drop
+    /// error handler.  Volfile servers added or removed since connecting
+    /// aren't carried over.
+    ///
+    /// Files and directories borrow the connection, so none can be open
+    /// across a reconnect and be left holding a finalized fd:
+    ///
+    /// ```compile_fail,E0502
+    /// # use gfapi_sys::gluster::Gluster;
+    /// # use std::path::Path;
+    /// let mut cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    /// let file = cluster.open(Path::new("file"), 0).unwrap();
+    /// cluster.reconnect().unwrap();
+    /// file.fsync().unwrap();
+    /// ```
+    pub fn reconnect(&mut self) -> Result<(), GlusterError> {
+        let fresh = self.try_clone()?;
+        let old = std::mem::replace(self, fresh);
+        drop(old);
+        Ok(())
+    }
+
+    /// Share this connection between threads.  Connecting takes a while,
+    /// so rather than try_clone for every worker connect once and hand
+    /// each a clone of the Arc.  Every method takes &self, and glfs_fini
+    /// runs once, when the last clone is dropped.
+    pub fn shared(self) -> Arc<Gluster> {
+        Arc::new(self)
+    }
+
+    /// Open a second, independent connection with the same settings,
+    /// including any logging set with set_logging.  It has its own
+    /// handle and is finalized separately, so either can be dropped
+    /// first.
+    pub fn try_clone(&self) -> Result<Gluster, GlusterError> {
+        let clone = self.config.connect()?;
+        let logging = match self.logging.lock() {
+            Ok(logging) => logging.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        if let Some((logfile, loglevel)) = logging {
+            clone.set_logging_level(&logfile, loglevel)?;
+        }
+        Ok(clone)
+    }
+
     /// Get the volfile associated with the virtual mount
     /// Sometimes it's useful e.g. for scripts to see the volfile, so that they
     /// can parse it and find subvolumes to do things like split-brain resolution
     /// or custom layouts.
     /// Note that the volume must be started (not necessarily mounted) for this
     /// to work.  Also this function isn't very useful at the moment.  It needs
-    /// to be parsed into a volume graph before it's really usable.  
+    /// to be parsed into a volume graph before it's really usable.  The same
+    /// as volfile.
     // TODO: Change this from String to a struct
     pub fn get_volfile(&self) -> Result<String, GlusterError> {
-        // Start with 1K buffer and see if that works.  Even small clusters
-        // have pretty large volfiles.
-        let capacity = 1024;
-        let mut buffer: Vec<u8> = Vec::with_capacity(capacity);
-        unsafe {
-            // This will likely fail and gluster will tell me the size it needs
-            let ret = glfs_get_volfile(
-                self.cluster_handle,
-                buffer.as_mut_ptr() as *mut c_void,
-                buffer.capacity() as usize,
-            );
-            if ret > 0 {
-                //>0: filled N bytes of buffer
-                buffer.truncate(ret as usize);
-                buffer.set_len(ret as usize);
-                return Ok(String::from_utf8_lossy(&buffer).into_owned());
-            }
-            if ret == 0 {
-                //0: no volfile available
-                return Err(GlusterError::new("No volfile available".into()));
-            }
-            if ret < 0 {
-                // <0: volfile length exceeds @len by N bytes (@buf unchanged)
-                trace!(
-                    "volfile length is too large.  resizing to {}",
-                    capacity + ret.abs() as usize
-                );
-                let mut buffer: Vec<u8> = Vec::with_capacity(capacity + ret.abs() as usize);
-                let retry = glfs_get_volfile(
-                    self.cluster_handle,
-                    buffer.as_mut_ptr() as *mut c_void,
-                    buffer.capacity() as usize,
-                );
-                if retry > 0 {
-                    //>0: filled N bytes of buffer
-                    buffer.truncate(retry as usize);
-                    buffer.set_len(retry as usize);
-                    return Ok(String::from_utf8_lossy(&buffer).into_owned());
-                }
-                if retry == 0 {
-                    //0: no volfile available
-                    return Err(GlusterError::new("No volfile available".into()));
-                }
-                if ret < 0 {
-                    // I give up
-                    return Err(GlusterError::new(
-                        "volfile changed size while checking".into(),
-                    ));
-                }
-            }
-        }
-        return Err(GlusterError::new("Unknown error getting volfile".into()));
+        self.volfile()
     }
 
     /// Fetch the volume uuid from the glusterd management server
@@ -409,28 +1018,116 @@ impl Gluster {
         Ok(uuid)
     }
 
-    pub fn open(&self, path: &Path, flags: i32) -> Result<*mut Struct_glfs_fd, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
-        unsafe {
-            let file_handle = glfs_open(self.cluster_handle, path.as_ptr(), flags);
-            Ok(file_handle)
+    /// The client side volfile this connection is using, for diagnostics
+    pub fn volfile(&self) -> Result<String, GlusterError> {
+        let mut buf = Vec::new();
+        self.volfile_into(&mut buf)?;
+        self.bytes_to_string(buf)
+    }
+
+    /// Replace the contents of buf with the client side volfile and
+    /// return its length.  buf's existing capacity is used first and it's
+    /// only grown when gfapi reports the volfile is longer.
+    pub fn volfile_into(&self, buf: &mut Vec<u8>) -> Result<usize, GlusterError> {
+        buf.clear();
+        if buf.capacity() == 0 {
+            buf.reserve(4096);
+        }
+        // The volfile can change between calls if the volume is
+        // reconfigured, so allow a few attempts
+        for _ in 0..4 {
+            let ret_code = unsafe {
+                glfs_get_volfile(
+                    self.cluster_handle,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.capacity(),
+                )
+            };
+            if ret_code > 0 {
+                unsafe { buf.set_len(ret_code as usize) };
+                return Ok(ret_code as usize);
+            }
+            if ret_code == 0 {
+                return Err(GlusterError::new("no volfile is available".to_string()));
+            }
+            // A negative result is how many bytes short the buffer was
+            let needed = buf.capacity() + ret_code.unsigned_abs();
+            buf.reserve_exact(needed);
+        }
+        Err(GlusterError::new(get_error()))
+    }
+
+    /// Open a file.  The GlusterFile closes itself when dropped and
+    /// borrows the connection, so it can't outlive it:
+    ///
+    /// ```compile_fail,E0597
+    /// # use gfapi_sys::gluster::Gluster;
+    /// # use std::path::Path;
+    /// let file = {
+    ///     let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    ///     cluster.open(Path::new("file"), 0).unwrap()
+    /// };
+    /// ```
+    pub fn open(&self, path: &Path, flags: i32) -> Result<GlusterFile<'_>, GlusterError> {
+        self.check_open_flags(flags)?;
+        let _op = self.track("open", path);
+        let c_path = self.c_path(path)?;
+        unsafe {
+            let file_handle = glfs_open(self.cluster_handle, c_path.as_ptr(), flags);
+            if file_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterFile::new(self, file_handle, flags, path))
         }
     }
+    /// Create a file.  Like open the GlusterFile closes itself when
+    /// dropped and can't outlive the connection.
     pub fn create(
         &self,
         path: &Path,
         flags: i32,
         mode: mode_t,
-    ) -> Result<*mut Struct_glfs_fd, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+    ) -> Result<GlusterFile<'_>, GlusterError> {
+        self.check_writable()?;
+        let _op = self.track("create", path);
+        let c_path = self.c_path(path)?;
         unsafe {
-            let file_handle = glfs_creat(self.cluster_handle, path.as_ptr(), flags, mode);
+            let file_handle = glfs_creat(self.cluster_handle, c_path.as_ptr(), flags, mode);
             if file_handle.is_null() {
                 return Err(GlusterError::new(get_error()));
             }
-            Ok(file_handle)
+            Ok(GlusterFile::new(self, file_handle, flags, path))
         }
     }
+    /// Same as open
+    pub fn open_file(&self, path: &Path, flags: i32) -> Result<GlusterFile<'_>, GlusterError> {
+        self.open(path, flags)
+    }
+    /// Same as create
+    pub fn create_file(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+    ) -> Result<GlusterFile<'_>, GlusterError> {
+        self.create(path, flags, mode)
+    }
+    /// create_file, with policy deciding whether the umask can take
+    /// bits out of mode.  With ModePolicy::Exact the mode is set even if
+    /// the file already existed.
+    pub fn create_file_with(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+        policy: ModePolicy,
+    ) -> Result<GlusterFile<'_>, GlusterError> {
+        let file = self.create_file(path, flags, mode)?;
+        if policy == ModePolicy::Exact {
+            self.fchmod(file.handle()?, mode)?;
+        }
+        Ok(file)
+    }
     pub fn close(&self, file_handle: *mut Struct_glfs_fd) -> Result<(), GlusterError> {
         unsafe {
             let ret_code = glfs_close(file_handle);
@@ -440,22 +1137,64 @@ impl Gluster {
         }
         Ok(())
     }
+    // The read and write wrappers below used to take a flags argument
+    // that was passed straight to gfapi, which ignores it.  O_SYNC and
+    // friends had no effect there, use write_sync or fsync instead.
+    //
+    // They read and write at the fd's position and move it on, like
+    // read(2) and write(2).  Use pread and pwrite for a given offset.
     pub fn read(
         &self,
         file_handle: *mut Struct_glfs_fd,
         fill_buffer: &mut Vec<u8>,
         count: usize,
-        flags: i32,
     ) -> Result<isize, GlusterError> {
-        self.pread(file_handle, fill_buffer, count, 0, flags)
+        fill_buffer.reserve(count.saturating_sub(fill_buffer.len()));
+        unsafe {
+            let read_size = glfs_read(
+                file_handle,
+                fill_buffer.as_mut_ptr() as *mut c_void,
+                count,
+                0,
+            );
+            if read_size < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            fill_buffer.set_len(read_size as usize);
+            Ok(read_size)
+        }
     }
     pub fn write(
         &self,
         file_handle: *mut Struct_glfs_fd,
         buffer: &[u8],
-        flags: i32,
     ) -> Result<isize, GlusterError> {
-        self.pwrite(file_handle, buffer, buffer.len(), 0, flags)
+        self.check_writable()?;
+        unsafe {
+            let write_size = glfs_write(
+                file_handle,
+                buffer.as_ptr() as *const c_void,
+                buffer.len(),
+                0,
+            );
+            if write_size < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(write_size)
+        }
+    }
+
+    /// write followed by fdatasync, so the data is on stable storage when
+    /// this returns.  What passing O_SYNC to write was expected to do.
+    /// Like write it goes at the fd's position and moves it on.
+    pub fn write_sync(
+        &self,
+        file_handle: *mut Struct_glfs_fd,
+        buffer: &[u8],
+    ) -> Result<isize, GlusterError> {
+        let written = self.write(file_handle, buffer)?;
+        self.fdatasync(file_handle)?;
+        Ok(written)
     }
 
     /*
@@ -492,14 +1231,13 @@ impl Gluster {
         &self,
         file_handle: *mut Struct_glfs_fd,
         iov: &mut [&mut [u8]],
-        flags: i32,
     ) -> Result<isize, GlusterError> {
         unsafe {
             let read_size = glfs_readv(
                 file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
-                flags,
+                0,
             );
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -511,14 +1249,14 @@ impl Gluster {
         &self,
         file_handle: *mut Struct_glfs_fd,
         iov: &[&[u8]],
-        flags: i32,
     ) -> Result<isize, GlusterError> {
+        self.check_writable()?;
         unsafe {
             let write_size = glfs_writev(
                 file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
-                flags,
+                0,
             );
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -534,7 +1272,6 @@ impl Gluster {
         fill_buffer: &mut Vec<u8>,
         count: usize,
         offset: i64,
-        flags: i32,
     ) -> Result<isize, GlusterError> {
         unsafe {
             let read_size = glfs_pread(
@@ -542,7 +1279,7 @@ impl Gluster {
                 fill_buffer.as_mut_ptr() as *mut c_void,
                 count,
                 offset,
-                flags,
+                0,
             );
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -557,15 +1294,15 @@ impl Gluster {
         buffer: &[u8],
         count: usize,
         offset: i64,
-        flags: i32,
     ) -> Result<isize, GlusterError> {
+        self.check_writable()?;
         unsafe {
             let write_size = glfs_pwrite(
                 file_handle,
                 buffer.as_ptr() as *mut c_void,
                 count,
                 offset,
-                flags,
+                0,
             );
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -579,7 +1316,6 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         iov: &mut [&mut [u8]],
         offset: i64,
-        flags: i32,
     ) -> Result<isize, GlusterError> {
         unsafe {
             let read_size = glfs_preadv(
@@ -587,7 +1323,7 @@ impl Gluster {
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 offset,
-                flags,
+                0,
             );
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -601,15 +1337,15 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         iov: &[&[u8]],
         offset: i64,
-        flags: i32,
     ) -> Result<isize, GlusterError> {
+        self.check_writable()?;
         unsafe {
             let write_size = glfs_pwritev(
                 file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 offset,
-                flags,
+                0,
             );
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
@@ -617,6 +1353,63 @@ impl Gluster {
             Ok(write_size)
         }
     }
+    /// pwritev until every byte of bufs is written, picking up where a
+    /// short write left off.  Returns the total written.
+    pub fn pwritev_all(
+        &self,
+        file: &GlusterFile,
+        bufs: &[IoSlice],
+        offset: i64,
+    ) -> Result<usize, GlusterError> {
+        self.check_writable()?;
+        let file_handle = file.handle()?;
+        if offset < 0 {
+            return Err(GlusterError::new("negative offset".to_string()));
+        }
+        vectored::write_all_vectored_at(bufs, offset as u64, |slices, at| unsafe {
+            // IoSlice is guaranteed to match struct iovec on unix
+            let written = glfs_pwritev(
+                file_handle,
+                slices.as_ptr() as *const iovec,
+                slices.len() as i32,
+                at as i64,
+                0,
+            );
+            if written < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(written as usize)
+        })
+    }
+
+    /// preadv until every byte of bufs is filled, picking up where a
+    /// short read left off.  Hitting the end of the file first is an
+    /// UnexpectedEof error.
+    pub fn preadv_exact(
+        &self,
+        file: &GlusterFile,
+        bufs: &mut [IoSliceMut],
+        offset: i64,
+    ) -> Result<(), GlusterError> {
+        let file_handle = file.handle()?;
+        if offset < 0 {
+            return Err(GlusterError::new("negative offset".to_string()));
+        }
+        vectored::read_exact_vectored_at(bufs, offset as u64, |slices, at| unsafe {
+            let read = glfs_preadv(
+                file_handle,
+                slices.as_ptr() as *const iovec,
+                slices.len() as i32,
+                at as i64,
+                0,
+            );
+            if read < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(read as usize)
+        })?;
+        Ok(())
+    }
     pub fn lseek(
         &self,
         file_handle: *mut Struct_glfs_fd,
@@ -632,7 +1425,9 @@ impl Gluster {
         }
     }
     pub fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("truncate", path);
+        let path = self.c_path(path)?;
 
         unsafe {
             let ret_code = glfs_truncate(self.cluster_handle, path.as_ptr(), length);
@@ -647,6 +1442,7 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         length: i64,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_ftruncate(file_handle, length);
             if ret_code < 0 {
@@ -656,7 +1452,8 @@ impl Gluster {
         Ok(())
     }
     pub fn lsstat(&self, path: &Path) -> Result<stat, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("lsstat", path);
+        let path = self.c_path(path)?;
         unsafe {
             let mut stat_buf: stat = zeroed();
             let ret_code = glfs_lstat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
@@ -668,7 +1465,7 @@ impl Gluster {
     }
     /// Tests for the existance of a file.  Returns true/false respectively.
     pub fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let path = self.c_path(path)?;
         unsafe {
             let mut stat_buf: stat = zeroed();
             let ret_code = glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
@@ -684,7 +1481,8 @@ impl Gluster {
     }
 
     pub fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("statvfs", path);
+        let path = self.c_path(path)?;
         unsafe {
             let mut stat_buf: statvfs = zeroed();
             let ret_code = glfs_statvfs(self.cluster_handle, path.as_ptr(), &mut stat_buf);
@@ -696,7 +1494,8 @@ impl Gluster {
     }
 
     pub fn stat(&self, path: &Path) -> Result<stat, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("stat", path);
+        let path = self.c_path(path)?;
         unsafe {
             let mut stat_buf: stat = zeroed();
             let ret_code = glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
@@ -736,7 +1535,8 @@ impl Gluster {
         Ok(())
     }
     pub fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("access", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_access(self.cluster_handle, path.as_ptr(), mode);
             if ret_code < 0 {
@@ -747,8 +1547,10 @@ impl Gluster {
     }
 
     pub fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
-        let old_path = try!(CString::new(oldpath.as_os_str().as_bytes()));
-        let new_path = try!(CString::new(newpath.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("symlink", newpath);
+        let old_path = self.c_path(oldpath)?;
+        let new_path = self.c_path(newpath)?;
         unsafe {
             let ret_code = glfs_symlink(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
             if ret_code < 0 {
@@ -759,7 +1561,8 @@ impl Gluster {
     }
 
     pub fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("readlink", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_readlink(
                 self.cluster_handle,
@@ -774,8 +1577,38 @@ impl Gluster {
         Ok(())
     }
 
+    /// The target of the symlink at path.  Unlike readlink this returns
+    /// only the bytes gfapi filled in, growing the buffer for long targets.
+    pub fn read_link(&self, path: &Path) -> Result<PathBuf, GlusterError> {
+        let path = self.c_path(path)?;
+        let mut buf: Vec<u8> = vec![0; 256];
+        loop {
+            let len = unsafe {
+                glfs_readlink(
+                    self.cluster_handle,
+                    path.as_ptr(),
+                    buf.as_mut_ptr() as *mut i8,
+                    buf.len(),
+                )
+            };
+            if len < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            let len = len as usize;
+            // A full buffer may mean the target was cut short
+            if len < buf.len() {
+                buf.truncate(len);
+                return Ok(PathBuf::from(OsString::from_vec(buf)));
+            }
+            let grown = buf.len() * 2;
+            buf.resize(grown, 0);
+        }
+    }
+
     pub fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("mknod", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_mknod(self.cluster_handle, path.as_ptr(), mode, dev);
             if ret_code < 0 {
@@ -786,7 +1619,9 @@ impl Gluster {
     }
 
     pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("mkdir", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_mkdir(self.cluster_handle, path.as_ptr(), mode);
             if ret_code < 0 {
@@ -796,8 +1631,61 @@ impl Gluster {
         Ok(())
     }
 
+    /// mkdir, with policy deciding whether the umask can take bits out of
+    /// mode
+    pub fn mkdir_with(
+        &self,
+        path: &Path,
+        mode: mode_t,
+        policy: ModePolicy,
+    ) -> Result<(), GlusterError> {
+        self.mkdir(path, mode)?;
+        if policy == ModePolicy::Exact {
+            self.chmod(path, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Recursively create a directory and all of its parent components if
+    /// they are missing.  Components that already exist are left alone.
+    pub fn create_dir_all(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.create_dir_all_with(path, mode, ModePolicy::RespectUmask)
+    }
+
+    /// create_dir_all, with policy deciding whether the umask can take
+    /// bits out of mode for the directories it creates
+    pub fn create_dir_all_with(
+        &self,
+        path: &Path,
+        mode: mode_t,
+        policy: ModePolicy,
+    ) -> Result<(), GlusterError> {
+        self.check_writable()?;
+        if path == Path::new("") || path == Path::new("/") {
+            return Ok(());
+        }
+        if self.exists(path)? {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dir_all_with(parent, mode, policy)?;
+        }
+        match self.mkdir_with(path, mode, policy) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // Someone else may have created it in the meantime
+                if errno() == Errno(EEXIST) {
+                    return Ok(());
+                }
+                Err(e)
+            }
+        }
+    }
+
     pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("unlink", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_unlink(self.cluster_handle, path.as_ptr());
             if ret_code < 0 {
@@ -807,7 +1695,9 @@ impl Gluster {
         Ok(())
     }
     pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("rmdir", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_rmdir(self.cluster_handle, path.as_ptr());
             if ret_code < 0 {
@@ -820,9 +1710,7 @@ impl Gluster {
     fn is_empty(&self, p: &Path) -> Result<bool, GlusterError> {
         let this = Path::new(".");
         let parent = Path::new("..");
-        let d = GlusterDirectory {
-            dir_handle: self.opendir(&p)?,
-        };
+        let d = self.opendir(&p)?;
         for dir_entry in d {
             if dir_entry.path == this || dir_entry.path == parent {
                 continue;
@@ -842,6 +1730,7 @@ impl Gluster {
     /// Removes a directory at this path, after removing all its contents.
     /// Use carefully!
     pub fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        self.check_writable()?;
         trace!("Removing {}", path.display());
         let mut stack: Vec<PathBuf> = vec![path.to_path_buf()];
         let mut done = false;
@@ -855,9 +1744,7 @@ impl Gluster {
                     trace!("break for PathBuf::from(\"\")");
                     break;
                 }
-                let d = GlusterDirectory {
-                    dir_handle: self.opendir(&p)?,
-                };
+                let d = self.opendir(&p)?;
                 // If there's nothing in there remove the directory
                 if self.is_empty(&p)? {
                     self.rmdir(&p)?;
@@ -913,8 +1800,10 @@ impl Gluster {
     }
 
     pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
-        let old_path = try!(CString::new(oldpath.as_os_str().as_bytes()));
-        let new_path = try!(CString::new(newpath.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("rename", oldpath);
+        let old_path = self.c_path(oldpath)?;
+        let new_path = self.c_path(newpath)?;
         unsafe {
             let ret_code = glfs_rename(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
             if ret_code < 0 {
@@ -924,9 +1813,47 @@ impl Gluster {
         Ok(())
     }
 
+    /// Rename oldpath to newpath, failing with GlusterError::AlreadyExists
+    /// instead of replacing newpath if something is already there.  Done
+    /// as a link to the new name followed by an unlink of the old one, so
+    /// of several callers racing onto the same newpath exactly one
+    /// succeeds.  If the unlink fails the new link is removed again.
+    ///
+    /// Directories can't be hard linked and a check followed by a rename
+    /// could let two callers both win, so they're refused with an
+    /// ErrorKind::Unsupported IoError.
+    pub fn rename_noreplace(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        self.check_writable()?;
+        let stat = self.lsstat(oldpath)?;
+        if stat.st_mode & S_IFMT == S_IFDIR {
+            return Err(GlusterError::IoError(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "{} is a directory, which can't be renamed without replacing",
+                    oldpath.display()
+                ),
+            )));
+        }
+        if let Err(e) = self.link(oldpath, newpath) {
+            if errno() == Errno(EEXIST) {
+                return Err(GlusterError::AlreadyExists {
+                    path: newpath.to_path_buf(),
+                });
+            }
+            return Err(e);
+        }
+        if let Err(e) = self.unlink(oldpath) {
+            let _ = self.unlink(newpath);
+            return Err(e);
+        }
+        Ok(())
+    }
+
     pub fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
-        let old_path = try!(CString::new(oldpath.as_os_str().as_bytes()));
-        let new_path = try!(CString::new(newpath.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("link", oldpath);
+        let old_path = self.c_path(oldpath)?;
+        let new_path = self.c_path(newpath)?;
         unsafe {
             let ret_code = glfs_link(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
             if ret_code < 0 {
@@ -936,16 +1863,175 @@ impl Gluster {
         Ok(())
     }
 
-    pub fn opendir(&self, path: &Path) -> Result<*mut Struct_glfs_fd, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+    /// Open a directory to iterate over its entries.  The handle is
+    /// closed when the GlusterDirectory is dropped and, like a
+    /// GlusterFile, can't outlive the connection:
+    ///
+    /// ```compile_fail,E0597
+    /// # use gfapi_sys::gluster::Gluster;
+    /// # use std::path::Path;
+    /// let dir = {
+    ///     let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    ///     cluster.opendir(Path::new("dir")).unwrap()
+    /// };
+    /// ```
+    pub fn opendir(&self, path: &Path) -> Result<GlusterDirectory<'_>, GlusterError> {
+        let _op = self.track("opendir", path);
+        let path = self.c_path(path)?;
+        unsafe {
+            let dir_handle = glfs_opendir(self.cluster_handle, path.as_ptr());
+            if dir_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterDirectory {
+                dir_handle,
+                gluster: PhantomData,
+            })
+        }
+    }
+    /// fsync a directory so entries just added to or removed from it are
+    /// durable
+    pub fn fsync_dir(&self, path: &Path) -> Result<(), GlusterError> {
+        let dir = self.opendir(path)?;
         unsafe {
-            let file_handle = glfs_opendir(self.cluster_handle, path.as_ptr());
-            Ok(file_handle)
+            if glfs_fsync(dir.dir_handle) < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
         }
+        Ok(())
     }
+    /// Count the entries in a directory, excluding . and ..  With a filter
+    /// only entries of that type are counted.  Names are compared in
+    /// place rather than turned into DirEntry values, and the only extra
+    /// calls are lstats of entries whose d_type is DT_UNKNOWN when a
+    /// filter is given.
+    pub fn dir_entry_count(
+        &self,
+        path: &Path,
+        filter: Option<FileType>,
+    ) -> Result<u64, GlusterError> {
+        let dir = self.opendir(path)?;
+        let dir_handle = dir.dir_handle;
+        let mut count = 0;
+        unsafe {
+            let mut dirent: dirent = zeroed();
+            loop {
+                let mut next_entry: *mut dirent = ptr::null_mut();
+                let ret_code = glfs_readdir_r(dir_handle, &mut dirent, &mut next_entry);
+                if ret_code < 0 {
+                    return Err(GlusterError::new(get_error()));
+                }
+                if next_entry.is_null() {
+                    break;
+                }
+                let name = CStr::from_ptr(dirent.d_name.as_ptr()).to_bytes();
+                if name == b"." || name == b".." {
+                    continue;
+                }
+                let wanted = match filter {
+                    None => true,
+                    Some(file_type) => match FileType::from_d_type(dirent.d_type) {
+                        Some(found) => found == file_type,
+                        None => match self.lsstat(&path.join(OsStr::from_bytes(name))) {
+                            Ok(stat) => FileType::from_mode(stat.st_mode) == Some(file_type),
+                            // Removed since it was listed
+                            Err(_) if errno() == Errno(ENOENT) => false,
+                            Err(e) => return Err(e),
+                        },
+                    },
+                };
+                if wanted {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// List a directory along with the metadata of every entry, excluding
+    /// . and ..  readdirplus is used to fetch the metadata in the same round
+    /// trip.  Entries the server didn't return a stat for are stat'd
+    /// separately using up to stat_concurrency threads.
+    pub fn list_dir(
+        &self,
+        path: &Path,
+        stat_concurrency: usize,
+    ) -> Result<Vec<(DirEntry, Metadata)>, GlusterError> {
+        let dir = self.opendir(path)?;
+        let dir_handle = dir.dir_handle;
+        let mut entries: Vec<(DirEntry, Option<Metadata>)> = Vec::new();
+        unsafe {
+            loop {
+                let mut dirent: dirent = zeroed();
+                let mut next_entry: *mut dirent = ptr::null_mut();
+                let mut stat_buf: stat = zeroed();
+                let ret_code =
+                    glfs_readdirplus_r(dir_handle, &mut stat_buf, &mut dirent, &mut next_entry);
+                if ret_code < 0 {
+                    return Err(GlusterError::new(get_error()));
+                }
+                if next_entry.is_null() {
+                    // End of stream reached
+                    break;
+                }
+                let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
+                let entry = DirEntry {
+                    path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
+                    inode: dirent.d_ino,
+                    file_type: dirent.d_type,
+                };
+                if entry.path == Path::new(".") || entry.path == Path::new("..") {
+                    continue;
+                }
+                // Servers without readdirplus support hand back an empty stat
+                let metadata = if stat_buf.st_ino == 0 {
+                    None
+                } else {
+                    Some(Metadata::from_stat(stat_buf))
+                };
+                entries.push((entry, metadata));
+            }
+        }
+        drop(dir);
+
+        let missing: Vec<PathBuf> = entries
+            .iter()
+            .filter(|e| e.1.is_none())
+            .map(|e| path.join(&e.0.path))
+            .collect();
+        let mut stats = self.stat_many(&missing, stat_concurrency).into_iter();
+        let mut listing = Vec::with_capacity(entries.len());
+        for (entry, metadata) in entries {
+            let metadata = match metadata {
+                Some(m) => m,
+                None => match stats.next() {
+                    Some(Ok(m)) => m,
+                    Some(Err(e)) => return Err(e),
+                    None => unreachable!(),
+                },
+            };
+            listing.push((entry, metadata));
+        }
+        Ok(listing)
+    }
+
     pub fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
-        let name = try!(CString::new(name));
+        let value = self.getxattr_raw_name(path, name.as_bytes())?;
+        self.bytes_to_string(value)
+    }
+
+    /// Like getxattr but returns the raw value, for attributes that
+    /// aren't text
+    pub fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        self.getxattr_raw_name(path, name.as_bytes())
+    }
+
+    /// Like getxattr_bytes but takes the attribute name as bytes, for
+    /// names that aren't valid UTF-8
+    pub fn getxattr_raw_name(&self, path: &Path, name: &[u8]) -> Result<Vec<u8>, GlusterError> {
+        let _op = self.track("getxattr_raw_name", path);
+        let path = self.c_path(path)?;
+        let name = CString::new(name)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
             let ret_code = glfs_getxattr(
@@ -953,19 +2039,19 @@ impl Gluster {
                 path.as_ptr(),
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
-            // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        Ok(xattr_val_buff)
     }
 
     pub fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("lgetxattr", path);
+        let path = self.c_path(path)?;
         let name = try!(CString::new(name));
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
@@ -974,89 +2060,123 @@ impl Gluster {
                 path.as_ptr(),
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
             // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        self.bytes_to_string(xattr_val_buff)
     }
     pub fn fgetxattr(
         &self,
         file_handle: *mut Struct_glfs_fd,
         name: &str,
     ) -> Result<String, GlusterError> {
-        let name = try!(CString::new(name));
+        let value = self.fgetxattr_raw_name(file_handle, name.as_bytes())?;
+        self.bytes_to_string(value)
+    }
+
+    // Like fgetxattr but takes the attribute name as bytes and returns the
+    // raw value, GlusterFile::getxattr_raw_name is the public way in
+    pub(crate) fn fgetxattr_raw_name(
+        &self,
+        file_handle: *mut Struct_glfs_fd,
+        name: &[u8],
+    ) -> Result<Vec<u8>, GlusterError> {
+        let name = CString::new(name)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
             let ret_code = glfs_fgetxattr(
                 file_handle,
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
             // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        Ok(xattr_val_buff)
     }
+    /// The attribute names gfapi returned, NUL separated as it sends them
     pub fn listxattr(&self, path: &Path) -> Result<String, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let names = self.list_xattr_raw(path)?;
+        self.bytes_to_string(join_xattr_names(&names))
+    }
+    pub fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        let names = self.llist_xattr_raw(path)?;
+        self.bytes_to_string(join_xattr_names(&names))
+    }
+    pub fn flistxattr(&self, file_handle: *mut Struct_glfs_fd) -> Result<String, GlusterError> {
+        let names = self.flist_xattr_raw(file_handle)?;
+        self.bytes_to_string(join_xattr_names(&names))
+    }
+
+    /// The names of path's extended attributes, one per entry, exactly as
+    /// gfapi returned them with no UTF-8 conversion
+    pub fn list_xattr_raw(&self, path: &Path) -> Result<Vec<Vec<u8>>, GlusterError> {
+        let _op = self.track("list_xattr_raw", path);
+        let path = self.c_path(path)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
             let ret_code = glfs_listxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
-            // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        Ok(split_xattr_names(&xattr_val_buff))
     }
-    pub fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+
+    /// Like list_xattr_raw but lists a symlink's own attributes
+    pub fn llist_xattr_raw(&self, path: &Path) -> Result<Vec<Vec<u8>>, GlusterError> {
+        let _op = self.track("llist_xattr_raw", path);
+        let path = self.c_path(path)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
             let ret_code = glfs_llistxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
-            // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        Ok(split_xattr_names(&xattr_val_buff))
     }
-    pub fn flistxattr(&self, file_handle: *mut Struct_glfs_fd) -> Result<String, GlusterError> {
+
+    // Like list_xattr_raw for an open file, GlusterFile::list_xattr_raw is
+    // the public way in
+    pub(crate) fn flist_xattr_raw(
+        &self,
+        file_handle: *mut Struct_glfs_fd,
+    ) -> Result<Vec<Vec<u8>>, GlusterError> {
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
             let ret_code = glfs_flistxattr(
                 file_handle,
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
+                xattr_val_buff.capacity(),
             );
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
-            // Set the buffer to the size of bytes read into it
             xattr_val_buff.set_len(ret_code as usize);
-            Ok(String::from_utf8_lossy(&xattr_val_buff).into_owned())
         }
+        Ok(split_xattr_names(&xattr_val_buff))
     }
     pub fn setxattr(
         &self,
@@ -1065,8 +2185,22 @@ impl Gluster {
         value: &[u8],
         flags: i32,
     ) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
-        let name = try!(CString::new(name));
+        self.setxattr_raw_name(path, name.as_bytes(), value, flags)
+    }
+
+    /// Like setxattr but takes the attribute name as bytes, for names
+    /// that aren't valid UTF-8
+    pub fn setxattr_raw_name(
+        &self,
+        path: &Path,
+        name: &[u8],
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.check_writable()?;
+        let _op = self.track("setxattr_raw_name", path);
+        let path = self.c_path(path)?;
+        let name = CString::new(name)?;
         unsafe {
             let ret_code = glfs_setxattr(
                 self.cluster_handle,
@@ -1089,8 +2223,10 @@ impl Gluster {
         path: &Path,
         flags: i32,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         let name = try!(CString::new(name));
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let _op = self.track("lsetxattr", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_lsetxattr(
                 self.cluster_handle,
@@ -1113,7 +2249,21 @@ impl Gluster {
         value: &[u8],
         flags: i32,
     ) -> Result<(), GlusterError> {
-        let name = try!(CString::new(name));
+        self.fsetxattr_raw_name(file_handle, name.as_bytes(), value, flags)
+    }
+
+    // Like fsetxattr but takes the attribute name as bytes, for names that
+    // aren't valid UTF-8.  GlusterFile::setxattr_raw_name is the public way
+    // in
+    pub(crate) fn fsetxattr_raw_name(
+        &self,
+        file_handle: *mut Struct_glfs_fd,
+        name: &[u8],
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.check_writable()?;
+        let name = CString::new(name)?;
         unsafe {
             let ret_code = glfs_fsetxattr(
                 file_handle,
@@ -1129,7 +2279,9 @@ impl Gluster {
         Ok(())
     }
     pub fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("removexattr", path);
+        let path = self.c_path(path)?;
         let name = try!(CString::new(name));
         unsafe {
             let ret_code = glfs_removexattr(self.cluster_handle, path.as_ptr(), name.as_ptr());
@@ -1140,7 +2292,9 @@ impl Gluster {
         Ok(())
     }
     pub fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("lremovexattr", path);
+        let path = self.c_path(path)?;
         let name = try!(CString::new(name));
         unsafe {
             let ret_code = glfs_lremovexattr(self.cluster_handle, path.as_ptr(), name.as_ptr());
@@ -1155,6 +2309,7 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         name: &str,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         let name = try!(CString::new(name));
 
         unsafe {
@@ -1172,6 +2327,7 @@ impl Gluster {
         keep_size: i32,
         len: usize,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_fallocate(file_handle, keep_size, offset, len);
             if ret_code < 0 {
@@ -1186,6 +2342,7 @@ impl Gluster {
         offset: i64,
         len: usize,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_discard(file_handle, offset, len);
             if ret_code < 0 {
@@ -1200,6 +2357,7 @@ impl Gluster {
         offset: i64,
         len: i64,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_zerofill(file_handle, offset, len);
             if ret_code < 0 {
@@ -1209,18 +2367,22 @@ impl Gluster {
         Ok(())
     }
     pub fn getcwd(&self) -> Result<String, GlusterError> {
-        let mut cwd_val_buff: Vec<u8> = Vec::with_capacity(1024);
-        unsafe {
+        let mut cwd_val_buff: Vec<u8> = vec![0; 4096];
+        let cwd = unsafe {
             let cwd = glfs_getcwd(
                 self.cluster_handle,
                 cwd_val_buff.as_mut_ptr() as *mut i8,
                 cwd_val_buff.len(),
             );
-            Ok(CStr::from_ptr(cwd).to_string_lossy().into_owned())
-        }
+            if cwd.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            CStr::from_ptr(cwd).to_bytes().to_vec()
+        };
+        self.bytes_to_string(cwd)
     }
     pub fn chdir(&self, path: &Path) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_chdir(self.cluster_handle, path.as_ptr());
             if ret_code < 0 {
@@ -1229,9 +2391,10 @@ impl Gluster {
         }
         Ok(())
     }
-    pub fn fchdir(&self, file_handle: *mut Struct_glfs_fd) -> Result<(), GlusterError> {
+    /// Change the working directory to an open directory
+    pub fn fchdir(&self, dir: &GlusterDirectory) -> Result<(), GlusterError> {
         unsafe {
-            let ret_code = glfs_fchdir(file_handle);
+            let ret_code = glfs_fchdir(dir.dir_handle);
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1242,7 +2405,9 @@ impl Gluster {
     /// times[0] specifies the new "last access time" (atime);
     /// times[1] specifies the new "last modification time" (mtime).
     pub fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("utimens", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_utimens(self.cluster_handle, path.as_ptr(), times.as_ptr());
             if ret_code < 0 {
@@ -1255,7 +2420,9 @@ impl Gluster {
     /// times[0] specifies the new "last access time" (atime);
     /// times[1] specifies the new "last modification time" (mtime).
     pub fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("lutimens", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_lutimens(self.cluster_handle, path.as_ptr(), times.as_ptr());
             if ret_code < 0 {
@@ -1272,6 +2439,7 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         times: &[timespec; 2],
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_futimens(file_handle, times.as_ptr());
             if ret_code < 0 {
@@ -1297,7 +2465,9 @@ impl Gluster {
     }
 
     pub fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("chmod", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_chmod(self.cluster_handle, path.as_ptr(), mode);
             if ret_code < 0 {
@@ -1312,6 +2482,7 @@ impl Gluster {
         file_handle: *mut Struct_glfs_fd,
         mode: mode_t,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_fchmod(file_handle, mode);
             if ret_code < 0 {
@@ -1322,7 +2493,9 @@ impl Gluster {
     }
 
     pub fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("chown", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_chown(self.cluster_handle, path.as_ptr(), uid, gid);
             if ret_code < 0 {
@@ -1333,7 +2506,9 @@ impl Gluster {
     }
 
     pub fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
-        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        self.check_writable()?;
+        let _op = self.track("lchown", path);
+        let path = self.c_path(path)?;
         unsafe {
             let ret_code = glfs_lchown(self.cluster_handle, path.as_ptr(), uid, gid);
             if ret_code < 0 {
@@ -1349,6 +2524,7 @@ impl Gluster {
         uid: u32,
         gid: u32,
     ) -> Result<(), GlusterError> {
+        self.check_writable()?;
         unsafe {
             let ret_code = glfs_fchown(file_handle, uid, gid);
             if ret_code < 0 {
@@ -1369,13 +2545,8 @@ impl Gluster {
     // }
     // }
     //
-    pub fn dup(
-        &self,
-        file_handle: *mut Struct_glfs_fd,
-    ) -> Result<*mut Struct_glfs_fd, GlusterError> {
-        unsafe {
-            let file_handle = glfs_dup(file_handle);
-            Ok(file_handle)
-        }
+    /// Duplicate an open file, see GlusterFile::try_clone
+    pub fn dup<'a>(&'a self, file: &GlusterFile<'a>) -> Result<GlusterFile<'a>, GlusterError> {
+        file.try_clone()
     }
 }
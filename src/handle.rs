@@ -0,0 +1,381 @@
+use libc::{c_int, c_void, ssize_t, stat, O_RDONLY, O_WRONLY};
+use uuid::Uuid;
+
+use cleanup::{self, DropError, DropTarget};
+use glfs::*;
+use gluster::{get_error, Gluster, GlusterError};
+
+use std::fmt;
+use std::mem::zeroed;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+/// True if the linked libgfapi exports glfs_h_anonymous_read, so h_pread
+/// and h_pwrite can use the server's anonymous fds instead of opening and
+/// closing the object around each call
+pub fn anonymous_fd_available() -> bool {
+    anonymous_io_fn("glfs_h_anonymous_read").is_some()
+        && anonymous_io_fn("glfs_h_anonymous_write").is_some()
+}
+
+fn anonymous_io_fn(name: &str) -> Option<glfs_h_anonymous_io_t> {
+    unsafe { optional_fn::<glfs_h_anonymous_io_t>(name) }
+}
+
+/// A gfapi object handle, which names a file by its gfid rather than its
+/// path.  It stays valid across renames of the file and needs no open
+/// fd, which suits a gateway whose clients hand back a gfid with each
+/// request.  Released with glfs_h_close when dropped.
+pub struct GlusterObject<'a> {
+    gluster: &'a Gluster,
+    object: *mut glfs_object,
+    gfid: Uuid,
+    path: Option<PathBuf>,
+}
+
+// Handles are only used through gfapi, which is thread safe
+unsafe impl<'a> Send for GlusterObject<'a> {}
+unsafe impl<'a> Sync for GlusterObject<'a> {}
+
+impl<'a> GlusterObject<'a> {
+    /// The file's gfid, which object_from_gfid turns back into an object
+    pub fn gfid(&self) -> Uuid {
+        self.gfid
+    }
+
+    pub(crate) fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    /// The path the object was looked up by, None if it came from
+    /// object_from_gfid
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // The object if it came from this connection, handles can't be
+    // passed between connections
+    fn raw_for(&self, gluster: &Gluster) -> Result<*mut glfs_object, GlusterError> {
+        if !ptr::eq(self.gluster, gluster) {
+            return Err(GlusterError::new(format!(
+                "object {} belongs to a different connection",
+                self.gfid
+            )));
+        }
+        Ok(self.object)
+    }
+}
+
+impl<'a> fmt::Debug for GlusterObject<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GlusterObject")
+            .field("gfid", &self.gfid)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl<'a> Drop for GlusterObject<'a> {
+    fn drop(&mut self) {
+        if unsafe { glfs_h_close(self.object) } < 0 {
+            cleanup::report(DropError {
+                target: DropTarget::Object,
+                path: None,
+                error: GlusterError::new(get_error()),
+            });
+        }
+    }
+}
+
+// An fd opened with glfs_h_open, closed when the last user drops it
+struct ObjectFd(*mut glfs_fd_t);
+
+unsafe impl Send for ObjectFd {}
+unsafe impl Sync for ObjectFd {}
+
+impl ObjectFd {
+    fn close(mut self) -> Result<(), GlusterError> {
+        let fd = self.0;
+        self.0 = ptr::null_mut();
+        if unsafe { glfs_close(fd) } < 0 {
+            return Err(GlusterError::new(get_error()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ObjectFd {
+    fn drop(&mut self) {
+        if self.0.is_null() {
+            return;
+        }
+        if unsafe { glfs_close(self.0) } < 0 {
+            cleanup::report(DropError {
+                target: DropTarget::File,
+                path: None,
+                error: GlusterError::new(get_error()),
+            });
+        }
+    }
+}
+
+fn check_io(ret: ssize_t) -> Result<usize, GlusterError> {
+    if ret < 0 {
+        return Err(GlusterError::new(get_error()));
+    }
+    Ok(ret as usize)
+}
+
+impl Gluster {
+    fn wrap_object(
+        &self,
+        object: *mut glfs_object,
+        path: Option<&Path>,
+    ) -> Result<GlusterObject<'_>, GlusterError> {
+        if object.is_null() {
+            return Err(GlusterError::new(get_error()));
+        }
+        let mut handle = [0u8; GFAPI_HANDLE_LENGTH];
+        let len = unsafe {
+            glfs_h_extract_handle(object, handle.as_mut_ptr(), GFAPI_HANDLE_LENGTH as c_int)
+        };
+        if len < 0 {
+            let error = GlusterError::new(get_error());
+            unsafe {
+                glfs_h_close(object);
+            }
+            return Err(error);
+        }
+        let gfid = Uuid::from_bytes(&handle)?;
+        Ok(GlusterObject {
+            gluster: self,
+            object,
+            gfid,
+            path: path.map(Path::to_path_buf),
+        })
+    }
+
+    /// Look up path, following symlinks, and return a handle to the file
+    pub fn lookup_object(&self, path: &Path) -> Result<GlusterObject<'_>, GlusterError> {
+        let c_path = self.c_path(path)?;
+        let object = unsafe {
+            let mut found: stat = zeroed();
+            glfs_h_lookupat(
+                self.cluster_handle,
+                ptr::null_mut(),
+                c_path.as_ptr(),
+                &mut found,
+                1,
+            )
+        };
+        self.wrap_object(object, Some(path))
+    }
+
+    /// A handle to the file with this gfid, as returned by
+    /// GlusterObject::gfid.  Fails with ESTALE once the file is gone.
+    pub fn object_from_gfid(&self, gfid: &Uuid) -> Result<GlusterObject<'_>, GlusterError> {
+        let mut handle = *gfid.as_bytes();
+        let object = unsafe {
+            glfs_h_create_from_handle(
+                self.cluster_handle,
+                handle.as_mut_ptr(),
+                GFAPI_HANDLE_LENGTH as c_int,
+                ptr::null_mut(),
+            )
+        };
+        self.wrap_object(object, None)
+    }
+
+    // Open the object for one call when anonymous fds aren't available
+    fn h_open(&self, object: *mut glfs_object, flags: c_int) -> Result<ObjectFd, GlusterError> {
+        let fd = unsafe { glfs_h_open(self.cluster_handle, object, flags) };
+        if fd.is_null() {
+            return Err(GlusterError::new(get_error()));
+        }
+        Ok(ObjectFd(fd))
+    }
+
+    /// Read into buf from offset of the object without opening it.  With
+    /// anonymous_fd_available the server uses an anonymous fd, otherwise
+    /// the object is opened and closed around the read.  Returns the
+    /// number of bytes read, 0 at the end of the file.
+    pub fn h_pread(
+        &self,
+        object: &GlusterObject,
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<usize, GlusterError> {
+        let raw = object.raw_for(self)?;
+        if let Some(anonymous_read) = anonymous_io_fn("glfs_h_anonymous_read") {
+            return check_io(unsafe {
+                anonymous_read(
+                    self.cluster_handle,
+                    raw,
+                    buf.as_mut_ptr() as *const c_void,
+                    buf.len(),
+                    offset as i64,
+                )
+            });
+        }
+        let fd = self.h_open(raw, O_RDONLY)?;
+        pread_fd(&fd, buf, offset)
+    }
+
+    /// Write buf at offset of the object without opening it, the
+    /// counterpart of h_pread.  Returns the number of bytes written.
+    pub fn h_pwrite(
+        &self,
+        object: &GlusterObject,
+        buf: &[u8],
+        offset: u64,
+    ) -> Result<usize, GlusterError> {
+        self.check_writable()?;
+        let raw = object.raw_for(self)?;
+        if let Some(anonymous_write) = anonymous_io_fn("glfs_h_anonymous_write") {
+            return check_io(unsafe {
+                anonymous_write(
+                    self.cluster_handle,
+                    raw,
+                    buf.as_ptr() as *const c_void,
+                    buf.len(),
+                    offset as i64,
+                )
+            });
+        }
+        let fd = self.h_open(raw, O_WRONLY)?;
+        let written = pwrite_fd(&fd, buf, offset)?;
+        // A failed close can lose the write, so it isn't left to Drop
+        fd.close()?;
+        Ok(written)
+    }
+
+    /// A cache of up to capacity fds opened by gfid, for callers doing
+    /// enough handle I/O that opening per call, or the anonymous fd
+    /// lookup on the server, shows up.  See HandleFdCache.
+    pub fn h_fd_cache(&self, capacity: usize) -> HandleFdCache<'_> {
+        HandleFdCache {
+            gluster: self,
+            capacity: capacity.max(1),
+            fds: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+fn pread_fd(fd: &ObjectFd, buf: &mut [u8], offset: u64) -> Result<usize, GlusterError> {
+    check_io(unsafe {
+        glfs_pread(
+            fd.0,
+            buf.as_mut_ptr() as *mut c_void,
+            buf.len(),
+            offset as i64,
+            0,
+        )
+    })
+}
+
+fn pwrite_fd(fd: &ObjectFd, buf: &[u8], offset: u64) -> Result<usize, GlusterError> {
+    check_io(unsafe {
+        glfs_pwrite(
+            fd.0,
+            buf.as_ptr() as *const c_void,
+            buf.len(),
+            offset as i64,
+            0,
+        )
+    })
+}
+
+struct CachedFd {
+    gfid: Uuid,
+    write: bool,
+    fd: Arc<ObjectFd>,
+}
+
+/// Positioned I/O by object handle through fds kept open between calls,
+/// from Gluster::h_fd_cache.  Each gfid gets a read fd and a write fd the
+/// first time it's used, the least recently used is closed once there
+/// are more than capacity.  Any number of threads can share the cache,
+/// an fd evicted while in use is closed when the call using it returns.
+/// The cached fds see writes made any other way, but a file that's
+/// removed stays open until it's evicted or invalidated.
+pub struct HandleFdCache<'a> {
+    gluster: &'a Gluster,
+    capacity: usize,
+    // Most recently used last
+    fds: Mutex<Vec<CachedFd>>,
+}
+
+impl<'a> HandleFdCache<'a> {
+    fn fd(&self, object: &GlusterObject, write: bool) -> Result<Arc<ObjectFd>, GlusterError> {
+        let raw = object.raw_for(self.gluster)?;
+        let gfid = object.gfid();
+        {
+            let mut fds = self.fds.lock().unwrap();
+            if let Some(index) = fds.iter().position(|c| c.gfid == gfid && c.write == write) {
+                let cached = fds.remove(index);
+                let fd = cached.fd.clone();
+                fds.push(cached);
+                return Ok(fd);
+            }
+        }
+        // Open without the lock so a slow open doesn't hold up hits
+        let flags = if write { O_WRONLY } else { O_RDONLY };
+        let fd = Arc::new(self.gluster.h_open(raw, flags)?);
+        let mut fds = self.fds.lock().unwrap();
+        fds.push(CachedFd {
+            gfid,
+            write,
+            fd: fd.clone(),
+        });
+        if fds.len() > self.capacity {
+            fds.remove(0);
+        }
+        Ok(fd)
+    }
+
+    /// Like Gluster::h_pread but through a cached fd
+    pub fn h_pread(
+        &self,
+        object: &GlusterObject,
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<usize, GlusterError> {
+        let fd = self.fd(object, false)?;
+        pread_fd(&fd, buf, offset)
+    }
+
+    /// Like Gluster::h_pwrite but through a cached fd.  The fd is closed
+    /// later, so a close error goes to cleanup::set_drop_error_handler
+    /// rather than back to the writer.  Use Gluster::h_pwrite where that
+    /// matters.
+    pub fn h_pwrite(
+        &self,
+        object: &GlusterObject,
+        buf: &[u8],
+        offset: u64,
+    ) -> Result<usize, GlusterError> {
+        self.gluster.check_writable()?;
+        let fd = self.fd(object, true)?;
+        pwrite_fd(&fd, buf, offset)
+    }
+
+    /// Close any fds cached for gfid
+    pub fn invalidate(&self, gfid: &Uuid) {
+        self.fds.lock().unwrap().retain(|c| c.gfid != *gfid);
+    }
+
+    /// Close every cached fd
+    pub fn clear(&self) {
+        self.fds.lock().unwrap().clear();
+    }
+
+    /// Number of fds open in the cache
+    pub fn len(&self) -> usize {
+        self.fds.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
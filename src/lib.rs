@@ -2,7 +2,79 @@ extern crate errno;
 extern crate libc;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 extern crate uuid;
 
+pub mod acl;
+pub mod attrs;
+pub mod audit;
+pub mod batch;
+pub mod builder;
+pub mod buf_writer;
+pub mod buffer_pool;
+pub mod cache;
+pub mod capacity;
+pub mod cat;
+pub mod checksum;
+pub mod checksum_cache;
+pub mod chunks;
+pub mod cleanup;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod copy;
+pub mod dedupe;
+pub mod delta;
+pub mod dir_stream;
+pub mod download;
+pub mod dry_run;
+pub mod env;
+pub mod file;
+pub mod fingerprint;
 pub mod glfs;
+pub mod glob;
 pub mod gluster;
+pub mod handle;
+pub mod local_fs;
+pub mod local_transfer;
+pub mod lock;
+pub mod log_writer;
+pub mod manifest;
+pub mod memory_fs;
+pub mod metadata;
+pub mod mirror;
+pub mod mode;
+pub mod object_store;
+pub mod parallel_read;
+pub mod path;
+pub mod preserve;
+pub mod readahead;
+pub mod remote_fs;
+pub mod remove;
+pub mod scoped;
+pub mod security;
+pub mod shred;
+pub mod snapshot;
+pub mod space;
+pub mod split;
+pub mod stale;
+pub mod symlink;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tls;
+pub mod trash;
+pub mod tree_image;
+pub mod tree_sync;
+pub mod tree_upload;
+pub mod tuning;
+pub mod upload;
+pub mod url;
+pub mod vectored;
+pub mod volume_set;
+pub mod walk;
+pub mod watchdog;
+pub mod write;
+pub mod xattr;
@@ -0,0 +1,204 @@
+use libc::{c_void, getxattr, listxattr, removexattr, setxattr, ENODATA, ERANGE};
+
+use remote_fs::{
+    RemoteDirEntry, RemoteFileType, RemoteFs, RemoteMetadata, RemoteReadDir, RemoteXattrs,
+};
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::ptr;
+
+/// RemoteFs over a local directory through std::fs, so code written
+/// against RemoteFs can run without a cluster.  "/" is root and paths
+/// can't climb out of it with "..".  Symlinks inside root are followed
+/// like any other, including ones pointing outside it.
+#[derive(Clone, Debug)]
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    /// A LocalFs rooted at root, which should already exist
+    pub fn new<P: AsRef<Path>>(root: P) -> LocalFs {
+        LocalFs {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Where path is on the local filesystem
+    fn local(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut local = self.root.clone();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => local.push(part),
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir | Component::Prefix(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} leaves the root of the LocalFs", path.display()),
+                    ))
+                }
+            }
+        }
+        Ok(local)
+    }
+
+    fn c_path(&self, path: &Path) -> io::Result<CString> {
+        Ok(CString::new(self.local(path)?.as_os_str().as_bytes())?)
+    }
+}
+
+fn file_type(file_type: fs::FileType) -> RemoteFileType {
+    if file_type.is_file() {
+        RemoteFileType::File
+    } else if file_type.is_dir() {
+        RemoteFileType::Dir
+    } else if file_type.is_symlink() {
+        RemoteFileType::Symlink
+    } else {
+        RemoteFileType::Other
+    }
+}
+
+impl RemoteFs for LocalFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.local(path)?)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        fs::write(self.local(path)?, data)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let metadata = fs::metadata(self.local(path)?)?;
+        Ok(RemoteMetadata {
+            file_type: file_type(metadata.file_type()),
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        let entries = fs::read_dir(self.local(path)?)?;
+        Ok(Box::new(entries.map(|entry| {
+            let entry = entry?;
+            Ok(RemoteDirEntry {
+                name: entry.file_name(),
+                file_type: file_type(entry.file_type()?),
+            })
+        })))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(self.local(path)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(self.local(from)?, self.local(to)?)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.local(path)?)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(self.local(path)?)
+    }
+}
+
+impl RemoteXattrs for LocalFs {
+    fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let path = self.c_path(path)?;
+        let name = CString::new(name)?;
+        loop {
+            let size = unsafe { getxattr(path.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+            if size < 0 {
+                let error = io::Error::last_os_error();
+                return match error.raw_os_error() {
+                    Some(ENODATA) => Ok(None),
+                    _ => Err(error),
+                };
+            }
+            let mut value: Vec<u8> = vec![0; size as usize];
+            let len = unsafe {
+                getxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_mut_ptr() as *mut c_void,
+                    value.len(),
+                )
+            };
+            if len >= 0 {
+                value.truncate(len as usize);
+                return Ok(Some(value));
+            }
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                // It grew between the two calls
+                Some(ERANGE) => continue,
+                Some(ENODATA) => return Ok(None),
+                _ => return Err(error),
+            }
+        }
+    }
+
+    fn set_xattr(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = self.c_path(path)?;
+        let name = CString::new(name)?;
+        let ret_code = unsafe {
+            setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            )
+        };
+        if ret_code < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn list_xattrs(&self, path: &Path) -> io::Result<Vec<String>> {
+        let path = self.c_path(path)?;
+        loop {
+            let size = unsafe { listxattr(path.as_ptr(), ptr::null_mut(), 0) };
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut names: Vec<u8> = vec![0; size as usize];
+            let len =
+                unsafe { listxattr(path.as_ptr(), names.as_mut_ptr() as *mut _, names.len()) };
+            if len < 0 {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() == Some(ERANGE) {
+                    continue;
+                }
+                return Err(error);
+            }
+            names.truncate(len as usize);
+            return Ok(names
+                .split(|&b| b == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .collect());
+        }
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &str) -> io::Result<()> {
+        let path = self.c_path(path)?;
+        let name = CString::new(name)?;
+        if unsafe { removexattr(path.as_ptr(), name.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
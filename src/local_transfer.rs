@@ -0,0 +1,317 @@
+use libc::{mode_t, posix_fallocate, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+
+use buffer_pool::BufferPool;
+use checksum::Crc32c;
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use mode::defaults;
+use write::checksum_range;
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Chunk sizes are rounded up to a multiple of this, so every read and
+/// write starts on a boundary of it
+pub const TRANSFER_ALIGNMENT: usize = 4096;
+
+/// Options for Gluster::upload_local_file and download_to_local_file
+#[derive(Clone, Debug)]
+pub struct LocalTransferOptions {
+    chunk_size: usize,
+    sparse: bool,
+    preallocate: bool,
+    verify: bool,
+    max_bytes_per_sec: Option<u64>,
+    mode: mode_t,
+}
+
+impl Default for LocalTransferOptions {
+    fn default() -> LocalTransferOptions {
+        LocalTransferOptions {
+            chunk_size: 4 * 1024 * 1024,
+            sparse: false,
+            preallocate: true,
+            verify: false,
+            max_bytes_per_sec: None,
+            mode: defaults::FILE_0644,
+        }
+    }
+}
+
+impl LocalTransferOptions {
+    pub fn new() -> LocalTransferOptions {
+        LocalTransferOptions::default()
+    }
+
+    /// Size of each pread and pwrite, rounded up to a multiple of
+    /// TRANSFER_ALIGNMENT.  Defaults to 4MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> LocalTransferOptions {
+        self.chunk_size = chunk_size.max(1).div_ceil(TRANSFER_ALIGNMENT) * TRANSFER_ALIGNMENT;
+        self
+    }
+
+    /// Leave holes in the destination where a whole chunk of the source
+    /// is zeros.  The destination isn't preallocated then.  Defaults to
+    /// false.
+    pub fn sparse(mut self, sparse: bool) -> LocalTransferOptions {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Allocate the destination's full length before writing, where the
+    /// filesystem supports it.  Defaults to true.
+    pub fn preallocate(mut self, preallocate: bool) -> LocalTransferOptions {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Once complete, sync the destination and read it back, comparing
+    /// the CRC32C of each chunk with what was sent.  Defaults to false.
+    pub fn verify(mut self, verify: bool) -> LocalTransferOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Limit the average transfer rate.  Defaults to unlimited.
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> LocalTransferOptions {
+        self.max_bytes_per_sec = Some(max_bytes_per_sec.max(1));
+        self
+    }
+
+    /// Permissions of a file created by upload_local_file, before the
+    /// umask.  Defaults to 0644.
+    pub fn mode(mut self, mode: mode_t) -> LocalTransferOptions {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Progress reported to the callback of the _with_progress transfers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransferProgress {
+    /// Bytes of the source dealt with so far, holes included
+    pub transferred: u64,
+    /// Size of the source
+    pub total: u64,
+}
+
+/// What a local transfer did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LocalTransferReport {
+    /// Bytes of the source dealt with, holes included
+    pub transferred: u64,
+    /// Bytes left as holes because sparse was set
+    pub holes: u64,
+    /// Size of the source
+    pub total: u64,
+    /// False if the progress callback stopped the transfer early
+    pub complete: bool,
+}
+
+// The two ends of a transfer, as far as the copy loop cares
+trait Side {
+    fn read_chunk_at(&self, buf: &mut [u8], offset: u64) -> Result<(), GlusterError>;
+    fn write_chunk_at(&self, buf: &[u8], offset: u64) -> Result<(), GlusterError>;
+}
+
+impl Side for File {
+    fn read_chunk_at(&self, buf: &mut [u8], offset: u64) -> Result<(), GlusterError> {
+        Ok(FileExt::read_exact_at(self, buf, offset)?)
+    }
+
+    fn write_chunk_at(&self, buf: &[u8], offset: u64) -> Result<(), GlusterError> {
+        Ok(FileExt::write_all_at(self, buf, offset)?)
+    }
+}
+
+impl<'a> Side for GlusterFile<'a> {
+    fn read_chunk_at(&self, buf: &mut [u8], offset: u64) -> Result<(), GlusterError> {
+        Ok(GlusterFile::read_exact_at(self, buf, offset)?)
+    }
+
+    fn write_chunk_at(&self, buf: &[u8], offset: u64) -> Result<(), GlusterError> {
+        Ok(GlusterFile::write_all_at(self, buf, offset)?)
+    }
+}
+
+// Offset, length and CRC32C of each chunk sent
+type ChunkChecksums = Vec<(u64, usize, u32)>;
+
+// Copy total bytes from source to destination a chunk at a time, at the
+// same offsets on both.  Returns the report and the CRC32C of each chunk
+// sent, for verification.
+fn transfer<F>(
+    source: &dyn Side,
+    destination: &dyn Side,
+    total: u64,
+    opts: &LocalTransferOptions,
+    progress: &mut F,
+) -> Result<(LocalTransferReport, ChunkChecksums), GlusterError>
+where
+    F: FnMut(&TransferProgress) -> bool,
+{
+    let mut buffer = BufferPool::global().get(opts.chunk_size);
+    let mut report = LocalTransferReport {
+        transferred: 0,
+        holes: 0,
+        total,
+        complete: true,
+    };
+    let mut checksums = Vec::new();
+    let start = Instant::now();
+    while report.transferred < total {
+        let offset = report.transferred;
+        let len = (total - offset).min(opts.chunk_size as u64) as usize;
+        let chunk = &mut buffer[..len];
+        source.read_chunk_at(chunk, offset)?;
+        if opts.sparse && chunk.iter().all(|&b| b == 0) {
+            report.holes += len as u64;
+        } else {
+            destination.write_chunk_at(chunk, offset)?;
+        }
+        if opts.verify {
+            checksums.push((offset, len, Crc32c::checksum(chunk)));
+        }
+        report.transferred += len as u64;
+
+        if let Some(rate) = opts.max_bytes_per_sec {
+            let due = Duration::from_secs_f64(report.transferred as f64 / rate as f64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+        let keep_going = progress(&TransferProgress {
+            transferred: report.transferred,
+            total,
+        });
+        if !keep_going && report.transferred < total {
+            report.complete = false;
+            break;
+        }
+    }
+    Ok((report, checksums))
+}
+
+impl Gluster {
+    /// Copy the local file local to dest on the volume.  See
+    /// upload_local_file_with_progress.
+    pub fn upload_local_file(
+        &self,
+        local: &Path,
+        dest: &Path,
+        opts: &LocalTransferOptions,
+    ) -> Result<LocalTransferReport, GlusterError> {
+        self.upload_local_file_with_progress(local, dest, opts, |_| true)
+    }
+
+    /// Copy the local file local to dest on the volume, creating or
+    /// truncating dest.  Both files are accessed with pread and pwrite
+    /// of opts.chunk_size at offsets that are multiples of it, rather
+    /// than through Read and Write.  progress is called after each chunk
+    /// and can return false to stop, leaving dest with what was copied.
+    pub fn upload_local_file_with_progress<F>(
+        &self,
+        local: &Path,
+        dest: &Path,
+        opts: &LocalTransferOptions,
+        mut progress: F,
+    ) -> Result<LocalTransferReport, GlusterError>
+    where
+        F: FnMut(&TransferProgress) -> bool,
+    {
+        let source = File::open(local)?;
+        let total = source.metadata()?.len();
+        let destination = self.create_file(dest, O_CREAT | O_WRONLY | O_TRUNC, opts.mode)?;
+        if opts.preallocate && !opts.sparse && total > 0 {
+            if let Err(e) = self.fallocate(destination.handle()?, 0, 0, total as usize) {
+                trace!("not preallocating {}: {}", dest.display(), e);
+            }
+        }
+        let (report, checksums) = transfer(&source, &destination, total, opts, &mut progress)?;
+        if report.complete {
+            // Covers a trailing hole
+            self.ftruncate(destination.handle()?, total as i64)?;
+            if opts.verify {
+                destination.fdatasync()?;
+                let verifier = self.open_file(dest, O_RDONLY)?;
+                for (offset, len, sent) in checksums {
+                    if checksum_range(&verifier, offset, len)? != sent {
+                        return Err(GlusterError::VerificationFailed {
+                            path: dest.to_path_buf(),
+                            offset,
+                        });
+                    }
+                }
+            }
+        }
+        destination.close()?;
+        Ok(report)
+    }
+
+    /// Copy src on the volume to the local file local.  See
+    /// download_to_local_file_with_progress.
+    pub fn download_to_local_file(
+        &self,
+        src: &Path,
+        local: &Path,
+        opts: &LocalTransferOptions,
+    ) -> Result<LocalTransferReport, GlusterError> {
+        self.download_to_local_file_with_progress(src, local, opts, |_| true)
+    }
+
+    /// Copy src on the volume to the local file local, creating or
+    /// truncating local, the same way upload_local_file_with_progress
+    /// copies the other way.  Unlike download it doesn't resume an
+    /// earlier run.
+    pub fn download_to_local_file_with_progress<F>(
+        &self,
+        src: &Path,
+        local: &Path,
+        opts: &LocalTransferOptions,
+        mut progress: F,
+    ) -> Result<LocalTransferReport, GlusterError>
+    where
+        F: FnMut(&TransferProgress) -> bool,
+    {
+        let source = self.open_file(src, O_RDONLY)?;
+        let total = Metadata::from_stat(source.fstat()?).len();
+        let destination = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(local)?;
+        if opts.preallocate && !opts.sparse && total > 0 {
+            let ret_code = unsafe { posix_fallocate(destination.as_raw_fd(), 0, total as i64) };
+            if ret_code != 0 {
+                trace!("not preallocating {}: errno {}", local.display(), ret_code);
+            }
+        }
+        let (report, checksums) = transfer(&source, &destination, total, opts, &mut progress)?;
+        if report.complete {
+            destination.set_len(total)?;
+            if opts.verify {
+                destination.sync_data()?;
+                let mut buffer = BufferPool::global().get(opts.chunk_size);
+                for (offset, len, sent) in checksums {
+                    let chunk = &mut buffer[..len];
+                    destination.read_exact_at(chunk, offset)?;
+                    if Crc32c::checksum(chunk) != sent {
+                        return Err(GlusterError::VerificationFailed {
+                            path: local.to_path_buf(),
+                            offset,
+                        });
+                    }
+                }
+            }
+        }
+        source.close()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,349 @@
+use errno::{errno, Errno};
+use libc::{c_char, gethostname, EEXIST, ENOENT, O_CREAT, O_EXCL, O_WRONLY};
+
+use cleanup::{self, DropError, DropTarget};
+use gluster::{Gluster, GlusterError};
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static LOCK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for lock_file and try_lock_file
+#[derive(Clone, Debug)]
+pub struct LockOptions {
+    stale_after: Option<Duration>,
+    timeout: Option<Duration>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for LockOptions {
+    fn default() -> LockOptions {
+        LockOptions {
+            stale_after: None,
+            timeout: None,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl LockOptions {
+    pub fn new() -> LockOptions {
+        LockOptions::default()
+    }
+
+    /// A lock acquired longer ago than this is assumed to belong to a
+    /// holder that died and may be broken by a new acquirer.  Defaults to
+    /// None, locks are never considered stale.
+    pub fn stale_after(mut self, stale_after: Duration) -> LockOptions {
+        self.stale_after = Some(stale_after);
+        self
+    }
+
+    /// Give up waiting in lock_file after this long.  Defaults to None,
+    /// wait forever.
+    pub fn timeout(mut self, timeout: Duration) -> LockOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// lock_file sleeps this long after the first failed attempt and
+    /// doubles the sleep after each one up to max.  Defaults to 10ms and
+    /// 1s.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> LockOptions {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+}
+
+/// Who holds a lock, as recorded in the lock file
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockHolder {
+    pub hostname: String,
+    pub pid: u32,
+    pub acquired_at: SystemTime,
+}
+
+impl LockHolder {
+    fn parse(contents: &[u8]) -> Option<LockHolder> {
+        let contents = String::from_utf8_lossy(contents);
+        let mut hostname = None;
+        let mut pid = None;
+        let mut acquired_ms = None;
+        for line in contents.lines() {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("host"), Some(v)) => hostname = Some(v.to_string()),
+                (Some("pid"), Some(v)) => pid = v.parse().ok(),
+                (Some("acquired"), Some(v)) => acquired_ms = v.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(LockHolder {
+            hostname: hostname?,
+            pid: pid?,
+            acquired_at: UNIX_EPOCH + Duration::from_millis(acquired_ms?),
+        })
+    }
+}
+
+fn local_hostname() -> String {
+    let mut buffer = [0 as c_char; 256];
+    let ret_code = unsafe { gethostname(buffer.as_mut_ptr(), buffer.len()) };
+    if ret_code < 0 {
+        return "unknown".to_string();
+    }
+    let bytes: Vec<u8> = buffer
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// A unique suffix for tokens and scratch file names
+fn unique_suffix() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!(
+        "{}-{}-{}",
+        process::id(),
+        LOCK_COUNTER.fetch_add(1, Ordering::SeqCst),
+        nanos
+    )
+}
+
+/// A held lock file.  The lock is released when this is dropped, but only
+/// if the file still holds our contents.  If the lock was broken as stale
+/// and someone else now holds it, their lock is left alone.
+#[derive(Debug)]
+pub struct FileLockGuard<'a> {
+    gluster: &'a Gluster,
+    path: PathBuf,
+    contents: Vec<u8>,
+    released: bool,
+}
+
+impl<'a> FileLockGuard<'a> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check that the lock file still holds our contents.  False means
+    /// another acquirer broke the lock as stale.
+    pub fn is_held(&self) -> Result<bool, GlusterError> {
+        match self.gluster.read_to_vec(&self.path) {
+            Ok(contents) => Ok(contents == self.contents),
+            Err(e) => {
+                if errno() == Errno(ENOENT) {
+                    return Ok(false);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Release the lock and report any error.  Dropping the guard also
+    /// releases it but errors are only logged.
+    pub fn release(mut self) -> Result<(), GlusterError> {
+        self.released = true;
+        self.unlock()
+    }
+
+    fn unlock(&self) -> Result<(), GlusterError> {
+        // There's a window between this check and the unlink where a new
+        // acquirer could break our lock.  That only happens if we held it
+        // past stale_after, which the caller has already agreed is broken.
+        if !self.is_held()? {
+            warn!(
+                "Lock {} was taken over by another holder, leaving it alone",
+                self.path.display()
+            );
+            return Ok(());
+        }
+        self.gluster.unlink(&self.path)
+    }
+}
+
+impl<'a> Drop for FileLockGuard<'a> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Err(error) = self.unlock() {
+            cleanup::report(DropError {
+                target: DropTarget::LockFile,
+                path: Some(self.path.clone()),
+                error,
+            });
+        }
+    }
+}
+
+impl Gluster {
+    /// Try once to take the lock file at path.  Returns None if someone
+    /// else holds it.  The lock is taken by creating the file with
+    /// O_CREAT | O_EXCL and writing the holder's hostname, pid and the
+    /// time into it.  A lock older than opts.stale_after is broken first.
+    pub fn try_lock_file(
+        &self,
+        path: &Path,
+        opts: &LockOptions,
+    ) -> Result<Option<FileLockGuard<'_>>, GlusterError> {
+        let contents = format!(
+            "host={}\npid={}\nacquired={}\ntoken={}\n",
+            local_hostname(),
+            process::id(),
+            millis_since_epoch(SystemTime::now()),
+            unique_suffix()
+        ).into_bytes();
+        match self.create_file(path, O_CREAT | O_EXCL | O_WRONLY, 0o644) {
+            Ok(mut file) => {
+                let written = file.write_all(&contents)
+                    .map_err(GlusterError::from)
+                    .and_then(|_| file.close());
+                if let Err(e) = written {
+                    let _ = self.unlink(path);
+                    return Err(e);
+                }
+                Ok(Some(FileLockGuard {
+                    gluster: self,
+                    path: path.to_path_buf(),
+                    contents,
+                    released: false,
+                }))
+            }
+            Err(e) => {
+                if errno() != Errno(EEXIST) {
+                    return Err(e);
+                }
+                if let Some(stale_after) = opts.stale_after {
+                    if self.break_stale_lock(path, stale_after)? {
+                        // Don't loop here, the next attempt decides
+                        // between competing breakers
+                        return self.try_lock_file(path, &LockOptions::default());
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Take the lock file at path, sleeping with exponential backoff
+    /// while someone else holds it.  Fails once opts.timeout has passed.
+    pub fn lock_file(
+        &self,
+        path: &Path,
+        opts: &LockOptions,
+    ) -> Result<FileLockGuard<'_>, GlusterError> {
+        let start = Instant::now();
+        let mut backoff = opts.initial_backoff;
+        loop {
+            if let Some(guard) = self.try_lock_file(path, opts)? {
+                return Ok(guard);
+            }
+            let mut sleep = backoff;
+            if let Some(timeout) = opts.timeout {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(GlusterError::new(format!(
+                        "timed out waiting for lock {}",
+                        path.display()
+                    )));
+                }
+                sleep = sleep.min(timeout - elapsed);
+            }
+            thread::sleep(sleep);
+            backoff = (backoff * 2).min(opts.max_backoff);
+        }
+    }
+
+    /// Read who currently holds the lock file at path, or None if it
+    /// isn't held or the file can't be parsed.
+    pub fn lock_holder(&self, path: &Path) -> Result<Option<LockHolder>, GlusterError> {
+        match self.read_to_vec(path) {
+            Ok(contents) => Ok(LockHolder::parse(&contents)),
+            Err(e) => {
+                if errno() == Errno(ENOENT) {
+                    return Ok(None);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // Move a stale lock out of the way.  Returns true if the lock at path
+    // is gone, whether we removed it or someone else did.
+    fn break_stale_lock(&self, path: &Path, stale_after: Duration) -> Result<bool, GlusterError> {
+        let contents = match self.read_to_vec(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                if errno() == Errno(ENOENT) {
+                    return Ok(true);
+                }
+                return Err(e);
+            }
+        };
+        let acquired_at = match LockHolder::parse(&contents) {
+            Some(holder) => holder.acquired_at,
+            // The holder may still be writing it, go by the mtime instead
+            None => {
+                let metadata = self.metadata(path)?;
+                UNIX_EPOCH + Duration::from_secs(metadata.mtime().max(0) as u64)
+            }
+        };
+        let age = SystemTime::now()
+            .duration_since(acquired_at)
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        if age < stale_after {
+            return Ok(false);
+        }
+
+        // Rename is atomic so only one breaker gets the file.  Whoever does
+        // checks it's still the stale lock they looked at, and puts it back
+        // if a fresh lock slipped in between the read and the rename.
+        let mut scratch_name = path.file_name().unwrap_or_default().to_os_string();
+        scratch_name.push(format!(".stale-{}", unique_suffix()));
+        let scratch = path.with_file_name(scratch_name);
+        if let Err(e) = self.rename(path, &scratch) {
+            if errno() == Errno(ENOENT) {
+                return Ok(true);
+            }
+            return Err(e);
+        }
+        let moved = self.read_to_vec(&scratch)?;
+        if moved != contents {
+            // link fails if yet another lock was taken in the meantime
+            if self.link(&scratch, path).is_err() {
+                warn!(
+                    "Unable to restore lock {} after breaking it by mistake",
+                    path.display()
+                );
+            }
+            self.unlink(&scratch)?;
+            return Ok(false);
+        }
+        warn!(
+            "Broke stale lock {} held since {:?}",
+            path.display(),
+            acquired_at
+        );
+        self.unlink(&scratch)?;
+        Ok(true)
+    }
+}
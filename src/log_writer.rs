@@ -0,0 +1,257 @@
+use errno::{errno, Errno};
+use libc::{EEXIST, ENOENT, O_APPEND, O_CREAT, O_EXCL, O_WRONLY};
+
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+use lock::LockOptions;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Size limits and framing for RotatingLogWriter
+#[derive(Clone, Debug)]
+pub struct LogOptions {
+    max_segment_bytes: u64,
+    max_segments: usize,
+    framed: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> LogOptions {
+        LogOptions {
+            max_segment_bytes: 64 * 1024 * 1024,
+            max_segments: 10,
+            framed: false,
+        }
+    }
+}
+
+impl LogOptions {
+    pub fn new() -> LogOptions {
+        LogOptions::default()
+    }
+
+    /// Start a new segment once the current one would grow past this.
+    /// A single record bigger than this still gets a segment to itself.
+    /// Defaults to 64MB.
+    pub fn max_segment_bytes(mut self, max_segment_bytes: u64) -> LogOptions {
+        self.max_segment_bytes = max_segment_bytes;
+        self
+    }
+
+    /// Number of segments kept including the one being written.  Older
+    /// segments are deleted during rotation.  Defaults to 10.
+    pub fn max_segments(mut self, max_segments: usize) -> LogOptions {
+        self.max_segments = max_segments.max(1);
+        self
+    }
+
+    /// Prefix each record with its length as a 4 byte big endian integer
+    /// so a record cut short by a crash can be told apart from a
+    /// complete one.  See decode_records.  Defaults to false.
+    pub fn framed(mut self, framed: bool) -> LogOptions {
+        self.framed = framed;
+        self
+    }
+}
+
+/// The records found in a framed log segment
+#[derive(Clone, Debug, PartialEq)]
+pub struct FramedRecords {
+    pub records: Vec<Vec<u8>>,
+    /// Bytes at the end of the segment that don't make up a whole record
+    pub trailing_bytes: usize,
+}
+
+/// Split the contents of a segment written with LogOptions::framed into
+/// records
+pub fn decode_records(data: &[u8]) -> FramedRecords {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while data.len() - offset >= 4 {
+        let len = ((data[offset] as usize) << 24) | ((data[offset + 1] as usize) << 16)
+            | ((data[offset + 2] as usize) << 8) | data[offset + 3] as usize;
+        if data.len() - offset - 4 < len {
+            break;
+        }
+        records.push(data[offset + 4..offset + 4 + len].to_vec());
+        offset += 4 + len;
+    }
+    FramedRecords {
+        records,
+        trailing_bytes: data.len() - offset,
+    }
+}
+
+/// An append only log on the volume split into segments by size.  Records
+/// are appended to base.  When it fills up base is renamed to base.1,
+/// base.1 to base.2 and so on, and the oldest segment past max_segments
+/// is dropped.  Several processes may append to the same log.  Rotation
+/// is done under a lock file next to base (see Gluster::lock_file), and a
+/// writer that finds somebody else already rotated just carries on in the
+/// fresh segment.
+pub struct RotatingLogWriter<'a> {
+    gluster: &'a Gluster,
+    base: PathBuf,
+    opts: LogOptions,
+    file: Option<GlusterFile<'a>>,
+    size: u64,
+}
+
+impl<'a> RotatingLogWriter<'a> {
+    /// Open the log at base, creating it if needed
+    pub fn new(
+        gluster: &'a Gluster,
+        base: &Path,
+        opts: LogOptions,
+    ) -> Result<RotatingLogWriter<'a>, GlusterError> {
+        let mut writer = RotatingLogWriter {
+            gluster,
+            base: base.to_path_buf(),
+            opts,
+            file: None,
+            size: 0,
+        };
+        writer.open_segment()?;
+        Ok(writer)
+    }
+
+    /// Path of segment n.  0 is the one being written, higher numbers are
+    /// older.
+    pub fn segment_path(&self, n: usize) -> PathBuf {
+        if n == 0 {
+            return self.base.clone();
+        }
+        let mut name = self.base.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", n));
+        self.base.with_file_name(name)
+    }
+
+    /// Paths of the segments that currently exist, oldest first
+    pub fn segments(&self) -> Result<Vec<PathBuf>, GlusterError> {
+        let mut segments = Vec::new();
+        for n in (0..self.opts.max_segments).rev() {
+            let path = self.segment_path(n);
+            if self.gluster.exists(&path)? {
+                segments.push(path);
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Append one record, rotating first if it would push the current
+    /// segment past max_segment_bytes
+    pub fn append(&mut self, record: &[u8]) -> Result<(), GlusterError> {
+        let mut buffer = Vec::with_capacity(record.len() + 4);
+        if self.opts.framed {
+            let len = record.len() as u32;
+            buffer.extend_from_slice(&[
+                (len >> 24) as u8,
+                (len >> 16) as u8,
+                (len >> 8) as u8,
+                len as u8,
+            ]);
+        }
+        buffer.extend_from_slice(record);
+        if self.size > 0 && self.size + buffer.len() as u64 > self.opts.max_segment_bytes {
+            self.rotate()?;
+        }
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => return Err(GlusterError::new("log segment is not open".to_string())),
+        };
+        // One write so records from different writers don't interleave
+        let written = file.write(&buffer)?;
+        if written != buffer.len() {
+            return Err(GlusterError::new(format!(
+                "short append to {}: {} of {} bytes",
+                self.base.display(),
+                written,
+                buffer.len()
+            )));
+        }
+        self.size = file.fstat()?.st_size as u64;
+        Ok(())
+    }
+
+    /// Flush the current segment to stable storage
+    pub fn sync(&self) -> Result<(), GlusterError> {
+        match self.file {
+            Some(ref file) => file.fdatasync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Start a new segment now regardless of the size of the current one
+    pub fn rotate(&mut self) -> Result<(), GlusterError> {
+        if let Some(file) = self.file.take() {
+            file.close()?;
+        }
+        let mut lock_name = self.base.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+        let lock_path = self.base.with_file_name(lock_name);
+        let lock_opts = LockOptions::new().stale_after(Duration::from_secs(60));
+        let guard = self.gluster.lock_file(&lock_path, &lock_opts)?;
+
+        // Another writer may have rotated while we waited for the lock
+        let current_size = match self.gluster.metadata(&self.base) {
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) => {
+                if errno() != Errno(ENOENT) {
+                    return Err(e);
+                }
+                None
+            }
+        };
+        if current_size.is_some_and(|size| size >= self.size && size > 0) {
+            self.shift_segments()?;
+        }
+        guard.release()?;
+        self.open_segment()
+    }
+
+    fn shift_segments(&self) -> Result<(), GlusterError> {
+        let last = self.opts.max_segments - 1;
+        if last == 0 {
+            return self.gluster.unlink(&self.base);
+        }
+        // Renames onto the oldest slot replace it, which is the pruning
+        for n in (0..last).rev() {
+            if let Err(e) = self.gluster
+                .rename(&self.segment_path(n), &self.segment_path(n + 1))
+            {
+                if errno() != Errno(ENOENT) {
+                    return Err(e);
+                }
+            }
+        }
+        // Clean up segments left over from a larger max_segments
+        let mut n = last + 1;
+        while self.gluster.exists(&self.segment_path(n))? {
+            self.gluster.unlink(&self.segment_path(n))?;
+            n += 1;
+        }
+        Ok(())
+    }
+
+    fn open_segment(&mut self) -> Result<(), GlusterError> {
+        let file = match self.gluster.create_file(
+            &self.base,
+            O_CREAT | O_EXCL | O_WRONLY | O_APPEND,
+            0o644,
+        ) {
+            Ok(file) => file,
+            Err(e) => {
+                if errno() != Errno(EEXIST) {
+                    return Err(e);
+                }
+                // Someone else created it first, append to theirs
+                self.gluster.open_file(&self.base, O_WRONLY | O_APPEND)?
+            }
+        };
+        self.size = file.fstat()?.st_size as u64;
+        self.file = Some(file);
+        Ok(())
+    }
+}
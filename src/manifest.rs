@@ -0,0 +1,396 @@
+use libc::O_RDONLY;
+
+use batch::parallel_map;
+use checksum::ChecksumAlgorithm;
+use checksum_cache::{from_hex, to_hex};
+use gluster::{Gluster, GlusterError};
+use walk::WalkOptions;
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// First line of Manifest::to_bytes
+const FORMAT_HEADER: &[u8] = b"gfapi-manifest v1";
+
+/// One file of a Manifest
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ManifestEntry {
+    /// Relative to the root the manifest was made of
+    pub path: PathBuf,
+    pub size: u64,
+    /// Seconds since the epoch
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    /// Lowercase hex SHA-256 of the contents
+    pub sha256: String,
+}
+
+/// Every regular file under a root with its size, mtime and SHA-256,
+/// sorted by path
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+/// What differs between two manifests, each list sorted by path
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// In the other manifest only
+    pub added: Vec<PathBuf>,
+    /// In this manifest only
+    pub removed: Vec<PathBuf>,
+    /// In both with a different size, mtime or digest
+    pub changed: Vec<PathBuf>,
+}
+
+impl ManifestDiff {
+    /// True when the manifests list the same files with the same
+    /// contents
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Manifest {
+    /// A manifest of entries, in whatever order they're given.  Of
+    /// entries with the same path the last one is kept.
+    pub fn new(mut entries: Vec<ManifestEntry>) -> Manifest {
+        // Stable, so the last of each path ends up last among them
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut deduped: Vec<ManifestEntry> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match deduped.last_mut() {
+                Some(last) if last.path == entry.path => *last = entry,
+                _ => deduped.push(entry),
+            }
+        }
+        Manifest { entries: deduped }
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// The entry for path, relative to the manifest's root
+    pub fn get(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(path))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The manifest as text, one line per entry:
+    ///
+    /// <size> <mtime seconds>.<nanoseconds> <hex sha256> <path>
+    ///
+    /// after a gfapi-manifest v1 header line.  Backslashes and newlines
+    /// in paths are escaped as \\ and \n, and paths needn't be UTF-8.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FORMAT_HEADER.to_vec();
+        bytes.push(b'\n');
+        for entry in &self.entries {
+            bytes.extend_from_slice(
+                format!(
+                    "{} {}.{:09} {} ",
+                    entry.size, entry.mtime, entry.mtime_nsec, entry.sha256
+                )
+                .as_bytes(),
+            );
+            for &b in entry.path.as_os_str().as_bytes() {
+                match b {
+                    b'\\' => bytes.extend_from_slice(b"\\\\"),
+                    b'\n' => bytes.extend_from_slice(b"\\n"),
+                    b => bytes.push(b),
+                }
+            }
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    /// Parse what to_bytes produced.  Anything malformed is None.
+    pub fn parse(bytes: &[u8]) -> Option<Manifest> {
+        let mut lines = bytes.split(|&b| b == b'\n');
+        if lines.next()? != FORMAT_HEADER {
+            return None;
+        }
+        let mut entries = Vec::new();
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+            entries.push(parse_entry(line)?);
+        }
+        Some(Manifest::new(entries))
+    }
+
+    /// What changed going from this manifest to other
+    pub fn diff(&self, other: &Manifest) -> ManifestDiff {
+        let mut diff = ManifestDiff::default();
+        let mut ours = self.entries.iter().peekable();
+        let mut theirs = other.entries.iter().peekable();
+        loop {
+            match (ours.peek(), theirs.peek()) {
+                (None, None) => break,
+                (Some(a), None) => {
+                    diff.removed.push(a.path.clone());
+                    ours.next();
+                }
+                (None, Some(b)) => {
+                    diff.added.push(b.path.clone());
+                    theirs.next();
+                }
+                (Some(a), Some(b)) => {
+                    if a.path < b.path {
+                        diff.removed.push(a.path.clone());
+                        ours.next();
+                    } else if a.path > b.path {
+                        diff.added.push(b.path.clone());
+                        theirs.next();
+                    } else {
+                        if a != b {
+                            diff.changed.push(a.path.clone());
+                        }
+                        ours.next();
+                        theirs.next();
+                    }
+                }
+            }
+        }
+        diff
+    }
+}
+
+fn parse_entry(line: &[u8]) -> Option<ManifestEntry> {
+    let mut fields = line.splitn(4, |&b| b == b' ');
+    let size = str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let mut mtime = str::from_utf8(fields.next()?).ok()?.splitn(2, '.');
+    let seconds = mtime.next()?.parse().ok()?;
+    let nanoseconds = mtime.next()?;
+    if nanoseconds.len() != 9 || !nanoseconds.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let sha256 = str::from_utf8(fields.next()?).ok()?;
+    if from_hex(sha256)?.len() != 32 {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut escaped = false;
+    for &b in fields.next()? {
+        match (escaped, b) {
+            (false, b'\\') => escaped = true,
+            (false, b) => path.push(b),
+            (true, b'\\') => {
+                path.push(b'\\');
+                escaped = false;
+            }
+            (true, b'n') => {
+                path.push(b'\n');
+                escaped = false;
+            }
+            (true, _) => return None,
+        }
+    }
+    if escaped || path.is_empty() {
+        return None;
+    }
+    Some(ManifestEntry {
+        path: PathBuf::from(OsStr::from_bytes(&path)),
+        size,
+        mtime: seconds,
+        mtime_nsec: nanoseconds.parse().ok()?,
+        sha256: sha256.to_string(),
+    })
+}
+
+/// A Manifest of every regular file under the local directory root,
+/// checksummed by up to workers threads.  Symlinks aren't followed and,
+/// like other special files, are left out.
+pub fn local_manifest(root: &Path, workers: usize) -> Result<Manifest, GlusterError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    let results = parallel_map(&files, workers.max(1), |path| {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let digest = ChecksumAlgorithm::Sha256.digest_reader(&mut file)?;
+        Ok(ManifestEntry {
+            // Everything found is under root
+            path: path.strip_prefix(root).unwrap_or(path).to_path_buf(),
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            sha256: to_hex(&digest),
+        })
+    });
+    let entries = results
+        .into_iter()
+        .collect::<Result<Vec<ManifestEntry>, GlusterError>>()?;
+    Ok(Manifest::new(entries))
+}
+
+/// Progress reported to ManifestOptions::on_progress
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ManifestProgress {
+    /// Files checksummed so far
+    pub files: u64,
+    /// Bytes checksummed so far
+    pub bytes: u64,
+    /// Files found by the walk, all of which are checksummed
+    pub total_files: u64,
+}
+
+type ProgressHandler = Arc<dyn Fn(&ManifestProgress) + Send + Sync>;
+
+/// Options for Gluster::manifest
+#[derive(Clone)]
+pub struct ManifestOptions {
+    walk: WalkOptions,
+    pub(crate) workers: usize,
+    use_checksum_cache: bool,
+    on_progress: Option<ProgressHandler>,
+}
+
+impl Default for ManifestOptions {
+    fn default() -> ManifestOptions {
+        ManifestOptions {
+            walk: WalkOptions::default(),
+            workers: 4,
+            use_checksum_cache: false,
+            on_progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for ManifestOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ManifestOptions")
+            .field("walk", &self.walk)
+            .field("workers", &self.workers)
+            .field("use_checksum_cache", &self.use_checksum_cache)
+            .field("on_progress", &self.on_progress.is_some())
+            .finish()
+    }
+}
+
+impl ManifestOptions {
+    pub fn new() -> ManifestOptions {
+        ManifestOptions::default()
+    }
+
+    /// How the tree is walked, with par_walk.  Any error the walk
+    /// returns fails the manifest, so WalkErrorPolicy::Ignore is the way
+    /// to leave out what can't be listed.
+    pub fn walk_options(mut self, walk: WalkOptions) -> ManifestOptions {
+        self.walk = walk;
+        self
+    }
+
+    /// Number of files checksummed at once.  Defaults to 4.
+    pub fn workers(mut self, workers: usize) -> ManifestOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Take digests from Gluster::checksum_cached, reusing and updating
+    /// the xattr cache, instead of always reading files through.
+    /// Defaults to false.
+    pub fn use_checksum_cache(mut self, use_cache: bool) -> ManifestOptions {
+        self.use_checksum_cache = use_cache;
+        self
+    }
+
+    /// Call handler after each file is checksummed.  It can be called
+    /// from several threads at once.
+    pub fn on_progress<F>(mut self, handler: F) -> ManifestOptions
+    where
+        F: Fn(&ManifestProgress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(handler));
+        self
+    }
+}
+
+impl Gluster {
+    /// A Manifest of every regular file under root, found with par_walk
+    /// and checksummed by up to opts.workers threads.  Symlinks and other
+    /// special files are left out.  The size and mtime are the ones the
+    /// walk saw.  The first error, from the walk or a checksum, fails
+    /// the whole manifest.
+    pub fn manifest(&self, root: &Path, opts: &ManifestOptions) -> Result<Manifest, GlusterError> {
+        let files = self.par_walk(root, &opts.walk, |walk| {
+            let mut files = Vec::new();
+            for entry in walk {
+                let entry = entry?;
+                if entry.metadata.is_file() {
+                    files.push(entry);
+                }
+            }
+            Ok::<_, GlusterError>(files)
+        })?;
+        let total_files = files.len() as u64;
+        let done_files = AtomicU64::new(0);
+        let done_bytes = AtomicU64::new(0);
+        let results = parallel_map(&files, opts.workers, |entry| {
+            let digest = if opts.use_checksum_cache {
+                self.checksum_cached(&entry.path, ChecksumAlgorithm::Sha256)?
+            } else {
+                let mut file = self.open_file(&entry.path, O_RDONLY)?;
+                let digest = ChecksumAlgorithm::Sha256.digest_reader(&mut file)?;
+                file.close()?;
+                digest
+            };
+            let size = entry.metadata.len();
+            if let Some(ref handler) = opts.on_progress {
+                handler(&ManifestProgress {
+                    files: done_files.fetch_add(1, Ordering::SeqCst) + 1,
+                    bytes: done_bytes.fetch_add(size, Ordering::SeqCst) + size,
+                    total_files,
+                });
+            }
+            Ok(ManifestEntry {
+                // Everything the walk finds is under root
+                path: entry
+                    .path
+                    .strip_prefix(root)
+                    .unwrap_or(&entry.path)
+                    .to_path_buf(),
+                size,
+                mtime: entry.metadata.mtime(),
+                mtime_nsec: entry.metadata.mtime_nsec(),
+                sha256: to_hex(&digest),
+            })
+        });
+        let entries = results
+            .into_iter()
+            .collect::<Result<Vec<ManifestEntry>, GlusterError>>()?;
+        Ok(Manifest::new(entries))
+    }
+}
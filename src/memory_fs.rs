@@ -0,0 +1,282 @@
+use libc::{EBUSY, EEXIST, EINVAL, EISDIR, ENODATA, ENOENT, ENOTDIR, ENOTEMPTY};
+
+use remote_fs::{
+    RemoteDirEntry, RemoteFileType, RemoteFs, RemoteMetadata, RemoteReadDir, RemoteXattrs,
+};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+#[derive(Clone, Debug)]
+struct Node {
+    // None for directories
+    data: Option<Vec<u8>>,
+    modified: SystemTime,
+    xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Node {
+    fn dir() -> Node {
+        Node {
+            data: None,
+            modified: SystemTime::now(),
+            xattrs: BTreeMap::new(),
+        }
+    }
+}
+
+/// An in-memory RemoteFs for tests of code written against RemoteFs.
+/// It holds files and directories only and fails with the same errnos a
+/// POSIX filesystem would, so error handling can be tested too.
+#[derive(Debug)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl Default for MemoryFs {
+    fn default() -> MemoryFs {
+        MemoryFs::new()
+    }
+}
+
+fn os_error(code: i32) -> io::Error {
+    io::Error::from_raw_os_error(code)
+}
+
+// path as a key, "/" followed by its normal components
+fn key(path: &Path) -> io::Result<PathBuf> {
+    let mut key = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => key.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} isn't a normalized path", path.display()),
+                ))
+            }
+        }
+    }
+    Ok(key)
+}
+
+// The parent of key, if it has one, has to be an existing directory
+fn check_parent(nodes: &BTreeMap<PathBuf, Node>, key: &Path) -> io::Result<()> {
+    match key.parent().map(|parent| nodes.get(parent)) {
+        None => Ok(()),
+        Some(None) => Err(os_error(ENOENT)),
+        Some(Some(node)) if node.data.is_some() => Err(os_error(ENOTDIR)),
+        Some(Some(_)) => Ok(()),
+    }
+}
+
+fn has_children(nodes: &BTreeMap<PathBuf, Node>, key: &Path) -> bool {
+    nodes
+        .range(key.to_path_buf()..)
+        .nth(1)
+        .is_some_and(|(child, _)| child.starts_with(key))
+}
+
+impl MemoryFs {
+    /// An empty filesystem with just the root directory
+    pub fn new() -> MemoryFs {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::dir());
+        MemoryFs {
+            nodes: Mutex::new(nodes),
+        }
+    }
+
+    fn nodes(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        match self.nodes.lock() {
+            Ok(nodes) => nodes,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn with_node<T, F>(&self, path: &Path, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Node) -> io::Result<T>,
+    {
+        let key = key(path)?;
+        match self.nodes().get_mut(&key) {
+            Some(node) => f(node),
+            None => Err(os_error(ENOENT)),
+        }
+    }
+}
+
+impl RemoteFs for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.with_node(path, |node| match node.data {
+            Some(ref data) => Ok(data.clone()),
+            None => Err(os_error(EISDIR)),
+        })
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let key = key(path)?;
+        let mut nodes = self.nodes();
+        check_parent(&nodes, &key)?;
+        let node = nodes.entry(key).or_insert_with(|| Node {
+            data: Some(Vec::new()),
+            modified: SystemTime::now(),
+            xattrs: BTreeMap::new(),
+        });
+        if node.data.is_none() {
+            return Err(os_error(EISDIR));
+        }
+        node.data = Some(data.to_vec());
+        node.modified = SystemTime::now();
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        self.with_node(path, |node| {
+            Ok(RemoteMetadata {
+                file_type: match node.data {
+                    Some(_) => RemoteFileType::File,
+                    None => RemoteFileType::Dir,
+                },
+                len: node.data.as_ref().map_or(0, |data| data.len() as u64),
+                modified: node.modified,
+            })
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        let key = key(path)?;
+        let nodes = self.nodes();
+        match nodes.get(&key) {
+            None => return Err(os_error(ENOENT)),
+            Some(node) if node.data.is_some() => return Err(os_error(ENOTDIR)),
+            Some(_) => {}
+        }
+        let entries: Vec<io::Result<RemoteDirEntry>> = nodes
+            .range(key.clone()..)
+            .skip(1)
+            .take_while(|&(child, _)| child.starts_with(&key))
+            .filter(|&(child, _)| child.parent() == Some(&key))
+            .map(|(child, node)| {
+                Ok(RemoteDirEntry {
+                    // Every key but the root has a file name
+                    name: child.file_name().unwrap().to_os_string(),
+                    file_type: match node.data {
+                        Some(_) => RemoteFileType::File,
+                        None => RemoteFileType::Dir,
+                    },
+                })
+            })
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let key = key(path)?;
+        let mut nodes = self.nodes();
+        if nodes.contains_key(&key) {
+            return Err(os_error(EEXIST));
+        }
+        check_parent(&nodes, &key)?;
+        nodes.insert(key, Node::dir());
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let from = key(from)?;
+        let to = key(to)?;
+        let mut nodes = self.nodes();
+        let from_is_dir = match nodes.get(&from) {
+            None => return Err(os_error(ENOENT)),
+            Some(node) => node.data.is_none(),
+        };
+        if from == to {
+            return Ok(());
+        }
+        if from.parent().is_none() || to.starts_with(&from) {
+            return Err(os_error(EINVAL));
+        }
+        check_parent(&nodes, &to)?;
+        match nodes.get(&to).map(|node| node.data.is_none()) {
+            Some(true) if !from_is_dir => return Err(os_error(EISDIR)),
+            Some(false) if from_is_dir => return Err(os_error(ENOTDIR)),
+            Some(true) if has_children(&nodes, &to) => return Err(os_error(ENOTEMPTY)),
+            _ => {}
+        }
+        nodes.remove(&to);
+        let moved: Vec<PathBuf> = nodes
+            .range(from.clone()..)
+            .take_while(|&(path, _)| path.starts_with(&from))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in moved {
+            if let Some(node) = nodes.remove(&path) {
+                // Every moved path starts with from
+                let rest = path.strip_prefix(&from).unwrap();
+                let moved_to = if rest.as_os_str().is_empty() {
+                    to.clone()
+                } else {
+                    to.join(rest)
+                };
+                nodes.insert(moved_to, node);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let key = key(path)?;
+        let mut nodes = self.nodes();
+        match nodes.get(&key) {
+            None => Err(os_error(ENOENT)),
+            Some(node) if node.data.is_none() => Err(os_error(EISDIR)),
+            Some(_) => {
+                nodes.remove(&key);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let key = key(path)?;
+        let mut nodes = self.nodes();
+        match nodes.get(&key) {
+            None => Err(os_error(ENOENT)),
+            Some(node) if node.data.is_some() => Err(os_error(ENOTDIR)),
+            Some(_) if key.parent().is_none() => Err(os_error(EBUSY)),
+            Some(_) if has_children(&nodes, &key) => Err(os_error(ENOTEMPTY)),
+            Some(_) => {
+                nodes.remove(&key);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl RemoteXattrs for MemoryFs {
+    fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        self.with_node(path, |node| Ok(node.xattrs.get(name).cloned()))
+    }
+
+    fn set_xattr(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        self.with_node(path, |node| {
+            node.xattrs.insert(name.to_string(), value.to_vec());
+            Ok(())
+        })
+    }
+
+    fn list_xattrs(&self, path: &Path) -> io::Result<Vec<String>> {
+        self.with_node(path, |node| Ok(node.xattrs.keys().cloned().collect()))
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &str) -> io::Result<()> {
+        self.with_node(path, |node| match node.xattrs.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(os_error(ENODATA)),
+        })
+    }
+}
@@ -0,0 +1,217 @@
+use libc::{c_uchar, gid_t, mode_t, stat, uid_t, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG,
+           DT_SOCK, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG, S_IFSOCK};
+
+use gluster::{Gluster, GlusterError};
+
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The kind of thing a directory entry or stat refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
+}
+
+impl FileType {
+    /// The type held in the S_IFMT bits of st_mode
+    pub fn from_mode(mode: mode_t) -> Option<FileType> {
+        match mode & S_IFMT {
+            S_IFREG => Some(FileType::File),
+            S_IFDIR => Some(FileType::Dir),
+            S_IFLNK => Some(FileType::Symlink),
+            S_IFBLK => Some(FileType::BlockDevice),
+            S_IFCHR => Some(FileType::CharDevice),
+            S_IFIFO => Some(FileType::Fifo),
+            S_IFSOCK => Some(FileType::Socket),
+            _ => None,
+        }
+    }
+
+    /// The type from a dirent's d_type.  None for DT_UNKNOWN, which some
+    /// bricks return, in which case only a stat can tell.
+    pub fn from_d_type(d_type: c_uchar) -> Option<FileType> {
+        match d_type {
+            DT_REG => Some(FileType::File),
+            DT_DIR => Some(FileType::Dir),
+            DT_LNK => Some(FileType::Symlink),
+            DT_BLK => Some(FileType::BlockDevice),
+            DT_CHR => Some(FileType::CharDevice),
+            DT_FIFO => Some(FileType::Fifo),
+            DT_SOCK => Some(FileType::Socket),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata about a file, as returned by stat.  This is a thin wrapper
+/// around libc::stat with convenience accessors.
+#[derive(Clone, Copy)]
+pub struct Metadata {
+    stat: stat,
+}
+
+impl Metadata {
+    pub fn from_stat(stat: stat) -> Metadata {
+        Metadata { stat }
+    }
+
+    /// The underlying stat structure
+    pub fn as_stat(&self) -> &stat {
+        &self.stat
+    }
+
+    /// Size of the file in bytes
+    pub fn len(&self) -> u64 {
+        self.stat.st_size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.stat.st_mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.stat.st_mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.stat.st_mode & S_IFMT == S_IFLNK
+    }
+
+    pub fn file_type(&self) -> Option<FileType> {
+        FileType::from_mode(self.stat.st_mode)
+    }
+
+    /// The full st_mode including the file type bits
+    pub fn mode(&self) -> mode_t {
+        self.stat.st_mode
+    }
+
+    /// Just the permission bits of st_mode
+    pub fn permissions(&self) -> mode_t {
+        self.stat.st_mode & 0o7777
+    }
+
+    /// Device the file is on.  The raw stat types vary in width between
+    /// targets, these are always u64.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn dev(&self) -> u64 {
+        self.stat.st_dev as u64
+    }
+
+    /// Inode number, unique among files on the same dev
+    #[allow(clippy::unnecessary_cast)]
+    pub fn ino(&self) -> u64 {
+        self.stat.st_ino as u64
+    }
+
+    /// Number of hard links to the file
+    #[allow(clippy::unnecessary_cast)]
+    pub fn nlink(&self) -> u64 {
+        self.stat.st_nlink as u64
+    }
+
+    /// dev and ino together, which identify the underlying file whatever
+    /// path reached it.  For sets of files already seen.
+    pub fn file_id(&self) -> (u64, u64) {
+        (self.dev(), self.ino())
+    }
+
+    /// True if both describe the same underlying file, as hard links to
+    /// one file or a symlink stat'd through to its target do
+    pub fn is_same_file_as(&self, other: &Metadata) -> bool {
+        self.file_id() == other.file_id()
+    }
+
+    pub fn uid(&self) -> uid_t {
+        self.stat.st_uid
+    }
+
+    pub fn gid(&self) -> gid_t {
+        self.stat.st_gid
+    }
+
+    /// Last access time in seconds since the epoch
+    pub fn atime(&self) -> i64 {
+        self.stat.st_atime
+    }
+
+    /// Nanosecond part of the last access time
+    pub fn atime_nsec(&self) -> i64 {
+        self.stat.st_atime_nsec
+    }
+
+    /// Last modification time in seconds since the epoch
+    pub fn mtime(&self) -> i64 {
+        self.stat.st_mtime
+    }
+
+    /// Nanosecond part of the last modification time
+    pub fn mtime_nsec(&self) -> i64 {
+        self.stat.st_mtime_nsec
+    }
+
+    /// Last modification time, to the nanosecond
+    pub fn modified(&self) -> SystemTime {
+        let (secs, nsec) = (self.mtime(), self.mtime_nsec());
+        if secs >= 0 {
+            UNIX_EPOCH + Duration::new(secs as u64, nsec as u32)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+                + Duration::from_nanos(nsec as u64)
+        }
+    }
+
+    /// Last status change time in seconds since the epoch
+    pub fn ctime(&self) -> i64 {
+        self.stat.st_ctime
+    }
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metadata")
+            .field("mode", &format_args!("{:o}", self.mode()))
+            .field("len", &self.len())
+            .field("uid", &self.uid())
+            .field("gid", &self.gid())
+            .field("mtime", &self.mtime())
+            .finish()
+    }
+}
+
+impl Gluster {
+    /// Stat a path, following symlinks
+    pub fn metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        Ok(Metadata::from_stat(self.stat(path)?))
+    }
+
+    /// Stat a path without following symlinks
+    pub fn symlink_metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        Ok(Metadata::from_stat(self.lsstat(path)?))
+    }
+
+    /// True if a and b are the same underlying file, following symlinks
+    /// in both.  Hard links to one file are the same file.
+    pub fn same_file(&self, a: &Path, b: &Path) -> Result<bool, GlusterError> {
+        Ok(self.metadata(a)?.is_same_file_as(&self.metadata(b)?))
+    }
+
+    /// Like same_file but compares symlinks themselves rather than what
+    /// they point to
+    pub fn same_file_no_follow(&self, a: &Path, b: &Path) -> Result<bool, GlusterError> {
+        Ok(self
+            .symlink_metadata(a)?
+            .is_same_file_as(&self.symlink_metadata(b)?))
+    }
+}
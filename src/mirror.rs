@@ -0,0 +1,234 @@
+use libc::O_RDONLY;
+
+use checksum::ChecksumAlgorithm;
+use gluster::{Gluster, GlusterError};
+use local_transfer::LocalTransferOptions;
+use metadata::Metadata;
+use walk::WalkOptions;
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes, Permissions};
+use std::io;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Options for Gluster::mirror_to_local
+#[derive(Clone, Debug, Default)]
+pub struct MirrorOptions {
+    delete_extraneous: bool,
+    checksum: bool,
+    walk: WalkOptions,
+    transfer: LocalTransferOptions,
+}
+
+impl MirrorOptions {
+    pub fn new() -> MirrorOptions {
+        MirrorOptions::default()
+    }
+
+    /// Remove local files, symlinks and directories that aren't on the
+    /// volume any more.  Defaults to false.
+    pub fn delete_extraneous(mut self, delete: bool) -> MirrorOptions {
+        self.delete_extraneous = delete;
+        self
+    }
+
+    /// Also compare the SHA-256 of files whose size and mtime match, and
+    /// download them again if it differs.  That reads every unchanged
+    /// file on both sides.  Defaults to false.
+    pub fn checksum(mut self, checksum: bool) -> MirrorOptions {
+        self.checksum = checksum;
+        self
+    }
+
+    /// How the remote tree is walked.  Any error the walk returns fails
+    /// the mirror, so WalkErrorPolicy::Ignore is the way to leave out
+    /// what can't be listed.
+    pub fn walk_options(mut self, walk: WalkOptions) -> MirrorOptions {
+        self.walk = walk;
+        self
+    }
+
+    /// How files are downloaded
+    pub fn transfer_options(mut self, transfer: LocalTransferOptions) -> MirrorOptions {
+        self.transfer = transfer;
+        self
+    }
+}
+
+/// What Gluster::mirror_to_local did.  Paths are relative to the roots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MirrorReport {
+    /// Files downloaded because they were missing or differed locally
+    pub downloaded: Vec<PathBuf>,
+    /// Files already the same locally
+    pub unchanged: u64,
+    /// Symlinks created or pointed somewhere new
+    pub symlinks: Vec<PathBuf>,
+    /// Directories created
+    pub dirs_created: u64,
+    /// Local entries removed because of delete_extraneous
+    pub deleted: Vec<PathBuf>,
+    /// Bytes of file data downloaded
+    pub bytes: u64,
+}
+
+// Where a file is downloaded to before it's renamed over local
+fn scratch_path(local: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(local.file_name().unwrap_or_default());
+    name.push(".mirror-tmp");
+    local.with_file_name(name)
+}
+
+// Remove whatever is at path, a whole tree if it's a directory
+fn remove_local(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// Set the mode and mtime of the local file or directory at path
+fn set_local_attrs(path: &Path, remote: &Metadata) -> io::Result<()> {
+    fs::set_permissions(path, Permissions::from_mode(remote.permissions()))?;
+    File::open(path)?.set_times(FileTimes::new().set_modified(remote.modified()))
+}
+
+impl Gluster {
+    /// Make local_root a copy of the tree under remote_root on the
+    /// volume.  Directories and symlinks are recreated, and files are
+    /// downloaded unless the local copy has the same size and mtime (and
+    /// SHA-256, see MirrorOptions::checksum).  Files and directories get
+    /// the mode and mtime they have on the volume.  Ownership and xattrs
+    /// aren't copied.
+    ///
+    /// Each file is downloaded beside its destination and renamed into
+    /// place, so an interrupted run leaves complete files only, and
+    /// running again carries on where it stopped since what was already
+    /// mirrored is skipped.
+    pub fn mirror_to_local(
+        &self,
+        remote_root: &Path,
+        local_root: &Path,
+        opts: &MirrorOptions,
+    ) -> Result<MirrorReport, GlusterError> {
+        let mut report = MirrorReport::default();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        // Directory modes and mtimes are set last, once nothing more is
+        // written into them
+        let mut dirs: Vec<(PathBuf, Metadata)> = Vec::new();
+        for entry in self.walk(remote_root, &opts.walk) {
+            let entry = entry?;
+            // Everything the walk finds is under remote_root
+            let relative = entry
+                .path
+                .strip_prefix(remote_root)
+                .unwrap_or(&entry.path)
+                .to_path_buf();
+            let local = local_root.join(&relative);
+            let metadata = entry.metadata;
+            if metadata.is_dir() {
+                match fs::symlink_metadata(&local) {
+                    Ok(ref existing) if existing.is_dir() => {}
+                    Ok(_) => {
+                        remove_local(&local)?;
+                        fs::create_dir(&local)?;
+                        report.dirs_created += 1;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                        fs::create_dir_all(&local)?;
+                        report.dirs_created += 1;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                dirs.push((local, metadata));
+            } else if metadata.is_symlink() {
+                let target = self.read_link(&entry.path)?;
+                if fs::read_link(&local).ok().as_ref() != Some(&target) {
+                    remove_local(&local)?;
+                    symlink(&target, &local)?;
+                    report.symlinks.push(relative.clone());
+                }
+            } else if metadata.is_file() {
+                if self.mirrored(&entry.path, &metadata, &local, opts)? {
+                    report.unchanged += 1;
+                } else {
+                    let scratch = scratch_path(&local);
+                    let downloaded =
+                        self.download_to_local_file(&entry.path, &scratch, &opts.transfer)?;
+                    set_local_attrs(&scratch, &metadata)?;
+                    remove_local(&local)?;
+                    fs::rename(&scratch, &local)?;
+                    report.bytes += downloaded.transferred - downloaded.holes;
+                    report.downloaded.push(relative.clone());
+                }
+            } else {
+                trace!("not mirroring special file {}", entry.path.display());
+                continue;
+            }
+            seen.insert(relative);
+        }
+        if opts.delete_extraneous {
+            delete_extraneous(local_root, Path::new(""), &seen, &mut report)?;
+        }
+        // Children before their parents
+        for (local, metadata) in dirs.iter().rev() {
+            set_local_attrs(local, metadata)?;
+        }
+        Ok(report)
+    }
+
+    // Whether the local file at local is already a copy of remote
+    fn mirrored(
+        &self,
+        remote: &Path,
+        metadata: &Metadata,
+        local: &Path,
+        opts: &MirrorOptions,
+    ) -> Result<bool, GlusterError> {
+        let existing = match fs::symlink_metadata(local) {
+            Ok(existing) => existing,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if !existing.is_file()
+            || existing.len() != metadata.len()
+            || existing.mtime() != metadata.mtime()
+            || existing.mtime_nsec() != metadata.mtime_nsec()
+        {
+            return Ok(false);
+        }
+        if !opts.checksum {
+            return Ok(true);
+        }
+        let mut remote_file = self.open_file(remote, O_RDONLY)?;
+        let remote_digest = ChecksumAlgorithm::Sha256.digest_reader(&mut remote_file)?;
+        remote_file.close()?;
+        let local_digest = ChecksumAlgorithm::Sha256.digest_reader(&mut File::open(local)?)?;
+        Ok(remote_digest == local_digest)
+    }
+}
+
+// Remove everything under local_root/relative that isn't in seen
+fn delete_extraneous(
+    local_root: &Path,
+    relative: &Path,
+    seen: &HashSet<PathBuf>,
+    report: &mut MirrorReport,
+) -> io::Result<()> {
+    for entry in fs::read_dir(local_root.join(relative))? {
+        let entry = entry?;
+        let child = relative.join(entry.file_name());
+        if !seen.contains(&child) {
+            remove_local(&entry.path())?;
+            report.deleted.push(child);
+        } else if entry.file_type()?.is_dir() {
+            delete_extraneous(local_root, &child, seen, report)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,390 @@
+// Parsing of chmod(1) style mode strings
+//
+// Accepts octal modes ("0644", "2775"), symbolic clauses ("u+rwx,g+rX",
+// "go=", "g=u") and ls style permission strings ("rwxr-x---").  Apart
+// from the ls form this follows coreutils chmod, including preserving
+// the set-user-ID and set-group-ID bits of directories unless the mode
+// mentions them.
+
+use libc::mode_t;
+
+use gluster::{Gluster, GlusterError};
+
+use std::error::Error as err;
+use std::fmt;
+use std::path::Path;
+
+const S_ISUID: mode_t = 0o4000;
+const S_ISGID: mode_t = 0o2000;
+const S_ISVTX: mode_t = 0o1000;
+const S_IRWXU: mode_t = 0o700;
+const S_IRWXG: mode_t = 0o070;
+const S_IRWXO: mode_t = 0o007;
+const ALL_READ: mode_t = 0o444;
+const ALL_WRITE: mode_t = 0o222;
+const ALL_EXEC: mode_t = 0o111;
+const ALL_BITS: mode_t = 0o7777;
+
+/// Modes for create, mkdir and WriteOptions::mode
+pub mod defaults {
+    use libc::mode_t;
+
+    /// rw-r--r--
+    pub const FILE_0644: mode_t = 0o644;
+    /// rw-------
+    pub const FILE_0600: mode_t = 0o600;
+    /// rw-r-----
+    pub const FILE_0640: mode_t = 0o640;
+    /// rw-rw-r--
+    pub const FILE_0664: mode_t = 0o664;
+    /// rwxr-xr-x, for executables
+    pub const EXEC_0755: mode_t = 0o755;
+    /// rwxr-xr-x
+    pub const DIR_0755: mode_t = 0o755;
+    /// rwx------
+    pub const DIR_0700: mode_t = 0o700;
+    /// rwxr-x---
+    pub const DIR_0750: mode_t = 0o750;
+    /// rwxrwxr-x
+    pub const DIR_0775: mode_t = 0o775;
+}
+
+/// What happens to the mode given when creating a file or directory
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ModePolicy {
+    /// Pass the mode along with the create and let the umask take bits
+    /// out of it, like open(2) and mkdir(2) do.  This is the default.
+    #[default]
+    RespectUmask,
+    /// Follow the create with a chmod so the new file ends up with
+    /// exactly the mode asked for, whatever the umask is
+    Exact,
+}
+
+/// Why a mode string couldn't be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// The string that was being parsed
+    pub mode: String,
+    /// Byte offset of the problem
+    pub position: usize,
+    /// What was wrong there
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode[self.position..].chars().next() {
+            Some(c) => write!(
+                f,
+                "invalid mode {:?}: unexpected {:?} at offset {}, {}",
+                self.mode, c, self.position, self.reason
+            ),
+            None => write!(f, "invalid mode {:?}: {}", self.mode, self.reason),
+        }
+    }
+}
+
+impl err for ParseError {
+    fn description(&self) -> &str {
+        self.reason
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Remove,
+    Set,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Flag {
+    Ordinary,
+    // X: execute only for directories or if something already has it
+    ExecuteIfAny,
+    // g=u and friends: take the bits from another class
+    CopyExisting,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Change {
+    op: Op,
+    flag: Flag,
+    // Bits the who part selected, 0 when it was left out
+    affected: mode_t,
+    value: mode_t,
+    // Bits spelled out, which are changed even on directories
+    mentioned: mode_t,
+}
+
+/// A parsed mode string, applied to a file's current mode with apply
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeChange {
+    changes: Vec<Change>,
+}
+
+impl ModeChange {
+    /// The new mode for a file with mode current.  Clauses without a who
+    /// part ("+x") behave as they do for chmod run with a umask of 0.
+    pub fn apply(&self, current: mode_t, is_dir: bool) -> mode_t {
+        self.apply_with_umask(current, is_dir, 0)
+    }
+
+    /// Like apply, but clauses without a who part leave the bits set in
+    /// umask alone, exactly like chmod(1)
+    pub fn apply_with_umask(&self, current: mode_t, is_dir: bool, umask: mode_t) -> mode_t {
+        let file_type = current & !ALL_BITS;
+        let mut mode = current & ALL_BITS;
+        for change in &self.changes {
+            let omit = if is_dir { S_ISUID | S_ISGID } else { 0 } & !change.mentioned;
+            let mut value = change.value;
+            match change.flag {
+                Flag::Ordinary => {}
+                Flag::CopyExisting => {
+                    value &= mode;
+                    if value & ALL_READ != 0 {
+                        value |= ALL_READ;
+                    }
+                    if value & ALL_WRITE != 0 {
+                        value |= ALL_WRITE;
+                    }
+                    if value & ALL_EXEC != 0 {
+                        value |= ALL_EXEC;
+                    }
+                }
+                Flag::ExecuteIfAny => {
+                    if mode & ALL_EXEC != 0 || is_dir {
+                        value |= ALL_EXEC;
+                    }
+                }
+            }
+            let limit = if change.affected != 0 {
+                change.affected
+            } else {
+                !umask
+            };
+            value &= limit & !omit;
+            mode = match change.op {
+                Op::Add => mode | value,
+                Op::Remove => mode & !value,
+                Op::Set => {
+                    let preserved = if change.affected != 0 {
+                        !change.affected
+                    } else {
+                        0
+                    } | omit;
+                    (mode & preserved) | value
+                }
+            };
+        }
+        file_type | (mode & ALL_BITS)
+    }
+}
+
+fn error(mode: &str, position: usize, reason: &'static str) -> ParseError {
+    ParseError {
+        mode: mode.to_string(),
+        position,
+        reason,
+    }
+}
+
+fn parse_octal(mode: &str) -> Result<ModeChange, ParseError> {
+    let mut value: mode_t = 0;
+    for (i, c) in mode.char_indices() {
+        let digit = match c.to_digit(8) {
+            Some(digit) => digit as mode_t,
+            None => return Err(error(mode, i, "octal modes only use the digits 0 to 7")),
+        };
+        value = value * 8 + digit;
+        if value > ALL_BITS {
+            return Err(error(mode, i, "octal modes can't be larger than 7777"));
+        }
+    }
+    // Like chmod, four digits or less leave a directory's setuid and
+    // setgid bits alone unless they're being set
+    let mentioned = if mode.len() < 5 {
+        (value & (S_ISUID | S_ISGID)) | S_ISVTX | S_IRWXU | S_IRWXG | S_IRWXO
+    } else {
+        ALL_BITS
+    };
+    Ok(ModeChange {
+        changes: vec![Change {
+            op: Op::Set,
+            flag: Flag::Ordinary,
+            affected: ALL_BITS,
+            value,
+            mentioned,
+        }],
+    })
+}
+
+// rwxr-x--- as printed by ls, with s/S and t/T in the execute positions
+fn parse_ls(mode: &str) -> Option<ModeChange> {
+    let bytes = mode.as_bytes();
+    if bytes.len() != 9 {
+        return None;
+    }
+    let special = [S_ISUID, S_ISGID, S_ISVTX];
+    let special_char = [b's', b's', b't'];
+    let mut value = 0;
+    for class in 0..3 {
+        let shift = 6 - 3 * class;
+        let (r, w, x) = (bytes[3 * class], bytes[3 * class + 1], bytes[3 * class + 2]);
+        match r {
+            b'r' => value |= 4 << shift,
+            b'-' => {}
+            _ => return None,
+        }
+        match w {
+            b'w' => value |= 2 << shift,
+            b'-' => {}
+            _ => return None,
+        }
+        if x == b'x' {
+            value |= 1 << shift;
+        } else if x == special_char[class] {
+            value |= (1 << shift) | special[class];
+        } else if x == special_char[class].to_ascii_uppercase() {
+            value |= special[class];
+        } else if x != b'-' {
+            return None;
+        }
+    }
+    Some(ModeChange {
+        changes: vec![Change {
+            op: Op::Set,
+            flag: Flag::Ordinary,
+            affected: ALL_BITS,
+            value,
+            mentioned: ALL_BITS,
+        }],
+    })
+}
+
+fn parse_symbolic(mode: &str) -> Result<ModeChange, ParseError> {
+    let bytes = mode.as_bytes();
+    let mut changes = Vec::new();
+    let mut i = 0;
+    loop {
+        // Who
+        let mut affected = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'u' => affected |= S_ISUID | S_IRWXU,
+                b'g' => affected |= S_ISGID | S_IRWXG,
+                b'o' => affected |= S_ISVTX | S_IRWXO,
+                b'a' => affected |= ALL_BITS,
+                _ => break,
+            }
+            i += 1;
+        }
+        // One or more operations, each followed by its permissions
+        let mut ops = 0;
+        loop {
+            let op = match bytes.get(i) {
+                Some(b'+') => Op::Add,
+                Some(b'-') => Op::Remove,
+                Some(b'=') => Op::Set,
+                _ => break,
+            };
+            i += 1;
+            ops += 1;
+            let mut value = 0;
+            let mut flag = Flag::Ordinary;
+            match bytes.get(i) {
+                Some(b'u') => {
+                    value = S_IRWXU;
+                    flag = Flag::CopyExisting;
+                    i += 1;
+                }
+                Some(b'g') => {
+                    value = S_IRWXG;
+                    flag = Flag::CopyExisting;
+                    i += 1;
+                }
+                Some(b'o') => {
+                    value = S_IRWXO;
+                    flag = Flag::CopyExisting;
+                    i += 1;
+                }
+                _ => {
+                    while i < bytes.len() {
+                        match bytes[i] {
+                            b'r' => value |= ALL_READ,
+                            b'w' => value |= ALL_WRITE,
+                            b'x' => value |= ALL_EXEC,
+                            b'X' => flag = Flag::ExecuteIfAny,
+                            b's' => value |= S_ISUID | S_ISGID,
+                            b't' => value |= S_ISVTX,
+                            _ => break,
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            let mentioned = if affected != 0 {
+                affected & value
+            } else {
+                value
+            };
+            changes.push(Change {
+                op,
+                flag,
+                affected,
+                value,
+                mentioned,
+            });
+        }
+        if ops == 0 {
+            return Err(match bytes.get(i) {
+                Some(_) => error(mode, i, "expected one of ugoa followed by +, - or ="),
+                None => error(mode, i, "a clause needs an operator, one of +, - or ="),
+            });
+        }
+        match bytes.get(i) {
+            None => break,
+            Some(b',') => i += 1,
+            Some(_) if flag_of_last(&changes) == Flag::CopyExisting => {
+                return Err(error(
+                    mode,
+                    i,
+                    "u, g or o after an operator can't be combined with other permissions",
+                ))
+            }
+            Some(_) => return Err(error(mode, i, "expected one of rwxXst, +-= or ','")),
+        }
+    }
+    Ok(ModeChange { changes })
+}
+
+fn flag_of_last(changes: &[Change]) -> Flag {
+    changes.last().map(|c| c.flag).unwrap_or(Flag::Ordinary)
+}
+
+/// Parse a mode string.  Octal modes, symbolic modes in any combination
+/// chmod(1) understands and ls style strings like "rwxr-x---" are
+/// accepted.
+pub fn parse(mode: &str) -> Result<ModeChange, ParseError> {
+    if mode.is_empty() {
+        return Err(error(mode, 0, "the mode is empty"));
+    }
+    if mode.as_bytes()[0].is_ascii_digit() {
+        return parse_octal(mode);
+    }
+    if let Some(change) = parse_ls(mode) {
+        return Ok(change);
+    }
+    parse_symbolic(mode)
+}
+
+impl Gluster {
+    /// chmod with a mode string like "g+w" or "0750", see mode::parse
+    pub fn chmod_str(&self, path: &Path, mode: &str) -> Result<(), GlusterError> {
+        let change = parse(mode)?;
+        let stat = self.stat(path)?;
+        let is_dir = stat.st_mode & ::libc::S_IFMT == ::libc::S_IFDIR;
+        self.chmod(path, change.apply(stat.st_mode, is_dir) & ALL_BITS)
+    }
+}
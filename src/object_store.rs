@@ -0,0 +1,513 @@
+use errno::{errno, Errno};
+use libc::{
+    DT_DIR, ENODATA, ENOENT, O_CREAT, O_EXCL, O_RDONLY, O_TRUNC, O_WRONLY, S_IFDIR, S_IFMT,
+};
+
+use checksum::Sha256;
+use checksum_cache::{from_hex, to_hex};
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The xattr that put() leaves alone but head() reports if something
+/// else has set it on an object, such as Gluster::checksum_cached.
+pub const CHECKSUM_XATTR: &str = "user.gfapi.checksum";
+
+// Directory under the root put_cas spools into, skipped by list()
+const CAS_TMP_DIR: &str = ".cas-tmp";
+
+static CAS_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Metadata about a single object in the store
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    /// Last modification time in seconds since the epoch
+    pub mtime: i64,
+    pub checksum: Option<String>,
+}
+
+/// One page of results from a list call.  Pass continuation back into
+/// list() to fetch the next page.  continuation is None on the last page.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub continuation: Option<String>,
+}
+
+/// A reference to content stored with put_cas: the SHA-256 of the
+/// content.  Displays and parses as the lowercase hex digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CasRef {
+    digest: [u8; 32],
+}
+
+impl CasRef {
+    pub fn from_digest(digest: [u8; 32]) -> CasRef {
+        CasRef { digest }
+    }
+
+    /// The CasRef of data
+    pub fn of(data: &[u8]) -> CasRef {
+        CasRef::from_digest(Sha256::checksum(data))
+    }
+
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    /// The key the content is stored under, objects/ab/cd/<hex digest>
+    pub fn key(&self) -> String {
+        let hex = self.to_string();
+        format!("objects/{}/{}/{}", &hex[..2], &hex[2..4], hex)
+    }
+}
+
+impl fmt::Display for CasRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&to_hex(&self.digest))
+    }
+}
+
+impl FromStr for CasRef {
+    type Err = GlusterError;
+
+    fn from_str(s: &str) -> Result<CasRef, GlusterError> {
+        match from_hex(s) {
+            Some(ref bytes) if bytes.len() == 32 => {
+                let mut digest = [0u8; 32];
+                digest.copy_from_slice(bytes);
+                Ok(CasRef { digest })
+            }
+            _ => Err(GlusterError::new(format!(
+                "{:?} isn't a SHA-256 hex digest",
+                s
+            ))),
+        }
+    }
+}
+
+/// Whether put_cas wrote the content or found it already stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CasOutcome {
+    Stored,
+    Deduplicated,
+}
+
+/// What put_cas did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CasPut {
+    pub cas_ref: CasRef,
+    /// Length of the content in bytes
+    pub size: u64,
+    pub outcome: CasOutcome,
+}
+
+/// A simple blob store over a directory prefix on a volume.  Keys are
+/// '/' separated and map directly to paths under the root.  Intermediate
+/// directories are created on put.
+pub struct ObjectStore<'a> {
+    gluster: &'a Gluster,
+    root: PathBuf,
+    page_size: usize,
+    prune_empty_dirs: bool,
+}
+
+impl<'a> ObjectStore<'a> {
+    /// Create a store rooted at root.  The root directory is created if it
+    /// doesn't exist yet.
+    pub fn new(gluster: &'a Gluster, root: &Path) -> Result<ObjectStore<'a>, GlusterError> {
+        gluster.create_dir_all(root, 0o755)?;
+        Ok(ObjectStore {
+            gluster,
+            root: root.to_path_buf(),
+            page_size: 1000,
+            prune_empty_dirs: false,
+        })
+    }
+
+    /// Maximum number of objects returned from a single list call.
+    /// Defaults to 1000.
+    pub fn page_size(mut self, page_size: usize) -> ObjectStore<'a> {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Remove directories left empty by delete(), up to but not
+    /// including the root.  Defaults to false.
+    pub fn prune_empty_dirs(mut self, prune: bool) -> ObjectStore<'a> {
+        self.prune_empty_dirs = prune;
+        self
+    }
+
+    fn key_path(&self, key: &str) -> Result<PathBuf, GlusterError> {
+        if key.is_empty() || key.ends_with('/') {
+            return Err(GlusterError::new(format!("Invalid object key: {:?}", key)));
+        }
+        let key_path = Path::new(key);
+        for component in key_path.components() {
+            match component {
+                Component::Normal(_) => {}
+                _ => {
+                    return Err(GlusterError::new(format!("Invalid object key: {:?}", key)));
+                }
+            }
+        }
+        Ok(self.root.join(key_path))
+    }
+
+    /// Store data under key, replacing any existing object
+    pub fn put(&self, key: &str, data: &[u8]) -> Result<(), GlusterError> {
+        let mut file = self.create(key)?;
+        file.write_all(data)?;
+        file.close()
+    }
+
+    /// Store everything read from reader under key, replacing any existing
+    /// object
+    pub fn put_reader<R: Read>(&self, key: &str, reader: &mut R) -> Result<u64, GlusterError> {
+        let mut file = self.create(key)?;
+        let written = ::std::io::copy(reader, &mut file)?;
+        file.close()?;
+        Ok(written)
+    }
+
+    fn create(&self, key: &str) -> Result<GlusterFile<'a>, GlusterError> {
+        let path = self.key_path(key)?;
+        if let Some(parent) = path.parent() {
+            self.gluster.create_dir_all(parent, 0o755)?;
+        }
+        self.gluster
+            .create_file(&path, O_CREAT | O_WRONLY | O_TRUNC, 0o644)
+    }
+
+    /// Read the whole object into memory
+    pub fn get(&self, key: &str) -> Result<Vec<u8>, GlusterError> {
+        let mut buffer = Vec::new();
+        self.get_reader(key)?.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Open the object for streaming reads
+    pub fn get_reader(&self, key: &str) -> Result<GlusterFile<'a>, GlusterError> {
+        let path = self.key_path(key)?;
+        self.gluster.open_file(&path, O_RDONLY)
+    }
+
+    /// Stat an object without reading it
+    pub fn head(&self, key: &str) -> Result<ObjectMeta, GlusterError> {
+        let path = self.key_path(key)?;
+        let stat = self.gluster.stat(&path)?;
+        if stat.st_mode & S_IFMT == S_IFDIR {
+            return Err(GlusterError::new(format!("{} is not an object", key)));
+        }
+        let checksum = match self.gluster.getxattr(&path, CHECKSUM_XATTR) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                if errno() != Errno(ENODATA) {
+                    return Err(e);
+                }
+                None
+            }
+        };
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: stat.st_size as u64,
+            mtime: stat.st_mtime,
+            checksum,
+        })
+    }
+
+    /// Delete an object.  If prune_empty_dirs is set any parent directories
+    /// left empty are removed as well.
+    pub fn delete(&self, key: &str) -> Result<(), GlusterError> {
+        let path = self.key_path(key)?;
+        self.gluster.unlink(&path)?;
+        if self.prune_empty_dirs {
+            let mut dir = path.parent();
+            while let Some(d) = dir {
+                if d == self.root.as_path() {
+                    break;
+                }
+                // rmdir fails on non empty directories which is where we stop
+                if self.gluster.rmdir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+        Ok(())
+    }
+
+    /// List objects whose key starts with prefix in lexicographic order.
+    /// Pass the continuation from the previous page to resume after it.
+    /// Each page reads only the directories it passes through, each one
+    /// whole, and skips directories that sort entirely before the
+    /// continuation without opening them.  So a page costs the size of
+    /// those directories, not of everything under prefix.
+    pub fn list(
+        &self,
+        prefix: &str,
+        continuation: Option<&str>,
+    ) -> Result<Page<ObjectMeta>, GlusterError> {
+        let mut pending = KeyWalk::new(self, prefix, continuation)?.peekable();
+        let mut items = Vec::new();
+        let mut last_key = None;
+        while items.len() < self.page_size {
+            let key = match pending.next() {
+                Some(key) => key?,
+                None => break,
+            };
+            match self.head(&key) {
+                Ok(meta) => items.push(meta),
+                // Deleted while we were listing
+                Err(_) if errno() == Errno(ENOENT) => continue,
+                Err(e) => return Err(e),
+            }
+            last_key = Some(key);
+        }
+        let continuation = match pending.peek() {
+            Some(_) => last_key,
+            None => None,
+        };
+        Ok(Page {
+            items,
+            continuation,
+        })
+    }
+
+    /// Store data under the key of its CasRef, unless content with the
+    /// same digest is already there, in which case nothing is written
+    pub fn put_cas(&self, data: &[u8]) -> Result<CasPut, GlusterError> {
+        let cas_ref = CasRef::of(data);
+        if self.has(&cas_ref)? {
+            return Ok(CasPut {
+                cas_ref,
+                size: data.len() as u64,
+                outcome: CasOutcome::Deduplicated,
+            });
+        }
+        let mut reader = data;
+        self.put_cas_reader(&mut reader)
+    }
+
+    /// Store everything read from reader under the key of its CasRef.
+    /// The content is hashed as it's spooled to a temporary file under
+    /// the root, so its length needn't be known up front, and the file is
+    /// then renamed into place only if nothing is stored under the key
+    /// yet.  Otherwise, including when a concurrent put_cas of the same
+    /// content wins the race, the temporary file is removed and the
+    /// outcome is Deduplicated.
+    pub fn put_cas_reader<R: Read>(&self, reader: &mut R) -> Result<CasPut, GlusterError> {
+        let tmp_dir = self.root.join(CAS_TMP_DIR);
+        self.gluster.create_dir_all(&tmp_dir, 0o755)?;
+        let tmp = tmp_dir.join(cas_tmp_name());
+        let mut file = self
+            .gluster
+            .create_file(&tmp, O_CREAT | O_EXCL | O_WRONLY, 0o644)?;
+        let spooled = spool(reader, &mut file).and_then(|spooled| {
+            file.close()?;
+            Ok(spooled)
+        });
+        let (cas_ref, size) = match spooled {
+            Ok(spooled) => spooled,
+            Err(e) => {
+                let _ = self.gluster.unlink(&tmp);
+                return Err(e);
+            }
+        };
+        let outcome = match self.link_cas(&tmp, &cas_ref) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                let _ = self.gluster.unlink(&tmp);
+                return Err(e);
+            }
+        };
+        if outcome == CasOutcome::Deduplicated {
+            self.gluster.unlink(&tmp)?;
+        }
+        Ok(CasPut {
+            cas_ref,
+            size,
+            outcome,
+        })
+    }
+
+    // Move the spooled file at tmp to where cas_ref is stored, unless
+    // something is already there
+    fn link_cas(&self, tmp: &Path, cas_ref: &CasRef) -> Result<CasOutcome, GlusterError> {
+        if self.has(cas_ref)? {
+            return Ok(CasOutcome::Deduplicated);
+        }
+        let path = self.key_path(&cas_ref.key())?;
+        if let Some(parent) = path.parent() {
+            self.gluster.create_dir_all(parent, 0o755)?;
+        }
+        match self.gluster.rename_noreplace(tmp, &path) {
+            Ok(()) => Ok(CasOutcome::Stored),
+            Err(GlusterError::AlreadyExists { .. }) => Ok(CasOutcome::Deduplicated),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The content stored under cas_ref
+    pub fn get_cas(&self, cas_ref: &CasRef) -> Result<Vec<u8>, GlusterError> {
+        self.get(&cas_ref.key())
+    }
+
+    /// Open the content stored under cas_ref for streaming reads
+    pub fn get_cas_reader(&self, cas_ref: &CasRef) -> Result<GlusterFile<'a>, GlusterError> {
+        self.get_reader(&cas_ref.key())
+    }
+
+    /// Whether content is stored under cas_ref
+    pub fn has(&self, cas_ref: &CasRef) -> Result<bool, GlusterError> {
+        let path = self.key_path(&cas_ref.key())?;
+        match self.gluster.stat(&path) {
+            Ok(_) => Ok(true),
+            Err(_) if errno() == Errno(ENOENT) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Keys under a store in lexicographic order, read one directory at a
+// time.  A directory sorts among its siblings as its name plus '/', which
+// is where the keys under it fall, so taking each directory's entries in
+// that order depth first gives every key in order.
+struct KeyWalk<'s, 'a: 's> {
+    store: &'s ObjectStore<'a>,
+    prefix: &'s str,
+    after: Option<&'s str>,
+    // Entries of each directory being walked, as keys with a trailing '/'
+    // for directories, sorted in reverse so the next one is popped
+    stack: Vec<Vec<String>>,
+}
+
+impl<'s, 'a> KeyWalk<'s, 'a> {
+    fn new(
+        store: &'s ObjectStore<'a>,
+        prefix: &'s str,
+        after: Option<&'s str>,
+    ) -> Result<KeyWalk<'s, 'a>, GlusterError> {
+        let mut walk = KeyWalk {
+            store,
+            prefix,
+            after,
+            stack: Vec::new(),
+        };
+        // Only walk the deepest directory that can contain the prefix
+        match prefix.rfind('/') {
+            Some(i) => walk.push_dir(&prefix[..i])?,
+            None => walk.push_dir("")?,
+        }
+        Ok(walk)
+    }
+
+    fn push_dir(&mut self, dir: &str) -> Result<(), GlusterError> {
+        let dir_path = if dir.is_empty() {
+            self.store.root.clone()
+        } else {
+            self.store.key_path(dir)?
+        };
+        let d = match self.store.gluster.opendir(&dir_path) {
+            Ok(d) => d,
+            Err(_) if errno() == Errno(ENOENT) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for dir_entry in d {
+            let name = dir_entry.path.to_string_lossy().into_owned();
+            if name == "." || name == ".." || (dir.is_empty() && name == CAS_TMP_DIR) {
+                continue;
+            }
+            let key = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            if dir_entry.file_type == DT_DIR {
+                let key = key + "/";
+                // Descend only into directories that can still match and
+                // have keys after the continuation
+                let matches = key.starts_with(self.prefix) || self.prefix.starts_with(&key);
+                let later = self
+                    .after
+                    .is_none_or(|a| a < key.as_str() || a.starts_with(&key));
+                if matches && later {
+                    entries.push(key);
+                }
+            } else if key.starts_with(self.prefix) && self.after.is_none_or(|a| a < key.as_str()) {
+                entries.push(key);
+            }
+        }
+        entries.sort_unstable_by(|a, b| b.cmp(a));
+        self.stack.push(entries);
+        Ok(())
+    }
+}
+
+impl<'s, 'a> Iterator for KeyWalk<'s, 'a> {
+    type Item = Result<String, GlusterError>;
+
+    fn next(&mut self) -> Option<Result<String, GlusterError>> {
+        loop {
+            let key = match self.stack.last_mut() {
+                Some(entries) => entries.pop(),
+                None => return None,
+            };
+            match key {
+                None => {
+                    self.stack.pop();
+                }
+                Some(ref dir) if dir.ends_with('/') => {
+                    if let Err(e) = self.push_dir(&dir[..dir.len() - 1]) {
+                        return Some(Err(e));
+                    }
+                }
+                Some(key) => return Some(Ok(key)),
+            }
+        }
+    }
+}
+
+// Copy reader into file, hashing as it goes
+fn spool<R: Read>(reader: &mut R, file: &mut GlusterFile) -> Result<(CasRef, u64), GlusterError> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut sha = Sha256::new();
+    let mut size = 0u64;
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        sha.update(&buffer[..len]);
+        file.write_all(&buffer[..len])?;
+        size += len as u64;
+    }
+    Ok((CasRef::from_digest(sha.finish()), size))
+}
+
+// A name for a spool file no other put_cas uses
+fn cas_tmp_name() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!(
+        "{}-{}-{}",
+        process::id(),
+        CAS_COUNTER.fetch_add(1, Ordering::SeqCst),
+        nanos
+    )
+}
@@ -0,0 +1,281 @@
+use libc::O_RDONLY;
+
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use readahead::{pread_chunk, pread_range, FileHandle};
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Options for Gluster::read_parallel
+#[derive(Clone, Debug)]
+pub struct ParallelReadOptions {
+    workers: usize,
+    chunk_size: usize,
+}
+
+impl Default for ParallelReadOptions {
+    fn default() -> ParallelReadOptions {
+        ParallelReadOptions {
+            workers: 4,
+            chunk_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl ParallelReadOptions {
+    pub fn new() -> ParallelReadOptions {
+        ParallelReadOptions::default()
+    }
+
+    /// Number of threads reading ranges at once, which is also how many
+    /// chunks can be held in memory.  Defaults to 4.
+    pub fn workers(mut self, workers: usize) -> ParallelReadOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Length of the range each pread covers.  Defaults to 4MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> ParallelReadOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+struct Request {
+    offset: u64,
+    len: usize,
+}
+
+struct Response {
+    offset: u64,
+    data: io::Result<Vec<u8>>,
+}
+
+// Bytes held in chunk buffers, and the most there ever were
+#[derive(Default)]
+struct BufferAccounting {
+    buffered: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl BufferAccounting {
+    fn allocate(&self, len: usize) {
+        let buffered = self.buffered.fetch_add(len, Ordering::SeqCst) + len;
+        self.peak.fetch_max(buffered, Ordering::SeqCst);
+    }
+
+    fn release(&self, len: usize) {
+        self.buffered.fetch_sub(len, Ordering::SeqCst);
+    }
+}
+
+/// A file read by several threads at once, each pread'ing one chunk
+/// sized range of it, with the bytes handed out strictly in order.
+/// Created with Gluster::read_parallel.
+///
+/// Ranges are handed to the workers in file order, and no more are
+/// started once workers chunks are in memory, counting the one being
+/// read from.  A slow consumer therefore holds at most
+/// workers * chunk_size bytes.  A failed pread is returned by the next
+/// read, whichever range it was for, and every read after it fails too.
+/// The file is read up to the length it had when it was opened.
+pub struct ParallelReader<'a> {
+    // Kept open for the workers' preads
+    _file: GlusterFile<'a>,
+    len: u64,
+    chunk_size: usize,
+    workers: usize,
+    next_request: u64,
+    outstanding: VecDeque<u64>,
+    ready: HashMap<u64, io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    current_pos: usize,
+    // The length current was allocated with
+    current_charge: usize,
+    failed: bool,
+    accounting: Arc<BufferAccounting>,
+    requests: Option<Sender<Request>>,
+    responses: Receiver<Response>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Gluster {
+    /// Open the file at path for reading by opts.workers threads in
+    /// parallel, for large files where the latency of each read rather
+    /// than bandwidth is the limit.  See ParallelReader.
+    pub fn read_parallel(
+        &self,
+        path: &Path,
+        opts: &ParallelReadOptions,
+    ) -> Result<ParallelReader<'_>, GlusterError> {
+        let file = self.open_file(path, O_RDONLY)?;
+        let len = Metadata::from_stat(file.fstat()?).len();
+        let raw_handle = file.io_handle()?;
+        let accounting = Arc::new(BufferAccounting::default());
+        let (request_tx, request_rx) = channel::<Request>();
+        let (response_tx, response_rx) = channel::<Response>();
+        let request_rx = Arc::new(Mutex::new(request_rx));
+        let mut threads = Vec::with_capacity(opts.workers);
+        for _ in 0..opts.workers {
+            let requests = request_rx.clone();
+            let responses = response_tx.clone();
+            let accounting = accounting.clone();
+            let file_handle = FileHandle(raw_handle);
+            threads.push(thread::spawn(move || {
+                let pread = |offset, len| pread_chunk(&file_handle, offset, len);
+                loop {
+                    let request = match requests.lock().unwrap().recv() {
+                        Ok(request) => request,
+                        // The ParallelReader was dropped
+                        Err(_) => return,
+                    };
+                    accounting.allocate(request.len);
+                    let response = Response {
+                        offset: request.offset,
+                        data: pread_range(&pread, request.offset, request.len),
+                    };
+                    if responses.send(response).is_err() {
+                        return;
+                    }
+                }
+            }));
+        }
+        Ok(ParallelReader {
+            _file: file,
+            len,
+            chunk_size: opts.chunk_size,
+            workers: opts.workers,
+            next_request: 0,
+            outstanding: VecDeque::with_capacity(opts.workers),
+            ready: HashMap::new(),
+            current: Vec::new(),
+            current_pos: 0,
+            current_charge: 0,
+            failed: false,
+            accounting,
+            requests: Some(request_tx),
+            responses: response_rx,
+            threads,
+        })
+    }
+}
+
+impl<'a> ParallelReader<'a> {
+    /// Bytes currently held in chunk buffers
+    pub fn buffered(&self) -> usize {
+        self.accounting.buffered.load(Ordering::SeqCst)
+    }
+
+    /// The most bytes ever held in chunk buffers at once, which stays
+    /// within workers * chunk_size
+    pub fn peak_buffered(&self) -> usize {
+        self.accounting.peak.load(Ordering::SeqCst)
+    }
+
+    // The length of the range starting at offset
+    fn range_len(&self, offset: u64) -> usize {
+        (self.len - offset).min(self.chunk_size as u64) as usize
+    }
+
+    fn fill_pipeline(&mut self) {
+        let held = if self.current_charge > 0 { 1 } else { 0 };
+        while self.next_request < self.len && self.outstanding.len() + held < self.workers {
+            let offset = self.next_request;
+            let len = self.range_len(offset);
+            if let Some(ref requests) = self.requests {
+                let _ = requests.send(Request { offset, len });
+            }
+            self.outstanding.push_back(offset);
+            self.next_request += len as u64;
+        }
+    }
+
+    // Pick up finished ranges without waiting, failing if any of them
+    // failed
+    fn collect_ready(&mut self) -> io::Result<()> {
+        while let Ok(response) = self.responses.try_recv() {
+            self.ready.insert(response.offset, response.data);
+        }
+        let failed = self
+            .ready
+            .iter()
+            .find(|&(_, data)| data.is_err())
+            .map(|(offset, _)| *offset);
+        match failed.and_then(|offset| self.ready.remove(&offset)) {
+            Some(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.accounting.release(self.current_charge);
+        self.current = Vec::new();
+        self.current_pos = 0;
+        self.current_charge = 0;
+        self.fill_pipeline();
+        let offset = match self.outstanding.pop_front() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        loop {
+            if let Some(data) = self.ready.remove(&offset) {
+                let data = data?;
+                let expected = self.range_len(offset);
+                if data.len() < expected {
+                    // The file shrank, what's beyond it never arrives
+                    self.len = offset + data.len() as u64;
+                    self.outstanding.clear();
+                }
+                self.current = data;
+                self.current_charge = expected;
+                return Ok(());
+            }
+            let response = self
+                .responses
+                .recv()
+                .map_err(|_| io::Error::other("parallel read worker exited"))?;
+            self.ready.insert(response.offset, response.data);
+            self.collect_ready()?;
+        }
+    }
+}
+
+impl<'a> Read for ParallelReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(io::Error::other("an earlier parallel read failed"));
+        }
+        let result = self.collect_ready().and_then(|_| {
+            if self.current_pos == self.current.len() {
+                self.next_chunk()?;
+            }
+            let available = &self.current[self.current_pos..];
+            let len = available.len().min(buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            self.current_pos += len;
+            Ok(len)
+        });
+        if result.is_err() {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+impl<'a> Drop for ParallelReader<'a> {
+    fn drop(&mut self) {
+        // Hang up on the workers and wait for them so none of them are
+        // still using the fd when the file closes
+        self.requests = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::error::Error as err;
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+/// Why a path was rejected by normalize or normalize_within
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathError {
+    /// The path contains a NUL byte, which can't be passed to gfapi
+    ContainsNul { path: PathBuf, position: usize },
+    /// The path is absolute or its .. components climb above the base it
+    /// was supposed to stay inside
+    EscapesBase { path: PathBuf, base: PathBuf },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathError::ContainsNul { ref path, position } => write!(
+                f,
+                "{} contains a NUL byte at position {}",
+                path.display(),
+                position
+            ),
+            PathError::EscapesBase { ref path, ref base } => write!(
+                f,
+                "{} is outside of {}",
+                path.display(),
+                base.display()
+            ),
+        }
+    }
+}
+
+impl err for PathError {
+    fn description(&self) -> &str {
+        match *self {
+            PathError::ContainsNul { .. } => "path contains a NUL byte",
+            PathError::EscapesBase { .. } => "path escapes its base directory",
+        }
+    }
+}
+
+fn check_nul(path: &Path) -> Result<(), PathError> {
+    match path.as_os_str().as_bytes().iter().position(|b| *b == 0) {
+        Some(position) => Err(PathError::ContainsNul {
+            path: path.to_path_buf(),
+            position,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Clean up a path without touching the filesystem.  . components and
+/// repeated or trailing separators are dropped and .. removes the
+/// component before it.  .. at the start of a relative path is kept, and
+/// .. directly under / is dropped like the kernel does.  A relative path
+/// that cancels out entirely becomes ".".
+///
+/// Lexical .. resolution is only right when nothing along the path is a
+/// symlink, which is why ScopedGluster also checks for those.
+pub fn normalize(path: &Path) -> Result<PathBuf, PathError> {
+    check_nul(path)?;
+    let mut absolute = false;
+    let mut parts: Vec<&OsStr> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::Prefix(_) => absolute = true,
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(last) if *last != OsStr::new("..") => {
+                    parts.pop();
+                }
+                _ => {
+                    if !absolute {
+                        parts.push(OsStr::new(".."));
+                    }
+                }
+            },
+            Component::Normal(part) => parts.push(part),
+        }
+    }
+    let mut normalized = if absolute {
+        PathBuf::from("/")
+    } else {
+        PathBuf::new()
+    };
+    for part in parts {
+        normalized.push(part);
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    Ok(normalized)
+}
+
+/// True if a normalized relative path climbs above where it starts
+pub fn escapes(normalized: &Path) -> bool {
+    normalized.has_root() || normalized.starts_with("..")
+}
+
+/// Normalize an untrusted relative path and join it onto base.  Fails
+/// with PathError::EscapesBase if path is absolute or would end up
+/// outside of base.  Use this before handing user supplied names to
+/// unlink, open and friends.
+pub fn normalize_within(base: &Path, path: &Path) -> Result<PathBuf, PathError> {
+    let normalized = normalize(path)?;
+    if escapes(&normalized) {
+        return Err(PathError::EscapesBase {
+            path: path.to_path_buf(),
+            base: base.to_path_buf(),
+        });
+    }
+    if normalized == Path::new(".") {
+        return Ok(base.to_path_buf());
+    }
+    Ok(base.join(normalized))
+}
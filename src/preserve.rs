@@ -0,0 +1,180 @@
+use errno::{errno, Errno};
+use libc::{stat, timespec, EPERM, O_RDONLY};
+
+use gluster::{Gluster, GlusterError};
+use xattr::XattrNamespace;
+
+use std::path::Path;
+
+/// Which of a copy's source attributes to carry over to the destination,
+/// set with WriteOptions::preserve.  Nothing is preserved by default,
+/// PreserveOptions::all is what cp -a does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreserveOptions {
+    times: bool,
+    mode: bool,
+    ownership: bool,
+    xattrs: Vec<XattrNamespace>,
+    best_effort: bool,
+}
+
+impl PreserveOptions {
+    pub fn new() -> PreserveOptions {
+        PreserveOptions::default()
+    }
+
+    /// Times, mode, ownership and user and security xattrs, with
+    /// ownership left behind if it can't be set
+    pub fn all() -> PreserveOptions {
+        PreserveOptions {
+            times: true,
+            mode: true,
+            ownership: true,
+            xattrs: vec![XattrNamespace::User, XattrNamespace::Security],
+            best_effort: true,
+        }
+    }
+
+    /// Set the destination's atime and mtime to the source's once the
+    /// data is written
+    pub fn times(mut self, preserve: bool) -> PreserveOptions {
+        self.times = preserve;
+        self
+    }
+
+    /// Give the destination the source's permission bits, umask and
+    /// WriteOptions::mode notwithstanding
+    pub fn mode(mut self, preserve: bool) -> PreserveOptions {
+        self.mode = preserve;
+        self
+    }
+
+    /// chown the destination to the source's owner and group, which
+    /// takes privilege on the bricks unless they're already the caller's
+    pub fn ownership(mut self, preserve: bool) -> PreserveOptions {
+        self.ownership = preserve;
+        self
+    }
+
+    /// Copy the source's extended attributes in these namespaces.
+    /// Writing trusted.* needs CAP_SYS_ADMIN on the bricks.
+    pub fn xattrs(mut self, namespaces: &[XattrNamespace]) -> PreserveOptions {
+        self.xattrs = namespaces.to_vec();
+        self
+    }
+
+    /// Turn EPERM from chown or from setting a trusted.* xattr into a
+    /// warning in the CopyReport instead of failing the copy.  Other
+    /// errors still fail it.
+    pub fn best_effort(mut self, best_effort: bool) -> PreserveOptions {
+        self.best_effort = best_effort;
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.times && !self.mode && !self.ownership && self.xattrs.is_empty()
+    }
+}
+
+/// Something a best effort copy couldn't preserve
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreserveItem {
+    Ownership,
+    /// The raw name of an extended attribute
+    Xattr(Vec<u8>),
+}
+
+/// A PreserveItem left behind and why
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreserveWarning {
+    pub item: PreserveItem,
+    pub error: String,
+}
+
+/// What Gluster::copy_with_report did
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CopyReport {
+    /// Bytes of file data copied
+    pub bytes: u64,
+    /// Attributes best_effort skipped
+    pub warnings: Vec<PreserveWarning>,
+}
+
+// Either a warning, when the failure was EPERM and best_effort allows
+// skipping item, or the error
+fn tolerate(
+    opts: &PreserveOptions,
+    item: PreserveItem,
+    result: Result<(), GlusterError>,
+    warnings: &mut Vec<PreserveWarning>,
+) -> Result<(), GlusterError> {
+    let error = match result {
+        Ok(()) => return Ok(()),
+        Err(error) => error,
+    };
+    let skippable = match item {
+        PreserveItem::Ownership => true,
+        PreserveItem::Xattr(ref name) => XattrNamespace::of(name) == Some(XattrNamespace::Trusted),
+    };
+    if opts.best_effort && skippable && errno() == Errno(EPERM) {
+        warnings.push(PreserveWarning {
+            item,
+            error: error.to_string(),
+        });
+        return Ok(());
+    }
+    Err(error)
+}
+
+/// Apply opts to the file at to from source, the stat of from on
+/// source_gluster, returning the warnings of anything best_effort let
+/// through.  Ownership goes first since chown can clear set-id bits, and
+/// times last so nothing else bumps them.
+pub(crate) fn apply(
+    source_gluster: &Gluster,
+    from: &Path,
+    source: &stat,
+    dest_gluster: &Gluster,
+    to: &Path,
+    opts: &PreserveOptions,
+) -> Result<Vec<PreserveWarning>, GlusterError> {
+    let mut warnings = Vec::new();
+    if opts.is_empty() {
+        return Ok(warnings);
+    }
+    let dest = dest_gluster.open_file(to, O_RDONLY)?;
+    let handle = dest.handle()?;
+    if opts.ownership {
+        let result = dest_gluster.fchown(handle, source.st_uid, source.st_gid);
+        tolerate(opts, PreserveItem::Ownership, result, &mut warnings)?;
+    }
+    if opts.mode {
+        dest_gluster.fchmod(handle, source.st_mode & 0o7777)?;
+    }
+    if !opts.xattrs.is_empty() {
+        for name in source_gluster.list_xattr_raw(from)? {
+            match XattrNamespace::of(&name) {
+                Some(namespace) if opts.xattrs.contains(&namespace) => {}
+                _ => continue,
+            }
+            let value = source_gluster.getxattr_raw_name(from, &name)?;
+            let result = dest_gluster.setxattr_raw_name(to, &name, &value, 0);
+            tolerate(opts, PreserveItem::Xattr(name), result, &mut warnings)?;
+        }
+    }
+    if opts.times {
+        let times = [
+            timespec {
+                tv_sec: source.st_atime,
+                tv_nsec: source.st_atime_nsec,
+            },
+            timespec {
+                tv_sec: source.st_mtime,
+                tv_nsec: source.st_mtime_nsec,
+            },
+        ];
+        dest_gluster.futimens(handle, &times)?;
+    }
+    dest.close()?;
+    Ok(warnings)
+}
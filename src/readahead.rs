@@ -0,0 +1,328 @@
+use errno::errno;
+use glfs::*;
+use libc::c_void;
+
+use file::GlusterFile;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+// Non sequential seeks in a row tolerated before readahead switches
+// itself off
+const MAX_RANDOM_SEEKS: u32 = 3;
+
+pub(crate) struct FileHandle(pub(crate) *mut Struct_glfs_fd);
+// Only used for glfs_pread which is safe to call from any thread
+unsafe impl Send for FileHandle {}
+unsafe impl Sync for FileHandle {}
+
+// A single glfs_pread of up to len bytes at offset
+pub(crate) fn pread_chunk(
+    file_handle: &FileHandle,
+    offset: u64,
+    len: usize,
+) -> io::Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = vec![0; len];
+    let read_size = unsafe {
+        glfs_pread(
+            file_handle.0,
+            buffer.as_mut_ptr() as *mut c_void,
+            len,
+            offset as i64,
+            0,
+        )
+    };
+    if read_size < 0 {
+        return Err(io::Error::from_raw_os_error(errno().0));
+    }
+    buffer.truncate(read_size as usize);
+    Ok(buffer)
+}
+
+// Read len bytes at offset with pread, fewer only at the end of the file.
+// A single pread can come back short anywhere.
+pub(crate) fn pread_range<R>(pread: &R, offset: u64, len: usize) -> io::Result<Vec<u8>>
+where
+    R: Fn(u64, usize) -> io::Result<Vec<u8>>,
+{
+    let mut data = pread(offset, len)?;
+    while data.len() < len {
+        let more = pread(offset + data.len() as u64, len - data.len())?;
+        if more.is_empty() {
+            break;
+        }
+        data.extend_from_slice(&more);
+    }
+    Ok(data)
+}
+
+struct Request {
+    generation: u64,
+    offset: u64,
+    len: usize,
+}
+
+struct Response {
+    generation: u64,
+    offset: u64,
+    data: io::Result<Vec<u8>>,
+}
+
+/// A file that keeps several reads in flight ahead of the consumer.
+/// Created with GlusterFile::with_readahead, or ReadAhead::new for
+/// anything else with a positioned read.  Bytes are always delivered in
+/// order.  At most window_bytes are buffered at any time.  Seeking
+/// discards the window, and after a few seeks in a row that land away
+/// from it readahead switches itself off, stopping its workers, and
+/// reads go straight to the file.
+pub struct ReadAhead<F> {
+    file: F,
+    chunk_size: usize,
+    depth: usize,
+    // Where the consumer is reading from
+    position: u64,
+    // Offset of the next chunk to request
+    next_request: u64,
+    generation: u64,
+    outstanding: VecDeque<u64>,
+    ready: HashMap<u64, io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    current_pos: usize,
+    eof: bool,
+    random_seeks: u32,
+    disabled: bool,
+    requests: Option<Sender<Request>>,
+    responses: Receiver<Response>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<'a> GlusterFile<'a> {
+    /// Read this file sequentially with up to depth reads of
+    /// window_bytes / depth each in flight at once.  The current file
+    /// offset is where reading starts.
+    pub fn with_readahead(
+        self,
+        window_bytes: usize,
+        depth: usize,
+    ) -> io::Result<ReadAhead<GlusterFile<'a>>> {
+        let file_handle = FileHandle(self.io_handle()?);
+        let pread = move |offset, len| pread_chunk(&file_handle, offset, len);
+        ReadAhead::new(self, pread, window_bytes, depth)
+    }
+}
+
+impl<F: Read + Seek> ReadAhead<F> {
+    /// Read file sequentially from its current position with up to depth
+    /// reads of window_bytes / depth each in flight at once.  pread(offset,
+    /// len) reads up to len bytes at offset without moving file's
+    /// position, and is called from depth worker threads at once.  Once
+    /// readahead switches itself off, reads and seeks go to file.
+    pub fn new<P>(
+        mut file: F,
+        pread: P,
+        window_bytes: usize,
+        depth: usize,
+    ) -> io::Result<ReadAhead<F>>
+    where
+        P: Fn(u64, usize) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        let depth = depth.max(1);
+        let chunk_size = (window_bytes / depth).max(1);
+        let position = file.stream_position()?;
+        let pread = Arc::new(pread);
+        let (request_tx, request_rx) = channel::<Request>();
+        let (response_tx, response_rx) = channel::<Response>();
+        let request_rx = Arc::new(Mutex::new(request_rx));
+        let mut workers = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            let requests = request_rx.clone();
+            let responses = response_tx.clone();
+            let pread = pread.clone();
+            workers.push(thread::spawn(move || loop {
+                let request = match requests.lock().unwrap().recv() {
+                    Ok(r) => r,
+                    // The ReadAhead was dropped
+                    Err(_) => return,
+                };
+                let data = pread_range(&*pread, request.offset, request.len);
+                let response = Response {
+                    generation: request.generation,
+                    offset: request.offset,
+                    data,
+                };
+                if responses.send(response).is_err() {
+                    return;
+                }
+            }));
+        }
+        Ok(ReadAhead {
+            file,
+            chunk_size,
+            depth,
+            position,
+            next_request: position,
+            generation: 0,
+            outstanding: VecDeque::with_capacity(depth),
+            ready: HashMap::new(),
+            current: Vec::new(),
+            current_pos: 0,
+            eof: false,
+            random_seeks: 0,
+            disabled: false,
+            requests: Some(request_tx),
+            responses: response_rx,
+            workers,
+        })
+    }
+}
+
+impl<F> ReadAhead<F> {
+    /// False once readahead has switched itself off after random seeks
+    pub fn is_active(&self) -> bool {
+        !self.disabled
+    }
+
+    fn fill_pipeline(&mut self) {
+        while !self.eof && self.outstanding.len() < self.depth {
+            let request = Request {
+                generation: self.generation,
+                offset: self.next_request,
+                len: self.chunk_size,
+            };
+            if let Some(ref requests) = self.requests {
+                let _ = requests.send(request);
+            }
+            self.outstanding.push_back(self.next_request);
+            self.next_request += self.chunk_size as u64;
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Vec<u8>> {
+        self.fill_pipeline();
+        let offset = match self.outstanding.pop_front() {
+            Some(offset) => offset,
+            None => return Ok(Vec::new()),
+        };
+        loop {
+            if let Some(data) = self.ready.remove(&offset) {
+                let data = data?;
+                if data.len() < self.chunk_size {
+                    // Short preads are retried, so this is the end of the
+                    // file and anything queued after it is past the end
+                    self.eof = true;
+                    self.outstanding.clear();
+                }
+                return Ok(data);
+            }
+            let response = self.responses
+                .recv()
+                .map_err(|_| io::Error::other("readahead worker exited"))?;
+            if response.generation == self.generation {
+                self.ready.insert(response.offset, response.data);
+            }
+        }
+    }
+
+    // Whether a seek to target lands in or just after the window, from
+    // the start of the chunk being read to the end of what readahead
+    // would have asked for next.  Skipping ahead like that is still a
+    // sequential read.
+    fn near_window(&self, target: u64) -> bool {
+        let start = self.position - self.current_pos as u64;
+        let end = self.next_request + (self.chunk_size * self.depth) as u64;
+        target >= start && target <= end
+    }
+
+    // Hang up on the workers and wait for them so none of them are still
+    // using the file
+    fn stop_workers(&mut self) {
+        self.requests = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+    fn discard_window(&mut self) {
+        self.generation += 1;
+        self.outstanding.clear();
+        self.ready.clear();
+        self.current.clear();
+        self.current_pos = 0;
+        self.eof = false;
+        self.next_request = self.position;
+    }
+}
+
+impl<F: Read> Read for ReadAhead<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.disabled {
+            let read = self.file.read(buf)?;
+            self.position += read as u64;
+            return Ok(read);
+        }
+        if self.current_pos == self.current.len() {
+            if !self.current.is_empty() {
+                // A whole chunk was read through, so whatever seeks came
+                // before weren't a random access pattern
+                self.random_seeks = 0;
+            }
+            if self.eof && self.outstanding.is_empty() {
+                return Ok(0);
+            }
+            self.current = self.next_chunk()?;
+            self.current_pos = 0;
+            if self.current.is_empty() {
+                return Ok(0);
+            }
+        }
+        let available = &self.current[self.current_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.current_pos += len;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<F: Seek> Seek for ReadAhead<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_position = match pos {
+            // The file's position doesn't move while reads are in flight
+            SeekFrom::Current(n) => {
+                let target = (self.position as i64 + n) as u64;
+                self.file.seek(SeekFrom::Start(target))?
+            }
+            other => self.file.seek(other)?,
+        };
+        if new_position == self.position {
+            return Ok(new_position);
+        }
+        if !self.disabled {
+            if self.near_window(new_position) {
+                self.random_seeks = 0;
+            } else {
+                self.random_seeks += 1;
+                if self.random_seeks >= MAX_RANDOM_SEEKS {
+                    self.disabled = true;
+                    self.discard_window();
+                    self.stop_workers();
+                }
+            }
+        }
+        self.position = new_position;
+        if !self.disabled {
+            self.discard_window();
+        }
+        Ok(new_position)
+    }
+}
+
+impl<F> Drop for ReadAhead<F> {
+    fn drop(&mut self) {
+        // The fd closes when the file is dropped after this
+        self.stop_workers();
+    }
+}
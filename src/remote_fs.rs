@@ -0,0 +1,257 @@
+use errno::{errno, set_errno, Errno};
+use libc::{mode_t, ENODATA, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+
+use gluster::{DirEntry, Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What kind of object a path names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoteFileType {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, fifos and sockets
+    Other,
+}
+
+/// The part of a stat every backend can fill in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteMetadata {
+    pub file_type: RemoteFileType,
+    /// Size in bytes, 0 for directories on backends that don't track one
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+impl RemoteMetadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == RemoteFileType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == RemoteFileType::File
+    }
+}
+
+/// One entry of a directory, without . and ..
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteDirEntry {
+    /// The entry's name, not its full path
+    pub name: OsString,
+    pub file_type: RemoteFileType,
+}
+
+/// The entries read_dir returns, in no particular order
+pub type RemoteReadDir<'a> = Box<dyn Iterator<Item = io::Result<RemoteDirEntry>> + 'a>;
+
+/// The file operations application code needs, over any backend.  It's
+/// implemented by Gluster, LocalFs for a local directory, MemoryFs for
+/// tests, and the DryRunGluster, AuditedGluster and ScopedGluster
+/// wrappers, and is object safe so a Box<dyn RemoteFs> can be picked at
+/// runtime.
+///
+/// Paths are absolute within the backend, "/" being its root.  Errors
+/// are io::Errors carrying the errno of the failure where there is one,
+/// so NotFound, AlreadyExists and friends can be matched the same way on
+/// every backend.
+pub trait RemoteFs: Send + Sync {
+    /// The whole contents of a file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Create or truncate the file at path and write data into it.  The
+    /// parent directory has to exist.
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Metadata of path, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata>;
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>>;
+
+    /// Create a single directory, failing if it exists
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Rename from to to, replacing to if it's a file or an empty
+    /// directory, as rename(2) does
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove an empty directory
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Whether anything exists at path.  Errors other than NotFound are
+    /// returned.
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.metadata(path) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Extended attributes, for backends that have them
+pub trait RemoteXattrs: RemoteFs {
+    /// The value of name on path, None if it isn't set
+    fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Set name on path, replacing any value it had
+    fn set_xattr(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()>;
+
+    fn list_xattrs(&self, path: &Path) -> io::Result<Vec<String>>;
+
+    /// Remove name from path, failing if it isn't set
+    fn remove_xattr(&self, path: &Path, name: &str) -> io::Result<()>;
+}
+
+/// Create path and whichever of its parents are missing, outermost
+/// first, like std::fs::create_dir_all.  Works over any RemoteFs,
+/// including the dry run, audited and scoped wrappers.
+pub fn create_dir_all<F: RemoteFs + ?Sized>(fs: &F, path: &Path) -> io::Result<()> {
+    let mut missing = Vec::new();
+    let mut current = Some(path);
+    while let Some(dir) = current {
+        if dir.as_os_str().is_empty() || fs.exists(dir)? {
+            break;
+        }
+        missing.push(dir);
+        current = dir.parent();
+    }
+    for dir in missing.into_iter().rev() {
+        if let Err(e) = fs.create_dir(dir) {
+            // Made by someone else in the meantime
+            if e.kind() != io::ErrorKind::AlreadyExists || !fs.metadata(dir)?.is_dir() {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove everything under path and then path itself, without following
+/// symlinks.  Entries are visited in name order so a dry run's plan is
+/// repeatable.
+pub fn remove_dir_all<F: RemoteFs + ?Sized>(fs: &F, path: &Path) -> io::Result<()> {
+    let mut entries = fs.read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    for entry in entries {
+        let child = path.join(&entry.name);
+        if entry.file_type == RemoteFileType::Dir {
+            remove_dir_all(fs, &child)?;
+        } else {
+            fs.remove_file(&child)?;
+        }
+    }
+    fs.remove_dir(path)
+}
+
+// Run a gfapi call and turn its failure into an io::Error.  errno is
+// cleared first so a GlusterError::Error that didn't come from gfapi
+// isn't given a stale errno.
+pub(crate) fn io_result<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> Result<T, GlusterError>,
+{
+    set_errno(Errno(0));
+    f().map_err(|e| match e {
+        GlusterError::IoError(e) => e,
+        GlusterError::Error(_) if errno().0 != 0 => io::Error::from_raw_os_error(errno().0),
+        e => io::Error::other(e),
+    })
+}
+
+fn file_type_from_mode(mode: mode_t) -> RemoteFileType {
+    match mode & S_IFMT {
+        S_IFREG => RemoteFileType::File,
+        S_IFDIR => RemoteFileType::Dir,
+        S_IFLNK => RemoteFileType::Symlink,
+        _ => RemoteFileType::Other,
+    }
+}
+
+pub(crate) fn remote_metadata(metadata: &Metadata) -> RemoteMetadata {
+    RemoteMetadata {
+        file_type: file_type_from_mode(metadata.mode()),
+        len: metadata.len(),
+        modified: metadata.modified(),
+    }
+}
+
+// read_dir over a listing already fetched with list_dir
+pub(crate) fn remote_read_dir<'a>(entries: Vec<(DirEntry, Metadata)>) -> RemoteReadDir<'a> {
+    Box::new(entries.into_iter().map(|(entry, metadata)| {
+        Ok(RemoteDirEntry {
+            name: entry.path.into_os_string(),
+            file_type: file_type_from_mode(metadata.mode()),
+        })
+    }))
+}
+
+impl RemoteFs for Gluster {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        io_result(|| self.read_to_vec(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        io_result(|| self.write_file(path, data))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let metadata = io_result(|| Gluster::metadata(self, path))?;
+        Ok(remote_metadata(&metadata))
+    }
+
+    /// The directory is listed with readdirplus up front, the iterator
+    /// walks the result
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        Ok(remote_read_dir(io_result(|| self.list_dir(path, 1))?))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.mkdir(path, 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        io_result(|| Gluster::rename(self, from, to))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.unlink(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.rmdir(path))
+    }
+}
+
+impl RemoteXattrs for Gluster {
+    fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match io_result(|| self.getxattr_raw_name(path, name.as_bytes())) {
+            Ok(value) => Ok(Some(value)),
+            Err(ref e) if e.raw_os_error() == Some(ENODATA) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn set_xattr(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        // flags 0 creates or replaces
+        io_result(|| self.setxattr_raw_name(path, name.as_bytes(), value, 0))
+    }
+
+    fn list_xattrs(&self, path: &Path) -> io::Result<Vec<String>> {
+        let names = io_result(|| self.list_xattr_raw(path))?;
+        Ok(names
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .collect())
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &str) -> io::Result<()> {
+        io_result(|| self.removexattr(path, name))
+    }
+}
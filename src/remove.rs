@@ -0,0 +1,188 @@
+use errno::{errno, Errno};
+use libc::{EACCES, ENOENT, ENOTEMPTY, EPERM};
+
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use walk::{WalkEntry, WalkOptions};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Options for Gluster::remove_matching
+#[derive(Clone, Debug, Default)]
+pub struct RemoveOptions {
+    prune_empty_dirs: bool,
+    dry_run: bool,
+}
+
+impl RemoveOptions {
+    pub fn new() -> RemoveOptions {
+        RemoveOptions::default()
+    }
+
+    /// Also remove directories left empty once their matching entries
+    /// are gone, working up towards the root, which is always kept.
+    /// Directories that were empty to begin with are left alone.
+    /// Defaults to false.
+    pub fn prune_empty_dirs(mut self, prune: bool) -> RemoveOptions {
+        self.prune_empty_dirs = prune;
+        self
+    }
+
+    /// Only report what would be removed.  Defaults to false.
+    pub fn dry_run(mut self, dry_run: bool) -> RemoveOptions {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// An entry remove_matching wasn't allowed to remove or list
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoveFailure {
+    /// None for a directory the walk couldn't list, which it doesn't
+    /// name
+    pub path: Option<PathBuf>,
+    pub error: String,
+}
+
+/// What Gluster::remove_matching removed, or would have in a dry run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemoveReport {
+    /// Files, symlinks and other non-directories, in walk order
+    pub removed: Vec<PathBuf>,
+    /// Directories pruned, deepest first
+    pub pruned: Vec<PathBuf>,
+    /// Matching entries that were already gone when their turn came
+    pub vanished: usize,
+    /// Permission errors, which don't stop the rest of the tree
+    pub failures: Vec<RemoveFailure>,
+}
+
+// How many entries of a directory the walk saw and how many of them were
+// removed, to tell which directories end up empty
+#[derive(Default)]
+struct DirCount {
+    seen: usize,
+    removed: usize,
+}
+
+// What errno says about a call that just failed: the entry vanished, or
+// a permission problem to report and carry on from.  None for anything
+// else.
+enum Tolerated {
+    Vanished,
+    Denied,
+}
+
+fn tolerated() -> Option<Tolerated> {
+    let error = errno();
+    if error == Errno(ENOENT) {
+        Some(Tolerated::Vanished)
+    } else if error == Errno(EACCES) || error == Errno(EPERM) {
+        Some(Tolerated::Denied)
+    } else {
+        None
+    }
+}
+
+impl Gluster {
+    /// Walk root depth first and remove every file, symlink or other
+    /// non-directory below it that pred accepts, for clearing out
+    /// scratch space by age and the like.  Directories are never matched,
+    /// see RemoveOptions::prune_empty_dirs.  Symlinks are removed, never
+    /// followed.  Entries that disappear before they're reached are
+    /// counted as vanished, and permission errors are collected in the
+    /// report, anything else stops the removal.  pred gets each entry's
+    /// lstat metadata alongside it.
+    pub fn remove_matching<F>(
+        &self,
+        root: &Path,
+        pred: F,
+        opts: &RemoveOptions,
+    ) -> Result<RemoveReport, GlusterError>
+    where
+        F: Fn(&WalkEntry, &Metadata) -> bool,
+    {
+        if !opts.dry_run {
+            self.check_writable()?;
+        }
+        // Past here a missing entry is a race, but a missing root isn't
+        self.symlink_metadata(root)?;
+        let mut report = RemoveReport::default();
+        let mut counts: HashMap<PathBuf, DirCount> = HashMap::new();
+        // Pre-order, so reversed it has every directory after its children
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for entry in self.walk(root, &WalkOptions::new().min_depth(1)) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => match tolerated() {
+                    Some(Tolerated::Vanished) => continue,
+                    Some(Tolerated::Denied) => {
+                        report.failures.push(RemoveFailure {
+                            path: None,
+                            error: error.to_string(),
+                        });
+                        continue;
+                    }
+                    None => return Err(error),
+                },
+            };
+            let parent = entry.path.parent().unwrap_or(root).to_path_buf();
+            counts.entry(parent.clone()).or_default().seen += 1;
+            if entry.metadata.is_dir() {
+                dirs.push(entry.path.clone());
+                continue;
+            }
+            if !pred(&entry, &entry.metadata) {
+                continue;
+            }
+            if !opts.dry_run {
+                if let Err(error) = self.unlink(&entry.path) {
+                    match tolerated() {
+                        Some(Tolerated::Vanished) => report.vanished += 1,
+                        Some(Tolerated::Denied) => report.failures.push(RemoveFailure {
+                            path: Some(entry.path),
+                            error: error.to_string(),
+                        }),
+                        None => return Err(error),
+                    }
+                    continue;
+                }
+            }
+            counts.entry(parent).or_default().removed += 1;
+            report.removed.push(entry.path);
+        }
+
+        if !opts.prune_empty_dirs {
+            return Ok(report);
+        }
+        for dir in dirs.into_iter().rev() {
+            let emptied = match counts.get(&dir) {
+                Some(count) => count.removed > 0 && count.removed == count.seen,
+                None => false,
+            };
+            if !emptied {
+                continue;
+            }
+            if !opts.dry_run {
+                if let Err(error) = self.rmdir(&dir) {
+                    match tolerated() {
+                        Some(Tolerated::Vanished) => report.vanished += 1,
+                        Some(Tolerated::Denied) => report.failures.push(RemoveFailure {
+                            path: Some(dir),
+                            error: error.to_string(),
+                        }),
+                        // Something new was created in it meanwhile
+                        None if errno() == Errno(ENOTEMPTY) => {}
+                        None => return Err(error),
+                    }
+                    continue;
+                }
+            }
+            let parent = dir.parent().unwrap_or(root).to_path_buf();
+            counts.entry(parent).or_default().removed += 1;
+            report.pruned.push(dir);
+        }
+        Ok(report)
+    }
+}
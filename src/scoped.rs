@@ -0,0 +1,248 @@
+use errno::{errno, set_errno, Errno};
+use libc::{mode_t, EACCES, ENOENT, O_NOFOLLOW};
+
+use file::GlusterFile;
+use gluster::{DirEntry, Gluster, GlusterError};
+use metadata::Metadata;
+use path::{self, PathError};
+use remote_fs::{io_result, remote_metadata, remote_read_dir};
+use remote_fs::{RemoteFs, RemoteMetadata, RemoteReadDir};
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A view of a connection confined to one directory.  Paths given to it
+/// are relative to that directory, and anything that would reach outside
+/// it is refused with GlusterError::EscapesRoot: absolute paths, paths
+/// whose .. components climb above the root, and paths that go through a
+/// symlink.
+///
+/// Symlinks are checked by lstat'ing every directory on the way to the
+/// target and files are opened with O_NOFOLLOW.  Another client swapping
+/// a directory for a symlink between the check and the call can still
+/// get past that (the same TOCTOU race as in any userspace jail), so if
+/// untrusted parties can write inside the root use a subdirectory mount
+/// (GlusterBuilder::subdir) instead, which the server enforces.
+#[derive(Debug)]
+pub struct ScopedGluster<'a> {
+    gluster: &'a Gluster,
+    root: PathBuf,
+}
+
+fn refuse(path: &Path) -> GlusterError {
+    set_errno(Errno(EACCES));
+    GlusterError::EscapesRoot {
+        path: path.to_path_buf(),
+    }
+}
+
+// Lexically resolve path relative to the root, refusing anything
+// absolute or climbing above it
+fn normalize(path: &Path) -> Result<Vec<PathBuf>, GlusterError> {
+    match path::normalize_within(Path::new(""), path) {
+        Ok(normalized) => Ok(normalized.iter().map(PathBuf::from).collect()),
+        Err(PathError::EscapesBase { .. }) => Err(refuse(path)),
+        Err(e) => Err(GlusterError::from(e)),
+    }
+}
+
+impl<'a> ScopedGluster<'a> {
+    /// The directory everything is confined to, as a path on the volume
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The connection being confined.  Anything done through it directly
+    /// isn't checked.
+    pub fn gluster(&self) -> &'a Gluster {
+        self.gluster
+    }
+
+    /// Map a path relative to the root onto the volume, refusing it if it
+    /// escapes the root or passes through a symlink.  The last component
+    /// may itself be a symlink, callers that would follow it check that.
+    pub fn resolve(&self, path: &Path) -> Result<PathBuf, GlusterError> {
+        let parts = normalize(path)?;
+        let mut resolved = self.root.clone();
+        for (i, part) in parts.iter().enumerate() {
+            resolved.push(part);
+            if i + 1 == parts.len() {
+                break;
+            }
+            match self.gluster.symlink_metadata(&resolved) {
+                Ok(ref metadata) if metadata.is_symlink() => return Err(refuse(path)),
+                Ok(_) => {}
+                // Whatever is called next fails with ENOENT too
+                Err(e) => {
+                    if errno() == Errno(ENOENT) {
+                        resolved.extend(&parts[i + 1..]);
+                        return Ok(resolved);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    // resolve, and also refuse a symlink as the last component for calls
+    // that would follow it
+    fn resolve_no_follow(&self, path: &Path) -> Result<PathBuf, GlusterError> {
+        let resolved = self.resolve(path)?;
+        match self.gluster.symlink_metadata(&resolved) {
+            Ok(ref metadata) if metadata.is_symlink() => Err(refuse(path)),
+            _ => Ok(resolved),
+        }
+    }
+
+    pub fn open_file(&self, path: &Path, flags: i32) -> Result<GlusterFile<'a>, GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.open_file(&resolved, flags | O_NOFOLLOW)
+    }
+
+    pub fn create_file(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+    ) -> Result<GlusterFile<'a>, GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.create_file(&resolved, flags | O_NOFOLLOW, mode)
+    }
+
+    pub fn read_to_vec(&self, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        self.gluster.read_to_vec(&resolved)
+    }
+
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        self.gluster.write_file(&resolved, data)
+    }
+
+    pub fn metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        self.gluster.metadata(&resolved)
+    }
+
+    pub fn symlink_metadata(&self, path: &Path) -> Result<Metadata, GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.symlink_metadata(&resolved)
+    }
+
+    pub fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.exists(&resolved)
+    }
+
+    pub fn list_dir(&self, path: &Path) -> Result<Vec<(DirEntry, Metadata)>, GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        self.gluster.list_dir(&resolved, 1)
+    }
+
+    pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.mkdir(&resolved, mode)
+    }
+
+    pub fn create_dir_all(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        self.gluster.create_dir_all(&resolved, mode)
+    }
+
+    pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.unlink(&resolved)
+    }
+
+    pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        let resolved = self.resolve(path)?;
+        self.gluster.rmdir(&resolved)
+    }
+
+    pub fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        let resolved = self.resolve_no_follow(path)?;
+        if resolved == self.root {
+            return Err(refuse(path));
+        }
+        self.gluster.remove_dir_all(&resolved)
+    }
+
+    /// Rename within the root.  Both paths are checked.
+    pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let old_resolved = self.resolve(oldpath)?;
+        let new_resolved = self.resolve(newpath)?;
+        self.gluster.rename(&old_resolved, &new_resolved)
+    }
+
+    /// Hard link within the root.  Both paths are checked.
+    pub fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let old_resolved = self.resolve(oldpath)?;
+        let new_resolved = self.resolve(newpath)?;
+        self.gluster.link(&old_resolved, &new_resolved)
+    }
+
+    /// Create a symlink at linkpath pointing to target.  The target must
+    /// be relative and stay inside the root when followed from the link's
+    /// directory, so the jail can't be used to plant a way out of it for
+    /// clients that do follow symlinks.
+    pub fn symlink(&self, target: &Path, linkpath: &Path) -> Result<(), GlusterError> {
+        let link_dir = linkpath.parent().unwrap_or_else(|| Path::new(""));
+        normalize(&link_dir.join(target)).map_err(|_| refuse(target))?;
+        let resolved = self.resolve(linkpath)?;
+        self.gluster.symlink(target, &resolved)
+    }
+}
+
+// RemoteFs paths start at "/", which here is the root
+fn relative(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// "/" is the root.  Paths are checked as with the inherent methods, and
+/// a refusal is an io::Error wrapping GlusterError::EscapesRoot.
+impl<'a> RemoteFs for ScopedGluster<'a> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        io_result(|| self.read_to_vec(relative(path)))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        io_result(|| self.write_file(relative(path), data))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let metadata = io_result(|| ScopedGluster::metadata(self, relative(path)))?;
+        Ok(remote_metadata(&metadata))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        let entries = io_result(|| self.list_dir(relative(path)))?;
+        Ok(remote_read_dir(entries))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.mkdir(relative(path), 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        io_result(|| ScopedGluster::rename(self, relative(from), relative(to)))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.unlink(relative(path)))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        io_result(|| self.rmdir(relative(path)))
+    }
+}
+
+impl Gluster {
+    /// Confine calls to the directory root.  See ScopedGluster.
+    pub fn scoped(&self, root: &Path) -> ScopedGluster<'_> {
+        ScopedGluster {
+            gluster: self,
+            root: root.to_path_buf(),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use errno::{errno, Errno};
+use libc::ENODATA;
+
+use gluster::{Gluster, GlusterError};
+
+use std::path::Path;
+
+/// SELinux label of a file
+pub const SELINUX_XATTR: &str = "security.selinux";
+/// File capabilities, a binary vfs_cap_data structure
+pub const CAPABILITY_XATTR: &str = "security.capability";
+
+/// Turn a security.selinux value into a context string.  The kernel and
+/// libselinux store the context with a trailing NUL, which is dropped.
+pub fn decode_selinux_context(value: &[u8]) -> Result<String, GlusterError> {
+    let end = value
+        .iter()
+        .rposition(|b| *b != 0)
+        .map(|last| last + 1)
+        .unwrap_or(0);
+    Ok(String::from_utf8(value[..end].to_vec())?)
+}
+
+/// The security.selinux value for context, NUL terminated the way
+/// setfiles and restorecon write it
+pub fn encode_selinux_context(context: &str) -> Vec<u8> {
+    let mut value = Vec::with_capacity(context.len() + 1);
+    value.extend_from_slice(context.trim_end_matches('\0').as_bytes());
+    value.push(0);
+    value
+}
+
+impl Gluster {
+    // getxattr_bytes with ENODATA turned into None
+    fn optional_xattr(&self, path: &Path, name: &str) -> Result<Option<Vec<u8>>, GlusterError> {
+        match self.getxattr_bytes(path, name) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                if errno() == Errno(ENODATA) {
+                    return Ok(None);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// SELinux context of path, e.g. "system_u:object_r:etc_t:s0", or
+    /// None if it isn't labelled
+    pub fn get_selinux_context(&self, path: &Path) -> Result<Option<String>, GlusterError> {
+        match self.optional_xattr(path, SELINUX_XATTR)? {
+            Some(value) => Ok(Some(decode_selinux_context(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Label path with an SELinux context.  Bricks with SELinux enforcing
+    /// may refuse contexts the client isn't allowed to relabel to.
+    pub fn set_selinux_context(&self, path: &Path, context: &str) -> Result<(), GlusterError> {
+        self.setxattr(path, SELINUX_XATTR, &encode_selinux_context(context), 0)
+    }
+
+    /// Raw security.capability value of path, None if it has no file
+    /// capabilities
+    pub fn get_capability(&self, path: &Path) -> Result<Option<Vec<u8>>, GlusterError> {
+        self.optional_xattr(path, CAPABILITY_XATTR)
+    }
+
+    /// Set the raw security.capability value of path.  Needs
+    /// CAP_SETFCAP on the bricks.
+    pub fn set_capability(&self, path: &Path, value: &[u8]) -> Result<(), GlusterError> {
+        self.setxattr(path, CAPABILITY_XATTR, value, 0)
+    }
+
+    /// Copy the SELinux label and file capabilities of from onto to.
+    /// Attributes from doesn't have are left alone on to.
+    pub fn copy_security_xattrs(&self, from: &Path, to: &Path) -> Result<(), GlusterError> {
+        for name in &[SELINUX_XATTR, CAPABILITY_XATTR] {
+            if let Some(value) = self.optional_xattr(from, name)? {
+                self.setxattr(to, name, &value, 0)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use libc::{O_NOFOLLOW, O_WRONLY};
+
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Options for Gluster::shred
+#[derive(Clone, Debug)]
+pub struct ShredOptions {
+    random_passes: usize,
+    zero_pass: bool,
+    truncate: bool,
+    unlink: bool,
+    force: bool,
+    chunk_size: usize,
+}
+
+impl Default for ShredOptions {
+    fn default() -> ShredOptions {
+        ShredOptions {
+            random_passes: 0,
+            zero_pass: true,
+            truncate: false,
+            unlink: true,
+            force: false,
+            chunk_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ShredOptions {
+    pub fn new() -> ShredOptions {
+        ShredOptions::default()
+    }
+
+    /// Overwrite with random data this many times before the zero pass.
+    /// Defaults to 0.
+    pub fn random_passes(mut self, passes: usize) -> ShredOptions {
+        self.random_passes = passes;
+        self
+    }
+
+    /// Finish with a pass of zeros.  Defaults to true.  Turning it off
+    /// leaves only the random passes, and shred refuses to run with none.
+    pub fn zero_pass(mut self, zero_pass: bool) -> ShredOptions {
+        self.zero_pass = zero_pass;
+        self
+    }
+
+    /// Truncate the file to zero length after overwriting it.  Defaults
+    /// to false.
+    pub fn truncate(mut self, truncate: bool) -> ShredOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Unlink the file at the end.  Defaults to true.
+    pub fn unlink(mut self, unlink: bool) -> ShredOptions {
+        self.unlink = unlink;
+        self
+    }
+
+    /// Shred files with more than one hard link.  Only this name is
+    /// removed, so the overwritten contents stay reachable through the
+    /// others.  Defaults to false.
+    pub fn force(mut self, force: bool) -> ShredOptions {
+        self.force = force;
+        self
+    }
+
+    /// How much is written at a time.  Defaults to 1MiB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> ShredOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    fn passes(&self) -> usize {
+        self.random_passes + if self.zero_pass { 1 } else { 0 }
+    }
+}
+
+/// Progress reported to the callback of shred_with_progress
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShredProgress {
+    /// The pass being written, starting from 1
+    pub pass: usize,
+    pub passes: usize,
+    /// Bytes overwritten so far in this pass
+    pub written: u64,
+    /// Length of the file
+    pub total: u64,
+}
+
+impl Gluster {
+    /// Overwrite the regular file at path and then unlink it.  See
+    /// shred_with_progress.
+    pub fn shred(&self, path: &Path, opts: &ShredOptions) -> Result<(), GlusterError> {
+        self.shred_with_progress(path, opts, |_| {})
+    }
+
+    /// Overwrite the whole length of the regular file at path with random
+    /// data and then zeros as opts asks, syncing after every pass, then
+    /// unlink it.  Options that make no passes at all are refused rather
+    /// than unlinking a file that was never overwritten.  Symlinks and
+    /// anything that isn't a regular file are always refused, and files
+    /// with other hard links are refused unless forced, since the data
+    /// would survive.  progress is called after each chunk.
+    ///
+    /// Gluster may keep old copies of the blocks elsewhere, in snapshots,
+    /// on bricks being healed or in the trash translator, which this can't
+    /// reach.
+    pub fn shred_with_progress<F>(
+        &self,
+        path: &Path,
+        opts: &ShredOptions,
+        mut progress: F,
+    ) -> Result<(), GlusterError>
+    where
+        F: FnMut(&ShredProgress),
+    {
+        if opts.passes() == 0 {
+            return Err(GlusterError::new(format!(
+                "no overwrite passes for {}, refusing to shred it",
+                path.display()
+            )));
+        }
+        let file = self.open_file(path, O_WRONLY | O_NOFOLLOW)?;
+        let metadata = Metadata::from_stat(file.fstat()?);
+        if !metadata.is_file() {
+            return Err(GlusterError::new(format!(
+                "{} isn't a regular file, refusing to shred it",
+                path.display()
+            )));
+        }
+        if metadata.nlink() > 1 && !opts.force {
+            return Err(GlusterError::new(format!(
+                "{} has {} hard links, refusing to shred it without force",
+                path.display(),
+                metadata.nlink()
+            )));
+        }
+
+        let total = metadata.len();
+        let passes = opts.passes();
+        let mut random = if opts.random_passes > 0 {
+            Some(File::open("/dev/urandom")?)
+        } else {
+            None
+        };
+        let mut buffer = vec![0; opts.chunk_size];
+        for pass in 1..=passes {
+            let zeros = opts.zero_pass && pass == passes;
+            if zeros {
+                buffer.iter_mut().for_each(|b| *b = 0);
+            }
+            let mut written = 0;
+            while written < total {
+                let len = (total - written).min(buffer.len() as u64) as usize;
+                if let (false, Some(random)) = (zeros, random.as_mut()) {
+                    random.read_exact(&mut buffer[..len])?;
+                }
+                file.write_all_at(&buffer[..len], written)?;
+                written += len as u64;
+                progress(&ShredProgress {
+                    pass,
+                    passes,
+                    written,
+                    total,
+                });
+            }
+            file.fsync()?;
+        }
+        if opts.truncate {
+            self.ftruncate(file.handle()?, 0)?;
+            file.fsync()?;
+        }
+        file.close()?;
+        if opts.unlink {
+            self.unlink(path)?;
+        }
+        Ok(())
+    }
+}
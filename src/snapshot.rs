@@ -0,0 +1,82 @@
+use errno::{errno, Errno};
+use libc::{ENOENT, EOPNOTSUPP};
+
+use gluster::{Gluster, GlusterError};
+use write::{VerifyMode, WriteOptions};
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Name of the virtual directory user serviceable snapshots (features.uss)
+/// adds to every directory
+pub const SNAPSHOT_DIR: &str = ".snaps";
+
+impl Gluster {
+    /// Names of the snapshots that can be browsed from the directory
+    /// path, sorted.  Fails with GlusterError::SnapshotsNotEnabled when
+    /// the volume doesn't have features.uss turned on.
+    pub fn list_snapshots(&self, path: &Path) -> Result<Vec<String>, GlusterError> {
+        let snaps = path.join(SNAPSHOT_DIR);
+        let listing = match self.list_dir(&snaps, 1) {
+            Ok(listing) => listing,
+            Err(e) => {
+                let error = errno();
+                if error == Errno(ENOENT) || error == Errno(EOPNOTSUPP) {
+                    return Err(GlusterError::SnapshotsNotEnabled { path: snaps });
+                }
+                return Err(e);
+            }
+        };
+        let mut names: Vec<String> = listing
+            .into_iter()
+            .map(|(entry, _)| entry.path.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Where path appears inside snapshot snap, going through the .snaps
+    /// directory of path's parent
+    pub fn snapshot_path(&self, snap: &str, path: &Path) -> PathBuf {
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        let snapshot_dir = parent.join(SNAPSHOT_DIR).join(snap);
+        match path.file_name() {
+            Some(name) => snapshot_dir.join(name),
+            None => snapshot_dir,
+        }
+    }
+
+    /// Read the version of the file at path stored in snapshot snap
+    pub fn read_from_snapshot(&self, snap: &str, path: &Path) -> Result<Vec<u8>, GlusterError> {
+        self.read_to_vec(&self.snapshot_path(snap, path))
+    }
+
+    /// Replace the live file at path with its version from snapshot snap.
+    /// The snapshot copy is written next to path first and renamed over
+    /// it, so readers see either the old or the restored contents.
+    /// Returns the number of bytes restored.
+    pub fn restore_file(&self, snap: &str, path: &Path) -> Result<u64, GlusterError> {
+        let source = self.snapshot_path(snap, path);
+        let metadata = self.metadata(&source)?;
+        let mut scratch_name = ::std::ffi::OsString::from(".");
+        scratch_name.push(path.file_name().unwrap_or_default());
+        scratch_name.push(format!(".restore-{}", process::id()));
+        let scratch = path.with_file_name(scratch_name);
+        // Verifying also syncs the copy before it replaces the live file
+        let opts = WriteOptions::new()
+            .mode(metadata.permissions())
+            .verify(VerifyMode::WholeFile);
+        let restored = match self.copy(&source, &scratch, &opts) {
+            Ok(restored) => restored,
+            Err(e) => {
+                let _ = self.unlink(&scratch);
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.rename(&scratch, path) {
+            let _ = self.unlink(&scratch);
+            return Err(e);
+        }
+        Ok(restored)
+    }
+}
@@ -0,0 +1,61 @@
+use gluster::{Gluster, GlusterError};
+
+use std::path::Path;
+
+// Set by the quota feature on directories with a limit.  The first 8
+// bytes of each are a big endian byte count, the hard limit and the
+// space used respectively.
+const QUOTA_LIMIT_XATTR: &str = "trusted.glusterfs.quota.limit-set";
+const QUOTA_SIZE_XATTR: &str = "trusted.glusterfs.quota.size";
+
+/// Free space as seen from a directory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FreeSpace {
+    /// Bytes that can be written by an unprivileged client
+    pub available: u64,
+    /// Size of the volume, or of the quota when one is tighter
+    pub total: u64,
+}
+
+fn be_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    Some(bytes[..8].iter().fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+}
+
+impl Gluster {
+    /// How much can be written under dir.  statvfs already reports the
+    /// quota instead of the volume size when features.quota-deem-statfs
+    /// is on (the default).  When it's off, the quota xattrs of dir and
+    /// its ancestors are checked too if this client may read them, which
+    /// usually needs root.
+    pub fn free_space(&self, dir: &Path) -> Result<FreeSpace, GlusterError> {
+        let stat = self.statvfs(dir)?;
+        let block_size = if stat.f_frsize > 0 {
+            stat.f_frsize
+        } else {
+            stat.f_bsize
+        };
+        let mut space = FreeSpace {
+            available: stat.f_bavail * block_size,
+            total: stat.f_blocks * block_size,
+        };
+        let mut current = Some(dir);
+        while let Some(path) = current {
+            let limit = self.getxattr_bytes(path, QUOTA_LIMIT_XATTR)
+                .ok()
+                .and_then(|v| be_u64(&v));
+            if let Some(limit) = limit {
+                let used = self.getxattr_bytes(path, QUOTA_SIZE_XATTR)
+                    .ok()
+                    .and_then(|v| be_u64(&v))
+                    .unwrap_or(0);
+                space.available = space.available.min(limit.saturating_sub(used));
+                space.total = space.total.min(limit);
+            }
+            current = path.parent().filter(|p| !p.as_os_str().is_empty());
+        }
+        Ok(space)
+    }
+}
@@ -0,0 +1,608 @@
+use errno::{errno, Errno};
+use libc::{
+    off_t, EINVAL, ENOENT, ENOSYS, EOPNOTSUPP, EXDEV, O_CREAT, O_EXCL, O_RDONLY, O_TRUNC, O_WRONLY,
+};
+
+use buffer_pool::BufferPool;
+use checksum::Sha256;
+use checksum_cache::{from_hex, to_hex};
+use file::GlusterFile;
+use glfs::{glfs_copy_file_range_t, optional_fn};
+use gluster::{get_error, Gluster, GlusterError};
+use mode::defaults;
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::str;
+
+// First line of PartsManifest::to_bytes
+const FORMAT_HEADER: &[u8] = b"gfapi-parts v1";
+
+/// True if the linked libgfapi exports glfs_copy_file_range, so split
+/// and join can have the bricks copy the data instead of streaming it
+/// through the client
+pub fn copy_file_range_available() -> bool {
+    copy_file_range_fn().is_some()
+}
+
+fn copy_file_range_fn() -> Option<glfs_copy_file_range_t> {
+    unsafe { optional_fn::<glfs_copy_file_range_t>("glfs_copy_file_range") }
+}
+
+/// Where Gluster::split writes the manifest for src: `<name>.parts` in
+/// dest_dir
+pub fn parts_manifest_path(src: &Path, dest_dir: &Path) -> PathBuf {
+    let mut name = src.file_name().unwrap_or_default().to_os_string();
+    name.push(".parts");
+    dest_dir.join(name)
+}
+
+/// Options for Gluster::split and Gluster::join
+#[derive(Clone, Debug)]
+pub struct SplitOptions {
+    server_side: bool,
+    verify: bool,
+    chunk_size: usize,
+}
+
+impl Default for SplitOptions {
+    fn default() -> SplitOptions {
+        SplitOptions {
+            server_side: true,
+            verify: true,
+            chunk_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl SplitOptions {
+    pub fn new() -> SplitOptions {
+        SplitOptions::default()
+    }
+
+    /// Copy with glfs_copy_file_range when libgfapi has it, falling back
+    /// to streaming through the client when it doesn't or the volume
+    /// refuses.  Defaults to true.
+    pub fn server_side(mut self, server_side: bool) -> SplitOptions {
+        self.server_side = server_side;
+        self
+    }
+
+    /// Have join check each part against the manifest's SHA-256 before
+    /// it's used.  Defaults to true.
+    pub fn verify(mut self, verify: bool) -> SplitOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Size of each read when streaming through the client.  Defaults
+    /// to 4MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> SplitOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+/// One part file of a PartsManifest
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Part {
+    /// File name, in the manifest's directory
+    pub name: PathBuf,
+    /// Where the part starts in the original file
+    pub offset: u64,
+    pub len: u64,
+    /// Lowercase hex SHA-256 of the part
+    pub sha256: String,
+}
+
+/// The parts a file was split into, in order, with enough about the
+/// original to tell whether a split of it can be carried on with
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PartsManifest {
+    /// Size of the original file
+    pub size: u64,
+    /// Modification time of the original file
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub part_size: u64,
+    /// Parts written so far, each starting where the last ended
+    pub parts: Vec<Part>,
+}
+
+impl PartsManifest {
+    /// True once the parts cover the whole of the original file
+    pub fn is_complete(&self) -> bool {
+        self.written() == self.size
+    }
+
+    /// Bytes of the original file the parts cover
+    pub fn written(&self) -> u64 {
+        self.parts.last().map_or(0, |part| part.offset + part.len)
+    }
+
+    /// The manifest as text:
+    ///
+    /// gfapi-parts v1
+    /// <size> <mtime seconds>.<nanoseconds> <part size>
+    /// <offset> <len> <hex sha256> <name>
+    ///
+    /// with one line like the last per part.  Backslashes and newlines in
+    /// names are escaped as \\ and \n.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FORMAT_HEADER.to_vec();
+        bytes.extend_from_slice(
+            format!(
+                "\n{} {}.{:09} {}\n",
+                self.size, self.mtime, self.mtime_nsec, self.part_size
+            )
+            .as_bytes(),
+        );
+        for part in &self.parts {
+            bytes.extend_from_slice(
+                format!("{} {} {} ", part.offset, part.len, part.sha256).as_bytes(),
+            );
+            for &b in part.name.as_os_str().as_bytes() {
+                match b {
+                    b'\\' => bytes.extend_from_slice(b"\\\\"),
+                    b'\n' => bytes.extend_from_slice(b"\\n"),
+                    b => bytes.push(b),
+                }
+            }
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    /// Parse what to_bytes produced.  Anything malformed, including parts
+    /// that leave gaps or run past the end of the file, is None.
+    pub fn parse(bytes: &[u8]) -> Option<PartsManifest> {
+        let mut lines = bytes.split(|&b| b == b'\n');
+        if lines.next()? != FORMAT_HEADER {
+            return None;
+        }
+        let header = str::from_utf8(lines.next()?).ok()?;
+        let mut fields = header.split(' ');
+        let size = fields.next()?.parse().ok()?;
+        let mut mtime = fields.next()?.splitn(2, '.');
+        let mtime_secs = mtime.next()?.parse().ok()?;
+        let mtime_nsec = mtime.next()?;
+        if mtime_nsec.len() != 9 || !mtime_nsec.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let part_size = fields.next()?.parse().ok()?;
+        if fields.next().is_some() || part_size == 0 {
+            return None;
+        }
+        let mut manifest = PartsManifest {
+            size,
+            mtime: mtime_secs,
+            mtime_nsec: mtime_nsec.parse().ok()?,
+            part_size,
+            parts: Vec::new(),
+        };
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+            let part = parse_part(line)?;
+            let end = part.offset.checked_add(part.len)?;
+            if part.offset != manifest.written() || end > size {
+                return None;
+            }
+            manifest.parts.push(part);
+        }
+        Some(manifest)
+    }
+}
+
+fn parse_part(line: &[u8]) -> Option<Part> {
+    let mut fields = line.splitn(4, |&b| b == b' ');
+    let offset = str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let len = str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let sha256 = str::from_utf8(fields.next()?).ok()?;
+    if from_hex(sha256)?.len() != 32 {
+        return None;
+    }
+    let mut name = Vec::new();
+    let mut escaped = false;
+    for &b in fields.next()? {
+        match (escaped, b) {
+            (false, b'\\') => escaped = true,
+            (false, b) => name.push(b),
+            (true, b'\\') => {
+                name.push(b'\\');
+                escaped = false;
+            }
+            (true, b'n') => {
+                name.push(b'\n');
+                escaped = false;
+            }
+            (true, _) => return None,
+        }
+    }
+    // Parts are always beside the manifest
+    if escaped || name.is_empty() || name.contains(&b'/') {
+        return None;
+    }
+    Some(Part {
+        name: PathBuf::from(OsStr::from_bytes(&name)),
+        offset,
+        len,
+        sha256: sha256.to_string(),
+    })
+}
+
+// Name of part index of a file called name
+fn part_name(name: &OsStr, index: u64) -> PathBuf {
+    let mut part = name.to_os_string();
+    part.push(format!(".part{:04}", index));
+    PathBuf::from(part)
+}
+
+// Where join records how many parts are in dest
+fn progress_path(dest: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(".join-progress");
+    dest.with_file_name(name)
+}
+
+// Copies len bytes between two files, on the bricks while that works and
+// through the client otherwise
+struct RangeCopier {
+    copy_file_range: Option<glfs_copy_file_range_t>,
+    chunk_size: usize,
+}
+
+impl RangeCopier {
+    fn new(opts: &SplitOptions) -> RangeCopier {
+        RangeCopier {
+            copy_file_range: if opts.server_side {
+                copy_file_range_fn()
+            } else {
+                None
+            },
+            chunk_size: opts.chunk_size,
+        }
+    }
+
+    // Copy len bytes from in_offset in from to out_offset in to.  When
+    // the data comes through the client it's added to digest, and the
+    // return is true; false means the bricks copied it and digest hasn't
+    // seen it.
+    fn copy(
+        &mut self,
+        from: &GlusterFile,
+        in_offset: u64,
+        to: &GlusterFile,
+        out_offset: u64,
+        len: u64,
+        digest: &mut Sha256,
+    ) -> Result<bool, GlusterError> {
+        if let Some(copy_file_range) = self.copy_file_range {
+            match server_side_copy(copy_file_range, from, in_offset, to, out_offset, len) {
+                Ok(()) => return Ok(false),
+                // Nothing was copied, so streaming can start from scratch
+                Err(Some(e)) => return Err(e),
+                Err(None) => {
+                    trace!("copy_file_range not supported, streaming through the client");
+                    self.copy_file_range = None;
+                }
+            }
+        }
+        let mut buffer = BufferPool::global().get(self.chunk_size);
+        let mut done = 0;
+        while done < len {
+            let want = (len - done).min(buffer.len() as u64) as usize;
+            from.read_exact_at(&mut buffer[..want], in_offset + done)?;
+            to.write_all_at(&buffer[..want], out_offset + done)?;
+            digest.update(&buffer[..want]);
+            done += want as u64;
+        }
+        Ok(true)
+    }
+}
+
+// Err(None) when the volume can't copy between these files at all and
+// nothing was copied
+fn server_side_copy(
+    copy_file_range: glfs_copy_file_range_t,
+    from: &GlusterFile,
+    in_offset: u64,
+    to: &GlusterFile,
+    out_offset: u64,
+    len: u64,
+) -> Result<(), Option<GlusterError>> {
+    let from_handle = from.handle()?;
+    let to_handle = to.handle()?;
+    let mut off_in = in_offset as off_t;
+    let mut off_out = out_offset as off_t;
+    let mut done = 0;
+    while done < len {
+        let copied = unsafe {
+            copy_file_range(
+                from_handle,
+                &mut off_in,
+                to_handle,
+                &mut off_out,
+                (len - done) as usize,
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if copied < 0 {
+            let unsupported = [ENOSYS, EOPNOTSUPP, EXDEV, EINVAL]
+                .iter()
+                .any(|&code| errno() == Errno(code));
+            if unsupported && done == 0 {
+                return Err(None);
+            }
+            return Err(Some(GlusterError::new(get_error())));
+        }
+        if copied == 0 {
+            return Err(Some(GlusterError::new(format!(
+                "{} ended {} bytes early",
+                from.path().display(),
+                len - done
+            ))));
+        }
+        done += copied as u64;
+    }
+    Ok(())
+}
+
+// SHA-256 of len bytes of file from offset
+fn digest_range(
+    file: &GlusterFile,
+    offset: u64,
+    len: u64,
+    chunk_size: usize,
+) -> Result<String, GlusterError> {
+    let mut buffer = BufferPool::global().get(chunk_size);
+    let mut digest = Sha256::new();
+    let mut done = 0;
+    while done < len {
+        let want = (len - done).min(buffer.len() as u64) as usize;
+        file.read_exact_at(&mut buffer[..want], offset + done)?;
+        digest.update(&buffer[..want]);
+        done += want as u64;
+    }
+    Ok(to_hex(&digest.finish()))
+}
+
+impl Gluster {
+    /// Split the file src into parts of part_size bytes (the last may be
+    /// shorter) named `<name>.part0000`, `<name>.part0001` and so on in
+    /// dest_dir, which is created if need be, and returns their paths in
+    /// order.  The parts are copied by the bricks with
+    /// glfs_copy_file_range where possible, see SplitOptions::server_side,
+    /// and each is then read back once for its SHA-256.
+    ///
+    /// The parts are recorded in a PartsManifest at
+    /// parts_manifest_path(src, dest_dir), rewritten as each part is
+    /// finished.  Running split again after an interruption keeps the
+    /// parts already recorded and carries on from the first missing one,
+    /// unless src has changed size or mtime or part_size differs, in
+    /// which case it starts over.
+    pub fn split(
+        &self,
+        src: &Path,
+        dest_dir: &Path,
+        part_size: u64,
+        opts: &SplitOptions,
+    ) -> Result<Vec<PathBuf>, GlusterError> {
+        if part_size == 0 {
+            return Err(GlusterError::new("part_size must be above 0".to_string()));
+        }
+        let name = match src.file_name() {
+            Some(name) => name,
+            None => {
+                return Err(GlusterError::new(format!(
+                    "{} has no file name to name the parts after",
+                    src.display()
+                )))
+            }
+        };
+        let metadata = self.metadata(src)?;
+        if !metadata.is_file() {
+            return Err(GlusterError::new(format!(
+                "{} isn't a regular file",
+                src.display()
+            )));
+        }
+        self.create_dir_all(dest_dir, defaults::DIR_0755)?;
+        let manifest_path = parts_manifest_path(src, dest_dir);
+        let fresh = PartsManifest {
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            part_size,
+            parts: Vec::new(),
+        };
+        let mut manifest = match self.read_to_vec(&manifest_path) {
+            Ok(bytes) => PartsManifest::parse(&bytes)
+                .filter(|previous| {
+                    previous.size == fresh.size
+                        && previous.mtime == fresh.mtime
+                        && previous.mtime_nsec == fresh.mtime_nsec
+                        && previous.part_size == part_size
+                })
+                .unwrap_or_else(|| fresh.clone()),
+            Err(_) if errno() == Errno(ENOENT) => fresh.clone(),
+            Err(e) => return Err(e),
+        };
+        // Keep recorded parts up to the first that's gone or the wrong size
+        let mut kept = 0;
+        for part in &manifest.parts {
+            match self.metadata(&dest_dir.join(&part.name)) {
+                Ok(ref existing) if existing.is_file() && existing.len() == part.len => kept += 1,
+                _ => break,
+            }
+        }
+        manifest.parts.truncate(kept);
+
+        let source = self.open_file(src, O_RDONLY)?;
+        let mut copier = RangeCopier::new(opts);
+        let count = manifest.size.div_ceil(part_size);
+        for index in manifest.parts.len() as u64..count {
+            let offset = index * part_size;
+            let len = part_size.min(manifest.size - offset);
+            let part_path = dest_dir.join(part_name(name, index));
+            let part = self.create_file(
+                &part_path,
+                O_CREAT | O_WRONLY | O_TRUNC,
+                defaults::FILE_0644,
+            )?;
+            let mut digest = Sha256::new();
+            let streamed = copier.copy(&source, offset, &part, 0, len, &mut digest)?;
+            part.fsync()?;
+            part.close()?;
+            let sha256 = if streamed {
+                to_hex(&digest.finish())
+            } else {
+                // Reading the part rather than src checks the copy too
+                let part = self.open_file(&part_path, O_RDONLY)?;
+                let sha256 = digest_range(&part, 0, len, opts.chunk_size)?;
+                part.close()?;
+                sha256
+            };
+            manifest.parts.push(Part {
+                name: part_name(name, index),
+                offset,
+                len,
+                sha256,
+            });
+            self.write_parts_manifest(&manifest_path, &manifest)?;
+        }
+        source.close()?;
+        // Written even when there was nothing to do, for an empty src
+        self.write_parts_manifest(&manifest_path, &manifest)?;
+        Ok(manifest
+            .parts
+            .iter()
+            .map(|part| dest_dir.join(&part.name))
+            .collect())
+    }
+
+    // Replace the manifest at path in one step, so an interrupted split
+    // never leaves half of one
+    fn write_parts_manifest(
+        &self,
+        path: &Path,
+        manifest: &PartsManifest,
+    ) -> Result<(), GlusterError> {
+        let mut scratch = path.as_os_str().to_os_string();
+        scratch.push(".tmp");
+        let scratch = PathBuf::from(scratch);
+        self.write_file(&scratch, &manifest.to_bytes())?;
+        self.rename(&scratch, path)
+    }
+
+    /// Put back together the file a complete PartsManifest describes,
+    /// reading the parts from the manifest's directory and writing dest,
+    /// which mustn't already exist.  Each part is checked against the
+    /// manifest's SHA-256 unless SplitOptions::verify is off, and copied
+    /// by the bricks where possible.  Returns the size of dest.
+    ///
+    /// How many parts are in dest is kept in `.<name>.join-progress`
+    /// beside it until the join finishes, so running join again after an
+    /// interruption cuts dest back to the last whole part and carries on
+    /// from there.
+    pub fn join(
+        &self,
+        parts_manifest: &Path,
+        dest: &Path,
+        opts: &SplitOptions,
+    ) -> Result<u64, GlusterError> {
+        let manifest = match PartsManifest::parse(&self.read_to_vec(parts_manifest)?) {
+            Some(manifest) => manifest,
+            None => {
+                return Err(GlusterError::new(format!(
+                    "{} isn't a parts manifest",
+                    parts_manifest.display()
+                )))
+            }
+        };
+        if !manifest.is_complete() {
+            return Err(GlusterError::new(format!(
+                "{} only covers {} of {} bytes, the split didn't finish",
+                parts_manifest.display(),
+                manifest.written(),
+                manifest.size
+            )));
+        }
+        let dir = parts_manifest.parent().unwrap_or_else(|| Path::new(""));
+        let progress = progress_path(dest);
+        let resume_from = match self.read_to_vec(&progress) {
+            Ok(bytes) => str::from_utf8(&bytes)
+                .ok()
+                .and_then(|done| done.trim().parse::<usize>().ok())
+                .filter(|&done| done <= manifest.parts.len())
+                .ok_or_else(|| GlusterError::new(format!("{} is corrupt", progress.display())))?,
+            Err(_) if errno() == Errno(ENOENT) => {
+                if self.symlink_metadata(dest).is_ok() {
+                    return Err(GlusterError::AlreadyExists {
+                        path: dest.to_path_buf(),
+                    });
+                }
+                // Written first, so a join interrupted straight after
+                // creating dest can still be carried on
+                self.write_file(&progress, b"0\n")?;
+                self.create_file(dest, O_CREAT | O_EXCL | O_WRONLY, defaults::FILE_0644)?
+                    .close()?;
+                0
+            }
+            Err(e) => return Err(e),
+        };
+        let start = manifest
+            .parts
+            .get(resume_from)
+            .map_or(manifest.size, |part| part.offset);
+        // Anything past the last whole part is from an interrupted copy
+        self.truncate(dest, start as i64)?;
+
+        let out = self.open_file(dest, O_WRONLY)?;
+        let mut copier = RangeCopier::new(opts);
+        for (index, part) in manifest.parts.iter().enumerate().skip(resume_from) {
+            let part_path = dir.join(&part.name);
+            let input = self.open_file(&part_path, O_RDONLY)?;
+            let actual = input.fstat()?.st_size as u64;
+            if actual != part.len {
+                return Err(GlusterError::new(format!(
+                    "{} is {} bytes, the manifest says {}",
+                    part_path.display(),
+                    actual,
+                    part.len
+                )));
+            }
+            let mismatch = || GlusterError::VerificationFailed {
+                path: part_path.clone(),
+                offset: 0,
+            };
+            // With a server side copy the part has to be read to check it
+            if opts.verify
+                && copier.copy_file_range.is_some()
+                && digest_range(&input, 0, part.len, opts.chunk_size)? != part.sha256
+            {
+                return Err(mismatch());
+            }
+            let mut digest = Sha256::new();
+            let streamed = copier.copy(&input, 0, &out, part.offset, part.len, &mut digest)?;
+            if opts.verify && streamed && to_hex(&digest.finish()) != part.sha256 {
+                return Err(mismatch());
+            }
+            input.close()?;
+            out.fsync()?;
+            self.write_file(&progress, format!("{}\n", index + 1).as_bytes())?;
+        }
+        out.close()?;
+        self.unlink(&progress)?;
+        Ok(manifest.size)
+    }
+}
@@ -0,0 +1,232 @@
+use errno::{errno, Errno};
+use libc::{stat, ESTALE, O_CREAT, O_EXCL, O_TRUNC};
+
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+use handle::GlusterObject;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// How many times a StaleRetryFile or StaleRetryObject re-resolves its
+/// path and retries after ESTALE before giving up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StaleRetry {
+    attempts: u32,
+}
+
+impl Default for StaleRetry {
+    fn default() -> StaleRetry {
+        StaleRetry { attempts: 1 }
+    }
+}
+
+impl StaleRetry {
+    pub fn new() -> StaleRetry {
+        StaleRetry::default()
+    }
+
+    /// Retries per operation.  Defaults to 1, 0 surfaces ESTALE at once.
+    pub fn attempts(mut self, attempts: u32) -> StaleRetry {
+        self.attempts = attempts;
+        self
+    }
+}
+
+/// Run op, and each time it fails with ESTALE call reresolve and run it
+/// again, up to policy's attempts.  Each successful reresolve adds one to
+/// recoveries.  An error from reresolve is returned as is.  op has to
+/// fail with errno set, as the gfapi calls do.
+pub fn retry_stale<T, E, F, R>(
+    policy: &StaleRetry,
+    recoveries: &AtomicU64,
+    mut op: F,
+    mut reresolve: R,
+) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    R: FnMut() -> Result<(), E>,
+{
+    let mut retries = 0;
+    loop {
+        let error = match op() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if errno() != Errno(ESTALE) || retries >= policy.attempts {
+            return Err(error);
+        }
+        retries += 1;
+        reresolve()?;
+        recoveries.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// A GlusterFile that reopens its path and retries when read_at, write_at
+/// or fstat fail with ESTALE, which cached fds can do while a volume is
+/// rebalanced or a brick replaced.  From GlusterFile::with_stale_retry.
+///
+/// This is opt-in because it changes what the file refers to: if the
+/// path was replaced by a different file since it was opened, the retry
+/// reads or writes the new one.  It's reopened with the original flags
+/// less O_CREAT, O_EXCL and O_TRUNC.  Only positioned I/O is offered, the
+/// wrapped file's position wouldn't survive a reopen.
+#[derive(Debug)]
+pub struct StaleRetryFile<'a> {
+    file: RwLock<GlusterFile<'a>>,
+    policy: StaleRetry,
+    recoveries: AtomicU64,
+}
+
+impl<'a> GlusterFile<'a> {
+    /// Wrap the file so ESTALE reopens it and retries, see StaleRetryFile
+    pub fn with_stale_retry(self, policy: StaleRetry) -> StaleRetryFile<'a> {
+        StaleRetryFile {
+            file: RwLock::new(self),
+            policy,
+            recoveries: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<'a> StaleRetryFile<'a> {
+    // Swap in a fresh fd for the path.  Nothing else holds the old one
+    // while the write lock is held, and closing a stale fd is expected
+    // to fail, so its error is dropped.
+    fn reopen(&self) -> Result<(), GlusterError> {
+        let mut file = self.file.write().unwrap();
+        let flags = file.flags() & !(O_CREAT | O_EXCL | O_TRUNC);
+        let fresh = file.gluster().open_file(file.path(), flags)?;
+        let stale = ::std::mem::replace(&mut *file, fresh);
+        let _ = stale.close();
+        Ok(())
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        retry_stale(
+            &self.policy,
+            &self.recoveries,
+            || self.file.read().unwrap().read_at(buf, offset),
+            || self.reopen().map_err(io::Error::other),
+        )
+    }
+
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        retry_stale(
+            &self.policy,
+            &self.recoveries,
+            || self.file.read().unwrap().write_at(buf, offset),
+            || self.reopen().map_err(io::Error::other),
+        )
+    }
+
+    pub fn fstat(&self) -> Result<stat, GlusterError> {
+        retry_stale(
+            &self.policy,
+            &self.recoveries,
+            || self.file.read().unwrap().fstat(),
+            || self.reopen(),
+        )
+    }
+
+    /// The path the file is reopened from
+    pub fn path(&self) -> PathBuf {
+        self.file.read().unwrap().path().to_path_buf()
+    }
+
+    /// How many times the file has been reopened after ESTALE
+    pub fn recoveries(&self) -> u64 {
+        self.recoveries.load(Ordering::SeqCst)
+    }
+
+    /// The file as currently open
+    pub fn into_inner(self) -> GlusterFile<'a> {
+        self.file.into_inner().unwrap()
+    }
+}
+
+/// A GlusterObject that looks its path up again and retries when
+/// h_pread or h_pwrite fail with ESTALE, the object handle counterpart of
+/// StaleRetryFile with the same caveat about replaced files.  From
+/// GlusterObject::with_stale_retry.
+#[derive(Debug)]
+pub struct StaleRetryObject<'a> {
+    gluster: &'a Gluster,
+    path: PathBuf,
+    object: RwLock<GlusterObject<'a>>,
+    policy: StaleRetry,
+    recoveries: AtomicU64,
+}
+
+impl<'a> GlusterObject<'a> {
+    /// Wrap an object from Gluster::lookup_object so ESTALE looks it up
+    /// again and retries, see StaleRetryObject.  Objects from
+    /// object_from_gfid have no path to look up and are refused.
+    pub fn with_stale_retry(
+        self,
+        policy: StaleRetry,
+    ) -> Result<StaleRetryObject<'a>, GlusterError> {
+        let path = match self.path() {
+            Some(path) => path.to_path_buf(),
+            None => {
+                return Err(GlusterError::new(format!(
+                    "object {} wasn't looked up by path",
+                    self.gfid()
+                )))
+            }
+        };
+        Ok(StaleRetryObject {
+            gluster: self.gluster(),
+            path,
+            object: RwLock::new(self),
+            policy,
+            recoveries: AtomicU64::new(0),
+        })
+    }
+}
+
+impl<'a> StaleRetryObject<'a> {
+    fn relookup(&self) -> Result<(), GlusterError> {
+        let fresh = self.gluster.lookup_object(&self.path)?;
+        *self.object.write().unwrap() = fresh;
+        Ok(())
+    }
+
+    /// Gluster::h_pread on the object
+    pub fn h_pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, GlusterError> {
+        retry_stale(
+            &self.policy,
+            &self.recoveries,
+            || {
+                let object = self.object.read().unwrap();
+                self.gluster.h_pread(&object, buf, offset)
+            },
+            || self.relookup(),
+        )
+    }
+
+    /// Gluster::h_pwrite on the object
+    pub fn h_pwrite(&self, buf: &[u8], offset: u64) -> Result<usize, GlusterError> {
+        retry_stale(
+            &self.policy,
+            &self.recoveries,
+            || {
+                let object = self.object.read().unwrap();
+                self.gluster.h_pwrite(&object, buf, offset)
+            },
+            || self.relookup(),
+        )
+    }
+
+    /// The path the object is looked up by
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How many times the object has been looked up again after ESTALE
+    pub fn recoveries(&self) -> u64 {
+        self.recoveries.load(Ordering::SeqCst)
+    }
+}
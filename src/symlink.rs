@@ -0,0 +1,60 @@
+use errno::{errno, Errno};
+use libc::{ENOENT, S_IFLNK, S_IFMT};
+
+use gluster::{Gluster, GlusterError};
+use path::normalize;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+impl Gluster {
+    /// Follow the symlink chain starting at path to the first thing that
+    /// isn't a symlink and return its path.  Each hop is an lstat and a
+    /// read_link, with relative targets taken against the directory of
+    /// the link that holds them.  Only the links themselves have to
+    /// exist, not every prefix the way realpath wants.
+    ///
+    /// Fails with GlusterError::SymlinkLoop after max_hops links or as
+    /// soon as a link comes around a second time, and with
+    /// GlusterError::DanglingSymlink if the last target is missing.  A
+    /// path that isn't a symlink is returned as it is after one lstat.
+    ///
+    /// .. in a target is resolved lexically, so it isn't right when the
+    /// link's own directory was reached through another symlink.
+    pub fn resolve_symlink(&self, path: &Path, max_hops: usize) -> Result<PathBuf, GlusterError> {
+        let mut current = normalize(path)?;
+        let mut seen = HashSet::new();
+        let mut hops = 0;
+        loop {
+            let stat = match self.lsstat(&current) {
+                Ok(stat) => stat,
+                Err(e) => {
+                    if hops > 0 && errno() == Errno(ENOENT) {
+                        return Err(GlusterError::DanglingSymlink {
+                            path: path.to_path_buf(),
+                            target: current,
+                        });
+                    }
+                    return Err(e);
+                }
+            };
+            if stat.st_mode & S_IFMT != S_IFLNK {
+                return Ok(current);
+            }
+            if hops == max_hops || !seen.insert(current.clone()) {
+                return Err(GlusterError::SymlinkLoop {
+                    path: path.to_path_buf(),
+                    hops,
+                });
+            }
+            let target = self.read_link(&current)?;
+            current = if target.is_absolute() {
+                normalize(&target)?
+            } else {
+                let parent = current.parent().unwrap_or_else(|| Path::new(""));
+                normalize(&parent.join(&target))?
+            };
+            hops += 1;
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use errno::{errno, Errno};
+use libc::{EEXIST, S_IRWXU};
+
+use cleanup::{self, DropError, DropTarget};
+use gluster::{Gluster, GlusterError};
+
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A uniquely named scratch directory on a volume for tests.  The
+/// directory and everything in it are removed when this is dropped, even
+/// if the test panicked.  Cleanup failures are logged rather than
+/// panicking.
+#[derive(Debug)]
+pub struct GlusterTempDir<'a> {
+    gluster: &'a Gluster,
+    path: PathBuf,
+    keep: bool,
+}
+
+impl<'a> GlusterTempDir<'a> {
+    /// Create a new directory inside base
+    pub fn new(gluster: &'a Gluster, base: &Path) -> Result<GlusterTempDir<'a>, GlusterError> {
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or(0);
+            let name = format!(
+                ".tmp-{}-{}-{}",
+                process::id(),
+                TEMP_DIR_COUNTER.fetch_add(1, Ordering::SeqCst),
+                nanos
+            );
+            let path = base.join(name);
+            match gluster.mkdir(&path, S_IRWXU) {
+                Ok(_) => {
+                    return Ok(GlusterTempDir {
+                        gluster,
+                        path,
+                        keep: false,
+                    })
+                }
+                Err(e) => {
+                    if errno() != Errno(EEXIST) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of name inside the temporary directory
+    pub fn child<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Leave the directory in place when this is dropped, for debugging.
+    /// Returns its path.
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl<'a> Drop for GlusterTempDir<'a> {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(error) = self.gluster.remove_dir_all(&self.path) {
+            cleanup::report(DropError {
+                target: DropTarget::TempDir,
+                path: Some(self.path.clone()),
+                error,
+            });
+        }
+    }
+}
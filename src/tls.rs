@@ -0,0 +1,190 @@
+use glfs::*;
+
+use gluster::{get_error, Gluster, GlusterError};
+use tuning::XlatorOption;
+
+use std::ffi::CString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Where gluster looks for the client certificate, key and CA bundle
+/// unless told otherwise
+pub const DEFAULT_CERT: &str = "/etc/ssl/glusterfs.pem";
+pub const DEFAULT_KEY: &str = "/etc/ssl/glusterfs.key";
+pub const DEFAULT_CA: &str = "/etc/ssl/glusterfs.ca";
+/// gfapi encrypts the connection to glusterd when this file exists.
+/// There's no API for it, the file is the only switch.
+pub const SECURE_ACCESS_FILE: &str = "/var/lib/glusterd/secure-access";
+
+// Client side xlator options controlling the data path
+const CLIENT_XLATORS: &str = "*-client-*";
+const SSL_ENABLED: &str = "transport.socket.ssl-enabled";
+const SSL_OWN_CERT: &str = "transport.socket.ssl-own-cert";
+const SSL_PRIVATE_KEY: &str = "transport.socket.ssl-private-key";
+const SSL_CA_LIST: &str = "transport.socket.ssl-ca-list";
+
+/// TLS settings for a connection.  Passed to GlusterBuilder::tls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsOptions {
+    cert: PathBuf,
+    key: PathBuf,
+    ca: PathBuf,
+    management: bool,
+}
+
+impl Default for TlsOptions {
+    fn default() -> TlsOptions {
+        TlsOptions {
+            cert: PathBuf::from(DEFAULT_CERT),
+            key: PathBuf::from(DEFAULT_KEY),
+            ca: PathBuf::from(DEFAULT_CA),
+            management: false,
+        }
+    }
+}
+
+impl TlsOptions {
+    pub fn new() -> TlsOptions {
+        TlsOptions::default()
+    }
+
+    /// Client certificate.  Defaults to /etc/ssl/glusterfs.pem.
+    pub fn cert<P: AsRef<Path>>(mut self, cert: P) -> TlsOptions {
+        self.cert = cert.as_ref().to_path_buf();
+        self
+    }
+
+    /// Private key for the certificate.  Defaults to
+    /// /etc/ssl/glusterfs.key.
+    pub fn key<P: AsRef<Path>>(mut self, key: P) -> TlsOptions {
+        self.key = key.as_ref().to_path_buf();
+        self
+    }
+
+    /// CA bundle the servers' certificates are checked against.  Defaults
+    /// to /etc/ssl/glusterfs.ca.
+    pub fn ca<P: AsRef<Path>>(mut self, ca: P) -> TlsOptions {
+        self.ca = ca.as_ref().to_path_buf();
+        self
+    }
+
+    /// Expect the connection to glusterd to be encrypted as well as the
+    /// connections to the bricks.  gfapi only does that when
+    /// SECURE_ACCESS_FILE exists, so this checks it's there.  Defaults to
+    /// false.
+    pub fn management(mut self, management: bool) -> TlsOptions {
+        self.management = management;
+        self
+    }
+
+    /// Check that all the files this configuration needs exist and can be
+    /// read.  GlusterBuilder::connect calls this before connecting since
+    /// a failed handshake otherwise only shows up as an opaque init error.
+    pub fn validate(&self) -> Result<(), GlusterError> {
+        let mut problems = Vec::new();
+        for (what, path) in [
+            ("certificate", &self.cert),
+            ("private key", &self.key),
+            ("CA bundle", &self.ca),
+        ] {
+            if let Err(e) = File::open(path) {
+                problems.push(format!("{} {}: {}", what, path.display(), e));
+            }
+        }
+        if self.management && !Path::new(SECURE_ACCESS_FILE).exists() {
+            problems.push(format!(
+                "management TLS was requested but {} doesn't exist, gfapi only \
+                 encrypts the glusterd connection when it does",
+                SECURE_ACCESS_FILE
+            ));
+        }
+        if !problems.is_empty() {
+            return Err(GlusterError::new(format!(
+                "TLS configuration is incomplete: {}",
+                problems.join("; ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Describe the configuration for error messages
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "cert {}, key {}, CA {}, management TLS {}",
+            self.cert.display(),
+            self.key.display(),
+            self.ca.display(),
+            if self.management { "on" } else { "off" }
+        )
+    }
+
+    /// Set the client xlator options on a handle from glfs_new.  Paths
+    /// that are the defaults are left alone so the volfile can still
+    /// override them.
+    pub(crate) fn apply(&self, gluster: &Gluster) -> Result<(), GlusterError> {
+        gluster.set_xlator_option(CLIENT_XLATORS, SSL_ENABLED, "on")?;
+        for (key, path, default) in [
+            (SSL_OWN_CERT, &self.cert, DEFAULT_CERT),
+            (SSL_PRIVATE_KEY, &self.key, DEFAULT_KEY),
+            (SSL_CA_LIST, &self.ca, DEFAULT_CA),
+        ] {
+            if path.as_path() != Path::new(default) {
+                gluster.set_xlator_option(CLIENT_XLATORS, key, &path.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which TLS related knobs this host offers.  Data path TLS and the
+/// certificate paths are set with glfs_set_xlator_option, which every
+/// libgfapi this crate links against has, so they're always available.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TlsCapabilities {
+    /// SECURE_ACCESS_FILE exists so the glusterd connection is encrypted
+    pub secure_management: bool,
+}
+
+/// Check which TLS knobs are available
+pub fn tls_capabilities() -> TlsCapabilities {
+    TlsCapabilities {
+        secure_management: Path::new(SECURE_ACCESS_FILE).exists(),
+    }
+}
+
+impl Gluster {
+    /// Set an option on the xlators matching xlator (a glob such as
+    /// "*-client-*") in the graph.  Only takes effect when called between
+    /// glfs_new and glfs_init so it's only useful to the builder.
+    pub(crate) fn set_xlator_option(
+        &self,
+        xlator: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), GlusterError> {
+        let xlator_c = CString::new(xlator)?;
+        let key_c = CString::new(key)?;
+        let value_c = CString::new(value)?;
+        unsafe {
+            let ret_code = glfs_set_xlator_option(
+                self.cluster_handle,
+                xlator_c.as_ptr(),
+                key_c.as_ptr(),
+                value_c.as_ptr(),
+            );
+            if ret_code < 0 {
+                return Err(GlusterError::new(format!(
+                    "unable to set xlator option {}: {}",
+                    key,
+                    get_error()
+                )));
+            }
+        }
+        let mut applied = match self.xlator_options.lock() {
+            Ok(applied) => applied,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        applied.push(XlatorOption::new(xlator, key, value));
+        Ok(())
+    }
+}
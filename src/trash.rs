@@ -0,0 +1,273 @@
+use gluster::{Gluster, GlusterError};
+use path;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Name of the sidecar in each trashed item's directory
+const INFO_NAME: &str = ".trashinfo";
+
+// Subdirectory of each trashed item's directory the item goes in, so
+// whatever it's called it can't land on the sidecar
+const ITEM_DIR: &str = "item";
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Where Gluster::trash and friends keep trashed items
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashOptions {
+    root: PathBuf,
+}
+
+impl Default for TrashOptions {
+    fn default() -> TrashOptions {
+        TrashOptions {
+            root: PathBuf::from("/.trash"),
+        }
+    }
+}
+
+impl TrashOptions {
+    pub fn new() -> TrashOptions {
+        TrashOptions::default()
+    }
+
+    /// Directory holding the trash.  Defaults to /.trash on the volume.
+    /// It's created when something is first trashed.
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> TrashOptions {
+        self.root = root.as_ref().to_path_buf();
+        self
+    }
+}
+
+/// Something moved to the trash by Gluster::trash
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashEntry {
+    /// date/unique-id, unique within the trash root.  Pass it to restore.
+    pub id: String,
+    /// Where the item was when it was trashed
+    pub original: PathBuf,
+    pub deleted_at: SystemTime,
+    /// Where the item is now
+    pub location: PathBuf,
+}
+
+// Days since the epoch to a year, month and day, from Howard Hinnant's
+// civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn date_dir(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The sidecar: the deletion time in seconds.nanoseconds since the epoch
+// on the first line, then the original path's bytes to the end of the
+// file so any path round trips
+fn encode_info(original: &Path, deleted_at: SystemTime) -> Vec<u8> {
+    let since_epoch = deleted_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut info = format!(
+        "deleted={}.{:09}\npath=",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    )
+    .into_bytes();
+    info.extend_from_slice(original.as_os_str().as_bytes());
+    info
+}
+
+// The directory of the trashed item id.  Ids come from callers, so one
+// has to name an item directory, date/unique-id, inside the trash root
+// and not the root, a date directory or anything outside.
+fn item_dir(id: &str, opts: &TrashOptions) -> Result<PathBuf, GlusterError> {
+    let dir = path::normalize_within(&opts.root, Path::new(id))?;
+    match dir.strip_prefix(&opts.root) {
+        Ok(rest) if rest.components().count() == 2 => Ok(dir),
+        _ => Err(GlusterError::new(format!("{:?} isn't a trash id", id))),
+    }
+}
+
+fn decode_info(info: &[u8]) -> Option<(PathBuf, SystemTime)> {
+    let newline = info.iter().position(|b| *b == b'\n')?;
+    let deleted = ::std::str::from_utf8(&info[..newline]).ok()?;
+    let deleted = deleted.strip_prefix("deleted=")?;
+    let mut parts = deleted.splitn(2, '.');
+    let secs: u64 = parts.next()?.parse().ok()?;
+    let nanos: u32 = parts.next()?.parse().ok()?;
+    let path = info[newline + 1..].strip_prefix(b"path=")?;
+    Some((
+        PathBuf::from(OsStr::from_bytes(path)),
+        UNIX_EPOCH + Duration::new(secs, nanos),
+    ))
+}
+
+impl Gluster {
+    /// Move path, a file, symlink or whole directory, into the trash
+    /// instead of deleting it, as
+    /// root/YYYY-MM-DD/unique-id/item/original-name, with a
+    /// unique-id/.trashinfo sidecar recording where it came from and
+    /// when.  The
+    /// move is a rename unless the trash is on another subvolume, see
+    /// move_path.
+    pub fn trash(&self, path: &Path, opts: &TrashOptions) -> Result<TrashEntry, GlusterError> {
+        self.check_writable()?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| GlusterError::new(format!("{} can't be trashed", path.display())))?;
+        self.symlink_metadata(path)?;
+        let deleted_at = SystemTime::now();
+        let since_epoch = deleted_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let id = format!(
+            "{}/{}.{:09}-{}-{}",
+            date_dir(deleted_at),
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        let dir = opts.root.join(&id);
+        self.create_dir_all(&dir.join(ITEM_DIR), 0o700)?;
+        let location = dir.join(ITEM_DIR).join(name);
+        let moved = self
+            .write_file(&dir.join(INFO_NAME), &encode_info(path, deleted_at))
+            .and_then(|_| self.move_path(path, &location));
+        if let Err(e) = moved {
+            let _ = self.remove_tree(&dir);
+            return Err(e);
+        }
+        Ok(TrashEntry {
+            id,
+            original: path.to_path_buf(),
+            deleted_at,
+            location,
+        })
+    }
+
+    // The entry for the trashed item id, read from its sidecar
+    fn trash_entry(&self, id: &str, opts: &TrashOptions) -> Result<TrashEntry, GlusterError> {
+        let dir = item_dir(id, opts)?;
+        let info = self.read_to_vec(&dir.join(INFO_NAME))?;
+        let (original, deleted_at) = decode_info(&info).ok_or_else(|| {
+            GlusterError::new(format!("{} has a damaged {}", dir.display(), INFO_NAME))
+        })?;
+        let name = original.file_name().unwrap_or_default().to_os_string();
+        Ok(TrashEntry {
+            id: id.to_string(),
+            original,
+            deleted_at,
+            location: dir.join(ITEM_DIR).join(name),
+        })
+    }
+
+    /// Everything in the trash, oldest first.  Directories in the trash
+    /// root that don't look like trashed items are skipped.
+    pub fn list_trash(&self, opts: &TrashOptions) -> Result<Vec<TrashEntry>, GlusterError> {
+        let mut entries = Vec::new();
+        if !self.exists(&opts.root)? {
+            return Ok(entries);
+        }
+        for (date, date_metadata) in self.list_dir(&opts.root, 1)? {
+            if !date_metadata.is_dir() {
+                continue;
+            }
+            for (item, item_metadata) in self.list_dir(&opts.root.join(&date.path), 1)? {
+                if !item_metadata.is_dir() {
+                    continue;
+                }
+                let id = date.path.join(&item.path);
+                if let Some(id) = id.to_str() {
+                    if let Ok(entry) = self.trash_entry(id, opts) {
+                        entries.push(entry);
+                    }
+                }
+            }
+        }
+        entries.sort_by_key(|entry| entry.deleted_at);
+        Ok(entries)
+    }
+
+    /// Move the trashed item id back to where it was trashed from,
+    /// recreating missing parent directories, and return that path.
+    /// Fails with GlusterError::AlreadyExists if something has taken its
+    /// place since.
+    pub fn restore(&self, id: &str, opts: &TrashOptions) -> Result<PathBuf, GlusterError> {
+        self.check_writable()?;
+        let entry = self.trash_entry(id, opts)?;
+        if self.exists(&entry.original)? {
+            return Err(GlusterError::AlreadyExists {
+                path: entry.original,
+            });
+        }
+        if let Some(parent) = entry.original.parent() {
+            if !parent.as_os_str().is_empty() {
+                self.create_dir_all(parent, 0o755)?;
+            }
+        }
+        self.move_path(&entry.location, &entry.original)?;
+        let dir = item_dir(id, opts)?;
+        self.remove_tree(&dir)?;
+        self.remove_empty_date_dir(&dir);
+        Ok(entry.original)
+    }
+
+    /// Delete everything trashed more than age ago for good.  Returns
+    /// how many items were purged.
+    pub fn purge_older_than(
+        &self,
+        age: Duration,
+        opts: &TrashOptions,
+    ) -> Result<usize, GlusterError> {
+        self.check_writable()?;
+        let cutoff = SystemTime::now() - age;
+        let mut purged = 0;
+        for entry in self.list_trash(opts)? {
+            if entry.deleted_at >= cutoff {
+                continue;
+            }
+            let dir = item_dir(&entry.id, opts)?;
+            self.remove_tree(&dir)?;
+            self.remove_empty_date_dir(&dir);
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    // Remove path and everything under it, without following symlinks
+    fn remove_tree(&self, path: &Path) -> Result<(), GlusterError> {
+        if !self.symlink_metadata(path)?.is_dir() {
+            return self.unlink(path);
+        }
+        for (entry, _) in self.list_dir(path, 1)? {
+            self.remove_tree(&path.join(&entry.path))?;
+        }
+        self.rmdir(path)
+    }
+
+    // Tidy up the date directory the item in dir was in once it's
+    // empty.  Another item may still be in it, or just arriving, so
+    // failure is fine.
+    fn remove_empty_date_dir(&self, dir: &Path) {
+        if let Some(date) = dir.parent() {
+            let _ = self.rmdir(date);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use errno::{errno, Errno};
+use libc::{dev_t, gid_t, mode_t, timespec, uid_t, EEXIST, EPERM, O_RDONLY};
+
+use checksum::{ChecksumAlgorithm, Sha256};
+use checksum_cache::to_hex;
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use mode::defaults;
+use preserve::{PreserveItem, PreserveWarning};
+use walk::WalkOptions;
+use write::WriteOptions;
+use xattr::XattrNamespace;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// What a TreeImage entry is
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageKind {
+    Dir,
+    /// A regular file, whose content restore_tree reads from the same
+    /// path under the image root and checks against sha256
+    File {
+        size: u64,
+        /// Hex SHA-256 of the content when the snapshot was taken
+        sha256: String,
+        /// (dev, ino) of the file when it has more than one link.
+        /// Entries sharing it are restored as hard links to one file.
+        link_group: Option<(u64, u64)>,
+    },
+    Symlink {
+        target: PathBuf,
+    },
+    /// A device, fifo or socket, whose type is in the entry's mode
+    Special {
+        rdev: u64,
+    },
+}
+
+/// One file, directory, symlink or special file in a TreeImage
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageEntry {
+    /// Relative to the image root, empty for the root itself
+    pub path: PathBuf,
+    pub kind: ImageKind,
+    /// The full st_mode, file type bits included
+    pub mode: mode_t,
+    pub uid: uid_t,
+    pub gid: gid_t,
+    pub atime: i64,
+    pub atime_nsec: i64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    /// user.* extended attributes by raw name, in name order.  Symlinks
+    /// can't have them.
+    pub xattrs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A description of a subtree of the volume, made by
+/// Gluster::snapshot_tree, that restore_tree recreates elsewhere.  File
+/// content isn't held, only referred to: it's read from the volume the
+/// snapshot was taken on when the image is restored.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeImage {
+    /// The path the snapshot was taken of
+    pub root: PathBuf,
+    /// In path order, so every directory comes before what's in it
+    pub entries: Vec<ImageEntry>,
+}
+
+impl TreeImage {
+    /// The entry for path, relative to the root
+    pub fn get(&self, path: &Path) -> Option<&ImageEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(path))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Paths of the files that are hard links to one another, each group
+    /// in path order
+    pub fn link_groups(&self) -> Vec<Vec<PathBuf>> {
+        let mut groups: Vec<((u64, u64), Vec<PathBuf>)> = Vec::new();
+        for entry in &self.entries {
+            if let ImageKind::File {
+                link_group: Some(id),
+                ..
+            } = entry.kind
+            {
+                match groups.iter_mut().find(|group| group.0 == id) {
+                    Some(group) => group.1.push(entry.path.clone()),
+                    None => groups.push((id, vec![entry.path.clone()])),
+                }
+            }
+        }
+        groups.into_iter().map(|group| group.1).collect()
+    }
+}
+
+/// Options for Gluster::restore_tree
+#[derive(Clone)]
+pub struct RestoreOptions<'a> {
+    source: Option<&'a Gluster>,
+    ownership: bool,
+    verify: bool,
+}
+
+impl<'a> Default for RestoreOptions<'a> {
+    fn default() -> RestoreOptions<'a> {
+        RestoreOptions {
+            source: None,
+            ownership: true,
+            verify: true,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for RestoreOptions<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RestoreOptions")
+            .field("source", &self.source.is_some())
+            .field("ownership", &self.ownership)
+            .field("verify", &self.verify)
+            .finish()
+    }
+}
+
+impl<'a> RestoreOptions<'a> {
+    pub fn new() -> RestoreOptions<'a> {
+        RestoreOptions::default()
+    }
+
+    /// The connection to the volume the snapshot was taken on, to read
+    /// file content from.  Defaults to the connection restoring.
+    pub fn source(mut self, source: &'a Gluster) -> RestoreOptions<'a> {
+        self.source = Some(source);
+        self
+    }
+
+    /// chown everything to its recorded owner and group.  Where that
+    /// fails with EPERM a warning is added to the report instead.
+    /// Defaults to true.
+    pub fn ownership(mut self, ownership: bool) -> RestoreOptions<'a> {
+        self.ownership = ownership;
+        self
+    }
+
+    /// Check each file's content against the SHA-256 recorded in the
+    /// image as it's copied, failing if the source has changed since.
+    /// Defaults to true.
+    pub fn verify(mut self, verify: bool) -> RestoreOptions<'a> {
+        self.verify = verify;
+        self
+    }
+}
+
+/// What Gluster::restore_tree did
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestoreReport {
+    /// Directories created, the root included
+    pub dirs: u64,
+    /// Files whose content was copied
+    pub files: u64,
+    /// Extra links made to files already restored
+    pub hard_links: u64,
+    pub symlinks: u64,
+    pub special: u64,
+    /// Bytes of file data copied
+    pub bytes: u64,
+    /// Paths in the destination and the ownership left behind on them
+    pub warnings: Vec<(PathBuf, PreserveWarning)>,
+}
+
+// Hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn timespec_of(secs: i64, nsecs: i64) -> timespec {
+    timespec {
+        tv_sec: secs as _,
+        tv_nsec: nsecs as _,
+    }
+}
+
+impl Gluster {
+    /// Describe the tree under root: every directory, file, symlink and
+    /// special file with its mode, owner, times and user.* xattrs, files
+    /// with the SHA-256 of their content and which of them are hard
+    /// links to each other.  Each file is read once to checksum it.
+    pub fn snapshot_tree(&self, root: &Path) -> Result<TreeImage, GlusterError> {
+        let root_metadata = self.symlink_metadata(root)?;
+        if !root_metadata.is_dir() {
+            return Err(GlusterError::new(format!(
+                "{} isn't a directory",
+                root.display()
+            )));
+        }
+        // Digests of the files with more than one link, by (dev, ino)
+        let mut digests: HashMap<(u64, u64), String> = HashMap::new();
+        let mut entries =
+            vec![self.image_entry(root, Path::new(""), &root_metadata, &mut digests)?];
+        for entry in self.walk(root, &WalkOptions::new().min_depth(1)) {
+            let entry = entry?;
+            let relative = entry
+                .path
+                .strip_prefix(root)
+                .unwrap_or(&entry.path)
+                .to_path_buf();
+            entries.push(self.image_entry(
+                &entry.path,
+                &relative,
+                &entry.metadata,
+                &mut digests,
+            )?);
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(TreeImage {
+            root: root.to_path_buf(),
+            entries,
+        })
+    }
+
+    fn image_entry(
+        &self,
+        path: &Path,
+        relative: &Path,
+        metadata: &Metadata,
+        digests: &mut HashMap<(u64, u64), String>,
+    ) -> Result<ImageEntry, GlusterError> {
+        let kind = if metadata.is_dir() {
+            ImageKind::Dir
+        } else if metadata.is_symlink() {
+            ImageKind::Symlink {
+                target: self.read_link(path)?,
+            }
+        } else if metadata.is_file() {
+            let link_group = if metadata.nlink() > 1 {
+                Some(metadata.file_id())
+            } else {
+                None
+            };
+            let sha256 = match link_group.and_then(|id| digests.get(&id)) {
+                Some(digest) => digest.clone(),
+                None => {
+                    let mut file = self.open_file(path, O_RDONLY)?;
+                    let digest = ChecksumAlgorithm::Sha256.digest_reader(&mut file)?;
+                    file.close()?;
+                    to_hex(&digest)
+                }
+            };
+            if let Some(id) = link_group {
+                digests.insert(id, sha256.clone());
+            }
+            ImageKind::File {
+                size: metadata.len(),
+                sha256,
+                link_group,
+            }
+        } else {
+            #[allow(clippy::unnecessary_cast)]
+            let rdev = metadata.as_stat().st_rdev as u64;
+            ImageKind::Special { rdev }
+        };
+        let mut xattrs = Vec::new();
+        if !metadata.is_symlink() {
+            for name in self.list_xattr_raw(path)? {
+                if XattrNamespace::of(&name) == Some(XattrNamespace::User) {
+                    let value = self.getxattr_raw_name(path, &name)?;
+                    xattrs.push((name, value));
+                }
+            }
+            xattrs.sort();
+        }
+        Ok(ImageEntry {
+            path: relative.to_path_buf(),
+            kind,
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            atime: metadata.atime(),
+            atime_nsec: metadata.atime_nsec(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            xattrs,
+        })
+    }
+
+    /// Recreate the tree image describes at dest_root, which mustn't
+    /// exist yet, on this connection.  File content is read from the
+    /// image's root on the connection given with RestoreOptions::source,
+    /// which can be this one.  Files in one link group are restored as
+    /// hard links to a single file.  Attributes are applied last,
+    /// deepest first, so a directory's mtime and a read only mode don't
+    /// get in the way of restoring what's in it.
+    pub fn restore_tree(
+        &self,
+        image: &TreeImage,
+        dest_root: &Path,
+        opts: &RestoreOptions,
+    ) -> Result<RestoreReport, GlusterError> {
+        let source = opts.source.unwrap_or(self);
+        let mut report = RestoreReport::default();
+        if let Some(parent) = dest_root.parent() {
+            self.create_dir_all(parent, defaults::DIR_0755)?;
+        }
+        // The first path restored for each link group
+        let mut linked: HashMap<(u64, u64), PathBuf> = HashMap::new();
+        // Entries whose attributes still need applying
+        let mut pending: Vec<(&ImageEntry, PathBuf)> = Vec::with_capacity(image.entries.len());
+        for entry in &image.entries {
+            let dest = if entry.path.as_os_str().is_empty() {
+                dest_root.to_path_buf()
+            } else {
+                dest_root.join(&entry.path)
+            };
+            match entry.kind {
+                ImageKind::Dir => {
+                    // Writable until its real mode goes on at the end
+                    if let Err(e) = self.mkdir(&dest, 0o700) {
+                        if errno() == Errno(EEXIST) {
+                            return Err(GlusterError::AlreadyExists { path: dest });
+                        }
+                        return Err(e);
+                    }
+                    report.dirs += 1;
+                }
+                ImageKind::File {
+                    size,
+                    ref sha256,
+                    link_group,
+                } => {
+                    if let Some(first) = link_group.and_then(|id| linked.get(&id)) {
+                        self.link(first, &dest)?;
+                        report.hard_links += 1;
+                        // The attributes belong to the file, applied
+                        // through its first link
+                        continue;
+                    }
+                    let from = image.root.join(&entry.path);
+                    let mut reader = HashingReader {
+                        inner: source.open_file(&from, O_RDONLY)?,
+                        hasher: Sha256::new(),
+                    };
+                    let write_opts = WriteOptions::new().mode(0o600);
+                    let bytes = self.write_from_reader(&dest, &mut reader, &write_opts)?;
+                    let HashingReader { inner, hasher } = reader;
+                    inner.close()?;
+                    if opts.verify && (bytes != size || to_hex(&hasher.finish()) != *sha256) {
+                        return Err(GlusterError::new(format!(
+                            "{} has changed since it was snapshotted",
+                            from.display()
+                        )));
+                    }
+                    if let Some(id) = link_group {
+                        linked.insert(id, dest.clone());
+                    }
+                    report.files += 1;
+                    report.bytes += bytes;
+                }
+                ImageKind::Symlink { ref target } => {
+                    self.symlink(target, &dest)?;
+                    report.symlinks += 1;
+                }
+                ImageKind::Special { rdev } => {
+                    self.mknod(&dest, entry.mode, rdev as dev_t)?;
+                    report.special += 1;
+                }
+            }
+            pending.push((entry, dest));
+        }
+        for &(entry, ref dest) in pending.iter().rev() {
+            self.restore_attributes(entry, dest, opts, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    // Put entry's xattrs, owner, mode and times on dest, in that order
+    // since chown can clear set-id bits
+    fn restore_attributes(
+        &self,
+        entry: &ImageEntry,
+        dest: &Path,
+        opts: &RestoreOptions,
+        report: &mut RestoreReport,
+    ) -> Result<(), GlusterError> {
+        let is_symlink = matches!(entry.kind, ImageKind::Symlink { .. });
+        for (name, value) in &entry.xattrs {
+            self.setxattr_raw_name(dest, name, value, 0)?;
+        }
+        if opts.ownership {
+            if let Err(e) = self.lchown(dest, entry.uid, entry.gid) {
+                if errno() != Errno(EPERM) {
+                    return Err(e);
+                }
+                report.warnings.push((
+                    dest.to_path_buf(),
+                    PreserveWarning {
+                        item: PreserveItem::Ownership,
+                        error: e.to_string(),
+                    },
+                ));
+            }
+        }
+        let times = [
+            timespec_of(entry.atime, entry.atime_nsec),
+            timespec_of(entry.mtime, entry.mtime_nsec),
+        ];
+        if is_symlink {
+            self.lutimens(dest, &times)
+        } else {
+            self.chmod(dest, entry.mode & 0o7777)?;
+            self.utimens(dest, &times)
+        }
+    }
+}
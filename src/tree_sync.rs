@@ -0,0 +1,348 @@
+use errno::{errno, Errno};
+use libc::{timespec, ENOENT, O_CREAT, O_EXCL, O_WRONLY};
+
+use copy::{CopyOptions, CopyOutcome, Overwrite};
+use gluster::{Gluster, GlusterError};
+use local_transfer::LocalTransferOptions;
+use manifest::{local_manifest, Manifest, ManifestEntry, ManifestOptions};
+use mode::defaults;
+
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Name of the manifest sync_tree keeps in the destination root
+pub const SYNC_MANIFEST: &str = ".gfapi-manifest";
+
+/// Where Gluster::sync_tree reads the files to sync from
+#[derive(Clone, Copy, Debug)]
+pub enum SourceTree<'a> {
+    /// A directory on the local filesystem
+    Local(&'a Path),
+    /// A directory on the same volume as the destination
+    Remote(&'a Path),
+}
+
+/// One change sync_tree made, or would make in a dry run.  Paths are
+/// relative to the roots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Copy a file the destination doesn't have
+    Create { path: PathBuf },
+    /// Copy a file whose size, mtime or digest differs
+    Update { path: PathBuf },
+    /// Remove a destination file missing from the source
+    Delete { path: PathBuf },
+}
+
+impl SyncAction {
+    pub fn path(&self) -> &Path {
+        match *self {
+            SyncAction::Create { ref path }
+            | SyncAction::Update { ref path }
+            | SyncAction::Delete { ref path } => path,
+        }
+    }
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyncAction::Create { ref path } => write!(f, "create {}", path.display()),
+            SyncAction::Update { ref path } => write!(f, "update {}", path.display()),
+            SyncAction::Delete { ref path } => write!(f, "delete {}", path.display()),
+        }
+    }
+}
+
+/// Options for Gluster::sync_tree
+#[derive(Clone, Debug)]
+pub struct TreeSyncOptions {
+    delete_extraneous: bool,
+    dry_run: bool,
+    trust_stored_manifest: bool,
+    manifest: ManifestOptions,
+    transfer: LocalTransferOptions,
+    copy: CopyOptions,
+}
+
+impl Default for TreeSyncOptions {
+    fn default() -> TreeSyncOptions {
+        TreeSyncOptions {
+            delete_extraneous: false,
+            dry_run: false,
+            trust_stored_manifest: true,
+            manifest: ManifestOptions::default(),
+            transfer: LocalTransferOptions::default(),
+            copy: CopyOptions::default().create_parents(true),
+        }
+    }
+}
+
+impl TreeSyncOptions {
+    pub fn new() -> TreeSyncOptions {
+        TreeSyncOptions::default()
+    }
+
+    /// Delete destination files that aren't in the source.  Directories
+    /// left empty stay.  Defaults to false.
+    pub fn delete_extraneous(mut self, delete: bool) -> TreeSyncOptions {
+        self.delete_extraneous = delete;
+        self
+    }
+
+    /// Work out the actions and return them without changing anything.
+    /// Defaults to false.
+    pub fn dry_run(mut self, dry_run: bool) -> TreeSyncOptions {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Compare the source against the manifest the last sync stored in
+    /// the destination, rather than checksumming the destination again.
+    /// Changes made to the destination behind sync_tree's back go
+    /// unnoticed then.  Without a stored manifest the destination is
+    /// always checksummed.  Defaults to true.
+    pub fn trust_stored_manifest(mut self, trust: bool) -> TreeSyncOptions {
+        self.trust_stored_manifest = trust;
+        self
+    }
+
+    /// How manifests of trees on the volume are made.  Its worker count
+    /// is used for local trees too.
+    pub fn manifest_options(mut self, manifest: ManifestOptions) -> TreeSyncOptions {
+        self.manifest = manifest;
+        self
+    }
+
+    /// How files are copied from a SourceTree::Local
+    pub fn transfer_options(mut self, transfer: LocalTransferOptions) -> TreeSyncOptions {
+        self.transfer = transfer;
+        self
+    }
+
+    /// How files are copied from a SourceTree::Remote.  Parents are
+    /// always created and destinations replaced, whatever the
+    /// CopyOptions say.
+    pub fn copy_options(mut self, copy: CopyOptions) -> TreeSyncOptions {
+        self.copy = copy;
+        self
+    }
+}
+
+/// What Gluster::sync_tree did
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeSyncReport {
+    /// In the order they were carried out, or planned in a dry run
+    pub actions: Vec<SyncAction>,
+    /// Files left alone because they were already the same
+    pub unchanged: u64,
+    /// Bytes of file data copied
+    pub bytes: u64,
+    pub dry_run: bool,
+}
+
+/// The actions that make a destination described by destination match
+/// a source described by source.  Creates and updates come first, in
+/// path order, then deletes if delete_extraneous is set.
+pub fn plan_sync(
+    source: &Manifest,
+    destination: &Manifest,
+    delete_extraneous: bool,
+) -> Vec<SyncAction> {
+    let diff = destination.diff(source);
+    let mut actions: Vec<SyncAction> = Vec::new();
+    let mut added = diff.added.into_iter().peekable();
+    let mut changed = diff.changed.into_iter().peekable();
+    // Merge the two sorted lists
+    loop {
+        let take_added = match (added.peek(), changed.peek()) {
+            (None, None) => break,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some(a), Some(c)) => a < c,
+        };
+        actions.push(if take_added {
+            SyncAction::Create {
+                path: added.next().unwrap(),
+            }
+        } else {
+            SyncAction::Update {
+                path: changed.next().unwrap(),
+            }
+        });
+    }
+    if delete_extraneous {
+        actions.extend(
+            diff.removed
+                .into_iter()
+                .map(|path| SyncAction::Delete { path }),
+        );
+    }
+    actions
+}
+
+// The manifest without the stored manifest's own entry
+fn without_sync_manifest(manifest: Manifest) -> Manifest {
+    Manifest::new(
+        manifest
+            .entries()
+            .iter()
+            .filter(|entry| entry.path != Path::new(SYNC_MANIFEST))
+            .cloned()
+            .collect(),
+    )
+}
+
+impl Gluster {
+    /// Make the tree under dest_root match source, copying only files
+    /// whose size, mtime or SHA-256 differ from what the destination
+    /// has.  Copied files get their source's mtime.  Afterwards the
+    /// source's manifest is stored in dest_root as .gfapi-manifest, and
+    /// the next sync compares against it instead of checksumming the
+    /// destination, see TreeSyncOptions::trust_stored_manifest.
+    ///
+    /// The first failure stops the sync and leaves the stored manifest
+    /// as it was, so the next sync redoes whatever didn't finish.
+    pub fn sync_tree(
+        &self,
+        source: SourceTree,
+        dest_root: &Path,
+        opts: &TreeSyncOptions,
+    ) -> Result<TreeSyncReport, GlusterError> {
+        let source_manifest = without_sync_manifest(match source {
+            SourceTree::Local(root) => local_manifest(root, opts.manifest.workers)?,
+            SourceTree::Remote(root) => self.manifest(root, &opts.manifest)?,
+        });
+        let destination_manifest = self.destination_manifest(dest_root, opts)?;
+        let actions = plan_sync(
+            &source_manifest,
+            &destination_manifest,
+            opts.delete_extraneous,
+        );
+        let mut report = TreeSyncReport {
+            unchanged: (source_manifest.len() - copies(&actions)) as u64,
+            dry_run: opts.dry_run,
+            ..TreeSyncReport::default()
+        };
+        if opts.dry_run {
+            report.actions = actions;
+            return Ok(report);
+        }
+        for action in actions {
+            let dest = dest_root.join(action.path());
+            match action {
+                SyncAction::Create { ref path } | SyncAction::Update { ref path } => {
+                    // Every created or updated path comes from the source
+                    let entry = source_manifest.get(path).unwrap();
+                    report.bytes += self.sync_one(source, path, &dest, opts)?;
+                    self.set_mtime(&dest, entry)?;
+                }
+                SyncAction::Delete { .. } => match self.unlink(&dest) {
+                    Ok(()) => {}
+                    // Already gone, the stored manifest was out of date
+                    Err(_) if errno() == Errno(ENOENT) => {}
+                    Err(e) => return Err(e),
+                },
+            }
+            report.actions.push(action);
+        }
+        self.store_sync_manifest(dest_root, &source_manifest)?;
+        Ok(report)
+    }
+
+    fn destination_manifest(
+        &self,
+        dest_root: &Path,
+        opts: &TreeSyncOptions,
+    ) -> Result<Manifest, GlusterError> {
+        if !self.exists(dest_root)? {
+            return Ok(Manifest::default());
+        }
+        if opts.trust_stored_manifest {
+            match self.read_to_vec(&dest_root.join(SYNC_MANIFEST)) {
+                Ok(bytes) => match Manifest::parse(&bytes) {
+                    Some(manifest) => return Ok(manifest),
+                    None => warn!(
+                        "ignoring the malformed {} in {}",
+                        SYNC_MANIFEST,
+                        dest_root.display()
+                    ),
+                },
+                Err(_) if errno() == Errno(ENOENT) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(without_sync_manifest(
+            self.manifest(dest_root, &opts.manifest)?,
+        ))
+    }
+
+    // Copy path from source to dest, returning the bytes copied
+    fn sync_one(
+        &self,
+        source: SourceTree,
+        path: &Path,
+        dest: &Path,
+        opts: &TreeSyncOptions,
+    ) -> Result<u64, GlusterError> {
+        match source {
+            SourceTree::Local(root) => {
+                if let Some(parent) = dest.parent() {
+                    self.create_dir_all(parent, defaults::DIR_0755)?;
+                }
+                let report = self.upload_local_file(&root.join(path), dest, &opts.transfer)?;
+                Ok(report.transferred - report.holes)
+            }
+            SourceTree::Remote(root) => {
+                let copy = opts
+                    .copy
+                    .clone()
+                    .create_parents(true)
+                    .overwrite(Overwrite::Always);
+                match self.copy_with(&root.join(path), dest, &copy)? {
+                    CopyOutcome::Copied(report) => Ok(report.bytes),
+                    _ => Ok(0),
+                }
+            }
+        }
+    }
+
+    fn set_mtime(&self, path: &Path, entry: &ManifestEntry) -> Result<(), GlusterError> {
+        let mtime = timespec {
+            tv_sec: entry.mtime as _,
+            tv_nsec: entry.mtime_nsec as _,
+        };
+        self.utimens(path, &[mtime, mtime])
+    }
+
+    // Replace the stored manifest in one rename
+    fn store_sync_manifest(
+        &self,
+        dest_root: &Path,
+        manifest: &Manifest,
+    ) -> Result<(), GlusterError> {
+        self.create_dir_all(dest_root, defaults::DIR_0755)?;
+        let scratch = dest_root.join(format!("{}.{}.tmp", SYNC_MANIFEST, process::id()));
+        let mut file =
+            self.create_file(&scratch, O_CREAT | O_EXCL | O_WRONLY, defaults::FILE_0644)?;
+        let stored = file
+            .write_all(&manifest.to_bytes())
+            .map_err(GlusterError::from)
+            .and_then(|_| file.close())
+            .and_then(|_| self.rename(&scratch, &dest_root.join(SYNC_MANIFEST)));
+        if stored.is_err() {
+            let _ = self.unlink(&scratch);
+        }
+        stored
+    }
+}
+
+// How many of actions copy a file
+fn copies(actions: &[SyncAction]) -> usize {
+    actions
+        .iter()
+        .filter(|action| !matches!(**action, SyncAction::Delete { .. }))
+        .count()
+}
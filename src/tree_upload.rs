@@ -0,0 +1,385 @@
+use errno::{errno, Errno};
+use libc::{mode_t, timespec, ENOENT};
+
+use batch::parallel_map;
+use buffer_pool::BufferPool;
+use glob::Glob;
+use gluster::{Gluster, GlusterError};
+use mode::defaults;
+use upload::{Upload, UploadOptions};
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Size of each part handed to Upload::write_part
+const UPLOAD_PART: usize = 4 * 1024 * 1024;
+
+/// Options for Gluster::upload_tree
+#[derive(Clone, Debug)]
+pub struct TreeUploadOptions {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    workers: usize,
+    skip_unchanged: bool,
+    collect_errors: bool,
+}
+
+impl Default for TreeUploadOptions {
+    fn default() -> TreeUploadOptions {
+        TreeUploadOptions {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            workers: 4,
+            skip_unchanged: true,
+            collect_errors: false,
+        }
+    }
+}
+
+impl TreeUploadOptions {
+    pub fn new() -> TreeUploadOptions {
+        TreeUploadOptions::default()
+    }
+
+    /// Only upload files and symlinks matching one of the include
+    /// patterns.  Directories are always created.  With no include
+    /// patterns everything not excluded is uploaded.
+    pub fn include(mut self, pattern: Glob) -> TreeUploadOptions {
+        self.include.push(pattern);
+        self
+    }
+
+    /// Leave out anything matching the pattern, and everything under a
+    /// directory that matches it
+    pub fn exclude(mut self, pattern: Glob) -> TreeUploadOptions {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Number of files uploaded at once.  Defaults to 4.
+    pub fn workers(mut self, workers: usize) -> TreeUploadOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Leave alone files already on the volume with the same size and
+    /// mtime.  Defaults to true.
+    pub fn skip_unchanged(mut self, skip: bool) -> TreeUploadOptions {
+        self.skip_unchanged = skip;
+        self
+    }
+
+    /// Carry on past paths that fail, recording them in
+    /// UploadReport::failed, instead of stopping at the first.  Defaults
+    /// to false.
+    pub fn collect_errors(mut self, collect: bool) -> TreeUploadOptions {
+        self.collect_errors = collect;
+        self
+    }
+
+    // Whether relative is left out of the upload
+    fn filtered(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.exclude.iter().any(|glob| glob.matches(relative)) {
+            return true;
+        }
+        !is_dir
+            && !self.include.is_empty()
+            && !self.include.iter().any(|glob| glob.matches(relative))
+    }
+}
+
+/// A path Gluster::upload_tree couldn't upload
+#[derive(Debug)]
+pub struct UploadFailure {
+    /// Relative to the roots
+    pub path: PathBuf,
+    pub error: GlusterError,
+}
+
+/// What Gluster::upload_tree did.  Paths are relative to the roots.
+#[derive(Debug, Default)]
+pub struct UploadReport {
+    /// Files uploaded, in the order the local tree was walked
+    pub uploaded: Vec<PathBuf>,
+    /// Files already on the volume with the same size and mtime
+    pub unchanged: u64,
+    /// Symlinks created or pointed somewhere new
+    pub symlinks: Vec<PathBuf>,
+    /// Directories created
+    pub dirs_created: u64,
+    /// Entries left out by the include and exclude patterns, counting
+    /// an excluded directory once
+    pub excluded: u64,
+    /// Bytes of file data uploaded
+    pub bytes: u64,
+    /// Only filled in with TreeUploadOptions::collect_errors
+    pub failed: Vec<UploadFailure>,
+}
+
+impl UploadReport {
+    /// True when nothing failed
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+// Something found in the local tree
+struct LocalEntry {
+    relative: PathBuf,
+    metadata: fs::Metadata,
+}
+
+// Record error against path, or return it if errors aren't being
+// collected
+fn record(
+    opts: &TreeUploadOptions,
+    report: &mut UploadReport,
+    path: PathBuf,
+    error: GlusterError,
+) -> Result<(), GlusterError> {
+    if !opts.collect_errors {
+        return Err(error);
+    }
+    report.failed.push(UploadFailure { path, error });
+    Ok(())
+}
+
+// Add what's under root/relative to entries, parents before their
+// children and in name order within a directory
+fn scan(
+    root: &Path,
+    relative: &Path,
+    opts: &TreeUploadOptions,
+    entries: &mut Vec<LocalEntry>,
+    report: &mut UploadReport,
+) -> Result<(), GlusterError> {
+    let names = fs::read_dir(root.join(relative)).and_then(|dir| {
+        dir.map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    let mut names = match names {
+        Ok(names) => names,
+        Err(e) => return record(opts, report, relative.to_path_buf(), e.into()),
+    };
+    names.sort();
+    for name in names {
+        let child = relative.join(name);
+        let metadata = match fs::symlink_metadata(root.join(&child)) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                record(opts, report, child, e.into())?;
+                continue;
+            }
+        };
+        let is_dir = metadata.is_dir();
+        if opts.filtered(&child, is_dir) {
+            report.excluded += 1;
+            continue;
+        }
+        entries.push(LocalEntry {
+            relative: child.clone(),
+            metadata,
+        });
+        if is_dir {
+            scan(root, &child, opts, entries, report)?;
+        }
+    }
+    Ok(())
+}
+
+fn timespec_of(secs: i64, nsecs: i64) -> timespec {
+    timespec {
+        tv_sec: secs as _,
+        tv_nsec: nsecs as _,
+    }
+}
+
+// Write everything source holds into upload
+fn send_parts(upload: &mut Upload, source: &mut File) -> Result<(), GlusterError> {
+    let mut buffer = BufferPool::global().get(UPLOAD_PART);
+    let mut offset = 0;
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        upload.write_part(offset, &buffer[..read])?;
+        offset += read as u64;
+    }
+}
+
+impl Gluster {
+    /// Copy the local tree under local_root into remote_root on the
+    /// volume, the other way from mirror_to_local.  Directories are
+    /// created with the local modes and symlinks recreated.  Files are
+    /// streamed up through Gluster::upload, up to opts.workers at a
+    /// time, so each appears on the volume complete or not at all, and
+    /// then given their local mtime.  Ownership and xattrs aren't
+    /// copied.
+    ///
+    /// Files already on the volume with the same size and mtime are
+    /// skipped, see TreeUploadOptions::skip_unchanged, so after an
+    /// interrupted upload running it again carries on where it stopped.
+    pub fn upload_tree(
+        &self,
+        local_root: &Path,
+        remote_root: &Path,
+        opts: &TreeUploadOptions,
+    ) -> Result<UploadReport, GlusterError> {
+        let mut report = UploadReport::default();
+        let root_mode = fs::metadata(local_root)?.mode() as mode_t;
+        if let Some(parent) = remote_root.parent() {
+            self.create_dir_all(parent, defaults::DIR_0755)?;
+        }
+        if self.upload_tree_dir(remote_root, root_mode)? {
+            report.dirs_created += 1;
+        }
+        let mut entries = Vec::new();
+        scan(local_root, Path::new(""), opts, &mut entries, &mut report)?;
+
+        // Directories are given their exact modes last, children first,
+        // in case one doesn't let its owner write into it
+        let mut dirs: Vec<(PathBuf, mode_t)> = vec![(remote_root.to_path_buf(), root_mode)];
+        let mut files: Vec<LocalEntry> = Vec::new();
+        for entry in entries {
+            let dest = remote_root.join(&entry.relative);
+            let file_type = entry.metadata.file_type();
+            if file_type.is_dir() {
+                match self.upload_tree_dir(&dest, entry.metadata.mode() as mode_t) {
+                    Ok(created) => {
+                        if created {
+                            report.dirs_created += 1;
+                        }
+                        dirs.push((dest, entry.metadata.mode() as mode_t));
+                    }
+                    Err(e) => record(opts, &mut report, entry.relative, e)?,
+                }
+            } else if file_type.is_symlink() {
+                match self.upload_tree_symlink(&local_root.join(&entry.relative), &dest) {
+                    Ok(true) => report.symlinks.push(entry.relative),
+                    Ok(false) => {}
+                    Err(e) => record(opts, &mut report, entry.relative, e)?,
+                }
+            } else if file_type.is_file() {
+                files.push(entry);
+            } else {
+                trace!("not uploading special file {}", entry.relative.display());
+            }
+        }
+
+        // Set once a file fails, so the rest aren't started when the
+        // failure is going to be returned anyway
+        let stop = AtomicBool::new(false);
+        let outcomes = parallel_map(&files, opts.workers, |entry| {
+            if stop.load(Ordering::SeqCst) {
+                return None;
+            }
+            let outcome = self.upload_tree_file(
+                &local_root.join(&entry.relative),
+                &remote_root.join(&entry.relative),
+                &entry.metadata,
+                opts,
+            );
+            if outcome.is_err() && !opts.collect_errors {
+                stop.store(true, Ordering::SeqCst);
+            }
+            Some(outcome)
+        });
+        for (entry, outcome) in files.into_iter().zip(outcomes) {
+            match outcome {
+                Some(Ok(Some(bytes))) => {
+                    report.bytes += bytes;
+                    report.uploaded.push(entry.relative);
+                }
+                Some(Ok(None)) => report.unchanged += 1,
+                Some(Err(e)) => record(opts, &mut report, entry.relative, e)?,
+                // Skipped after an earlier failure
+                None => {}
+            }
+        }
+
+        for (dest, mode) in dirs.iter().rev() {
+            if let Err(e) = self.chmod(dest, mode & 0o7777) {
+                let relative = dest.strip_prefix(remote_root).unwrap_or(dest);
+                record(opts, &mut report, relative.to_path_buf(), e)?;
+            }
+        }
+        Ok(report)
+    }
+
+    // Make sure dest is a directory, returning whether it was created
+    fn upload_tree_dir(&self, dest: &Path, mode: mode_t) -> Result<bool, GlusterError> {
+        match self.symlink_metadata(dest) {
+            Ok(ref metadata) if metadata.is_dir() => Ok(false),
+            Ok(_) => Err(GlusterError::new(format!(
+                "{} is in the way of a directory",
+                dest.display()
+            ))),
+            Err(_) if errno() == Errno(ENOENT) => {
+                // Writable until upload_tree sets the real mode
+                self.mkdir(dest, (mode & 0o7777) | 0o700)?;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Point dest where the local symlink at local points, returning
+    // whether anything changed
+    fn upload_tree_symlink(&self, local: &Path, dest: &Path) -> Result<bool, GlusterError> {
+        let target = fs::read_link(local)?;
+        if self.read_link(dest).ok().as_ref() == Some(&target) {
+            return Ok(false);
+        }
+        match self.unlink(dest) {
+            Ok(()) => {}
+            Err(_) if errno() == Errno(ENOENT) => {}
+            Err(e) => return Err(e),
+        }
+        self.symlink(&target, dest)?;
+        Ok(true)
+    }
+
+    // Upload local to dest unless it's unchanged, returning the bytes
+    // uploaded or None if it was skipped
+    fn upload_tree_file(
+        &self,
+        local: &Path,
+        dest: &Path,
+        metadata: &fs::Metadata,
+        opts: &TreeUploadOptions,
+    ) -> Result<Option<u64>, GlusterError> {
+        if opts.skip_unchanged {
+            if let Ok(existing) = self.symlink_metadata(dest) {
+                if existing.is_file()
+                    && existing.len() == metadata.len()
+                    && existing.mtime() == metadata.mtime()
+                    && existing.mtime_nsec() == metadata.mtime_nsec()
+                {
+                    return Ok(None);
+                }
+            }
+        }
+        let mut source = File::open(local)?;
+        let upload_opts = UploadOptions::new()
+            .total_len(metadata.len())
+            .mode(metadata.mode() as mode_t & 0o7777);
+        let mut upload = self.upload(dest, &upload_opts)?;
+        if let Err(e) = send_parts(&mut upload, &mut source) {
+            let _ = upload.abort();
+            return Err(e);
+        }
+        let len = upload.finish()?;
+        self.utimens(
+            dest,
+            &[
+                timespec_of(metadata.atime(), metadata.atime_nsec()),
+                timespec_of(metadata.mtime(), metadata.mtime_nsec()),
+            ],
+        )?;
+        Ok(Some(len))
+    }
+}
@@ -0,0 +1,189 @@
+use gluster::GlusterError;
+
+use std::fmt;
+
+/// One option set on the client graph with glfs_set_xlator_option
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XlatorOption {
+    /// Glob matching the xlators to set it on, e.g. "*-io-cache"
+    pub xlator: String,
+    pub key: String,
+    pub value: String,
+}
+
+impl XlatorOption {
+    pub fn new(xlator: &str, key: &str, value: &str) -> XlatorOption {
+        XlatorOption {
+            xlator: xlator.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for XlatorOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}={}", self.xlator, self.key, self.value)
+    }
+}
+
+const IO_CACHE: &str = "*-io-cache";
+const READ_AHEAD: &str = "*-read-ahead";
+const WRITE_BEHIND: &str = "*-write-behind";
+const READDIR_AHEAD: &str = "*-readdir-ahead";
+const OPEN_BEHIND: &str = "*-open-behind";
+const MD_CACHE: &str = "*-md-cache";
+
+const KB: u64 = 1024;
+const MB: u64 = 1024 * KB;
+const GB: u64 = 1024 * MB;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    // A byte count like "64MB", within the range
+    Size(u64, u64),
+    // A plain number within the range
+    Count(u64, u64),
+    Bool,
+}
+
+// The client side options the profiles use, with what values they accept
+const KNOWN_OPTIONS: &[(&str, &str, Kind)] = &[
+    (IO_CACHE, "cache-size", Kind::Size(4 * MB, 32 * GB)),
+    (IO_CACHE, "cache-timeout", Kind::Count(0, 60)),
+    (READ_AHEAD, "page-count", Kind::Count(1, 16)),
+    (WRITE_BEHIND, "cache-size", Kind::Size(512 * KB, GB)),
+    (WRITE_BEHIND, "flush-behind", Kind::Bool),
+    (
+        READDIR_AHEAD,
+        "rda-request-size",
+        Kind::Size(4 * KB, 128 * KB),
+    ),
+    (READDIR_AHEAD, "rda-cache-limit", Kind::Size(0, GB)),
+    (OPEN_BEHIND, "lazy-open", Kind::Bool),
+    (OPEN_BEHIND, "read-after-open", Kind::Bool),
+    (MD_CACHE, "md-cache-timeout", Kind::Count(0, 600)),
+];
+
+// Parse a gluster size such as 512KB, 64MB or 1GB into bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let upper = value.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &upper[digits.len()..] {
+        "" | "B" => 1,
+        "K" | "KB" => KB,
+        "M" | "MB" => MB,
+        "G" | "GB" => GB,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+fn validate(option: &XlatorOption) -> Result<(), GlusterError> {
+    let kind = KNOWN_OPTIONS
+        .iter()
+        .find(|&&(xlator, key, _)| xlator == option.xlator && key == option.key)
+        .map(|&(_, _, kind)| kind);
+    let valid = match kind {
+        // Passed through as is for the volfile to judge
+        None => return Ok(()),
+        Some(Kind::Size(min, max)) => match parse_size(&option.value) {
+            Some(size) => size >= min && size <= max,
+            None => false,
+        },
+        Some(Kind::Count(min, max)) => match option.value.parse::<u64>() {
+            Ok(count) => count >= min && count <= max,
+            Err(_) => false,
+        },
+        Some(Kind::Bool) => [
+            "on", "off", "yes", "no", "true", "false", "enable", "disable",
+        ]
+        .contains(&option.value.as_str()),
+    };
+    if !valid {
+        return Err(GlusterError::new(format!(
+            "invalid value for tuning option {}",
+            option
+        )));
+    }
+    Ok(())
+}
+
+/// A set of client side performance options applied to a connection by
+/// GlusterBuilder::tuning.  Options the profile doesn't set keep the
+/// values from the volfile.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum TuningProfile {
+    /// Leave everything to the volfile
+    #[default]
+    Default,
+    /// Large sequential reads and writes: big caches, deep read-ahead and
+    /// a wide write-behind window
+    Throughput,
+    /// Many small files: long metadata caching, lazy opens and large
+    /// directory read-ahead
+    SmallFiles,
+    /// Keep the client's caches small
+    LowMemory,
+    /// Exactly these options, see TuningProfile::with
+    Custom(Vec<XlatorOption>),
+}
+
+impl TuningProfile {
+    /// Start from this profile and set one more option, replacing what
+    /// the profile had for the same xlator and key
+    pub fn with(self, xlator: &str, key: &str, value: &str) -> TuningProfile {
+        let mut options = self.base_options();
+        let option = XlatorOption::new(xlator, key, value);
+        match options
+            .iter_mut()
+            .find(|o| o.xlator == option.xlator && o.key == option.key)
+        {
+            Some(existing) => existing.value = option.value,
+            None => options.push(option),
+        }
+        TuningProfile::Custom(options)
+    }
+
+    fn base_options(&self) -> Vec<XlatorOption> {
+        let options: &[(&str, &str, &str)] = match *self {
+            TuningProfile::Default => &[],
+            TuningProfile::Throughput => &[
+                (IO_CACHE, "cache-size", "256MB"),
+                (READ_AHEAD, "page-count", "16"),
+                (WRITE_BEHIND, "cache-size", "4MB"),
+                (WRITE_BEHIND, "flush-behind", "on"),
+                (READDIR_AHEAD, "rda-cache-limit", "10MB"),
+            ],
+            TuningProfile::SmallFiles => &[
+                (MD_CACHE, "md-cache-timeout", "600"),
+                (OPEN_BEHIND, "lazy-open", "on"),
+                (OPEN_BEHIND, "read-after-open", "on"),
+                (READDIR_AHEAD, "rda-request-size", "128KB"),
+                (READDIR_AHEAD, "rda-cache-limit", "64MB"),
+                (WRITE_BEHIND, "flush-behind", "on"),
+            ],
+            TuningProfile::LowMemory => &[
+                (IO_CACHE, "cache-size", "4MB"),
+                (READ_AHEAD, "page-count", "1"),
+                (WRITE_BEHIND, "cache-size", "512KB"),
+                (READDIR_AHEAD, "rda-cache-limit", "1MB"),
+            ],
+            TuningProfile::Custom(ref options) => return options.clone(),
+        };
+        options
+            .iter()
+            .map(|&(xlator, key, value)| XlatorOption::new(xlator, key, value))
+            .collect()
+    }
+
+    /// The options this profile sets, after checking the values of the
+    /// ones it knows about are in range
+    pub fn options(&self) -> Result<Vec<XlatorOption>, GlusterError> {
+        let options = self.base_options();
+        for option in &options {
+            validate(option)?;
+        }
+        Ok(options)
+    }
+}
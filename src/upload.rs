@@ -0,0 +1,379 @@
+use errno::{errno, Errno};
+use libc::{mode_t, ENOENT, O_CREAT, O_RDONLY, O_RDWR};
+
+use buffer_pool::BufferPool;
+use checksum::Crc32c;
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TEMP_SUFFIX: &str = ".upload";
+const STATE_SUFFIX: &str = ".upload-state";
+
+/// Options for Gluster::upload
+#[derive(Clone, Debug)]
+pub struct UploadOptions {
+    total_len: Option<u64>,
+    crc32c: Option<u32>,
+    mode: mode_t,
+}
+
+impl Default for UploadOptions {
+    fn default() -> UploadOptions {
+        UploadOptions {
+            total_len: None,
+            crc32c: None,
+            mode: 0o644,
+        }
+    }
+}
+
+impl UploadOptions {
+    pub fn new() -> UploadOptions {
+        UploadOptions::default()
+    }
+
+    /// Expected size of the finished file.  finish fails unless exactly
+    /// this much has been written.  Without it finish only checks there
+    /// are no holes.
+    pub fn total_len(mut self, total_len: u64) -> UploadOptions {
+        self.total_len = Some(total_len);
+        self
+    }
+
+    /// Expected CRC32C of the finished file, checked by finish
+    pub fn crc32c(mut self, crc32c: u32) -> UploadOptions {
+        self.crc32c = Some(crc32c);
+        self
+    }
+
+    /// Permissions of the finished file.  Defaults to 0644.
+    pub fn mode(mut self, mode: mode_t) -> UploadOptions {
+        self.mode = mode;
+        self
+    }
+}
+
+// Hidden names next to dest for the partial data and the progress record
+fn sidecar(dest: &Path, suffix: &str) -> PathBuf {
+    let mut name = ::std::ffi::OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(suffix);
+    dest.with_file_name(name)
+}
+
+// Add [start, end) to a sorted list of disjoint ranges, merging neighbours
+fn add_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    if start >= end {
+        return;
+    }
+    ranges.push((start, end));
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for &(s, e) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    *ranges = merged;
+}
+
+struct UploadState {
+    total_len: Option<u64>,
+    crc32c: Option<u32>,
+    mode: mode_t,
+    ranges: Vec<(u64, u64)>,
+}
+
+impl UploadState {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = String::new();
+        if let Some(total_len) = self.total_len {
+            out.push_str(&format!("total={}\n", total_len));
+        }
+        if let Some(crc) = self.crc32c {
+            out.push_str(&format!("crc32c={:08x}\n", crc));
+        }
+        out.push_str(&format!("mode={:o}\n", self.mode));
+        for &(start, end) in &self.ranges {
+            out.push_str(&format!("range={}-{}\n", start, end));
+        }
+        out.into_bytes()
+    }
+
+    fn decode(data: &[u8], path: &Path) -> Result<UploadState, GlusterError> {
+        let corrupt = || GlusterError::new(format!("corrupt upload state in {}", path.display()));
+        let text = String::from_utf8_lossy(data);
+        let mut state = UploadState {
+            total_len: None,
+            crc32c: None,
+            mode: 0o644,
+            ranges: Vec::new(),
+        };
+        for line in text.lines() {
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Err(corrupt()),
+            };
+            match key {
+                "total" => state.total_len = Some(value.parse().map_err(|_| corrupt())?),
+                "crc32c" => {
+                    state.crc32c = Some(u32::from_str_radix(value, 16).map_err(|_| corrupt())?)
+                }
+                "mode" => state.mode = mode_t::from_str_radix(value, 8).map_err(|_| corrupt())?,
+                "range" => {
+                    let mut bounds = value.splitn(2, '-');
+                    let start: u64 = bounds
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(corrupt)?;
+                    let end: u64 = bounds
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(corrupt)?;
+                    add_range(&mut state.ranges, start, end);
+                }
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// A resumable upload into dest.  Parts are written into a hidden temp
+/// file next to dest and the byte ranges known to be on stable storage
+/// are recorded in a hidden state file next to it, so after a crash
+/// Upload::resume picks up where the last session left off.  finish
+/// checks the file is complete and renames it into place.  Sessions that
+/// are never finished can be found with Gluster::pending_uploads.
+pub struct Upload<'a> {
+    gluster: &'a Gluster,
+    dest: PathBuf,
+    file: GlusterFile<'a>,
+    state: UploadState,
+}
+
+impl<'a> Upload<'a> {
+    /// Reopen the unfinished upload into dest
+    pub fn resume(gluster: &'a Gluster, dest: &Path) -> Result<Upload<'a>, GlusterError> {
+        let state_path = sidecar(dest, STATE_SUFFIX);
+        let state = UploadState::decode(&gluster.read_to_vec(&state_path)?, &state_path)?;
+        let file = gluster.open_file(&sidecar(dest, TEMP_SUFFIX), O_RDWR)?;
+        Ok(Upload {
+            gluster,
+            dest: dest.to_path_buf(),
+            file,
+            state,
+        })
+    }
+
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    /// Byte ranges written so far as sorted, non overlapping
+    /// [start, end) pairs
+    pub fn completed_ranges(&self) -> &[(u64, u64)] {
+        &self.state.ranges
+    }
+
+    /// Gaps still to be written.  Needs UploadOptions::total_len to know
+    /// about anything missing past the last written byte.
+    pub fn missing_ranges(&self) -> Vec<(u64, u64)> {
+        let mut missing = Vec::new();
+        let mut position = 0;
+        for &(start, end) in &self.state.ranges {
+            if start > position {
+                missing.push((position, start));
+            }
+            position = end;
+        }
+        if let Some(total_len) = self.state.total_len {
+            if position < total_len {
+                missing.push((position, total_len));
+            }
+        }
+        missing
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.state.ranges.iter().map(|&(s, e)| e - s).sum()
+    }
+
+    /// Write data at offset.  The part is synced before it's recorded as
+    /// done, so the state file never claims more than what's stored.
+    pub fn write_part(&mut self, offset: u64, data: &[u8]) -> Result<(), GlusterError> {
+        if let Some(total_len) = self.state.total_len {
+            if offset + data.len() as u64 > total_len {
+                return Err(GlusterError::new(format!(
+                    "part at {} of {} bytes goes past the expected length {}",
+                    offset,
+                    data.len(),
+                    total_len
+                )));
+            }
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+        self.file.fdatasync()?;
+        add_range(&mut self.state.ranges, offset, offset + data.len() as u64);
+        self.save_state()
+    }
+
+    // Replace the state file atomically so a crash mid write leaves the
+    // previous state behind
+    fn save_state(&self) -> Result<(), GlusterError> {
+        let state_path = sidecar(&self.dest, STATE_SUFFIX);
+        let mut scratch_name = state_path.file_name().unwrap_or_default().to_os_string();
+        scratch_name.push(".tmp");
+        let scratch = state_path.with_file_name(scratch_name);
+        self.gluster.write_file(&scratch, &self.state.encode())?;
+        self.gluster.rename(&scratch, &state_path)
+    }
+
+    /// Check every byte has been written (and the checksum, if one was
+    /// given), fsync and move the file into place.  Returns the size of
+    /// the file.
+    pub fn finish(self) -> Result<u64, GlusterError> {
+        let missing = self.missing_ranges();
+        if let Some(&(start, end)) = missing.first() {
+            return Err(GlusterError::new(format!(
+                "upload to {} is incomplete, bytes {} to {} are missing",
+                self.dest.display(),
+                start,
+                end
+            )));
+        }
+        let len = self.state.ranges.last().map(|r| r.1).unwrap_or(0);
+        self.file.fsync()?;
+        if let Some(expected) = self.state.crc32c {
+            let mut reader = self.gluster.open_file(&sidecar(&self.dest, TEMP_SUFFIX), O_RDONLY)?;
+            let mut crc = Crc32c::new();
+            let mut buffer = BufferPool::global().get(1024 * 1024);
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                crc.update(&buffer[..read]);
+            }
+            if crc.finish() != expected {
+                return Err(GlusterError::VerificationFailed {
+                    path: self.dest.clone(),
+                    offset: 0,
+                });
+            }
+        }
+        let Upload {
+            gluster,
+            dest,
+            file,
+            state,
+        } = self;
+        file.close()?;
+        gluster.chmod(&sidecar(&dest, TEMP_SUFFIX), state.mode)?;
+        gluster.rename(&sidecar(&dest, TEMP_SUFFIX), &dest)?;
+        gluster.unlink(&sidecar(&dest, STATE_SUFFIX))?;
+        Ok(len)
+    }
+
+    /// Give up on the upload and remove everything it wrote
+    pub fn abort(self) -> Result<(), GlusterError> {
+        let Upload {
+            gluster,
+            dest,
+            file,
+            ..
+        } = self;
+        file.close()?;
+        remove_upload_files(gluster, &dest)
+    }
+}
+
+fn remove_upload_files(gluster: &Gluster, dest: &Path) -> Result<(), GlusterError> {
+    for path in &[sidecar(dest, TEMP_SUFFIX), sidecar(dest, STATE_SUFFIX)] {
+        if let Err(e) = gluster.unlink(path) {
+            if errno() != Errno(ENOENT) {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An upload found on the volume that was never finished or aborted
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingUpload {
+    pub dest: PathBuf,
+    pub bytes_written: u64,
+    /// When the state file was last updated
+    pub last_modified: SystemTime,
+}
+
+impl Gluster {
+    /// Start a new resumable upload into dest, throwing away any earlier
+    /// unfinished upload into the same place
+    pub fn upload(&self, dest: &Path, opts: &UploadOptions) -> Result<Upload<'_>, GlusterError> {
+        remove_upload_files(self, dest)?;
+        let file = self.create_file(&sidecar(dest, TEMP_SUFFIX), O_CREAT | O_RDWR, 0o600)?;
+        let upload = Upload {
+            gluster: self,
+            dest: dest.to_path_buf(),
+            file,
+            state: UploadState {
+                total_len: opts.total_len,
+                crc32c: opts.crc32c,
+                mode: opts.mode,
+                ranges: Vec::new(),
+            },
+        };
+        upload.save_state()?;
+        Ok(upload)
+    }
+
+    /// Unfinished uploads into dir
+    pub fn pending_uploads(&self, dir: &Path) -> Result<Vec<PendingUpload>, GlusterError> {
+        let mut pending = Vec::new();
+        for (entry, metadata) in self.list_dir(dir, 1)? {
+            let name = entry.path.to_string_lossy().into_owned();
+            if !name.starts_with('.') || !name.ends_with(STATE_SUFFIX) {
+                continue;
+            }
+            let dest = dir.join(&name[1..name.len() - STATE_SUFFIX.len()]);
+            let state_path = dir.join(&name);
+            let state = match self.read_to_vec(&state_path) {
+                Ok(data) => UploadState::decode(&data, &state_path)?,
+                // Finished or aborted since the listing
+                Err(_) => continue,
+            };
+            pending.push(PendingUpload {
+                dest,
+                bytes_written: state.ranges.iter().map(|&(s, e)| e - s).sum(),
+                last_modified: UNIX_EPOCH + Duration::from_secs(metadata.mtime().max(0) as u64),
+            });
+        }
+        pending.sort_by(|a, b| a.dest.cmp(&b.dest));
+        Ok(pending)
+    }
+
+    /// Remove unfinished uploads into dir that haven't made progress for
+    /// longer than idle.  Returns the number removed.
+    pub fn clean_uploads(&self, dir: &Path, idle: Duration) -> Result<usize, GlusterError> {
+        let mut removed = 0;
+        for upload in self.pending_uploads(dir)? {
+            let age = SystemTime::now()
+                .duration_since(upload.last_modified)
+                .unwrap_or_default();
+            if age >= idle {
+                remove_upload_files(self, &upload.dest)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,267 @@
+use builder::{parse_servers, valid_transport, GlusterBuilder, VolfileServer};
+use gluster::{Gluster, GlusterError, GlusterLogLevel};
+
+use std::error::Error as err;
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+/// Why a gluster:// URL couldn't be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub enum UrlError {
+    /// The URL doesn't start with gluster://.  Holds the scheme it has,
+    /// empty if there's none.
+    UnsupportedScheme(String),
+    /// An entry of the host list isn't host[:port]
+    InvalidHost {
+        token: String,
+        reason: &'static str,
+    },
+    /// There's no path, or its first segment is empty
+    MissingVolume,
+    /// A path segment has bad percent-encoding, or decodes to something
+    /// that can't be a single path component
+    InvalidPathSegment(String),
+    UnknownQueryKey(String),
+    InvalidQueryValue {
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UrlError::UnsupportedScheme(ref scheme) if scheme.is_empty() => {
+                f.write_str("not a URL, expected gluster://")
+            }
+            UrlError::UnsupportedScheme(ref scheme) => {
+                write!(f, "unsupported scheme {:?}, expected gluster://", scheme)
+            }
+            UrlError::InvalidHost { ref token, reason } => {
+                write!(f, "invalid host {:?} in URL: {}", token, reason)
+            }
+            UrlError::MissingVolume => f.write_str("URL has no volume"),
+            UrlError::InvalidPathSegment(ref segment) => {
+                write!(f, "invalid path segment {:?} in URL", segment)
+            }
+            UrlError::UnknownQueryKey(ref key) => write!(f, "unknown URL parameter {:?}", key),
+            UrlError::InvalidQueryValue { ref key, ref value } => {
+                write!(f, "invalid value {:?} for URL parameter {}", value, key)
+            }
+        }
+    }
+}
+
+impl err for UrlError {
+    fn description(&self) -> &str {
+        "invalid gluster URL"
+    }
+}
+
+// RFC 3986 unreserved characters, which never need escaping
+fn unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_' || b == b'~'
+}
+
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if unreserved(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = s.get(i + 1..i + 3)?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        i += 3;
+    }
+    Some(decoded)
+}
+
+// A decoded path segment, which has to stay one normal component
+fn path_segment(segment: &str) -> Result<Vec<u8>, UrlError> {
+    let invalid = || UrlError::InvalidPathSegment(segment.to_string());
+    let decoded = percent_decode(segment).ok_or_else(invalid)?;
+    if decoded.is_empty() || decoded.contains(&b'/') || decoded.contains(&0) {
+        return Err(invalid());
+    }
+    let mut components = Path::new(OsStr::from_bytes(&decoded)).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(decoded),
+        _ => Err(invalid()),
+    }
+}
+
+fn query_bool(key: &str, value: &str) -> Result<bool, UrlError> {
+    match value {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(UrlError::InvalidQueryValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// Parse url into a builder, see Gluster::from_url
+pub fn parse_url(url: &str) -> Result<GlusterBuilder, UrlError> {
+    let rest = match url.find("://") {
+        Some(end) if url[..end].eq_ignore_ascii_case("gluster") => &url[end + 3..],
+        Some(end) => return Err(UrlError::UnsupportedScheme(url[..end].to_string())),
+        None => return Err(UrlError::UnsupportedScheme(String::new())),
+    };
+    let (rest, query) = match rest.find('?') {
+        Some(start) => (&rest[..start], Some(&rest[start + 1..])),
+        None => (rest, None),
+    };
+    let (hosts, path) = match rest.find('/') {
+        Some(start) => (&rest[..start], &rest[start + 1..]),
+        None => (rest, ""),
+    };
+
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let volume = segments.next().ok_or(UrlError::MissingVolume)?;
+    let volume = String::from_utf8(path_segment(volume)?)
+        .map_err(|_| UrlError::InvalidPathSegment(volume.to_string()))?;
+    let mut builder = GlusterBuilder::new(&volume);
+    let mut subdir = PathBuf::from("/");
+    for segment in segments {
+        subdir.push(OsStr::from_bytes(&path_segment(segment)?));
+    }
+    if subdir.parent().is_some() {
+        builder = builder.subdir(subdir);
+    }
+
+    // An empty host list keeps the builder's localhost
+    if !hosts.is_empty() {
+        let mut servers = match parse_servers(hosts) {
+            Ok(servers) => servers.into_iter(),
+            Err(GlusterError::InvalidServer { token, reason, .. }) => {
+                return Err(UrlError::InvalidHost { token, reason })
+            }
+            Err(_) => {
+                return Err(UrlError::InvalidHost {
+                    token: hosts.to_string(),
+                    reason: "invalid host list",
+                })
+            }
+        };
+        // parse_servers never returns an empty list
+        let first = servers.next().unwrap();
+        builder = builder.server(&first.host).port(first.port);
+        for backup in servers {
+            builder = builder.backup_server(&backup.host, backup.port);
+        }
+    }
+
+    for pair in query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+    {
+        let mut parts = pair.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let raw_value = parts.next().unwrap_or("");
+        let invalid = || UrlError::InvalidQueryValue {
+            key: key.to_string(),
+            value: raw_value.to_string(),
+        };
+        let value = percent_decode(raw_value)
+            .and_then(|value| String::from_utf8(value).ok())
+            .ok_or_else(invalid)?;
+        match key {
+            "transport" if valid_transport(&value) => builder = builder.transport(&value),
+            "log-level" => {
+                let level = value.parse::<GlusterLogLevel>().map_err(|_| invalid())?;
+                builder = builder.log_level(level);
+            }
+            "read-only" => builder = builder.read_only(query_bool(key, &value)?),
+            "transport" => return Err(invalid()),
+            _ => return Err(UrlError::UnknownQueryKey(key.to_string())),
+        }
+    }
+    Ok(builder)
+}
+
+impl Gluster {
+    /// A builder configured from a URL of the form
+    ///
+    /// gluster://host1:24007,host2,[fd00::1]/volume/sub/dir?transport=tcp&log-level=warning&read-only=true
+    ///
+    /// The hosts are a server list as parse_servers takes it, localhost
+    /// if left empty.  The first path segment is the volume and any more
+    /// are a subdirectory to mount, percent-encoded where needed.  The
+    /// parameters are transport, log-level, a GlusterLogLevel name logged
+    /// to gfapi's default log file, and read-only.  Nothing is connected,
+    /// so the builder can be changed further first.
+    pub fn from_url(url: &str) -> Result<GlusterBuilder, GlusterError> {
+        Ok(parse_url(url)?)
+    }
+}
+
+impl GlusterBuilder {
+    /// The builder's servers, volume, subdirectory, transport, log level
+    /// and read_only as a URL from_url reads back, for logging.  TLS,
+    /// tuning, a log file and a local volfile can't be expressed and are
+    /// left out.
+    pub fn to_url(&self) -> String {
+        let mut url = String::from("gluster://");
+        let primary = VolfileServer {
+            host: self.server.clone(),
+            port: self.port,
+        };
+        let servers: Vec<String> = Some(&primary)
+            .into_iter()
+            .chain(self.backup_servers.iter())
+            .map(|server| server.to_string())
+            .collect();
+        url.push_str(&servers.join(","));
+        url.push('/');
+        url.push_str(&percent_encode(self.volume.as_bytes()));
+        if let Some(ref subdir) = self.subdir {
+            for component in subdir.components() {
+                if let Component::Normal(part) = component {
+                    url.push('/');
+                    url.push_str(&percent_encode(part.as_bytes()));
+                }
+            }
+        }
+        let mut query = Vec::new();
+        if self.transport != "tcp" {
+            query.push(format!(
+                "transport={}",
+                percent_encode(self.transport.as_bytes())
+            ));
+        }
+        if let Some((_, level)) = self.logging {
+            query.push(format!("log-level={}", level));
+        }
+        if self.read_only {
+            query.push("read-only=true".to_string());
+        }
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        url
+    }
+}
@@ -0,0 +1,152 @@
+use gluster::GlusterError;
+
+use std::io::{self, ErrorKind, IoSlice, IoSliceMut};
+use std::mem;
+
+// Total length of a set of buffers, refusing anything a single vectored
+// call couldn't report back in its ssize_t result
+fn total_len<I: Iterator<Item = usize>>(lens: I, offset: u64) -> Result<usize, GlusterError> {
+    let mut total: usize = 0;
+    for len in lens {
+        total = total.checked_add(len).ok_or_else(too_long)?;
+    }
+    if total > isize::MAX as usize {
+        return Err(too_long());
+    }
+    if offset
+        .checked_add(total as u64)
+        .is_none_or(|end| end > i64::MAX as u64)
+    {
+        return Err(GlusterError::IoError(io::Error::new(
+            ErrorKind::InvalidInput,
+            "vectored transfer runs past the largest file offset",
+        )));
+    }
+    Ok(total)
+}
+
+fn too_long() -> GlusterError {
+    GlusterError::IoError(io::Error::new(
+        ErrorKind::InvalidInput,
+        "buffers are too long for one vectored transfer",
+    ))
+}
+
+// Drop the first n bytes from the front of bufs, the same as the
+// unstable IoSlice::advance_slices
+fn advance_mut(bufs: &mut Vec<&mut [u8]>, mut n: usize) {
+    let mut consumed = 0;
+    for buf in bufs.iter() {
+        if n < buf.len() {
+            break;
+        }
+        n -= buf.len();
+        consumed += 1;
+    }
+    bufs.drain(..consumed);
+    if let Some(first) = bufs.first_mut() {
+        let rest = mem::take(first);
+        *first = &mut rest[n..];
+    }
+}
+
+// advance_mut for shared buffers
+fn advance(bufs: &mut Vec<&[u8]>, mut n: usize) {
+    let mut consumed = 0;
+    for buf in bufs.iter() {
+        if n < buf.len() {
+            break;
+        }
+        n -= buf.len();
+        consumed += 1;
+    }
+    bufs.drain(..consumed);
+    if let Some(first) = bufs.first_mut() {
+        *first = &first[n..];
+    }
+}
+
+/// Write every byte of bufs starting at offset, calling write as many
+/// times as it takes.  write is given the buffers still to go and the
+/// offset they belong at, and returns how much it wrote, which may be
+/// less than asked.  Calls failing with ErrorKind::Interrupted are
+/// retried.  Returns the total written.
+pub fn write_all_vectored_at<F>(
+    bufs: &[IoSlice],
+    offset: u64,
+    mut write: F,
+) -> Result<usize, GlusterError>
+where
+    F: FnMut(&[IoSlice], u64) -> io::Result<usize>,
+{
+    let total = total_len(bufs.iter().map(|b| b.len()), offset)?;
+    let mut remaining: Vec<&[u8]> = bufs
+        .iter()
+        .map(|b| &**b)
+        .filter(|b| !b.is_empty())
+        .collect();
+    let mut done = 0;
+    while done < total {
+        let slices: Vec<IoSlice> = remaining.iter().map(|b| IoSlice::new(b)).collect();
+        match write(&slices, offset + done as u64) {
+            Ok(0) => {
+                return Err(GlusterError::IoError(io::Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write the whole buffer",
+                )))
+            }
+            Ok(n) => {
+                done += n;
+                advance(&mut remaining, n);
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(GlusterError::IoError(e)),
+        }
+    }
+    Ok(total)
+}
+
+/// Fill every byte of bufs from offset onwards, calling read as many
+/// times as it takes.  read is given the buffers still to fill and the
+/// offset they start at, and returns how much it read.  Reaching the end
+/// of the file first is an UnexpectedEof error, after which the contents
+/// of bufs are unspecified.  Calls failing with ErrorKind::Interrupted are
+/// retried.  Returns the total read.
+pub fn read_exact_vectored_at<F>(
+    bufs: &mut [IoSliceMut],
+    offset: u64,
+    mut read: F,
+) -> Result<usize, GlusterError>
+where
+    F: FnMut(&mut [IoSliceMut], u64) -> io::Result<usize>,
+{
+    let total = total_len(bufs.iter().map(|b| b.len()), offset)?;
+    let mut remaining: Vec<&mut [u8]> = bufs
+        .iter_mut()
+        .map(|b| &mut **b)
+        .filter(|b| !b.is_empty())
+        .collect();
+    let mut done = 0;
+    while done < total {
+        let result = {
+            let mut slices: Vec<IoSliceMut> =
+                remaining.iter_mut().map(|b| IoSliceMut::new(b)).collect();
+            read(&mut slices, offset + done as u64)
+        };
+        match result {
+            Ok(0) => {
+                return Err(GlusterError::IoError(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "failed to fill the whole buffer",
+                )))
+            }
+            Ok(n) => {
+                done += n;
+                advance_mut(&mut remaining, n);
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(GlusterError::IoError(e)),
+        }
+    }
+    Ok(total)
+}
@@ -0,0 +1,327 @@
+use libc::O_RDONLY;
+
+use builder::GlusterBuilder;
+use copy::{self, CopyOptions, CopyOutcome};
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+use preserve::{self, CopyReport};
+use write::WriteOptions;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A path on a named volume, written volume:/path
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VolumePath {
+    pub volume: String,
+    pub path: PathBuf,
+}
+
+impl VolumePath {
+    pub fn new<P: AsRef<Path>>(volume: &str, path: P) -> VolumePath {
+        VolumePath {
+            volume: volume.to_string(),
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl FromStr for VolumePath {
+    type Err = GlusterError;
+
+    /// Parse volume:/path.  The path is everything after the first colon.
+    fn from_str(s: &str) -> Result<VolumePath, GlusterError> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(volume), Some(path)) if !volume.is_empty() && !path.is_empty() => {
+                Ok(VolumePath::new(volume, path))
+            }
+            _ => Err(GlusterError::new(format!(
+                "{:?} isn't of the form volume:/path",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for VolumePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.volume, self.path.display())
+    }
+}
+
+impl<'a> From<(&'a str, &'a Path)> for VolumePath {
+    fn from((volume, path): (&'a str, &'a Path)) -> VolumePath {
+        VolumePath::new(volume, path)
+    }
+}
+
+/// Connection state of one volume in a VolumeSet
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeState {
+    /// Registered but not used yet
+    NotConnected,
+    Connected,
+    /// The last connection attempt failed with this error.  The next use
+    /// tries again.
+    Failed(String),
+}
+
+/// What VolumeSet::status reports for each volume
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolumeStatus {
+    pub name: String,
+    pub state: VolumeState,
+}
+
+struct Volume {
+    config: GlusterBuilder,
+    connection: Option<Arc<Gluster>>,
+    last_error: Option<String>,
+}
+
+/// Connections to several volumes, addressed by name.  Each volume
+/// connects the first time it's used, and a volume that fails to connect
+/// doesn't affect the others.
+pub struct VolumeSet {
+    volumes: Mutex<BTreeMap<String, Volume>>,
+}
+
+impl Default for VolumeSet {
+    fn default() -> VolumeSet {
+        VolumeSet::new()
+    }
+}
+
+impl VolumeSet {
+    pub fn new() -> VolumeSet {
+        VolumeSet {
+            volumes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Volume>> {
+        match self.volumes.lock() {
+            Ok(volumes) => volumes,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Add a volume under name, replacing any earlier one with that name.
+    /// Nothing is connected until the volume is used.
+    pub fn register(&self, name: &str, config: GlusterBuilder) {
+        self.lock().insert(
+            name.to_string(),
+            Volume {
+                config,
+                connection: None,
+                last_error: None,
+            },
+        );
+    }
+
+    /// Drop a volume and its connection once nothing else is using it.
+    /// Returns whether it was registered.
+    pub fn unregister(&self, name: &str) -> bool {
+        self.lock().remove(name).is_some()
+    }
+
+    /// The connection for volume name, connecting if it isn't yet
+    pub fn volume(&self, name: &str) -> Result<Arc<Gluster>, GlusterError> {
+        let config = {
+            let volumes = self.lock();
+            match volumes.get(name) {
+                Some(&Volume {
+                    connection: Some(ref connection),
+                    ..
+                }) => return Ok(connection.clone()),
+                Some(volume) => volume.config.clone(),
+                None => {
+                    return Err(GlusterError::new(format!(
+                        "no volume named {} is registered",
+                        name
+                    )))
+                }
+            }
+        };
+        // Connect without holding the lock so a slow or hanging volume
+        // doesn't hold up the others
+        let result = config.connect().map(Gluster::shared);
+        let mut volumes = self.lock();
+        let volume = match volumes.get_mut(name) {
+            Some(volume) => volume,
+            // Unregistered while connecting
+            None => return result,
+        };
+        match result {
+            Ok(connection) => {
+                // Another thread may have won the race, keep its connection
+                let connection = volume.connection.get_or_insert(connection).clone();
+                volume.last_error = None;
+                Ok(connection)
+            }
+            Err(e) => {
+                volume.last_error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Connection state of every registered volume, sorted by name
+    pub fn status(&self) -> Vec<VolumeStatus> {
+        self.lock()
+            .iter()
+            .map(|(name, volume)| VolumeStatus {
+                name: name.clone(),
+                state: match (volume.connection.as_ref(), volume.last_error.as_ref()) {
+                    (Some(_), _) => VolumeState::Connected,
+                    (None, Some(error)) => VolumeState::Failed(error.clone()),
+                    (None, None) => VolumeState::NotConnected,
+                },
+            })
+            .collect()
+    }
+
+    /// Run f with the connection and path of path
+    pub fn with<T, F>(&self, path: &VolumePath, f: F) -> Result<T, GlusterError>
+    where
+        F: FnOnce(&Gluster, &Path) -> Result<T, GlusterError>,
+    {
+        let gluster = self.volume(&path.volume)?;
+        f(&gluster, &path.path)
+    }
+
+    pub fn read_to_vec(&self, path: &VolumePath) -> Result<Vec<u8>, GlusterError> {
+        self.with(path, |gluster, path| gluster.read_to_vec(path))
+    }
+
+    pub fn write_file(&self, path: &VolumePath, data: &[u8]) -> Result<(), GlusterError> {
+        self.with(path, |gluster, path| gluster.write_file(path, data))
+    }
+
+    pub fn metadata(&self, path: &VolumePath) -> Result<Metadata, GlusterError> {
+        self.with(path, |gluster, path| gluster.metadata(path))
+    }
+
+    pub fn exists(&self, path: &VolumePath) -> Result<bool, GlusterError> {
+        self.with(path, |gluster, path| gluster.exists(path))
+    }
+
+    pub fn unlink(&self, path: &VolumePath) -> Result<(), GlusterError> {
+        self.with(path, |gluster, path| gluster.unlink(path))
+    }
+
+    pub fn mkdir(&self, path: &VolumePath, mode: ::libc::mode_t) -> Result<(), GlusterError> {
+        self.with(path, |gluster, path| gluster.mkdir(path, mode))
+    }
+
+    /// Copy a file, between volumes or within one.  Returns the number of
+    /// bytes copied.
+    pub fn copy(
+        &self,
+        from: &VolumePath,
+        to: &VolumePath,
+        opts: &WriteOptions,
+    ) -> Result<u64, GlusterError> {
+        let outcome = self.copy_with(from, to, &CopyOptions::from(opts.clone()))?;
+        log_warnings(from, to, &outcome);
+        match outcome {
+            CopyOutcome::Copied(report) => Ok(report.bytes),
+            // Overwrite::Always never skips and copy_with never renames
+            _ => Ok(0),
+        }
+    }
+
+    /// Copy a file according to opts, between volumes or within one
+    pub fn copy_with(
+        &self,
+        from: &VolumePath,
+        to: &VolumePath,
+        opts: &CopyOptions,
+    ) -> Result<CopyOutcome, GlusterError> {
+        let source = self.volume(&from.volume)?;
+        if from.volume == to.volume {
+            return source.copy_with(&from.path, &to.path, opts);
+        }
+        let dest = self.volume(&to.volume)?;
+        let source_metadata = source.metadata(&from.path)?;
+        let destination = copy::existing(&dest, &to.path, true)?;
+        if !copy::resolve(
+            opts,
+            &from.path,
+            &source_metadata,
+            &to.path,
+            destination.as_ref(),
+        )? {
+            return Ok(CopyOutcome::Skipped);
+        }
+        copy::create_parents(&dest, &to.path, opts)?;
+        let mut file = source.open_file(&from.path, O_RDONLY)?;
+        let source_stat = file.fstat()?;
+        let bytes = dest.write_from_reader(&to.path, &mut file, &opts.write)?;
+        let warnings = preserve::apply(
+            &source,
+            &from.path,
+            &source_stat,
+            &dest,
+            &to.path,
+            &opts.write.preserve,
+        )?;
+        Ok(CopyOutcome::Copied(CopyReport { bytes, warnings }))
+    }
+
+    /// Move a file.  Within a volume this is a rename.  Between volumes
+    /// the file is copied and then the original removed, so a failure
+    /// part way leaves the original in place.
+    pub fn move_item(
+        &self,
+        from: &VolumePath,
+        to: &VolumePath,
+        opts: &WriteOptions,
+    ) -> Result<(), GlusterError> {
+        let outcome = self.move_item_with(from, to, &CopyOptions::from(opts.clone()))?;
+        log_warnings(from, to, &outcome);
+        Ok(())
+    }
+
+    /// Like move_item, with an existing destination dealt with according
+    /// to opts first.  A skipped move leaves the source where it is.
+    pub fn move_item_with(
+        &self,
+        from: &VolumePath,
+        to: &VolumePath,
+        opts: &CopyOptions,
+    ) -> Result<CopyOutcome, GlusterError> {
+        if from.volume == to.volume {
+            let gluster = self.volume(&from.volume)?;
+            let source = gluster.symlink_metadata(&from.path)?;
+            let destination = copy::existing(&gluster, &to.path, false)?;
+            if !copy::resolve(opts, &from.path, &source, &to.path, destination.as_ref())? {
+                return Ok(CopyOutcome::Skipped);
+            }
+            copy::create_parents(&gluster, &to.path, opts)?;
+            gluster.rename(&from.path, &to.path)?;
+            return Ok(CopyOutcome::Renamed);
+        }
+        let outcome = self.copy_with(from, to, opts)?;
+        if let CopyOutcome::Copied(_) = outcome {
+            self.unlink(from)?;
+        }
+        Ok(outcome)
+    }
+}
+
+// Log what preserve had to skip, as Gluster::copy does
+fn log_warnings(from: &VolumePath, to: &VolumePath, outcome: &CopyOutcome) {
+    if let CopyOutcome::Copied(ref report) = *outcome {
+        for warning in &report.warnings {
+            warn!(
+                "copying {} to {} didn't preserve {:?}: {}",
+                from, to, warning.item, warning.error
+            );
+        }
+    }
+}
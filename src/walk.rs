@@ -0,0 +1,438 @@
+
+use gluster::{Gluster, GlusterError};
+use metadata::Metadata;
+
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// What a walk does when a directory can't be listed or an entry can't
+/// be stat'd
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WalkErrorPolicy {
+    /// Return the error and end the walk
+    Stop,
+    /// Return the error and carry on with the rest of the tree
+    Report,
+    /// Leave out whatever failed without saying so
+    Ignore,
+}
+
+/// Options for Gluster::walk and Gluster::par_walk
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+    min_depth: usize,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    on_error: WalkErrorPolicy,
+    threads: usize,
+    prefetch: usize,
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            min_depth: 0,
+            max_depth: None,
+            follow_links: false,
+            on_error: WalkErrorPolicy::Report,
+            threads: 4,
+            prefetch: 1,
+        }
+    }
+}
+
+impl WalkOptions {
+    pub fn new() -> WalkOptions {
+        WalkOptions::default()
+    }
+
+    /// Leave out entries shallower than depth.  The root is depth 0 and
+    /// its children depth 1.  Defaults to 0.
+    pub fn min_depth(mut self, depth: usize) -> WalkOptions {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Don't go deeper than depth.  Defaults to no limit.
+    pub fn max_depth(mut self, depth: usize) -> WalkOptions {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Descend into symlinks to directories and report the metadata of
+    /// what links point to.  Each directory is only visited once, which
+    /// stops symlink loops.  Defaults to false.
+    pub fn follow_links(mut self, follow: bool) -> WalkOptions {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Defaults to WalkErrorPolicy::Report
+    pub fn on_error(mut self, policy: WalkErrorPolicy) -> WalkOptions {
+        self.on_error = policy;
+        self
+    }
+
+    /// Number of worker threads par_walk uses, which is also how many
+    /// directories it has open at once.  Defaults to 4.
+    pub fn threads(mut self, threads: usize) -> WalkOptions {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stat up to concurrency entries of a directory at once, from
+    /// threads sharing the connection, instead of one after another.
+    /// Only entries the listing didn't bring metadata for need a stat,
+    /// which is all of them on servers without readdirplus, plus the
+    /// targets of symlinks when following links.  A directory's entries
+    /// are all resolved before the first of them is yielded, so entries
+    /// come out in the same order either way.  Defaults to 1.
+    pub fn prefetch_metadata(mut self, concurrency: usize) -> WalkOptions {
+        self.prefetch = concurrency.max(1);
+        self
+    }
+}
+
+/// A file or directory found by a walk
+#[derive(Clone, Debug)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    /// How many levels below the root, which is 0
+    pub depth: usize,
+    /// From lstat, or stat when following links
+    pub metadata: Metadata,
+}
+
+// What listing one directory turned up
+struct Expansion {
+    results: Vec<Result<WalkEntry, GlusterError>>,
+    subdirs: Vec<(PathBuf, usize)>,
+}
+
+// Whether entry should be descended into, marking it visited when
+// following links
+fn should_descend(
+    entry: &WalkEntry,
+    opts: &WalkOptions,
+    visited: &Mutex<HashSet<(u64, u64)>>,
+) -> bool {
+    if !entry.metadata.is_dir() {
+        return false;
+    }
+    if let Some(max) = opts.max_depth {
+        if entry.depth >= max {
+            return false;
+        }
+    }
+    if !opts.follow_links {
+        return true;
+    }
+    let mut visited = match visited.lock() {
+        Ok(visited) => visited,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    visited.insert(entry.metadata.file_id())
+}
+
+fn start(
+    gluster: &Gluster,
+    root: &Path,
+    opts: &WalkOptions,
+    visited: &Mutex<HashSet<(u64, u64)>>,
+) -> Expansion {
+    let metadata = if opts.follow_links {
+        gluster.metadata(root)
+    } else {
+        gluster.symlink_metadata(root)
+    };
+    let mut expansion = Expansion {
+        results: Vec::new(),
+        subdirs: Vec::new(),
+    };
+    match metadata {
+        Ok(metadata) => {
+            let entry = WalkEntry {
+                path: root.to_path_buf(),
+                depth: 0,
+                metadata,
+            };
+            if should_descend(&entry, opts, visited) {
+                expansion.subdirs.push((entry.path.clone(), 0));
+            }
+            if opts.min_depth == 0 {
+                expansion.results.push(Ok(entry));
+            }
+        }
+        Err(e) => expansion.results.push(Err(e)),
+    }
+    expansion
+}
+
+fn expand(
+    gluster: &Gluster,
+    dir: &Path,
+    depth: usize,
+    opts: &WalkOptions,
+    visited: &Mutex<HashSet<(u64, u64)>>,
+) -> Expansion {
+    let mut expansion = Expansion {
+        results: Vec::new(),
+        subdirs: Vec::new(),
+    };
+    let listing = match gluster.list_dir(dir, opts.prefetch) {
+        Ok(listing) => listing,
+        Err(e) => {
+            expansion.results.push(Err(e));
+            return expansion;
+        }
+    };
+    let links: Vec<PathBuf> = if opts.follow_links {
+        listing
+            .iter()
+            .filter(|(_, listed)| listed.is_symlink())
+            .map(|(dir_entry, _)| dir.join(&dir_entry.path))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut targets = gluster.stat_many(&links, opts.prefetch).into_iter();
+    for (dir_entry, listed) in listing {
+        let path = dir.join(&dir_entry.path);
+        let metadata = if opts.follow_links && listed.is_symlink() {
+            match targets.next() {
+                Some(Ok(metadata)) => metadata,
+                Some(Err(e)) => {
+                    expansion.results.push(Err(e));
+                    continue;
+                }
+                None => unreachable!(),
+            }
+        } else {
+            listed
+        };
+        let entry = WalkEntry {
+            path,
+            depth: depth + 1,
+            metadata,
+        };
+        if should_descend(&entry, opts, visited) {
+            expansion.subdirs.push((entry.path.clone(), entry.depth));
+        }
+        if entry.depth >= opts.min_depth {
+            expansion.results.push(Ok(entry));
+        }
+    }
+    expansion
+}
+
+/// Iterator over a directory tree, depth first, from Gluster::walk
+pub struct Walk<'a> {
+    gluster: &'a Gluster,
+    opts: WalkOptions,
+    pending: Vec<(PathBuf, usize)>,
+    ready: VecDeque<Result<WalkEntry, GlusterError>>,
+    visited: Mutex<HashSet<(u64, u64)>>,
+    stopped: bool,
+}
+
+impl<'a> Walk<'a> {
+    fn absorb(&mut self, expansion: Expansion) {
+        // Reversed so the stack hands directories back in listing order
+        self.pending.extend(expansion.subdirs.into_iter().rev());
+        self.ready.extend(expansion.results);
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Result<WalkEntry, GlusterError>;
+
+    fn next(&mut self) -> Option<Result<WalkEntry, GlusterError>> {
+        loop {
+            if self.stopped {
+                return None;
+            }
+            match self.ready.pop_front() {
+                Some(Ok(entry)) => return Some(Ok(entry)),
+                Some(Err(e)) => match self.opts.on_error {
+                    WalkErrorPolicy::Stop => {
+                        self.stopped = true;
+                        return Some(Err(e));
+                    }
+                    WalkErrorPolicy::Report => return Some(Err(e)),
+                    WalkErrorPolicy::Ignore => continue,
+                },
+                None => {}
+            }
+            let (dir, depth) = self.pending.pop()?;
+            let expansion = expand(self.gluster, &dir, depth, &self.opts, &self.visited);
+            self.absorb(expansion);
+        }
+    }
+}
+
+// Directories waiting to be listed by par_walk's workers, and how many
+// workers are busy listing one and may add more
+struct Frontier {
+    dirs: VecDeque<(PathBuf, usize)>,
+    busy: usize,
+}
+
+/// Results of Gluster::par_walk as they're produced by its workers, in no
+/// particular order, handed to the closure given to par_walk.  Once the
+/// closure returns the workers are stopped and joined.
+pub struct ParWalk {
+    results: Option<Receiver<Result<WalkEntry, GlusterError>>>,
+    stop: Arc<AtomicBool>,
+    frontier: Arc<(Mutex<Frontier>, Condvar)>,
+}
+
+impl Iterator for ParWalk {
+    type Item = Result<WalkEntry, GlusterError>;
+
+    fn next(&mut self) -> Option<Result<WalkEntry, GlusterError>> {
+        self.results.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for ParWalk {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Unblock workers waiting to send or waiting for work
+        self.results = None;
+        self.frontier.1.notify_all();
+    }
+}
+
+fn par_worker(
+    gluster: &Gluster,
+    opts: &WalkOptions,
+    frontier: &(Mutex<Frontier>, Condvar),
+    visited: &Mutex<HashSet<(u64, u64)>>,
+    stop: &AtomicBool,
+    send: &dyn Fn(Result<WalkEntry, GlusterError>) -> bool,
+) {
+    let (ref lock, ref wakeup) = *frontier;
+    loop {
+        let (dir, depth) = {
+            let mut state = match lock.lock() {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            loop {
+                if stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Some(next) = state.dirs.pop_front() {
+                    state.busy += 1;
+                    break next;
+                }
+                if state.busy == 0 {
+                    // Nothing queued and nobody left to queue more
+                    wakeup.notify_all();
+                    return;
+                }
+                state = match wakeup.wait(state) {
+                    Ok(state) => state,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+            }
+        };
+        let expansion = expand(gluster, &dir, depth, opts, visited);
+        for result in expansion.results {
+            let failed = result.is_err();
+            if failed && opts.on_error == WalkErrorPolicy::Ignore {
+                continue;
+            }
+            if !send(result) {
+                stop.store(true, Ordering::SeqCst);
+            }
+            if failed && opts.on_error == WalkErrorPolicy::Stop {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+        let mut state = match lock.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        state.dirs.extend(expansion.subdirs);
+        state.busy -= 1;
+        wakeup.notify_all();
+    }
+}
+
+impl Gluster {
+    /// Walk the tree under root depth first, starting with root itself.
+    /// Directories are listed one at a time as the iterator reaches them.
+    pub fn walk(&self, root: &Path, opts: &WalkOptions) -> Walk<'_> {
+        let mut walk = Walk {
+            gluster: self,
+            opts: opts.clone(),
+            pending: Vec::new(),
+            ready: VecDeque::new(),
+            visited: Mutex::new(HashSet::new()),
+            stopped: false,
+        };
+        let expansion = start(self, root, opts, &walk.visited);
+        walk.absorb(expansion);
+        walk
+    }
+
+    /// Walk the tree under root with several threads, each listing a
+    /// directory at a time from a shared queue and adding the
+    /// subdirectories it finds back onto it.  Takes the same options as
+    /// walk, but results arrive in no particular order.  At most
+    /// WalkOptions::threads directories are open at once.  The results
+    /// are passed to f, and the workers are stopped and joined before
+    /// this returns f's result.
+    pub fn par_walk<R, F>(&self, root: &Path, opts: &WalkOptions, f: F) -> R
+    where
+        F: FnOnce(&mut ParWalk) -> R,
+    {
+        let (tx, rx) = sync_channel(1024);
+        let stop = Arc::new(AtomicBool::new(false));
+        let visited = Arc::new(Mutex::new(HashSet::new()));
+        let first = start(self, root, opts, &visited);
+        for result in first.results {
+            if result.is_err() && opts.on_error == WalkErrorPolicy::Ignore {
+                continue;
+            }
+            if result.is_err() && opts.on_error == WalkErrorPolicy::Stop {
+                stop.store(true, Ordering::SeqCst);
+            }
+            let _ = tx.send(result);
+        }
+        let frontier = Arc::new((
+            Mutex::new(Frontier {
+                dirs: first.subdirs.into_iter().collect(),
+                busy: 0,
+            }),
+            Condvar::new(),
+        ));
+        // The scope joins the workers before the connection borrow ends,
+        // even if f panics
+        thread::scope(|scope| {
+            for _ in 0..opts.threads {
+                let frontier = frontier.clone();
+                let visited = visited.clone();
+                let stop = stop.clone();
+                let tx = tx.clone();
+                scope.spawn(move || {
+                    par_worker(self, opts, &frontier, &visited, &stop, &|result| {
+                        tx.send(result).is_ok()
+                    });
+                });
+            }
+            drop(tx);
+            let mut walk = ParWalk {
+                results: Some(rx),
+                stop,
+                frontier,
+            };
+            f(&mut walk)
+        })
+    }
+}
@@ -0,0 +1,400 @@
+use gluster::Gluster;
+use remote_fs::{RemoteFs, RemoteMetadata, RemoteReadDir, RemoteXattrs};
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+type StuckHandler = Arc<dyn Fn(&InFlightOp) + Send + Sync>;
+
+/// Settings for the watchdog a connection runs, see
+/// GlusterBuilder::watchdog
+#[derive(Clone)]
+pub struct WatchdogOptions {
+    threshold: Duration,
+    interval: Duration,
+    on_stuck: Option<StuckHandler>,
+}
+
+impl Default for WatchdogOptions {
+    fn default() -> WatchdogOptions {
+        WatchdogOptions {
+            threshold: Duration::from_secs(30),
+            interval: Duration::from_secs(1),
+            on_stuck: None,
+        }
+    }
+}
+
+impl fmt::Debug for WatchdogOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WatchdogOptions")
+            .field("threshold", &self.threshold)
+            .field("interval", &self.interval)
+            .field("on_stuck", &self.on_stuck.is_some())
+            .finish()
+    }
+}
+
+// Handlers are equal only if they're the same one
+impl PartialEq for WatchdogOptions {
+    fn eq(&self, other: &WatchdogOptions) -> bool {
+        let same_handler = match (&self.on_stuck, &other.on_stuck) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.threshold == other.threshold && self.interval == other.interval && same_handler
+    }
+}
+
+impl WatchdogOptions {
+    pub fn new() -> WatchdogOptions {
+        WatchdogOptions::default()
+    }
+
+    /// How long an operation runs before it's reported as stuck.
+    /// Defaults to 30 seconds.
+    pub fn threshold(mut self, threshold: Duration) -> WatchdogOptions {
+        self.threshold = threshold;
+        self
+    }
+
+    /// How often the operations in flight are looked over.  Defaults to
+    /// once a second.
+    pub fn interval(mut self, interval: Duration) -> WatchdogOptions {
+        self.interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Call handler, from the watchdog's thread, once for each operation
+    /// that passes the threshold.  Without one stuck operations are
+    /// logged with warn!.
+    pub fn on_stuck<F>(mut self, handler: F) -> WatchdogOptions
+    where
+        F: Fn(&InFlightOp) + Send + Sync + 'static,
+    {
+        self.on_stuck = Some(Arc::new(handler));
+        self
+    }
+}
+
+/// An operation that has called into gfapi and not yet returned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InFlightOp {
+    /// Name of the operation, such as "stat" or "rename"
+    pub op: &'static str,
+    /// The path it was called with, the first of two for rename and
+    /// link
+    pub path: PathBuf,
+    pub started: Instant,
+    /// How long it had been running when this was taken
+    pub elapsed: Duration,
+    pub thread: ThreadId,
+    pub thread_name: Option<String>,
+}
+
+struct Tracked {
+    op: &'static str,
+    path: PathBuf,
+    started: Instant,
+    thread: ThreadId,
+    thread_name: Option<String>,
+    reported: bool,
+}
+
+impl Tracked {
+    fn snapshot(&self, now: Instant) -> InFlightOp {
+        InFlightOp {
+            op: self.op,
+            path: self.path.clone(),
+            started: self.started,
+            elapsed: now.saturating_duration_since(self.started),
+            thread: self.thread,
+            thread_name: self.thread_name.clone(),
+        }
+    }
+}
+
+struct Registry {
+    next_id: AtomicU64,
+    ops: Mutex<BTreeMap<u64, Tracked>>,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Registry {
+    fn ops(&self) -> MutexGuard<'_, BTreeMap<u64, Tracked>> {
+        match self.ops.lock() {
+            Ok(ops) => ops,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    // Mark and return the operations past threshold not reported yet
+    fn newly_stuck(&self, threshold: Duration) -> Vec<InFlightOp> {
+        let now = Instant::now();
+        let mut stuck = Vec::new();
+        for tracked in self.ops().values_mut() {
+            if !tracked.reported && now.saturating_duration_since(tracked.started) >= threshold {
+                tracked.reported = true;
+                stuck.push(tracked.snapshot(now));
+            }
+        }
+        stuck
+    }
+}
+
+fn report(on_stuck: &Option<StuckHandler>, op: &InFlightOp) {
+    match *on_stuck {
+        Some(ref handler) => handler(op),
+        None => warn!(
+            "{} of {} has been running for {:?} on thread {:?}{}",
+            op.op,
+            op.path.display(),
+            op.elapsed,
+            op.thread,
+            op.thread_name
+                .as_ref()
+                .map(|name| format!(" ({})", name))
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// Keeps a registry of operations in flight and a thread that reports
+/// any running longer than WatchdogOptions::threshold, so a wedged brick
+/// shows up before everything waiting on it stalls.  The thread is
+/// stopped when the watchdog is dropped.
+pub struct Watchdog {
+    registry: Arc<Registry>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("in_flight", &self.registry.ops().len())
+            .finish()
+    }
+}
+
+impl Watchdog {
+    /// Start the watchdog's thread
+    pub fn start(opts: &WatchdogOptions) -> Watchdog {
+        let registry = Arc::new(Registry {
+            next_id: AtomicU64::new(0),
+            ops: Mutex::new(BTreeMap::new()),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+        let watched = registry.clone();
+        let opts = opts.clone();
+        let thread = thread::Builder::new()
+            .name("gfapi-watchdog".to_string())
+            .spawn(move || {
+                let mut stopped = match watched.stopped.lock() {
+                    Ok(stopped) => stopped,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                while !*stopped {
+                    stopped = match watched.wake.wait_timeout(stopped, opts.interval) {
+                        Ok((stopped, _)) => stopped,
+                        Err(poisoned) => poisoned.into_inner().0,
+                    };
+                    for op in watched.newly_stuck(opts.threshold) {
+                        report(&opts.on_stuck, &op);
+                    }
+                }
+            })
+            .ok();
+        if thread.is_none() {
+            warn!("couldn't start the watchdog thread, stuck operations won't be reported");
+        }
+        Watchdog { registry, thread }
+    }
+
+    /// Register op on path as in flight until the guard is dropped
+    pub fn track(&self, op: &'static str, path: &Path) -> OpGuard<'_> {
+        let id = self.registry.next_id.fetch_add(1, Ordering::SeqCst);
+        let current = thread::current();
+        self.registry.ops().insert(
+            id,
+            Tracked {
+                op,
+                path: path.to_path_buf(),
+                started: Instant::now(),
+                thread: current.id(),
+                thread_name: current.name().map(str::to_string),
+                reported: false,
+            },
+        );
+        OpGuard {
+            tracked: Some((&self.registry, id)),
+        }
+    }
+
+    /// Everything in flight, longest running first
+    pub fn in_flight(&self) -> Vec<InFlightOp> {
+        let now = Instant::now();
+        let mut ops: Vec<InFlightOp> = self
+            .registry
+            .ops()
+            .values()
+            .map(|tracked| tracked.snapshot(now))
+            .collect();
+        ops.sort_by_key(|op| Reverse(op.elapsed));
+        ops
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        match self.registry.stopped.lock() {
+            Ok(mut stopped) => *stopped = true,
+            Err(poisoned) => *poisoned.into_inner() = true,
+        }
+        self.registry.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Removes an operation from its watchdog's registry when dropped.  A
+/// guard from a connection without a watchdog does nothing.
+pub struct OpGuard<'a> {
+    tracked: Option<(&'a Arc<Registry>, u64)>,
+}
+
+impl<'a> OpGuard<'a> {
+    /// A guard that tracks nothing
+    pub fn none() -> OpGuard<'a> {
+        OpGuard { tracked: None }
+    }
+}
+
+impl<'a> Drop for OpGuard<'a> {
+    fn drop(&mut self) {
+        if let Some((registry, id)) = self.tracked.take() {
+            registry.ops().remove(&id);
+        }
+    }
+}
+
+impl Gluster {
+    /// The operations on this connection that are inside gfapi right
+    /// now, longest running first.  Always empty without a watchdog, see
+    /// GlusterBuilder::watchdog.
+    pub fn in_flight(&self) -> Vec<InFlightOp> {
+        match self.watchdog {
+            Some(ref watchdog) => watchdog.in_flight(),
+            None => Vec::new(),
+        }
+    }
+
+    // Register op on path with the watchdog, if there is one, until the
+    // guard is dropped
+    pub(crate) fn track(&self, op: &'static str, path: &Path) -> OpGuard<'_> {
+        match self.watchdog {
+            Some(ref watchdog) => watchdog.track(op, path),
+            None => OpGuard::none(),
+        }
+    }
+}
+
+/// A RemoteFs whose every call is tracked by a watchdog, for backends
+/// that don't have one of their own
+pub struct WatchedFs<F> {
+    inner: F,
+    watchdog: Watchdog,
+}
+
+impl<F: RemoteFs> WatchedFs<F> {
+    pub fn new(inner: F, opts: &WatchdogOptions) -> WatchedFs<F> {
+        WatchedFs {
+            inner,
+            watchdog: Watchdog::start(opts),
+        }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// See Watchdog::in_flight
+    pub fn in_flight(&self) -> Vec<InFlightOp> {
+        self.watchdog.in_flight()
+    }
+}
+
+impl<F: RemoteFs> RemoteFs for WatchedFs<F> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let _op = self.watchdog.track("read", path);
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let _op = self.watchdog.track("write", path);
+        self.inner.write(path, data)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        let _op = self.watchdog.track("metadata", path);
+        self.inner.metadata(path)
+    }
+
+    /// Only the call itself is tracked, not iterating over the result
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        let _op = self.watchdog.track("read_dir", path);
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let _op = self.watchdog.track("create_dir", path);
+        self.inner.create_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let _op = self.watchdog.track("rename", from);
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let _op = self.watchdog.track("remove_file", path);
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let _op = self.watchdog.track("remove_dir", path);
+        self.inner.remove_dir(path)
+    }
+}
+
+impl<F: RemoteXattrs> RemoteXattrs for WatchedFs<F> {
+    fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let _op = self.watchdog.track("get_xattr", path);
+        self.inner.get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let _op = self.watchdog.track("set_xattr", path);
+        self.inner.set_xattr(path, name, value)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> io::Result<Vec<String>> {
+        let _op = self.watchdog.track("list_xattrs", path);
+        self.inner.list_xattrs(path)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &str) -> io::Result<()> {
+        let _op = self.watchdog.track("remove_xattr", path);
+        self.inner.remove_xattr(path, name)
+    }
+}
@@ -0,0 +1,416 @@
+use errno::{errno, Errno};
+use libc::{mode_t, ENOENT, EXDEV, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+
+use buffer_pool::BufferPool;
+use checksum::Crc32c;
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+use mode::{defaults, ModePolicy};
+use preserve::{self, CopyReport, PreserveOptions};
+
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// How much checking write_from_reader does after sending data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerifyMode {
+    /// Trust the write
+    None,
+    /// fdatasync and read back each chunk right after writing it.  This
+    /// catches problems early but costs a sync and a read per chunk.
+    PerChunk,
+    /// fdatasync once at the end and read the whole file back.  Costs a
+    /// second full read of the file.
+    WholeFile,
+}
+
+/// How much free space a write needs before it starts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FreeSpaceRequirement {
+    /// At least this many bytes
+    Exact(u64),
+    /// As many bytes as the data being written.  For write_from_reader
+    /// the length isn't known up front so only the periodic check
+    /// applies, see WriteOptions::recheck_free_space_every.
+    SizeOfInput,
+    /// This percentage of the volume (or quota) size
+    Percent(f64),
+}
+
+/// Options for write_file_with, write_from_reader and copy
+#[derive(Clone, Debug)]
+pub struct WriteOptions {
+    verify: VerifyMode,
+    chunk_size: usize,
+    pub(crate) mode: mode_t,
+    mode_policy: ModePolicy,
+    free_space: Option<FreeSpaceRequirement>,
+    free_space_recheck: Option<u64>,
+    preserve_security: bool,
+    pub(crate) preserve: PreserveOptions,
+    sparse: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            verify: VerifyMode::None,
+            chunk_size: 1024 * 1024,
+            mode: defaults::FILE_0644,
+            mode_policy: ModePolicy::RespectUmask,
+            free_space: None,
+            free_space_recheck: None,
+            preserve_security: false,
+            preserve: PreserveOptions::default(),
+            sparse: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Read data back after writing it and compare CRC32C checksums of
+    /// what was sent and what was stored.  The read back uses a separate
+    /// file handle after an fdatasync so it doesn't just see the client
+    /// side write-behind buffers.  Defaults to VerifyMode::None.
+    pub fn verify(mut self, verify: VerifyMode) -> WriteOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Size of each write sent to the volume.  Defaults to 1MB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> WriteOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Permissions used when the destination is created.  Defaults to 0644.
+    pub fn mode(mut self, mode: mode_t) -> WriteOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// Whether the umask can take bits out of mode.  Defaults to
+    /// ModePolicy::RespectUmask.
+    pub fn mode_policy(mut self, policy: ModePolicy) -> WriteOptions {
+        self.mode_policy = policy;
+        self
+    }
+
+    /// Check the destination directory has enough free space (see
+    /// Gluster::free_space) before transferring anything, and fail with
+    /// GlusterError::InsufficientSpace if it doesn't.  Defaults to no
+    /// check.
+    pub fn require_free_space(mut self, requirement: FreeSpaceRequirement) -> WriteOptions {
+        self.free_space = Some(requirement);
+        self
+    }
+
+    /// Check free space again every this many bytes during the transfer.
+    /// Each check needs room for the rest of the input, or for another
+    /// interval's worth when the input length isn't known.  Useful for
+    /// long running writes of unknown length.  Defaults to None, only
+    /// check before starting.
+    pub fn recheck_free_space_every(mut self, bytes: u64) -> WriteOptions {
+        self.free_space_recheck = Some(bytes.max(1));
+        self
+    }
+
+    /// Have copy carry the source's SELinux label and file capabilities
+    /// over to the destination.  Defaults to false.
+    pub fn preserve_security_xattrs(mut self, preserve: bool) -> WriteOptions {
+        self.preserve_security = preserve;
+        self
+    }
+
+    /// Which of the source's times, mode, ownership and xattrs copy
+    /// carries over to the destination.  Defaults to none of them.
+    pub fn preserve(mut self, preserve: PreserveOptions) -> WriteOptions {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Seek over chunks that are all zeros instead of writing them, so
+    /// they become holes in the destination.  Only whole chunks of zeros
+    /// are skipped, see chunk_size.  Defaults to false.
+    pub fn sparse(mut self, sparse: bool) -> WriteOptions {
+        self.sparse = sparse;
+        self
+    }
+}
+
+// Fail unless the directory holding path has at least needed bytes free.
+// needed is worked out from the space info when it's None.
+fn check_free_space(
+    gluster: &Gluster,
+    path: &Path,
+    requirement: FreeSpaceRequirement,
+    needed: Option<u64>,
+    input_len: Option<u64>,
+) -> Result<(), GlusterError> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let space = gluster.free_space(dir)?;
+    let needed = match (needed, requirement) {
+        (Some(needed), _) => needed,
+        (None, FreeSpaceRequirement::Exact(n)) => n,
+        (None, FreeSpaceRequirement::SizeOfInput) => match input_len {
+            Some(len) => len,
+            None => return Ok(()),
+        },
+        (None, FreeSpaceRequirement::Percent(percent)) => {
+            (space.total as f64 * percent / 100.0).ceil() as u64
+        }
+    };
+    if space.available < needed {
+        return Err(GlusterError::InsufficientSpace {
+            needed,
+            available: space.available,
+        });
+    }
+    Ok(())
+}
+
+// Keep reading until buf is full or the reader runs dry
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, GlusterError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(GlusterError::IoError(e)),
+        }
+    }
+    Ok(filled)
+}
+
+// Most read back at a time when verifying
+const CHECKSUM_CHUNK: usize = 1024 * 1024;
+
+// Read len bytes at offset back from the volume and checksum them
+pub(crate) fn checksum_range(file: &GlusterFile, offset: u64, len: usize) -> Result<u32, GlusterError> {
+    let mut crc = Crc32c::new();
+    let mut done = 0;
+    let mut buffer = BufferPool::global().get(len.min(CHECKSUM_CHUNK));
+    while done < len {
+        let want = (len - done).min(buffer.len());
+        let read = file.read_at(&mut buffer[..want], offset + done as u64)?;
+        if read == 0 {
+            // Came up short, the stored file is truncated
+            break;
+        }
+        crc.update(&buffer[..read]);
+        done += read;
+    }
+    if done < len {
+        // Make sure a short file never compares equal
+        crc.update(&[0xff]);
+    }
+    Ok(crc.finish())
+}
+
+impl Gluster {
+    /// Create or truncate the file at path and write data into it
+    /// according to opts
+    pub fn write_file_with(
+        &self,
+        path: &Path,
+        data: &[u8],
+        opts: &WriteOptions,
+    ) -> Result<(), GlusterError> {
+        let mut reader = data;
+        write_sized(self, path, &mut reader, Some(data.len() as u64), opts)?;
+        Ok(())
+    }
+
+    /// Create or truncate the file at path and fill it with everything
+    /// read from reader.  Returns the number of bytes written.  If
+    /// verification is enabled a mismatch is reported as
+    /// GlusterError::VerificationFailed with the offset of the first bad
+    /// chunk.
+    pub fn write_from_reader<R: Read>(
+        &self,
+        path: &Path,
+        reader: &mut R,
+        opts: &WriteOptions,
+    ) -> Result<u64, GlusterError> {
+        write_sized(self, path, reader, None, opts)
+    }
+
+    /// Copy the file at from to to, both on this volume.  Returns the
+    /// number of bytes copied.  Anything WriteOptions::preserve with
+    /// best_effort had to skip is logged, use copy_with_report to get
+    /// it back instead.  Fails with GlusterError::SameFile, leaving both
+    /// alone, when to is from or a hard link to it.
+    pub fn copy(&self, from: &Path, to: &Path, opts: &WriteOptions) -> Result<u64, GlusterError> {
+        let report = self.copy_with_report(from, to, opts)?;
+        for warning in &report.warnings {
+            warn!(
+                "copying {} to {} didn't preserve {:?}: {}",
+                from.display(),
+                to.display(),
+                warning.item,
+                warning.error
+            );
+        }
+        Ok(report.bytes)
+    }
+
+    /// Like copy, but returns what was copied along with anything
+    /// WriteOptions::preserve skipped
+    pub fn copy_with_report(
+        &self,
+        from: &Path,
+        to: &Path,
+        opts: &WriteOptions,
+    ) -> Result<CopyReport, GlusterError> {
+        // Creating to truncates it, which would empty from before it's read
+        match self.same_file(from, to) {
+            Ok(true) => {
+                return Err(GlusterError::SameFile {
+                    from: from.to_path_buf(),
+                    to: to.to_path_buf(),
+                })
+            }
+            Ok(false) => {}
+            Err(_) if errno() == Errno(ENOENT) => {}
+            Err(e) => return Err(e),
+        }
+        let mut source = self.open_file(from, O_RDONLY)?;
+        let source_stat = source.fstat()?;
+        let len = source_stat.st_size as u64;
+        let bytes = write_sized(self, to, &mut source, Some(len), opts)?;
+        if opts.preserve_security {
+            self.copy_security_xattrs(from, to)?;
+        }
+        let warnings = preserve::apply(self, from, &source_stat, self, to, &opts.preserve)?;
+        Ok(CopyReport { bytes, warnings })
+    }
+
+    /// Move from to to, renaming where possible.  When rename fails with
+    /// EXDEV, because the two are on different subvolumes that can't
+    /// rename between each other, the item is copied with its times,
+    /// mode, ownership and xattrs (see PreserveOptions::all) and then
+    /// removed.  Directories are moved entry by entry that way, so a
+    /// failure part way leaves some of it in each place.
+    pub fn move_path(&self, from: &Path, to: &Path) -> Result<(), GlusterError> {
+        match self.rename(from, to) {
+            Err(ref e) if errno() == Errno(EXDEV) => {
+                trace!("rename of {} failed: {}, copying", from.display(), e);
+            }
+            result => return result,
+        }
+        let metadata = self.symlink_metadata(from)?;
+        if metadata.is_symlink() {
+            let target = self.read_link(from)?;
+            self.symlink(&target, to)?;
+            return self.unlink(from);
+        }
+        if metadata.is_dir() {
+            self.mkdir(to, metadata.permissions())?;
+            for (entry, _) in self.list_dir(from, 1)? {
+                self.move_path(&from.join(&entry.path), &to.join(&entry.path))?;
+            }
+            return self.rmdir(from);
+        }
+        let opts = WriteOptions::new().preserve(PreserveOptions::all());
+        self.copy(from, to, &opts)?;
+        self.unlink(from)
+    }
+}
+
+// write_from_reader for input of a known or unknown length
+fn write_sized<R: Read>(
+    gluster: &Gluster,
+    path: &Path,
+    reader: &mut R,
+    input_len: Option<u64>,
+    opts: &WriteOptions,
+) -> Result<u64, GlusterError> {
+    if let Some(requirement) = opts.free_space {
+        check_free_space(gluster, path, requirement, None, input_len)?;
+    }
+    let mut file = gluster.create_file_with(
+        path,
+        O_CREAT | O_WRONLY | O_TRUNC,
+        opts.mode,
+        opts.mode_policy,
+    )?;
+    let verifier = match opts.verify {
+        VerifyMode::None => None,
+        _ => Some(gluster.open_file(path, O_RDONLY)?),
+    };
+    let mut chunk_checksums: Vec<(u64, usize, u32)> = Vec::new();
+    let mut buffer = BufferPool::global().get(opts.chunk_size);
+    let mut offset: u64 = 0;
+    let mut next_space_check = opts.free_space_recheck;
+    loop {
+        let len = read_chunk(reader, &mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        if opts.sparse && buffer[..len].iter().all(|&b| b == 0) {
+            file.seek(SeekFrom::Current(len as i64))?;
+        } else {
+            file.write_all(&buffer[..len])?;
+        }
+        let sent = Crc32c::checksum(&buffer[..len]);
+        match (opts.verify, verifier.as_ref()) {
+            (VerifyMode::PerChunk, Some(verifier)) => {
+                file.fdatasync()?;
+                if checksum_range(verifier, offset, len)? != sent {
+                    return Err(GlusterError::VerificationFailed {
+                        path: path.to_path_buf(),
+                        offset,
+                    });
+                }
+            }
+            (VerifyMode::WholeFile, _) => chunk_checksums.push((offset, len, sent)),
+            _ => {}
+        }
+        offset += len as u64;
+        if let (Some(requirement), Some(check_at)) = (opts.free_space, next_space_check) {
+            if offset >= check_at {
+                let interval = opts.free_space_recheck.unwrap_or(1);
+                let needed = match input_len {
+                    Some(total) => total.saturating_sub(offset),
+                    None => interval,
+                };
+                check_free_space(gluster, path, requirement, Some(needed), input_len)?;
+                next_space_check = Some(offset + interval);
+            }
+        }
+    }
+    if opts.sparse {
+        // A trailing hole only exists once the length is set
+        gluster.ftruncate(file.handle()?, offset as i64)?;
+    }
+    if let Some(verifier) = verifier {
+        if opts.verify == VerifyMode::WholeFile {
+            file.fdatasync()?;
+            for (chunk_offset, len, sent) in chunk_checksums {
+                if checksum_range(&verifier, chunk_offset, len)? != sent {
+                    return Err(GlusterError::VerificationFailed {
+                        path: path.to_path_buf(),
+                        offset: chunk_offset,
+                    });
+                }
+            }
+        }
+        let stored = verifier.fstat()?.st_size as u64;
+        if stored != offset {
+            return Err(GlusterError::VerificationFailed {
+                path: path.to_path_buf(),
+                offset: stored.min(offset),
+            });
+        }
+    }
+    file.close()?;
+    Ok(offset)
+}
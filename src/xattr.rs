@@ -0,0 +1,201 @@
+use file::GlusterFile;
+use gluster::{Gluster, GlusterError};
+
+use std::fmt;
+use std::path::Path;
+
+/// The namespaces extended attribute names are divided into, by the
+/// prefix before the first dot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XattrNamespace {
+    /// user.*, readable and writable by anyone with access to the file
+    User,
+    /// security.*, SELinux labels and file capabilities
+    Security,
+    /// trusted.*, which needs CAP_SYS_ADMIN on the bricks.  Gluster keeps
+    /// its own bookkeeping here too.
+    Trusted,
+    /// system.*, POSIX ACLs
+    System,
+}
+
+impl XattrNamespace {
+    /// The namespace name belongs to, None for a name without one of the
+    /// known prefixes
+    pub fn of(name: &[u8]) -> Option<XattrNamespace> {
+        [
+            XattrNamespace::User,
+            XattrNamespace::Security,
+            XattrNamespace::Trusted,
+            XattrNamespace::System,
+        ]
+        .iter()
+        .cloned()
+        .find(|namespace| name.starts_with(namespace.prefix().as_bytes()))
+    }
+
+    /// The prefix, including the dot, names in this namespace start with
+    pub fn prefix(self) -> &'static str {
+        match self {
+            XattrNamespace::User => "user.",
+            XattrNamespace::Security => "security.",
+            XattrNamespace::Trusted => "trusted.",
+            XattrNamespace::System => "system.",
+        }
+    }
+}
+
+impl fmt::Display for XattrNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.prefix().trim_end_matches('.'))
+    }
+}
+
+type NamePredicate = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Which extended attributes copy_xattrs copies, and whether it carries
+/// on past ones it can't
+pub struct XattrFilter {
+    // None for every namespace, and names outside them
+    namespaces: Option<Vec<XattrNamespace>>,
+    predicate: Option<NamePredicate>,
+    best_effort: bool,
+}
+
+impl XattrFilter {
+    /// Every attribute the source lists
+    pub fn all() -> XattrFilter {
+        XattrFilter {
+            namespaces: None,
+            predicate: None,
+            best_effort: false,
+        }
+    }
+
+    /// Only attributes in these namespaces
+    pub fn namespaces(namespaces: &[XattrNamespace]) -> XattrFilter {
+        XattrFilter {
+            namespaces: Some(namespaces.to_vec()),
+            ..XattrFilter::all()
+        }
+    }
+
+    /// Also require predicate to accept the raw attribute name
+    pub fn matching<F>(mut self, predicate: F) -> XattrFilter
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    /// Copy what can be copied and report the rest with
+    /// GlusterError::XattrsPartiallyCopied, instead of stopping at the
+    /// first failure.  Typically trusted.* attributes, which need
+    /// CAP_SYS_ADMIN to write.  Defaults to false.
+    pub fn best_effort(mut self, best_effort: bool) -> XattrFilter {
+        self.best_effort = best_effort;
+        self
+    }
+
+    /// True if the attribute called name passes the filter
+    pub fn accepts(&self, name: &[u8]) -> bool {
+        if let Some(ref namespaces) = self.namespaces {
+            match XattrNamespace::of(name) {
+                Some(namespace) if namespaces.contains(&namespace) => {}
+                _ => return false,
+            }
+        }
+        match self.predicate {
+            Some(ref predicate) => predicate(name),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for XattrFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("XattrFilter")
+            .field("namespaces", &self.namespaces)
+            .field("predicate", &self.predicate.is_some())
+            .field("best_effort", &self.best_effort)
+            .finish()
+    }
+}
+
+/// An attribute a best effort copy_xattrs couldn't copy
+#[derive(Clone, Debug, PartialEq)]
+pub struct XattrFailure {
+    /// The raw attribute name
+    pub name: Vec<u8>,
+    pub error: String,
+}
+
+// Copy each of names that filter accepts with get and set
+fn copy_each<G, S>(
+    names: Vec<Vec<u8>>,
+    filter: &XattrFilter,
+    get: G,
+    set: S,
+) -> Result<usize, GlusterError>
+where
+    G: Fn(&[u8]) -> Result<Vec<u8>, GlusterError>,
+    S: Fn(&[u8], &[u8]) -> Result<(), GlusterError>,
+{
+    let mut copied = 0;
+    let mut failed = Vec::new();
+    for name in names {
+        if !filter.accepts(&name) {
+            continue;
+        }
+        match get(&name).and_then(|value| set(&name, &value)) {
+            Ok(()) => copied += 1,
+            Err(error) if filter.best_effort => failed.push(XattrFailure {
+                name,
+                error: error.to_string(),
+            }),
+            Err(error) => return Err(error),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(GlusterError::XattrsPartiallyCopied { copied, failed });
+    }
+    Ok(copied)
+}
+
+impl Gluster {
+    /// Set every extended attribute of from that filter accepts on to,
+    /// replacing values to already has.  Attributes only to has are left
+    /// alone.  Returns how many were copied.
+    pub fn copy_xattrs(
+        &self,
+        from: &Path,
+        to: &Path,
+        filter: XattrFilter,
+    ) -> Result<usize, GlusterError> {
+        let names = self.list_xattr_raw(from)?;
+        copy_each(
+            names,
+            &filter,
+            |name| self.getxattr_raw_name(from, name),
+            |name, value| self.setxattr_raw_name(to, name, value, 0),
+        )
+    }
+
+    /// Like copy_xattrs between two open files, which can be on
+    /// different connections
+    pub fn fcopy_xattrs(
+        &self,
+        from: &GlusterFile,
+        to: &GlusterFile,
+        filter: XattrFilter,
+    ) -> Result<usize, GlusterError> {
+        let names = from.list_xattr_raw()?;
+        copy_each(
+            names,
+            &filter,
+            |name| from.getxattr_raw_name(name),
+            |name, value| to.setxattr_raw_name(name, value, 0),
+        )
+    }
+}
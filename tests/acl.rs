@@ -0,0 +1,116 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::acl::{Acl, AclEntry, AclError, AclPerms, AclTag};
+
+fn hex(s: &str) -> Vec<u8> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn entry(tag: AclTag, bits: u16) -> AclEntry {
+    AclEntry::new(tag, AclPerms::from_bits(bits))
+}
+
+// getfattr -e hex -n system.posix_acl_access of a 0640 file after
+// setfacl -m u:1000:rw-,g:100:r-x,m::rwx
+const NAMED_ACCESS: &str = "02000000
+    0100 0600 ffffffff
+    0200 0600 e8030000
+    0400 0400 ffffffff
+    0800 0500 64000000
+    1000 0700 ffffffff
+    2000 0000 ffffffff";
+
+// getfattr -e hex -n system.posix_acl_default of a directory after
+// setfacl -d -m u::rwx,g::r-x,o::---
+const MINIMAL_DEFAULT: &str = "02000000
+    0100 0700 ffffffff
+    0400 0500 ffffffff
+    2000 0000 ffffffff";
+
+fn named_access() -> Acl {
+    Acl::new(vec![
+        entry(AclTag::UserObj, 6),
+        entry(AclTag::User(1000), 6),
+        entry(AclTag::GroupObj, 4),
+        entry(AclTag::Group(100), 5),
+        entry(AclTag::Mask, 7),
+        entry(AclTag::Other, 0),
+    ])
+}
+
+#[test]
+fn acl_decodes_setfacl_fixtures() {
+    assert_eq!(Acl::from_bytes(&hex(NAMED_ACCESS)).unwrap(), named_access());
+    assert_eq!(
+        Acl::from_bytes(&hex(MINIMAL_DEFAULT)).unwrap(),
+        Acl::from_mode(0o750)
+    );
+    assert_eq!(Acl::from_bytes(&hex("02000000")).unwrap(), Acl::default());
+}
+
+#[test]
+fn acl_encodes_sorted_like_setfacl() {
+    assert_eq!(named_access().to_bytes(), hex(NAMED_ACCESS));
+    let mut shuffled = named_access().entries().to_vec();
+    shuffled.reverse();
+    assert_eq!(Acl::new(shuffled).to_bytes(), hex(NAMED_ACCESS));
+    assert_eq!(Acl::from_mode(0o750).to_bytes(), hex(MINIMAL_DEFAULT));
+}
+
+#[test]
+fn acl_rejects_malformed_bytes() {
+    assert_eq!(Acl::from_bytes(&[]), Err(AclError::BadLength(0)));
+    assert_eq!(
+        Acl::from_bytes(&hex("02000000 0100 0600")),
+        Err(AclError::BadLength(8))
+    );
+    assert_eq!(
+        Acl::from_bytes(&hex("01000000 0100 0600 ffffffff")),
+        Err(AclError::BadVersion(1))
+    );
+    assert_eq!(
+        Acl::from_bytes(&hex("02000000 4000 0600 ffffffff")),
+        Err(AclError::UnknownTag(0x40))
+    );
+}
+
+#[test]
+fn acl_validation() {
+    assert_eq!(named_access().validate(), Ok(()));
+    assert_eq!(Acl::from_mode(0o644).validate(), Ok(()));
+
+    let mut acl = named_access();
+    acl.remove(AclTag::Mask);
+    assert_eq!(acl.validate(), Err(AclError::MissingMask));
+
+    let mut acl = Acl::from_mode(0o644);
+    acl.remove(AclTag::Other);
+    assert_eq!(acl.validate(), Err(AclError::MissingEntry(AclTag::Other)));
+    assert_eq!(
+        Acl::default().validate(),
+        Err(AclError::MissingEntry(AclTag::UserObj))
+    );
+
+    let mut entries = named_access().entries().to_vec();
+    entries.push(entry(AclTag::User(1000), 4));
+    assert_eq!(
+        Acl::new(entries).validate(),
+        Err(AclError::DuplicateEntry(AclTag::User(1000)))
+    );
+}
+
+#[test]
+fn acl_set_replaces_entries() {
+    let mut acl = Acl::from_mode(0o600);
+    acl.set(AclTag::User(1000), AclPerms::from_bits(4));
+    acl.set(AclTag::User(1000), AclPerms::from_bits(6));
+    acl.set(AclTag::Mask, AclPerms::from_bits(6));
+    assert_eq!(acl.entries().len(), 5);
+    assert_eq!(acl.get(AclTag::User(1000)), Some(AclPerms::from_bits(6)));
+    assert_eq!(acl.get(AclTag::User(1000)).unwrap().to_string(), "rw-");
+    assert_eq!(acl.validate(), Ok(()));
+}
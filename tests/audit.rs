@@ -0,0 +1,56 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::audit::{AuditOp, AuditRecord, AuditSink, FileAuditSink};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn record(seq: u64, op: AuditOp, errno: Option<i32>) -> AuditRecord {
+    AuditRecord {
+        seq,
+        timestamp: UNIX_EPOCH + Duration::from_millis(1_500_000_000_250),
+        op,
+        path: PathBuf::from("/data/a"),
+        target: None,
+        size: None,
+        uid: 1000,
+        errno,
+    }
+}
+
+#[test]
+fn audit_records_format_as_tab_separated_lines() {
+    let mut copy = record(0, AuditOp::Copy, None);
+    copy.target = Some(PathBuf::from("/data/b"));
+    copy.size = Some(42);
+    assert_eq!(
+        copy.to_string(),
+        "0\t1500000000.250\t1000\tcopy\t/data/a\t/data/b\t42\tok"
+    );
+    assert_eq!(
+        record(1, AuditOp::RemoveDirAll, Some(2)).to_string(),
+        "1\t1500000000.250\t1000\tremove_dir_all\t/data/a\t-\t-\terrno 2"
+    );
+}
+
+#[test]
+fn file_sink_appends_one_line_per_record() {
+    let path = env::temp_dir().join(format!("gfapi-audit-{}.log", process::id()));
+    let _ = fs::remove_file(&path);
+    {
+        let sink = FileAuditSink::open(&path).unwrap();
+        sink.record(&record(0, AuditOp::Write, None)).unwrap();
+    }
+    // Reopening appends rather than truncating
+    let sink = FileAuditSink::open(&path).unwrap();
+    sink.record(&record(1, AuditOp::Unlink, Some(13))).unwrap();
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = journal.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("0\t") && lines[0].ends_with("\twrite\t/data/a\t-\t-\tok"));
+    assert!(lines[1].starts_with("1\t") && lines[1].ends_with("\terrno 13"));
+}
@@ -0,0 +1,123 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::buffer_pool::BufferPool;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Counts allocations of a megabyte or more, to compare the pool with
+// allocating a fresh Vec each time
+struct CountingAlloc;
+
+static LARGE_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() >= 1024 * 1024 {
+            LARGE_ALLOCS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if layout.size() >= 1024 * 1024 {
+            LARGE_ALLOCS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+fn large_allocs_during<F: FnOnce()>(f: F) -> usize {
+    let before = LARGE_ALLOCS.load(Ordering::SeqCst);
+    f();
+    LARGE_ALLOCS.load(Ordering::SeqCst) - before
+}
+
+// Everything runs in one test since the counters are process wide
+#[test]
+fn buffers_are_reused_and_capped() {
+    let pool = BufferPool::global();
+
+    // A fresh Vec per call allocates every time, the pool only once
+    let fresh = large_allocs_during(|| {
+        for i in 0..100 {
+            let mut buffer = vec![0u8; 1024 * 1024];
+            buffer[i] = 1;
+            assert_eq!(buffer[i], 1);
+        }
+    });
+    let pooled = large_allocs_during(|| {
+        for i in 0..100 {
+            let mut buffer = pool.get(1024 * 1024);
+            buffer[i] = 1;
+            assert_eq!(buffer[i], 1);
+        }
+    });
+    assert_eq!(fresh, 100);
+    assert_eq!(pooled, 1);
+
+    let before = pool.stats();
+    {
+        let small = pool.get(1000);
+        assert_eq!(small.len(), 1000);
+    }
+    let after = pool.stats();
+    assert_eq!(
+        after.reused + after.allocated,
+        before.reused + before.allocated + 1
+    );
+    assert!(after.pooled_bytes >= 64 * 1024);
+
+    // Buffers go back even when the code using them bails out
+    let before = pool.stats();
+    let result: Result<(), ()> = (|| {
+        let _buffer = pool.get(1024 * 1024);
+        Err(())
+    })();
+    assert!(result.is_err());
+    let after = pool.stats();
+    assert_eq!(after.reused, before.reused + 1);
+    assert_eq!(after.pooled_bytes, before.pooled_bytes);
+
+    // Each thread has its own stacks
+    let before = pool.stats();
+    thread::spawn(move || {
+        drop(pool.get(4 * 1024 * 1024));
+        drop(pool.get(4 * 1024 * 1024));
+    })
+    .join()
+    .unwrap();
+    let after = pool.stats();
+    assert_eq!(after.allocated, before.allocated + 1);
+    assert_eq!(after.reused, before.reused + 1);
+    // The thread's buffers are freed when it exits
+    assert_eq!(after.pooled_bytes, before.pooled_bytes);
+
+    // Oversized buffers bypass the pool
+    let before = pool.stats();
+    drop(pool.get(16 * 1024 * 1024));
+    let after = pool.stats();
+    assert_eq!(after.allocated, before.allocated + 1);
+    assert_eq!(after.pooled_bytes, before.pooled_bytes);
+
+    // Nothing is kept past the cap
+    pool.clear_thread();
+    assert_eq!(pool.stats().pooled_bytes, 0);
+    pool.set_max_bytes(1024 * 1024);
+    let before = pool.stats();
+    {
+        let _a = pool.get(1024 * 1024);
+        let _b = pool.get(1024 * 1024);
+    }
+    let after = pool.stats();
+    assert_eq!(after.discarded, before.discarded + 1);
+    assert_eq!(after.pooled_bytes, 1024 * 1024);
+}
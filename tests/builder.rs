@@ -0,0 +1,162 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::builder::{parse_servers, GlusterBuilder, VolfileServer};
+use gfapi_sys::gluster::{Gluster, GlusterError};
+
+fn server(host: &str, port: u16) -> VolfileServer {
+    VolfileServer {
+        host: host.to_string(),
+        port,
+    }
+}
+
+// The token, entry position and reason of a parse failure
+fn rejected(list: &str) -> (String, usize, &'static str) {
+    match parse_servers(list) {
+        Err(GlusterError::InvalidServer {
+            token,
+            position,
+            reason,
+        }) => (token, position, reason),
+        other => panic!("{:?} parsed as {:?}", list, other),
+    }
+}
+
+#[test]
+fn ports_default_to_24007() {
+    assert_eq!(
+        parse_servers("host1:24008,host2,host3:24009").unwrap(),
+        vec![
+            server("host1", 24008),
+            server("host2", 24007),
+            server("host3", 24009),
+        ]
+    );
+    assert_eq!(
+        parse_servers("gluster").unwrap(),
+        vec![server("gluster", 24007)]
+    );
+}
+
+#[test]
+fn whitespace_around_entries_is_trimmed() {
+    assert_eq!(
+        parse_servers(" host1:24007 ,\thost2 , 10.0.0.3:1 ").unwrap(),
+        vec![
+            server("host1", 24007),
+            server("host2", 24007),
+            server("10.0.0.3", 1),
+        ]
+    );
+}
+
+#[test]
+fn ipv6_literals_go_in_brackets() {
+    assert_eq!(
+        parse_servers("[fd00::1]:24008,[::1],host").unwrap(),
+        vec![
+            server("fd00::1", 24008),
+            server("::1", 24007),
+            server("host", 24007),
+        ]
+    );
+    assert_eq!(
+        rejected("host,fd00::1"),
+        (
+            "fd00::1".to_string(),
+            1,
+            "IPv6 addresses need brackets, as in [::1]:24007"
+        )
+    );
+}
+
+#[test]
+fn empty_entries_are_rejected() {
+    assert_eq!(rejected(""), (String::new(), 0, "empty entry"));
+    assert_eq!(rejected("host1,,host2"), (String::new(), 1, "empty entry"));
+    assert_eq!(rejected("host1, "), (String::new(), 1, "empty entry"));
+}
+
+#[test]
+fn bad_ports_are_rejected() {
+    let reason = "port isn't a number from 1 to 65535";
+    for token in &["host:", "host:0", "host:65536", "host:+80", "host:http"] {
+        assert_eq!(
+            rejected(&format!("ok,{}", token)),
+            (token.to_string(), 1, reason)
+        );
+    }
+    assert_eq!(rejected("[::1]:"), ("[::1]:".to_string(), 0, reason));
+}
+
+#[test]
+fn bad_hosts_are_rejected() {
+    assert_eq!(
+        rejected(":24007"),
+        (":24007".to_string(), 0, "missing host")
+    );
+    assert_eq!(
+        rejected("my host"),
+        ("my host".to_string(), 0, "host contains whitespace")
+    );
+    assert_eq!(
+        rejected("[fd00::1"),
+        ("[fd00::1".to_string(), 0, "unclosed [")
+    );
+    assert_eq!(
+        rejected("[host]:24007"),
+        (
+            "[host]:24007".to_string(),
+            0,
+            "not an IPv6 address inside []"
+        )
+    );
+    assert_eq!(
+        rejected("[::1]24007"),
+        ("[::1]24007".to_string(), 0, "expected :port after ]")
+    );
+}
+
+#[test]
+fn the_error_names_the_token() {
+    let error = parse_servers("a,b,c:x").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid volfile server \"c:x\" at entry 2: port isn't a number from 1 to 65535"
+    );
+}
+
+#[test]
+fn display_round_trips() {
+    let servers = vec![server("fd00::1", 24008), server("host", 24007)];
+    let list: Vec<String> = servers.iter().map(|s| s.to_string()).collect();
+    assert_eq!(list, vec!["[fd00::1]:24008", "host:24007"]);
+    assert_eq!(parse_servers(&list.join(",")).unwrap(), servers);
+}
+
+#[test]
+fn misconfigured_builders_are_rejected_before_connecting() {
+    let error = |builder: GlusterBuilder| match builder.connect() {
+        Err(e) => e.to_string(),
+        Ok(_) => panic!("{:?} connected", builder),
+    };
+    assert!(error(Gluster::builder("test").server("")).contains("no volfile server configured"));
+    assert!(error(Gluster::builder("")).contains("volume name is empty"));
+    assert_eq!(
+        error(Gluster::builder("test").transport("udp")),
+        "unknown transport \"udp\", expected tcp, unix or rdma"
+    );
+    assert!(error(Gluster::builder("test").backup_server("", 24007)).contains("has no host"));
+    assert!(
+        error(Gluster::builder("test").xlator_option("", "key", "value"))
+            .contains("needs both an xlator and a key")
+    );
+
+    // A local volfile needs no server
+    let local = Gluster::builder("test").server("").volfile("/etc/test.vol");
+    assert!(local.validate().is_ok());
+    assert!(Gluster::builder("test")
+        .xlator_option("*-write-behind", "cache-size", "4MB")
+        .validate()
+        .is_ok());
+}
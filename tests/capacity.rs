@@ -0,0 +1,87 @@
+extern crate gfapi_sys;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use gfapi_sys::capacity::{CapacityEvent, CapacityWatcher, Crossing};
+use gfapi_sys::gluster::GlusterError;
+use gfapi_sys::space::FreeSpace;
+
+// Free space as a fake volume reports it, None makes the poll fail
+type Fake = Arc<Mutex<Option<u64>>>;
+
+fn set_used(fake: &Fake, used: u64) {
+    *fake.lock().unwrap() = Some(1000 - used);
+}
+
+fn next(watcher: &CapacityWatcher) -> Result<CapacityEvent, GlusterError> {
+    watcher.events().recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+fn expect(watcher: &CapacityWatcher, threshold: f64, direction: Crossing) {
+    let event = next(watcher).unwrap();
+    assert_eq!((event.crossed_threshold, event.direction), (threshold, direction));
+}
+
+fn expect_quiet(watcher: &CapacityWatcher) {
+    thread::sleep(Duration::from_millis(50));
+    assert!(watcher.events().try_recv().is_err());
+}
+
+#[test]
+fn capacity_watcher_reports_crossings_with_hysteresis() {
+    let fake: Fake = Arc::new(Mutex::new(Some(500)));
+    let source = fake.clone();
+    let watcher = CapacityWatcher::spawn(Duration::from_millis(5), &[95.0, 80.0, 90.0], move || {
+        match *source.lock().unwrap() {
+            Some(available) => Ok(FreeSpace {
+                available,
+                total: 1000,
+            }),
+            None => Err(GlusterError::Error("statvfs failed".to_string())),
+        }
+    });
+    expect_quiet(&watcher);
+
+    set_used(&fake, 850);
+    let event = next(&watcher).unwrap();
+    assert_eq!(event.crossed_threshold, 80.0);
+    assert_eq!(event.direction, Crossing::Rising);
+    assert_eq!(event.bytes_free, 150);
+    assert_eq!(event.percent_used, 85.0);
+
+    set_used(&fake, 920);
+    expect(&watcher, 90.0, Crossing::Rising);
+    // Within the hysteresis band, no flapping
+    set_used(&fake, 895);
+    expect_quiet(&watcher);
+    set_used(&fake, 905);
+    expect_quiet(&watcher);
+    set_used(&fake, 880);
+    expect(&watcher, 90.0, Crossing::Falling);
+
+    set_used(&fake, 970);
+    expect(&watcher, 90.0, Crossing::Rising);
+    expect(&watcher, 95.0, Crossing::Rising);
+    set_used(&fake, 100);
+    expect(&watcher, 95.0, Crossing::Falling);
+    expect(&watcher, 90.0, Crossing::Falling);
+    expect(&watcher, 80.0, Crossing::Falling);
+    expect_quiet(&watcher);
+
+    // Errors are reported and polling carries on
+    *fake.lock().unwrap() = None;
+    assert!(next(&watcher).is_err());
+    set_used(&fake, 990);
+    loop {
+        match next(&watcher) {
+            Err(_) => continue,
+            Ok(event) => {
+                assert_eq!(event.crossed_threshold, 80.0);
+                break;
+            }
+        }
+    }
+    drop(watcher);
+}
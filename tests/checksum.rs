@@ -0,0 +1,84 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::checksum::{Adler32, ChecksumAlgorithm, Crc32c, Sha256};
+
+#[test]
+fn crc32c_check_value() {
+    assert_eq!(Crc32c::checksum(b"123456789"), 0xe306_9283);
+    assert_eq!(Crc32c::checksum(b""), 0);
+
+    let mut crc = Crc32c::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xe306_9283);
+}
+
+#[test]
+fn adler32_check_value() {
+    assert_eq!(Adler32::checksum(b"Wikipedia"), 0x11e6_0398);
+    assert_eq!(Adler32::checksum(b""), 1);
+
+    // Long enough to need reducing part way through
+    let data = vec![0xff; 100_000];
+    let mut adler = Adler32::new();
+    for chunk in data.chunks(777) {
+        adler.update(chunk);
+    }
+    assert_eq!(adler.finish(), 0x149a_302c);
+    assert_eq!(Adler32::checksum(&data), 0x149a_302c);
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn sha256_test_vectors() {
+    assert_eq!(
+        hex(&Sha256::checksum(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex(&Sha256::checksum(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    // Two blocks once padded
+    assert_eq!(
+        hex(&Sha256::checksum(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+
+    // Updates that don't line up with blocks
+    let data = vec![b'a'; 1_000_000];
+    let mut sha = Sha256::new();
+    for chunk in data.chunks(997) {
+        sha.update(chunk);
+    }
+    assert_eq!(
+        hex(&sha.finish()),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+}
+
+#[test]
+fn checksum_algorithms_by_name() {
+    for &algorithm in &[ChecksumAlgorithm::Crc32c, ChecksumAlgorithm::Sha256] {
+        let name = algorithm.to_string();
+        assert_eq!(name.parse::<ChecksumAlgorithm>().unwrap(), algorithm);
+        assert_eq!(
+            name.to_uppercase().parse::<ChecksumAlgorithm>().unwrap(),
+            algorithm
+        );
+        let digest = algorithm.digest(b"123456789");
+        assert_eq!(digest.len(), algorithm.digest_len());
+        let mut reader: &[u8] = b"123456789";
+        assert_eq!(algorithm.digest_reader(&mut reader).unwrap(), digest);
+    }
+    assert_eq!(
+        ChecksumAlgorithm::Crc32c.digest(b"123456789"),
+        vec![0xe3, 0x06, 0x92, 0x83]
+    );
+    assert!("md5".parse::<ChecksumAlgorithm>().is_err());
+}
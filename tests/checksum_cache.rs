@@ -0,0 +1,76 @@
+extern crate gfapi_sys;
+extern crate libc;
+
+use gfapi_sys::checksum::ChecksumAlgorithm;
+use gfapi_sys::checksum_cache::CachedChecksum;
+use gfapi_sys::metadata::Metadata;
+
+fn metadata(len: i64, mtime: i64, mtime_nsec: i64) -> Metadata {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    stat.st_mode = libc::S_IFREG | 0o644;
+    stat.st_size = len as libc::off_t;
+    stat.st_mtime = mtime as libc::time_t;
+    stat.st_mtime_nsec = mtime_nsec as _;
+    Metadata::from_stat(stat)
+}
+
+#[test]
+fn cached_checksums_round_trip() {
+    let file = metadata(9, 1_700_000_000, 42);
+    let cached = CachedChecksum::new(
+        ChecksumAlgorithm::Crc32c,
+        &file,
+        ChecksumAlgorithm::Crc32c.digest(b"123456789"),
+    );
+    assert_eq!(
+        cached.to_bytes(),
+        b"v1 crc32c 9 1700000000.000000042 e3069283".to_vec()
+    );
+    assert_eq!(
+        CachedChecksum::parse(&cached.to_bytes()),
+        Some(cached.clone())
+    );
+    assert!(cached.matches(&file));
+
+    let sha = CachedChecksum::new(
+        ChecksumAlgorithm::Sha256,
+        &metadata(0, -5, 999_999_999),
+        ChecksumAlgorithm::Sha256.digest(b""),
+    );
+    assert_eq!(CachedChecksum::parse(&sha.to_bytes()), Some(sha));
+}
+
+#[test]
+fn a_changed_size_or_mtime_misses() {
+    let cached = CachedChecksum::new(ChecksumAlgorithm::Crc32c, &metadata(9, 100, 5), vec![0; 4]);
+    assert!(!cached.matches(&metadata(10, 100, 5)));
+    assert!(!cached.matches(&metadata(9, 101, 5)));
+    assert!(!cached.matches(&metadata(9, 100, 6)));
+}
+
+#[test]
+fn corrupted_values_are_ignored() {
+    let corrupted: &[&[u8]] = &[
+        b"",
+        b"garbage",
+        b"v2 crc32c 9 100.000000000 e3069283",
+        b"v1 md5 9 100.000000000 e3069283",
+        b"v1 crc32c nine 100.000000000 e3069283",
+        b"v1 crc32c 9 100 e3069283",
+        b"v1 crc32c 9 100.5 e3069283",
+        b"v1 crc32c 9 100.000000000 e30692",
+        b"v1 crc32c 9 100.000000000 e30692zz",
+        b"v1 sha256 9 100.000000000 e3069283",
+        b"v1 crc32c 9 100.000000000 e3069283 extra",
+        b"v1 crc32c 9 100.000000000 e3069283\n",
+        b"v1 crc32c 9 100.000000000 \xff\xfe",
+    ];
+    for value in corrupted {
+        assert_eq!(
+            CachedChecksum::parse(value),
+            None,
+            "{}",
+            String::from_utf8_lossy(value)
+        );
+    }
+}
@@ -0,0 +1,91 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::cli::{format_mode, progress_bar, remote_from_args, RemoteTarget, Target};
+
+use std::path::PathBuf;
+
+fn remote(host: &str, port: u16, volume: &str, path: &str) -> RemoteTarget {
+    RemoteTarget {
+        host: host.to_string(),
+        port,
+        volume: volume.to_string(),
+        path: PathBuf::from(path),
+    }
+}
+
+#[test]
+fn remote_urls_parse() {
+    let parsed: RemoteTarget = "gluster://server/vol/dir/file".parse().unwrap();
+    assert_eq!(parsed, remote("server", 24007, "vol", "/dir/file"));
+    let parsed: RemoteTarget = "gluster://server:24100/vol".parse().unwrap();
+    assert_eq!(parsed, remote("server", 24100, "vol", "/"));
+    let parsed: RemoteTarget = "gluster://[fe80::1]:24008/vol/a".parse().unwrap();
+    assert_eq!(parsed, remote("fe80::1", 24008, "vol", "/a"));
+
+    for bad in &[
+        "server/vol/file",
+        "gluster://",
+        "gluster://server",
+        "gluster:///vol/file",
+        "gluster://server:port/vol",
+        "gluster://[fe80::1/vol",
+    ] {
+        assert!(bad.parse::<RemoteTarget>().is_err(), "{} parsed", bad);
+    }
+}
+
+#[test]
+fn remote_urls_display_round_trip() {
+    for url in &[
+        "gluster://server/vol/dir/file",
+        "gluster://server:24100/vol/",
+    ] {
+        let parsed: RemoteTarget = url.parse().unwrap();
+        assert_eq!(parsed.to_string(), *url);
+    }
+}
+
+#[test]
+fn targets_are_local_unless_urls() {
+    assert_eq!(
+        "/tmp/file".parse::<Target>().unwrap(),
+        Target::Local(PathBuf::from("/tmp/file"))
+    );
+    assert_eq!(
+        "gluster://h/v/f".parse::<Target>().unwrap(),
+        Target::Remote(remote("h", 24007, "v", "/f"))
+    );
+    assert!("".parse::<Target>().is_err());
+}
+
+#[test]
+fn tool_arguments_take_either_form() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        remote_from_args(&args(&["vol", "host:24009", "dir"])).unwrap(),
+        remote("host", 24009, "vol", "/dir")
+    );
+    assert_eq!(
+        remote_from_args(&args(&["gluster://host/vol/dir"])).unwrap(),
+        remote("host", 24007, "vol", "/dir")
+    );
+    assert!(remote_from_args(&args(&["vol", "host"])).is_err());
+}
+
+#[test]
+fn modes_format_like_ls() {
+    assert_eq!(format_mode(0o040755), "drwxr-xr-x");
+    assert_eq!(format_mode(0o100644), "-rw-r--r--");
+    assert_eq!(format_mode(0o120777), "lrwxrwxrwx");
+    assert_eq!(format_mode(0o104755), "-rwsr-xr-x");
+    assert_eq!(format_mode(0o102644), "-rw-r-Sr--");
+    assert_eq!(format_mode(0o041777), "drwxrwxrwt");
+}
+
+#[test]
+fn progress_bars_fill_in_proportion() {
+    assert_eq!(progress_bar(0, 100, 10), "[          ]   0% 0/100");
+    assert_eq!(progress_bar(50, 100, 10), "[#####     ]  50% 50/100");
+    assert_eq!(progress_bar(100, 100, 10), "[##########] 100% 100/100");
+    assert_eq!(progress_bar(0, 0, 4), "[####] 100% 0/0");
+}
@@ -0,0 +1,150 @@
+extern crate gfapi_sys;
+extern crate toml;
+
+use gfapi_sys::builder::GlusterBuilder;
+use gfapi_sys::config::GlusterConfig;
+use gfapi_sys::gluster::{Gluster, GlusterError, GlusterLogLevel};
+use gfapi_sys::tuning::{TuningProfile, XlatorOption};
+
+const SAMPLE: &str = r#"
+volume = "archive"
+servers = ["gl1:24008", "gl2", "[fd00::1]"]
+transport = "tcp"
+subdir = "/tenants/a"
+read_only = true
+
+[log]
+file = "/var/log/app/gfapi.log"
+level = "warning"
+
+[xlator_options]
+"*-io-cache.cache-size" = "256MB"
+"*-write-behind.flush-behind" = "on"
+
+[timeouts]
+ping = 10
+"#;
+
+fn config_error(toml: &str) -> (String, String) {
+    let config: GlusterConfig = toml::from_str(toml).unwrap();
+    match GlusterBuilder::from_config(&config) {
+        Err(GlusterError::InvalidConfig { field, reason }) => (field, reason),
+        other => panic!("{:?} gave {:?}", config, other),
+    }
+}
+
+#[test]
+fn sample_config_builds_the_same_builder() {
+    let config: GlusterConfig = toml::from_str(SAMPLE).unwrap();
+    assert_eq!(config.volume, "archive");
+    assert_eq!(config.timeouts.ping, Some(10));
+    assert_eq!(config.timeouts.frame, None);
+
+    let expected = Gluster::builder("archive")
+        .server("gl1")
+        .port(24008)
+        .backup_server("gl2", 24007)
+        .backup_server("fd00::1", 24007)
+        .transport("tcp")
+        .subdir("/tenants/a")
+        .logging("/var/log/app/gfapi.log", GlusterLogLevel::Warning)
+        .tuning(TuningProfile::Custom(vec![
+            XlatorOption::new("*-io-cache", "cache-size", "256MB"),
+            XlatorOption::new("*-write-behind", "flush-behind", "on"),
+            XlatorOption::new("*-client-*", "ping-timeout", "10"),
+        ]))
+        .read_only(true);
+    assert_eq!(GlusterBuilder::from_config(&config).unwrap(), expected);
+}
+
+#[test]
+fn a_volfile_replaces_the_servers() {
+    let config: GlusterConfig = toml::from_str(
+        r#"
+volume = "scratch"
+volfile = "/etc/glusterfs/scratch.vol"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        GlusterBuilder::from_config(&config).unwrap(),
+        Gluster::builder("scratch").volfile("/etc/glusterfs/scratch.vol")
+    );
+}
+
+#[test]
+fn an_empty_server_list_is_rejected() {
+    assert_eq!(
+        config_error("volume = \"v\"\nservers = []\n"),
+        (
+            "servers".to_string(),
+            "is empty, give at least one server or a volfile".to_string()
+        )
+    );
+}
+
+#[test]
+fn volfile_and_servers_conflict() {
+    let (field, reason) =
+        config_error("volume = \"v\"\nservers = [\"gl1\"]\nvolfile = \"/etc/v.vol\"\n");
+    assert_eq!(field, "volfile");
+    assert_eq!(reason, "can't be used together with servers");
+}
+
+#[test]
+fn malformed_fields_are_named() {
+    assert_eq!(
+        config_error("volume = \"\"\nservers = [\"gl1\"]\n").0,
+        "volume"
+    );
+    assert_eq!(
+        config_error("volume = \"v\"\nservers = [\"gl1\", \"gl2:0\"]\n"),
+        (
+            "servers".to_string(),
+            "invalid volfile server \"gl2:0\" at entry 1: port isn't a number from 1 to 65535"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        config_error("volume = \"v\"\nservers = [\"gl1\"]\ntransport = \"udp\"\n").0,
+        "transport"
+    );
+    assert_eq!(
+        config_error(
+            "volume = \"v\"\nservers = [\"gl1\"]\n[log]\nfile = \"/l\"\nlevel = \"loud\"\n"
+        )
+        .0,
+        "log.level"
+    );
+    assert_eq!(
+        config_error("volume = \"v\"\nservers = [\"gl1\"]\n[xlator_options]\nnodot = \"1\"\n").0,
+        "xlator_options"
+    );
+    assert_eq!(
+        config_error(
+            "volume = \"v\"\nservers = [\"gl1\"]\n[xlator_options]\n\"*-read-ahead.page-count\" = \"99\"\n"
+        )
+        .0,
+        "xlator_options"
+    );
+    assert_eq!(
+        config_error("volume = \"v\"\nservers = [\"gl1\"]\n[timeouts]\nframe = 0\n"),
+        ("timeouts.frame".to_string(), "can't be 0".to_string())
+    );
+}
+
+#[test]
+fn unknown_fields_are_refused() {
+    assert!(toml::from_str::<GlusterConfig>("volume = \"v\"\nserver = \"gl1\"\n").is_err());
+}
+
+#[test]
+fn the_error_names_the_field() {
+    let config: GlusterConfig = toml::from_str("volume = \"v\"\n").unwrap();
+    assert_eq!(
+        GlusterBuilder::from_config(&config)
+            .unwrap_err()
+            .to_string(),
+        "config field servers: is empty, give at least one server or a volfile"
+    );
+}
@@ -0,0 +1,148 @@
+// The behaviour every RemoteFs backend has to share, run against each of
+// them by tests/remote_fs.rs and, against a live volume, tests/test.rs.
+
+use gfapi_sys::remote_fs::{self, RemoteDirEntry, RemoteFileType, RemoteFs, RemoteXattrs};
+use libc::ENOTEMPTY;
+
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::Path;
+
+fn sorted_entries(fs: &dyn RemoteFs, dir: &Path) -> Vec<RemoteDirEntry> {
+    let mut entries: Vec<RemoteDirEntry> =
+        fs.read_dir(dir).unwrap().collect::<Result<_, _>>().unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+fn entry(name: &str, file_type: RemoteFileType) -> RemoteDirEntry {
+    RemoteDirEntry {
+        name: OsString::from(name),
+        file_type,
+    }
+}
+
+/// Exercise fs inside root, an existing empty directory, leaving it empty
+pub fn run(fs: &dyn RemoteFs, root: &Path) {
+    let file = root.join("file");
+    let dir = root.join("dir");
+
+    // Files are created, truncated and read back whole
+    assert_eq!(fs.read(&file).unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.exists(&file).unwrap());
+    fs.write(&file, b"hello world").unwrap();
+    fs.write(&file, b"hello").unwrap();
+    assert_eq!(fs.read(&file).unwrap(), b"hello");
+    let metadata = fs.metadata(&file).unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.len, 5);
+    assert!(fs.exists(&file).unwrap());
+
+    // Directories
+    fs.create_dir(&dir).unwrap();
+    assert_eq!(
+        fs.create_dir(&dir).unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert!(fs.metadata(&dir).unwrap().is_dir());
+    assert_eq!(
+        fs.write(&root.join("missing/file"), b"x")
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    assert!(fs.read(&dir).is_err());
+    assert!(fs.read_dir(&file).is_err());
+    fs.write(&dir.join("inner"), b"inner").unwrap();
+    assert_eq!(
+        sorted_entries(fs, root),
+        vec![
+            entry("dir", RemoteFileType::Dir),
+            entry("file", RemoteFileType::File),
+        ]
+    );
+    assert_eq!(
+        sorted_entries(fs, &dir),
+        vec![entry("inner", RemoteFileType::File)]
+    );
+
+    // Renames move whole trees and replace files
+    let renamed = root.join("renamed");
+    fs.rename(&dir, &renamed).unwrap();
+    assert!(!fs.exists(&dir).unwrap());
+    assert_eq!(fs.read(&renamed.join("inner")).unwrap(), b"inner");
+    fs.rename(&file, &renamed.join("inner")).unwrap();
+    assert!(!fs.exists(&file).unwrap());
+    assert_eq!(fs.read(&renamed.join("inner")).unwrap(), b"hello");
+    assert_eq!(
+        fs.rename(&file, &dir).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    // Removal
+    assert_eq!(
+        fs.remove_dir(&renamed).unwrap_err().raw_os_error(),
+        Some(ENOTEMPTY)
+    );
+    assert!(fs.remove_file(&renamed).is_err());
+    fs.remove_file(&renamed.join("inner")).unwrap();
+    assert_eq!(
+        fs.remove_file(&renamed.join("inner")).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    fs.remove_dir(&renamed).unwrap();
+    assert_eq!(
+        fs.remove_dir(&renamed).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    assert!(sorted_entries(fs, root).is_empty());
+}
+
+/// Exercise the xattrs of fs inside root, an existing empty directory,
+/// leaving it empty
+pub fn run_xattrs(fs: &dyn RemoteXattrs, root: &Path) {
+    let file = root.join("xattrs");
+    let name = "user.conformance";
+    fs.write(&file, b"").unwrap();
+
+    assert_eq!(fs.get_xattr(&file, name).unwrap(), None);
+    fs.set_xattr(&file, name, b"one").unwrap();
+    assert_eq!(fs.get_xattr(&file, name).unwrap(), Some(b"one".to_vec()));
+    fs.set_xattr(&file, name, b"two").unwrap();
+    assert_eq!(fs.get_xattr(&file, name).unwrap(), Some(b"two".to_vec()));
+    assert!(fs.list_xattrs(&file).unwrap().contains(&name.to_string()));
+    fs.remove_xattr(&file, name).unwrap();
+    assert_eq!(fs.get_xattr(&file, name).unwrap(), None);
+    assert!(fs.remove_xattr(&file, name).is_err());
+    assert!(!fs.list_xattrs(&file).unwrap().contains(&name.to_string()));
+    assert_eq!(
+        fs.get_xattr(&root.join("missing"), name)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+
+    fs.remove_file(&file).unwrap();
+}
+
+/// Exercise remote_fs::create_dir_all and remove_dir_all over fs inside
+/// root, an existing empty directory, leaving it empty
+pub fn run_tree(fs: &dyn RemoteFs, root: &Path) {
+    let top = root.join("tree");
+    let deep = top.join("a/b/c");
+    remote_fs::create_dir_all(fs, &deep).unwrap();
+    assert!(fs.metadata(&deep).unwrap().is_dir());
+    // Already there is fine
+    remote_fs::create_dir_all(fs, &deep).unwrap();
+    fs.write(&deep.join("file"), b"deep").unwrap();
+    fs.write(&top.join("a/file"), b"shallow").unwrap();
+    fs.create_dir(&top.join("empty")).unwrap();
+
+    remote_fs::remove_dir_all(fs, &top).unwrap();
+    assert!(!fs.exists(&top).unwrap());
+    assert!(sorted_entries(fs, root).is_empty());
+    assert_eq!(
+        remote_fs::remove_dir_all(fs, &top).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+}
@@ -0,0 +1,70 @@
+extern crate gfapi_sys;
+extern crate libc;
+
+use gfapi_sys::copy::{CopyOptions, Overwrite};
+use gfapi_sys::metadata::Metadata;
+use gfapi_sys::write::WriteOptions;
+
+fn metadata(len: i64, mtime: i64, mtime_nsec: i64) -> Metadata {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    stat.st_mode = libc::S_IFREG | 0o644;
+    stat.st_size = len as libc::off_t;
+    stat.st_mtime = mtime as libc::time_t;
+    stat.st_mtime_nsec = mtime_nsec as _;
+    Metadata::from_stat(stat)
+}
+
+#[test]
+fn overwrite_policies_against_existing_destinations() {
+    let source = metadata(10, 1000, 500);
+    let newer = metadata(10, 1000, 501);
+    let older = metadata(10, 999, 900);
+    let same_time = metadata(10, 1000, 500);
+    let different_size = metadata(11, 1000, 500);
+
+    // (policy, destination, replaced)
+    let table = [
+        (Overwrite::Never, &newer, false),
+        (Overwrite::Never, &older, false),
+        (Overwrite::Never, &different_size, false),
+        (Overwrite::Always, &newer, true),
+        (Overwrite::Always, &older, true),
+        (Overwrite::Always, &same_time, true),
+        (Overwrite::IfNewer, &newer, false),
+        (Overwrite::IfNewer, &older, true),
+        (Overwrite::IfNewer, &same_time, false),
+        (Overwrite::IfDifferentSize, &newer, false),
+        (Overwrite::IfDifferentSize, &older, false),
+        (Overwrite::IfDifferentSize, &different_size, true),
+    ];
+    for &(policy, destination, replaced) in &table {
+        assert_eq!(
+            policy.replaces(&source, destination),
+            replaced,
+            "{:?} over {:?}",
+            policy,
+            destination
+        );
+    }
+}
+
+#[test]
+fn if_different_size_ignores_times() {
+    let source = metadata(10, 1000, 0);
+    assert!(!Overwrite::IfDifferentSize.replaces(&source, &metadata(10, 5, 0)));
+    assert!(Overwrite::IfDifferentSize.replaces(&source, &metadata(0, 5000, 0)));
+}
+
+#[test]
+fn write_options_convert_to_always_overwrite() {
+    let opts = CopyOptions::from(WriteOptions::new().chunk_size(4096));
+    let debug = format!("{:?}", opts);
+    assert!(debug.contains("overwrite: Always"), "{}", debug);
+    assert!(debug.contains("chunk_size: 4096"), "{}", debug);
+    let opts = CopyOptions::new()
+        .overwrite(Overwrite::Never)
+        .on_conflict(|conflict| conflict.policy);
+    let debug = format!("{:?}", opts);
+    assert!(debug.contains("on_conflict: true"), "{}", debug);
+    assert!(debug.contains("overwrite: Never"), "{}", debug);
+}
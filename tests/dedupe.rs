@@ -0,0 +1,55 @@
+extern crate gfapi_sys;
+#[cfg(feature = "serde")]
+extern crate toml;
+
+use gfapi_sys::dedupe::{DuplicateGroup, DuplicateReport};
+
+use std::path::PathBuf;
+
+fn group(size: u64, paths: &[&str]) -> DuplicateGroup {
+    DuplicateGroup {
+        size,
+        sha256: "cd".repeat(32),
+        paths: paths.iter().map(PathBuf::from).collect(),
+    }
+}
+
+fn report() -> DuplicateReport {
+    let groups = vec![
+        group(100, &["a", "b"]),
+        group(10, &["c", "d", "e", "f"]),
+        group(1000, &["g", "h", "i"]),
+    ];
+    DuplicateReport {
+        files_scanned: 12,
+        hard_links: 1,
+        reclaimable: groups.iter().map(|g| g.reclaimable()).sum(),
+        groups,
+    }
+}
+
+#[test]
+fn reclaimable_counts_all_but_one_copy() {
+    let report = report();
+    let reclaimable: Vec<u64> = report.groups.iter().map(|g| g.reclaimable()).collect();
+    assert_eq!(reclaimable, vec![100, 30, 2000]);
+    assert_eq!(report.reclaimable, 2130);
+    assert_eq!(report.groups[1].count(), 4);
+    assert_eq!(group(5, &["alone"]).reclaimable(), 0);
+}
+
+#[test]
+fn sorting_puts_the_biggest_savings_first() {
+    let mut report = report();
+    report.sort_by_reclaimable();
+    let sizes: Vec<u64> = report.groups.iter().map(|g| g.size).collect();
+    assert_eq!(sizes, vec![1000, 100, 10]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn reports_round_trip_through_serde() {
+    let report = report();
+    let text = toml::to_string(&report).unwrap();
+    assert_eq!(toml::from_str::<DuplicateReport>(&text).unwrap(), report);
+}
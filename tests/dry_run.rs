@@ -0,0 +1,56 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::dry_run::PlannedOp;
+
+use std::path::PathBuf;
+
+#[test]
+fn planned_ops_display_paths_sizes_and_modes() {
+    let ops = vec![
+        PlannedOp::Mkdir {
+            path: PathBuf::from("/dst"),
+            mode: 0o755,
+        },
+        PlannedOp::Create {
+            path: PathBuf::from("/dst/a"),
+            mode: 0o644,
+        },
+        PlannedOp::Write {
+            path: PathBuf::from("/dst/a"),
+            len: 12,
+        },
+        PlannedOp::Rename {
+            from: PathBuf::from("/dst/a"),
+            to: PathBuf::from("/dst/b"),
+        },
+        PlannedOp::SetXattr {
+            path: PathBuf::from("/dst/b"),
+            name: "user.tag".to_string(),
+            len: 3,
+        },
+        PlannedOp::Chmod {
+            path: PathBuf::from("/dst/b"),
+            mode: 0o4755,
+        },
+        PlannedOp::Unlink {
+            path: PathBuf::from("/dst/b"),
+        },
+        PlannedOp::Rmdir {
+            path: PathBuf::from("/dst"),
+        },
+    ];
+    let lines: Vec<String> = ops.iter().map(PlannedOp::to_string).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "mkdir /dst (0755)",
+            "create /dst/a (0644)",
+            "write 12 bytes to /dst/a",
+            "rename /dst/a to /dst/b",
+            "set user.tag (3 bytes) on /dst/b",
+            "chmod /dst/b to 4755",
+            "unlink /dst/b",
+            "rmdir /dst",
+        ]
+    );
+}
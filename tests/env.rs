@@ -0,0 +1,174 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::builder::GlusterBuilder;
+use gfapi_sys::gluster::{Gluster, GlusterError, GlusterLogLevel};
+
+use std::env;
+use std::ffi::OsString;
+
+// Sets environment variables for the life of a test and puts back what
+// was there before.  Tests use their own prefix so they can run in
+// parallel.
+struct ScopedEnv {
+    saved: Vec<(String, Option<OsString>)>,
+}
+
+impl ScopedEnv {
+    fn new(vars: &[(&str, &str)]) -> ScopedEnv {
+        let mut saved = Vec::new();
+        for &(name, value) in vars {
+            saved.push((name.to_string(), env::var_os(name)));
+            env::set_var(name, value);
+        }
+        ScopedEnv { saved }
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        for (name, value) in self.saved.drain(..) {
+            match value {
+                Some(value) => env::set_var(&name, value),
+                None => env::remove_var(&name),
+            }
+        }
+    }
+}
+
+fn env_error(prefix: &str) -> (String, String) {
+    match GlusterBuilder::from_env_prefixed(prefix) {
+        Err(GlusterError::InvalidEnv { name, reason }) => (name, reason),
+        other => panic!("from_env_prefixed({:?}) gave {:?}", prefix, other),
+    }
+}
+
+#[test]
+fn everything_set() {
+    let _env = ScopedEnv::new(&[
+        ("ALL_GLUSTER_VOLUME", "archive"),
+        ("ALL_GLUSTER_SERVERS", "gl1:24008, gl2,[fd00::1]"),
+        ("ALL_GLUSTER_TRANSPORT", "rdma"),
+        ("ALL_GLUSTER_SUBDIR", "/tenants/a"),
+        ("ALL_GLUSTER_LOG_FILE", "/var/log/app/gfapi.log"),
+        ("ALL_GLUSTER_LOG_LEVEL", "Warn"),
+    ]);
+    let expected = Gluster::builder("archive")
+        .server("gl1")
+        .port(24008)
+        .backup_server("gl2", 24007)
+        .backup_server("fd00::1", 24007)
+        .transport("rdma")
+        .subdir("/tenants/a")
+        .logging("/var/log/app/gfapi.log", GlusterLogLevel::Warning);
+    assert_eq!(GlusterBuilder::from_env_prefixed("ALL_").unwrap(), expected);
+}
+
+#[test]
+fn unset_and_empty_variables_leave_the_defaults() {
+    let _env = ScopedEnv::new(&[
+        ("MIN_GLUSTER_VOLUME", "scratch/sub"),
+        ("MIN_GLUSTER_SERVERS", ""),
+        ("MIN_GLUSTER_TRANSPORT", ""),
+    ]);
+    assert_eq!(
+        GlusterBuilder::from_env_prefixed("MIN_").unwrap(),
+        Gluster::builder("scratch/sub")
+    );
+}
+
+#[test]
+fn log_level_defaults_to_info() {
+    let _env = ScopedEnv::new(&[
+        ("LOGINFO_GLUSTER_VOLUME", "vol"),
+        ("LOGINFO_GLUSTER_LOG_FILE", "/tmp/gfapi.log"),
+    ]);
+    assert_eq!(
+        GlusterBuilder::from_env_prefixed("LOGINFO_").unwrap(),
+        Gluster::builder("vol").logging("/tmp/gfapi.log", GlusterLogLevel::Info)
+    );
+}
+
+#[test]
+fn missing_volume_is_named() {
+    let _env = ScopedEnv::new(&[("NOVOL_GLUSTER_SERVERS", "gl1")]);
+    assert_eq!(
+        env_error("NOVOL_"),
+        ("NOVOL_GLUSTER_VOLUME".to_string(), "isn't set".to_string())
+    );
+    let error = GlusterBuilder::from_env_prefixed("NOVOL_").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "environment variable NOVOL_GLUSTER_VOLUME: isn't set"
+    );
+}
+
+#[test]
+fn malformed_servers_are_named() {
+    let _env = ScopedEnv::new(&[
+        ("BADSRV_GLUSTER_VOLUME", "vol"),
+        ("BADSRV_GLUSTER_SERVERS", "gl1,,gl2"),
+    ]);
+    assert_eq!(
+        env_error("BADSRV_"),
+        (
+            "BADSRV_GLUSTER_SERVERS".to_string(),
+            "invalid volfile server \"\" at entry 1: empty entry".to_string()
+        )
+    );
+}
+
+#[test]
+fn malformed_transport_and_log_level_are_named() {
+    let _env = ScopedEnv::new(&[
+        ("BADTR_GLUSTER_VOLUME", "vol"),
+        ("BADTR_GLUSTER_TRANSPORT", "udp"),
+    ]);
+    assert_eq!(
+        env_error("BADTR_"),
+        (
+            "BADTR_GLUSTER_TRANSPORT".to_string(),
+            "\"udp\" isn't tcp, unix or rdma".to_string()
+        )
+    );
+
+    let _env = ScopedEnv::new(&[
+        ("BADLVL_GLUSTER_VOLUME", "vol"),
+        ("BADLVL_GLUSTER_LOG_FILE", "/tmp/gfapi.log"),
+        ("BADLVL_GLUSTER_LOG_LEVEL", "loud"),
+    ]);
+    let (name, reason) = env_error("BADLVL_");
+    assert_eq!(name, "BADLVL_GLUSTER_LOG_LEVEL");
+    assert!(reason.contains("\"loud\" isn't a log level"), "{}", reason);
+}
+
+#[test]
+fn log_level_needs_a_log_file() {
+    let _env = ScopedEnv::new(&[
+        ("NOFILE_GLUSTER_VOLUME", "vol"),
+        ("NOFILE_GLUSTER_LOG_LEVEL", "debug"),
+    ]);
+    assert_eq!(
+        env_error("NOFILE_"),
+        (
+            "NOFILE_GLUSTER_LOG_FILE".to_string(),
+            "isn't set but NOFILE_GLUSTER_LOG_LEVEL is".to_string()
+        )
+    );
+}
+
+#[test]
+fn log_levels_parse_by_name() {
+    assert_eq!(
+        "TRACE".parse::<GlusterLogLevel>().unwrap(),
+        GlusterLogLevel::Trace
+    );
+    assert_eq!(
+        "crit".parse::<GlusterLogLevel>().unwrap(),
+        GlusterLogLevel::Critical
+    );
+    assert_eq!(
+        "none".parse::<GlusterLogLevel>().unwrap(),
+        GlusterLogLevel::None
+    );
+    assert!("verbose".parse::<GlusterLogLevel>().is_err());
+}
@@ -0,0 +1,65 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::fingerprint::{DirFingerprint, FingerprintLevel};
+
+fn fingerprint() -> DirFingerprint {
+    DirFingerprint {
+        level: FingerprintLevel::Inodes,
+        mtime: 1_500_000_000,
+        mtime_nsec: 42,
+        ctime: 1_500_000_001,
+        ctime_nsec: 999_999_999,
+        size: 4096,
+        entries: 3,
+        hash: 0xdead_beef_0123_4567,
+    }
+}
+
+#[test]
+fn fingerprints_round_trip_through_text() {
+    let fp = fingerprint();
+    let line = fp.to_string();
+    assert_eq!(
+        line,
+        "inodes\t1500000000.000000042\t1500000001.999999999\t4096\t3\tdeadbeef01234567"
+    );
+    assert_eq!(line.parse::<DirFingerprint>().unwrap(), fp);
+    // A trailing newline from a stored file is fine
+    assert_eq!(format!("{}\n", line).parse::<DirFingerprint>().unwrap(), fp);
+    let names = DirFingerprint {
+        level: FingerprintLevel::Names,
+        ..fp
+    };
+    assert_eq!(names.to_string().parse::<DirFingerprint>().unwrap(), names);
+}
+
+#[test]
+fn malformed_fingerprints_are_rejected() {
+    for bad in &[
+        "",
+        "names",
+        "everything\t1.0\t1.0\t0\t0\t0",
+        "names\t1\t1.0\t0\t0\t0",
+        "names\t1.0\t1.0\t-1\t0\t0",
+        "names\t1.0\t1.0\t0\t0\tnothex",
+        "names\t1.0\t1.0\t0\t0\t0\textra",
+    ] {
+        assert!(bad.parse::<DirFingerprint>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn any_difference_counts_as_changed() {
+    let fp = fingerprint();
+    assert!(!fp.changed_from(&fp));
+    assert!(fp.changed_from(&DirFingerprint { hash: 1, ..fp }));
+    assert!(fp.changed_from(&DirFingerprint { entries: 4, ..fp }));
+    assert!(fp.changed_from(&DirFingerprint {
+        mtime_nsec: 43,
+        ..fp
+    }));
+    assert!(fp.changed_from(&DirFingerprint {
+        level: FingerprintLevel::Names,
+        ..fp
+    }));
+}
@@ -0,0 +1,50 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::glob::Glob;
+use gfapi_sys::gluster::GlusterError;
+
+use std::path::Path;
+
+fn matches(pattern: &str, path: &str) -> bool {
+    Glob::new(pattern).unwrap().matches(Path::new(path))
+}
+
+#[test]
+fn patterns_without_a_slash_match_the_file_name() {
+    assert!(matches("*.tmp", "a.tmp"));
+    assert!(matches("*.tmp", "deep/down/b.tmp"));
+    assert!(!matches("*.tmp", "a.tmp/c"));
+    assert!(matches("file?.[ch]", "src/file1.c"));
+    assert!(!matches("file?.[ch]", "src/file10.c"));
+    assert!(matches("[!.]*", "visible"));
+    assert!(!matches("[!.]*", ".hidden"));
+    assert!(matches("[a-c]x", "bx"));
+    assert!(!matches("[a-c]x", "dx"));
+    assert!(matches("\\*", "*"));
+    assert!(!matches("\\*", "a"));
+}
+
+#[test]
+fn patterns_with_a_slash_match_the_whole_path() {
+    assert!(matches("src/*.rs", "src/lib.rs"));
+    assert!(!matches("src/*.rs", "src/a/lib.rs"));
+    assert!(!matches("src/*.rs", "other/src/lib.rs"));
+    assert!(matches("src/**/*.rs", "src/lib.rs"));
+    assert!(matches("src/**/*.rs", "src/a/b/lib.rs"));
+    assert!(matches("**/target", "target"));
+    assert!(matches("**/target", "a/b/target"));
+    assert!(matches("build/**", "build/x/y"));
+    assert!(!matches("build/**", "builder/x"));
+}
+
+#[test]
+fn malformed_patterns_are_rejected() {
+    for pattern in &["[abc", "a\\", "a**", "**b/c", "[z-a]"] {
+        match Glob::new(pattern) {
+            Err(GlusterError::InvalidGlob { .. }) => {}
+            other => panic!("{:?} gave {:?}", pattern, other),
+        }
+    }
+    let glob: Glob = "*.rs".parse().unwrap();
+    assert_eq!(glob.to_string(), "*.rs");
+}
@@ -0,0 +1,13 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::log_writer::decode_records;
+
+#[test]
+fn decode_records_reports_partial_trailer() {
+    let mut data = vec![0, 0, 0, 3, b'a', b'b', b'c', 0, 0, 0, 0];
+    data.extend_from_slice(&[0, 0, 0, 5, b'x', b'y']);
+    let decoded = decode_records(&data);
+    assert_eq!(decoded.records, vec![b"abc".to_vec(), Vec::new()]);
+    assert_eq!(decoded.trailing_bytes, 6);
+    assert_eq!(decode_records(&[0, 0]).trailing_bytes, 2);
+}
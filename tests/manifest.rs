@@ -0,0 +1,104 @@
+extern crate gfapi_sys;
+#[cfg(feature = "serde")]
+extern crate toml;
+
+use gfapi_sys::manifest::{Manifest, ManifestEntry};
+
+use std::path::{Path, PathBuf};
+
+fn entry(path: &str, size: u64, sha256: &str) -> ManifestEntry {
+    ManifestEntry {
+        path: PathBuf::from(path),
+        size,
+        mtime: 1_700_000_000,
+        mtime_nsec: 0,
+        sha256: sha256.to_string(),
+    }
+}
+
+#[test]
+fn manifests_are_sorted_by_path() {
+    let manifest = Manifest::new(vec![
+        entry("b/c", 1, "02"),
+        entry("a", 1, "01"),
+        entry("b", 2, "03"),
+        entry("a", 5, "04"),
+    ]);
+    let paths: Vec<&Path> = manifest
+        .entries()
+        .iter()
+        .map(|e| e.path.as_path())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![Path::new("a"), Path::new("b"), Path::new("b/c")]
+    );
+    // The later of two entries for a path wins
+    assert_eq!(manifest.get(Path::new("a")).unwrap().sha256, "04");
+    assert!(manifest.get(Path::new("c")).is_none());
+}
+
+#[test]
+fn diff_lists_added_removed_and_changed_paths() {
+    let before = Manifest::new(vec![
+        entry("kept", 1, "01"),
+        entry("edited", 1, "02"),
+        entry("gone", 1, "03"),
+    ]);
+    let mut touched = entry("touched", 1, "05");
+    let after = Manifest::new(vec![
+        entry("kept", 1, "01"),
+        entry("edited", 1, "0f"),
+        entry("new", 1, "04"),
+        touched.clone(),
+    ]);
+    let diff = before.diff(&after);
+    assert_eq!(
+        diff.added,
+        vec![PathBuf::from("new"), PathBuf::from("touched")]
+    );
+    assert_eq!(diff.removed, vec![PathBuf::from("gone")]);
+    assert_eq!(diff.changed, vec![PathBuf::from("edited")]);
+    assert!(!diff.is_empty());
+    assert!(after.diff(&after).is_empty());
+
+    touched.mtime += 1;
+    let retouched = Manifest::new(vec![touched]);
+    let only_touched = Manifest::new(vec![entry("touched", 1, "05")]);
+    assert_eq!(
+        only_touched.diff(&retouched).changed,
+        vec![PathBuf::from("touched")]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn manifests_round_trip_through_serde() {
+    let manifest = Manifest::new(vec![entry("a", 3, "ab"), entry("dir/b", 0, "cd")]);
+    let text = toml::to_string(&manifest).unwrap();
+    assert_eq!(toml::from_str::<Manifest>(&text).unwrap(), manifest);
+}
+
+#[test]
+fn manifests_round_trip_through_bytes() {
+    let digest = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    let mut odd = entry("dir/with space\nand\\slash", 0, digest);
+    odd.mtime = -3;
+    odd.mtime_nsec = 5;
+    let manifest = Manifest::new(vec![odd, entry("plain", 12, digest)]);
+    let bytes = manifest.to_bytes();
+    assert!(bytes.starts_with(b"gfapi-manifest v1\n"));
+    assert_eq!(bytes.iter().filter(|&&b| b == b'\n').count(), 3);
+    assert_eq!(Manifest::parse(&bytes), Some(manifest));
+    assert_eq!(
+        Manifest::parse(b"gfapi-manifest v1\n"),
+        Some(Manifest::default())
+    );
+
+    assert!(Manifest::parse(b"").is_none());
+    assert!(Manifest::parse(b"gfapi-manifest v2\n").is_none());
+    let short_digest = b"gfapi-manifest v1\n1 0.000000000 abcd name\n";
+    assert!(Manifest::parse(short_digest).is_none());
+    let bad_escape = format!("gfapi-manifest v1\n1 0.000000000 {} a\\tb\n", digest);
+    assert!(Manifest::parse(bad_escape.as_bytes()).is_none());
+}
@@ -0,0 +1,43 @@
+extern crate gfapi_sys;
+extern crate libc;
+
+use gfapi_sys::metadata::{FileType, Metadata};
+use libc::{DT_DIR, DT_LNK, DT_REG, DT_UNKNOWN, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG};
+
+#[test]
+fn file_type_from_mode_ignores_permissions() {
+    assert_eq!(FileType::from_mode(S_IFREG | 0o644), Some(FileType::File));
+    assert_eq!(FileType::from_mode(S_IFDIR | 0o1777), Some(FileType::Dir));
+    assert_eq!(
+        FileType::from_mode(S_IFLNK | 0o777),
+        Some(FileType::Symlink)
+    );
+    assert_eq!(FileType::from_mode(S_IFIFO), Some(FileType::Fifo));
+    assert_eq!(FileType::from_mode(0o644), None);
+}
+
+#[test]
+fn unknown_d_type_has_no_file_type() {
+    assert_eq!(FileType::from_d_type(DT_REG), Some(FileType::File));
+    assert_eq!(FileType::from_d_type(DT_DIR), Some(FileType::Dir));
+    assert_eq!(FileType::from_d_type(DT_LNK), Some(FileType::Symlink));
+    assert_eq!(FileType::from_d_type(DT_UNKNOWN), None);
+}
+
+fn metadata(dev: u64, ino: u64, nlink: u64) -> Metadata {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    stat.st_dev = dev as libc::dev_t;
+    stat.st_ino = ino as libc::ino_t;
+    stat.st_nlink = nlink as libc::nlink_t;
+    Metadata::from_stat(stat)
+}
+
+#[test]
+fn same_file_needs_dev_and_ino_to_match() {
+    let file = metadata(7, 42, 2);
+    assert_eq!((file.dev(), file.ino(), file.nlink()), (7, 42, 2));
+    assert_eq!(file.file_id(), (7, 42));
+    assert!(file.is_same_file_as(&metadata(7, 42, 1)));
+    assert!(!file.is_same_file_as(&metadata(7, 43, 2)));
+    assert!(!file.is_same_file_as(&metadata(8, 42, 2)));
+}
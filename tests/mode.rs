@@ -0,0 +1,118 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::mode::parse;
+
+// Expected results are what coreutils chmod does with a umask of 0
+#[test]
+fn mode_matches_coreutils_chmod() {
+    let cases: &[(u32, bool, &str, u32)] = &[
+        (0o644, false, "0755", 0o755),
+        (0o644, false, "755", 0o755),
+        (0o755, false, "0", 0o000),
+        (0o2755, true, "755", 0o2755),
+        (0o2755, true, "00755", 0o755),
+        (0o2755, false, "755", 0o755),
+        (0o755, true, "2755", 0o2755),
+        (0o4755, true, "1755", 0o5755),
+        (0o644, false, "u+x", 0o744),
+        (0o644, false, "g+w,o-r", 0o660),
+        (0o644, false, "a=r", 0o444),
+        (0o644, false, "ug=rw,o=", 0o660),
+        (0o777, false, "go=", 0o700),
+        (0o640, false, "o=g", 0o644),
+        (0o750, false, "g=u", 0o770),
+        (0o751, false, "u-o", 0o651),
+        (0o644, false, "a+X", 0o644),
+        (0o744, false, "a+X", 0o755),
+        (0o644, true, "a+X", 0o755),
+        (0o600, false, "u+rwX,g+rX", 0o640),
+        (0o700, false, "u+rwX,g+rX", 0o750),
+        (0o755, false, "u+s", 0o4755),
+        (0o755, false, "g+s", 0o2755),
+        (0o755, false, "+t", 0o1755),
+        (0o755, false, "o+t", 0o1755),
+        (0o755, false, "ug+s", 0o6755),
+        (0o4755, false, "u=rwx", 0o755),
+        (0o2755, true, "g=rx", 0o2755),
+        (0o2755, true, "g-s", 0o755),
+        (0o2755, true, "=", 0o2000),
+        (0o600, false, "u+r-w+x", 0o500),
+        (0o600, false, "=rw", 0o666),
+        (0o000, false, "+", 0o000),
+        (0o644, false, "rwxr-x---", 0o750),
+        (0o644, false, "rwsr-sr-t", 0o7755),
+        (0o644, false, "rwSr-Sr-T", 0o7644),
+        (0o777, false, "---------", 0o000),
+        (0o100644, false, "u+x", 0o100744),
+        (0o040755, true, "go-rx", 0o040700),
+    ];
+    for &(current, is_dir, mode, expected) in cases {
+        let change = parse(mode).unwrap_or_else(|e| panic!("{}: {}", mode, e));
+        assert_eq!(
+            change.apply(current, is_dir),
+            expected,
+            "{:o} {} with {:?}",
+            current,
+            if is_dir { "dir" } else { "file" },
+            mode
+        );
+    }
+}
+
+#[test]
+fn mode_without_who_respects_umask() {
+    assert_eq!(
+        parse("+x").unwrap().apply_with_umask(0o600, false, 0o022),
+        0o711
+    );
+    assert_eq!(
+        parse("=rw").unwrap().apply_with_umask(0o600, false, 0o022),
+        0o644
+    );
+    assert_eq!(
+        parse("-w").unwrap().apply_with_umask(0o666, false, 0o022),
+        0o466
+    );
+    // A who part ignores the umask
+    assert_eq!(
+        parse("a+w").unwrap().apply_with_umask(0o444, false, 0o022),
+        0o666
+    );
+}
+
+#[test]
+fn mode_rejects_invalid_strings() {
+    let cases: &[(&str, usize)] = &[
+        ("", 0),
+        ("u", 1),
+        ("ugo", 3),
+        ("u+q", 2),
+        ("z+r", 0),
+        ("8", 0),
+        ("0x12", 1),
+        ("17777", 4),
+        ("u+r,", 4),
+        ("u+r,,g+w", 4),
+        ("g=ur", 3),
+        ("+r g", 2),
+        ("rwxr-x--z", 0),
+    ];
+    for &(mode, position) in cases {
+        match parse(mode) {
+            Ok(change) => panic!("{:?} parsed as {:?}", mode, change),
+            Err(e) => assert_eq!(e.position, position, "{:?}: {}", mode, e),
+        }
+    }
+    assert_eq!(
+        parse("u+q").unwrap_err().to_string(),
+        "invalid mode \"u+q\": unexpected 'q' at offset 2, expected one of rwxXst, +-= or ','"
+    );
+    assert_eq!(
+        parse("17777").unwrap_err().to_string(),
+        "invalid mode \"17777\": unexpected '7' at offset 4, octal modes can't be larger than 7777"
+    );
+    assert_eq!(
+        parse("u").unwrap_err().to_string(),
+        "invalid mode \"u\": a clause needs an operator, one of +, - or ="
+    );
+}
@@ -0,0 +1,23 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::object_store::CasRef;
+
+#[test]
+fn cas_refs_are_sha256_hex() {
+    let cas_ref = CasRef::of(b"abc");
+    let hex = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    assert_eq!(cas_ref.to_string(), hex);
+    assert_eq!(hex.parse::<CasRef>().unwrap(), cas_ref);
+    assert_eq!(hex.to_uppercase().parse::<CasRef>().unwrap(), cas_ref);
+    assert_eq!(cas_ref.key(), format!("objects/ba/78/{}", hex));
+}
+
+#[test]
+fn malformed_cas_refs_are_rejected() {
+    assert!("".parse::<CasRef>().is_err());
+    assert!("ba7816bf".parse::<CasRef>().is_err());
+    let not_hex = "zz7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    assert!(not_hex.parse::<CasRef>().is_err());
+    let too_long = format!("{}00", CasRef::of(b"").to_string());
+    assert!(too_long.parse::<CasRef>().is_err());
+}
@@ -0,0 +1,86 @@
+extern crate gfapi_sys;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use gfapi_sys::path::{escapes, normalize, normalize_within, PathError};
+
+fn norm(path: &str) -> PathBuf {
+    normalize(Path::new(path)).unwrap()
+}
+
+#[test]
+fn normalize_cleans_up_paths() {
+    let cases = [
+        ("", "."),
+        (".", "."),
+        ("./", "."),
+        ("/", "/"),
+        ("//", "/"),
+        ("/..", "/"),
+        ("/../a", "/a"),
+        ("a", "a"),
+        ("a/", "a"),
+        ("a//", "a"),
+        ("a/./b//c/..", "a/b"),
+        ("a/b/c/../../d", "a/d"),
+        ("a/..", "."),
+        ("a/../..", ".."),
+        ("..", ".."),
+        ("../..", "../.."),
+        ("../a/../b", "../b"),
+        ("./../a", "../a"),
+        ("/a/b/../../..", "/"),
+        ("/a/./b/", "/a/b"),
+        ("a/b/", "a/b"),
+        ("...", "..."),
+        ("a/.../b", "a/.../b"),
+    ];
+    for &(input, expected) in &cases {
+        assert_eq!(norm(input), PathBuf::from(expected), "normalizing {:?}", input);
+    }
+}
+
+#[test]
+fn escapes_detects_climbing_paths() {
+    assert!(escapes(&norm("../a")));
+    assert!(escapes(&norm("a/../..")));
+    assert!(escapes(&norm("/a")));
+    assert!(!escapes(&norm("a/..")));
+    assert!(!escapes(&norm("a/../b")));
+    assert!(!escapes(&norm("..a")));
+}
+
+#[test]
+fn normalize_within_keeps_paths_under_base() {
+    let base = Path::new("/srv/uploads");
+    assert_eq!(
+        normalize_within(base, Path::new("a/./b/../c")).unwrap(),
+        PathBuf::from("/srv/uploads/a/c")
+    );
+    assert_eq!(normalize_within(base, Path::new("")).unwrap(), base.to_path_buf());
+    assert_eq!(normalize_within(base, Path::new("a/..")).unwrap(), base.to_path_buf());
+    for bad in &["..", "../x", "a/../../x", "/etc/passwd", "./../x", "a/b/../../../x"] {
+        match normalize_within(base, Path::new(bad)) {
+            Err(PathError::EscapesBase { ref path, .. }) => assert_eq!(path, Path::new(bad)),
+            other => panic!("{} was accepted: {:?}", bad, other),
+        }
+    }
+}
+
+#[test]
+fn normalize_handles_non_utf8_and_nul() {
+    let raw = OsStr::from_bytes(b"dir/\xff\xfe/../name\x80");
+    assert_eq!(
+        normalize(Path::new(raw)).unwrap(),
+        PathBuf::from(OsStr::from_bytes(b"dir/name\x80"))
+    );
+    let with_nul = OsStr::from_bytes(b"a/b\0c");
+    match normalize(Path::new(with_nul)) {
+        Err(PathError::ContainsNul { position, .. }) => assert_eq!(position, 3),
+        other => panic!("NUL was accepted: {:?}", other),
+    }
+    let message = normalize(Path::new(with_nul)).unwrap_err().to_string();
+    assert!(message.contains("NUL byte at position 3"));
+}
@@ -0,0 +1,114 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::readahead::ReadAhead;
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// A file whose preads take a while and return at most limit bytes,
+// counting how many are in flight at once
+#[derive(Default)]
+struct SlowFile {
+    data: Vec<u8>,
+    limit: usize,
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+    calls: AtomicUsize,
+}
+
+impl SlowFile {
+    fn new(len: usize, limit: usize) -> Arc<SlowFile> {
+        Arc::new(SlowFile {
+            data: (0..len).map(|i| (i % 251) as u8).collect(),
+            limit,
+            ..SlowFile::default()
+        })
+    }
+
+    fn pread(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(20));
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let start = (offset as usize).min(self.data.len());
+        let end = (start + len.min(self.limit)).min(self.data.len());
+        Ok(self.data[start..end].to_vec())
+    }
+
+    fn reader(self: &Arc<SlowFile>, window: usize, depth: usize) -> ReadAhead<Cursor<Vec<u8>>> {
+        let file = self.clone();
+        ReadAhead::new(
+            Cursor::new(self.data.clone()),
+            move |offset, len| file.pread(offset, len),
+            window,
+            depth,
+        )
+        .unwrap()
+    }
+}
+
+#[test]
+fn several_reads_are_in_flight_at_once() {
+    let file = SlowFile::new(256 * 1024, usize::MAX);
+    let mut reader = file.reader(64 * 1024, 4);
+    let mut read_back = Vec::new();
+    reader.read_to_end(&mut read_back).unwrap();
+    assert!(read_back == file.data);
+    assert!(file.peak.load(Ordering::SeqCst) > 1);
+    assert!(file.peak.load(Ordering::SeqCst) <= 4);
+}
+
+#[test]
+fn short_preads_in_the_middle_are_continued() {
+    // Every pread comes back with at most 1000 of the 16KiB asked for
+    let file = SlowFile::new(100 * 1024, 1000);
+    let mut reader = file.reader(64 * 1024, 4);
+    let mut read_back = Vec::new();
+    reader.read_to_end(&mut read_back).unwrap();
+    assert!(read_back == file.data);
+    assert!(reader.is_active());
+}
+
+#[test]
+fn random_seeks_switch_readahead_off() {
+    let file = SlowFile::new(1024 * 1024, usize::MAX);
+    let mut reader = file.reader(16 * 1024, 4);
+    let mut buf = [0; 100];
+
+    // Skipping ahead within the window is still sequential
+    for _ in 0..5 {
+        reader.seek(SeekFrom::Current(1000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+    }
+    assert!(reader.is_active());
+
+    // Two far away seeks, then a whole chunk read through in order,
+    // resets the count
+    for &offset in &[900 * 1024, 100 * 1024] {
+        reader.seek(SeekFrom::Start(offset)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+    }
+    let mut chunk = vec![0; 8 * 1024];
+    reader.read_exact(&mut chunk).unwrap();
+    assert!(reader.is_active());
+
+    // Three in a row switch it off for good
+    for &offset in &[700 * 1024, 10 * 1024, 500 * 1024] {
+        reader.seek(SeekFrom::Start(offset)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        let at = offset as usize;
+        assert_eq!(&buf[..], &file.data[at..at + 100]);
+    }
+    assert!(!reader.is_active());
+
+    // From then on reads go to the file and no more preads are issued
+    let calls = file.calls.load(Ordering::SeqCst);
+    reader.seek(SeekFrom::Start(300 * 1024)).unwrap();
+    reader.read_exact(&mut chunk).unwrap();
+    assert!(chunk[..] == file.data[300 * 1024..308 * 1024]);
+    assert_eq!(file.calls.load(Ordering::SeqCst), calls);
+}
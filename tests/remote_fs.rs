@@ -0,0 +1,134 @@
+extern crate gfapi_sys;
+extern crate libc;
+
+mod conformance;
+
+use gfapi_sys::local_fs::LocalFs;
+use gfapi_sys::memory_fs::MemoryFs;
+use gfapi_sys::remote_fs::{RemoteFs, RemoteXattrs};
+
+use libc::{ENOTSUP, EOPNOTSUPP};
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// A fresh local directory, removed again when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("gfapi-remote-fs-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn memory_fs_conforms() {
+    let fs = MemoryFs::new();
+    fs.create_dir(Path::new("/suite")).unwrap();
+    conformance::run(&fs, Path::new("/suite"));
+    conformance::run_xattrs(&fs, Path::new("/suite"));
+    conformance::run_tree(&fs, Path::new("/suite"));
+    fs.remove_dir(Path::new("/suite")).unwrap();
+    conformance::run(&fs, Path::new("/"));
+}
+
+#[test]
+fn local_fs_conforms() {
+    let tmp = TempDir::new("conform");
+    let fs = LocalFs::new(&tmp.0);
+    conformance::run(&fs, Path::new("/"));
+    conformance::run_tree(&fs, Path::new("/"));
+
+    // Not every filesystem a temp dir can be on has user xattrs
+    fs.write(Path::new("/probe"), b"").unwrap();
+    match fs.set_xattr(Path::new("/probe"), "user.probe", b"") {
+        Err(ref e) if e.raw_os_error() == Some(ENOTSUP) || e.raw_os_error() == Some(EOPNOTSUPP) => {
+        }
+        _ => {
+            fs.remove_file(Path::new("/probe")).unwrap();
+            conformance::run_xattrs(&fs, Path::new("/"));
+        }
+    }
+}
+
+#[test]
+fn backends_work_boxed() {
+    let tmp = TempDir::new("boxed");
+    let backends: Vec<Box<dyn RemoteXattrs>> =
+        vec![Box::new(MemoryFs::new()), Box::new(LocalFs::new(&tmp.0))];
+    for backend in backends {
+        let fs: Box<dyn RemoteFs> = backend;
+        fs.write(Path::new("/greeting"), b"hi").unwrap();
+        let names: Vec<_> = fs
+            .read_dir(Path::new("/"))
+            .unwrap()
+            .map(|entry| entry.unwrap().name)
+            .collect();
+        assert_eq!(names, vec!["greeting"]);
+        fs.remove_file(Path::new("/greeting")).unwrap();
+    }
+}
+
+#[test]
+fn paths_stay_inside_the_root() {
+    let tmp = TempDir::new("escape");
+    let local = LocalFs::new(&tmp.0);
+    let memory = MemoryFs::new();
+    let backends: [&dyn RemoteFs; 2] = [&local, &memory];
+    for fs in backends.iter() {
+        assert_eq!(
+            fs.read(Path::new("/../etc/passwd")).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        // Relative paths are taken from the root
+        fs.write(Path::new("relative"), b"r").unwrap();
+        assert_eq!(fs.read(Path::new("/relative")).unwrap(), b"r");
+    }
+}
+
+#[test]
+fn memory_fs_fails_like_posix() {
+    let fs = MemoryFs::new();
+    fs.create_dir(Path::new("/a")).unwrap();
+    fs.create_dir(Path::new("/a/b")).unwrap();
+    fs.create_dir(Path::new("/c")).unwrap();
+    fs.write(Path::new("/f"), b"").unwrap();
+
+    let errno = |result: std::io::Result<()>| result.unwrap_err().raw_os_error().unwrap();
+    assert_eq!(
+        errno(fs.rename(Path::new("/a"), Path::new("/a/b/a"))),
+        libc::EINVAL
+    );
+    assert_eq!(
+        errno(fs.rename(Path::new("/f"), Path::new("/c"))),
+        libc::EISDIR
+    );
+    assert_eq!(
+        errno(fs.rename(Path::new("/c"), Path::new("/f"))),
+        libc::ENOTDIR
+    );
+    assert_eq!(
+        errno(fs.rename(Path::new("/c"), Path::new("/a"))),
+        libc::ENOTEMPTY
+    );
+    assert_eq!(errno(fs.write(Path::new("/f/x"), b"")), libc::ENOTDIR);
+    assert_eq!(errno(fs.write(Path::new("/a"), b"")), libc::EISDIR);
+    assert_eq!(errno(fs.remove_dir(Path::new("/"))), libc::EBUSY);
+
+    // An empty directory can be replaced by another one
+    fs.rename(Path::new("/a"), Path::new("/c")).unwrap();
+    assert!(fs.metadata(Path::new("/c/b")).unwrap().is_dir());
+    assert!(!fs.exists(Path::new("/a")).unwrap());
+}
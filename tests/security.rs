@@ -0,0 +1,32 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::security::{decode_selinux_context, encode_selinux_context};
+
+#[test]
+fn selinux_context_drops_trailing_nul() {
+    assert_eq!(
+        decode_selinux_context(b"system_u:object_r:etc_t:s0\0").unwrap(),
+        "system_u:object_r:etc_t:s0"
+    );
+    // Some tools don't write the NUL
+    assert_eq!(
+        decode_selinux_context(b"system_u:object_r:etc_t:s0").unwrap(),
+        "system_u:object_r:etc_t:s0"
+    );
+    assert_eq!(decode_selinux_context(b"\0").unwrap(), "");
+    assert!(decode_selinux_context(b"\xff\xfe\0").is_err());
+}
+
+#[test]
+fn selinux_context_is_written_nul_terminated() {
+    assert_eq!(
+        encode_selinux_context("unconfined_u:object_r:user_home_t:s0"),
+        b"unconfined_u:object_r:user_home_t:s0\0".to_vec()
+    );
+    assert_eq!(encode_selinux_context("a_t\0"), b"a_t\0".to_vec());
+    let context = "system_u:object_r:var_t:s0:c1,c2";
+    assert_eq!(
+        decode_selinux_context(&encode_selinux_context(context)).unwrap(),
+        context
+    );
+}
@@ -0,0 +1,62 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::split::{parts_manifest_path, Part, PartsManifest};
+
+use std::path::{Path, PathBuf};
+
+fn part(name: &str, offset: u64, len: u64) -> Part {
+    Part {
+        name: PathBuf::from(name),
+        offset,
+        len,
+        sha256: "ab".repeat(32),
+    }
+}
+
+fn manifest(parts: Vec<Part>) -> PartsManifest {
+    PartsManifest {
+        size: 12,
+        mtime: 1_700_000_000,
+        mtime_nsec: 42,
+        part_size: 5,
+        parts,
+    }
+}
+
+#[test]
+fn parts_manifests_round_trip() {
+    let complete = manifest(vec![
+        part("big.part0000", 0, 5),
+        part("big.part0001", 5, 5),
+        part("odd\\name\n.part0002", 10, 2),
+    ]);
+    assert!(complete.is_complete());
+    assert_eq!(
+        PartsManifest::parse(&complete.to_bytes()),
+        Some(complete.clone())
+    );
+
+    let partial = manifest(vec![part("big.part0000", 0, 5)]);
+    assert!(!partial.is_complete());
+    assert_eq!(partial.written(), 5);
+    assert_eq!(PartsManifest::parse(&partial.to_bytes()), Some(partial));
+}
+
+#[test]
+fn parts_manifests_with_gaps_are_rejected() {
+    let gap = manifest(vec![part("a.part0000", 0, 5), part("a.part0002", 10, 2)]);
+    assert_eq!(PartsManifest::parse(&gap.to_bytes()), None);
+    let overrun = manifest(vec![part("a.part0000", 0, 13)]);
+    assert_eq!(PartsManifest::parse(&overrun.to_bytes()), None);
+    let elsewhere = manifest(vec![part("../a.part0000", 0, 5)]);
+    assert_eq!(PartsManifest::parse(&elsewhere.to_bytes()), None);
+    assert_eq!(PartsManifest::parse(b"gfapi-manifest v1\n"), None);
+}
+
+#[test]
+fn manifest_sits_beside_the_parts() {
+    assert_eq!(
+        parts_manifest_path(Path::new("/data/big.img"), Path::new("/out")),
+        PathBuf::from("/out/big.img.parts")
+    );
+}
@@ -0,0 +1,104 @@
+extern crate errno;
+extern crate gfapi_sys;
+extern crate libc;
+
+use errno::{set_errno, Errno};
+use gfapi_sys::stale::{retry_stale, StaleRetry};
+use libc::{EIO, ESTALE};
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// An operation that fails with errno set to each of errors in turn, then
+// succeeds
+fn failing<'a>(
+    errors: &'a [i32],
+    calls: &'a Cell<usize>,
+) -> impl FnMut() -> Result<u32, String> + 'a {
+    move || {
+        let call = calls.get();
+        calls.set(call + 1);
+        match errors.get(call) {
+            Some(&error) => {
+                set_errno(Errno(error));
+                Err(format!("errno {}", error))
+            }
+            None => Ok(42),
+        }
+    }
+}
+
+#[test]
+fn one_estale_is_recovered_transparently() {
+    let calls = Cell::new(0);
+    let reresolved = Cell::new(0);
+    let recoveries = AtomicU64::new(0);
+    let result = retry_stale(
+        &StaleRetry::new(),
+        &recoveries,
+        failing(&[ESTALE], &calls),
+        || {
+            reresolved.set(reresolved.get() + 1);
+            Ok(())
+        },
+    );
+    assert_eq!(result, Ok(42));
+    assert_eq!(calls.get(), 2);
+    assert_eq!(reresolved.get(), 1);
+    assert_eq!(recoveries.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn estale_past_the_attempts_is_surfaced() {
+    let calls = Cell::new(0);
+    let recoveries = AtomicU64::new(0);
+    let result = retry_stale(
+        &StaleRetry::new().attempts(2),
+        &recoveries,
+        failing(&[ESTALE, ESTALE, ESTALE], &calls),
+        || Ok(()),
+    );
+    assert_eq!(result, Err(format!("errno {}", ESTALE)));
+    assert_eq!(calls.get(), 3);
+    assert_eq!(recoveries.load(Ordering::SeqCst), 2);
+
+    calls.set(0);
+    let result = retry_stale(
+        &StaleRetry::new().attempts(0),
+        &recoveries,
+        failing(&[ESTALE], &calls),
+        || Ok(()),
+    );
+    assert!(result.is_err());
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn other_errors_are_not_retried() {
+    let calls = Cell::new(0);
+    let recoveries = AtomicU64::new(0);
+    let result = retry_stale(
+        &StaleRetry::new(),
+        &recoveries,
+        failing(&[EIO], &calls),
+        || panic!("reresolved after EIO"),
+    );
+    assert_eq!(result, Err(format!("errno {}", EIO)));
+    assert_eq!(calls.get(), 1);
+    assert_eq!(recoveries.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn a_failed_reresolve_is_returned() {
+    let calls = Cell::new(0);
+    let recoveries = AtomicU64::new(0);
+    let result = retry_stale(
+        &StaleRetry::new(),
+        &recoveries,
+        failing(&[ESTALE], &calls),
+        || Err("gone".to_string()),
+    );
+    assert_eq!(result, Err("gone".to_string()));
+    assert_eq!(calls.get(), 1);
+    assert_eq!(recoveries.load(Ordering::SeqCst), 0);
+}
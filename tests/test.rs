@@ -1,10 +1,66 @@
 extern crate gfapi_sys;
 extern crate libc;
 
-use std::path::Path;
+mod conformance;
 
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use gfapi_sys::acl::{Acl, AclPerms, AclTag, AclType};
+use gfapi_sys::attrs::SetAttrs;
+use gfapi_sys::audit::{AuditOp, MemoryAuditSink};
+use gfapi_sys::batch::{BatchOptions, BatchStatus};
+use gfapi_sys::buf_writer::GlusterBufWriter;
+use gfapi_sys::builder::VolfileServer;
+use gfapi_sys::cache::{CacheOptions, CachedGluster};
+use gfapi_sys::capacity::Crossing;
+use gfapi_sys::cat::{CatFile, CatOptions};
+use gfapi_sys::checksum::{ChecksumAlgorithm, Crc32c};
+use gfapi_sys::checksum_cache::{CachedChecksum, CHECKSUM_XATTR};
+use gfapi_sys::cleanup::{set_drop_error_handler, DropError};
+use gfapi_sys::copy::{ConflictAction, CopyOptions, CopyOutcome, Overwrite};
+use gfapi_sys::dedupe::{DedupeOptions, DedupeReport, DuplicateOptions};
+use gfapi_sys::delta::{SourceFile, SyncOptions};
+use gfapi_sys::dir_stream::StreamedEntry;
+use gfapi_sys::dry_run::PlannedOp;
+use gfapi_sys::download::{DownloadOptions, RemoteChanged};
+use gfapi_sys::file::{GlusterFile, LockKind};
+use gfapi_sys::fingerprint::{DirFingerprint, FingerprintLevel};
+use gfapi_sys::glob::Glob;
 use gfapi_sys::gluster::*;
-use libc::{O_CREAT, O_RDWR, O_TRUNC, O_APPEND, SEEK_SET, S_IRWXU, timespec};
+use gfapi_sys::handle::GlusterObject;
+use gfapi_sys::local_transfer::LocalTransferOptions;
+use gfapi_sys::lock::LockOptions;
+use gfapi_sys::metadata::{FileType, Metadata};
+use gfapi_sys::mirror::MirrorOptions;
+use gfapi_sys::mode::{defaults, ModePolicy};
+use gfapi_sys::log_writer::{decode_records, LogOptions, RotatingLogWriter};
+use gfapi_sys::manifest::ManifestOptions;
+use gfapi_sys::object_store::{CasOutcome, ObjectStore};
+use gfapi_sys::parallel_read::ParallelReadOptions;
+use gfapi_sys::preserve::PreserveOptions;
+use gfapi_sys::remote_fs::{self, RemoteFs};
+use gfapi_sys::remove::RemoveOptions;
+use gfapi_sys::shred::ShredOptions;
+use gfapi_sys::split::{parts_manifest_path, SplitOptions};
+use gfapi_sys::stale::StaleRetry;
+use gfapi_sys::testing::GlusterTempDir;
+use gfapi_sys::tuning::{TuningProfile, XlatorOption};
+use gfapi_sys::tls::TlsOptions;
+use gfapi_sys::trash::TrashOptions;
+use gfapi_sys::tree_image::{ImageKind, RestoreOptions, TreeImage};
+use gfapi_sys::tree_sync::{SourceTree, SyncAction, TreeSyncOptions, SYNC_MANIFEST};
+use gfapi_sys::tree_upload::TreeUploadOptions;
+use gfapi_sys::upload::{Upload, UploadOptions};
+use gfapi_sys::volume_set::{VolumePath, VolumeSet, VolumeState};
+use gfapi_sys::walk::{WalkEntry, WalkErrorPolicy, WalkOptions};
+use gfapi_sys::write::{FreeSpaceRequirement, VerifyMode, WriteOptions};
+use gfapi_sys::xattr::{XattrFilter, XattrNamespace};
+use libc::{EISDIR, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_APPEND, SEEK_SET, S_IFIFO, S_IRWXU, timespec};
 
 #[test]
 // A simple connect, mkdir, read write ls test.  Should provide a basic level of comfort that
@@ -13,22 +69,24 @@ use libc::{O_CREAT, O_RDWR, O_TRUNC, O_APPEND, SEEK_SET, S_IRWXU, timespec};
 fn integration_test1() {
     println!("Connecting to localhost gluster");
     let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
     println!("Creating a directory");
-    cluster.mkdir(&Path::new("gfapi/new_dir"), S_IRWXU).unwrap();
+    cluster.mkdir(&tmp.child("new_dir"), S_IRWXU).unwrap();
     println!("Creating a test file");
-    let file_handle = cluster.create(&Path::new("gfapi/test"),
+    let file = cluster.create(&tmp.child("test"),
                 O_CREAT | O_RDWR | O_TRUNC,
                 S_IRWXU)
         .unwrap();
+    let file_handle = file.handle().unwrap();
     println!("Writing to test file");
-    let bytes_written = cluster.write(file_handle, &"hello world".as_bytes(), O_APPEND).unwrap();
-    println!("Wrote {} bytes to gfapi/test", bytes_written);
+    let bytes_written = cluster.write(file_handle, &"hello world".as_bytes()).unwrap();
+    println!("Wrote {} bytes to {}", bytes_written, tmp.child("test").display());
     println!("Seeking back to 0");
     cluster.lseek(file_handle, 0, SEEK_SET).unwrap();
     let mut read_buff: Vec<u8> = Vec::with_capacity(1024);
     println!("Read back test file");
-    let bytes_read = cluster.read(file_handle, &mut read_buff, 1024, 0).unwrap();
-    println!("Read {} bytes from gfapi/test", bytes_read);
+    let bytes_read = cluster.read(file_handle, &mut read_buff, 1024).unwrap();
+    println!("Read {} bytes from {}", bytes_read, tmp.child("test").display());
     assert_eq!(bytes_written, bytes_read);
     let file_times = [timespec {
                           tv_sec: 0,
@@ -38,9 +96,3525 @@ fn integration_test1() {
                           tv_sec: 0,
                           tv_nsec: 0,
                       }];
-    cluster.utimens(&Path::new("gfapi/test"), &file_times).unwrap();
-    let d = GlusterDirectory { dir_handle: cluster.opendir(&Path::new("gfapi")).unwrap() };
+    cluster.utimens(&tmp.child("test"), &file_times).unwrap();
+    let d = cluster.opendir(tmp.path()).unwrap();
     for dir_entry in d {
         println!("Dir_entry: {:?}", dir_entry);
     }
+    file.close().unwrap();
+}
+
+#[test]
+fn object_store_round_trip() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let store = ObjectStore::new(&cluster, &tmp.child("objects"))
+        .unwrap()
+        .page_size(2)
+        .prune_empty_dirs(true);
+    store.put("a", b"first").unwrap();
+    store.put("nested/b", b"second").unwrap();
+    store.put("nested/deeper/c", b"third").unwrap();
+    let mut reader: &[u8] = b"fourth";
+    store.put_reader("nested/deeper/d", &mut reader).unwrap();
+
+    assert_eq!(store.get("nested/b").unwrap(), b"second".to_vec());
+    assert_eq!(store.head("nested/deeper/d").unwrap().size, 6);
+
+    let first = store.list("nested/", None).unwrap();
+    let keys: Vec<String> = first.items.iter().map(|m| m.key.clone()).collect();
+    assert_eq!(keys, vec!["nested/b", "nested/deeper/c"]);
+    let second = store.list("nested/", first.continuation.as_ref().map(|c| c.as_str())).unwrap();
+    let keys: Vec<String> = second.items.iter().map(|m| m.key.clone()).collect();
+    assert_eq!(keys, vec!["nested/deeper/d"]);
+    assert!(second.continuation.is_none());
+
+    for key in &["a", "nested/b", "nested/deeper/c", "nested/deeper/d"] {
+        store.delete(key).unwrap();
+    }
+    assert!(!cluster.exists(&tmp.child("objects/nested")).unwrap());
+    assert!(store.list("", None).unwrap().items.is_empty());
+}
+
+#[test]
+fn object_store_pages_in_key_order_across_directories() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let store = ObjectStore::new(&cluster, &tmp.child("objects"))
+        .unwrap()
+        .page_size(1);
+    // '-' sorts before '/' and '0' after it, so "a/b" falls between
+    // the two even though it's in a directory
+    let keys = ["a-c", "a/b", "a/d/e", "a0", "b"];
+    for key in keys.iter().rev() {
+        store.put(key, key.as_bytes()).unwrap();
+    }
+
+    let mut listed = Vec::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let page = store.list("", continuation.as_ref().map(|c| c.as_str())).unwrap();
+        assert!(page.items.len() <= 1);
+        listed.extend(page.items.into_iter().map(|m| m.key));
+        continuation = page.continuation;
+        if continuation.is_none() {
+            break;
+        }
+    }
+    assert_eq!(listed, keys);
+
+    let page = store.list("a/", Some("a/b")).unwrap();
+    let keys: Vec<String> = page.items.iter().map(|m| m.key.clone()).collect();
+    assert_eq!(keys, vec!["a/d/e"]);
+    assert!(page.continuation.is_none());
+}
+
+#[test]
+fn object_store_deduplicates_cas_puts() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let store = ObjectStore::new(&cluster, &tmp.child("cas")).unwrap();
+    let first = store.put_cas(b"same content").unwrap();
+    let mut reader: &[u8] = b"same content";
+    let second = store.put_cas_reader(&mut reader).unwrap();
+    assert_eq!(first.outcome, CasOutcome::Stored);
+    assert_eq!(second.outcome, CasOutcome::Deduplicated);
+    assert_eq!(first.cas_ref, second.cas_ref);
+    assert_eq!(second.size, 12);
+    assert!(store.has(&first.cas_ref).unwrap());
+    assert_eq!(
+        store.get_cas(&first.cas_ref).unwrap(),
+        b"same content".to_vec()
+    );
+
+    let keys: Vec<String> = store
+        .list("", None)
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|m| m.key)
+        .collect();
+    assert_eq!(keys, vec![first.cas_ref.key()]);
+    assert!(cluster
+        .list_dir(&tmp.child("cas/.cas-tmp"), 1)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn unlink_many_reports_each_path() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    cluster.mkdir(&tmp.child("dir"), S_IRWXU).unwrap();
+    for name in &["one", "two"] {
+        let file = cluster.create(&tmp.child(name), O_CREAT | O_RDWR | O_TRUNC, S_IRWXU).unwrap();
+        file.close().unwrap();
+    }
+    let paths = vec![
+        tmp.child("one"),
+        tmp.child("missing"),
+        tmp.child("dir"),
+        tmp.child("two"),
+    ];
+    let opts = BatchOptions {
+        ignore_missing: true,
+        workers: 2,
+    };
+    let result = cluster.unlink_many(paths.iter().map(|p| p.as_path()), &opts);
+    assert_eq!((result.removed, result.missing, result.failed), (2, 1, 1));
+    match result.outcomes[2].status {
+        BatchStatus::Failed { errno, .. } => assert_eq!(errno, EISDIR),
+        ref other => panic!("expected EISDIR, got {:?}", other),
+    }
+    assert_eq!(result.outcomes[2].path, tmp.child("dir"));
+
+    let result = cluster.rmdir_many(vec![paths[2].as_path()], &BatchOptions::default());
+    assert!(result.is_ok());
+}
+
+#[test]
+fn stat_many_preserves_order() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    for i in 0..10 {
+        let file = cluster.create(&tmp.child(i.to_string()), O_CREAT | O_RDWR | O_TRUNC, S_IRWXU).unwrap();
+        cluster.write(file.handle().unwrap(), &vec![0; i]).unwrap();
+        file.close().unwrap();
+    }
+    // Every 7th path doesn't exist
+    let paths: Vec<PathBuf> = (0..1000)
+        .map(|i| if i % 7 == 0 {
+            tmp.child(format!("missing{}", i))
+        } else {
+            tmp.child((i % 10).to_string())
+        })
+        .collect();
+    let results = cluster.stat_many(&paths, 8);
+    assert_eq!(results.len(), 1000);
+    for (i, result) in results.iter().enumerate() {
+        match *result {
+            Ok(ref metadata) => {
+                assert!(i % 7 != 0);
+                assert_eq!(metadata.len(), (i % 10) as u64);
+            }
+            Err(_) => assert_eq!(i % 7, 0),
+        }
+    }
+    let listing = cluster.list_dir(tmp.path(), 4).unwrap();
+    assert_eq!(listing.len(), 10);
+}
+
+#[test]
+fn cached_reads_only_stat() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let cache = CachedGluster::new(&cluster, CacheOptions::default());
+    let path = tmp.child("cached");
+    cache.write_file(&path, b"template").unwrap();
+    assert_eq!(cache.read_to_vec(&path).unwrap(), b"template".to_vec());
+    assert_eq!(cache.read_to_vec(&path).unwrap(), b"template".to_vec());
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.validations), (1, 1, 2));
+
+    cache.write_file(&path, b"changed").unwrap();
+    assert_eq!(cache.read_to_vec(&path).unwrap(), b"changed".to_vec());
+    assert_eq!(cache.stats().misses, 2);
+}
+
+#[test]
+fn cached_listing_invalidated_by_create() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let cache = CachedGluster::new(
+        &cluster,
+        CacheOptions {
+            listing_ttl: Some(Duration::from_secs(60)),
+            ..CacheOptions::default()
+        },
+    );
+    let dir = tmp.child("listing");
+    cache.mkdir(&dir, S_IRWXU).unwrap();
+    assert!(cache.list_dir(&dir).unwrap().is_empty());
+    assert!(cache.list_dir(&dir).unwrap().is_empty());
+    assert_eq!(cache.stats().listing_hits, 1);
+
+    cache.write_file(&dir.join("new"), b"").unwrap();
+    let listing = cache.list_dir(&dir).unwrap();
+    assert_eq!(listing.len(), 1);
+    assert_eq!(listing[0].0.path, Path::new("new"));
+    assert_eq!(cache.stats().listing_misses, 2);
+}
+
+#[test]
+fn cached_entries_under_a_renamed_directory_are_dropped() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    // With a ttl cached bytes aren't checked against the volume, so
+    // anything left behind by the rename would still be served
+    let cache = CachedGluster::new(
+        &cluster,
+        CacheOptions {
+            ttl: Some(Duration::from_secs(60)),
+            listing_ttl: Some(Duration::from_secs(60)),
+            ..CacheOptions::default()
+        },
+    );
+    let dir = tmp.child("renamed");
+    let moved = tmp.child("moved");
+    cache.mkdir(&dir, S_IRWXU).unwrap();
+    cache.mkdir(&dir.join("sub"), S_IRWXU).unwrap();
+    cache.write_file(&dir.join("sub/file"), b"cached").unwrap();
+    assert_eq!(cache.read_to_vec(&dir.join("sub/file")).unwrap(), b"cached");
+    assert_eq!(cache.list_dir(&dir).unwrap().len(), 1);
+    assert_eq!(cache.list_dir(&dir.join("sub")).unwrap().len(), 1);
+
+    cache.rename(&dir, &moved).unwrap();
+    assert!(cache.read_to_vec(&dir.join("sub/file")).is_err());
+    assert!(cache.list_dir(&dir).is_err());
+    assert!(cache.list_dir(&dir.join("sub")).is_err());
+    assert_eq!(cache.read_to_vec(&moved.join("sub/file")).unwrap(), b"cached");
+
+    // rmdir drops the directory's own listing and its parent's
+    assert_eq!(cache.list_dir(&moved).unwrap().len(), 1);
+    cache.unlink(&moved.join("sub/file")).unwrap();
+    assert!(cache.list_dir(&moved.join("sub")).unwrap().is_empty());
+    cache.rmdir(&moved.join("sub")).unwrap();
+    assert!(cache.list_dir(&moved.join("sub")).is_err());
+    assert!(cache.list_dir(&moved).unwrap().is_empty());
+}
+
+#[test]
+fn negative_cache_remembers_missing_paths() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let cache = CachedGluster::new(
+        &cluster,
+        CacheOptions {
+            negative_ttl: Some(Duration::from_secs(60)),
+            ..CacheOptions::default()
+        },
+    );
+    let overrides = tmp.child("overrides");
+    cache.mkdir(&overrides, S_IRWXU).unwrap();
+    let path = overrides.join("tenant.toml");
+
+    assert!(cache.metadata(&path).is_err());
+    assert!(cache.metadata(&path).is_err());
+    assert!(cache.open_file(&path, O_RDONLY).is_err());
+    assert_eq!(cache.stats().negative_inserts, 1);
+    assert_eq!(cache.stats().negative_hits, 2);
+
+    // Created through the wrapper, so it's seen straight away
+    cache.write_file(&path, b"limit = 1").unwrap();
+    assert_eq!(cache.metadata(&path).unwrap().len(), 9);
+    assert_eq!(cache.read_to_vec(&path).unwrap(), b"limit = 1".to_vec());
+
+    // Created behind the wrapper's back it takes an invalidate
+    let other = overrides.join("other.toml");
+    assert!(cache.metadata(&other).is_err());
+    cluster.write_file(&other, b"").unwrap();
+    assert!(cache.metadata(&other).is_err());
+    cache.invalidate(&overrides);
+    assert!(cache.metadata(&other).is_ok());
+}
+
+#[test]
+fn readahead_streams_whole_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("readahead");
+    let data: Vec<u8> = (0..64 * 1024 * 1024).map(|i: usize| (i % 251) as u8).collect();
+    cluster.write_file(&path, &data).unwrap();
+
+    let file = cluster.open_file(&path, O_RDONLY).unwrap();
+    let mut reader = file.with_readahead(4 * 1024 * 1024, 4).unwrap();
+    let mut read_back = Vec::with_capacity(data.len());
+    reader.read_to_end(&mut read_back).unwrap();
+    assert!(read_back == data);
+    assert!(reader.is_active());
+}
+
+#[test]
+fn read_parallel_matches_sequential_read() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("parallel");
+    let mut file = cluster
+        .create_file(&path, O_CREAT | O_RDWR | O_TRUNC, S_IRWXU)
+        .unwrap();
+    // 128MB and a bit, so the last range is short
+    for i in 0..128 {
+        let block: Vec<u8> = (0..1024 * 1024)
+            .map(|j: usize| ((i * 7 + j) % 251) as u8)
+            .collect();
+        file.write_all(&block).unwrap();
+    }
+    file.write_all(b"tail").unwrap();
+    file.close().unwrap();
+
+    let mut sequential = cluster.open_file(&path, O_RDONLY).unwrap();
+    let expected = ChecksumAlgorithm::Sha256
+        .digest_reader(&mut sequential)
+        .unwrap();
+
+    let chunk_size = 4 * 1024 * 1024;
+    let opts = ParallelReadOptions::new().workers(4).chunk_size(chunk_size);
+    let mut reader = cluster.read_parallel(&path, &opts).unwrap();
+    let digest = ChecksumAlgorithm::Sha256
+        .digest_reader(&mut reader)
+        .unwrap();
+    assert_eq!(digest, expected);
+    assert!(reader.peak_buffered() > 0);
+    assert!(reader.peak_buffered() <= 4 * chunk_size);
+    assert_eq!(reader.buffered(), 0);
+}
+
+#[test]
+fn buf_writer_coalesces_small_writes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("buf_writer");
+    let file = cluster.create_file(&path, O_CREAT | O_RDWR | O_TRUNC, S_IRWXU).unwrap();
+    let mut writer = GlusterBufWriter::with_capacity(64 * 1024, file);
+    let mut expected = Vec::new();
+    for i in 0..5000 {
+        let record = format!("{:099}\n", i);
+        writer.write_all(record.as_bytes()).unwrap();
+        expected.extend_from_slice(record.as_bytes());
+    }
+    // Bigger than the buffer so it bypasses it
+    let big = vec![b'x'; 128 * 1024];
+    writer.write_all(&big).unwrap();
+    expected.extend_from_slice(&big);
+    // 655 records fill the buffer, so seven full buffers, what's left
+    // of the records when the big write arrives, and the big write
+    assert_eq!(writer.writes(), 9);
+    writer.into_inner().unwrap().close().unwrap();
+    assert!(cluster.read_to_vec(&path).unwrap() == expected);
+}
+
+#[test]
+fn verified_writes_round_trip() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let data: Vec<u8> = (0..300 * 1024).map(|i: usize| (i % 7) as u8).collect();
+    for mode in &[VerifyMode::PerChunk, VerifyMode::WholeFile] {
+        let opts = WriteOptions::new().verify(*mode).chunk_size(64 * 1024);
+        cluster.write_file_with(&tmp.child("verified"), &data, &opts).unwrap();
+        let copied = cluster
+            .copy(&tmp.child("verified"), &tmp.child("verified_copy"), &opts)
+            .unwrap();
+        assert_eq!(copied, data.len() as u64);
+        assert!(cluster.read_to_vec(&tmp.child("verified_copy")).unwrap() == data);
+    }
+}
+
+#[test]
+fn verification_catches_data_changed_behind_the_write() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("tampered");
+
+    // Overwrites the start of the destination once its own data runs
+    // out, between the last write and the read back
+    struct Tamper<'a> {
+        cluster: &'a Gluster,
+        path: &'a Path,
+        data: &'a [u8],
+    }
+    impl<'a> Read for Tamper<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                let mut file = self.cluster.open_file(self.path, libc::O_WRONLY).unwrap();
+                file.write_all(b"tampered").unwrap();
+                file.close().unwrap();
+            }
+            self.data.read(buf)
+        }
+    }
+
+    let data = vec![3; 128 * 1024];
+    let opts = WriteOptions::new().verify(VerifyMode::WholeFile).chunk_size(64 * 1024);
+    let mut reader = Tamper {
+        cluster: &cluster,
+        path: &path,
+        data: &data,
+    };
+    match cluster.write_from_reader(&path, &mut reader, &opts) {
+        Err(GlusterError::VerificationFailed { path: failed, offset }) => {
+            assert_eq!(failed, path);
+            assert_eq!(offset, 0);
+        }
+        other => panic!("tampering went unnoticed: {:?}", other),
+    }
+}
+
+#[test]
+fn copy_refuses_to_copy_a_file_onto_itself() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("self-copy");
+    let hard = tmp.child("self-copy-link");
+    cluster.write_file(&path, b"keep me").unwrap();
+    cluster.link(&path, &hard).unwrap();
+
+    let opts = WriteOptions::new();
+    for to in &[&path, &hard] {
+        match cluster.copy(&path, to, &opts) {
+            Err(GlusterError::SameFile { from, to: dest }) => {
+                assert_eq!(from, path);
+                assert_eq!(&dest, *to);
+            }
+            other => panic!("copy onto {} wasn't refused: {:?}", to.display(), other),
+        }
+        assert_eq!(cluster.read_to_vec(&path).unwrap(), b"keep me");
+    }
+}
+
+#[test]
+fn shared_file_positions_stay_consistent() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("shared_positions");
+    let file = cluster.create_file(&path, O_CREAT | O_RDWR | O_TRUNC, S_IRWXU).unwrap();
+    let file = Mutex::new(file);
+    // Each thread owns one 4K region and repeatedly rewrites and rereads it
+    thread::scope(|scope| for region in 0..2u8 {
+        let file = &file;
+        scope.spawn(move || for round in 0..200u32 {
+            let fill = region.wrapping_mul(100).wrapping_add(round as u8);
+            let offset = region as u64 * 4096;
+            let mut file = file.lock().unwrap();
+            file.seek(SeekFrom::Start(offset)).unwrap();
+            file.write_all(&[fill; 4096]).unwrap();
+            file.seek(SeekFrom::Start(offset)).unwrap();
+            let mut read_back = [0; 4096];
+            file.read_exact(&mut read_back).unwrap();
+            assert!(read_back.iter().all(|b| *b == fill));
+        });
+    });
+
+    // Clones share one position, so writes through them on different
+    // threads each land in a range of their own rather than over each
+    // other
+    let path = tmp.child("shared_clones");
+    let file = cluster.create_file(&path, O_CREAT | O_RDWR | O_TRUNC, S_IRWXU).unwrap();
+    let record = 512;
+    let (threads, rounds) = (4u8, 100);
+    thread::scope(|scope| for id in 0..threads {
+        let mut clone = file.try_clone().unwrap();
+        scope.spawn(move || for _ in 0..rounds {
+            assert_eq!(clone.write(&vec![id + 1; record]).unwrap(), record);
+        });
+    });
+    let data = cluster.read_to_vec(&path).unwrap();
+    assert_eq!(data.len(), threads as usize * rounds * record);
+    let mut counts = [0; 4];
+    for chunk in data.chunks(record) {
+        assert!(chunk.iter().all(|b| *b == chunk[0]), "records overlapped");
+        counts[chunk[0] as usize - 1] += 1;
+    }
+    assert_eq!(counts, [rounds; 4]);
+    file.close().unwrap();
+}
+
+#[test]
+fn lock_file_has_a_single_owner() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("lock");
+    let holders = AtomicUsize::new(0);
+    let acquired = AtomicUsize::new(0);
+    let opts = LockOptions::new().backoff(Duration::from_millis(1), Duration::from_millis(20));
+    thread::scope(|scope| for _ in 0..4 {
+        scope.spawn(|| for _ in 0..10 {
+            let guard = cluster.lock_file(&path, &opts).unwrap();
+            assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+            acquired.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(2));
+            holders.fetch_sub(1, Ordering::SeqCst);
+            guard.release().unwrap();
+        });
+    });
+    assert_eq!(acquired.load(Ordering::SeqCst), 40);
+    assert!(!cluster.exists(&path).unwrap());
+}
+
+#[test]
+fn stale_lock_is_taken_over() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("lock");
+    let first = cluster.lock_file(&path, &LockOptions::new()).unwrap();
+    assert!(cluster.try_lock_file(&path, &LockOptions::new()).unwrap().is_none());
+
+    thread::sleep(Duration::from_millis(1500));
+    let opts = LockOptions::new().stale_after(Duration::from_secs(1));
+    let second = cluster.try_lock_file(&path, &opts).unwrap().unwrap();
+    assert!(!first.is_held().unwrap());
+    assert!(second.is_held().unwrap());
+    // The broken holder must not remove the new holder's lock
+    drop(first);
+    assert!(cluster.exists(&path).unwrap());
+    let holder = cluster.lock_holder(&path).unwrap().unwrap();
+    assert_eq!(holder.pid, std::process::id());
+    drop(second);
+    assert!(!cluster.exists(&path).unwrap());
+}
+
+#[test]
+fn rotating_log_keeps_every_record_once() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let opts = LogOptions::new()
+        .max_segment_bytes(1000)
+        .max_segments(3)
+        .framed(true);
+    let mut log = RotatingLogWriter::new(&cluster, &tmp.child("audit.log"), opts).unwrap();
+    // 4 byte length prefix + 96 bytes makes 10 records per segment
+    for i in 0..25 {
+        log.append(format!("{:096}", i).as_bytes()).unwrap();
+    }
+    let segments = log.segments().unwrap();
+    assert_eq!(
+        segments,
+        vec![tmp.child("audit.log.2"), tmp.child("audit.log.1"), tmp.child("audit.log")]
+    );
+    let mut records = Vec::new();
+    for (segment, expected) in segments.iter().zip(&[10, 10, 5]) {
+        let data = cluster.read_to_vec(segment).unwrap();
+        assert!(data.len() <= 1000);
+        let decoded = decode_records(&data);
+        assert_eq!(decoded.trailing_bytes, 0);
+        assert_eq!(decoded.records.len(), *expected);
+        records.extend(decoded.records);
+    }
+    let expected: Vec<Vec<u8>> = (0..25).map(|i| format!("{:096}", i).into_bytes()).collect();
+    assert!(records == expected);
+
+    // Another rotation pushes the oldest segment out
+    for i in 25..35 {
+        log.append(format!("{:096}", i).as_bytes()).unwrap();
+    }
+    let oldest = decode_records(&cluster.read_to_vec(&tmp.child("audit.log.2")).unwrap());
+    assert_eq!(oldest.records[0], format!("{:096}", 10).into_bytes());
+    assert!(!cluster.exists(&tmp.child("audit.log.3")).unwrap());
+}
+
+#[test]
+fn subdir_mount_is_rooted_at_the_subdir() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    cluster.create_dir_all(&tmp.child("tenants/acme"), S_IRWXU).unwrap();
+    let tenant = Gluster::builder(&format!("test/{}/tenants/acme", tmp.path().display()))
+        .server("localhost")
+        .port(24007)
+        .connect()
+        .unwrap();
+    tenant.write_file(&Path::new("/x"), b"tenant data").unwrap();
+    assert_eq!(
+        cluster.read_to_vec(&tmp.child("tenants/acme/x")).unwrap(),
+        b"tenant data".to_vec()
+    );
+    assert!(!tenant.exists(&Path::new("/tenants")).unwrap());
+}
+
+#[test]
+fn tls_reports_missing_certificates() {
+    let result = Gluster::builder("test")
+        .tls(TlsOptions::new().cert("/nonexistent/client.pem"))
+        .connect();
+    let message = match result {
+        Ok(_) => panic!("connected without a certificate"),
+        Err(e) => e.to_string(),
+    };
+    assert!(message.contains("/nonexistent/client.pem"));
+}
+
+#[test]
+// Needs a volume with client.ssl on and certificates in place, named by
+// GFAPI_TLS_VOLUME
+fn tls_connection() {
+    let volume = match std::env::var("GFAPI_TLS_VOLUME") {
+        Ok(volume) => volume,
+        Err(_) => return,
+    };
+    let cluster = Gluster::builder(&volume)
+        .tls(TlsOptions::new())
+        .connect()
+        .unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("/")).unwrap();
+    cluster.write_file(&tmp.child("tls"), b"encrypted").unwrap();
+    assert_eq!(cluster.read_to_vec(&tmp.child("tls")).unwrap(), b"encrypted".to_vec());
+}
+
+#[test]
+fn read_only_connection_blocks_every_mutation() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let file = tmp.child("file");
+    cluster.write_file(&file, b"contents").unwrap();
+    cluster.mkdir(&tmp.child("dir"), S_IRWXU).unwrap();
+
+    let ro = Gluster::builder("test").read_only(true).connect().unwrap();
+    assert!(ro.is_read_only());
+    let opened = ro.open(&file, O_RDONLY).unwrap();
+    let fd = opened.handle().unwrap();
+    let new = tmp.child("new");
+    let times = [timespec { tv_sec: 0, tv_nsec: 0 }, timespec { tv_sec: 0, tv_nsec: 0 }];
+    let results: Vec<(&str, Result<(), GlusterError>)> = vec![
+        ("open O_RDWR", ro.open(&file, O_RDWR).map(|_| ())),
+        ("open O_TRUNC", ro.open(&file, O_RDONLY | O_TRUNC).map(|_| ())),
+        ("open_file O_APPEND", ro.open_file(&file, O_RDONLY | O_APPEND).map(|_| ())),
+        ("create", ro.create(&new, O_CREAT | O_RDWR, S_IRWXU).map(|_| ())),
+        ("create_file", ro.create_file(&new, O_CREAT | O_RDWR, S_IRWXU).map(|_| ())),
+        ("write", ro.write(fd, b"x").map(|_| ())),
+        ("pwrite", ro.pwrite(fd, b"x", 1, 0).map(|_| ())),
+        ("writev", ro.writev(fd, &[b"x"]).map(|_| ())),
+        ("pwritev", ro.pwritev(fd, &[b"x"], 0).map(|_| ())),
+        ("write_file", ro.write_file(&new, b"x")),
+        ("truncate", ro.truncate(&file, 0)),
+        ("ftruncate", ro.ftruncate(fd, 0)),
+        ("symlink", ro.symlink(&file, &new)),
+        ("mknod", ro.mknod(&new, S_IRWXU, 0)),
+        ("mkdir", ro.mkdir(&new, S_IRWXU)),
+        ("create_dir_all", ro.create_dir_all(&new.join("a"), S_IRWXU)),
+        ("unlink", ro.unlink(&file)),
+        ("rmdir", ro.rmdir(&tmp.child("dir"))),
+        ("remove_dir_all", ro.remove_dir_all(&tmp.child("dir"))),
+        ("rename", ro.rename(&file, &new)),
+        ("link", ro.link(&file, &new)),
+        ("setxattr", ro.setxattr(&file, "user.a", b"b", 0)),
+        ("lsetxattr", ro.lsetxattr("user.a", b"b", &file, 0)),
+        ("fsetxattr", ro.fsetxattr(fd, "user.a", b"b", 0)),
+        ("removexattr", ro.removexattr(&file, "user.a")),
+        ("lremovexattr", ro.lremovexattr(&file, "user.a")),
+        ("fremovexattr", ro.fremovexattr(fd, "user.a")),
+        ("fallocate", ro.fallocate(fd, 0, 0, 10)),
+        ("discard", ro.discard(fd, 0, 10)),
+        ("zerofill", ro.zerofill(fd, 0, 10)),
+        ("utimens", ro.utimens(&file, &times)),
+        ("lutimens", ro.lutimens(&file, &times)),
+        ("futimens", ro.futimens(fd, &times)),
+        ("chmod", ro.chmod(&file, S_IRWXU)),
+        ("fchmod", ro.fchmod(fd, S_IRWXU)),
+        ("chown", ro.chown(&file, 0, 0)),
+        ("lchown", ro.lchown(&file, 0, 0)),
+        ("fchown", ro.fchown(fd, 0, 0)),
+    ];
+    for (name, result) in results {
+        match result {
+            Err(GlusterError::ReadOnly) => {}
+            other => panic!("{} was not blocked: {:?}", name, other),
+        }
+    }
+    opened.close().unwrap();
+
+    // Reads still work and nothing changed
+    assert_eq!(ro.read_to_vec(&file).unwrap(), b"contents".to_vec());
+    assert_eq!(ro.list_dir(tmp.path(), 1).unwrap().len(), 2);
+    assert!(!cluster.exists(&new).unwrap());
+}
+
+#[test]
+fn scoped_gluster_refuses_escapes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    cluster.mkdir(&tmp.child("jail"), S_IRWXU).unwrap();
+    cluster.mkdir(&tmp.child("jail/a"), S_IRWXU).unwrap();
+    cluster.write_file(&tmp.child("secret"), b"secret").unwrap();
+    // Symlinks inside the jail pointing out of it
+    cluster.symlink(&Path::new(".."), &tmp.child("jail/up")).unwrap();
+    cluster.symlink(&Path::new("../secret"), &tmp.child("jail/secret_link")).unwrap();
+
+    let jail = cluster.scoped(&tmp.child("jail"));
+    jail.write_file(&Path::new("a/inside"), b"ok").unwrap();
+    jail.rename(&Path::new("a/inside"), &Path::new("moved")).unwrap();
+    jail.link(&Path::new("moved"), &Path::new("a/linked")).unwrap();
+    assert_eq!(jail.read_to_vec(&Path::new("./a/../a/linked")).unwrap(), b"ok".to_vec());
+
+    let escapes: Vec<(&str, Result<(), GlusterError>)> = vec![
+        ("../secret", jail.read_to_vec(&Path::new("../secret")).map(|_| ())),
+        ("a/../../secret", jail.read_to_vec(&Path::new("a/../../secret")).map(|_| ())),
+        ("./../secret", jail.metadata(&Path::new("./../secret")).map(|_| ())),
+        ("absolute", jail.read_to_vec(&tmp.child("secret")).map(|_| ())),
+        ("/", jail.list_dir(&Path::new("/")).map(|_| ())),
+        ("..", jail.list_dir(&Path::new("..")).map(|_| ())),
+        ("up/secret", jail.read_to_vec(&Path::new("up/secret")).map(|_| ())),
+        ("secret_link", jail.read_to_vec(&Path::new("secret_link")).map(|_| ())),
+        ("open secret_link", jail.open_file(&Path::new("secret_link"), O_RDONLY).map(|_| ())),
+        ("write up/new", jail.write_file(&Path::new("up/new"), b"x")),
+        ("rename out", jail.rename(&Path::new("moved"), &Path::new("../moved"))),
+        ("rename in", jail.rename(&Path::new("../secret"), &Path::new("stolen"))),
+        ("link in", jail.link(&Path::new("a/../../secret"), &Path::new("stolen"))),
+        ("unlink", jail.unlink(&Path::new("a/../../secret"))),
+        ("mkdir", jail.mkdir(&Path::new("../escaped"), S_IRWXU)),
+        ("symlink out", jail.symlink(&Path::new("../../secret"), &Path::new("a/out"))),
+        ("absolute symlink", jail.symlink(&Path::new("/etc/passwd"), &Path::new("pw"))),
+        ("remove root", jail.remove_dir_all(&Path::new("a/.."))),
+    ];
+    for (name, result) in escapes {
+        match result {
+            Err(GlusterError::EscapesRoot { .. }) => {}
+            other => panic!("{} was not refused: {:?}", name, other),
+        }
+    }
+    assert_eq!(cluster.read_to_vec(&tmp.child("secret")).unwrap(), b"secret".to_vec());
+    assert!(!cluster.exists(&tmp.child("escaped")).unwrap());
+    // A relative symlink that stays inside is fine
+    jail.symlink(&Path::new("../moved"), &Path::new("a/back")).unwrap();
+}
+
+#[test]
+fn writes_fail_fast_without_free_space() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let space = cluster.free_space(tmp.path()).unwrap();
+    assert!(space.available <= space.total);
+
+    let path = tmp.child("big");
+    for requirement in &[
+        FreeSpaceRequirement::Exact(space.total + 1),
+        FreeSpaceRequirement::Percent(100.5),
+    ] {
+        let opts = WriteOptions::new().require_free_space(*requirement);
+        match cluster.write_file_with(&path, b"data", &opts) {
+            Err(GlusterError::InsufficientSpace { needed, available }) => {
+                assert!(needed > available)
+            }
+            other => panic!("{:?} was not rejected: {:?}", requirement, other),
+        }
+        // Nothing was created
+        assert!(!cluster.exists(&path).unwrap());
+    }
+
+    let opts = WriteOptions::new()
+        .require_free_space(FreeSpaceRequirement::SizeOfInput)
+        .recheck_free_space_every(64 * 1024)
+        .chunk_size(16 * 1024);
+    let data = vec![7; 256 * 1024];
+    let mut reader: &[u8] = &data;
+    assert_eq!(
+        cluster.write_from_reader(&path, &mut reader, &opts).unwrap(),
+        data.len() as u64
+    );
+    assert_eq!(cluster.copy(&path, &tmp.child("copy"), &opts).unwrap(), data.len() as u64);
+}
+
+#[test]
+fn upload_resumes_after_a_crash() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let dest = tmp.child("upload");
+    let data: Vec<u8> = (0..1024 * 1024).map(|i: usize| (i % 253) as u8).collect();
+    let part = 128 * 1024;
+    let opts = UploadOptions::new()
+        .total_len(data.len() as u64)
+        .crc32c(Crc32c::checksum(&data));
+
+    let mut upload = cluster.upload(&dest, &opts).unwrap();
+    for i in &[0, 1, 2, 5] {
+        upload.write_part((i * part) as u64, &data[i * part..(i + 1) * part]).unwrap();
+    }
+    // Crash halfway through
+    drop(upload);
+    assert!(!cluster.exists(&dest).unwrap());
+    let pending = cluster.pending_uploads(tmp.path()).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].dest, dest);
+    assert_eq!(pending[0].bytes_written, 4 * part as u64);
+
+    let mut upload = Upload::resume(&cluster, &dest).unwrap();
+    assert_eq!(
+        upload.missing_ranges(),
+        vec![(3 * part as u64, 5 * part as u64), (6 * part as u64, data.len() as u64)]
+    );
+    for (start, end) in upload.missing_ranges() {
+        upload.write_part(start, &data[start as usize..end as usize]).unwrap();
+    }
+    assert_eq!(upload.finish().unwrap(), data.len() as u64);
+    assert!(cluster.read_to_vec(&dest).unwrap() == data);
+    assert!(cluster.pending_uploads(tmp.path()).unwrap().is_empty());
+
+    // Abandoned sessions can be cleaned up
+    let mut abandoned = cluster.upload(&tmp.child("abandoned"), &UploadOptions::new()).unwrap();
+    abandoned.write_part(0, b"partial").unwrap();
+    drop(abandoned);
+    assert_eq!(cluster.clean_uploads(tmp.path(), Duration::from_secs(0)).unwrap(), 1);
+    assert_eq!(cluster.list_dir(tmp.path(), 1).unwrap().len(), 1);
+}
+
+#[test]
+fn download_resumes_without_refetching() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let src = tmp.child("remote");
+    let data: Vec<u8> = (0..1024 * 1024 + 100).map(|i: usize| (i % 241) as u8).collect();
+    cluster.write_file(&src, &data).unwrap();
+    let local = std::env::temp_dir().join(format!("gfapi-download-{}", std::process::id()));
+    let chunk = 64 * 1024;
+    let opts = DownloadOptions::new()
+        .chunk_size(chunk)
+        .verify(true)
+        .on_change(RemoteChanged::Error);
+
+    // Interrupt once 300K have arrived
+    let first = cluster
+        .download_with_progress(&src, &local, &opts, |p| p.downloaded < 300 * 1024)
+        .unwrap();
+    assert!(!first.complete);
+    let interrupted_at = first.transferred;
+    assert!(interrupted_at >= 300 * 1024 && interrupted_at < 300 * 1024 + chunk as u64);
+
+    let mut calls = 0;
+    let second = cluster
+        .download_with_progress(&src, &local, &opts, |_| {
+            calls += 1;
+            true
+        })
+        .unwrap();
+    assert!(second.complete);
+    assert_eq!(second.resumed_from, interrupted_at);
+    assert_eq!(second.transferred, data.len() as u64 - interrupted_at);
+    assert!(calls > 0);
+    assert!(std::fs::read(&local).unwrap() == data);
+    assert!(!Path::new(&format!("{}.download-state", local.display())).exists());
+    std::fs::remove_file(&local).unwrap();
+}
+
+#[test]
+fn local_file_round_trips_through_volume() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let local = std::env::temp_dir().join(format!("gfapi-transfer-{}", std::process::id()));
+    let back = std::env::temp_dir().join(format!("gfapi-transfer-back-{}", std::process::id()));
+    // 64MB with a zeroed stretch in the middle and a short tail
+    let mut data: Vec<u8> = (0..64 * 1024 * 1024 + 10)
+        .map(|i: usize| (i % 239) as u8)
+        .collect();
+    for b in &mut data[16 * 1024 * 1024..24 * 1024 * 1024] {
+        *b = 0;
+    }
+    std::fs::write(&local, &data).unwrap();
+    let expected = ChecksumAlgorithm::Sha256.digest(&data);
+
+    let remote = tmp.child("transfer");
+    let opts = LocalTransferOptions::new().verify(true);
+    let mut calls = 0;
+    let up = cluster
+        .upload_local_file_with_progress(&local, &remote, &opts, |p| {
+            calls += 1;
+            assert_eq!(p.total, data.len() as u64);
+            true
+        })
+        .unwrap();
+    assert!(up.complete);
+    assert_eq!(up.transferred, data.len() as u64);
+    assert_eq!(up.holes, 0);
+    assert_eq!(calls, 17);
+    let mut remote_file = cluster.open_file(&remote, O_RDONLY).unwrap();
+    assert_eq!(
+        ChecksumAlgorithm::Sha256
+            .digest_reader(&mut remote_file)
+            .unwrap(),
+        expected
+    );
+
+    let sparse = LocalTransferOptions::new().sparse(true).verify(true);
+    let down = cluster
+        .download_to_local_file(&remote, &back, &sparse)
+        .unwrap();
+    assert!(down.complete);
+    assert_eq!(down.holes, 8 * 1024 * 1024);
+    let mut back_file = std::fs::File::open(&back).unwrap();
+    assert_eq!(
+        ChecksumAlgorithm::Sha256
+            .digest_reader(&mut back_file)
+            .unwrap(),
+        expected
+    );
+
+    std::fs::remove_file(&local).unwrap();
+    std::fs::remove_file(&back).unwrap();
+}
+
+#[test]
+fn manifest_diff_finds_the_edited_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("tree");
+    cluster.create_dir_all(&root.join("a/b"), 0o755).unwrap();
+    cluster.mkdir(&root.join("empty"), 0o755).unwrap();
+    let files = ["top", "a/one", "a/two", "a/b/three"];
+    for (i, name) in files.iter().enumerate() {
+        cluster
+            .write_file(&root.join(name), &vec![i as u8; 1000 * i])
+            .unwrap();
+    }
+    cluster
+        .symlink(Path::new("top"), &root.join("link"))
+        .unwrap();
+
+    let progress = Arc::new(AtomicUsize::new(0));
+    let seen = progress.clone();
+    let opts = ManifestOptions::new().workers(3).on_progress(move |_| {
+        seen.fetch_add(1, Ordering::SeqCst);
+    });
+    let before = cluster.manifest(&root, &opts).unwrap();
+    let paths: Vec<PathBuf> = before.entries().iter().map(|e| e.path.clone()).collect();
+    let expected: Vec<PathBuf> = ["a/b/three", "a/one", "a/two", "top"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(paths, expected);
+    assert_eq!(progress.load(Ordering::SeqCst), 4);
+    assert_eq!(
+        before.get(Path::new("top")).unwrap().sha256,
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(before.get(Path::new("a/two")).unwrap().size, 2000);
+
+    cluster.write_file(&root.join("a/one"), b"edited").unwrap();
+    let after = cluster.manifest(&root, &opts).unwrap();
+    let diff = before.diff(&after);
+    assert_eq!(diff.changed, vec![PathBuf::from("a/one")]);
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+}
+
+#[test]
+fn sync_tree_copies_only_what_changed() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let local = std::env::temp_dir().join(format!("gfapi-sync-tree-{}", std::process::id()));
+    std::fs::create_dir_all(local.join("sub")).unwrap();
+    for name in &["one", "two", "three", "sub/four"] {
+        std::fs::write(local.join(name), name.as_bytes()).unwrap();
+    }
+    let dest = tmp.child("mirror");
+    let opts = TreeSyncOptions::new().delete_extraneous(true);
+
+    let first = cluster
+        .sync_tree(SourceTree::Local(&local), &dest, &opts)
+        .unwrap();
+    assert_eq!(first.actions.len(), 4);
+    assert_eq!(
+        cluster.read_to_vec(&dest.join("sub/four")).unwrap(),
+        b"sub/four".to_vec()
+    );
+    assert!(cluster.exists(&dest.join(SYNC_MANIFEST)).unwrap());
+
+    std::fs::write(local.join("one"), b"one, longer now").unwrap();
+    std::fs::write(local.join("sub/four"), b"FOUR").unwrap();
+    std::fs::remove_file(local.join("two")).unwrap();
+    let expected = vec![
+        SyncAction::Update {
+            path: PathBuf::from("one"),
+        },
+        SyncAction::Update {
+            path: PathBuf::from("sub/four"),
+        },
+        SyncAction::Delete {
+            path: PathBuf::from("two"),
+        },
+    ];
+
+    let planned = cluster
+        .sync_tree(
+            SourceTree::Local(&local),
+            &dest,
+            &opts.clone().dry_run(true),
+        )
+        .unwrap();
+    assert_eq!(planned.actions, expected);
+    assert!(cluster.exists(&dest.join("two")).unwrap());
+
+    let second = cluster
+        .sync_tree(SourceTree::Local(&local), &dest, &opts)
+        .unwrap();
+    assert_eq!(second.actions, expected);
+    assert_eq!(second.unchanged, 1);
+    assert!(!cluster.exists(&dest.join("two")).unwrap());
+    assert_eq!(
+        cluster.read_to_vec(&dest.join("one")).unwrap(),
+        b"one, longer now".to_vec()
+    );
+
+    // Checksumming the destination instead of trusting the stored
+    // manifest finds nothing left to do either
+    let fresh = opts.clone().trust_stored_manifest(false);
+    let third = cluster
+        .sync_tree(SourceTree::Local(&local), &dest, &fresh)
+        .unwrap();
+    assert!(third.actions.is_empty());
+    std::fs::remove_dir_all(&local).unwrap();
+}
+
+#[test]
+fn mirror_to_local_downloads_only_what_changed() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let remote = tmp.child("tree");
+    cluster
+        .create_dir_all(&remote.join("sub/deeper"), defaults::DIR_0755)
+        .unwrap();
+    for name in &["one", "two", "sub/three", "sub/deeper/four"] {
+        cluster
+            .write_file(&remote.join(name), name.as_bytes())
+            .unwrap();
+    }
+    cluster
+        .symlink(Path::new("sub/three"), &remote.join("link"))
+        .unwrap();
+    let local = std::env::temp_dir().join(format!("gfapi-mirror-{}", std::process::id()));
+    let opts = MirrorOptions::new().delete_extraneous(true);
+
+    let first = cluster.mirror_to_local(&remote, &local, &opts).unwrap();
+    assert_eq!(first.downloaded.len(), 4);
+    assert_eq!(first.symlinks, vec![PathBuf::from("link")]);
+    assert_eq!(
+        std::fs::read(local.join("sub/deeper/four")).unwrap(),
+        b"sub/deeper/four".to_vec()
+    );
+    assert_eq!(
+        std::fs::read_link(local.join("link")).unwrap(),
+        PathBuf::from("sub/three")
+    );
+
+    cluster
+        .write_file(&remote.join("sub/three"), b"three, but longer")
+        .unwrap();
+    std::fs::write(local.join("stray"), b"not on the volume").unwrap();
+    let second = cluster.mirror_to_local(&remote, &local, &opts).unwrap();
+    assert_eq!(second.downloaded, vec![PathBuf::from("sub/three")]);
+    assert_eq!(second.unchanged, 3);
+    assert!(second.symlinks.is_empty());
+    assert_eq!(second.deleted, vec![PathBuf::from("stray")]);
+    assert_eq!(
+        std::fs::read(local.join("sub/three")).unwrap(),
+        b"three, but longer".to_vec()
+    );
+
+    // Nothing left to do, even comparing checksums
+    let third = cluster
+        .mirror_to_local(&remote, &local, &opts.clone().checksum(true))
+        .unwrap();
+    assert!(third.downloaded.is_empty());
+    assert_eq!(third.unchanged, 4);
+    std::fs::remove_dir_all(&local).unwrap();
+}
+
+#[test]
+fn upload_tree_skips_excluded_and_unchanged_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let local = std::env::temp_dir().join(format!("gfapi-upload-tree-{}", std::process::id()));
+    std::fs::create_dir_all(local.join("a/b")).unwrap();
+    std::fs::create_dir_all(local.join("build")).unwrap();
+    for name in &["top", "a/one", "a/b/two", "a/b/scratch.tmp", "build/out"] {
+        std::fs::write(local.join(name), name.as_bytes()).unwrap();
+    }
+    std::fs::set_permissions(local.join("a/b"), std::fs::Permissions::from_mode(0o750)).unwrap();
+    std::os::unix::fs::symlink("b/two", local.join("a/link")).unwrap();
+    let remote = tmp.child("tree");
+    let opts = TreeUploadOptions::new()
+        .exclude(Glob::new("*.tmp").unwrap())
+        .exclude(Glob::new("build").unwrap())
+        .workers(2);
+
+    let first = cluster.upload_tree(&local, &remote, &opts).unwrap();
+    assert!(first.is_ok());
+    assert_eq!(first.uploaded.len(), 3);
+    assert_eq!(first.excluded, 2);
+    assert_eq!(first.symlinks, vec![PathBuf::from("a/link")]);
+
+    let mut found: Vec<(PathBuf, Option<FileType>)> = cluster
+        .walk(&remote, &WalkOptions::new())
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.depth > 0)
+        .map(|entry| {
+            let relative = entry.path.strip_prefix(&remote).unwrap().to_path_buf();
+            (relative, entry.metadata.file_type())
+        })
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    let expected: Vec<(PathBuf, Option<FileType>)> = vec![
+        ("a", FileType::Dir),
+        ("a/b", FileType::Dir),
+        ("a/b/two", FileType::File),
+        ("a/link", FileType::Symlink),
+        ("a/one", FileType::File),
+        ("top", FileType::File),
+    ]
+    .into_iter()
+    .map(|(path, file_type)| (PathBuf::from(path), Some(file_type)))
+    .collect();
+    assert_eq!(found, expected);
+    assert_eq!(
+        cluster.metadata(&remote.join("a/b")).unwrap().permissions(),
+        0o750
+    );
+    assert_eq!(
+        cluster.read_to_vec(&remote.join("a/b/two")).unwrap(),
+        b"a/b/two".to_vec()
+    );
+    assert_eq!(
+        cluster.read_link(&remote.join("a/link")).unwrap(),
+        PathBuf::from("b/two")
+    );
+
+    std::fs::write(local.join("a/one"), b"one, changed").unwrap();
+    let second = cluster.upload_tree(&local, &remote, &opts).unwrap();
+    assert_eq!(second.uploaded, vec![PathBuf::from("a/one")]);
+    assert_eq!(second.unchanged, 2);
+    assert!(second.symlinks.is_empty());
+    assert_eq!(second.dirs_created, 0);
+    std::fs::remove_dir_all(&local).unwrap();
+}
+
+#[test]
+fn restore_tree_reproduces_a_snapshot_exactly() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let source = tmp.child("source");
+    cluster
+        .create_dir_all(&source.join("a/empty"), defaults::DIR_0755)
+        .unwrap();
+    cluster
+        .mkdir(&source.join("b"), defaults::DIR_0755)
+        .unwrap();
+    cluster
+        .write_file(&source.join("a/data"), b"some data")
+        .unwrap();
+    cluster.write_file(&source.join("a/nothing"), b"").unwrap();
+    cluster.chmod(&source.join("a/data"), 0o640).unwrap();
+    cluster
+        .setxattr(&source.join("a/data"), "user.origin", b"fidelity", 0)
+        .unwrap();
+    cluster
+        .link(&source.join("a/data"), &source.join("b/also-data"))
+        .unwrap();
+    cluster
+        .symlink(Path::new("../a/data"), &source.join("b/to-data"))
+        .unwrap();
+    cluster
+        .symlink(Path::new("nowhere"), &source.join("b/dangling"))
+        .unwrap();
+    cluster
+        .mknod(&source.join("b/pipe"), S_IFIFO | 0o600, 0)
+        .unwrap();
+    let old = timespec {
+        tv_sec: 1_000_000_000,
+        tv_nsec: 123_456_789,
+    };
+    cluster
+        .utimens(&source.join("a/data"), &[old, old])
+        .unwrap();
+    cluster.chmod(&source.join("a/empty"), 0o555).unwrap();
+    cluster.chmod(&source.join("a"), 0o750).unwrap();
+    cluster.utimens(&source.join("a"), &[old, old]).unwrap();
+
+    let image = cluster.snapshot_tree(&source).unwrap();
+    assert_eq!(
+        image.link_groups(),
+        vec![vec![PathBuf::from("a/data"), PathBuf::from("b/also-data")]]
+    );
+
+    // Restore through a second connection, reading from the first
+    let other = Gluster::connect("test", "localhost", 24007).unwrap();
+    let dest = tmp.child("restored");
+    let report = other
+        .restore_tree(&image, &dest, &RestoreOptions::new().source(&cluster))
+        .unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(report.hard_links, 1);
+    assert_eq!(report.symlinks, 2);
+    assert_eq!(report.special, 1);
+    assert_eq!(report.bytes, 9);
+    assert!(other
+        .same_file(&dest.join("a/data"), &dest.join("b/also-data"))
+        .unwrap());
+
+    // Everything but atimes, which reading the files to snapshot them
+    // can move, and inode numbers, which can't be the same
+    fn comparable(image: &TreeImage) -> TreeImage {
+        let mut image = image.clone();
+        image.root = PathBuf::new();
+        for entry in &mut image.entries {
+            entry.atime = 0;
+            entry.atime_nsec = 0;
+            if let ImageKind::File {
+                ref mut link_group, ..
+            } = entry.kind
+            {
+                *link_group = link_group.map(|_| (0, 0));
+            }
+        }
+        image
+    }
+    let restored = other.snapshot_tree(&dest).unwrap();
+    assert_eq!(comparable(&restored), comparable(&image));
+    assert_eq!(restored.link_groups(), image.link_groups());
+
+    match other.restore_tree(&image, &dest, &RestoreOptions::new().source(&cluster)) {
+        Err(GlusterError::AlreadyExists { path }) => assert_eq!(path, dest),
+        result => panic!("expected AlreadyExists, got {:?}", result),
+    }
+}
+
+#[test]
+fn cat_joins_files_in_order() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let parts: Vec<PathBuf> = (0..3)
+        .map(|i| tmp.child(&format!("part-{:04}", i)))
+        .collect();
+    cluster.write_file(&parts[0], b"first").unwrap();
+    cluster.write_file(&parts[1], b"").unwrap();
+    cluster.write_file(&parts[2], b"third").unwrap();
+
+    let mut out = Vec::new();
+    let report = cluster
+        .cat(&parts, &mut out, &CatOptions::new().chunk_size(2))
+        .unwrap();
+    assert_eq!(out, b"firstthird".to_vec());
+    assert_eq!(report.bytes, 10);
+    assert_eq!(
+        report
+            .files
+            .iter()
+            .map(|file| file.bytes)
+            .collect::<Vec<u64>>(),
+        vec![5, 0, 5]
+    );
+
+    let mut with_missing = parts.clone();
+    with_missing.insert(1, tmp.child("part-missing"));
+    let mut out = Vec::new();
+    match cluster.cat(&with_missing, &mut out, &CatOptions::new()) {
+        Err(_) => assert_eq!(out, b"first".to_vec()),
+        Ok(report) => panic!("a missing input was accepted: {:?}", report),
+    }
+
+    let mut out = Vec::new();
+    let opts = CatOptions::new().separator(b"\n").ignore_missing(true);
+    let report = cluster.cat(&with_missing, &mut out, &opts).unwrap();
+    assert_eq!(out, b"first\n\nthird".to_vec());
+    assert_eq!(report.bytes, 12);
+    assert_eq!(
+        report.files[1],
+        CatFile {
+            path: tmp.child("part-missing"),
+            bytes: 0,
+            missing: true,
+        }
+    );
+}
+
+#[test]
+fn split_and_join_round_trip_a_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let src = tmp.child("fixture.bin");
+    let data: Vec<u8> = (0..32 * 1024 * 1024u32)
+        .map(|i| (i * 31 % 251) as u8)
+        .collect();
+    cluster.write_file(&src, &data).unwrap();
+    let digest_of = |path: &Path| {
+        let mut file = cluster.open_file(path, O_RDONLY).unwrap();
+        ChecksumAlgorithm::Sha256.digest_reader(&mut file).unwrap()
+    };
+    let parts_dir = tmp.child("parts");
+    let part_size = 5 * 1024 * 1024;
+    let opts = SplitOptions::new();
+
+    let parts = cluster.split(&src, &parts_dir, part_size, &opts).unwrap();
+    assert_eq!(parts.len(), 7);
+    assert_eq!(parts[0], parts_dir.join("fixture.bin.part0000"));
+    assert_eq!(cluster.metadata(&parts[6]).unwrap().len(), 2 * 1024 * 1024);
+
+    // A lost part is written again, the rest are kept
+    cluster.unlink(&parts[6]).unwrap();
+    let again = cluster.split(&src, &parts_dir, part_size, &opts).unwrap();
+    assert_eq!(again, parts);
+
+    let manifest = parts_manifest_path(&src, &parts_dir);
+    let joined = tmp.child("joined.bin");
+    assert_eq!(
+        cluster.join(&manifest, &joined, &opts).unwrap(),
+        data.len() as u64
+    );
+    assert_eq!(digest_of(&joined), digest_of(&src));
+    match cluster.join(&manifest, &joined, &opts) {
+        Err(GlusterError::AlreadyExists { .. }) => {}
+        other => panic!("join overwrote its destination: {:?}", other),
+    }
+
+    // An interrupted join is cut back to its last whole part
+    let resumed = tmp.child("resumed.bin");
+    cluster
+        .write_file(&resumed, &data[..12 * 1024 * 1024])
+        .unwrap();
+    cluster
+        .write_file(&tmp.child(".resumed.bin.join-progress"), b"2\n")
+        .unwrap();
+    cluster.join(&manifest, &resumed, &opts).unwrap();
+    assert_eq!(digest_of(&resumed), digest_of(&src));
+
+    // A damaged part is caught
+    cluster
+        .write_file(&parts[3], &vec![0; part_size as usize])
+        .unwrap();
+    match cluster.join(&manifest, &tmp.child("bad.bin"), &opts) {
+        Err(GlusterError::VerificationFailed { path, .. }) => assert_eq!(path, parts[3]),
+        other => panic!("a damaged part was joined: {:?}", other),
+    }
+}
+
+#[test]
+fn find_duplicates_groups_identical_files() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("dupes");
+    cluster.mkdir(&root, 0o755).unwrap();
+    cluster.mkdir(&root.join("sub"), 0o755).unwrap();
+    let big: Vec<u8> = (0..1024 * 1024).map(|i| (i % 253) as u8).collect();
+    // Same length and ends, different in the middle
+    let mut near = big.clone();
+    near[512 * 1024] ^= 1;
+    let small = b"small and duplicated".to_vec();
+    cluster.write_file(&root.join("big-1"), &big).unwrap();
+    cluster.write_file(&root.join("sub/big-2"), &big).unwrap();
+    cluster.write_file(&root.join("near"), &near).unwrap();
+    cluster.write_file(&root.join("small-1"), &small).unwrap();
+    cluster.write_file(&root.join("small-2"), &small).unwrap();
+    cluster.write_file(&root.join("small-3"), &small).unwrap();
+    cluster.write_file(&root.join("empty-1"), b"").unwrap();
+    cluster.write_file(&root.join("empty-2"), b"").unwrap();
+    // Another name for big-1 frees nothing
+    cluster
+        .link(&root.join("big-1"), &root.join("sub/big-link"))
+        .unwrap();
+
+    let before = cluster.symlink_metadata(&root.join("small-1")).unwrap();
+    let mut report = cluster
+        .find_duplicates(&root, &DuplicateOptions::new().workers(2))
+        .unwrap();
+    assert_eq!(report.files_scanned, 6);
+    assert_eq!(report.hard_links, 1);
+    assert_eq!(report.groups.len(), 2);
+    assert_eq!(
+        report.groups[0].paths,
+        vec![root.join("big-1"), root.join("sub/big-2")]
+    );
+    assert_eq!(report.groups[0].reclaimable(), big.len() as u64);
+    assert_eq!(
+        report.groups[1].paths,
+        vec![
+            root.join("small-1"),
+            root.join("small-2"),
+            root.join("small-3")
+        ]
+    );
+    assert_eq!(report.groups[1].reclaimable(), 2 * small.len() as u64);
+    assert_eq!(
+        report.reclaimable,
+        big.len() as u64 + 2 * small.len() as u64
+    );
+    report.sort_by_reclaimable();
+    assert_eq!(report.groups[0].size, big.len() as u64);
+    // Nothing was changed
+    let after = cluster.symlink_metadata(&root.join("small-1")).unwrap();
+    assert_eq!(after.nlink(), before.nlink());
+
+    let with_empty = cluster
+        .find_duplicates(&root, &DuplicateOptions::new().min_size(0))
+        .unwrap();
+    assert_eq!(with_empty.groups.len(), 3);
+    assert_eq!(with_empty.groups[0].paths[0], root.join("big-1"));
+    assert_eq!(with_empty.groups[1].paths[0], root.join("empty-1"));
+}
+
+#[test]
+fn dropped_files_are_closed() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("opened-often");
+    cluster.write_file(&path, b"0123456789").unwrap();
+    // Each of these would leave an fd open on the bricks if drop leaked it
+    for _ in 0..5000 {
+        let file = cluster.open_file(&path, O_RDONLY).unwrap();
+        assert!(!file.is_closed());
+    }
+
+    let file = cluster.open_file(&path, O_RDWR).unwrap();
+    file.set_len(4).unwrap();
+    assert_eq!(file.fstat().unwrap().st_size, 4);
+    file.set_len(6).unwrap();
+    // close consumes the file, so it can't be closed twice
+    file.close().unwrap();
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"0123\0\0".to_vec());
+}
+
+#[test]
+fn dropping_a_directory_closes_it() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let dir = tmp.child("many-entries");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    for i in 0..200 {
+        cluster
+            .write_file(&dir.join(format!("entry-{:03}", i)), b"")
+            .unwrap();
+    }
+    // Each of these used to leave its handle open by stopping early
+    for _ in 0..2000 {
+        let d = cluster.opendir(&dir).unwrap();
+        assert_eq!(d.take(5).count(), 5);
+    }
+    // Iterating to the end and then dropping closes it only once
+    let mut d = cluster.opendir(&dir).unwrap();
+    let names = d
+        .by_ref()
+        .filter(|entry| entry.path != Path::new(".") && entry.path != Path::new(".."))
+        .count();
+    assert_eq!(names, 200);
+    assert!(d.next().is_none());
+    drop(d);
+    let plus = cluster.opendir(&dir).unwrap().plus();
+    assert_eq!(plus.take(3).count(), 3);
+}
+
+#[test]
+fn opening_a_missing_file_fails() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    match cluster.open(&tmp.child("not-there"), O_RDONLY) {
+        Err(e) => assert!(
+            e.to_string().contains("No such file or directory"),
+            "unexpected error {}",
+            e
+        ),
+        Ok(_) => panic!("opening a missing file succeeded"),
+    };
+}
+
+#[test]
+fn opendir_reports_why_it_failed() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    match cluster.opendir(&tmp.child("not-there")) {
+        Err(e) => assert!(
+            e.to_string().contains("No such file or directory"),
+            "unexpected error {}",
+            e
+        ),
+        Ok(_) => panic!("opening a missing directory succeeded"),
+    }
+    let file = tmp.child("plain-file");
+    cluster.write_file(&file, b"not a directory").unwrap();
+    match cluster.opendir(&file) {
+        Err(e) => assert!(
+            e.to_string().contains("Not a directory"),
+            "unexpected error {}",
+            e
+        ),
+        Ok(_) => panic!("opening a regular file as a directory succeeded"),
+    };
+}
+
+#[test]
+fn cloned_files_share_the_file_and_close_separately() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("cloned");
+    cluster.write_file(&path, b"").unwrap();
+    let mut file = cluster.open_file(&path, O_RDWR).unwrap();
+    let mut clone = file.try_clone().unwrap();
+    assert_eq!(clone.path(), file.path());
+
+    file.write_all(b"written through the original").unwrap();
+    file.fsync().unwrap();
+    // The write moved the clone's position too, like dup(2)
+    assert_eq!(clone.stream_position().unwrap(), 28);
+    file.seek(SeekFrom::Start(8)).unwrap();
+    let mut contents = String::new();
+    clone.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "through the original");
+    assert_eq!(file.stream_position().unwrap(), 28);
+
+    // Closing one leaves the other usable
+    file.close().unwrap();
+    clone.write_all_at(b"WRITTEN", 0).unwrap();
+    clone.close().unwrap();
+    assert_eq!(
+        cluster.read_to_vec(&path).unwrap(),
+        b"WRITTEN through the original".to_vec()
+    );
+}
+
+#[test]
+fn disconnect_then_drop_finalizes_once() {
+    // disconnect consumes the connection and Drop then runs on it, which
+    // used to hand glfs_fini the same handle twice
+    for _ in 0..3 {
+        let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+        cluster.get_volume_id().unwrap();
+        cluster.disconnect().unwrap();
+    }
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    assert!(cluster.get_volume_id().is_ok());
+}
+
+#[test]
+fn disconnect_returns_the_fini_result() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = PathBuf::from(format!("gfapi/leaked-{}", std::process::id()));
+    cluster.create_dir_all(&tmp, 0o755).unwrap();
+    let path = tmp.join("leaked");
+    cluster.write_file(&path, b"leaked").unwrap();
+    // Leak the fd so glfs_fini has something left to tear down
+    std::mem::forget(cluster.open_file(&path, O_RDONLY).unwrap());
+    // Whatever fini said comes back here rather than going to the drop
+    // error handler
+    if let Err(e) = cluster.disconnect() {
+        assert!(!e.to_string().is_empty());
+    }
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.unlink(&path).unwrap();
+    cluster.rmdir(&tmp).unwrap();
+}
+
+#[test]
+fn shared_connections_work_from_many_threads() {
+    let cluster = Gluster::connect("test", "localhost", 24007)
+        .unwrap()
+        .shared();
+    let path = PathBuf::from(format!("gfapi/shared-{}", std::process::id()));
+    cluster.write_file(&path, b"shared").unwrap();
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let cluster = cluster.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    assert_eq!(cluster.stat(&path).unwrap().st_size, 6);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(Arc::strong_count(&cluster), 1);
+    cluster.unlink(&path).unwrap();
+    // The last clone finalizes the connection, and only it
+    drop(cluster);
+}
+
+#[test]
+fn raw_handles_round_trip() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let config = cluster.config().clone();
+    let volume_id = cluster.get_volume_id().unwrap();
+    let raw = cluster.into_raw();
+    assert!(!raw.is_null());
+    let cluster = unsafe { Gluster::from_raw(raw, &config) };
+    assert_eq!(cluster.as_raw(), raw);
+    assert_eq!(cluster.get_volume_id().unwrap(), volume_id);
+
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("raw-fd");
+    cluster.write_file(&path, b"raw").unwrap();
+    let file = cluster.open(&path, O_RDONLY).unwrap();
+    let fd = file.into_raw_fd();
+    let mut file = unsafe { GlusterFile::from_raw_fd(&cluster, fd, O_RDONLY, &path) };
+    assert_eq!(file.as_raw_fd(), fd);
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "raw");
+    file.close().unwrap();
+}
+
+#[test]
+fn files_and_directories_have_their_own_handle_types() {
+    fn is_dir<F: GlusterFd>(fd: &F) -> bool {
+        fd.fstat().unwrap().st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("typed");
+    cluster.write_file(&path, b"typed").unwrap();
+    let file = cluster.open(&path, O_RDONLY).unwrap();
+    let dir = cluster.opendir(tmp.path()).unwrap();
+    assert!(!is_dir(&file));
+    assert!(is_dir(&dir));
+
+    cluster.fchdir(&dir).unwrap();
+    assert_eq!(cluster.stat(&Path::new("typed")).unwrap().st_size, 5);
+    assert!(is_dir(&dir.plus()));
+}
+
+#[test]
+fn builder_xlator_options_follow_the_tuning_profile() {
+    let cluster = Gluster::builder("test")
+        .server("localhost")
+        .port(24007)
+        .transport("tcp")
+        .tuning(TuningProfile::SmallFiles)
+        .xlator_option("*-write-behind", "cache-size", "4MB")
+        .connect()
+        .unwrap();
+    let applied = cluster.applied_xlator_options();
+    assert_eq!(
+        applied.last(),
+        Some(&XlatorOption::new("*-write-behind", "cache-size", "4MB"))
+    );
+    assert_eq!(
+        applied.len(),
+        TuningProfile::SmallFiles.options().unwrap().len() + 1
+    );
+}
+
+#[test]
+fn volfile_servers_can_be_added_and_removed() {
+    let cluster = Gluster::builder("test")
+        .server("localhost")
+        .backup_server("127.0.0.1", 24007)
+        .connect()
+        .unwrap();
+    let server = |host: &str| VolfileServer {
+        host: host.to_string(),
+        port: 24007,
+    };
+    assert_eq!(
+        cluster.volfile_servers(),
+        vec![server("localhost"), server("127.0.0.1")]
+    );
+
+    cluster
+        .add_volfile_server("decommissioned.invalid", 24007, "tcp")
+        .unwrap();
+    assert_eq!(cluster.volfile_servers().len(), 3);
+    cluster
+        .remove_volfile_server("decommissioned.invalid", 24007, "tcp")
+        .unwrap();
+    cluster.remove_volfile_server("127.0.0.1", 24007, "tcp").unwrap();
+    assert_eq!(cluster.volfile_servers(), vec![server("localhost")]);
+
+    // gfapi's error, not a panic, for one that was never added
+    assert!(cluster
+        .remove_volfile_server("never-added.invalid", 24007, "tcp")
+        .is_err());
+    assert_eq!(cluster.volfile_servers(), vec![server("localhost")]);
+    assert!(cluster.get_volume_id().is_ok());
+}
+
+#[test]
+fn reconnect_replaces_the_connection() {
+    let mut cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let path = PathBuf::from(format!("gfapi/reconnect-{}", std::process::id()));
+    cluster.write_file(&path, b"before").unwrap();
+    let old_handle = cluster.as_raw();
+
+    cluster.reconnect().unwrap();
+    assert!(!cluster.as_raw().is_null());
+    assert_ne!(cluster.as_raw(), old_handle);
+    assert_eq!(cluster.stat(&path).unwrap().st_size, 6);
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"before".to_vec());
+    cluster.unlink(&path).unwrap();
+}
+
+#[test]
+fn watch_capacity_outlives_the_callers_handle() {
+    let cluster = Gluster::connect("test", "localhost", 24007)
+        .unwrap()
+        .shared();
+    let watcher = cluster.watch_capacity(Duration::from_millis(10), &[0.0]);
+    // The watcher's clone keeps the connection alive
+    drop(cluster);
+    let event = watcher
+        .events()
+        .recv_timeout(Duration::from_secs(10))
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.crossed_threshold, 0.0);
+    assert_eq!(event.direction, Crossing::Rising);
+}
+
+#[test]
+fn snapshot_paths_go_through_snaps() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    assert_eq!(
+        cluster.snapshot_path("daily", &Path::new("a/b/file")),
+        PathBuf::from("a/b/.snaps/daily/file")
+    );
+    assert_eq!(
+        cluster.snapshot_path("daily", &Path::new("file")),
+        PathBuf::from(".snaps/daily/file")
+    );
+    // The test volume doesn't have uss on
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    match cluster.list_snapshots(tmp.path()) {
+        Err(GlusterError::SnapshotsNotEnabled { .. }) => {}
+        other => panic!("expected SnapshotsNotEnabled, got {:?}", other),
+    }
+}
+
+#[test]
+// Needs a volume with features.uss on, named by GFAPI_SNAPSHOT_VOLUME,
+// holding a file GFAPI_SNAPSHOT_FILE that was changed after snapshot
+// GFAPI_SNAPSHOT_NAME was taken
+fn restore_file_from_snapshot() {
+    let (volume, file, snap) = match (
+        std::env::var("GFAPI_SNAPSHOT_VOLUME"),
+        std::env::var("GFAPI_SNAPSHOT_FILE"),
+        std::env::var("GFAPI_SNAPSHOT_NAME"),
+    ) {
+        (Ok(volume), Ok(file), Ok(snap)) => (volume, PathBuf::from(file), snap),
+        _ => return,
+    };
+    let cluster = Gluster::connect(&volume, "localhost", 24007).unwrap();
+    let parent = file.parent().unwrap_or(Path::new("")).to_path_buf();
+    assert!(cluster.list_snapshots(&parent).unwrap().contains(&snap));
+    let old = cluster.read_from_snapshot(&snap, &file).unwrap();
+    assert_eq!(cluster.restore_file(&snap, &file).unwrap(), old.len() as u64);
+    assert!(cluster.read_to_vec(&file).unwrap() == old);
+}
+
+#[test]
+fn acl_round_trip() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let file = tmp.child("acl");
+    cluster.write_file(&file, b"acl").unwrap();
+    cluster.chmod(&file, 0o640).unwrap();
+    assert_eq!(
+        cluster.get_acl(&file, AclType::Access).unwrap(),
+        Acl::from_mode(0o640)
+    );
+
+    let mut acl = Acl::from_mode(0o640);
+    acl.set(AclTag::User(1000), AclPerms::from_bits(6));
+    acl.set(AclTag::Mask, AclPerms::from_bits(6));
+    cluster.set_acl(&file, AclType::Access, &acl).unwrap();
+    let read_back = cluster.get_acl(&file, AclType::Access).unwrap();
+    assert_eq!(read_back.get(AclTag::User(1000)), Some(AclPerms::from_bits(6)));
+
+    acl.remove(AclTag::Mask);
+    assert!(cluster.set_acl(&file, AclType::Access, &acl).is_err());
+
+    let dir = tmp.path();
+    assert!(cluster.get_acl(dir, AclType::Default).unwrap().is_empty());
+    cluster
+        .set_acl(dir, AclType::Default, &Acl::from_mode(0o750))
+        .unwrap();
+    assert_eq!(
+        cluster.get_acl(dir, AclType::Default).unwrap(),
+        Acl::from_mode(0o750)
+    );
+    cluster.set_acl(dir, AclType::Default, &Acl::default()).unwrap();
+    assert!(cluster.get_acl(dir, AclType::Default).unwrap().is_empty());
+}
+
+#[test]
+fn security_xattrs() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let file = tmp.child("labelled");
+    cluster.write_file(&file, b"label").unwrap();
+    assert_eq!(cluster.get_capability(&file).unwrap(), None);
+
+    // Only works where the bricks accept relabelling by this client
+    let context = "system_u:object_r:etc_t:s0";
+    if cluster.set_selinux_context(&file, context).is_ok() {
+        assert_eq!(
+            cluster.get_selinux_context(&file).unwrap(),
+            Some(context.to_string())
+        );
+        let copy = tmp.child("labelled-copy");
+        let opts = WriteOptions::new().preserve_security_xattrs(true);
+        cluster.copy(&file, &copy, &opts).unwrap();
+        assert_eq!(
+            cluster.get_selinux_context(&copy).unwrap(),
+            Some(context.to_string())
+        );
+    }
+}
+
+#[test]
+fn chmod_with_mode_strings() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let file = tmp.child("modes");
+    cluster.write_file(&file, b"modes").unwrap();
+    cluster.chmod_str(&file, "0640").unwrap();
+    assert_eq!(cluster.stat(&file).unwrap().st_mode & 0o7777, 0o640);
+    cluster.chmod_str(&file, "g+w,o+X").unwrap();
+    assert_eq!(cluster.stat(&file).unwrap().st_mode & 0o7777, 0o660);
+    match cluster.chmod_str(&file, "g+q") {
+        Err(GlusterError::ModeError(e)) => assert_eq!(e.position, 2),
+        other => panic!("expected a ModeError, got {:?}", other),
+    }
+}
+
+#[test]
+fn exact_modes_ignore_the_umask() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let old_umask = unsafe { libc::umask(0o077) };
+
+    let file = tmp.child("exact-file");
+    let flags = libc::O_CREAT | libc::O_WRONLY;
+    cluster
+        .create_file_with(&file, flags, defaults::FILE_0644, ModePolicy::Exact)
+        .unwrap();
+    let dirs = tmp.child("exact/a/b");
+    cluster
+        .create_dir_all_with(&dirs, defaults::DIR_0755, ModePolicy::Exact)
+        .unwrap();
+    let written = tmp.child("exact-written");
+    let opts = WriteOptions::new()
+        .mode(defaults::FILE_0664)
+        .mode_policy(ModePolicy::Exact);
+    cluster.write_file_with(&written, b"exact", &opts).unwrap();
+
+    unsafe { libc::umask(old_umask) };
+    assert_eq!(cluster.stat(&file).unwrap().st_mode & 0o7777, 0o644);
+    assert_eq!(cluster.stat(&written).unwrap().st_mode & 0o7777, 0o664);
+    for dir in &["exact", "exact/a", "exact/a/b"] {
+        assert_eq!(cluster.stat(&tmp.child(dir)).unwrap().st_mode & 0o7777, 0o755);
+    }
+}
+
+#[test]
+fn close_and_disconnect_report_errors() {
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+    set_drop_error_handler(Some(Box::new(|_: &DropError| {
+        REPORTED.fetch_add(1, Ordering::SeqCst);
+    })));
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    {
+        let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+        let path = tmp.child("closed");
+        let mut file = cluster
+            .create_file(&path, libc::O_CREAT | libc::O_WRONLY, 0o644)
+            .unwrap();
+        assert_eq!(file.path(), path.as_path());
+        file.write_all(b"closed").unwrap();
+        file.close().unwrap();
+
+        let mut dropped = cluster.open_file(&path, libc::O_WRONLY).unwrap();
+        dropped.write_all(b"dropped").unwrap();
+    }
+    cluster.disconnect().unwrap();
+    set_drop_error_handler(None);
+    assert_eq!(REPORTED.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn try_clone_outlives_the_original() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster
+        .set_logging(&Path::new("/dev/null"), GlusterLogLevel::Warning)
+        .unwrap();
+    let clone = cluster.try_clone().unwrap();
+    assert_eq!(clone.get_volume_id().unwrap(), cluster.get_volume_id().unwrap());
+    drop(cluster);
+
+    let tmp = GlusterTempDir::new(&clone, &Path::new("gfapi")).unwrap();
+    let file = tmp.child("cloned");
+    clone.write_file(&file, b"cloned").unwrap();
+    assert_eq!(clone.read_to_vec(&file).unwrap(), b"cloned".to_vec());
+}
+
+#[test]
+fn volume_set_routes_and_copies_between_volumes() {
+    // Two names for the test volume, each with its own connection
+    let volumes = VolumeSet::new();
+    volumes.register("one", Gluster::builder("test"));
+    volumes.register("two", Gluster::builder("test"));
+    volumes.register("broken", Gluster::builder("test").port(1));
+
+    let one = volumes.volume("one").unwrap();
+    let tmp = GlusterTempDir::new(&one, &Path::new("gfapi")).unwrap();
+    let source = VolumePath::new("one", tmp.child("source"));
+    let copied = VolumePath::new("two", tmp.child("copied"));
+    let moved = VolumePath::new("two", tmp.child("moved"));
+    volumes.write_file(&source, b"routed").unwrap();
+
+    let opts = WriteOptions::new();
+    assert_eq!(volumes.copy(&source, &copied, &opts).unwrap(), 6);
+    assert_eq!(volumes.read_to_vec(&copied).unwrap(), b"routed".to_vec());
+    volumes.move_item(&source, &moved, &opts).unwrap();
+    assert!(!volumes.exists(&source).unwrap());
+    assert_eq!(volumes.read_to_vec(&moved).unwrap(), b"routed".to_vec());
+
+    // A volume that can't connect doesn't affect the others
+    assert!(volumes.read_to_vec(&VolumePath::new("broken", "x")).is_err());
+    assert!(volumes.exists(&copied).unwrap());
+    for status in volumes.status() {
+        match (status.name.as_str(), status.state) {
+            ("broken", VolumeState::Failed(_)) => {}
+            ("one", VolumeState::Connected) | ("two", VolumeState::Connected) => {}
+            other => panic!("unexpected status {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn tuning_profile_is_applied() {
+    let cluster = Gluster::builder("test")
+        .tuning(TuningProfile::SmallFiles)
+        .connect()
+        .unwrap();
+    assert_eq!(
+        cluster.applied_xlator_options(),
+        TuningProfile::SmallFiles.options().unwrap()
+    );
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    cluster.write_file(&tmp.child("tuned"), b"tuned").unwrap();
+
+    let invalid = TuningProfile::Default.with("*-read-ahead", "page-count", "100");
+    assert!(Gluster::builder("test").tuning(invalid).connect().is_err());
+}
+
+#[test]
+fn range_readers_share_one_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("ranges");
+    let data: Vec<u8> = (0..1024 * 1024 + 3).map(|i| (i % 251) as u8).collect();
+    cluster.write_file(&path, &data).unwrap();
+
+    let file = cluster.open_file(&path, libc::O_RDONLY).unwrap();
+    let quarter = data.len() as u64 / 4;
+    let parts: Vec<Vec<u8>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let len = if i == 3 { None } else { Some(quarter) };
+                let mut reader = file.reader_at(i * quarter, len);
+                scope.spawn(move || {
+                    let mut part = Vec::new();
+                    reader.read_to_end(&mut part).unwrap();
+                    part
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert_eq!(parts.concat(), data);
+
+    let mut reader = file.reader_at(10, Some(20));
+    assert_eq!(reader.seek(SeekFrom::End(-5)).unwrap(), 15);
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, &data[25..30]);
+    let mut unbounded = file.reader_at(data.len() as u64 - 2, None);
+    assert_eq!(unbounded.seek(SeekFrom::End(0)).unwrap(), 2);
+}
+
+#[test]
+fn chunked_reads() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("chunked");
+    let data: Vec<u8> = (0..10_000).map(|i| (i % 253) as u8).collect();
+    cluster.write_file(&path, &data).unwrap();
+
+    let chunks: Vec<Vec<u8>> = cluster
+        .chunks(&path, 4096)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let sizes: Vec<usize> = chunks.iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![4096, 4096, 1808]);
+    assert_eq!(chunks.concat(), data);
+
+    // An exact multiple doesn't produce an empty chunk at the end
+    assert_eq!(cluster.chunks(&path, 5000).unwrap().count(), 2);
+
+    let mut seen = Vec::new();
+    let total = cluster
+        .chunks(&path, 3000)
+        .unwrap()
+        .for_each_chunk(|chunk| seen.extend_from_slice(chunk))
+        .unwrap();
+    assert_eq!(total, data.len() as u64);
+    assert_eq!(seen, data);
+
+    // Dropping part way closes the file, so nothing holds it open
+    // afterwards
+    {
+        let mut early = cluster.chunks(&path, 100).unwrap();
+        assert_eq!(early.next().unwrap().unwrap(), &data[..100]);
+    }
+    cluster.unlink(&path).unwrap();
+    assert!(cluster.chunks(&path, 100).is_err());
+}
+
+#[test]
+fn positioned_io_from_many_threads() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("positioned");
+    let file = cluster
+        .create_file(&path, libc::O_CREAT | libc::O_RDWR, 0o644)
+        .unwrap();
+    let block = 64 * 1024;
+    thread::scope(|scope| {
+        for i in 0..8u8 {
+            let file = &file;
+            scope.spawn(move || {
+                let data = vec![i; block];
+                file.write_all_at(&data, i as u64 * block as u64).unwrap();
+            });
+        }
+    });
+    thread::scope(|scope| {
+        for i in 0..8u8 {
+            let file = &file;
+            scope.spawn(move || {
+                let mut data = vec![0; block];
+                file.read_exact_at(&mut data, i as u64 * block as u64)
+                    .unwrap();
+                assert!(data.iter().all(|b| *b == i));
+            });
+        }
+    });
+    let mut past_end = vec![0; 10];
+    let err = file.read_exact_at(&mut past_end, 8 * block as u64 - 5)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    file.close().unwrap();
+}
+
+// Paths, depths and sizes of a walk, sorted so walks in any order compare
+fn walk_totals<I>(walk: &mut I) -> Vec<(PathBuf, usize, u64)>
+where
+    I: Iterator<Item = Result<WalkEntry, GlusterError>>,
+{
+    let mut totals: Vec<_> = walk
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let len = if entry.metadata.is_dir() { 0 } else { entry.metadata.len() };
+            (entry.path, entry.depth, len)
+        })
+        .collect();
+    totals.sort();
+    totals
+}
+
+#[test]
+fn par_walk_matches_walk() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("tree");
+    for a in 0..4 {
+        for b in 0..3 {
+            let dir = root.join(format!("a{}", a)).join(format!("b{}", b));
+            cluster.create_dir_all(&dir, 0o755).unwrap();
+            for f in 0..5 {
+                cluster
+                    .write_file(&dir.join(format!("f{}", f)), &vec![0; a * 100 + b * 10 + f])
+                    .unwrap();
+            }
+        }
+    }
+    cluster.symlink(&Path::new("a0"), &root.join("link")).unwrap();
+
+    let opts = WalkOptions::new().threads(3);
+    let sequential = walk_totals(&mut cluster.walk(&root, &opts));
+    // Root, 4 a dirs, 12 b dirs, 60 files and the link
+    assert_eq!(sequential.len(), 1 + 4 + 12 + 60 + 1);
+    assert_eq!(sequential, cluster.par_walk(&root, &opts, walk_totals));
+
+    let limited = opts.clone().min_depth(1).max_depth(2);
+    let sequential = walk_totals(&mut cluster.walk(&root, &limited));
+    assert!(sequential.iter().all(|&(_, depth, _)| depth == 1 || depth == 2));
+    assert_eq!(sequential.len(), 4 + 12 + 1);
+    assert_eq!(sequential, cluster.par_walk(&root, &limited, walk_totals));
+
+    // The link now reports as a directory, but a0's contents are only
+    // walked once
+    let following = opts.clone().follow_links(true);
+    let sequential = walk_totals(&mut cluster.walk(&root, &following));
+    assert_eq!(sequential.len(), 1 + 4 + 12 + 60 + 1);
+    assert_eq!(sequential, cluster.par_walk(&root, &following, walk_totals));
+
+    let missing = tmp.child("missing");
+    let stop = WalkOptions::new().on_error(WalkErrorPolicy::Stop);
+    let results: Vec<_> = cluster.par_walk(&missing, &stop, |walk| walk.collect());
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+    let ignore = WalkOptions::new().on_error(WalkErrorPolicy::Ignore);
+    assert_eq!(cluster.walk(&missing, &ignore).count(), 0);
+}
+
+#[test]
+fn prefetched_walk_yields_the_same_entries_in_order() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("prefetch");
+    for d in 0..3 {
+        let dir = root.join(format!("d{}", d));
+        cluster.create_dir_all(&dir, 0o755).unwrap();
+        for f in 0..40 {
+            cluster
+                .write_file(&dir.join(format!("f{}", f)), &vec![0; d * 40 + f])
+                .unwrap();
+            cluster
+                .symlink(&Path::new(&format!("f{}", f)), &dir.join(format!("l{}", f)))
+                .unwrap();
+        }
+    }
+    let in_order = |opts: &WalkOptions| -> Vec<(PathBuf, usize, u32, u64)> {
+        cluster
+            .walk(&root, opts)
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let metadata = entry.metadata;
+                (entry.path, entry.depth, metadata.mode(), metadata.len())
+            })
+            .collect()
+    };
+    for follow in &[false, true] {
+        let plain = WalkOptions::new().follow_links(*follow);
+        let prefetched = plain.clone().prefetch_metadata(16);
+        let expected = in_order(&plain);
+        assert_eq!(expected.len(), 1 + 3 + 3 * 80);
+        assert_eq!(in_order(&prefetched), expected);
+    }
+}
+
+#[test]
+fn streamed_dir_applies_backpressure() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let dir = tmp.child("streamed");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    for i in 0..50 {
+        cluster
+            .write_file(&dir.join(format!("f{}", i)), b"x")
+            .unwrap();
+    }
+
+    let mut names = cluster.read_dir_streamed_plus(&dir, 4, |stream| {
+        thread::sleep(Duration::from_millis(500));
+        // Four buffered and at most one waiting to be sent
+        assert!(stream.entries_read() <= 5);
+        let first: StreamedEntry = stream.next().unwrap().unwrap();
+        assert!(first.metadata.map(|m| m.len() == 1).unwrap_or(true));
+        let mut names: Vec<_> = stream.map(|e| e.unwrap().entry.path).collect();
+        names.push(first.entry.path);
+        names
+    });
+    names.sort();
+    let mut expected: Vec<_> = (0..50).map(|i| PathBuf::from(format!("f{}", i))).collect();
+    expected.sort();
+    assert_eq!(names, expected);
+
+    // Returning early stops the producer and closes the directory
+    let read = cluster.read_dir_streamed(&dir, 1, |stream| {
+        assert!(stream.next().unwrap().is_ok());
+        stream.entries_read()
+    });
+    assert!(read < 50);
+
+    let results: Vec<_> =
+        cluster.read_dir_streamed(&tmp.child("missing"), 1, |stream| stream.collect());
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn dry_run_plans_without_touching_the_volume() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let src = tmp.child("src");
+    cluster.mkdir(&src, 0o755).unwrap();
+    cluster.mkdir(&src.join("sub"), 0o755).unwrap();
+    cluster.write_file(&src.join("a"), b"hello").unwrap();
+    cluster.write_file(&src.join("sub/b"), b"hi").unwrap();
+
+    let dry = cluster.dry_run();
+    let dst = tmp.child("dst");
+    dry.create_dir_all(&dst.join("sub"), 0o755).unwrap();
+    let opts = WriteOptions::new();
+    assert_eq!(dry.copy(&src.join("a"), &dst.join("a"), &opts).unwrap(), 5);
+    dry.remove_dir_all(&src).unwrap();
+    assert_eq!(
+        dry.planned(),
+        vec![
+            PlannedOp::Mkdir { path: dst.clone(), mode: 0o755 },
+            PlannedOp::Mkdir { path: dst.join("sub"), mode: 0o755 },
+            PlannedOp::Create { path: dst.join("a"), mode: 0o644 },
+            PlannedOp::Write { path: dst.join("a"), len: 5 },
+            PlannedOp::Unlink { path: src.join("a") },
+            PlannedOp::Unlink { path: src.join("sub/b") },
+            PlannedOp::Rmdir { path: src.join("sub") },
+            PlannedOp::Rmdir { path: src.clone() },
+        ]
+    );
+    assert_eq!(dry.to_string().lines().count(), 8);
+
+    // Nothing was changed
+    assert!(!cluster.exists(&dst).unwrap());
+    assert_eq!(cluster.read_to_vec(&src.join("sub/b")).unwrap(), b"hi");
+    assert_eq!(dry.take_planned().len(), 8);
+    assert!(dry.planned().is_empty());
+}
+
+#[test]
+fn audit_journal_records_changes_in_order() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let sink = MemoryAuditSink::new();
+    let audited = cluster.audited(&sink);
+    let src = tmp.child("audited");
+    let dst = tmp.child("audited-copy");
+    audited.write_file(&src, b"journal").unwrap();
+    audited.copy(&src, &dst, &WriteOptions::new()).unwrap();
+    audited.unlink(&src).unwrap();
+    // Failures are recorded too, with an errno only when one caused them
+    assert!(audited.unlink(&src).is_err());
+    assert!(audited.copy(&dst, &dst, &WriteOptions::new()).is_err());
+    audited.unlink(&dst).unwrap();
+
+    let records = sink.records();
+    let summary: Vec<_> = records
+        .iter()
+        .map(|r| (r.seq, r.op, r.path.clone(), r.target.clone(), r.size, r.errno))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0, AuditOp::Write, src.clone(), None, Some(7), None),
+            (1, AuditOp::Copy, src.clone(), Some(dst.clone()), Some(7), None),
+            (2, AuditOp::Unlink, src.clone(), None, None, None),
+            (3, AuditOp::Unlink, src.clone(), None, None, Some(libc::ENOENT)),
+            (4, AuditOp::Copy, dst.clone(), Some(dst.clone()), None, None),
+            (5, AuditOp::Unlink, dst.clone(), None, None, None),
+        ]
+    );
+    let uid = unsafe { libc::getuid() };
+    assert!(records.iter().all(|r| r.uid == uid));
+    assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+}
+
+#[test]
+fn shred_overwrites_then_unlinks() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("secret");
+    let secret = vec![0xa5; 300 * 1024];
+    cluster.write_file(&path, &secret).unwrap();
+
+    // Keep the file to check what was left in it
+    let keep = ShredOptions::new()
+        .random_passes(2)
+        .chunk_size(128 * 1024)
+        .unlink(false);
+    let mut reports = Vec::new();
+    cluster
+        .shred_with_progress(&path, &keep, |p| reports.push(*p))
+        .unwrap();
+    let contents = cluster.read_to_vec(&path).unwrap();
+    assert_eq!(contents.len(), secret.len());
+    assert!(contents.iter().all(|b| *b == 0));
+    // Three chunks in each of three passes
+    assert_eq!(reports.len(), 9);
+    assert_eq!(reports[8].pass, 3);
+    assert_eq!(reports[8].written, secret.len() as u64);
+
+    // Without any passes nothing would be overwritten
+    let nothing = ShredOptions::new().zero_pass(false);
+    assert!(cluster.shred(&path, &nothing).is_err());
+    assert!(cluster.exists(&path).unwrap());
+
+    // Hard linked files are refused without force
+    let other = tmp.child("secret-link");
+    cluster.link(&path, &other).unwrap();
+    assert!(cluster.shred(&path, &ShredOptions::new()).is_err());
+    cluster.unlink(&other).unwrap();
+
+    cluster
+        .shred(&path, &ShredOptions::new().truncate(true))
+        .unwrap();
+    assert!(!cluster.exists(&path).unwrap());
+
+    // Directories and symlinks aren't shredded
+    cluster.mkdir(&tmp.child("dir"), 0o755).unwrap();
+    assert!(cluster.shred(&tmp.child("dir"), &ShredOptions::new()).is_err());
+    cluster.write_file(&path, b"data").unwrap();
+    cluster.symlink(&path, &other).unwrap();
+    assert!(cluster.shred(&other, &ShredOptions::new()).is_err());
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"data");
+}
+
+#[test]
+fn sync_file_only_rewrites_changed_blocks() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let block = 4096;
+    let mut data: Vec<u8> = (0..64 * block).map(|i| (i / 7) as u8).collect();
+    let local = std::env::temp_dir().join(format!("gfapi-sync-{}", std::process::id()));
+    std::fs::write(&local, &data).unwrap();
+    let dest = tmp.child("synced");
+    let opts = SyncOptions::new();
+
+    let first = cluster
+        .sync_file(SourceFile::Local(&local), &dest, block, &opts)
+        .unwrap();
+    assert_eq!(first.blocks_transferred, 64);
+    assert_eq!(first.blocks_reused, 0);
+
+    // Change three scattered blocks and drop the last half block
+    for &i in &[3, 30, 61] {
+        data[i * block + 100] ^= 0xff;
+    }
+    data.truncate(63 * block + block / 2);
+    std::fs::write(&local, &data).unwrap();
+    let second = cluster
+        .sync_file(SourceFile::Local(&local), &dest, block, &opts)
+        .unwrap();
+    std::fs::remove_file(&local).unwrap();
+    assert_eq!(second.blocks_transferred, 4);
+    assert_eq!(second.blocks_reused, 60);
+    assert_eq!(second.bytes_transferred, 3 * block as u64 + block as u64 / 2);
+    assert_eq!(second.len, data.len() as u64);
+    let synced = cluster.read_to_vec(&dest).unwrap();
+    assert_eq!(Crc32c::checksum(&synced), Crc32c::checksum(&data));
+
+    // A remote source onto a longer destination
+    let copy = tmp.child("synced-copy");
+    cluster.write_file(&copy, &vec![0; 80 * block]).unwrap();
+    let remote = cluster
+        .sync_file(SourceFile::Remote(&dest), &copy, block, &opts)
+        .unwrap();
+    assert_eq!(remote.len, data.len() as u64);
+    assert_eq!(cluster.read_to_vec(&copy).unwrap(), data);
+    let again = cluster
+        .sync_file(SourceFile::Remote(&dest), &copy, block, &opts)
+        .unwrap();
+    assert_eq!(again.blocks_transferred, 0);
+}
+
+#[test]
+fn read_to_writer_streams_whole_files() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("streamed-out");
+    let data: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| i as u8).collect();
+    cluster.write_file(&path, &data).unwrap();
+    let mut out = Vec::new();
+    assert_eq!(cluster.read_to_writer(&path, &mut out).unwrap(), data.len() as u64);
+    assert_eq!(out, data);
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), data);
+}
+
+#[test]
+fn write_sync_reaches_the_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("synced-write");
+    let file = cluster
+        .create(&path, O_CREAT | O_RDWR | O_TRUNC, 0o644)
+        .unwrap();
+    let fd = file.handle().unwrap();
+    assert_eq!(cluster.write_sync(fd, b"durable").unwrap(), 7);
+    // The second write goes after the first, not over it
+    assert_eq!(cluster.write_sync(fd, b" twice").unwrap(), 6);
+    file.close().unwrap();
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"durable twice");
+}
+
+#[test]
+fn resolve_symlink_follows_relative_chains() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let a = tmp.child("links-a");
+    let b = a.join("b");
+    cluster.mkdir(&a, 0o755).unwrap();
+    cluster.mkdir(&b, 0o755).unwrap();
+    let target = b.join("real");
+    cluster.write_file(&target, b"end of the chain").unwrap();
+
+    // a/first -> b/second -> ../third -> b/real
+    cluster.symlink(Path::new("b/second"), &a.join("first")).unwrap();
+    cluster.symlink(Path::new("../third"), &b.join("second")).unwrap();
+    cluster.symlink(Path::new("b/real"), &a.join("third")).unwrap();
+    assert_eq!(cluster.resolve_symlink(&a.join("first"), 8).unwrap(), target);
+    match cluster.resolve_symlink(&a.join("first"), 2) {
+        Err(GlusterError::SymlinkLoop { hops, .. }) => assert_eq!(hops, 2),
+        other => panic!("expected SymlinkLoop, got {:?}", other),
+    }
+    assert_eq!(cluster.resolve_symlink(&target, 0).unwrap(), target);
+
+    cluster.symlink(Path::new("b/gone"), &a.join("dangling")).unwrap();
+    match cluster.resolve_symlink(&a.join("dangling"), 8) {
+        Err(GlusterError::DanglingSymlink { target, .. }) => assert_eq!(target, b.join("gone")),
+        other => panic!("expected DanglingSymlink, got {:?}", other),
+    }
+
+    cluster.symlink(Path::new("ping"), &a.join("pong")).unwrap();
+    cluster.symlink(Path::new("pong"), &a.join("ping")).unwrap();
+    match cluster.resolve_symlink(&a.join("ping"), 40) {
+        Err(GlusterError::SymlinkLoop { hops, .. }) => assert_eq!(hops, 2),
+        other => panic!("expected SymlinkLoop, got {:?}", other),
+    }
+}
+
+#[test]
+fn rename_noreplace_has_one_winner() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let claim = tmp.child("claimed");
+    let sources: Vec<PathBuf> = (0..8).map(|i| tmp.child(format!("claimant-{}", i))).collect();
+    for source in &sources {
+        cluster.write_file(source, source.to_str().unwrap().as_bytes()).unwrap();
+    }
+
+    let winners = AtomicUsize::new(0);
+    let losers = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for source in &sources {
+            let (cluster, claim, winners, losers) = (&cluster, &claim, &winners, &losers);
+            s.spawn(move || match cluster.rename_noreplace(source, claim) {
+                Ok(()) => {
+                    winners.fetch_add(1, Ordering::SeqCst);
+                }
+                Err(GlusterError::AlreadyExists { ref path }) if path == claim => {
+                    losers.fetch_add(1, Ordering::SeqCst);
+                }
+                Err(e) => panic!("unexpected error {:?}", e),
+            });
+        }
+    });
+    assert_eq!(winners.load(Ordering::SeqCst), 1);
+    assert_eq!(losers.load(Ordering::SeqCst), sources.len() - 1);
+
+    // The winner's source is gone and the rest are untouched
+    let contents = String::from_utf8(cluster.read_to_vec(&claim).unwrap()).unwrap();
+    for source in &sources {
+        let exists = cluster.exists(source).unwrap();
+        assert_eq!(!exists, contents == source.to_str().unwrap());
+    }
+
+    let dir = tmp.child("claim-dir");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    assert!(cluster.rename_noreplace(&dir, &tmp.child("claimed-dir")).is_err());
+}
+
+#[test]
+fn dedupe_hardlink_links_identical_files() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("dedupe");
+    cluster.mkdir(&root, 0o755).unwrap();
+    cluster.mkdir(&root.join("sub"), 0o755).unwrap();
+    let same: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut other = same.clone();
+    *other.last_mut().unwrap() ^= 1;
+    let copies = [root.join("a"), root.join("b"), root.join("sub/c")];
+    for copy in &copies {
+        cluster.write_file(copy, &same).unwrap();
+    }
+    // Same length but a different last byte, and a different length
+    cluster.write_file(&root.join("d"), &other).unwrap();
+    cluster.write_file(&root.join("e"), b"short").unwrap();
+    let nlink = |path: &Path| cluster.symlink_metadata(path).unwrap().as_stat().st_nlink;
+
+    let planned = cluster
+        .dedupe_hardlink(&root, &DedupeOptions::new().dry_run(true))
+        .unwrap();
+    assert_eq!(planned.links_created, 0);
+    assert_eq!(planned.bytes_reclaimed, 2 * same.len() as u64);
+    assert!(copies.iter().all(|copy| nlink(copy) == 1));
+
+    let report = cluster.dedupe_hardlink(&root, &DedupeOptions::new()).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.groups[0].canonical, copies[0]);
+    assert_eq!(report.groups[0].duplicates, &copies[1..]);
+    assert_eq!(report.links_created, 2);
+    assert_eq!(report.bytes_reclaimed, 2 * same.len() as u64);
+    for copy in &copies {
+        assert_eq!(nlink(copy), 3);
+        assert_eq!(cluster.read_to_vec(copy).unwrap(), same);
+    }
+    assert_eq!(nlink(&root.join("d")), 1);
+    assert_eq!(cluster.read_to_vec(&root.join("d")).unwrap(), other);
+    assert_eq!(nlink(&root.join("e")), 1);
+
+    // Everything already shares an inode the second time
+    let again = cluster.dedupe_hardlink(&root, &DedupeOptions::new()).unwrap();
+    assert_eq!(again, DedupeReport::default());
+}
+
+#[test]
+fn dir_entry_count_matches_listing() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let dir = tmp.child("counted");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    for i in 0..25 {
+        cluster
+            .write_file(&dir.join(format!("file-{}", i)), b"x")
+            .unwrap();
+    }
+    for i in 0..4 {
+        cluster.mkdir(&dir.join(format!("dir-{}", i)), 0o755).unwrap();
+    }
+    cluster.symlink(Path::new("file-0"), &dir.join("link")).unwrap();
+
+    let listed = cluster.list_dir(&dir, 1).unwrap();
+    assert_eq!(cluster.dir_entry_count(&dir, None).unwrap(), listed.len() as u64);
+    assert_eq!(cluster.dir_entry_count(&dir, None).unwrap(), 30);
+    assert_eq!(cluster.dir_entry_count(&dir, Some(FileType::File)).unwrap(), 25);
+    assert_eq!(cluster.dir_entry_count(&dir, Some(FileType::Dir)).unwrap(), 4);
+    assert_eq!(cluster.dir_entry_count(&dir, Some(FileType::Symlink)).unwrap(), 1);
+    assert_eq!(cluster.dir_entry_count(&dir, Some(FileType::Fifo)).unwrap(), 0);
+}
+
+#[test]
+fn volfile_names_the_volume() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let volfile = cluster.volfile().unwrap();
+    assert!(!volfile.is_empty());
+    assert!(volfile.contains("test"));
+
+    // Starting too small makes it grow to the reported length
+    let mut buf = Vec::with_capacity(8);
+    let len = cluster.volfile_into(&mut buf).unwrap();
+    assert_eq!(len, volfile.len());
+    assert_eq!(buf, volfile.as_bytes());
+}
+
+#[test]
+fn overlong_paths_fail_before_gfapi() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let long_path = PathBuf::from(format!("/{}", vec!["abcdefghi"; 500].join("/")));
+    match cluster.stat(&long_path) {
+        Err(GlusterError::PathTooLong {
+            component: None,
+            len,
+            limit,
+            ..
+        }) => {
+            assert_eq!(len, 5000);
+            assert_eq!(limit, 4096);
+        }
+        other => panic!("expected PathTooLong, got {:?}", other),
+    }
+
+    let long_name = "n".repeat(300);
+    let path = Path::new("/gfapi").join(&long_name).join("child");
+    match cluster.stat(&path) {
+        Err(GlusterError::PathTooLong {
+            component: Some(component),
+            len,
+            limit,
+            ..
+        }) => {
+            assert_eq!(component, long_name.as_str());
+            assert_eq!(len, 300);
+            assert_eq!(limit, 255);
+        }
+        other => panic!("expected PathTooLong, got {:?}", other),
+    }
+
+    let relaxed = Gluster::builder("test")
+        .path_limits(8192, 512)
+        .connect()
+        .unwrap();
+    match relaxed.stat(&path) {
+        Err(GlusterError::PathTooLong { .. }) => panic!("limit wasn't raised"),
+        _ => {}
+    }
+}
+
+#[test]
+fn strict_utf8_refuses_to_replace_bytes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("not-utf8-xattr");
+    cluster.write_file(&path, b"").unwrap();
+    let raw = [b'o', b'k', 0xff, 0xfe];
+    cluster.setxattr(&path, "user.raw", &raw, 0).unwrap();
+
+    assert_eq!(cluster.getxattr(&path, "user.raw").unwrap(), "ok\u{fffd}\u{fffd}");
+    assert_eq!(cluster.getxattr_bytes(&path, "user.raw").unwrap(), raw);
+
+    let strict = Gluster::builder("test").strict_utf8(true).connect().unwrap();
+    match strict.getxattr(&path, "user.raw") {
+        Err(GlusterError::FromUtf8Error(e)) => assert_eq!(e.into_bytes(), raw),
+        other => panic!("expected FromUtf8Error, got {:?}", other),
+    }
+    assert_eq!(strict.getxattr_bytes(&path, "user.raw").unwrap(), raw);
+    strict.setxattr(&path, "user.text", b"fine", 0).unwrap();
+    assert_eq!(strict.getxattr(&path, "user.text").unwrap(), "fine");
+}
+
+#[test]
+fn raw_xattr_names_round_trip() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("latin1-xattr");
+    cluster.write_file(&path, b"").unwrap();
+    // user.café in Latin-1
+    let name = b"user.caf\xe9".to_vec();
+    cluster.setxattr_raw_name(&path, &name, b"value", 0).unwrap();
+
+    let names = cluster.list_xattr_raw(&path).unwrap();
+    assert!(names.contains(&name));
+    assert_eq!(cluster.getxattr_raw_name(&path, &name).unwrap(), b"value");
+    assert!(cluster.llist_xattr_raw(&path).unwrap().contains(&name));
+    let file = cluster.open(&path, O_RDONLY).unwrap();
+    assert!(file.list_xattr_raw().unwrap().contains(&name));
+    assert_eq!(file.getxattr_raw_name(&name).unwrap(), b"value");
+    file.setxattr_raw_name(&name, b"changed", 0).unwrap();
+    assert_eq!(cluster.getxattr_raw_name(&path, &name).unwrap(), b"changed");
+    file.close().unwrap();
+
+    // The String listing still has every name, NUL terminated
+    let listed = cluster.listxattr(&path).unwrap();
+    assert_eq!(listed.matches('\0').count(), names.len());
+}
+
+#[test]
+fn set_attrs_keeps_setuid_after_chown() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("set-attrs");
+    cluster.write_file(&path, b"0123456789").unwrap();
+    let mtime = timespec {
+        tv_sec: 1_500_000_000,
+        tv_nsec: 0,
+    };
+    let attrs = SetAttrs::new()
+        .size(4)
+        .owner(1000, 1000)
+        .mode(0o4755)
+        .mtime(mtime);
+    // Whichever way the linked libgfapi makes it happen the result is
+    // the same
+    cluster.set_attrs(&path, &attrs).unwrap();
+    let metadata = cluster.metadata(&path).unwrap();
+    assert_eq!(metadata.len(), 4);
+    assert_eq!(metadata.uid(), 1000);
+    assert_eq!(metadata.gid(), 1000);
+    assert_eq!(metadata.permissions(), 0o4755);
+    assert_eq!(metadata.mtime(), 1_500_000_000);
+
+    // Failing on the first step changes nothing, so the error is plain
+    let dir = tmp.child("set-attrs-dir");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    match cluster.set_attrs(&dir, &SetAttrs::new().size(0).mode(0o700)) {
+        Err(GlusterError::AttrsPartiallyApplied { .. }) => panic!("nothing was applied"),
+        Err(_) => {}
+        Ok(()) => panic!("truncating a directory worked"),
+    }
+    assert_eq!(cluster.metadata(&dir).unwrap().permissions(), 0o755);
+}
+
+#[test]
+fn query_lock_reports_the_holder() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let other = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("locked-range");
+    cluster.write_file(&path, &[0; 4096]).unwrap();
+
+    let holder = cluster.open_file(&path, O_RDWR).unwrap();
+    let asker = other.open_file(&path, O_RDWR).unwrap();
+    assert_eq!(asker.query_lock(0, 0, LockKind::Shared).unwrap(), None);
+    assert!(holder.try_lock_range(100, 50, LockKind::Exclusive).unwrap());
+
+    let conflict = asker
+        .query_lock(0, 1000, LockKind::Shared)
+        .unwrap()
+        .expect("the exclusive lock conflicts");
+    assert_eq!(conflict.start, 100);
+    assert_eq!(conflict.len, 50);
+    assert_eq!(conflict.kind, LockKind::Exclusive);
+    assert!(!asker.try_lock_range(120, 10, LockKind::Shared).unwrap());
+    // Outside the held range there's nothing in the way
+    assert_eq!(asker.query_lock(150, 10, LockKind::Exclusive).unwrap(), None);
+    assert_eq!(holder.query_lock(100, 50, LockKind::Exclusive).unwrap(), None);
+
+    holder.unlock_range(100, 50).unwrap();
+    assert_eq!(asker.query_lock(0, 0, LockKind::Exclusive).unwrap(), None);
+}
+
+#[test]
+fn lock_timeout_waits_for_release() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let other = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("lock-timeout");
+    cluster.write_file(&path, &[0; 1024]).unwrap();
+    let holder = cluster.open_file(&path, O_RDWR).unwrap();
+    let waiter = other.open_file(&path, O_RDWR).unwrap();
+
+    let held = holder
+        .lock_exclusive_timeout(0, 0, Duration::from_secs(1))
+        .unwrap();
+    thread::scope(|s| {
+        s.spawn(move || {
+            thread::sleep(Duration::from_secs(1));
+            held.release().unwrap();
+        });
+        let started = std::time::Instant::now();
+        let guard = waiter
+            .lock_shared_timeout(0, 0, Duration::from_secs(10))
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(900));
+        assert_eq!(guard.kind(), LockKind::Shared);
+        assert_eq!(guard.range(), (0, 0));
+    });
+}
+
+#[test]
+fn lock_timeout_gives_up_with_the_holder() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let other = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("lock-never-released");
+    cluster.write_file(&path, &[0; 1024]).unwrap();
+    let holder = cluster.open_file(&path, O_RDWR).unwrap();
+    let waiter = other.open_file(&path, O_RDWR).unwrap();
+
+    let _held = holder
+        .lock_shared_timeout(10, 20, Duration::from_secs(1))
+        .unwrap();
+    // Shared locks don't conflict with each other
+    waiter
+        .lock_shared_timeout(0, 100, Duration::from_millis(100))
+        .unwrap()
+        .release()
+        .unwrap();
+    let result = waiter
+        .lock_exclusive_timeout(0, 100, Duration::from_millis(500))
+        .map(|_| ());
+    match result {
+        Err(GlusterError::Timeout {
+            waited,
+            conflict: Some(conflict),
+        }) => {
+            assert!(waited >= Duration::from_millis(500));
+            assert_eq!(conflict.kind, LockKind::Shared);
+            assert_eq!((conflict.start, conflict.len), (10, 20));
+        }
+        other => panic!("expected Timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn dir_fingerprint_tracks_entries() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let dir = tmp.child("fingerprinted");
+    cluster.mkdir(&dir, 0o755).unwrap();
+    cluster.write_file(&dir.join("a"), b"first").unwrap();
+
+    let names = cluster.dir_fingerprint(&dir).unwrap();
+    let inodes = cluster
+        .dir_fingerprint_with(&dir, FingerprintLevel::Inodes)
+        .unwrap();
+    assert_eq!(names.entries, 1);
+    assert!(!cluster.dir_fingerprint(&dir).unwrap().changed_from(&names));
+
+    // Rewriting a file in place only shows up at the inode level
+    cluster.write_file(&dir.join("a"), b"second version").unwrap();
+    assert!(!cluster.dir_fingerprint(&dir).unwrap().changed_from(&names));
+    let rewritten = cluster
+        .dir_fingerprint_with(&dir, FingerprintLevel::Inodes)
+        .unwrap();
+    assert!(rewritten.changed_from(&inodes));
+
+    // A new entry changes both, and survives a round trip through text
+    cluster.write_file(&dir.join("b"), b"").unwrap();
+    let added = cluster.dir_fingerprint(&dir).unwrap();
+    assert!(added.changed_from(&names));
+    assert_eq!(added.entries, 2);
+    let stored: DirFingerprint = added.to_string().parse().unwrap();
+    assert!(!cluster.dir_fingerprint(&dir).unwrap().changed_from(&stored));
+}
+
+#[test]
+fn closed_file_reports_handle_closed() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("closed-early");
+    cluster.write_file(&path, b"contents").unwrap();
+    let mut file = cluster.open_file(&path, O_RDWR).unwrap();
+    file.close_in_place().unwrap();
+    assert!(file.is_closed());
+
+    let is_handle_closed = |e: &GlusterError| match *e {
+        GlusterError::HandleClosed { ref opened_path } => opened_path == &path,
+        _ => false,
+    };
+    assert!(is_handle_closed(&file.fstat().unwrap_err()));
+    assert!(is_handle_closed(&file.fsync().unwrap_err()));
+    assert!(is_handle_closed(&file.close_in_place().unwrap_err()));
+    let mut buf = [0; 8];
+    for err in vec![
+        file.read_at(&mut buf, 0).unwrap_err(),
+        file.read(&mut buf).unwrap_err(),
+        file.write(b"more").unwrap_err(),
+    ] {
+        let inner = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<GlusterError>())
+            .unwrap();
+        assert!(is_handle_closed(inner), "{:?}", err);
+    }
+}
+
+#[test]
+fn handle_io_matches_path_io() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("by-handle");
+    cluster.write_file(&path, b"written by path").unwrap();
+
+    let object: GlusterObject = cluster.lookup_object(&path).unwrap();
+    let mut buf = [0; 64];
+    let n = cluster.h_pread(&object, &mut buf, 11).unwrap();
+    assert_eq!(&buf[..n], b"path");
+
+    assert_eq!(cluster.h_pwrite(&object, b"HANDLE", 11).unwrap(), 6);
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"written by HANDLE");
+
+    // The gfid alone is enough to find the file again, after a rename too
+    let renamed = tmp.child("by-handle-renamed");
+    cluster.rename(&path, &renamed).unwrap();
+    let again = cluster.object_from_gfid(&object.gfid()).unwrap();
+    assert_eq!(again.gfid(), object.gfid());
+    let n = cluster.h_pread(&again, &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"written by HANDLE");
+
+    let cache = cluster.h_fd_cache(1);
+    cache.h_pwrite(&again, b"W", 0).unwrap();
+    let n = cache.h_pread(&again, &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"Written by HANDLE");
+    // A read fd and a write fd, but only room for one
+    assert_eq!(cache.len(), 1);
+    cache.invalidate(&again.gfid());
+    assert!(cache.is_empty());
+
+    // Objects belong to the connection that looked them up
+    let other = Gluster::connect("test", "localhost", 24007).unwrap();
+    assert!(other.h_pread(&object, &mut buf, 0).is_err());
+}
+
+#[test]
+fn same_file_sees_through_links() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let original = tmp.child("same-original");
+    let hard = tmp.child("same-hardlink");
+    let copy = tmp.child("same-copy");
+    let soft = tmp.child("same-symlink");
+    cluster.write_file(&original, b"identity").unwrap();
+    cluster.link(&original, &hard).unwrap();
+    cluster.write_file(&copy, b"identity").unwrap();
+    cluster.symlink(&original, &soft).unwrap();
+
+    assert!(cluster.same_file(&original, &hard).unwrap());
+    assert_eq!(cluster.metadata(&original).unwrap().nlink(), 2);
+    assert!(!cluster.same_file(&original, &copy).unwrap());
+    assert!(cluster.same_file(&original, &soft).unwrap());
+    assert!(!cluster.same_file_no_follow(&original, &soft).unwrap());
+    assert!(cluster.same_file_no_follow(&original, &hard).unwrap());
+}
+
+#[test]
+fn vectored_positioned_io_round_trips_through_a_file() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let path = tmp.child("vectored");
+    let file = cluster
+        .create(&path, O_CREAT | O_RDWR | O_TRUNC, 0o644)
+        .unwrap();
+
+    let written = cluster
+        .pwritev_all(&file, &[IoSlice::new(b"head"), IoSlice::new(b"-tail")], 3)
+        .unwrap();
+    assert_eq!(written, 9);
+
+    let mut head = [0u8; 5];
+    let mut tail = [0u8; 4];
+    cluster
+        .preadv_exact(
+            &file,
+            &mut [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)],
+            3,
+        )
+        .unwrap();
+    assert_eq!(&head, b"head-");
+    assert_eq!(&tail, b"tail");
+    assert!(cluster
+        .preadv_exact(&file, &mut [IoSliceMut::new(&mut head)], 10)
+        .is_err());
+}
+
+#[test]
+fn append_record_offsets_point_at_each_record() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let record = |i: usize| format!("record {:03}\n", i).into_bytes();
+
+    for &(name, locked) in &[("appended", false), ("appended-locked", true)] {
+        let path = tmp.child(name);
+        cluster.write_file(&path, b"").unwrap();
+        let offsets: Vec<(usize, u64)> = thread::scope(|scope| {
+            let appenders: Vec<_> = (0..100)
+                .map(|i| {
+                    let (cluster, path) = (&cluster, &path);
+                    scope.spawn(move || {
+                        let file = cluster.open_file(path, O_RDWR | O_APPEND).unwrap();
+                        let offset = if locked {
+                            file.append_record_locked(&record(i), Duration::from_secs(60))
+                        } else {
+                            file.append_record(&record(i))
+                        };
+                        (i, offset.unwrap())
+                    })
+                })
+                .collect();
+            appenders.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let contents = cluster.read_to_vec(&path).unwrap();
+        assert_eq!(contents.len(), 100 * record(0).len());
+        for (i, offset) in offsets {
+            let start = offset as usize;
+            assert_eq!(&contents[start..start + record(i).len()], &record(i)[..]);
+        }
+    }
+
+    // Threads sharing one handle and its clones still get their own offsets
+    let path = tmp.child("appended-shared");
+    cluster.write_file(&path, b"").unwrap();
+    let mut shared = cluster.open_file(&path, O_RDWR | O_APPEND).unwrap();
+    let offsets: Vec<(usize, u64)> = thread::scope(|scope| {
+        let appenders: Vec<_> = (0..100)
+            .map(|i| {
+                let clone = if i % 2 == 0 {
+                    Some(shared.try_clone().unwrap())
+                } else {
+                    None
+                };
+                let shared = &shared;
+                scope.spawn(move || {
+                    let file = clone.as_ref().unwrap_or(shared);
+                    (i, file.append_record(&record(i)).unwrap())
+                })
+            })
+            .collect();
+        appenders.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let contents = cluster.read_to_vec(&path).unwrap();
+    for (i, offset) in offsets {
+        let start = offset as usize;
+        assert_eq!(&contents[start..start + record(i).len()], &record(i)[..]);
+    }
+    assert_eq!(
+        shared.seek(SeekFrom::Current(0)).unwrap(),
+        contents.len() as u64
+    );
+
+    // Without O_APPEND the offset can't be trusted
+    let plain = cluster.open_file(&tmp.child("appended"), O_RDWR).unwrap();
+    assert!(plain.append_record(b"x").is_err());
+}
+
+#[test]
+fn copy_preserves_mode_times_and_xattrs() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let from = tmp.child("preserve-source");
+    let to = tmp.child("preserve-copy");
+    cluster.write_file(&from, b"keep my attributes").unwrap();
+    cluster.chmod(&from, 0o640).unwrap();
+    cluster.setxattr(&from, "user.origin", b"test", 0).unwrap();
+    cluster.setxattr(&from, "user.rank", b"7", 0).unwrap();
+    let times = [
+        timespec {
+            tv_sec: 1_000_000_000,
+            tv_nsec: 0,
+        },
+        timespec {
+            tv_sec: 1_234_567_890,
+            tv_nsec: 500,
+        },
+    ];
+    cluster.utimens(&from, &times).unwrap();
+
+    let opts = WriteOptions::new().preserve(
+        PreserveOptions::new()
+            .times(true)
+            .mode(true)
+            .xattrs(&[XattrNamespace::User]),
+    );
+    let report = cluster.copy_with_report(&from, &to, &opts).unwrap();
+    assert_eq!(report.bytes, 18);
+    assert!(report.warnings.is_empty());
+
+    let copied = cluster.metadata(&to).unwrap();
+    assert_eq!(copied.permissions(), 0o640);
+    assert_eq!((copied.mtime(), copied.mtime_nsec()), (1_234_567_890, 500));
+    assert_eq!(cluster.getxattr(&to, "user.origin").unwrap(), "test");
+    assert_eq!(cluster.getxattr(&to, "user.rank").unwrap(), "7");
+
+    // Ownership as the owner already is always allowed
+    let owned = tmp.child("preserve-all");
+    let report = cluster
+        .copy_with_report(&from, &owned, &WriteOptions::new().preserve(PreserveOptions::all()))
+        .unwrap();
+    assert!(report.warnings.is_empty());
+    assert_eq!(cluster.metadata(&owned).unwrap().uid(), copied.uid());
+}
+
+#[test]
+fn copy_xattrs_replicates_user_attributes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let from = tmp.child("xattr-source");
+    let to = tmp.child("xattr-dest");
+    let by_handle = tmp.child("xattr-dest-by-handle");
+    for path in &[&from, &to, &by_handle] {
+        cluster.write_file(path, b"").unwrap();
+    }
+    let attrs: Vec<(&[u8], &[u8])> = vec![
+        (b"user.text", b"plain"),
+        (b"user.binary", b"\x00\xff\x10\x80"),
+        (b"user.empty", b""),
+    ];
+    for &(name, value) in &attrs {
+        cluster.setxattr_raw_name(&from, name, value, 0).unwrap();
+    }
+    cluster.setxattr(&to, "user.text", b"stale", 0).unwrap();
+
+    let user_attrs = |path: &Path| {
+        let mut map: Vec<(Vec<u8>, Vec<u8>)> = cluster
+            .list_xattr_raw(path)
+            .unwrap()
+            .into_iter()
+            .filter(|name| name.starts_with(b"user."))
+            .map(|name| {
+                let value = cluster.getxattr_raw_name(path, &name).unwrap();
+                (name, value)
+            })
+            .collect();
+        map.sort();
+        map
+    };
+
+    let filter = XattrFilter::namespaces(&[XattrNamespace::User]);
+    assert_eq!(cluster.copy_xattrs(&from, &to, filter).unwrap(), 3);
+    assert_eq!(user_attrs(&to), user_attrs(&from));
+
+    let source = cluster.open_file(&from, O_RDONLY).unwrap();
+    let dest = cluster.open_file(&by_handle, O_RDWR).unwrap();
+    let filter = XattrFilter::namespaces(&[XattrNamespace::User])
+        .matching(|name| name != b"user.empty")
+        .best_effort(true);
+    assert_eq!(cluster.fcopy_xattrs(&source, &dest, filter).unwrap(), 2);
+    assert_eq!(user_attrs(&by_handle).len(), 2);
+}
+
+#[test]
+fn remove_matching_deletes_old_files_and_prunes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, &Path::new("gfapi")).unwrap();
+    let root = tmp.child("scratch");
+    for dir in &["a", "b/c", "d", "empty"] {
+        cluster.create_dir_all(&root.join(dir), 0o755).unwrap();
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let ten_days_ago = timespec {
+        tv_sec: now - 10 * 24 * 3600,
+        tv_nsec: 0,
+    };
+    let old = ["old1", "a/old2", "b/c/old3", "d/old4"];
+    let new = ["new1", "d/new2"];
+    for name in old.iter().chain(new.iter()) {
+        cluster.write_file(&root.join(name), b"data").unwrap();
+    }
+    for name in &old {
+        cluster
+            .utimens(&root.join(name), &[ten_days_ago, ten_days_ago])
+            .unwrap();
+    }
+    let week_old = |_: &WalkEntry, metadata: &Metadata| metadata.mtime() < now - 7 * 24 * 3600;
+
+    let mut expected: Vec<PathBuf> = old.iter().map(|name| root.join(name)).collect();
+    expected.sort();
+    let mut expected_pruned = vec![root.join("a"), root.join("b"), root.join("b/c")];
+    expected_pruned.sort();
+
+    let planned = cluster
+        .remove_matching(
+            &root,
+            &week_old,
+            &RemoveOptions::new().prune_empty_dirs(true).dry_run(true),
+        )
+        .unwrap();
+    assert!(cluster.exists(&root.join("old1")).unwrap());
+
+    let mut report = cluster
+        .remove_matching(&root, &week_old, &RemoveOptions::new().prune_empty_dirs(true))
+        .unwrap();
+    assert_eq!(planned, report);
+    report.removed.sort();
+    report.pruned.sort();
+    assert_eq!(report.removed, expected);
+    assert_eq!(report.pruned, expected_pruned);
+    assert!(report.failures.is_empty());
+
+    for name in &old {
+        assert!(!cluster.exists(&root.join(name)).unwrap());
+    }
+    for name in new.iter().chain(["d", "empty"].iter()) {
+        assert!(cluster.exists(&root.join(name)).unwrap(), "{}", name);
+    }
+    assert!(!cluster.exists(&root.join("b")).unwrap());
+}
+
+#[test]
+fn trash_restores_and_purges() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let opts = TrashOptions::new().root(tmp.child("trash"));
+    let path = tmp.child("doomed");
+    cluster.write_file(&path, b"keep me").unwrap();
+
+    let entry = cluster.trash(&path, &opts).unwrap();
+    assert!(!cluster.exists(&path).unwrap());
+    assert_eq!(entry.original, path);
+    assert_eq!(cluster.read_to_vec(&entry.location).unwrap(), b"keep me");
+    assert_eq!(cluster.list_trash(&opts).unwrap(), vec![entry.clone()]);
+
+    assert_eq!(cluster.restore(&entry.id, &opts).unwrap(), path);
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"keep me");
+    assert!(cluster.list_trash(&opts).unwrap().is_empty());
+
+    // Age the entry by rewriting its sidecar with a deletion time long past
+    let entry = cluster.trash(&path, &opts).unwrap();
+    let mut info = b"deleted=1000.000000000\npath=".to_vec();
+    info.extend_from_slice(path.to_str().unwrap().as_bytes());
+    let info_path = tmp.child("trash").join(&entry.id).join(".trashinfo");
+    cluster.write_file(&info_path, &info).unwrap();
+    cluster.write_file(&path, b"new").unwrap();
+    let fresh = cluster.trash(&path, &opts).unwrap();
+
+    let day = Duration::from_secs(24 * 3600);
+    assert_eq!(cluster.purge_older_than(day, &opts).unwrap(), 1);
+    assert!(!cluster.exists(&entry.location).unwrap());
+    assert_eq!(cluster.list_trash(&opts).unwrap(), vec![fresh]);
+}
+
+#[test]
+fn trash_keeps_sidecar_named_items_and_refuses_foreign_ids() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let opts = TrashOptions::new().root(tmp.child("trash"));
+    let path = tmp.child(".trashinfo");
+    cluster.write_file(&path, b"not a sidecar").unwrap();
+
+    let entry = cluster.trash(&path, &opts).unwrap();
+    assert_eq!(cluster.list_trash(&opts).unwrap(), vec![entry.clone()]);
+    assert_eq!(cluster.restore(&entry.id, &opts).unwrap(), path);
+    assert_eq!(cluster.read_to_vec(&path).unwrap(), b"not a sidecar");
+
+    // Ids that name the trash root, a date directory or anything outside
+    // the trash are refused without touching it
+    let outside = tmp.child("outside");
+    cluster.write_file(&outside, b"safe").unwrap();
+    let entry = cluster.trash(&path, &opts).unwrap();
+    let date = entry.id.split('/').next().unwrap().to_string();
+    for id in &[".", "", date.as_str(), "../outside", "/etc/passwd", "a/../../outside"] {
+        assert!(cluster.restore(id, &opts).is_err(), "{:?}", id);
+    }
+    assert_eq!(cluster.read_to_vec(&outside).unwrap(), b"safe");
+    assert_eq!(cluster.list_trash(&opts).unwrap(), vec![entry]);
+}
+
+#[test]
+fn stale_retry_wrappers_pass_calls_through() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let path = tmp.child("wrapped");
+
+    let file = cluster
+        .create_file(&path, O_CREAT | O_RDWR | O_TRUNC, 0o644)
+        .unwrap()
+        .with_stale_retry(StaleRetry::new().attempts(2));
+    assert_eq!(file.write_at(b"hello", 0).unwrap(), 5);
+    let mut buf = [0u8; 5];
+    assert_eq!(file.read_at(&mut buf, 0).unwrap(), 5);
+    assert_eq!(&buf, b"hello");
+    assert_eq!(file.fstat().unwrap().st_size, 5);
+    assert_eq!(file.path(), path);
+    assert_eq!(file.recoveries(), 0);
+    file.into_inner().close().unwrap();
+
+    let object = cluster
+        .lookup_object(&path)
+        .unwrap()
+        .with_stale_retry(StaleRetry::new())
+        .unwrap();
+    let mut buf = [0u8; 5];
+    assert_eq!(object.h_pread(&mut buf, 0).unwrap(), 5);
+    assert_eq!(&buf, b"hello");
+    assert_eq!(object.recoveries(), 0);
+
+    // Without a path there's nothing to look up again
+    let gfid = cluster.lookup_object(&path).unwrap().gfid();
+    let by_gfid = cluster.object_from_gfid(&gfid).unwrap();
+    assert!(by_gfid.with_stale_retry(StaleRetry::new()).is_err());
+}
+
+#[test]
+fn gluster_conforms_to_remote_fs() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    conformance::run(&cluster, tmp.path());
+    conformance::run_xattrs(&cluster, tmp.path());
+    conformance::run_tree(&cluster, tmp.path());
+}
+
+#[test]
+fn wrappers_conform_to_remote_fs() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+
+    let sink = MemoryAuditSink::new();
+    let audited = cluster.audited(&sink);
+    conformance::run(&audited, tmp.path());
+    conformance::run_tree(&audited, tmp.path());
+    assert!(sink.records().iter().any(|r| r.op == AuditOp::Rename));
+
+    let scoped = cluster.scoped(tmp.path());
+    conformance::run(&scoped, Path::new("/"));
+    conformance::run_tree(&scoped, Path::new("/"));
+    assert!(scoped.read(Path::new("/../outside")).is_err());
+
+    // A dry run plans the tree helpers' changes from what's on the volume
+    let dry = cluster.dry_run();
+    cluster.mkdir(&tmp.child("exists"), 0o755).unwrap();
+    let deep = tmp.child("exists/a/b");
+    remote_fs::create_dir_all(&dry, &deep).unwrap();
+    assert_eq!(
+        dry.take_planned(),
+        vec![
+            PlannedOp::Mkdir {
+                path: tmp.child("exists/a"),
+                mode: 0o755,
+            },
+            PlannedOp::Mkdir {
+                path: deep.clone(),
+                mode: 0o755,
+            },
+        ]
+    );
+    assert!(!cluster.exists(&tmp.child("exists/a")).unwrap());
+}
+
+#[test]
+fn copy_with_applies_each_overwrite_policy() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let set_mtime = |path: &Path, secs| {
+        let time = timespec {
+            tv_sec: secs,
+            tv_nsec: 0,
+        };
+        cluster.utimens(path, &[time, time]).unwrap();
+    };
+    let source = tmp.child("source");
+    cluster.write_file(&source, b"0123456789").unwrap();
+    set_mtime(&source, 2000);
+
+    // Destinations as (contents, mtime): newer, older, same size and time,
+    // different size
+    let destinations: [(&[u8], i64); 4] = [
+        (b"newer-dest", 3000),
+        (b"older-dest", 1000),
+        (b"same-dest!", 2000),
+        (b"short", 2000),
+    ];
+    let policies = [
+        (Overwrite::Never, [false, false, false, false]),
+        (Overwrite::Always, [true, true, true, true]),
+        (Overwrite::IfNewer, [false, true, false, false]),
+        (Overwrite::IfDifferentSize, [false, false, false, true]),
+    ];
+    for &(policy, replaced) in &policies {
+        let opts = CopyOptions::new().overwrite(policy);
+        for (i, &(contents, mtime)) in destinations.iter().enumerate() {
+            let dest = tmp.child(format!("dest-{:?}-{}", policy, i));
+            cluster.write_file(&dest, contents).unwrap();
+            set_mtime(&dest, mtime);
+            let outcome = cluster.copy_with(&source, &dest, &opts).unwrap();
+            let stored = cluster.read_to_vec(&dest).unwrap();
+            if replaced[i] {
+                assert_eq!(stored, b"0123456789", "{:?} over {}", policy, i);
+                match outcome {
+                    CopyOutcome::Copied(report) => assert_eq!(report.bytes, 10),
+                    other => panic!("{:?} over {} gave {:?}", policy, i, other),
+                }
+            } else {
+                assert_eq!(stored, contents, "{:?} over {}", policy, i);
+                assert_eq!(outcome, CopyOutcome::Skipped);
+            }
+        }
+        // A missing destination is always copied
+        let fresh = tmp.child(format!("fresh-{:?}", policy));
+        cluster.copy_with(&source, &fresh, &opts).unwrap();
+        assert_eq!(cluster.read_to_vec(&fresh).unwrap(), b"0123456789");
+    }
+}
+
+#[test]
+fn copy_with_refuses_the_same_file_under_every_policy() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let source = tmp.child("source");
+    let hard = tmp.child("source-link");
+    cluster.write_file(&source, b"0123456789").unwrap();
+    cluster.link(&source, &hard).unwrap();
+
+    let refused = |result: Result<CopyOutcome, GlusterError>| match result {
+        Err(GlusterError::SameFile { .. }) => {}
+        other => panic!("same file copy wasn't refused: {:?}", other),
+    };
+    for &policy in &[
+        Overwrite::Never,
+        Overwrite::Always,
+        Overwrite::IfNewer,
+        Overwrite::IfDifferentSize,
+    ] {
+        let opts = CopyOptions::new().overwrite(policy).on_conflict(|conflict| {
+            panic!("asked about {}", conflict.destination.display())
+        });
+        for to in &[&source, &hard] {
+            refused(cluster.copy_with(&source, to, &opts));
+        }
+    }
+    let pairs = vec![(source.clone(), source.clone()), (source.clone(), hard.clone())];
+    for result in cluster.copy_parallel(&pairs, 2, &CopyOptions::new()) {
+        refused(result);
+    }
+    assert_eq!(cluster.read_to_vec(&source).unwrap(), b"0123456789");
+}
+
+#[test]
+fn copy_options_handle_conflicts_parents_and_trees() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let source = tmp.child("source");
+    cluster.write_file(&source, b"data").unwrap();
+    let existing = tmp.child("existing");
+    cluster.write_file(&existing, b"old").unwrap();
+
+    let abort = CopyOptions::new().on_conflict(|conflict| {
+        assert_eq!(conflict.policy, ConflictAction::Overwrite);
+        ConflictAction::Abort
+    });
+    match cluster.copy_with(&source, &existing, &abort) {
+        Err(GlusterError::CopyAborted { path }) => assert_eq!(path, existing),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(cluster.read_to_vec(&existing).unwrap(), b"old");
+
+    // Missing parents are an error unless create_parents is set
+    let nested = tmp.child("a/b/c");
+    assert!(cluster
+        .copy_with(&source, &nested, &CopyOptions::new())
+        .is_err());
+    cluster
+        .copy_with(&source, &nested, &CopyOptions::new().create_parents(true))
+        .unwrap();
+    assert_eq!(cluster.read_to_vec(&nested).unwrap(), b"data");
+
+    let pairs: Vec<(PathBuf, PathBuf)> = (0..8)
+        .map(|i| (source.clone(), tmp.child(format!("parallel-{}", i))))
+        .collect();
+    for result in cluster.copy_parallel(&pairs, 4, &CopyOptions::new().buffer_size(3)) {
+        assert!(matches!(result.unwrap(), CopyOutcome::Copied(_)));
+    }
+
+    // A tree merged into a partial copy of itself
+    let tree = tmp.child("tree");
+    cluster.create_dir_all(&tree.join("sub"), 0o755).unwrap();
+    cluster.write_file(&tree.join("one"), b"1").unwrap();
+    cluster.write_file(&tree.join("sub/two"), b"22").unwrap();
+    cluster
+        .symlink(Path::new("one"), &tree.join("link"))
+        .unwrap();
+    let copy = tmp.child("copies/tree");
+    cluster.create_dir_all(&copy, 0o755).unwrap();
+    cluster.write_file(&copy.join("one"), b"x").unwrap();
+    let report = cluster
+        .copy_dir(
+            &tree,
+            &copy,
+            &CopyOptions::new().overwrite(Overwrite::Never),
+        )
+        .unwrap();
+    assert_eq!((report.copied, report.skipped, report.bytes), (2, 1, 2));
+    assert_eq!(cluster.read_to_vec(&copy.join("one")).unwrap(), b"x");
+    assert_eq!(cluster.read_to_vec(&copy.join("sub/two")).unwrap(), b"22");
+    assert_eq!(
+        cluster.read_link(&copy.join("link")).unwrap(),
+        Path::new("one")
+    );
+    assert!(cluster
+        .copy_dir(&tree, &tree.join("sub/inside"), &CopyOptions::new())
+        .is_err());
+}
+
+#[test]
+fn sparse_copy_keeps_contents() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let source = tmp.child("source");
+    let mut data = vec![0u8; 3 * 4096];
+    data[4096] = 7;
+    cluster.write_file(&source, &data).unwrap();
+    let dest = tmp.child("dest");
+    let opts = CopyOptions::new()
+        .sparse(true)
+        .buffer_size(4096)
+        .verify(VerifyMode::WholeFile);
+    cluster.copy_with(&source, &dest, &opts).unwrap();
+    assert_eq!(cluster.read_to_vec(&dest).unwrap(), data);
+}
+
+#[test]
+fn checksum_cache_hits_and_invalidates() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let tmp = GlusterTempDir::new(&cluster, Path::new("gfapi")).unwrap();
+    let path = tmp.child("summed");
+    let sha = ChecksumAlgorithm::Sha256;
+    cluster.write_file(&path, b"first").unwrap();
+    assert_eq!(cluster.cached_checksum(&path).unwrap(), None);
+
+    let digest = cluster.checksum_cached(&path, sha).unwrap();
+    assert_eq!(digest, sha.digest(b"first"));
+    let cached = cluster.cached_checksum(&path).unwrap().unwrap();
+    assert_eq!(cached.digest, digest);
+    assert!(cluster.verify_checksum(&path, sha, &digest).unwrap());
+
+    // A hit returns what's cached without reading the file, so a planted
+    // digest for the same size and mtime comes back
+    let planted = CachedChecksum {
+        digest: vec![0xab; 32],
+        ..cached.clone()
+    };
+    cluster
+        .setxattr(&path, CHECKSUM_XATTR, &planted.to_bytes(), 0)
+        .unwrap();
+    assert_eq!(cluster.checksum_cached(&path, sha).unwrap(), vec![0xab; 32]);
+
+    // Rewriting the file invalidates it
+    cluster.write_file(&path, b"second!").unwrap();
+    assert_eq!(
+        cluster.checksum_cached(&path, sha).unwrap(),
+        sha.digest(b"second!")
+    );
+    assert!(!cluster.verify_checksum(&path, sha, &digest).unwrap());
+
+    // Asking for another algorithm recomputes and replaces the entry
+    let crc = cluster
+        .checksum_cached(&path, ChecksumAlgorithm::Crc32c)
+        .unwrap();
+    assert_eq!(crc, ChecksumAlgorithm::Crc32c.digest(b"second!"));
+    assert_eq!(
+        cluster.cached_checksum(&path).unwrap().unwrap().algorithm,
+        ChecksumAlgorithm::Crc32c
+    );
+
+    // A corrupted value is ignored and replaced
+    cluster
+        .setxattr(&path, CHECKSUM_XATTR, b"v1 sha256 not a cache entry", 0)
+        .unwrap();
+    assert_eq!(cluster.cached_checksum(&path).unwrap(), None);
+    assert_eq!(
+        cluster.checksum_cached(&path, sha).unwrap(),
+        sha.digest(b"second!")
+    );
+    assert!(cluster.cached_checksum(&path).unwrap().is_some());
+
+    cluster.clear_checksum_cache(&path).unwrap();
+    assert_eq!(cluster.cached_checksum(&path).unwrap(), None);
+    cluster.clear_checksum_cache(&path).unwrap();
 }
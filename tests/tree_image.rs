@@ -0,0 +1,95 @@
+extern crate gfapi_sys;
+extern crate libc;
+#[cfg(feature = "serde")]
+extern crate toml;
+
+use gfapi_sys::tree_image::{ImageEntry, ImageKind, TreeImage};
+use libc::{S_IFDIR, S_IFLNK, S_IFREG};
+
+use std::path::{Path, PathBuf};
+
+fn entry(path: &str, kind: ImageKind) -> ImageEntry {
+    let mode = match kind {
+        ImageKind::Dir => S_IFDIR | 0o755,
+        ImageKind::Symlink { .. } => S_IFLNK | 0o777,
+        _ => S_IFREG | 0o644,
+    };
+    ImageEntry {
+        path: PathBuf::from(path),
+        kind,
+        mode,
+        uid: 1000,
+        gid: 1000,
+        atime: 1_600_000_000,
+        atime_nsec: 1,
+        mtime: 1_500_000_000,
+        mtime_nsec: 2,
+        xattrs: Vec::new(),
+    }
+}
+
+fn file(path: &str, link_group: Option<(u64, u64)>) -> ImageEntry {
+    entry(
+        path,
+        ImageKind::File {
+            size: 3,
+            sha256: "ab".repeat(32),
+            link_group,
+        },
+    )
+}
+
+fn image() -> TreeImage {
+    let mut linked = file("b/linked", Some((7, 42)));
+    linked.xattrs = vec![(b"user.note".to_vec(), b"kept".to_vec())];
+    TreeImage {
+        root: PathBuf::from("source"),
+        entries: vec![
+            entry("", ImageKind::Dir),
+            entry("a", ImageKind::Dir),
+            file("a/first", Some((7, 42))),
+            file("a/plain", None),
+            entry("b", ImageKind::Dir),
+            linked,
+            entry(
+                "b/link",
+                ImageKind::Symlink {
+                    target: PathBuf::from("../a/plain"),
+                },
+            ),
+            file("c", Some((7, 43))),
+        ],
+    }
+}
+
+#[test]
+fn entries_are_found_by_relative_path() {
+    let image = image();
+    assert_eq!(image.get(Path::new("")).unwrap().kind, ImageKind::Dir);
+    assert_eq!(
+        image.get(Path::new("b/link")).unwrap().kind,
+        ImageKind::Symlink {
+            target: PathBuf::from("../a/plain"),
+        }
+    );
+    assert!(image.get(Path::new("b/missing")).is_none());
+}
+
+#[test]
+fn link_groups_collect_paths_sharing_an_inode() {
+    assert_eq!(
+        image().link_groups(),
+        vec![
+            vec![PathBuf::from("a/first"), PathBuf::from("b/linked")],
+            vec![PathBuf::from("c")],
+        ]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn images_round_trip_through_serde() {
+    let image = image();
+    let text = toml::to_string(&image).unwrap();
+    assert_eq!(toml::from_str::<TreeImage>(&text).unwrap(), image);
+}
@@ -0,0 +1,74 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::manifest::{Manifest, ManifestEntry};
+use gfapi_sys::tree_sync::{plan_sync, SyncAction};
+
+use std::path::PathBuf;
+
+fn entry(path: &str, size: u64, mtime: i64) -> ManifestEntry {
+    ManifestEntry {
+        path: PathBuf::from(path),
+        size,
+        mtime,
+        mtime_nsec: 0,
+        sha256: format!("{:064x}", size),
+    }
+}
+
+fn create(path: &str) -> SyncAction {
+    SyncAction::Create {
+        path: PathBuf::from(path),
+    }
+}
+
+fn update(path: &str) -> SyncAction {
+    SyncAction::Update {
+        path: PathBuf::from(path),
+    }
+}
+
+fn delete(path: &str) -> SyncAction {
+    SyncAction::Delete {
+        path: PathBuf::from(path),
+    }
+}
+
+#[test]
+fn everything_is_created_in_an_empty_destination() {
+    let source = Manifest::new(vec![entry("b", 1, 1), entry("a/c", 2, 1)]);
+    assert_eq!(
+        plan_sync(&source, &Manifest::default(), true),
+        vec![create("a/c"), create("b")]
+    );
+    assert!(plan_sync(&source, &source, true).is_empty());
+}
+
+#[test]
+fn only_differing_files_are_copied() {
+    let destination = Manifest::new(vec![
+        entry("same", 1, 1),
+        entry("resized", 1, 1),
+        entry("touched", 1, 1),
+        entry("extra", 1, 1),
+    ]);
+    let source = Manifest::new(vec![
+        entry("same", 1, 1),
+        entry("resized", 2, 1),
+        entry("touched", 1, 2),
+        entry("added", 1, 1),
+    ]);
+    assert_eq!(
+        plan_sync(&source, &destination, false),
+        vec![create("added"), update("resized"), update("touched")]
+    );
+    assert_eq!(
+        plan_sync(&source, &destination, true),
+        vec![
+            create("added"),
+            update("resized"),
+            update("touched"),
+            delete("extra")
+        ]
+    );
+    assert_eq!(delete("extra").to_string(), "delete extra");
+}
@@ -0,0 +1,94 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::tuning::{TuningProfile, XlatorOption};
+
+fn option_strings(profile: &TuningProfile) -> Vec<String> {
+    profile
+        .options()
+        .unwrap()
+        .iter()
+        .map(XlatorOption::to_string)
+        .collect()
+}
+
+#[test]
+fn tuning_profiles_produce_expected_options() {
+    assert!(option_strings(&TuningProfile::Default).is_empty());
+    assert_eq!(
+        option_strings(&TuningProfile::Throughput),
+        vec![
+            "*-io-cache.cache-size=256MB",
+            "*-read-ahead.page-count=16",
+            "*-write-behind.cache-size=4MB",
+            "*-write-behind.flush-behind=on",
+            "*-readdir-ahead.rda-cache-limit=10MB",
+        ]
+    );
+    assert_eq!(
+        option_strings(&TuningProfile::SmallFiles),
+        vec![
+            "*-md-cache.md-cache-timeout=600",
+            "*-open-behind.lazy-open=on",
+            "*-open-behind.read-after-open=on",
+            "*-readdir-ahead.rda-request-size=128KB",
+            "*-readdir-ahead.rda-cache-limit=64MB",
+            "*-write-behind.flush-behind=on",
+        ]
+    );
+    assert_eq!(
+        option_strings(&TuningProfile::LowMemory),
+        vec![
+            "*-io-cache.cache-size=4MB",
+            "*-read-ahead.page-count=1",
+            "*-write-behind.cache-size=512KB",
+            "*-readdir-ahead.rda-cache-limit=1MB",
+        ]
+    );
+}
+
+#[test]
+fn tuning_custom_options_override_the_profile() {
+    let profile = TuningProfile::LowMemory
+        .with("*-read-ahead", "page-count", "4")
+        .with("*-quick-read", "cache-size", "8MB");
+    assert_eq!(
+        option_strings(&profile),
+        vec![
+            "*-io-cache.cache-size=4MB",
+            "*-read-ahead.page-count=4",
+            "*-write-behind.cache-size=512KB",
+            "*-readdir-ahead.rda-cache-limit=1MB",
+            // Not one the profiles know, passed through unchecked
+            "*-quick-read.cache-size=8MB",
+        ]
+    );
+    assert_eq!(
+        TuningProfile::Custom(vec![XlatorOption::new(
+            "*-md-cache",
+            "md-cache-timeout",
+            "5"
+        )]),
+        TuningProfile::Default.with("*-md-cache", "md-cache-timeout", "5")
+    );
+}
+
+#[test]
+fn tuning_rejects_out_of_range_values() {
+    for &(xlator, key, value) in &[
+        ("*-read-ahead", "page-count", "0"),
+        ("*-read-ahead", "page-count", "17"),
+        ("*-read-ahead", "page-count", "many"),
+        ("*-io-cache", "cache-size", "1MB"),
+        ("*-io-cache", "cache-size", "64XB"),
+        ("*-write-behind", "cache-size", "2GB"),
+        ("*-write-behind", "flush-behind", "maybe"),
+        ("*-md-cache", "md-cache-timeout", "601"),
+    ] {
+        let profile = TuningProfile::Default.with(xlator, key, value);
+        assert!(profile.options().is_err(), "{}.{}={}", xlator, key, value);
+    }
+    assert!(TuningProfile::Default
+        .with("*-io-cache", "cache-size", "1GB")
+        .options()
+        .is_ok());
+}
@@ -0,0 +1,163 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::builder::GlusterBuilder;
+use gfapi_sys::gluster::{Gluster, GlusterError, GlusterLogLevel};
+use gfapi_sys::url::{parse_url, UrlError};
+
+#[test]
+fn urls_parse_into_builders() {
+    let table: Vec<(&str, GlusterBuilder)> = vec![
+        ("gluster://gl1/vol", Gluster::builder("vol").server("gl1")),
+        (
+            "gluster://host1:24007,host2/volname/sub/dir?transport=tcp&log-level=warning",
+            Gluster::builder("volname")
+                .server("host1")
+                .backup_server("host2", 24007)
+                .subdir("/sub/dir")
+                .log_level(GlusterLogLevel::Warning),
+        ),
+        (
+            "gluster://[fd00::1]:24008,[::1]/vol",
+            Gluster::builder("vol")
+                .server("fd00::1")
+                .port(24008)
+                .backup_server("::1", 24007),
+        ),
+        (
+            "GLUSTER://gl1/vol/?read-only=true&transport=rdma",
+            Gluster::builder("vol")
+                .server("gl1")
+                .read_only(true)
+                .transport("rdma"),
+        ),
+        (
+            "gluster://gl1/vol/my%20dir/caf%C3%A9//x",
+            Gluster::builder("vol")
+                .server("gl1")
+                .subdir("/my dir/café/x"),
+        ),
+        ("gluster:///vol", Gluster::builder("vol")),
+        (
+            "gluster://gl1/vol?read-only=0&",
+            Gluster::builder("vol").server("gl1").read_only(false),
+        ),
+    ];
+    for (url, expected) in table {
+        assert_eq!(parse_url(url).unwrap(), expected, "{}", url);
+    }
+}
+
+#[test]
+fn bad_urls_are_rejected() {
+    let table: Vec<(&str, UrlError)> = vec![
+        (
+            "glusterfs://gl1/vol",
+            UrlError::UnsupportedScheme("glusterfs".to_string()),
+        ),
+        ("gl1/vol", UrlError::UnsupportedScheme(String::new())),
+        ("gluster://gl1", UrlError::MissingVolume),
+        ("gluster://gl1/", UrlError::MissingVolume),
+        ("gluster://gl1/?read-only=true", UrlError::MissingVolume),
+        (
+            "gluster://gl1,/vol",
+            UrlError::InvalidHost {
+                token: String::new(),
+                reason: "empty entry",
+            },
+        ),
+        (
+            "gluster://fd00::1/vol",
+            UrlError::InvalidHost {
+                token: "fd00::1".to_string(),
+                reason: "IPv6 addresses need brackets, as in [::1]:24007",
+            },
+        ),
+        (
+            "gluster://gl1/vol/a%2Fb",
+            UrlError::InvalidPathSegment("a%2Fb".to_string()),
+        ),
+        (
+            "gluster://gl1/vol/%2E%2E",
+            UrlError::InvalidPathSegment("%2E%2E".to_string()),
+        ),
+        (
+            "gluster://gl1/vol/..",
+            UrlError::InvalidPathSegment("..".to_string()),
+        ),
+        (
+            "gluster://gl1/vol/50%",
+            UrlError::InvalidPathSegment("50%".to_string()),
+        ),
+        (
+            "gluster://gl1/v%FFl",
+            UrlError::InvalidPathSegment("v%FFl".to_string()),
+        ),
+        (
+            "gluster://gl1/vol?timeout=5",
+            UrlError::UnknownQueryKey("timeout".to_string()),
+        ),
+        (
+            "gluster://gl1/vol?transport=udp",
+            UrlError::InvalidQueryValue {
+                key: "transport".to_string(),
+                value: "udp".to_string(),
+            },
+        ),
+        (
+            "gluster://gl1/vol?log-level=loud",
+            UrlError::InvalidQueryValue {
+                key: "log-level".to_string(),
+                value: "loud".to_string(),
+            },
+        ),
+        (
+            "gluster://gl1/vol?read-only=maybe",
+            UrlError::InvalidQueryValue {
+                key: "read-only".to_string(),
+                value: "maybe".to_string(),
+            },
+        ),
+    ];
+    for (url, expected) in table {
+        assert_eq!(parse_url(url).unwrap_err(), expected, "{}", url);
+    }
+}
+
+#[test]
+fn from_url_errors_are_typed() {
+    match Gluster::from_url("http://gl1/vol") {
+        Err(GlusterError::UrlError(UrlError::UnsupportedScheme(scheme))) => {
+            assert_eq!(scheme, "http")
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        Gluster::from_url("gluster://gl1/vol?x=1")
+            .unwrap_err()
+            .to_string(),
+        "unknown URL parameter \"x\""
+    );
+}
+
+#[test]
+fn to_url_round_trips() {
+    let builders = vec![
+        Gluster::builder("vol"),
+        Gluster::builder("vol")
+            .server("host1")
+            .backup_server("fd00::1", 24008)
+            .subdir("/my dir/café")
+            .transport("rdma")
+            .log_level(GlusterLogLevel::Debug)
+            .read_only(true),
+    ];
+    assert_eq!(builders[0].to_url(), "gluster://localhost:24007/vol");
+    assert_eq!(
+        builders[1].to_url(),
+        "gluster://host1:24007,[fd00::1]:24008/vol/my%20dir/caf%C3%A9\
+         ?transport=rdma&log-level=debug&read-only=true"
+    );
+    for builder in builders {
+        assert_eq!(parse_url(&builder.to_url()).unwrap(), builder);
+    }
+}
@@ -0,0 +1,123 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::vectored::{read_exact_vectored_at, write_all_vectored_at};
+
+use std::io::{self, ErrorKind, IoSlice, IoSliceMut};
+
+// A file that transfers at most limit bytes per call, recording the
+// offset of every call
+struct ShortFile {
+    data: Vec<u8>,
+    limit: usize,
+    calls: Vec<u64>,
+    interrupt_first: bool,
+}
+
+impl ShortFile {
+    fn new(data: Vec<u8>, limit: usize) -> ShortFile {
+        ShortFile {
+            data,
+            limit,
+            calls: Vec::new(),
+            interrupt_first: false,
+        }
+    }
+
+    fn interrupted(&mut self) -> bool {
+        let interrupt = self.interrupt_first;
+        self.interrupt_first = false;
+        interrupt
+    }
+
+    fn write(&mut self, bufs: &[IoSlice], offset: u64) -> io::Result<usize> {
+        if self.interrupted() {
+            return Err(io::Error::new(ErrorKind::Interrupted, "signal"));
+        }
+        self.calls.push(offset);
+        let mut at = offset as usize;
+        let mut written = 0;
+        for buf in bufs {
+            for &byte in buf.iter() {
+                if written == self.limit {
+                    return Ok(written);
+                }
+                if at >= self.data.len() {
+                    self.data.resize(at + 1, 0);
+                }
+                self.data[at] = byte;
+                at += 1;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+
+    fn read(&mut self, bufs: &mut [IoSliceMut], offset: u64) -> io::Result<usize> {
+        if self.interrupted() {
+            return Err(io::Error::new(ErrorKind::Interrupted, "signal"));
+        }
+        self.calls.push(offset);
+        let mut at = offset as usize;
+        let mut read = 0;
+        for buf in bufs.iter_mut() {
+            for byte in buf.iter_mut() {
+                if read == self.limit || at >= self.data.len() {
+                    return Ok(read);
+                }
+                *byte = self.data[at];
+                at += 1;
+                read += 1;
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[test]
+fn short_writes_resume_mid_buffer() {
+    let mut file = ShortFile::new(Vec::new(), 3);
+    file.interrupt_first = true;
+    let (a, b, c) = (b"hello".to_vec(), Vec::new(), b" vectored world".to_vec());
+    let bufs = [IoSlice::new(&a), IoSlice::new(&b), IoSlice::new(&c)];
+    let written = write_all_vectored_at(&bufs, 10, |s, at| file.write(s, at)).unwrap();
+    assert_eq!(written, 20);
+    assert_eq!(&file.data[10..], b"hello vectored world");
+    assert_eq!(file.calls, vec![10, 13, 16, 19, 22, 25, 28]);
+}
+
+#[test]
+fn short_reads_fill_every_buffer() {
+    let data: Vec<u8> = (0..100).collect();
+    let mut file = ShortFile::new(data.clone(), 7);
+    let (mut a, mut b, mut c) = (vec![0; 4], vec![0; 0], vec![0; 30]);
+    {
+        let mut bufs = [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ];
+        let read = read_exact_vectored_at(&mut bufs, 50, |s, at| file.read(s, at)).unwrap();
+        assert_eq!(read, 34);
+    }
+    assert_eq!(a, &data[50..54]);
+    assert_eq!(c, &data[54..84]);
+    assert_eq!(file.calls, vec![50, 57, 64, 71, 78]);
+}
+
+#[test]
+fn running_out_early_is_an_error() {
+    let mut file = ShortFile::new(vec![1; 10], 4);
+    let mut buf = vec![0; 8];
+    let err = read_exact_vectored_at(&mut [IoSliceMut::new(&mut buf)], 5, |s, at| {
+        file.read(s, at)
+    })
+    .unwrap_err();
+    assert_eq!(err.to_string(), "failed to fill the whole buffer");
+
+    let err = write_all_vectored_at(&[IoSlice::new(b"abc")], 0, |_, _| Ok(0)).unwrap_err();
+    assert_eq!(err.to_string(), "failed to write the whole buffer");
+
+    let err = write_all_vectored_at(&[IoSlice::new(b"abc")], i64::MAX as u64 - 1, |_, _| Ok(3))
+        .unwrap_err();
+    assert!(err.to_string().contains("largest file offset"));
+}
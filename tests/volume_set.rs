@@ -0,0 +1,47 @@
+extern crate gfapi_sys;
+
+use std::path::{Path, PathBuf};
+
+use gfapi_sys::gluster::Gluster;
+use gfapi_sys::volume_set::{VolumePath, VolumeSet, VolumeState, VolumeStatus};
+
+#[test]
+fn volume_paths_parse_and_display() {
+    let path: VolumePath = "vol-a:/data/x".parse().unwrap();
+    assert_eq!(path, VolumePath::new("vol-a", "/data/x"));
+    assert_eq!(path.to_string(), "vol-a:/data/x");
+    // Only the first colon separates the volume
+    let path: VolumePath = "vol-b:/a:b".parse().unwrap();
+    assert_eq!(path.path, PathBuf::from("/a:b"));
+    assert_eq!(
+        VolumePath::from(("vol-c", Path::new("rel"))),
+        VolumePath::new("vol-c", "rel")
+    );
+    for bad in &["", "vol-a", ":/data", "vol-a:"] {
+        assert!(bad.parse::<VolumePath>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn volume_set_connects_lazily() {
+    let volumes = VolumeSet::new();
+    volumes.register("b", Gluster::builder("vol-b"));
+    volumes.register("a", Gluster::builder("vol-a"));
+    assert_eq!(
+        volumes.status(),
+        vec![
+            VolumeStatus {
+                name: "a".to_string(),
+                state: VolumeState::NotConnected,
+            },
+            VolumeStatus {
+                name: "b".to_string(),
+                state: VolumeState::NotConnected,
+            },
+        ]
+    );
+    assert!(volumes.unregister("b"));
+    assert!(!volumes.unregister("b"));
+    assert!(volumes.volume("b").is_err());
+    assert_eq!(volumes.status().len(), 1);
+}
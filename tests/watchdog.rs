@@ -0,0 +1,109 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::memory_fs::MemoryFs;
+use gfapi_sys::remote_fs::{RemoteFs, RemoteMetadata, RemoteReadDir};
+use gfapi_sys::watchdog::{InFlightOp, WatchdogOptions, WatchedFs};
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// MemoryFs with reads that hang for a while, like a wedged brick
+struct SlowFs {
+    inner: MemoryFs,
+    read_delay: Duration,
+}
+
+impl RemoteFs for SlowFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        thread::sleep(self.read_delay);
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.write(path, data)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<RemoteMetadata> {
+        self.inner.metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<RemoteReadDir<'_>> {
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.inner.create_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_dir(path)
+    }
+}
+
+fn watched(read_delay: Duration, reports: &Arc<Mutex<Vec<InFlightOp>>>) -> WatchedFs<SlowFs> {
+    let reports = reports.clone();
+    let opts = WatchdogOptions::new()
+        .threshold(Duration::from_millis(100))
+        .interval(Duration::from_millis(10))
+        .on_stuck(move |op| reports.lock().unwrap().push(op.clone()));
+    WatchedFs::new(
+        SlowFs {
+            inner: MemoryFs::new(),
+            read_delay,
+        },
+        &opts,
+    )
+}
+
+#[test]
+fn a_stuck_operation_is_reported_once() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let fs = watched(Duration::from_millis(400), &reports);
+    fs.write(Path::new("/wedged"), b"data").unwrap();
+    assert!(fs.in_flight().is_empty());
+
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| fs.read(Path::new("/wedged")).unwrap());
+        thread::sleep(Duration::from_millis(200));
+        let in_flight = fs.in_flight();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].op, "read");
+        assert_eq!(in_flight[0].path, PathBuf::from("/wedged"));
+        assert_eq!(in_flight[0].thread, reader.thread().id());
+        assert_eq!(reader.join().unwrap(), b"data".to_vec());
+    });
+
+    // Give the watchdog another look after the read returned
+    thread::sleep(Duration::from_millis(50));
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].op, "read");
+    assert!(reports[0].elapsed >= Duration::from_millis(100));
+    assert!(fs.in_flight().is_empty());
+}
+
+#[test]
+fn quick_operations_are_not_reported() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let fs = watched(Duration::from_millis(0), &reports);
+    for i in 0..50 {
+        let path = PathBuf::from(format!("/file-{}", i));
+        fs.write(&path, b"x").unwrap();
+        fs.read(&path).unwrap();
+        fs.remove_file(&path).unwrap();
+    }
+    thread::sleep(Duration::from_millis(150));
+    assert!(reports.lock().unwrap().is_empty());
+    assert!(fs.in_flight().is_empty());
+}
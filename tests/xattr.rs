@@ -0,0 +1,47 @@
+extern crate gfapi_sys;
+
+use gfapi_sys::xattr::{XattrFilter, XattrNamespace};
+
+#[test]
+fn namespaces_come_from_the_prefix() {
+    assert_eq!(
+        XattrNamespace::of(b"user.checksum"),
+        Some(XattrNamespace::User)
+    );
+    assert_eq!(
+        XattrNamespace::of(b"security.selinux"),
+        Some(XattrNamespace::Security)
+    );
+    assert_eq!(
+        XattrNamespace::of(b"trusted.glusterfs.volume-id"),
+        Some(XattrNamespace::Trusted)
+    );
+    assert_eq!(
+        XattrNamespace::of(b"system.posix_acl_access"),
+        Some(XattrNamespace::System)
+    );
+    // The dot is part of the prefix
+    assert_eq!(XattrNamespace::of(b"username"), None);
+    assert_eq!(XattrNamespace::of(b"\xffuser.x"), None);
+    assert_eq!(XattrNamespace::User.to_string(), "user");
+    assert_eq!(XattrNamespace::Trusted.prefix(), "trusted.");
+}
+
+#[test]
+fn filters_combine_namespaces_and_predicates() {
+    assert!(XattrFilter::all().accepts(b"trusted.gfid"));
+    assert!(XattrFilter::all().accepts(b"no-namespace"));
+
+    let user = XattrFilter::namespaces(&[XattrNamespace::User]);
+    assert!(user.accepts(b"user.a"));
+    assert!(!user.accepts(b"trusted.a"));
+    assert!(!user.accepts(b"no-namespace"));
+
+    let user_tags = XattrFilter::namespaces(&[XattrNamespace::User])
+        .matching(|name| name.starts_with(b"user.tag."));
+    assert!(user_tags.accepts(b"user.tag.colour"));
+    assert!(!user_tags.accepts(b"user.other"));
+    assert!(!XattrFilter::namespaces(&[XattrNamespace::Security])
+        .matching(|name| name.starts_with(b"user."))
+        .accepts(b"user.a"));
+}